@@ -0,0 +1,27 @@
+//! Generates the gRPC code of `proto/supervisor.proto` when built with the `grpc` feature
+//! (see `src/lib/grpc.rs`).
+//!
+//! The proto files are parsed in Rust instead of by `protoc`, so that cross compiling the
+//! supervisor does not need a `protoc` for the build host.
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto");
+    #[cfg(feature = "grpc")]
+    if let Err(e) = compile_protos() {
+        panic!("Failed to generate the gRPC code: {}", e);
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn compile_protos() -> Result<(), Box<dyn std::error::Error>> {
+    use prost::Message;
+    use protobuf::Message as _;
+    let descriptors = protobuf_parse::Parser::new()
+        .pure()
+        .include("proto")
+        .input("proto/supervisor.proto")
+        .file_descriptor_set()?;
+    let descriptors = prost_types::FileDescriptorSet::decode(descriptors.write_to_bytes()?.as_slice())?;
+    tonic_prost_build::configure().compile_fds(descriptors)?;
+    Ok(())
+}
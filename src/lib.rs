@@ -7,8 +7,53 @@ pub mod lib {
     pub mod configuration;
     pub mod logging;
     pub mod deployment;
+    pub mod download;
+    pub mod health;
+    pub mod signing;
+    pub mod bundle;
+    pub mod maintenance;
+    pub mod progress;
+    pub mod checksum;
+    pub mod url_signing;
+    pub mod cli;
+    pub mod config_file;
+    pub mod reload;
+    pub mod settings;
+    pub mod execution;
+    pub mod self_check;
+    pub mod camera;
+    pub mod systemd;
+    pub mod shutdown;
+    pub mod request_history;
+    pub mod mqtt;
+    pub mod coap;
+    pub mod callback;
+    pub mod telemetry;
+    pub mod result_sink;
+    pub mod unix_socket;
+    pub mod orchestrator_compat;
+    pub mod metrics;
+    pub mod actions;
+    pub mod execution_events;
+    pub mod grpc;
+    pub mod twin;
+    pub mod stats;
+    pub mod replication;
+    pub mod admission;
+    pub mod negotiation;
+    pub mod audit;
+    pub mod startup;
+    pub mod device_limits;
+    pub mod durable_queue;
+    pub mod wasm_memory;
+    pub mod app_state;
+    pub mod device_registry;
+    #[cfg(feature = "test-util")]
+    pub mod test_support;
 }
 pub mod structs {
+    pub mod deployment_supervisor;
     pub mod device;
     pub mod request_entry;
+    pub mod supervisor_config;
 }
@@ -0,0 +1,138 @@
+//! # actions.rs
+//!
+//! Functions of deployments invoked as asynchronous actions, as the W3C Web of Things HTTP
+//! profile describes them, so that WoT clients like node-wot can run them without knowing
+//! anything of the supervisor.
+//!
+//! `POST /{deployment}/actions/{module}/{function}` answers `202 Accepted` at once, with the
+//! `ActionStatus` of the execution and its URL, `/actions/{request_id}`, in `Location`. The
+//! execution itself is the same as on `/{deployment}/modules/{module}/{function}`, and once it
+//! has finished the status is read from its entry in the request history.
+//!
+//! `DELETE /actions/{request_id}` cancels an action that is still waiting for an execution
+//! thread. It is then recorded as failed without its function being run. An action whose
+//! function is already running cannot be cancelled, as a Wasm call is not interrupted halfway.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+use crate::structs::request_entry::RequestEntry;
+
+/// Result recorded for an action cancelled before its function was run.
+pub const CANCELLED_ERROR: &str = "Cancelled before the execution started";
+
+/// Where an action that has not finished is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionState {
+    /// Waiting for an execution thread.
+    Pending,
+    /// Its function is running.
+    Running,
+    /// Cancelled while pending, waiting to be recorded as failed.
+    Cancelled,
+}
+
+/// An action that has not finished.
+#[derive(Debug, Clone, Copy)]
+struct UnfinishedAction {
+    state: ActionState,
+    time_requested: DateTime<Utc>,
+}
+
+/// Actions that have not finished, by request ID.
+static ACTIONS: Lazy<Mutex<HashMap<String, UnfinishedAction>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Status of an action, in the shape of the WoT HTTP profile.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionStatus {
+    /// `pending`, `running`, `completed` or `failed`.
+    pub status: &'static str,
+    /// Result of the function, once completed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    /// Why the action failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+    /// Path the status of the action is queried and the action cancelled at.
+    pub href: String,
+    /// When the action was requested.
+    pub time_requested: DateTime<Utc>,
+}
+
+/// Path of the status of an action.
+pub fn action_href(request_id: &str) -> String {
+    format!("/actions/{}", request_id)
+}
+
+/// Records a request as an action waiting to be run.
+pub fn register_action(entry: &RequestEntry) {
+    let action = UnfinishedAction { state: ActionState::Pending, time_requested: entry.work_queued_at };
+    ACTIONS.lock().insert(entry.request_id.clone(), action);
+}
+
+/// Marks an action as running, right before its function is run.
+///
+/// # Returns
+/// Whether the function is to be run, which it is not if the action was cancelled. Requests
+/// that are not actions are always run.
+pub fn start_action(request_id: &str) -> bool {
+    match ACTIONS.lock().get_mut(request_id) {
+        Some(action) if action.state == ActionState::Cancelled => false,
+        Some(action) => {
+            action.state = ActionState::Running;
+            true
+        }
+        None => true,
+    }
+}
+
+/// Forgets an action once its result is in the request history.
+pub fn finish_action(request_id: &str) {
+    ACTIONS.lock().remove(request_id);
+}
+
+/// Cancels an action that has not started running.
+///
+/// # Returns
+/// The status of the cancelled action, or the state of an action that was not pending, which
+/// is `None` for requests that are not actions or have finished.
+pub fn cancel_action(request_id: &str) -> Result<ActionStatus, Option<ActionState>> {
+    let mut actions = ACTIONS.lock();
+    match actions.get_mut(request_id) {
+        Some(action) if action.state != ActionState::Running => {
+            action.state = ActionState::Cancelled;
+            Ok(unfinished_status(request_id, *action))
+        }
+        Some(action) => Err(Some(action.state)),
+        None => Err(None),
+    }
+}
+
+/// Returns the status of an action that has not finished, if it is one.
+pub fn pending_action_status(request_id: &str) -> Option<ActionStatus> {
+    let action = *ACTIONS.lock().get(request_id)?;
+    Some(unfinished_status(request_id, action))
+}
+
+/// Status of an action that is not in the request history yet.
+fn unfinished_status(request_id: &str, action: UnfinishedAction) -> ActionStatus {
+    let (status, error) = match action.state {
+        ActionState::Pending => ("pending", None),
+        ActionState::Running => ("running", None),
+        ActionState::Cancelled => ("failed", Some(Value::from(CANCELLED_ERROR))),
+    };
+    ActionStatus { status, output: None, error, href: action_href(request_id), time_requested: action.time_requested }
+}
+
+/// Status of a finished request, from its entry in the request history.
+pub fn finished_action_status(entry: &RequestEntry) -> ActionStatus {
+    let (status, output, error) = match entry.success {
+        true => ("completed", Some(entry.result.clone().unwrap_or(Value::Null)), None),
+        false => ("failed", None, Some(entry.result.clone().unwrap_or(Value::Null))),
+    };
+    ActionStatus { status, output, error, href: action_href(&entry.request_id), time_requested: entry.work_queued_at }
+}
@@ -0,0 +1,147 @@
+//! # admission.rs
+//!
+//! Admission checks of deployments against what the device has, so that a deployment the
+//! device cannot run is refused when it is created instead of failing when it is executed.
+//!
+//! A manifest may declare `requires` for the whole deployment and for each of its modules:
+//! - `capabilities`: capabilities of the device the modules use, e.g. `camera` or `wasi-nn`
+//! - `minMemoryBytes`: memory of the device
+//! - `minFreeDiskBytes`: free space on the disk holding the instance directory
+//! - `architectures`: CPU architectures the modules are built for, any of which will do
+//!
+//! All of them are optional. The facts they are checked against are published under `admission`
+//! of the device description (see `device_facts`), so that orchestrators can filter devices
+//! before placing deployments on them.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::lib::camera::camera_enabled;
+use crate::lib::configuration::instance_disk_available;
+use crate::lib::constants::{
+    CAMERA_FUNCTIONS, DISKS, NETWORK_FUNCTIONS, SUPERVISOR_INTERFACES, SYSTEM, WASI_FUNCTIONS, WASI_NN_FUNCTIONS,
+};
+
+/// Capabilities of the device and the host functions that provide them.
+const CAPABILITY_FUNCTIONS: [(&str, &[&str]); 4] = [
+    ("camera", CAMERA_FUNCTIONS),
+    ("network", NETWORK_FUNCTIONS),
+    ("wasi", WASI_FUNCTIONS),
+    ("wasi-nn", WASI_NN_FUNCTIONS),
+];
+
+/// Requirements a deployment or module declares under `requires`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Requirements {
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    pub min_memory_bytes: Option<u64>,
+    pub min_free_disk_bytes: Option<u64>,
+    #[serde(default)]
+    pub architectures: Vec<String>,
+}
+
+/// What the device has, as requirements are checked against.
+pub struct DeviceFacts {
+    /// Capabilities whose host functions are offered and may be used
+    pub capabilities: Vec<&'static str>,
+    pub memory_bytes: u64,
+    /// Free bytes on the disk holding the instance directory, if it was found
+    pub free_disk_bytes: Option<u64>,
+    /// Names the CPU architecture of the device goes by, e.g. `armv7l` and `arm`
+    pub architectures: Vec<String>,
+}
+
+impl DeviceFacts {
+    /// The facts as published in the device description.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "capabilities": self.capabilities,
+            "memoryBytes": self.memory_bytes,
+            "freeDiskBytes": self.free_disk_bytes,
+            "architectures": self.architectures,
+        })
+    }
+}
+
+/// Gathers the capabilities, memory, free disk space and architecture of the device.
+///
+/// A capability is there when the host functions providing it are offered, and the camera only
+/// when it is enabled (see `camera.rs`).
+pub fn device_facts() -> DeviceFacts {
+    let capabilities = CAPABILITY_FUNCTIONS.iter()
+        .filter(|(capability, functions)| {
+            functions.iter().any(|function| SUPERVISOR_INTERFACES.contains(function))
+                && (*capability != "camera" || camera_enabled())
+        })
+        .map(|(capability, _)| *capability)
+        .collect();
+    let memory_bytes = {
+        let mut sys = SYSTEM.lock();
+        sys.refresh_memory();
+        sys.total_memory()
+    };
+    let free_disk_bytes = {
+        let mut disks = DISKS.lock();
+        disks.refresh(true);
+        instance_disk_available(&disks)
+    };
+    let mut architectures = vec![sysinfo::System::cpu_arch()];
+    if !architectures.iter().any(|arch| arch == std::env::consts::ARCH) {
+        architectures.push(std::env::consts::ARCH.to_string());
+    }
+    DeviceFacts { capabilities, memory_bytes, free_disk_bytes, architectures }
+}
+
+/// Checks the `requires` of a deployment manifest and of each of its modules against the
+/// facts of the device.
+///
+/// # Returns
+/// Every requirement the device does not meet, each with the `requirement`, what is `required`
+/// and what is `available`, and the `module` declaring it if it is not the whole deployment.
+/// An error if a `requires` is not valid.
+pub fn unmet_requirements(manifest: &Value, facts: &DeviceFacts) -> Result<Vec<Value>, String> {
+    let mut declared = vec![(None, manifest.get("requires"))];
+    for module in manifest["modules"].as_array().into_iter().flatten() {
+        declared.push((module.get("name").and_then(Value::as_str), module.get("requires")));
+    }
+
+    let mut unmet = Vec::new();
+    for (module, requires) in declared {
+        let Some(requires) = requires.filter(|requires| !requires.is_null()) else { continue };
+        let requirements = serde_json::from_value::<Requirements>(requires.clone()).map_err(|e| match module {
+            Some(module) => format!("Invalid requires of module '{}': {}", module, e),
+            None => format!("Invalid requires: {}", e),
+        })?;
+        let mut push = |requirement: &str, required: Value, available: Value| {
+            let mut entry = json!({ "requirement": requirement, "required": required, "available": available });
+            if let Some(module) = module {
+                entry["module"] = json!(module);
+            }
+            unmet.push(entry);
+        };
+
+        let missing: Vec<&String> = requirements.capabilities.iter()
+            .filter(|capability| !facts.capabilities.contains(&capability.as_str()))
+            .collect();
+        if !missing.is_empty() {
+            push("capabilities", json!(missing), json!(facts.capabilities));
+        }
+        if let Some(min) = requirements.min_memory_bytes
+            && facts.memory_bytes < min
+        {
+            push("minMemoryBytes", json!(min), json!(facts.memory_bytes));
+        }
+        if let Some(min) = requirements.min_free_disk_bytes
+            && facts.free_disk_bytes.is_none_or(|free| free < min)
+        {
+            push("minFreeDiskBytes", json!(min), json!(facts.free_disk_bytes));
+        }
+        let architecture_matches = requirements.architectures.iter()
+            .any(|required| facts.architectures.iter().any(|arch| arch.eq_ignore_ascii_case(required)));
+        if !requirements.architectures.is_empty() && !architecture_matches {
+            push("architectures", json!(requirements.architectures), json!(facts.architectures));
+        }
+    }
+    Ok(unmet)
+}
@@ -5,7 +5,9 @@
 //! It exposes endpoints to:
 //!
 //! - Query device metadata (WasmIoT & WoT descriptions)
-//! - Check system health (CPU, memory, network)
+//! - Check system health (CPU, memory, network) and its overall status
+//! - Inspect the recorded health history
+//! - Read and update the runtime configuration
 //! - Create and delete WebAssembly deployments
 //! - Trigger function execution in deployed modules (GET/POST with optional input files)
 //! - Fetch module-generated result files
@@ -40,39 +42,138 @@
 
 
 use actix_web::web::Data;
-use parking_lot::Mutex;
+use parking_lot::{ArcMutexGuard, Mutex, RawMutex};
 use tokio::task;
+use tokio::io::AsyncWriteExt;
 use actix_multipart::Multipart;
-use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::{mime, web, FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder};
+use actix_web::http::{header, StatusCode};
 use actix_files::NamedFile;
 use sysinfo::System;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use chrono::Utc;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
 use log::error;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use once_cell::sync::Lazy;
-use std::path::PathBuf;
-use wasmtime::Val;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
 use sanitize_filename;
 use futures_util::StreamExt;
+use tracing::Instrument;
 use std::fs::File;
-use std::env;
-use std::io::Write;
-use crate::lib::configuration::{get_wot_td, get_device_description};
-use crate::lib::logging::send_log;
+use crate::lib::configuration::{
+    get_wot_td,
+    get_device_description,
+    get_supervisor_config,
+    patch_supervisor_config,
+    public_url,
+};
+use crate::lib::logging::{send_log, pending_log_count};
+use crate::lib::app_state::{app_state, in_current_app_state, with_app_state, AppState};
 use crate::function_name;
-use crate::lib::deployment::{Deployment, EndpointArgs, ModuleEndpointMap, EndpointData, Endpoint};
-use crate::lib::wasmtime::{WasmtimeRuntime, ModuleConfig};
-use crate::lib::constants::{MODULE_FOLDER, PARAMS_FOLDER, DEPLOYMENTS_FOLDER};
-use crate::lib::zeroconf::{register_health_check, WebthingZeroconf};
+use crate::lib::deployment::{CallData, Deployment, EndpointArgs, ModuleEndpointMap, EndpointData, Endpoint, Healthcheck, HealthcheckPolicy, MountStage, Queueing, ResultSource, SecretValue, module_secret_env, module_mount_path, wasm_val_json};
+use crate::lib::wasmtime::{WasmtimeRuntime, ModuleConfig, MountLayout, MountPermission, MountPermissions, Preopen, protect_read_only, module_cache_stats};
+use crate::lib::constants::{
+    get_api_token,
+    MODULE_FOLDER,
+    PARAMS_FOLDER,
+    DEPLOYMENTS_FOLDER,
+    BUNDLE_IMPORT_FOLDER,
+    OUTPUTS_FOLDER_NAME,
+    INPUTS_FOLDER_NAME,
+    INSTANCE_PATH,
+    REQUEST_HISTORY_FILE_NAME,
+    get_description_max_age,
+    get_download_concurrency,
+    get_deployment_download_timeout,
+    get_require_signed_deployments,
+    get_expiry_check_interval,
+    get_expired_deployment_grace,
+    get_max_file_bytes,
+    get_dev_mode,
+    get_max_deployment_bytes,
+    get_gc_grace,
+    get_result_cleanup_interval,
+    get_request_history_retention,
+    get_request_history_max_entries,
+    get_inline_result_max_bytes,
+    get_chain_mirror_max_bytes,
+    get_endpoint_examples,
+    get_healthcheck_timeout,
+    get_module_timeout,
+};
+use crate::lib::zeroconf::{register_health_check, registration_status, rename_service, WebthingZeroconf};
+use crate::lib::reload::reload_configuration;
+use crate::lib::settings::{get_setting, is_setting_set, set_setting, SettingSource};
+use crate::lib::health::{ExecutionGuard, current_health_snapshot, get_health_history, in_flight_executions_of};
+use crate::lib::execution::{run_with_priority, should_shed, PRIORITY_HEADER};
+use crate::lib::shutdown::{finish_execution, in_flight_requests, track_execution};
+use crate::lib::request_history::{RequestHistory, archive_requests, read_archived_request, prune_request_archive};
+use crate::lib::camera::{camera_enabled, modules_requiring_camera};
+use crate::lib::signing::verify_manifest;
+use crate::lib::bundle::{export_manifest, build_bundle, unpack_bundle};
+use crate::lib::maintenance::{
+    GcReport,
+    RetentionPolicy,
+    RetentionReport,
+    collect_orphaned_folders,
+    enforce_result_retention,
+    result_storage_stats,
+};
+use crate::lib::url_signing::{sign_path, verify_path};
+use crate::lib::callback::{callback_host_allowed, deliver_callback, parse_callback_url};
+use crate::lib::result_sink::{ResultSink, upload_outputs};
+use crate::lib::unix_socket::is_trusted_peer;
+use crate::lib::orchestrator_compat::{logging_endpoint, negotiate, API_VERSION_HEADER, LEGACY_DEPLOY_PATH};
+use crate::lib::metrics::{collect_metrics, record_execution, record_transferred, render_prometheus};
+use crate::lib::stats::{record_invocation, record_transfer, remove_stats, stats_report, stats_summary};
+use crate::lib::wasm_memory::{memory_report, record_deployment_memory, record_module_memory, remove_memory, total_wasm_memory_bytes};
+use crate::lib::device_registry::{parse_devices, patch_devices, resolve_target_url};
+use crate::lib::replication::{forget_replicas, parse_replicas, replica_statuses, sync_replicas};
+use crate::lib::admission::{device_facts, unmet_requirements};
+use crate::lib::device_limits::{check_device_limits, LimitExceeded};
+use crate::lib::durable_queue::{enqueue, queue_stats, queued_request, should_queue, EnqueueError};
+use crate::lib::negotiation::{negotiate_representation, negotiated_response, Representation};
+use crate::lib::audit::{audit, audit_execution, read_audit, verify_chain};
+use crate::lib::startup::{is_loading, load_state, load_states, loading_deployment_ids, send_startup_report, startup_report, DeploymentLoad, LoadState};
+use crate::lib::execution_events::{emit_execution_event, ExecutionEvent};
+use crate::lib::twin::{build_twin, twin_changed, twin_revision};
+use crate::lib::actions::{
+    action_href,
+    cancel_action,
+    finish_action,
+    finished_action_status,
+    pending_action_status,
+    register_action,
+    start_action,
+    ActionState,
+    CANCELLED_ERROR,
+};
+use crate::lib::telemetry::{execution_span, outgoing_traceparent, valid_traceparent, TRACEPARENT_HEADER};
+use crate::lib::checksum::{file_digest, file_metadata, digest_header_value};
+use crate::lib::progress::{DeploymentPhase, start_progress, update_progress, finish_progress, get_progress};
+use crate::lib::download::{
+    ArtifactSource,
+    ArtifactKind,
+    DownloadJob,
+    DownloadLimits,
+    DownloadOutcome,
+    PreflightError,
+    cache_path,
+    check_download_space,
+    download_all,
+    parse_peers,
+};
 use indexmap::IndexMap;
+use crate::structs::deployment_supervisor::{parse_instructions, parse_mounts};
 use crate::structs::device::{
     HealthReport, 
-    NetworkInterfaceUsage, 
 };
-use crate::lib::constants::{SYSTEM, NETWORKS, DISKS};
-use crate::structs::request_entry::RequestEntry;
+use crate::structs::request_entry::{RequestEntry, ChainStep, CallbackDelivery, Priority};
 use urlencoding;
 
 /// Represents a failure to fetch one or more module binaries or data files.
@@ -82,16 +183,110 @@ pub struct FetchFailures {
     pub errors: Vec<Value>,
 }
 
-/// Global in-memory storage of active deployments.
+/// A deployment behind a lock of its own, so that functions of different deployments can run
+/// at the same time.
+pub type SharedDeployment = Arc<tokio::sync::Mutex<Deployment>>;
+
+/// Locks the active deployments of the supervisor being served (see `app_state.rs`).
 ///
 /// Maps a deployment ID to its corresponding `Deployment` struct,
 /// including runtime environments, modules, instructions and mounts.
-pub static DEPLOYMENTS: Lazy<Mutex<HashMap<String, Deployment>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// The map is only locked to look up, add or remove deployments and never across an `.await`,
+/// while each deployment is locked for as long as one of its functions runs.
+pub fn lock_deployments() -> ArcMutexGuard<RawMutex, HashMap<String, SharedDeployment>> {
+    app_state().deployments.lock_arc()
+}
+
+/// Returns the deployment with the given ID.
+pub fn get_deployment(deployment_id: &str) -> Option<SharedDeployment> {
+    lock_deployments().get(deployment_id).cloned()
+}
+
+/// Returns every deployment, to go through them without holding `lock_deployments`.
+fn all_deployments() -> Vec<SharedDeployment> {
+    lock_deployments().values().cloned().collect()
+}
+
+/// Returns the deployment, module and function names of every deployed function, sorted.
+pub async fn deployed_functions() -> Vec<(String, String, String)> {
+    let mut functions = Vec::new();
+    for deployment in all_deployments() {
+        let deployment = deployment.lock().await;
+        for (module_name, endpoints) in &deployment.endpoints {
+            for function_name in endpoints.keys() {
+                functions.push((deployment.id.clone(), module_name.clone(), function_name.clone()));
+            }
+        }
+    }
+    functions.sort();
+    functions
+}
 
-/// History of request executions, including success/failure and output data.
+/// Adds a deployment, replacing the one with the same ID if any, and drops the description
+/// documents that listed the deployments before it.
 ///
-/// This mirrors `request_history` in the original Python code.
-static REQUEST_HISTORY: Lazy<Mutex<Vec<RequestEntry>>> = Lazy::new(|| Mutex::new(Vec::new()));
+/// # Returns
+/// The deployment that was replaced.
+pub fn insert_deployment(deployment: Deployment) -> Option<SharedDeployment> {
+    let deployment_id = deployment.id.clone();
+    let replaced = lock_deployments().insert(deployment_id, Arc::new(tokio::sync::Mutex::new(deployment)));
+    invalidate_description_cache();
+    replaced
+}
+
+/// Locks the history of request executions of the supervisor being served, including
+/// success/failure and output data.
+///
+/// This mirrors `request_history` in the original Python code. Entries evicted from it are
+/// archived on disk (see `request_history.rs`).
+fn lock_request_history() -> ArcMutexGuard<RawMutex, RequestHistory> {
+    app_state().request_history.lock_arc()
+}
+
+/// The last refusal to run a module that failed to load.
+struct LoadFailure {
+    load_error: String,
+    refused_at: std::time::Instant,
+}
+
+/// Last refusals to run modules that failed to load, by deployment and module.
+static LOAD_FAILURES: Lazy<Mutex<HashMap<(String, String), LoadFailure>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Time without refusals after which the next refusal to run a module that failed to load
+/// starts a new burst, recorded in the request history again.
+const LOAD_FAILURE_BURST_WINDOW: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Records a refusal to run a function of a module that failed to load.
+///
+/// The orchestrator retries such requests, so only the first refusal of each burst is
+/// recorded in the request history, as a failed entry marked with `load_error`. A burst ends
+/// after `LOAD_FAILURE_BURST_WINDOW` without refusals, or when the load error changes.
+fn record_load_failure(deployment_id: &str, module_name: &str, function_name: &str, method: &str, load_error: &str) {
+    let now = std::time::Instant::now();
+    let key = (deployment_id.to_string(), module_name.to_string());
+    let failure = LoadFailure { load_error: load_error.to_string(), refused_at: now };
+    let new_burst = match LOAD_FAILURES.lock().insert(key, failure) {
+        Some(previous) => previous.load_error != load_error
+            || now.duration_since(previous.refused_at) > LOAD_FAILURE_BURST_WINDOW,
+        None => true,
+    };
+    if !new_burst {
+        return;
+    }
+    let mut entry = RequestEntry::new(
+        deployment_id.to_string(),
+        module_name.to_string(),
+        function_name.to_string(),
+        method.to_string(),
+        json!({}),
+        HashMap::new(),
+        Utc::now(),
+    );
+    entry.load_error = true;
+    entry.result = Some(Value::String(format!("Module '{}' failed to load: {}", module_name, load_error)));
+    let evicted = lock_request_history().push(entry, get_request_history_max_entries());
+    archive_evicted(evicted);
+}
 
 /// Constructs and returns the filesystem path to the given module's `.wasm` file.
 pub fn get_module_path(deployment_id: &str, module_name: &str) -> PathBuf {
@@ -115,18 +310,374 @@ pub fn get_params_path(deployment_id: &str, module_name: &str, filename: Option<
 }
 
 
-/// Helper that generates urls for output files
-fn make_output_url(deployment_id: &str, module_name: &str, filename: &str) -> String {
-    let scheme = std::env::var("DEFAULT_URL_SCHEME").unwrap_or_else(|_| "http".to_string());
-    let host = std::env::var("WASMIOT_SUPERVISOR_IP").unwrap_or_else(|_| "localhost".to_string());
-    let port = std::env::var("WASMIOT_SUPERVISOR_PORT").unwrap_or_else(|_| "8080".to_string());
-    format!("{scheme}://{host}:{port}/module_results/{}/{}/{}",
+/// Constructs the path to the outputs of one request to a module, or to an output file in it.
+///
+/// Folder structure: params/{deployment_id}/{module_name}/outputs/{request_id}/{filename}
+pub fn get_output_path(deployment_id: &str, module_name: &str, request_id: &str, filename: Option<&str>) -> PathBuf {
+    let base = get_params_path(deployment_id, module_name, Some(OUTPUTS_FOLDER_NAME)).join(request_id);
+    match filename {
+        Some(file) => base.join(file),
+        None => base,
+    }
+}
+
+/// Constructs the path to the uploaded inputs of one request to a module, or to an input file in it.
+///
+/// Folder structure: params/{deployment_id}/{module_name}/inputs/{request_id}/{filename}
+pub fn get_input_path(deployment_id: &str, module_name: &str, request_id: &str, filename: Option<&str>) -> PathBuf {
+    let base = get_params_path(deployment_id, module_name, Some(INPUTS_FOLDER_NAME)).join(request_id);
+    match filename {
+        Some(file) => base.join(file),
+        None => base,
+    }
+}
+
+/// Returns the ID of the latest request to a module that produced an output file with the
+/// given name, based on the modification times of the files.
+fn latest_output_request(deployment_id: &str, module_name: &str, filename: &str) -> Option<String> {
+    let outputs = get_params_path(deployment_id, module_name, Some(OUTPUTS_FOLDER_NAME));
+    std::fs::read_dir(outputs).ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let modified = std::fs::metadata(entry.path().join(filename)).ok()?.modified().ok()?;
+            Some((modified, entry.file_name().to_string_lossy().to_string()))
+        })
+        .max()
+        .map(|(_, request_id)| request_id)
+}
+
+/// Moves an output file written by a module into the outputs folder of the request, so
+/// that later requests writing a file with the same name cannot overwrite it.
+fn store_request_output(deployment_id: &str, module_name: &str, layout: MountLayout, request_id: &str, filename: &str) -> Result<(), String> {
+    let written = module_mount_path(deployment_id, module_name, layout, MountStage::OUTPUT, filename);
+    if !written.exists() {
+        log::warn!("Output file {} was not written by the module", written.display());
+        return Ok(());
+    }
+    let stored = get_output_path(deployment_id, module_name, request_id, Some(filename));
+    if let Some(parent) = stored.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create output directory {}: {}", parent.display(), e))?;
+    }
+    std::fs::rename(&written, &stored)
+        .map_err(|e| format!("Failed to store output file {}: {}", filename, e))
+}
+
+/// The input files of a request linked to the mount locations of its module for the execution.
+///
+/// The links are removed when this is dropped, which must happen before the deployment is
+/// unlocked, so that they cannot be taken for inputs of a later request.
+struct LinkedInputs(Vec<PathBuf>);
+
+impl LinkedInputs {
+    fn of(entry: &RequestEntry, layout: MountLayout) -> Self {
+        LinkedInputs(entry.request_files.keys()
+            .map(|mount_path| module_mount_path(&entry.deployment_id, &entry.module_name, layout, MountStage::EXECUTION, mount_path))
+            .collect())
+    }
+}
+
+impl Drop for LinkedInputs {
+    fn drop(&mut self) {
+        for linked in &self.0 {
+            if let Err(e) = std::fs::remove_file(linked)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                log::warn!("Failed to remove input file {}: {}", linked.display(), e);
+            }
+        }
+    }
+}
+
+/// Removes the inputs folder of a request once it has been executed, unless the endpoint keeps it.
+fn remove_request_inputs(entry: &RequestEntry) {
+    if entry.inputs_retained {
+        return;
+    }
+    let inputs = get_input_path(&entry.deployment_id, &entry.module_name, &entry.request_id, None);
+    if let Err(e) = std::fs::remove_dir_all(&inputs)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        log::warn!("Failed to remove inputs of request {}: {}", entry.request_id, e);
+    }
+}
+
+/// Whether a path segment from a URL is usable as a file or folder name as is.
+fn is_plain_filename(name: &str) -> bool {
+    !name.is_empty() && sanitize_filename::sanitize(name) == name
+}
+
+/// Helper that generates urls for output files of a request
+fn make_output_url(deployment_id: &str, module_name: &str, request_id: &str, filename: &str) -> String {
+    public_url(&sign_path(&format!("/module_results/{}/{}/{}/{}",
         urlencoding::encode(deployment_id),
         urlencoding::encode(module_name),
+        urlencoding::encode(request_id),
         urlencoding::encode(filename)
-    )
+    )))
+}
+
+
+/// Reads an output file of a request for including it in the execution response, so that
+/// callers of functions with small outputs do not need another request to fetch them.
+///
+/// # Returns
+/// The file as `{filename, contentType, size, encoding, content}` with base64 content, or
+/// `None` if the file is not below `WASMIOT_INLINE_RESULT_MAX_BYTES` or cannot be read.
+pub fn inline_output(deployment_id: &str, module_name: &str, request_id: &str, filename: &str, content_type: &str) -> Option<Value> {
+    let max_bytes = get_inline_result_max_bytes();
+    let path = get_output_path(deployment_id, module_name, request_id, Some(filename));
+    let size = std::fs::metadata(&path).ok()?.len();
+    if size >= max_bytes {
+        return None;
+    }
+    let content = std::fs::read(&path)
+        .map_err(|e| log::warn!("Failed to read output file {} for the response: {}", path.display(), e))
+        .ok()?;
+    Some(json!({
+        "filename": filename,
+        "contentType": content_type,
+        "size": size,
+        "encoding": "base64",
+        "content": openssl::base64::encode_block(&content)
+    }))
+}
+
+/// Refuses access to a result file with 403 unless the request carries a valid signature of
+/// `path`, when result URLs are signed (see `url_signing`). Trusted clients of the Unix domain
+/// socket need no signature (see `unix_socket.rs`).
+fn check_result_access(req: &HttpRequest, path: &str) -> Result<(), HttpResponse> {
+    if is_trusted_peer(req) {
+        return Ok(());
+    }
+    verify_path(path, req.query_string())
+        .map_err(|e| HttpResponse::Forbidden().json(json!({ "error": e })))
+}
+
+/// Downloads output files of a chained call into the outputs folder of a request.
+///
+/// Fails without keeping any of the files if one cannot be downloaded or the files are over
+/// `WASMIOT_CHAIN_MIRROR_MAX_BYTES` in total. The bytes downloaded are added to `received`,
+/// whether the copies are kept or not.
+///
+/// # Returns
+/// The URLs of the copies on this device, in the order of `urls`.
+pub async fn mirror_outputs(
+    client: &reqwest::Client,
+    deployment_id: &str,
+    module_name: &str,
+    request_id: &str,
+    urls: &[String],
+    received: &mut u64,
+) -> Result<Vec<String>, String> {
+    let max_bytes = get_chain_mirror_max_bytes();
+    if max_bytes == 0 {
+        return Err("Mirroring is disabled".to_string());
+    }
+    let mut written = Vec::new();
+    let mut local_urls = Vec::new();
+    let mut total: u64 = 0;
+    for url in urls {
+        let copied = mirror_output(client, url, max_bytes, &mut total, |filename| {
+            get_output_path(deployment_id, module_name, request_id, Some(filename))
+        }).await;
+        match copied {
+            Ok((filename, path)) => {
+                local_urls.push(make_output_url(deployment_id, module_name, request_id, &filename));
+                written.push(path);
+            }
+            Err(e) => {
+                *received += total;
+                for path in written {
+                    std::fs::remove_file(path).ok();
+                }
+                return Err(e);
+            }
+        }
+    }
+    *received += total;
+    Ok(local_urls)
+}
+
+/// Downloads one output file of a chained call to the path given for its name by `target`,
+/// counting its size into `total`.
+async fn mirror_output(
+    client: &reqwest::Client,
+    url: &str,
+    max_bytes: u64,
+    total: &mut u64,
+    target: impl Fn(&str) -> PathBuf,
+) -> Result<(String, PathBuf), String> {
+    let filename = output_filename(url)?;
+    let response = client.get(url).send().await
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch {}: {}", url, response.status()));
+    }
+    let path = target(&filename);
+    save_output(response, url, &path, max_bytes, total).await?;
+    Ok((filename, path))
+}
+
+/// Name of the file an output URL points to.
+fn output_filename(url: &str) -> Result<String, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid output URL {}: {}", url, e))?;
+    parsed.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|name| urlencoding::decode(name).ok())
+        .map(|name| name.to_string())
+        .filter(|name| is_plain_filename(name))
+        .ok_or_else(|| format!("No file name in output URL {}", url))
+}
+
+/// Writes the body of a response fetched from `url` to `path` chunk by chunk, counting its size
+/// into `total`, so that large files are never held in memory.
+///
+/// The file is removed if it goes over `max_bytes` or anything else fails.
+async fn save_output(
+    mut response: reqwest::Response,
+    url: &str,
+    path: &Path,
+    max_bytes: u64,
+    total: &mut u64,
+) -> Result<(), String> {
+    if response.content_length().is_some_and(|length| *total + length > max_bytes) {
+        return Err(format!("Outputs are over the limit of {} bytes", max_bytes));
+    }
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await
+            .map_err(|e| format!("Failed to create output directory {}: {}", parent.display(), e))?;
+    }
+    let mut file = tokio::fs::File::create(path).await
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let result = async {
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("Failed to fetch {}: {}", url, e))? {
+            *total += chunk.len() as u64;
+            if *total > max_bytes {
+                return Err(format!("Outputs are over the limit of {} bytes", max_bytes));
+            }
+            file.write_all(&chunk).await.map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        file.flush().await.map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }.await;
+    if let Err(e) = result {
+        drop(file);
+        tokio::fs::remove_file(path).await.ok();
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// What the `resultUrl` of a chained call points to.
+pub enum ChainedResult {
+    /// The request history entry of the chained call.
+    Json(Value),
+    /// An output file, with its body not read yet.
+    File(reqwest::Response),
+}
+
+/// Fetches the `resultUrl` of a chained call.
+///
+/// Only JSON responses are read into memory. Anything else is left to `save_chained_result`,
+/// so that a large file at the end of the chain is not buffered on the way to disk. The size of
+/// a JSON response is added to `received`.
+pub async fn fetch_chained_result(client: &reqwest::Client, url: &str, received: &mut u64) -> Result<ChainedResult, String> {
+    let response = client.get(url).send().await
+        .map_err(|e| format!("Failed to fetch resultUrl {}: {}", url, e))?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to fetch resultUrl {}: {}", url, response.status()));
+    }
+    let is_file = response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|content_type| !content_type.starts_with("application/json"));
+    if is_file {
+        return Ok(ChainedResult::File(response));
+    }
+    let body = response.bytes().await
+        .map_err(|e| format!("Failed to fetch resultUrl {}: {}", url, e))?;
+    *received += body.len() as u64;
+    serde_json::from_slice(&body)
+        .map(ChainedResult::Json)
+        .map_err(|e| format!("Invalid JSON from resultUrl {}: {}", url, e))
+}
+
+/// Streams a file fetched by `fetch_chained_result` into the outputs folder of a request,
+/// within `WASMIOT_CHAIN_MIRROR_MAX_BYTES`, adding the bytes downloaded to `received`.
+///
+/// # Returns
+/// The URL of the copy on this device.
+pub async fn save_chained_result(
+    response: reqwest::Response,
+    deployment_id: &str,
+    module_name: &str,
+    request_id: &str,
+    received: &mut u64,
+) -> Result<String, String> {
+    let max_bytes = get_chain_mirror_max_bytes();
+    if max_bytes == 0 {
+        return Err("Mirroring is disabled".to_string());
+    }
+    let url = response.url().to_string();
+    let filename = output_filename(&url)?;
+    let path = get_output_path(deployment_id, module_name, request_id, Some(&filename));
+    let mut total = 0;
+    let saved = save_output(response, &url, &path, max_bytes, &mut total).await;
+    *received += total;
+    saved?;
+    Ok(make_output_url(deployment_id, module_name, request_id, &filename))
 }
 
+/// Makes a multipart part of a file that is streamed from disk as the request is sent.
+///
+/// The size of the part is known, so that a request made of such parts has a Content-Length
+/// the receiving supervisor can check against its limits before reading the files.
+pub async fn file_part(path: &Path) -> Result<reqwest::multipart::Part, String> {
+    let file = tokio::fs::File::open(path).await
+        .map_err(|e| format!("Failed to open file for subcall: {}", e))?;
+    let size = file.metadata().await
+        .map_err(|e| format!("Failed to open file for subcall: {}", e))?
+        .len();
+    Ok(reqwest::multipart::Part::stream_with_length(reqwest::Body::from(file), size))
+}
+
+/// Streams an upload to `path` chunk by chunk with `tokio::fs`, so that a slow disk does not
+/// hold up the other requests of the worker receiving it.
+///
+/// Uploads over `max_bytes` are refused, and the file is removed if anything fails.
+///
+/// # Returns
+/// The size of the upload, or the status and error to respond with.
+async fn save_upload<S, E>(mut upload: S, path: &Path, what: &str, max_bytes: Option<u64>) -> Result<u64, (StatusCode, String)>
+where
+    S: futures_util::Stream<Item = Result<web::Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let mut file = tokio::fs::File::create(path).await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save {}: {}", what, e)))?;
+    let result = async {
+        let mut written: u64 = 0;
+        while let Some(chunk) = upload.next().await {
+            let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to receive {}: {}", what, e)))?;
+            written += chunk.len() as u64;
+            if let Some(max_bytes) = max_bytes
+                && written > max_bytes
+            {
+                return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("The {} is over the limit of {} bytes", what, max_bytes)));
+            }
+            file.write_all(&chunk).await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save {}: {}", what, e)))?;
+        }
+        // Wait for the last writes to reach the file before it is used
+        file.flush().await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save {}: {}", what, e)))?;
+        Ok(written)
+    }.await;
+    if result.is_err() {
+        drop(file);
+        tokio::fs::remove_file(path).await.ok();
+    }
+    result
+}
 
 /// Helper to save a deployment to deployment folder as json.
 fn save_deployment_to_disk(deployment: &Deployment) -> Result<(), String> {
@@ -149,6 +700,207 @@ fn save_deployment_to_disk(deployment: &Deployment) -> Result<(), String> {
     Ok(())
 }
 
+/// Returns a copy of a module manifest with the values of its `secrets` replaced, for logging.
+fn redact_secrets(module: &Value) -> Value {
+    let mut redacted = module.clone();
+    if let Some(secrets) = redacted.get_mut("secrets").and_then(Value::as_object_mut) {
+        for value in secrets.values_mut() {
+            *value = json!("<redacted>");
+        }
+    }
+    redacted
+}
+
+/// Puts the files of a deployment back as they were before a failed request to create it,
+/// unless disarmed.
+///
+/// A new deployment is written straight to its module and params folders, which are removed on
+/// failure along with its persisted JSON, including serialized modules. A deployment replacing
+/// one of the same ID is written to staging folders next to them instead, so that the files of
+/// the deployment it replaces stay as they are while it is downloaded. `swap` then moves those
+/// files aside and puts the new ones in their place, and a failure after that moves the old
+/// files and JSON back. With `keepPartial`, the files of the failed deployment are kept, in the
+/// staging folders if it was to replace another.
+struct DeploymentRollback {
+    deployment_id: String,
+    keep_partial: bool,
+    /// Whether the files of a deployment of the same ID are being replaced
+    replaces: bool,
+    /// Folders whose files have been moved aside by `swap`
+    swapped: Vec<&'static Path>,
+    /// Whether the persisted JSON has been copied aside by `swap`
+    json_swapped: bool,
+    armed: bool,
+}
+
+impl DeploymentRollback {
+    /// Starts creating a deployment, clearing whatever an earlier failed attempt left behind.
+    fn new(deployment_id: &str, keep_partial: bool, replaces: bool) -> Self {
+        let rollback = DeploymentRollback {
+            deployment_id: deployment_id.to_string(),
+            keep_partial,
+            replaces,
+            swapped: Vec::new(),
+            json_swapped: false,
+            armed: true,
+        };
+        for root in [&**MODULE_FOLDER, &**PARAMS_FOLDER] {
+            std::fs::remove_dir_all(rollback.staged(root)).ok();
+            std::fs::remove_dir_all(rollback.replaced(root)).ok();
+            if !replaces {
+                std::fs::remove_dir_all(root.join(deployment_id)).ok();
+            }
+        }
+        rollback
+    }
+
+    /// The folder under `root` the files of the deployment are staged in.
+    fn staged(&self, root: &Path) -> PathBuf {
+        root.join(format!(".{}.staged", self.deployment_id))
+    }
+
+    /// The folder under `root` the files of the replaced deployment are moved aside to.
+    fn replaced(&self, root: &Path) -> PathBuf {
+        root.join(format!(".{}.replaced", self.deployment_id))
+    }
+
+    /// Where the persisted JSON of the replaced deployment is copied aside to.
+    fn replaced_json(&self) -> PathBuf {
+        get_deployment_path(&self.deployment_id).with_extension("json.replaced")
+    }
+
+    /// The folder under `root` the files of the deployment are written to.
+    fn target(&self, root: &Path) -> PathBuf {
+        if self.replaces { self.staged(root) } else { root.join(&self.deployment_id) }
+    }
+
+    /// Where a file written for the deployment ends up once the deployment is in place.
+    fn placed(&self, path: &Path) -> PathBuf {
+        for root in [&**MODULE_FOLDER, &**PARAMS_FOLDER] {
+            if let Ok(relative) = path.strip_prefix(self.target(root)) {
+                return root.join(&self.deployment_id).join(relative);
+            }
+        }
+        path.to_path_buf()
+    }
+
+    /// Puts the files written for the deployment in place of those of the deployment it
+    /// replaces, which are kept aside with its persisted JSON until the request finishes. The
+    /// inputs and outputs of requests to the replaced deployment stay in place.
+    fn swap(&mut self) -> std::io::Result<()> {
+        if !self.replaces {
+            return Ok(());
+        }
+        let json_path = get_deployment_path(&self.deployment_id);
+        if json_path.exists() {
+            std::fs::copy(&json_path, self.replaced_json())?;
+        }
+        self.json_swapped = true;
+        for root in [&**MODULE_FOLDER, &**PARAMS_FOLDER] {
+            let current = root.join(&self.deployment_id);
+            if current.exists() {
+                std::fs::rename(&current, self.replaced(root))?;
+            }
+            self.swapped.push(root);
+            std::fs::rename(self.staged(root), &current)?;
+        }
+        move_request_files(&self.replaced(&PARAMS_FOLDER), &PARAMS_FOLDER.join(&self.deployment_id));
+        Ok(())
+    }
+
+    /// Keeps the files, called once the deployment has been created successfully.
+    fn disarm(&mut self) {
+        self.armed = false;
+        for root in [&**MODULE_FOLDER, &**PARAMS_FOLDER] {
+            std::fs::remove_dir_all(self.replaced(root)).ok();
+        }
+        std::fs::remove_file(self.replaced_json()).ok();
+    }
+}
+
+impl Drop for DeploymentRollback {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        log::warn!("Rolling back files of failed deployment '{}'", self.deployment_id);
+        if !self.replaces {
+            if self.keep_partial {
+                return;
+            }
+            for dir in [MODULE_FOLDER.join(&self.deployment_id), PARAMS_FOLDER.join(&self.deployment_id)] {
+                if dir.exists() && let Err(e) = std::fs::remove_dir_all(&dir) {
+                    error!("Failed to remove {} of failed deployment: {}", dir.display(), e);
+                }
+            }
+            let json_path = get_deployment_path(&self.deployment_id);
+            if json_path.exists() && let Err(e) = std::fs::remove_file(&json_path) {
+                error!("Failed to remove {} of failed deployment: {}", json_path.display(), e);
+            }
+            return;
+        }
+
+        // The files of the replaced deployment are put back, the new ones going back to staging
+        for root in std::mem::take(&mut self.swapped).into_iter().rev() {
+            let current = root.join(&self.deployment_id);
+            let (staged, replaced) = (self.staged(root), self.replaced(root));
+            if !staged.exists() && current.exists() {
+                if root == &**PARAMS_FOLDER {
+                    move_request_files(&current, &replaced);
+                }
+                if let Err(e) = std::fs::rename(&current, &staged) {
+                    error!("Failed to move {} of failed deployment aside: {}", current.display(), e);
+                    continue;
+                }
+            }
+            if replaced.exists() && let Err(e) = std::fs::rename(&replaced, &current) {
+                error!("Failed to put back {} of replaced deployment: {}", current.display(), e);
+            }
+        }
+        if self.json_swapped {
+            let (json_path, replaced_json) = (get_deployment_path(&self.deployment_id), self.replaced_json());
+            let restored = if replaced_json.exists() {
+                std::fs::rename(&replaced_json, &json_path)
+            } else {
+                std::fs::remove_file(&json_path).or_else(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e),
+                })
+            };
+            if let Err(e) = restored {
+                error!("Failed to put back {} of replaced deployment: {}", json_path.display(), e);
+            }
+        }
+        if !self.keep_partial {
+            for root in [&**MODULE_FOLDER, &**PARAMS_FOLDER] {
+                std::fs::remove_dir_all(self.staged(root)).ok();
+            }
+        }
+    }
+}
+
+/// Moves the inputs and outputs of requests of each module folder under `from` to the module
+/// folder of the same name under `to`, if there is one.
+fn move_request_files(from: &Path, to: &Path) {
+    let Ok(modules) = std::fs::read_dir(from) else { return };
+    for module in modules.filter_map(Result::ok) {
+        let target = to.join(module.file_name());
+        if !target.is_dir() {
+            continue;
+        }
+        for name in [INPUTS_FOLDER_NAME, OUTPUTS_FOLDER_NAME] {
+            let (source, destination) = (module.path().join(name), target.join(name));
+            if !source.exists() {
+                continue;
+            }
+            // An empty folder made for the new files gives way to the existing ones
+            std::fs::remove_dir(&destination).ok();
+            if !destination.exists() && let Err(e) = std::fs::rename(&source, &destination) {
+                log::warn!("Failed to move {} to {}: {}", source.display(), destination.display(), e);
+            }
+        }
+    }
+}
 
 
 /// Executes the WebAssembly function for the given request and performs any chained subcalls.
@@ -158,10 +910,43 @@ fn save_deployment_to_disk(deployment: &Deployment) -> Result<(), String> {
 /// 2. Interprets its result,
 /// 3. Initiates a next call if the deployment specifies one,
 /// 4. Returns the result or sub-response.
+///
+/// An output file below `WASMIOT_INLINE_RESULT_MAX_BYTES` is included in the result as
+/// `inline` (see `inline_output`), and is used as is from the response of a chained call.
+///
+/// Output files of a chained call are copied into the outputs folder of this request, unless
+/// the deployment opted out or they are over `WASMIOT_CHAIN_MIRROR_MAX_BYTES`, so that the
+/// `outputs` of the request point to this device instead of the one further down the chain.
+/// Each chained call is recorded in the `chain_trace` of the request.
+///
+/// A watcher of the request is told how the execution progresses (see `execution_events.rs`).
 pub async fn do_wasm_work(entry: &mut RequestEntry) -> Result<Value, String> {
-    let mut deployments = DEPLOYMENTS.lock();
-    let deployment = deployments.get_mut(&entry.deployment_id)
+    let _in_flight = ExecutionGuard::new(&entry.deployment_id);
+    track_execution(entry);
+    emit_execution_event(&entry.request_id, ExecutionEvent::Started);
+    let shared = get_deployment(&entry.deployment_id)
         .ok_or_else(|| format!("Deployment '{}' not found", entry.deployment_id))?;
+    let mut deployment = shared.lock().await;
+    entry.inputs_retained = !entry.request_files.is_empty() && deployment.endpoints
+        .get(&entry.module_name)
+        .and_then(|functions| functions.get(&entry.function_name))
+        .is_some_and(|endpoint| endpoint.keep_inputs);
+    let mount_layout = deployment.modules.get(&entry.module_name)
+        .map(|module| module.mount_layout)
+        .unwrap_or_default();
+    let linked_inputs = LinkedInputs::of(entry, mount_layout);
+    if !deployment.active {
+        return Err(format!("Deployment '{}' is paused", entry.deployment_id));
+    }
+    if deployment.is_degraded() {
+        return Err(format!(
+            "Deployment '{}' is degraded, missing files: {}",
+            entry.deployment_id, deployment.missing_files.join("; ")
+        ));
+    }
+    if let Some(load_error) = deployment.load_errors.get(&entry.module_name) {
+        return Err(format!("Module '{}' failed to load: {}", entry.module_name, load_error));
+    }
 
     let func_name = function_name!().to_string();
     let module_name_clone = entry.module_name.clone();
@@ -179,13 +964,13 @@ pub async fn do_wasm_work(entry: &mut RequestEntry) -> Result<Value, String> {
         .as_object()
         .map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
         .unwrap_or_else(IndexMap::new);
-    let (_module, wasm_args) = deployment.prepare_for_running(
+    let (module, wasm_args) = deployment.prepare_for_running(
         &entry.deployment_id,
         &entry.module_name,
         &entry.function_name,
         &request_args,
         &entry.request_files,
-    ).await?;
+    ).instrument(tracing::info_span!("prepare")).await?;
     let func_name = function_name!().to_string();
     let entry_function_name = entry.function_name.clone();
     let entry_clone = entry.clone();
@@ -198,26 +983,63 @@ pub async fn do_wasm_work(entry: &mut RequestEntry) -> Result<Value, String> {
         ).await;
     });
 
+    // Components take the arguments as JSON, converted to the types of their parameters
+    let component_args = match module.is_component() {
+        true => Some(deployment.bind_arguments(&entry.module_name, &entry.function_name, &request_args)?),
+        false => None,
+    };
+    // A string result is read from the memory of the module, where the function points at it
+    let from_memory = deployment.endpoint_result_source(&entry.module_name, &entry.function_name) == Some(ResultSource::Memory);
     let runtime = deployment.runtimes.get_mut(&entry.module_name)
         .ok_or_else(|| format!("Runtime not found for module '{}'", entry.module_name))?;
+    // Healthchecks are stopped within the time limit of their own, not left running
+    let timeout = match entry.method == HEALTHCHECK_METHOD {
+        true => get_healthcheck_timeout(),
+        false => get_module_timeout(),
+    };
 
-    let return_count = runtime.get_return_types(&entry.module_name, &entry.function_name).await.len();
-    let output_vals = runtime.run_function(
-        &entry.module_name,
-        &entry.function_name,
-        wasm_args,
-        return_count,
-    ).await;
-
-    let raw_output = output_vals.first().map(|v| match v {
-        Val::I32(i) => json!(i),
-        Val::I64(i) => json!(i),
-        Val::F32(f) => json!(f32::from_bits(*f)),
-        Val::F64(f) => json!(f64::from_bits(*f)),
-        _ => Value::Null,
-    }).unwrap_or(Value::Null);
-
-    let raw_output_clone = raw_output.clone();
+    // Functions that return nothing have no result to interpret or pass on
+    let raw_output = match component_args {
+        Some(args) => {
+            let returns_nothing = module.component_signatures.get(&entry.function_name)
+                .is_some_and(|signature| signature.results.is_empty());
+            let output = runtime.run_component_function_within(
+                &entry.module_name,
+                &entry.function_name,
+                &args,
+                timeout,
+            ).instrument(tracing::info_span!("run")).await?;
+            (!returns_nothing).then_some(output)
+        }
+        None => {
+            let return_count = runtime.get_signature(&entry.module_name, &entry.function_name)
+                .map_or(0, |signature| signature.results.len());
+            // A trap fails the execution with the trap and where in the module it happened
+            let output_vals = runtime.run_function_within(
+                &entry.module_name,
+                &entry.function_name,
+                wasm_args,
+                return_count,
+                timeout,
+            ).instrument(tracing::info_span!("run")).await?;
+            match (from_memory, output_vals.as_slice()) {
+                (true, [wasmtime::Val::I32(ptr), wasmtime::Val::I32(len)]) => {
+                    let (text, lossy) = runtime.read_string(&entry.module_name, *ptr as u32, *len as u32).await?;
+                    entry.result_lossy = lossy;
+                    Some(Value::String(text))
+                }
+                (true, _) => return Err(format!(
+                    "Function '{}' must return a pointer and a length (two i32) for its result to be read from memory",
+                    entry.function_name
+                )),
+                (false, _) => output_vals.first().map(wasm_val_json),
+            }
+        }
+    };
+    record_module_memory(&entry.deployment_id, &entry.module_name, runtime);
+    emit_execution_event(&entry.request_id, ExecutionEvent::Returned(raw_output.clone().unwrap_or(Value::Null)));
+
+    let raw_output_clone = raw_output.clone().unwrap_or(Value::Null);
     let entry_clone = entry.clone();
     let func_name = function_name!().to_string();
     tokio::spawn(async move {
@@ -230,7 +1052,7 @@ pub async fn do_wasm_work(entry: &mut RequestEntry) -> Result<Value, String> {
         .await;
     });
 
-    let (this_result, next_call) = deployment.interpret_call_from(
+    let (this_result, next_calls) = deployment.interpret_call_from(
         &entry.deployment_id,
         &entry.module_name,
         &entry.function_name,
@@ -250,14 +1072,26 @@ pub async fn do_wasm_work(entry: &mut RequestEntry) -> Result<Value, String> {
             ).await;
         });
     }
+    let mut inline = None;
     if let Some(EndpointData::StrList(filenames)) = &this_result.1 {
+        // Keep the outputs of each request apart, so that their URLs keep serving them
+        for filename in filenames {
+            store_request_output(&entry.deployment_id, &entry.module_name, mount_layout, &entry.request_id, filename)?;
+        }
         if let Some(filename) = filenames.first() {
-            let result_url = make_output_url(&entry.deployment_id, &entry.module_name, filename);
+            let content_type = deployment.mounts.get(&entry.module_name)
+                .and_then(|functions| functions.get(&entry.function_name))
+                .and_then(|stages| stages.get(&MountStage::OUTPUT))
+                .and_then(|mounts| mounts.iter().find(|mount| &mount.path == filename))
+                .map(|mount| mount.media_type.clone())
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            inline = inline_output(&entry.deployment_id, &entry.module_name, &entry.request_id, filename, &content_type);
+            let result_url = make_output_url(&entry.deployment_id, &entry.module_name, &entry.request_id, filename);
             let result_url_clone = result_url.clone();
             let func_name = function_name!().to_string();
             if let Some(EndpointData::StrList(filenames)) = &this_result.1 {
                 entry.outputs = filenames.iter()
-                    .map(|f| make_output_url(&entry.deployment_id, &entry.module_name, f))
+                    .map(|f| make_output_url(&entry.deployment_id, &entry.module_name, &entry.request_id, f))
                     .collect();
             }
             let entry_clone = entry.clone();
@@ -277,110 +1111,214 @@ pub async fn do_wasm_work(entry: &mut RequestEntry) -> Result<Value, String> {
         EndpointArgs::StrList(vs) => Value::Array(vs.into_iter().map(Value::String).collect()),
         EndpointArgs::Dict(map) => Value::Object(map.into_iter().collect()),
     });
+    if raw_output.is_none() {
+        entry.has_result = false;
+        entry.result = Some(json!({ "status": "completed" }));
+    }
 
-    if let Some(call_data) = next_call {
-        let mut files = HashMap::new();
-        let EndpointData::StrList(ref file_names) = call_data.files;
+    if let Some(first_call) = next_calls.first() {
+        let mut file_paths = Vec::new();
+        let EndpointData::StrList(ref file_names) = first_call.files;
         for name in file_names {
-            let full_path = get_params_path(&entry.deployment_id, &entry.module_name, Some(name));
-            let file = std::fs::File::open(&full_path)
-                .map_err(|e| format!("Failed to open file for subcall: {}", e))?;
-            files.insert(name.clone(), file);
-        }
-
-        let mut headers = reqwest::header::HeaderMap::new();
-        for (k, v) in &call_data.headers {
-            if let (Ok(key), Ok(val)) = (
-                reqwest::header::HeaderName::from_bytes(k.as_bytes()),
-                reqwest::header::HeaderValue::from_str(v),
-            ) {
-                headers.insert(key, val);
-            }
+            // Outputs of this request were moved to its outputs folder, other files are still in params
+            let output_path = get_output_path(&entry.deployment_id, &entry.module_name, &entry.request_id, Some(name));
+            let full_path = if output_path.exists() {
+                output_path
+            } else {
+                get_params_path(&entry.deployment_id, &entry.module_name, Some(name))
+            };
+            file_paths.push((name.clone(), full_path));
         }
 
-        let module_name_clone = entry.module_name.clone();
-        let call_data_url_clone = call_data.url.clone();
-        let func_name = function_name!().to_string();
-        let entry_clone = entry.clone();
-        task::spawn(async move {
-            send_log(
-                "DEBUG",
-                &format!("Making sub-call from '{}' to '{}'", &module_name_clone, &call_data_url_clone),
-                &func_name,
-                Some(&entry_clone),
-            ).await;
-        });
-
-        drop(deployments);
-
-        let mut form = reqwest::multipart::Form::new();
-
-        for (name, mut file) in files {
-            let mut buf = Vec::new();
-            use std::io::Read;
-            file.read_to_end(&mut buf).map_err(|e| format!("Failed to read file for multipart: {}", e))?;
-
-            form = form.part(name.clone(), reqwest::multipart::Part::bytes(buf).file_name(name));
-        }
+        let mirror_chained_results = deployment.mirror_chained_results;
+        let devices = deployment.devices.clone();
+        // Other requests to the deployment need not wait for the chained call
+        drop(linked_inputs);
+        drop(deployment);
 
+        // Each fallback target is tried with the same payload when the ones before it are
+        // unreachable or fail with a server error
         let client = reqwest::Client::new();
-        let response = client
-            .request(
-                call_data.method.to_string().to_uppercase().parse().unwrap_or(reqwest::Method::POST),
-                &call_data.url
-            )
-            .headers(headers)
-            .multipart(form)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to send chained request: {}", e))?;
+        let targets = next_calls.len();
+        let mut served = None;
+        let self_url = public_url("");
+        for (attempt, mut call_data) in next_calls.into_iter().enumerate() {
+            let last = attempt + 1 == targets;
+            // Placeholders of devices are resolved for each call, as the devices may have moved
+            let sent = match resolve_target_url(&call_data.url, &devices, &self_url) {
+                Ok(url) => {
+                    call_data.url = url;
+                    send_chained_call(&client, &call_data, &file_paths, entry).await
+                }
+                Err(e) => Err(e),
+            };
+            let (retryable, error) = match sent {
+                Ok(response) if response.status().is_success() => {
+                    served = Some((call_data, response));
+                    break;
+                }
+                Ok(response) => {
+                    // Pass on the error of a failed chained call, e.g. when the target deployment is paused
+                    let status = response.status();
+                    let body = response.json::<Value>().await.ok();
+                    // A function that ran and failed there would fail on the fallback targets as well
+                    let executed = body.as_ref().is_some_and(|body| body.get("entry").is_some());
+                    let error = body
+                        .and_then(|body| body.get("error").and_then(Value::as_str).map(str::to_string))
+                        .unwrap_or_else(|| status.to_string());
+                    (status.is_server_error() && !executed, format!("Chained call to {} failed ({}): {}", call_data.url, status.as_u16(), error))
+                }
+                Err(e) => (true, e),
+            };
+            if targets > 1 {
+                record_chain_step(entry, ChainStep {
+                    url: call_data.url.clone(),
+                    outputs: Vec::new(),
+                    mirrored: false,
+                    mirror_error: None,
+                    error: Some(error.clone()),
+                });
+            }
+            if last || !retryable {
+                return Err(error);
+            }
+            log::warn!("{}, trying the next fallback target", error);
+        }
+        let Some((call_data, response)) = served else {
+            return Err("No target of the chained call was tried".to_string());
+        };
+        entry.served_by = Some(call_data.url.clone());
+        // The payload is counted once, for the target that served it
+        entry.bytes_out += chained_payload_bytes(&call_data, &file_paths).await;
 
         // Assume JSON response from the chained call
-        let chained_json: Value = response
-            .json()
+        let body = response
+            .bytes()
             .await
+            .map_err(|e| format!("Failed to read response from {}: {}", call_data.url, e))?;
+        entry.bytes_in += body.len() as u64;
+        let chained_json: Value = serde_json::from_slice(&body)
             .map_err(|e| format!("Invalid response JSON from {}: {}", call_data.url, e))?;
 
-        // If there's a resultUrl, fetch it (also expected to be JSON)
+        // A small output is included in the response, so there is no need to fetch it
+        if let Some(result) = chained_json.get("result").filter(|result| result.get("inline").is_some()) {
+            entry.success = true;
+            return Ok(result.clone());
+        }
+
+        // If there's a resultUrl, fetch it (expected to be JSON, unless it is an output file)
         if let Some(url) = chained_json.get("resultUrl").and_then(|v| v.as_str()) {
-            let fetched_json: Value = client
-                .get(url)
-                .send()
-                .await
-                .map_err(|e| format!("Failed to fetch resultUrl {}: {}", url, e))?
-                .json()
-                .await
-                .map_err(|e| format!("Invalid JSON from resultUrl {}: {}", url, e))?;
+            let fetched_json = match fetch_chained_result(&client, url, &mut entry.bytes_in).await? {
+                ChainedResult::Json(fetched_json) => fetched_json,
+                ChainedResult::File(response) => {
+                    let mut step = ChainStep {
+                        url: call_data.url.clone(),
+                        outputs: vec![url.to_string()],
+                        mirrored: false,
+                        mirror_error: None,
+                        error: None,
+                    };
+                    let saved = if mirror_chained_results {
+                        save_chained_result(response, &entry.deployment_id, &entry.module_name, &entry.request_id, &mut entry.bytes_in).await
+                    } else {
+                        Err("Mirroring is disabled for the deployment".to_string())
+                    };
+                    match saved {
+                        Ok(local_url) => {
+                            entry.outputs = vec![local_url];
+                            step.mirrored = true;
+                        }
+                        Err(e) => {
+                            log::warn!("Not mirroring result file of chained call to {}: {}", call_data.url, e);
+                            entry.outputs = vec![url.to_string()];
+                            step.mirror_error = Some(e);
+                        }
+                    }
+                    record_chain_step(entry, step);
+                    entry.success = true;
+                    return Ok(json!({ "result": Value::Null, "outputs": entry.outputs }));
+                }
+            };
 
             // If the fetched JSON contains a "result" key, return that; else return the fetched JSON.
-            let final_json: Value = fetched_json
+            let mut final_json: Value = fetched_json
                 .get("result")
                 .cloned()
                 .unwrap_or_else(|| fetched_json.clone());
 
+            // Copy output files from the end of the chain, so that they can be fetched from here
+            let remote_outputs: Vec<String> = fetched_json.get("outputs")
+                .and_then(Value::as_array)
+                .map(|outputs| outputs.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default();
+            let mut step = ChainStep {
+                url: call_data.url.clone(),
+                outputs: remote_outputs.clone(),
+                mirrored: false,
+                mirror_error: None,
+                error: None,
+            };
+            if !remote_outputs.is_empty() {
+                let mirrored = if mirror_chained_results {
+                    mirror_outputs(&client, &entry.deployment_id, &entry.module_name, &entry.request_id, &remote_outputs, &mut entry.bytes_in).await
+                } else {
+                    Err("Mirroring is disabled for the deployment".to_string())
+                };
+                match mirrored {
+                    Ok(local_outputs) => {
+                        entry.outputs = local_outputs;
+                        step.mirrored = true;
+                    }
+                    Err(e) => {
+                        log::warn!("Not mirroring outputs of chained call to {}: {}", call_data.url, e);
+                        entry.outputs = remote_outputs;
+                        step.mirror_error = Some(e);
+                    }
+                }
+                final_json = json!({ "result": final_json, "outputs": entry.outputs });
+            }
+            record_chain_step(entry, step);
+
             // Return the final JSON, but dont overwrite own results in history with it
             entry.success = true;
             return Ok(final_json);
         }
 
         // No resultUrl -> record and return the original chained JSON
+        record_chain_step(entry, ChainStep {
+            url: call_data.url.clone(),
+            outputs: Vec::new(),
+            mirrored: false,
+            mirror_error: None,
+            error: None,
+        });
         entry.success = true;
         return Ok(chained_json);
 
     }
 
-    Ok(json!({ "result": entry.result }))
+    let mut final_json = json!({ "result": entry.result });
+    if let Some(inline) = inline {
+        final_json["inline"] = inline;
+    }
+    Ok(final_json)
 }
 
 /// Executes a WebAssembly function call and records its result in history.
 ///
 /// This function performs the full execution lifecycle of a `RequestEntry`, including:
-/// - Calling the Wasm function via `do_wasm_work()`
+/// - Calling the Wasm function via `do_wasm_work()`, within the `execution` span of the
+///   request (see `telemetry.rs`)
 /// - Setting the result and success state
 /// - Logging the outcome (both to stdout and external log sink)
-/// - Appending the result to global `REQUEST_HISTORY`
+/// - Appending the result to the request history, and no longer tracking the execution
+///   as running (see `shutdown.rs`)
+/// - Uploading the output files to the result sink of the deployment, if it has one (see
+///   `result_sink.rs`)
+/// - Posting the entry to its `callback_url` in the background, if it has one (see `callback.rs`)
 ///
 /// This is the main entry point for any completed function execution (GET or POST).
+/// The function runs on an execution thread (see `execution.rs`), not on the HTTP worker.
 ///
 /// # Arguments
 /// - `entry`: The request entry to execute
@@ -390,15 +1328,47 @@ pub async fn do_wasm_work(entry: &mut RequestEntry) -> Result<Value, String> {
 /// - An optional `Value` containing the final result from the execution
 pub async fn make_history(mut entry: RequestEntry) -> (RequestEntry, Option<Value>) {
     let mut final_opt: Option<Value> = None;
-
-    match do_wasm_work(&mut entry).await {
-        Ok(final_json) => {
-            entry.success = true;
-            final_opt = Some(final_json);
+    let started = std::time::Instant::now();
+    audit_execution("execution.start", &entry);
+
+    let span = execution_span(&entry);
+    let job_entry = entry.clone();
+    let job_span = span.clone();
+    let queued = std::time::Instant::now();
+    let outcome = run_with_priority(entry.priority, move || async move {
+        let mut entry = job_entry;
+        entry.queue_wait_ms = Some(queued.elapsed().as_millis() as u64);
+        let result = match start_action(&entry.request_id) {
+            true => do_wasm_work(&mut entry).instrument(job_span).await,
+            false => Err(CANCELLED_ERROR.to_string()),
+        };
+        (entry, result)
+    }).await;
+    let result = match outcome {
+        Ok((executed, result)) => {
+            entry = executed;
+            result
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(mut final_json) => {
+            entry.success = true;
+            if let Some(sink) = result_sink_of(&entry.deployment_id).await {
+                upload_outputs(&mut entry, &sink).await;
+                if final_json.get("outputs").is_some() {
+                    final_json["outputs"] = json!(entry.outputs);
+                }
+            }
+            capture_example(&entry).await;
+            final_opt = Some(final_json);
         }
         Err(err) => {
             entry.result = Some(Value::String(err.clone()));
             entry.success = false;
+            span.record("otel.status_code", "ERROR");
+            span.record("error", err.as_str());
             log::error!("Error during Wasm execution: {}", err);
             let func_name = function_name!().to_string();
             let entry_clone = entry.clone();
@@ -413,39 +1383,412 @@ pub async fn make_history(mut entry: RequestEntry) -> (RequestEntry, Option<Valu
         }
     }
 
-    REQUEST_HISTORY.lock().push(entry.clone());
+    if !entry.request_files.is_empty() {
+        let executed = entry.clone();
+        task::spawn_blocking(move || remove_request_inputs(&executed)).await.ok();
+    }
+
+    record_execution(&entry.deployment_id, entry.success);
+    record_transferred(entry.bytes_in, entry.bytes_out);
+    record_invocation(&entry, started.elapsed());
+    audit_execution("execution.finish", &entry);
+    let evicted = lock_request_history().push(entry.clone(), get_request_history_max_entries());
+    finish_execution(&entry.request_id);
+    finish_action(&entry.request_id);
+    if entry.callback_url.is_some() {
+        task::spawn(in_current_app_state(deliver_callback(entry.clone())));
+    }
+    if !evicted.is_empty() {
+        task::spawn_blocking(move || archive_evicted(evicted)).await.ok();
+    }
     (entry, final_opt)
 }
 
+/// Size of the payload `send_chained_call` sends to a target: the output files, or else the
+/// JSON body of the call.
+async fn chained_payload_bytes(call_data: &CallData, file_paths: &[(String, PathBuf)]) -> u64 {
+    if file_paths.is_empty() {
+        return call_data.body.as_ref()
+            .and_then(|body| serde_json::to_vec(body).ok())
+            .map_or(0, |body| body.len() as u64);
+    }
+    let mut bytes = 0;
+    for (_, path) in file_paths {
+        bytes += tokio::fs::metadata(path).await.map_or(0, |metadata| metadata.len());
+    }
+    bytes
+}
+
+/// Makes a chained call of a request to one of the targets of the link of its function, with
+/// the output files in `file_paths` or the JSON body of the call.
+///
+/// # Returns
+/// The response of the target, whatever its status, or why it could not be reached.
+async fn send_chained_call(
+    client: &reqwest::Client,
+    call_data: &CallData,
+    file_paths: &[(String, PathBuf)],
+    entry: &RequestEntry,
+) -> Result<reqwest::Response, String> {
+    let mut files = HashMap::new();
+    for (name, full_path) in file_paths {
+        files.insert(name.clone(), file_part(full_path).await?);
+    }
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (k, v) in &call_data.headers {
+        if let (Ok(key), Ok(val)) = (
+            reqwest::header::HeaderName::from_bytes(k.as_bytes()),
+            reqwest::header::HeaderValue::from_str(v),
+        ) {
+            headers.insert(key, val);
+        }
+    }
+
+    let module_name_clone = entry.module_name.clone();
+    let call_data_url_clone = call_data.url.clone();
+    let func_name = function_name!().to_string();
+    let entry_clone = entry.clone();
+    task::spawn(async move {
+        send_log(
+            "DEBUG",
+            &format!("Making sub-call from '{}' to '{}'", &module_name_clone, &call_data_url_clone),
+            &func_name,
+            Some(&entry_clone),
+        ).await;
+    });
+
+    emit_execution_event(&entry.request_id, ExecutionEvent::ChainedCall(call_data.url.clone()));
+
+    // A string result goes as the JSON body the next endpoint declares, unless there are files
+    let json_body = call_data.body.as_ref().filter(|_| files.is_empty());
+    let has_files = !files.is_empty();
+    let mut form = reqwest::multipart::Form::new();
+    for (name, part) in files {
+        form = form.part(name.clone(), part.file_name(name));
+    }
+
+    // Continue the trace of this request on the next device
+    let chained_span = tracing::info_span!(
+        "chained_call",
+        url = %call_data.url,
+        http.status_code = tracing::field::Empty,
+    );
+    if let Some(traceparent) = outgoing_traceparent(&chained_span, entry.traceparent.as_deref())
+        && let Ok(value) = reqwest::header::HeaderValue::from_str(&traceparent)
+    {
+        headers.insert(TRACEPARENT_HEADER, value);
+    }
+    // The chained call waits for an execution thread with the priority of this request
+    headers.insert(PRIORITY_HEADER, reqwest::header::HeaderValue::from_static(entry.priority.as_str()));
+
+    let request = client
+        .request(
+            call_data.method.to_string().to_uppercase().parse().unwrap_or(reqwest::Method::POST),
+            &call_data.url
+        )
+        .headers(headers);
+    let request = match json_body {
+        Some(body) => request.json(body),
+        // A multipart upload without parts is refused as incomplete, so no files go as no body
+        None if !has_files => request,
+        None => request.multipart(form),
+    };
+    let response = request
+        .send()
+        .instrument(chained_span.clone())
+        .await
+        .map_err(|e| format!("Failed to send chained request to {}: {}", call_data.url, e))?;
+    chained_span.record("http.status_code", response.status().as_u16());
+    Ok(response)
+}
+
+/// Records a chained call in `chain_trace` of a request, telling the watcher of the execution
+/// about it (see `execution_events.rs`).
+fn record_chain_step(entry: &mut RequestEntry, step: ChainStep) {
+    emit_execution_event(&entry.request_id, ExecutionEvent::ChainStep(step.clone()));
+    entry.chain_trace.push(step);
+}
+
+/// Returns the result sink of a deployment, if it has one.
+async fn result_sink_of(deployment_id: &str) -> Option<ResultSink> {
+    let shared = get_deployment(deployment_id)?;
+    let deployment = shared.lock().await;
+    deployment.result_sink.clone()
+}
+
+/// Captures a successful execution as an example of the endpoint of its function (see
+/// `Deployment::record_example`), saving the deployment when one is captured.
+async fn capture_example(entry: &RequestEntry) {
+    let max_examples = get_endpoint_examples();
+    if max_examples == 0 {
+        return;
+    }
+    let Some(shared) = get_deployment(&entry.deployment_id) else {
+        return;
+    };
+    let mut deployment = shared.lock().await;
+    if deployment.record_example(entry, max_examples)
+        && let Err(e) = save_deployment_to_disk(&deployment)
+    {
+        log::warn!("Failed to save example of {}/{}: {}", entry.module_name, entry.function_name, e);
+    }
+}
+
+/// Records how delivering the callback of a request went (see `callback.rs`) in its entry, if
+/// the entry is still in the in-memory request history.
+pub fn record_callback_delivery(request_id: &str, delivery: CallbackDelivery) {
+    if let Some(entry) = lock_request_history().get_mut(request_id) {
+        entry.callback = Some(delivery);
+    }
+}
+
+/// Writes entries evicted from the request history to the archive, logging failures.
+fn archive_evicted(evicted: Vec<RequestEntry>) {
+    if let Err(e) = archive_requests(&evicted) {
+        log::error!("{}", e);
+    }
+}
+
+/// Records requests whose execution was still running when the supervisor shut down as
+/// aborted in the request history.
+pub fn record_aborted_requests(entries: Vec<RequestEntry>) {
+    let mut evicted = Vec::new();
+    let mut history = lock_request_history();
+    for mut entry in entries {
+        log::warn!(
+            "Execution of {}/{}/{} (request {}) did not finish before shutdown, recording it as aborted",
+            entry.deployment_id, entry.module_name, entry.function_name, entry.request_id
+        );
+        entry.success = false;
+        entry.aborted = true;
+        entry.result = Some(Value::String("Aborted, the supervisor shut down before the execution finished".to_string()));
+        evicted.extend(history.push(entry, get_request_history_max_entries()));
+    }
+    drop(history);
+    archive_evicted(evicted);
+}
+
+/// Saves the request history to `REQUEST_HISTORY_FILE_NAME` in the instance folder, so that
+/// the result URLs given out stay valid after a restart.
+pub fn save_request_history() -> Result<(), String> {
+    let path = INSTANCE_PATH.join(REQUEST_HISTORY_FILE_NAME);
+    let history: Vec<RequestEntry> = lock_request_history().iter().cloned().collect();
+    let file = File::create(&path).map_err(|e| {
+        format!("Failed to create request history file {}: {}", path.display(), e)
+    })?;
+    serde_json::to_writer(file, &history).map_err(|e| {
+        format!("Failed to write request history to {}: {}", path.display(), e)
+    })
+}
+
+/// Reads the request history saved by `save_request_history` on the last shutdown, if any.
+pub fn load_request_history() -> Result<usize, String> {
+    let path = INSTANCE_PATH.join(REQUEST_HISTORY_FILE_NAME);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to read request history from {}: {}", path.display(), e)),
+    };
+    let saved: Vec<RequestEntry> = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to parse request history in {}: {}", path.display(), e))?;
+    let count = saved.len();
+    // Requests made since startup come after the saved ones
+    let evicted = lock_request_history().prepend(saved, get_request_history_max_entries());
+    archive_evicted(evicted);
+    Ok(count)
+}
+
+
+/// A serialized description document together with the ETag computed from its contents.
+#[derive(Debug, Clone)]
+pub struct CachedDescription {
+    body: String,
+    etag: String,
+    /// Value of `DESCRIPTION_CACHE_GENERATION` the document was built at.
+    generation: u64,
+}
+
+impl CachedDescription {
+    /// The document with `value` added to it as `name`, for parts of it that change too often
+    /// to be cached. They are left out of the `ETag`.
+    fn with_field(mut self, name: &str, value: &impl Serialize) -> Self {
+        if let (Some(body), Ok(value)) = (self.body.strip_suffix('}'), serde_json::to_string(value)) {
+            let separator = if body.ends_with('{') { "" } else { "," };
+            self.body = format!("{}{}{}:{}}}", body, separator, json!(name), value);
+        }
+        self
+    }
+}
+
+/// Cache key for the WasmIoT device description document.
+const WASMIOT_DESCRIPTION_KEY: &str = "wasmiot-device-description";
+
+/// Cache key for the WoT Thing Description document.
+const WOT_DESCRIPTION_KEY: &str = "wot-thing-description";
+
+/// Incremented on every invalidation, so that the documents cached in each `AppState` before it
+/// are rebuilt and those built from stale data are not cached.
+///
+/// The counter is shared by every supervisor of the process, as configuration changes concern
+/// them all; a change to the deployments of one only costs the others a rebuild.
+static DESCRIPTION_CACHE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Drops the cached description documents so they are rebuilt on the next request.
+///
+/// Should be called whenever deployments, capabilities or configuration change. The device
+/// twin is told of the change too (see `twin.rs`).
+pub fn invalidate_description_cache() {
+    DESCRIPTION_CACHE_GENERATION.fetch_add(1, Ordering::SeqCst);
+    app_state().descriptions.lock().clear();
+    twin_changed();
+}
+
+/// Returns the description for `key` cached in the state of the supervisor, building and
+/// caching it with `build` if missing or stale.
+async fn get_cached_description<F>(key: &'static str, build: impl FnOnce() -> F) -> CachedDescription
+where
+    F: std::future::Future<Output = Value>,
+{
+    let state = app_state();
+    let generation = DESCRIPTION_CACHE_GENERATION.load(Ordering::SeqCst);
+    if let Some(cached) = state.descriptions.lock().get(key)
+        && cached.generation == generation
+    {
+        return cached.clone();
+    }
+
+    let body = serde_json::to_string(&build().await).unwrap_or_else(|_| "{}".to_string());
+    let etag = format!("\"{}\"", hex::encode(Sha256::digest(body.as_bytes())));
+    let cached = CachedDescription { body, etag, generation };
+
+    let mut cache = state.descriptions.lock();
+    if generation == DESCRIPTION_CACHE_GENERATION.load(Ordering::SeqCst) {
+        cache.insert(key, cached.clone());
+    }
+    cached
+}
+
+/// Checks whether any entity tag in the request's `If-None-Match` header matches `etag`.
+fn if_none_match(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(header::IF_NONE_MATCH)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+/// Serves a cached description document with `ETag` and `Cache-Control` headers,
+/// answering with `304 Not Modified` when the client already has the current version.
+fn description_response(req: &HttpRequest, cached: CachedDescription) -> HttpResponse {
+    let cache_control = format!("max-age={}", get_description_max_age());
+    if if_none_match(req, &cached.etag) {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, cached.etag))
+            .insert_header((header::CACHE_CONTROL, cache_control))
+            .finish();
+    }
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((header::ETAG, cached.etag))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .body(cached.body)
+}
 
 /// Returns a machine-readable description of the device and supported Wasm host functions.
 ///
 /// This follows the WasmIoT specification's device discovery protocol, enabling clients
 /// to understand what built-in functions (host APIs) are available to Wasm modules.
 ///
-/// This is served at the special `.well-known` path.
-pub async fn wasmiot_device_description() -> impl Responder {
+/// This is served at the special `.well-known` path. The document is cached and served
+/// with an `ETag`, so pollers sending `If-None-Match` get a `304` when nothing changed. The
+/// `deviceUsage` of the latest health snapshot is added to it as served, outside the `ETag`.
+pub async fn wasmiot_device_description(req: HttpRequest) -> impl Responder {
     let func_name = function_name!().to_string();
     tokio::spawn(async move {
         send_log("INFO", "Device description request served", &func_name, None).await;
     });
 
-    HttpResponse::Ok().json(get_device_description())
+    let cached = get_cached_description(WASMIOT_DESCRIPTION_KEY, || async { get_device_description() }).await;
+    // What the deployments use changes with every output written, so it comes from the latest
+    // health snapshot instead of the cache
+    description_response(&req, cached.with_field("deviceUsage", &current_health_snapshot().device_usage))
+}
+
+/// Builds the WoT Thing Description from the static base document and the active deployments.
+///
+/// Device-level properties come from `device-description.json`, and every endpoint of the
+/// active deployments is added as an action so WoT tooling can see what the device can do right
+/// now. Paused and standby deployments, which refuse executions with 423, are left out.
+pub async fn build_wot_td() -> Value {
+    let mut td = get_wot_td();
+    let mut actions = td.get("actions")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+
+    for deployment in all_deployments() {
+        let deployment = deployment.lock().await;
+        if deployment.active {
+            actions.extend(deployment.wot_actions());
+        }
+    }
+
+    td["actions"] = Value::Object(actions);
+    td
 }
 
 /// Returns the W3C Web of Things (WoT) Thing Description for this device.
 ///
 /// This describes the exposed capabilities and HTTP API surface of the device
 /// in a standard semantic format that can be consumed by WoT-compatible tools.
+/// Functions of active deployments are listed as actions, and the cached document is
+/// invalidated whenever deployments change.
 ///
-/// Served at a standard `.well-known` endpoint.
-pub async fn thingi_description() -> impl Responder {
+/// Served at a standard `.well-known` endpoint, with the same `ETag` handling as the
+/// WasmIoT device description.
+pub async fn thingi_description(req: HttpRequest) -> impl Responder {
     let func_name = function_name!().to_string();
     tokio::spawn(async move {
         send_log("INFO", "Web of Things description request served", &func_name, None).await;
     });
 
-    HttpResponse::Ok().json(get_wot_td())
+    let cached = get_cached_description(WOT_DESCRIPTION_KEY, build_wot_td).await;
+    description_response(&req, cached)
+}
+
+/// Answers `200 {"status": "ok"}` for as long as the HTTP server is responsive.
+///
+/// Unlike `/health` it does not sample the system, so it is cheap enough to poll often, e.g. by
+/// the systemd watchdog (see `systemd.rs`).
+pub async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": "ok" }))
+}
+
+/// Returns the device twin, the identity, capabilities, configuration, deployments and health
+/// summary of the device in one document (see `twin.rs`).
+///
+/// The `revision` of the twin is sent as its `ETag`, so pollers sending `If-None-Match` get a
+/// `304` until it changes.
+pub async fn device_twin(req: HttpRequest) -> impl Responder {
+    let etag = format!("\"{}\"", twin_revision());
+    if if_none_match(&req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish();
+    }
+    let twin = build_twin().await;
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, format!("\"{}\"", twin["revision"])))
+        .json(twin)
+}
+
+/// Returns the metrics of the supervisor in the Prometheus text format (see `metrics.rs`).
+pub async fn metrics() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(render_prometheus(&collect_metrics()))
 }
 
 /// Returns a system-level health report for the device.
@@ -454,71 +1797,48 @@ pub async fn thingi_description() -> impl Responder {
 /// - CPU usage
 /// - Memory usage
 /// - Per-interface network traffic (bytes up/down)
+/// - Linear memory of the Wasm modules of all deployments, as last measured (see `wasm_memory.rs`)
+///
+/// The metrics are also compared against the health thresholds of the `SupervisorConfig`,
+/// giving an overall `status` (`ok`, `degraded` or `critical`) and the `reasons` for it.
+/// The HTTP status is always 200 since the device itself is reachable.
+///
+/// The system metrics come from the latest snapshot taken in the background, and
+/// `snapshotAgeMs` tells how long ago that was.
 ///
 /// Useful for monitoring the host system and debugging Wasm workload issues.
 pub async fn thingi_health(request: HttpRequest) -> impl Responder {
-    // Get system info
-    let (cpu_usage, memory_usage, uptime) = {
-        let uptime = System::uptime();
-        let mut sys =  SYSTEM.lock();
-        sys.refresh_cpu_usage();
-        sys.refresh_memory();
-        let cpu = sys.global_cpu_usage() / 100.0; // Divide by hundred to convert % to 0..1
-        let used = sys.used_memory() as f32;
-        let total = sys.total_memory() as f32;
-        let mem = if total > 0.0 { used / total } else { 0.0 };
-        (cpu, mem, uptime)
-    };
-
-    // Get network info, and handle possible poisoned mutex by reinitializing
-    let network_usage = {
-        let mut networks =  NETWORKS.lock();
-        networks.refresh(true);
-        let mut network_usage = std::collections::HashMap::new();
-        for (if_name, data) in networks.iter() {
-            network_usage.insert(
-                if_name.clone(),
-                NetworkInterfaceUsage {
-                    down_bytes: data.total_received(),
-                    up_bytes: data.total_transmitted(),
-                },
-            );
-        }
-        network_usage
-    };
-
-    // Get disk info
-    let storage_usage = {
-        let mut disks =  DISKS.lock();
-        disks.refresh(true);
-        let disk_list = disks.list();
-        let mut storage_usage = std::collections::HashMap::new();
-        for disk in disk_list.iter() {
-            let disk_name = disk.name();
-            let disk_total_bytes = disk.total_space();
-            let disk_available_bytes = disk.available_space();
-            let used_percentage = if disk_total_bytes > 0 {
-                (disk_total_bytes - disk_available_bytes) as f32 / disk_total_bytes as f32
-            } else {
-                0.0
-            };
-            storage_usage.insert(
-                disk_name.to_string_lossy().to_string(),
-                used_percentage
-            );
-        }
-        storage_usage
-    };
+    // The system metrics are refreshed in the background, see `health::run_health_sampler`
+    let snapshot = current_health_snapshot();
+
+    // Compare the metrics against the configured thresholds
+    let (status, reasons) = get_supervisor_config().health_thresholds.evaluate(
+        snapshot.cpu_usage,
+        snapshot.memory_usage,
+        snapshot.instance_disk_usage,
+        snapshot.temperature,
+        pending_log_count(),
+    );
 
     let report = HealthReport {
-        cpu_usage,
-        memory_usage,
-        network_usage,
-        uptime,
-        storage_usage
+        cpu_usage: snapshot.cpu_usage,
+        memory_usage: snapshot.memory_usage,
+        network_usage: snapshot.network_usage.clone(),
+        uptime: System::uptime(),
+        storage_usage: snapshot.storage_usage.clone(),
+        status,
+        reasons,
+        result_storage: result_storage_stats(),
+        device_usage: snapshot.device_usage.clone(),
+        device_limits: get_supervisor_config().device_limits,
+        in_flight_executions: in_flight_requests(),
+        execution_queue: queue_stats(),
+        module_cache: module_cache_stats(),
+        wasm_memory_bytes: total_wasm_memory_bytes(),
+        snapshot_age_ms: snapshot.age().as_millis() as u64,
     };
 
-    let orchestrator_url = env::var("WASMIOT_ORCHESTRATOR_URL").unwrap_or(String::new());
+    let orchestrator_url = get_setting("WASMIOT_ORCHESTRATOR_URL").unwrap_or_default();
     let orchestrator_ip = match reqwest::Url::parse(&orchestrator_url) {
         Ok(url) => url.host().map(|s| s.to_string()).unwrap_or(String::new()),
         Err(_) => String::new(),
@@ -571,11 +1891,124 @@ pub async fn thingi_health(request: HttpRequest) -> impl Responder {
 
     HttpResponse::Ok().json(report)
         .customize()
-        .insert_header(("Custom-Orchestrator-Set", env::var("WASMIOT_ORCHESTRATOR_URL").is_ok().to_string()))
+        .insert_header(("Custom-Orchestrator-Set", is_setting_set("WASMIOT_ORCHESTRATOR_URL").to_string()))
+}
+
+/// Query parameters of the health history endpoint.
+#[derive(Deserialize)]
+pub struct HealthHistoryQuery {
+    since: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Returns the recorded health samples of the device, oldest first.
+///
+/// # Query Parameters
+/// - `since`: Only samples taken after this RFC 3339 timestamp are returned
+/// - `limit`: Only the most recent `limit` samples are returned
+pub async fn health_history(query: web::Query<HealthHistoryQuery>) -> impl Responder {
+    let HealthHistoryQuery { since, limit } = query.into_inner();
+    let since = match since.map(|s| DateTime::parse_from_rfc3339(&s)).transpose() {
+        Ok(since) => since.map(|t| t.with_timezone(&Utc)),
+        Err(e) => {
+            let func_name = function_name!().to_string();
+            let msg = format!("Invalid 'since' timestamp: {}", e);
+            let log_msg = msg.clone();
+            tokio::spawn(async move {
+                send_log("ERROR", &log_msg, &func_name, None).await;
+            });
+            return HttpResponse::BadRequest().json(json!({"error": msg}));
+        }
+    };
+    HttpResponse::Ok().json(get_health_history(since, limit))
+}
+
+/// Returns the current runtime configuration of the supervisor.
+///
+/// `startup.settings` holds the settings the supervisor was started with, merged from the
+/// command line, the environment and the configuration file.
+pub async fn supervisor_config_get() -> impl Responder {
+    HttpResponse::Ok().json(get_supervisor_config())
+}
+
+/// Updates the runtime configuration of the supervisor without a restart.
+///
+/// The body is a JSON merge patch, so only the changed fields need to be sent, e.g.
+/// `{"healthThresholds": {"cpu": {"degraded": 0.7}}}`. Returns the full updated
+/// configuration, or 400 if the patched configuration is invalid.
+///
+/// `{"publicBaseUrl": "https://proxy.example/device"}` sets the prefix of the links the
+/// supervisor gives out about itself (see `public_url`), and `null` goes back to the default.
+///
+/// `{"name": "camera-2"}` renames the supervisor: the name is saved to the configuration file,
+/// the mDNS advertisement is created again with it, and the supervisor registers again to the
+/// orchestrator with its old name as `previousName`. Names that are not valid DNS-SD instance
+/// names are rejected.
+pub async fn supervisor_config_patch(req: HttpRequest, payload: web::Json<Value>) -> impl Responder {
+    let func_name = function_name!().to_string();
+    let previous_name = get_supervisor_config().name;
+    match patch_supervisor_config(&payload.into_inner()) {
+        Ok(config) => {
+            let msg = if config.name != previous_name {
+                if let Some(zc) = req.app_data::<Data<Arc<Mutex<WebthingZeroconf>>>>() {
+                    rename_service(zc.get_ref().clone(), &config.name);
+                }
+                audit("registration.rename", json!({
+                    "previousName": previous_name,
+                    "name": config.name,
+                    "source": request_source(&req),
+                }));
+                format!("Supervisor renamed from '{}' to '{}'", previous_name, config.name)
+            } else {
+                "Supervisor configuration updated".to_string()
+            };
+            invalidate_description_cache();
+            tokio::spawn(async move {
+                send_log("INFO", &msg, &func_name, None).await;
+            });
+            HttpResponse::Ok().json(config)
+        }
+        Err(e) => {
+            let msg = e.clone();
+            tokio::spawn(async move {
+                send_log("ERROR", &format!("Rejected configuration update: {}", msg), &func_name, None).await;
+            });
+            HttpResponse::BadRequest().json(json!({"error": e}))
+        }
+    }
+}
+
+/// Reads the configuration file again and applies the settings that changed (see `reload.rs`).
+///
+/// Returns the `applied` settings, the ones that `requiresRestart` to take effect, and the
+/// `unknownKeys` of the file, or 400 if the configuration file cannot be read or is invalid.
+pub async fn supervisor_config_reload(req: HttpRequest) -> impl Responder {
+    let func_name = function_name!().to_string();
+    let zc = req.app_data::<Data<Arc<Mutex<WebthingZeroconf>>>>().map(|data| data.get_ref().clone());
+    match reload_configuration(zc.as_ref()) {
+        Ok(report) => {
+            let msg = format!("Configuration reloaded, applied: [{}]", report.applied.join(", "));
+            tokio::spawn(async move {
+                send_log("INFO", &msg, &func_name, None).await;
+            });
+            HttpResponse::Ok().json(report)
+        }
+        Err(e) => {
+            let msg = e.clone();
+            tokio::spawn(async move {
+                send_log("ERROR", &format!("Failed to reload configuration: {}", msg), &func_name, None).await;
+            });
+            HttpResponse::BadRequest().json(json!({"error": e}))
+        }
+    }
 }
 
 /// Registers the active orchestrator URL to the device.
-pub async fn register_orchestrator(payload: web::Json<Value>) -> impl Responder {
+///
+/// The version the orchestrator sends in `X-Wasmiot-Api-Version` is recorded for it, and the
+/// logs are sent to the endpoint of that version (see `orchestrator_compat.rs`). The response
+/// tells the version agreed on.
+pub async fn register_orchestrator(req: HttpRequest, payload: web::Json<Value>) -> impl Responder {
     let func_name = function_name!().to_string();
     let data: Value = payload.into_inner();
 
@@ -590,28 +2023,109 @@ pub async fn register_orchestrator(payload: web::Json<Value>) -> impl Responder
         return HttpResponse::BadRequest().json(json!({"error": "Invalid url"}));
     }
 
-    // Note: on non-Windows platforms setting the environment variables should only be done
-    // if no other process is writing or reading them at the same time
-    let logging_endpoint = format!("{}/device/logs", orchestrator_url);
-    unsafe {
-        env::set_var("WASMIOT_ORCHESTRATOR_URL", orchestrator_url);
-        env::set_var("WASMIOT_LOGGING_ENDPOINT", &logging_endpoint);
-    }
-    assert_eq!(env::var("WASMIOT_ORCHESTRATOR_URL"), Ok(orchestrator_url.to_string()));
-    assert_eq!(env::var("WASMIOT_LOGGING_ENDPOINT"), Ok(logging_endpoint));
+    let requested_version = req.headers().get(API_VERSION_HEADER).and_then(|value| value.to_str().ok());
+    let version = negotiate(orchestrator_url, requested_version);
+    let logging_endpoint = logging_endpoint(orchestrator_url, version);
+    set_setting("WASMIOT_ORCHESTRATOR_URL", orchestrator_url, SettingSource::Api);
+    // Set through the API, so reloading the configuration file does not undo the registration
+    set_setting("WASMIOT_LOGGING_ENDPOINT", &logging_endpoint, SettingSource::Api);
+    // The newly registered orchestrator gets the twin right away
+    twin_changed();
+    audit("registration.orchestrator", json!({
+        "url": orchestrator_url,
+        "apiVersion": version.header_value(),
+        "source": request_source(&req),
+    }));
 
     let orchestrator_url_string = orchestrator_url.to_string();
 
     tokio::spawn(async move {
         send_log("INFO", &format!("Orchestrator registered at url {orchestrator_url_string}"), &func_name, None).await;
     });
-    HttpResponse::Ok().json(json!({"status": "success"}))
+    // The newly registered orchestrator is told how loading the deployments went
+    tokio::spawn(send_startup_report());
+    HttpResponse::Ok()
+        .insert_header((API_VERSION_HEADER, version.header_value()))
+        .json(json!({"status": "success"}))
+}
+
+/// Returns how loading the deployments went at startup (see `startup.rs`): how many were
+/// `loaded` and `failed`, how long it took, and the `loadState` and duration of each.
+pub async fn startup_report_get() -> impl Responder {
+    HttpResponse::Ok().json(startup_report())
+}
+
+/// Returns the registration of the supervisor: the service advertised over mDNS, the
+/// orchestrator it registers to, and how many times the watchdog has restarted the
+/// advertisement (see `zeroconf.rs`), so that an advertisement that keeps failing shows.
+pub async fn registration(req: HttpRequest) -> impl Responder {
+    match req.app_data::<Data<Arc<Mutex<WebthingZeroconf>>>>() {
+        Some(zc) => HttpResponse::Ok().json(registration_status(zc.get_ref())),
+        None => HttpResponse::ServiceUnavailable().json(json!({ "error": "The service is not advertised" })),
+    }
+}
+
+/// Checks that a request carries `Authorization: Bearer <WASMIOT_API_TOKEN>`.
+///
+/// # Returns
+/// 403 if no token is set, so that the protected endpoints are off until one is, or 401 if
+/// the request has no token or a wrong one.
+fn check_api_token(req: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(token) = get_api_token() else {
+        return Err(HttpResponse::Forbidden().json(json!({
+            "error": "This endpoint is only served when WASMIOT_API_TOKEN is set"
+        })));
+    };
+    let given = req.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if given.len() != token.len() || !openssl::memcmp::eq(given.as_bytes(), token.as_bytes()) {
+        return Err(HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .json(json!({ "error": "Missing or invalid API token" })));
+    }
+    Ok(())
+}
+
+/// Returns entries of the audit log, oldest first (see `audit.rs`). Requires the API token.
+///
+/// # Query Parameters
+/// - `since`: Only entries made at or after this RFC 3339 time
+/// - `limit`: Most entries returned, 100 by default and 1000 at most
+///
+/// `verified` tells whether the HMAC chain of the returned entries holds, and is `null` when
+/// `WASMIOT_AUDIT_HMAC_KEY` is not set.
+pub async fn audit_log(req: HttpRequest) -> impl Responder {
+    if let Err(denied) = check_api_token(&req) {
+        return denied;
+    }
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|q| q.into_inner())
+        .unwrap_or_default();
+    let since = match query.get("since").map(|since| DateTime::parse_from_rfc3339(since)).transpose() {
+        Ok(since) => since.map(|since| since.with_timezone(&Utc)),
+        Err(_) => return HttpResponse::BadRequest().json(json!({ "error": "since must be an RFC 3339 timestamp" })),
+    };
+    let limit = match query.get("limit").map(|limit| limit.parse::<usize>()).transpose() {
+        Ok(limit) => limit.unwrap_or(100).min(1000),
+        Err(_) => return HttpResponse::BadRequest().json(json!({ "error": "limit must be a non-negative integer" })),
+    };
+    let entries = task::spawn_blocking(move || read_audit(since, limit)).await.unwrap_or_default();
+    let verified = verify_chain(&entries);
+    HttpResponse::Ok().json(json!({
+        "entries": entries,
+        "verified": verified,
+    }))
 }
 
 /// Serves a file produced as output by a WebAssembly module.
 ///
-/// This handles URLs like `/module_results/{deployment_id}/{module_name}/{filename}` and returns
-/// the corresponding file from the module’s parameter/output folder.
+/// Outputs are kept per request and their URLs look like
+/// `/module_results/{deployment_id}/{module_name}/{request_id}/{filename}` (see `get_request_result`).
+/// This handles the flat URLs `/module_results/{deployment_id}/{module_name}/{filename}` of
+/// earlier versions by redirecting to the file of the latest request that produced one with
+/// the same name, or serving the file from the module's params folder if no request has.
 ///
 /// # Path Parameters
 /// - `deployment_id`: The deployment that contains the module
@@ -619,7 +2133,9 @@ pub async fn register_orchestrator(payload: web::Json<Value>) -> impl Responder
 /// - `filename`: The output file name
 pub async fn get_module_result(req: HttpRequest, path: web::Path<(String, String, String)>) -> impl Responder {
     let (deployment_id, module_name, filename) = path.into_inner();
-    let file_path = get_params_path(&deployment_id, &module_name, Some(&filename));
+    if let Err(denied) = check_result_access(&req, req.path()) {
+        return denied;
+    }
 
     let func_name = function_name!().to_string();
     let log_msg = format!("Request for module execution result: {}/{}/{}", deployment_id, module_name, filename);
@@ -627,84 +2143,459 @@ pub async fn get_module_result(req: HttpRequest, path: web::Path<(String, String
         send_log("INFO", &log_msg, &func_name, None).await;
     });
 
-    match NamedFile::open(&file_path) {
-        Ok(file) => file.into_response(&req),
-        Err(_) => HttpResponse::NotFound().json(json!({
+    let request_url = |request_id: &str| make_output_url(&deployment_id, &module_name, request_id, &filename);
+    serve_latest_output(&req, &deployment_id, &module_name, &filename, request_url).await
+        .unwrap_or_else(|| HttpResponse::NotFound().json(json!({
             "error": "Module result file not found",
             "deployment_id": deployment_id,
             "module": module_name,
             "filename": filename
-        })),
-    }
+        })))
 }
 
-/// Handler for getting request history list
+/// Serves a file produced as output by a WebAssembly module by the URLs of the oldest versions,
+/// which did not include the deployment.
 ///
-/// This is here to match a path that has no parameters vs the default 1 parameter
-pub async fn request_history_list_1() -> impl Responder {
-    let new_path = web::Path::from("".to_string());
-    request_history_list(new_path).await
+/// This handles URLs like `/module_results/{module_name}/{filename}` by finding the deployment
+/// that contains the module and serving the file like `get_module_result`. If several
+/// deployments contain a module with the name, the file cannot be resolved and the candidate
+/// deployments are returned with 409 instead.
+///
+/// # Path Parameters
+/// - `module_name`: The module that created the file
+/// - `filename`: The output file name
+pub async fn get_module_result_legacy(req: HttpRequest, path: web::Path<(String, String)>) -> impl Responder {
+    let (module_name, filename) = path.into_inner();
+    if let Err(denied) = check_result_access(&req, req.path()) {
+        return denied;
+    }
+
+    let mut deployment_ids = Vec::new();
+    for deployment in all_deployments() {
+        let deployment = deployment.lock().await;
+        if deployment._modules.iter().any(|module| module.name == module_name) {
+            deployment_ids.push(deployment.id.clone());
+        }
+    }
+    deployment_ids.sort();
+
+    let deployment_id = match deployment_ids.as_slice() {
+        [] => {
+            return HttpResponse::NotFound().json(json!({
+                "error": "No deployment contains the module",
+                "module": module_name,
+                "filename": filename
+            }));
+        }
+        [deployment_id] => deployment_id.clone(),
+        _ => {
+            let func_name = function_name!().to_string();
+            let log_msg = format!(
+                "Ambiguous request for module execution result {}/{}: module is in deployments {:?}",
+                module_name, filename, deployment_ids
+            );
+            tokio::spawn(async move {
+                send_log("WARN", &log_msg, &func_name, None).await;
+            });
+            let candidates: Vec<String> = deployment_ids.iter()
+                .map(|id| public_url(&format!("/module_results/{}/{}/{}", id, module_name, filename)))
+                .collect();
+            return HttpResponse::Conflict().json(json!({
+                "error": "Several deployments contain the module, use a URL that includes the deployment",
+                "module": module_name,
+                "filename": filename,
+                "deployments": deployment_ids,
+                "candidates": candidates
+            }));
+        }
+    };
+
+    let request_url = |request_id: &str| make_output_url(&deployment_id, &module_name, request_id, &filename);
+    serve_latest_output(&req, &deployment_id, &module_name, &filename, request_url).await
+        .unwrap_or_else(|| HttpResponse::NotFound().json(json!({
+            "error": "Module result file not found",
+            "deployment_id": deployment_id,
+            "module": module_name,
+            "filename": filename
+        })))
 }
 
-/// Returns previous WebAssembly execution entries or one specific request if ID is given.
-///
-/// Supports:
-/// - `/request-history` to get all requests
-/// - `/request-history/{request_id}` to get a specific request entry
+/// Serves a file produced as output by a WebAssembly module for one request.
 ///
-/// The response includes the success state and result of each request.
-/// If the matched request failed, it returns HTTP 500 instead of 200.
-pub async fn request_history_list(path: web::Path<String>) -> impl Responder {
-    let id = path.into_inner();
-    let history = REQUEST_HISTORY.lock();
-    if id != "" {
-        let func_name = function_name!().to_string();
-        let log_msg = format!("Requested history for request ID: {}", id);
-        tokio::spawn(async move {
-            send_log("INFO", &log_msg, &func_name, None).await;
-        });
-        if let Some(req) = history.iter().find(|r| r.request_id == id) {
-            let status_code = if req.success { 200 } else { 500 };
-            return HttpResponse::build(actix_web::http::StatusCode::from_u16(status_code).unwrap())
-                .json(req);
+/// This handles URLs like `/module_results/{deployment_id}/{module_name}/{request_id}/{filename}`
+/// as returned in the `outputs` of a request, and returns the file from the outputs folder
+/// of the request. When result URLs are signed, requests without the valid, unexpired
+/// signature given out with the URL are refused with 403 (see `url_signing`).
+pub async fn get_request_result(req: HttpRequest, path: web::Path<(String, String, String, String)>) -> impl Responder {
+    let (deployment_id, module_name, request_id, filename) = path.into_inner();
+    if let Err(denied) = check_result_access(&req, req.path()) {
+        return denied;
+    }
+    serve_request_output(&req, &deployment_id, &module_name, &request_id, &filename).await
+}
+
+/// Serves an output file of one request, or 404 if there is no such file.
+async fn serve_request_output(req: &HttpRequest, deployment_id: &str, module_name: &str, request_id: &str, filename: &str) -> HttpResponse {
+    if is_plain_filename(request_id) && is_plain_filename(filename) {
+        let file_path = get_output_path(deployment_id, module_name, request_id, Some(filename));
+        if let Some(response) = serve_result_file(req, file_path).await {
+            record_served_bytes(request_id, &response).await;
+            return response;
         }
-        HttpResponse::NotFound().json(json!({
-            "error": "No request with that ID",
-            "request_id": id
-        }))
-    } else {
-        let func_name = function_name!().to_string();
-        let log_msg = "Requested history for all requests".to_string();
-        tokio::spawn(async move {
-            send_log("INFO", &log_msg, &func_name, None).await;
-        });
+    }
+    HttpResponse::NotFound().json(json!({
+        "error": "Module result file not found",
+        "deployment_id": deployment_id,
+        "module": module_name,
+        "request_id": request_id,
+        "filename": filename
+    }))
+}
 
-        HttpResponse::Ok().json(&*history)
+/// Counts the body of a response serving the result of a request as sent for the request, in
+/// its history entry, the statistics of its function and the metrics of the device. A request
+/// archived already is counted in the statistics and metrics alone.
+async fn record_served_bytes(request_id: &str, response: &HttpResponse) {
+    let BodySize::Sized(bytes) = response.body().size() else { return };
+    if bytes == 0 {
+        return;
     }
+    let entry = lock_request_history().get_mut(request_id).map(|entry| {
+        entry.bytes_out += bytes;
+        entry.clone()
+    });
+    let Some(entry) = (match entry {
+        Some(entry) => Some(entry),
+        None => find_request(request_id).await,
+    }) else { return };
+    record_transfer(&entry, 0, bytes);
+    record_transferred(0, bytes);
 }
 
-/// Handler for running a module function
+/// Serves a file by the flat URL of earlier versions, where outputs were not kept per request.
 ///
-/// This is here to match a path that has only 3 parameters vs the default 4 parameters
-pub async fn run_module_function_3(
-    path: web::Path<(String, String, String)>,
-    req: HttpRequest,
-    payload: web::Payload,
-) -> impl Responder {
+/// Redirects to `request_url` of the latest request that produced an output with the name,
+/// or serves the file from the module's params folder if no request has.
+///
+/// # Returns
+/// `None` if there is no such file.
+async fn serve_latest_output(
+    req: &HttpRequest,
+    deployment_id: &str,
+    module_name: &str,
+    filename: &str,
+    request_url: impl Fn(&str) -> String,
+) -> Option<HttpResponse> {
+    if is_plain_filename(filename)
+        && let Some(request_id) = latest_output_request(deployment_id, module_name, filename)
+    {
+        return Some(HttpResponse::TemporaryRedirect()
+            .insert_header((header::LOCATION, request_url(&request_id)))
+            .finish());
+    }
+    let file_path = get_params_path(deployment_id, module_name, Some(filename));
+    serve_result_file(req, file_path).await
+}
+
+/// Serves a result file with its SHA-256 digest in the `ETag` and `Digest` headers, so that
+/// clients can check the download without requesting the metadata of the file separately.
+///
+/// # Returns
+/// `None` if there is no such file.
+async fn serve_result_file(req: &HttpRequest, file_path: PathBuf) -> Option<HttpResponse> {
+    let file = NamedFile::open(&file_path).ok()?;
+    let digest = match web::block(move || file_digest(&file_path)).await {
+        Ok(Ok(digest)) => Some(digest),
+        Ok(Err(e)) => {
+            error!("{}", e);
+            None
+        }
+        Err(e) => {
+            error!("Failed to hash result file: {}", e);
+            None
+        }
+    };
+    let Some(digest) = digest else {
+        return Some(file.into_response(req));
+    };
+
+    let etag = format!("\"{}\"", digest);
+    let not_modified = req.headers().get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+    let mut response = if not_modified {
+        HttpResponse::NotModified().finish()
+    } else if is_compressible(file.content_type()) && !req.headers().contains_key(header::RANGE) {
+        file.use_etag(false).into_response(req)
+    } else {
+        // Compressing the file again would only cost CPU time, and compressing a part of it
+        // would not match the requested byte range
+        file.use_etag(false).set_content_encoding(header::ContentEncoding::Identity).into_response(req)
+    };
+    let headers = response.headers_mut();
+    if let Ok(value) = header::HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Some(value) = digest_header_value(&digest).and_then(|v| header::HeaderValue::from_str(&v).ok()) {
+        headers.insert(header::HeaderName::from_static("digest"), value);
+    }
+    Some(response)
+}
+
+/// Whether files of a content type get smaller when compressed. Images, audio, video and
+/// archives are compressed already, and unknown binary data rarely shrinks.
+fn is_compressible(content_type: &actix_web::mime::Mime) -> bool {
+    use actix_web::mime;
+    match (content_type.type_(), content_type.subtype().as_str()) {
+        (mime::IMAGE, "svg") => true,
+        (mime::IMAGE | mime::AUDIO | mime::VIDEO, _) => false,
+        (mime::APPLICATION, subtype) => !matches!(subtype,
+            "zip" | "gzip" | "x-gzip" | "zstd" | "x-bzip2" | "x-xz" | "x-7z-compressed"
+            | "x-rar-compressed" | "vnd.rar" | "pdf" | "octet-stream"
+        ),
+        (mime::FONT, subtype) => !matches!(subtype, "woff" | "woff2"),
+        _ => true,
+    }
+}
+
+/// Returns the metadata of a file produced as output by a WebAssembly module, including the
+/// SHA-256 digest of its contents for checking downloads.
+///
+/// This handles URLs like `/module_results/{deployment_id}/{module_name}/{filename}/meta`, and
+/// resolves the file like `get_module_result`: the file of the latest request that produced
+/// one with the name, or the file in the module's params folder if no request has.
+pub async fn get_module_result_meta(req: HttpRequest, path: web::Path<(String, String, String)>) -> impl Responder {
+    let (deployment_id, module_name, filename) = path.into_inner();
+    if let Err(denied) = check_result_access(&req, req.path().strip_suffix("/meta").unwrap_or(req.path())) {
+        return denied;
+    }
+    let request_id = if is_plain_filename(&filename) {
+        latest_output_request(&deployment_id, &module_name, &filename)
+    } else {
+        None
+    };
+    let file_path = match &request_id {
+        Some(request_id) => get_output_path(&deployment_id, &module_name, request_id, Some(&filename)),
+        None => get_params_path(&deployment_id, &module_name, Some(&filename)),
+    };
+    result_meta_response(file_path, deployment_id, module_name, request_id, filename).await
+}
+
+/// Returns the metadata of a file produced as output by a WebAssembly module for one request.
+///
+/// This handles URLs like `/module_results/{deployment_id}/{module_name}/{request_id}/{filename}/meta`.
+pub async fn get_request_result_meta(req: HttpRequest, path: web::Path<(String, String, String, String)>) -> impl Responder {
+    let (deployment_id, module_name, request_id, filename) = path.into_inner();
+    if let Err(denied) = check_result_access(&req, req.path().strip_suffix("/meta").unwrap_or(req.path())) {
+        return denied;
+    }
+    if !is_plain_filename(&request_id) || !is_plain_filename(&filename) {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Module result file not found",
+            "deployment_id": deployment_id,
+            "module": module_name,
+            "request_id": request_id,
+            "filename": filename
+        }));
+    }
+    let file_path = get_output_path(&deployment_id, &module_name, &request_id, Some(&filename));
+    result_meta_response(file_path, deployment_id, module_name, Some(request_id), filename).await
+}
+
+/// Builds the response of the metadata endpoints, hashing the file outside the async runtime.
+async fn result_meta_response(
+    file_path: PathBuf,
+    deployment_id: String,
+    module_name: String,
+    request_id: Option<String>,
+    filename: String,
+) -> HttpResponse {
+    if !file_path.is_file() {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Module result file not found",
+            "deployment_id": deployment_id,
+            "module": module_name,
+            "request_id": request_id,
+            "filename": filename
+        }));
+    }
+    let metadata = match web::block(move || file_metadata(&file_path)).await {
+        Ok(Ok(metadata)) => metadata,
+        Ok(Err(e)) => return HttpResponse::InternalServerError().json(json!({ "error": e })),
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e.to_string() })),
+    };
+    let url = match &request_id {
+        Some(request_id) => make_output_url(&deployment_id, &module_name, request_id, &filename),
+        None => public_url(&sign_path(&format!("/module_results/{}/{}/{}",
+            urlencoding::encode(&deployment_id),
+            urlencoding::encode(&module_name),
+            urlencoding::encode(&filename),
+        ))),
+    };
+    HttpResponse::Ok().json(json!({
+        "deploymentId": deployment_id,
+        "module": module_name,
+        "requestId": request_id,
+        "filename": filename,
+        "size": metadata.size,
+        "modified": metadata.modified,
+        "contentType": metadata.content_type,
+        "sha256": metadata.sha256,
+        "url": url
+    }))
+}
+
+/// Handler for getting request history list
+///
+/// This is here to match a path that has no parameters vs the default 1 parameter
+pub async fn request_history_list_1(req: HttpRequest) -> impl Responder {
+    let new_path = web::Path::from("".to_string());
+    request_history_list(new_path, req).await
+}
+
+/// Returns previous WebAssembly execution entries or one specific request if ID is given.
+///
+/// Supports:
+/// - `/request-history` to get all requests
+/// - `/request-history/{request_id}` to get a specific request entry
+///
+/// The response includes the success state and result of each request.
+/// If the matched request failed, it returns HTTP 500 instead of 200.
+///
+/// The entries are answered as JSON, CBOR or an HTML page by the `Accept` header of the
+/// request (see `negotiation.rs`). With `?legacy=true` they are in the snake_case format of
+/// schema version 1 instead of the current one (see `RequestEntry::legacy_json`).
+pub async fn request_history_list(path: web::Path<String>, req: HttpRequest) -> impl Responder {
+    let id = path.into_inner();
+    let representation = negotiate_representation(&req, &[Representation::Json, Representation::Cbor, Representation::Html]);
+    let legacy = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .is_ok_and(|query| query.get("legacy").is_some_and(|value| value == "true"));
+    if id != "" {
+        let func_name = function_name!().to_string();
+        let log_msg = format!("Requested history for request ID: {}", id);
+        tokio::spawn(async move {
+            send_log("INFO", &log_msg, &func_name, None).await;
+        });
+        if let Some(req) = find_request(&id).await {
+            let status_code = if req.success { 200 } else { 500 };
+            let title = format!("Request {}", req.request_id);
+            let status = StatusCode::from_u16(status_code).unwrap();
+            let response = match legacy {
+                true => negotiated_response(status, representation, &title, &req.legacy_json()),
+                false => negotiated_response(status, representation, &title, &req),
+            };
+            // Fetching the entry by its resultUrl downloads the result of the request
+            record_served_bytes(&req.request_id, &response).await;
+            return response;
+        }
+        // Requests in the durable execution queue have not been run yet
+        if let Some((position, queued)) = queued_request(&id) {
+            return HttpResponse::Accepted().json(json!({
+                "requestId": id,
+                "status": "queued",
+                "position": position,
+                "queuedAt": queued.queued_at,
+            }));
+        }
+        HttpResponse::NotFound().json(json!({
+            "error": "No request with that ID",
+            "request_id": id
+        }))
+    } else {
+        let func_name = function_name!().to_string();
+        let log_msg = "Requested history for all requests".to_string();
+        tokio::spawn(async move {
+            send_log("INFO", &log_msg, &func_name, None).await;
+        });
+
+        if legacy {
+            let entries: Vec<Value> = lock_request_history().iter().map(RequestEntry::legacy_json).collect();
+            return negotiated_response(StatusCode::OK, representation, "Request history", &entries);
+        }
+        negotiated_response(StatusCode::OK, representation, "Request history", &lock_request_history().iter().collect::<Vec<_>>())
+    }
+}
+
+/// Returns a request from the request history, or from the archive if it has been evicted.
+pub async fn find_request(request_id: &str) -> Option<RequestEntry> {
+    let found = lock_request_history().get(request_id).cloned();
+    if found.is_some() {
+        return found;
+    }
+    // Older requests have been evicted to the archive
+    let archived_id = request_id.to_string();
+    web::block(move || read_archived_request(&archived_id)).await.ok().flatten()
+}
+
+/// Returns the requests in the in-memory request history, oldest first.
+pub fn recent_requests() -> Vec<RequestEntry> {
+    lock_request_history().iter().cloned().collect()
+}
+
+/// Handler for running a module function
+///
+/// This is here to match a path that has only 3 parameters vs the default 4 parameters
+pub async fn run_module_function_3(
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> impl Responder {
     let (deployment_id, module_name, function_name) = path.into_inner();
     let new_path = web::Path::from((deployment_id, module_name, function_name, None));
     run_module_function(new_path, req, payload).await
 }
 
 
+/// Handler for getting an output file of one request to a module function
+///
+/// This is here to match the request-scoped path of outputs, with 5 parameters
+pub async fn run_module_function_5(
+    path: web::Path<(String, String, String, String, String)>,
+    req: HttpRequest,
+) -> impl Responder {
+    let (deployment_id, module_name, _function_name, request_id, filename) = path.into_inner();
+    if let Err(denied) = check_result_access(&req, req.path()) {
+        return denied;
+    }
+    serve_request_output(&req, &deployment_id, &module_name, &request_id, &filename).await
+}
+
+
 /// Executes a function in a given module in a given deployment.
 ///
-/// If a filename is provided, this acts as a file-serving route.
+/// If a filename is provided, this acts as a file-serving route, redirecting to the output
+/// of the latest request with that name if there is one (see `get_module_result`).
 /// Otherwise, this will:
 /// - Save incoming multipart files (if any)
 /// - Construct a `RequestEntry`
 /// - Either push to the async queue (POST) or execute immediately (GET)
 /// - Return a link to the result in request history
+///
+/// A failed execution is answered with 500 and its whole request history entry, as
+/// described for `execution_error_response`, instead of only the link to it.
+///
+/// Executions of a paused deployment are refused with 423 without touching its runtimes,
+/// and those of a deployment with missing files (see `Deployment::missing_files`) with 503.
+///
+/// A `callbackUrl`, as a query parameter or a form field, gets the request entry posted to it
+/// once the execution has finished (see `callback.rs`). Its host must be in `callbackHosts`
+/// of the deployment, or the request is refused with 403, and an invalid URL with 400.
+///
+/// A W3C `traceparent` header makes the execution part of that trace (see `telemetry.rs`).
+///
+/// The arguments of the function can also be posted as a JSON object instead of a multipart
+/// form, as WoT clients do.
+///
+/// Each part of a multipart upload is the input file of the execution stage mount of the same
+/// name, saved under the file name of the mount. Parts that match no mount are refused with
+/// 400 unless the endpoint has `allowExtraFiles`, and so are requests missing the file of a
+/// required mount.
+///
+/// The response of an execution is answered as JSON or CBOR by the `Accept` header of the
+/// request (see `negotiation.rs`).
+///
+/// A request to a function whose endpoint has `queueing: durable` is queued on disk while the
+/// device is busy, and answered with 202 at once (see `durable_queue.rs`).
 pub async fn run_module_function(
     path: web::Path<(String, String, String, Option<String>)>,
     req: HttpRequest,
@@ -714,7 +2605,6 @@ pub async fn run_module_function(
 
     // Serve static file if filename is provided
     if let Some(filename) = maybe_filename {
-        let file_path = get_params_path(&deployment_id, &module_name, Some(&filename));
         let log_msg = format!(
             "Serving file: {}/{}/{}/{}",
             deployment_id.clone(),
@@ -731,95 +2621,42 @@ pub async fn run_module_function(
                 None
             ).await;
         });
-        return match NamedFile::open(&file_path) {
-            Ok(file) => file.into_response(&req),
-            Err(_) => HttpResponse::NotFound().json(json!({
+        if let Err(denied) = check_result_access(&req, req.path()) {
+            return denied;
+        }
+        let request_url = |request_id: &str| public_url(&sign_path(&format!("/{}/modules/{}/{}/{}/{}",
+            urlencoding::encode(&deployment_id),
+            urlencoding::encode(&module_name),
+            urlencoding::encode(&function_name),
+            urlencoding::encode(request_id),
+            urlencoding::encode(&filename)
+        )));
+        return serve_latest_output(&req, &deployment_id, &module_name, &filename, request_url).await
+            .unwrap_or_else(|| HttpResponse::NotFound().json(json!({
                 "error": "File not found",
                 "deployment_id": deployment_id,
                 "module": module_name,
                 "filename": filename,
-            })),
-        };
+            })));
     }
 
-    // Check if deployment and module exist
-    let deployments_map = DEPLOYMENTS.lock();
-    let deployment = match deployments_map.get(&deployment_id) {
-        Some(dep) => dep,
-        None => {
-            return HttpResponse::NotFound().json(json!({
-                "error": "Deployment not found",
-                "deployment_id": deployment_id
-            }));
-        }
+    let entry = match prepare_execution(&deployment_id, &module_name, &function_name, &req, payload).await {
+        Ok(entry) => entry,
+        Err(response) => return response,
     };
-
-    if !deployment.modules.contains_key(&module_name) {
-        return HttpResponse::NotFound().json(json!({
-            "error": "Module not found in deployment",
-            "deployment_id": deployment_id,
-            "module_name": module_name
-        }));
+    if let Err(response) = check_required_inputs(&entry).await {
+        return response;
     }
-    drop(deployments_map); // Free the lock early
-
-    // Parse query parameters into JSON
-    let query_str = req.uri().query().unwrap_or("");
-    let query_map: HashMap<String, String> =
-        serde_urlencoded::from_str(query_str).unwrap_or_default();
-    let request_args = json!(query_map);
-
-    // Handle multipart file uploads (for POST only)
-    let mut request_files: HashMap<String, String> = HashMap::new();
-    let is_post = req.method() == "POST";
-    if is_post {
-        let mut multipart = Multipart::new(&req.headers(), payload);
-        while let Some(Ok(mut field)) = multipart.next().await {
-            let content_disposition = field.content_disposition();
-            let param_name = content_disposition.get_name().unwrap_or("file").to_string();
-            let filename = content_disposition
-                .get_filename()
-                .map(sanitize_filename::sanitize)
-                .unwrap_or_else(|| format!("{}_input.dat", param_name));
-
-            let save_path = get_params_path(&deployment_id, &module_name, Some(&filename));
-            if let Some(parent) = save_path.parent() {
-                std::fs::create_dir_all(parent).ok();
-            }
-
-            let mut f = match File::create(&save_path) {
-                Ok(f) => f,
-                Err(e) => {
-                    return HttpResponse::InternalServerError().json(json!({
-                        "error": format!("Failed to save file: {}", e)
-                    }));
-                }
-            };
-
-            while let Some(chunk) = field.next().await {
-                let data = chunk.unwrap();
-                if let Err(e) = f.write_all(&data) {
-                    return HttpResponse::InternalServerError().json(json!({
-                        "error": format!("File write error: {}", e)
-                    }));
-                }
-            }
 
-            request_files.insert(param_name, save_path.to_string_lossy().to_string());
-        }
+    // Requests to functions with durable queueing wait on disk while the device is busy
+    let queueing = match get_deployment(&deployment_id) {
+        Some(shared) => shared.lock().await.endpoint_queueing(&module_name, &function_name),
+        None => Queueing::Immediate,
+    };
+    if queueing == Queueing::Durable && should_queue() {
+        return queue_execution(entry);
     }
 
-    // Create RequestEntry
-    let entry = RequestEntry::new(
-        deployment_id.clone(),
-        module_name.clone(),
-        function_name.clone(),
-        req.method().to_string(),
-        request_args,
-        request_files,
-        Utc::now(),
-    );
-
     let log_msg = format!(
         "Executing module function: {}/{}/{}",
         deployment_id.clone(),
@@ -837,140 +2674,600 @@ pub async fn run_module_function(
         ).await;
     });
 
+    let representation = negotiate_representation(&req, &[Representation::Json, Representation::Cbor]);
     let (entry, final_opt) = make_history(entry).await;
-    let http_scheme = env::var("DEFAULT_URL_SCHEME").unwrap_or_else(|_| {
-        error!("Failed to read DEFAULT_URL_SCHEME from enviroment variables, defaulting to 'http'.");
-        "http".to_string()
-    });
-    let host = env::var("WASMIOT_SUPERVISOR_IP").unwrap_or_else(|_| {
-        error!("Failed to read WASMIOT_SUPERVISOR_IP from enviroment variables, defaulting to 'localhost'.");
-        "localhost".to_string()
-    });
-    let port = env::var("WASMIOT_SUPERVISOR_PORT").unwrap_or_else(|_| {
-        error!("Failed to read WASMIOT_SUPERVISOR_PORT from enviroment variables, defaulting to '8080'.");
-        "8080".to_string()
-    });
-    let result_url = format!("{}://{}:{}/request-history/{}", http_scheme, host, port, entry.request_id);
-    let mut resp = json!({ "resultUrl": result_url });
-    if let Some(final_json) = final_opt {
-        resp["result"] = final_json;
+    if !entry.success {
+        // Answered with the status the entry is served with in the request history
+        return negotiated_response(StatusCode::INTERNAL_SERVER_ERROR, representation, "Execution failed", &execution_error_response(&entry));
     }
-    HttpResponse::Ok().json(resp)
+    negotiated_response(StatusCode::OK, representation, "Execution", &execution_response(&entry, final_opt))
 }
 
+/// Queues a request to a function with durable queueing (see `durable_queue.rs`), answering
+/// `202 Accepted` with its request ID and where its result will be, or 429 when the queue is full.
+fn queue_execution(entry: RequestEntry) -> HttpResponse {
+    let request_id = entry.request_id.clone();
+    let result_url = public_url(&format!("/request-history/{}", request_id));
+    let position = match enqueue(entry.clone()) {
+        Ok(position) => position,
+        Err(EnqueueError::Full(max_depth)) => {
+            remove_request_inputs(&entry);
+            return HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, "1"))
+                .json(json!({ "error": format!("The execution queue already has the most requests allowed ({})", max_depth) }));
+        }
+        Err(EnqueueError::Failed(e)) => {
+            remove_request_inputs(&entry);
+            return HttpResponse::InternalServerError().json(json!({ "error": e }));
+        }
+    };
 
-/// Deletes (removes) an active deployment from memory by its ID.
-///
-/// This endpoint is typically used when a pipeline or WebAssembly workload
-/// is no longer needed and should be unloaded.
-///
-/// # Path Parameters
-/// - `deployment_id`: ID of the deployment to delete (string)
-///
-/// # Behavior
-/// - If the deployment exists in memory, it is removed and a success message is returned.
-/// - If not found, returns a 404 with an error message.
-///
-/// # Example
-/// DELETE /deploy/my-deployment-id
-pub async fn deployment_delete(path: web::Path<String>) -> impl Responder {
-    let deployment_id = path.into_inner();
+    let log_msg = format!(
+        "Queued module function: {}/{}/{} at position {}",
+        entry.deployment_id, entry.module_name, entry.function_name, position
+    );
     let func_name = function_name!().to_string();
+    tokio::spawn(async move {
+        send_log("INFO", &log_msg, &func_name, Some(&entry)).await;
+    });
+    HttpResponse::Accepted()
+        .insert_header((header::LOCATION, result_url.clone()))
+        .json(json!({
+            "requestId": request_id,
+            "resultUrl": result_url,
+            "status": "queued",
+            "position": position,
+        }))
+}
 
-    let log_msg = format!("Delete request for deployment: {}", deployment_id);
+/// Invokes a function as a Web of Things action (see `actions.rs`), answering `202 Accepted`
+/// with the status of the action once its inputs are saved, instead of waiting for the
+/// execution to finish.
+///
+/// The request is checked and its inputs read like in `run_module_function`.
+pub async fn invoke_action(
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> impl Responder {
+    let (deployment_id, module_name, function_name) = path.into_inner();
+    let entry = match prepare_execution(&deployment_id, &module_name, &function_name, &req, payload).await {
+        Ok(entry) => entry,
+        Err(response) => return response,
+    };
+    if let Err(response) = check_required_inputs(&entry).await {
+        return response;
+    }
+    register_action(&entry);
+    let status = pending_action_status(&entry.request_id);
+
+    let log_msg = format!("Invoking action: {}/{}/{}", deployment_id, module_name, function_name);
+    let func_name = function_name!().to_string();
+    let entry_clone = entry.clone();
     tokio::spawn(async move {
-        send_log("INFO", &log_msg, &func_name, None).await;
+        send_log("INFO", &log_msg, &func_name, Some(&entry_clone)).await;
     });
 
-    let mut deps = DEPLOYMENTS.lock();
+    let location = public_url(&action_href(&entry.request_id));
+    tokio::spawn(in_current_app_state(make_history(entry)));
+    HttpResponse::Accepted()
+        .insert_header((header::LOCATION, location))
+        .json(status)
+}
+
+/// Checks that running a function would be accepted, without running it, so that a chain can
+/// be checked before it is started.
+///
+/// The request is read like in `run_module_function`, and its arguments and files are checked
+/// against the signature and the mounts of the function (see `Deployment::validate_call`). The
+/// answer tells what would be run: the arguments converted to the types of the parameters, the
+/// files and where the module would find them, and the next endpoint in the chain. It is 200 if
+/// the function would be run, and 422 with the `problems` if not.
+///
+/// Nothing is added to the request history, and the uploaded files are removed afterwards.
+pub async fn validate_execution(
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest,
+    payload: web::Payload,
+) -> impl Responder {
+    let (deployment_id, module_name, function_name) = path.into_inner();
+    let entry = match prepare_execution(&deployment_id, &module_name, &function_name, &req, payload).await {
+        Ok(entry) => entry,
+        Err(response) => return response,
+    };
+    let Some(shared) = get_deployment(&deployment_id) else {
+        remove_request_inputs(&entry);
+        return HttpResponse::NotFound().json(json!({
+            "error": "Deployment not found",
+            "deployment_id": deployment_id
+        }));
+    };
+    let request_args: IndexMap<String, Value> = entry.request_args
+        .as_object()
+        .map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default();
+    let validation = shared.lock().await
+        .validate_call(&deployment_id, &module_name, &function_name, &request_args, &entry.request_files)
+        .await;
+    remove_request_inputs(&entry);
+
+    let status = match validation.valid {
+        true => StatusCode::OK,
+        false => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    let mut body = json!(validation);
+    body["deploymentId"] = json!(deployment_id);
+    body["module"] = json!(module_name);
+    body["function"] = json!(function_name);
+    HttpResponse::build(status).json(body)
+}
 
-    if deps.remove(&deployment_id).is_some() {
+/// Returns the status of an action (see `actions.rs`), or of any other request in the
+/// request history, in the shape of the WoT HTTP profile.
+pub async fn action_status(path: web::Path<String>) -> impl Responder {
+    let request_id = path.into_inner();
+    let found = lock_request_history().get(&request_id).cloned();
+    if let Some(entry) = found {
+        return HttpResponse::Ok().json(finished_action_status(&entry));
+    }
+    if let Some(status) = pending_action_status(&request_id) {
+        return HttpResponse::Ok().json(status);
+    }
+    let archived_id = request_id.clone();
+    match web::block(move || read_archived_request(&archived_id)).await.ok().flatten() {
+        Some(entry) => HttpResponse::Ok().json(finished_action_status(&entry)),
+        None => HttpResponse::NotFound().json(json!({
+            "error": "No action with that ID",
+            "request_id": request_id
+        })),
+    }
+}
 
-        // Delete deployment JSON file
-        let json_path = get_deployment_path(&deployment_id);
-        if let Err(e) = std::fs::remove_file(&json_path) {
-            let func_name = function_name!().to_string();
-            tokio::spawn(async move {
-                send_log(
-                    "WARN",
-                    &format!("Failed to delete deployment JSON saved on disk {}: {}", json_path.display(), e),
-                    &func_name,
-                    None
-                ).await;
-            });
-        } else {
+/// Cancels an action that is waiting for an execution thread (see `actions.rs`), answering
+/// with its status. Actions that are running or have finished are refused with 409.
+pub async fn cancel_action_request(path: web::Path<String>) -> impl Responder {
+    let request_id = path.into_inner();
+    let error = match cancel_action(&request_id) {
+        Ok(status) => {
             let func_name = function_name!().to_string();
+            let log_msg = format!("Cancelled action {}", request_id);
             tokio::spawn(async move {
-                send_log(
-                    "DEBUG",
-                    &format!("Deleted deployment JSON file: {}", json_path.display()),
-                    &func_name,
-                    None
-                ).await;
+                send_log("INFO", &log_msg, &func_name, None).await;
             });
+            return HttpResponse::Ok().json(status);
+        }
+        Err(Some(ActionState::Running)) => "The action is running and cannot be cancelled",
+        Err(_) if lock_request_history().get(&request_id).is_some() => "The action has already finished",
+        Err(_) => {
+            return HttpResponse::NotFound().json(json!({
+                "error": "No action with that ID",
+                "request_id": request_id
+            }));
         }
+    };
+    HttpResponse::Conflict().json(json!({ "error": error, "request_id": request_id }))
+}
 
-        // Delete the module and params folders related to this deployment
-        let module_deployment_path = MODULE_FOLDER.join(&deployment_id);
-        let params_deployment_path = PARAMS_FOLDER.join(&deployment_id);
-        if module_deployment_path.exists() {
-            if let Err(e) = std::fs::remove_dir_all(&module_deployment_path) {
-                let func_name = function_name!().to_string();
-                tokio::spawn(async move {
-                    send_log(
-                        "WARN",
-                        &format!("Failed to delete module deployment folder {}: {}", module_deployment_path.display(), e),
-                        &func_name,
-                        None
-                    ).await;
-                });
-            } else {
-                let func_name = function_name!().to_string();
-                tokio::spawn(async move {
-                    send_log(
-                        "DEBUG",
-                        &format!("Deleted module deployment folder: {}", module_deployment_path.display()),
-                        &func_name,
-                        None
-                    ).await;
-                });
-            }
+/// Checks that a function of a deployment can be executed and builds the `RequestEntry` of
+/// executing it, with its arguments from the query, and for POST its input files from a
+/// multipart form or its arguments from a JSON object.
+///
+/// # Returns
+/// The entry, ready to be executed, or the response refusing the request.
+async fn prepare_execution(
+    deployment_id: &str,
+    module_name: &str,
+    function_name: &str,
+    req: &HttpRequest,
+    payload: web::Payload,
+) -> Result<RequestEntry, HttpResponse> {
+    // Check if deployment and module exist
+    let shared = match get_deployment(deployment_id) {
+        Some(shared) => shared,
+        None if is_loading(deployment_id) => {
+            return Err(HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "5"))
+                .json(json!({
+                    "error": "deployment is still being loaded",
+                    "deployment_id": deployment_id
+                })));
         }
-        if params_deployment_path.exists() {
-            if let Err(e) = std::fs::remove_dir_all(&params_deployment_path) {
-                let func_name = function_name!().to_string();
-                tokio::spawn(async move {
-                    send_log(
-                        "WARN",
-                        &format!("Failed to delete params deployment folder {}: {}", params_deployment_path.display(), e),
-                        &func_name,
-                        None
-                    ).await;
-                });
-            } else {
-                let func_name = function_name!().to_string();
-                tokio::spawn(async move {
-                    send_log(
-                        "DEBUG",
-                        &format!("Deleted params deployment folder: {}", params_deployment_path.display()),
-                        &func_name,
-                        None
-                    ).await;
-                });
+        None => {
+            return Err(HttpResponse::NotFound().json(json!({
+                "error": "Deployment not found",
+                "deployment_id": deployment_id
+            })));
+        }
+    };
+    let deployment = shared.lock().await;
+
+    if !deployment.active {
+        return Err(HttpResponse::build(StatusCode::LOCKED).json(json!({
+            "error": "deployment paused",
+            "deployment_id": deployment_id
+        })));
+    }
+
+    if deployment.is_degraded() {
+        return Err(HttpResponse::ServiceUnavailable().json(json!({
+            "error": "deployment degraded, files are missing",
+            "deployment_id": deployment_id,
+            "missingFiles": deployment.missing_files
+        })));
+    }
+
+    if let Some(load_error) = deployment.load_errors.get(module_name) {
+        record_load_failure(deployment_id, module_name, function_name, req.method().as_str(), load_error);
+        return Err(HttpResponse::ServiceUnavailable().json(json!({
+            "error": "module failed to load",
+            "deployment_id": deployment_id,
+            "module_name": module_name,
+            "loadError": load_error,
+            "hint": "The module cannot run until the deployment is deployed again with the module fixed"
+        })));
+    }
+
+    let execution_permission = match deployment.modules.get(module_name) {
+        Some(config) => config.permissions.execution,
+        None => {
+            return Err(HttpResponse::NotFound().json(json!({
+                "error": "Module not found in deployment",
+                "deployment_id": deployment_id,
+                "module_name": module_name
+            })));
+        }
+    };
+    let callback_hosts = deployment.callback_hosts.clone();
+    let default_priority = deployment.endpoint_priority(module_name, function_name);
+    let execution_mounts = deployment.execution_mounts(module_name, function_name);
+    let allow_extra_files = deployment.endpoint_allows_extra_files(module_name, function_name);
+    drop(deployment); // Free the lock early
+
+    let priority = match req.headers().get(PRIORITY_HEADER) {
+        Some(value) => match value.to_str().map_err(|e| e.to_string()).and_then(str::parse::<Priority>) {
+            Ok(priority) => priority,
+            Err(e) => return Err(HttpResponse::BadRequest().json(json!({ "error": e }))),
+        },
+        None => default_priority,
+    };
+    if should_shed(priority) {
+        return Err(HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, "1"))
+            .json(json!({ "error": "Every execution thread is busy, low priority requests are refused" })));
+    }
+
+    // Parse query parameters into JSON, apart from the callback URL
+    let query_str = req.uri().query().unwrap_or("");
+    let mut query_map: HashMap<String, String> =
+        serde_urlencoded::from_str(query_str).unwrap_or_default();
+    let callback_url = match query_map.remove("callbackUrl").map(|url| check_callback_url(&url, &callback_hosts)) {
+        Some(Ok(url)) => Some(url),
+        Some(Err((status, e))) => return Err(HttpResponse::build(status).json(json!({ "error": e }))),
+        None => None,
+    };
+    let request_args = json!(query_map);
+
+    // Create RequestEntry, its ID names the folder the input files are uploaded to
+    let mut entry = RequestEntry::new(
+        deployment_id.to_string(),
+        module_name.to_string(),
+        function_name.to_string(),
+        req.method().to_string(),
+        request_args,
+        HashMap::new(),
+        Utc::now(),
+    );
+    entry.callback_url = callback_url;
+    entry.traceparent = req.headers().get(TRACEPARENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| valid_traceparent(value))
+        .map(str::to_string);
+    entry.caller = req.peer_addr().map(|addr| addr.ip().to_string());
+    entry.priority = priority;
+
+    // Handle multipart file uploads or JSON arguments (for POST only)
+    let is_post = req.method() == "POST";
+    if is_post && req.mime_type().ok().flatten().is_some_and(|mime| mime.essence_str() == mime::APPLICATION_JSON) {
+        let body = web::Json::<Value>::from_request(req, &mut payload.into_inner()).await
+            .map_err(|e| HttpResponse::BadRequest().json(json!({ "error": format!("Invalid arguments: {}", e) })))?;
+        entry.bytes_in += req.headers().get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .unwrap_or_else(|| serde_json::to_vec(&*body).map_or(0, |body| body.len() as u64));
+        let Value::Object(args) = body.into_inner() else {
+            return Err(HttpResponse::BadRequest().json(json!({ "error": "Invalid arguments: expected a JSON object" })));
+        };
+        if let Some(request_args) = entry.request_args.as_object_mut() {
+            request_args.extend(args);
+        }
+    } else if is_post && req.headers().contains_key(header::CONTENT_TYPE) {
+        // A POST without a body, like a chained call without files, has nothing to upload
+        let mut multipart = Multipart::new(&req.headers(), payload);
+        while let Some(field) = multipart.next().await {
+            let field = match field {
+                Ok(field) => field,
+                Err(e) => {
+                    remove_request_inputs(&entry);
+                    return Err(HttpResponse::BadRequest().json(json!({
+                        "error": format!("Invalid multipart upload: {}", e)
+                    })));
+                }
+            };
+            let content_disposition = field.content_disposition();
+            let param_name = content_disposition.get_name().unwrap_or("file").to_string();
+            if param_name == "callbackUrl" && content_disposition.get_filename().is_none() {
+                match read_callback_field(field, &callback_hosts).await {
+                    Ok(url) => entry.callback_url = Some(url),
+                    Err((status, e)) => {
+                        remove_request_inputs(&entry);
+                        return Err(HttpResponse::build(status).json(json!({ "error": e })));
+                    }
+                }
+                continue;
+            }
+            // Parts are mapped to mounts by their name, and saved under the file name of the
+            // mount whatever the name of the uploaded file
+            let filename = match &execution_mounts {
+                Some(mounts) => {
+                    let Some(mount) = mounts.iter().find(|mount| mount.path == param_name) else {
+                        if allow_extra_files {
+                            continue;
+                        }
+                        remove_request_inputs(&entry);
+                        return Err(HttpResponse::BadRequest().json(json!({
+                            "error": format!("Part '{}' does not match any input file of the function", param_name),
+                            "mounts": mounts.iter().map(|mount| &mount.path).collect::<Vec<_>>()
+                        })));
+                    };
+                    if entry.request_files.contains_key(&param_name) {
+                        remove_request_inputs(&entry);
+                        return Err(HttpResponse::BadRequest().json(json!({
+                            "error": format!("Input file '{}' was uploaded more than once", param_name)
+                        })));
+                    }
+                    sanitize_filename::sanitize(&mount.path)
+                }
+                None => content_disposition
+                    .get_filename()
+                    .map(sanitize_filename::sanitize)
+                    .unwrap_or_else(|| format!("{}_input.dat", param_name)),
+            };
+
+            let save_path = get_input_path(deployment_id, module_name, &entry.request_id, Some(&filename));
+            if let Some(parent) = save_path.parent() {
+                tokio::fs::create_dir_all(parent).await.ok();
+            }
+
+            match save_upload(field, &save_path, "file", None).await {
+                Ok(size) => entry.bytes_in += size,
+                Err((status, e)) => {
+                    remove_request_inputs(&entry);
+                    return Err(HttpResponse::build(status).json(json!({ "error": e })));
+                }
+            }
+
+            if execution_permission == MountPermission::Read
+                && let Err(e) = protect_read_only(&save_path)
+            {
+                remove_request_inputs(&entry);
+                return Err(HttpResponse::InternalServerError().json(json!({
+                    "error": format!("Failed to make input file read-only: {}", e)
+                })));
             }
+
+            entry.request_files.insert(param_name, save_path.to_string_lossy().to_string());
         }
+    }
+    entry.work_queued_at = Utc::now();
+    Ok(entry)
+}
 
-        let func_name = function_name!().to_string();
-        let did = deployment_id.clone();
-        tokio::spawn(async move {
-            send_log(
-                "INFO",
-                &format!("Successfully deleted deployment '{}' and all associated files", did),
-                &func_name,
-                None
-            ).await;
+/// Refuses a request that is missing the input file of a required execution stage mount of
+/// the function with 400, listing the missing mounts, before it is executed.
+async fn check_required_inputs(entry: &RequestEntry) -> Result<(), HttpResponse> {
+    let Some(shared) = get_deployment(&entry.deployment_id) else { return Ok(()) };
+    let execution_mounts = shared.lock().await
+        .execution_mounts(&entry.module_name, &entry.function_name)
+        .unwrap_or_default();
+    let missing_mounts: Vec<&String> = execution_mounts.iter()
+        .filter(|mount| mount.required && !entry.request_files.contains_key(&mount.path))
+        .map(|mount| &mount.path)
+        .collect();
+    if missing_mounts.is_empty() {
+        return Ok(());
+    }
+    remove_request_inputs(entry);
+    Err(HttpResponse::BadRequest().json(json!({
+        "error": "Required input files missing",
+        "missingMounts": missing_mounts
+    })))
+}
+
+/// Checks that a callback URL is valid and allowed by the deployment, refusing it with 400 or 403.
+fn check_callback_url(url: &str, callback_hosts: &[String]) -> Result<String, (StatusCode, String)> {
+    let url = parse_callback_url(url).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if !callback_host_allowed(&url, callback_hosts) {
+        let host = url.host_str().unwrap_or_default();
+        return Err((StatusCode::FORBIDDEN, format!("Callbacks to {} are not allowed by the deployment", host)));
+    }
+    Ok(url.to_string())
+}
+
+/// Reads a text form field of a multipart upload of at most `max_bytes`.
+async fn read_text_field(mut field: actix_multipart::Field, name: &str, max_bytes: usize) -> Result<String, (StatusCode, String)> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid multipart upload: {}", e)))?;
+        value.extend_from_slice(&chunk);
+        if value.len() > max_bytes {
+            return Err((StatusCode::BAD_REQUEST, format!("{} is too long", name)));
+        }
+    }
+    String::from_utf8(value).map_err(|_| (StatusCode::BAD_REQUEST, format!("{} is not UTF-8", name)))
+}
+
+/// Reads the `callbackUrl` form field of a request to run a function and checks it.
+async fn read_callback_field(field: actix_multipart::Field, callback_hosts: &[String]) -> Result<String, (StatusCode, String)> {
+    const MAX_URL_BYTES: usize = 2048;
+    let url = read_text_field(field, "callbackUrl", MAX_URL_BYTES).await?;
+    check_callback_url(url.trim(), callback_hosts)
+}
+
+/// Builds the response to an executed request, linking to it in the request history and
+/// including the final result of the execution, if it succeeded.
+pub fn execution_response(entry: &RequestEntry, final_opt: Option<Value>) -> Value {
+    let result_url = public_url(&format!("/request-history/{}", entry.request_id));
+    let mut resp = json!({ "resultUrl": result_url });
+    if let Some(served_by) = &entry.served_by {
+        resp["servedBy"] = json!(served_by);
+    }
+    if let Some(final_json) = final_opt {
+        resp["result"] = final_json;
+    }
+    resp
+}
+
+/// Builds the response to a request whose execution failed: why, as `error`, and the whole
+/// request history entry as `entry`, along with the link to the entry, so that the caller
+/// need not fetch it to learn what went wrong.
+pub fn execution_error_response(entry: &RequestEntry) -> Value {
+    let mut resp = execution_response(entry, None);
+    resp["error"] = match &entry.result {
+        Some(Value::String(error)) => json!(error),
+        _ => json!("Execution failed"),
+    };
+    resp["entry"] = serde_json::to_value(entry).unwrap_or(Value::Null);
+    resp
+}
+
+/// Runs a function for a transport other than HTTP (see `mqtt.rs`, `coap.rs` and `grpc.rs`), like
+/// `run_module_function` does for HTTP requests.
+///
+/// # Arguments
+/// - `method`: What the request came as, recorded in the request history
+/// - `request_args`: The arguments of the function
+/// - `input`: Contents of the input file of the function, saved as the file of its only
+///   execution stage mount
+///
+/// # Returns
+/// The executed request and its final result as `make_history` returns them, or the status
+/// and error when the function could not be executed at all.
+pub async fn execute_function(
+    deployment_id: &str,
+    module_name: &str,
+    function_name: &str,
+    method: &str,
+    request_args: Value,
+    input: Option<Vec<u8>>,
+) -> Result<(RequestEntry, Option<Value>), (StatusCode, String)> {
+    let entry = prepare_function_call(deployment_id, module_name, function_name, method, request_args, input).await?;
+    Ok(make_history(entry).await)
+}
+
+/// Checks that a function can be executed and builds the `RequestEntry` of executing it, as
+/// described for `execute_function`, without executing it yet.
+pub async fn prepare_function_call(
+    deployment_id: &str,
+    module_name: &str,
+    function_name: &str,
+    method: &str,
+    request_args: Value,
+    input: Option<Vec<u8>>,
+) -> Result<RequestEntry, (StatusCode, String)> {
+    let shared = get_deployment(deployment_id).ok_or_else(|| match is_loading(deployment_id) {
+        true => (StatusCode::SERVICE_UNAVAILABLE, format!("Deployment '{}' is still being loaded", deployment_id)),
+        false => (StatusCode::NOT_FOUND, format!("Deployment '{}' not found", deployment_id)),
+    })?;
+    let deployment = shared.lock().await;
+    if !deployment.active {
+        return Err((StatusCode::LOCKED, format!("Deployment '{}' is paused", deployment_id)));
+    }
+    if deployment.is_degraded() {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, format!("Deployment '{}' is degraded, files are missing", deployment_id)));
+    }
+    if let Some(load_error) = deployment.load_errors.get(module_name) {
+        record_load_failure(deployment_id, module_name, function_name, method, load_error);
+        return Err((StatusCode::SERVICE_UNAVAILABLE, format!(
+            "Module '{}' failed to load, deploy it again with the module fixed: {}",
+            module_name, load_error
+        )));
+    }
+    let execution_permission = deployment.modules.get(module_name)
+        .map(|config| config.permissions.execution)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Module '{}' not found in deployment", module_name)))?;
+    let input_mount = deployment.mounts.get(module_name)
+        .and_then(|functions| functions.get(function_name))
+        .and_then(|stages| stages.get(&MountStage::EXECUTION))
+        .and_then(|mounts| match mounts.as_slice() {
+            [mount] => Some(mount.path.clone()),
+            _ => None,
         });
+    let priority = deployment.endpoint_priority(module_name, function_name);
+    drop(deployment); // Free the lock early
+    if should_shed(priority) {
+        return Err((StatusCode::TOO_MANY_REQUESTS, "Every execution thread is busy, low priority requests are refused".to_string()));
+    }
+
+    let mut entry = RequestEntry::new(
+        deployment_id.to_string(),
+        module_name.to_string(),
+        function_name.to_string(),
+        method.to_string(),
+        request_args,
+        HashMap::new(),
+        Utc::now(),
+    );
+    entry.priority = priority;
+    if let Some(input) = input {
+        let Some(mount_path) = input_mount else {
+            return Err((StatusCode::BAD_REQUEST, "The function does not take a single input file".to_string()));
+        };
+        let filename = format!("{}_input.dat", sanitize_filename::sanitize(&mount_path));
+        let save_path = get_input_path(deployment_id, module_name, &entry.request_id, Some(&filename));
+        let saved = async {
+            if let Some(parent) = save_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&save_path, &input).await?;
+            if execution_permission == MountPermission::Read {
+                protect_read_only(&save_path)?;
+            }
+            Ok::<(), std::io::Error>(())
+        }.await;
+        if let Err(e) = saved {
+            remove_request_inputs(&entry);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save input file: {}", e)));
+        }
+        entry.request_files.insert(mount_path, save_path.to_string_lossy().to_string());
+    }
+    entry.work_queued_at = Utc::now();
+
+    let log_msg = format!("Executing module function from {}: {}/{}/{}", method, deployment_id, module_name, function_name);
+    let func_name = function_name!().to_string();
+    let entry_clone = entry.clone();
+    tokio::spawn(async move {
+        send_log("INFO", &log_msg, &func_name, Some(&entry_clone)).await;
+    });
+    Ok(entry)
+}
 
+
+/// Deletes (removes) an active deployment from memory by its ID.
+///
+/// This endpoint is typically used when a pipeline or WebAssembly workload
+/// is no longer needed and should be unloaded.
+///
+/// # Path Parameters
+/// - `deployment_id`: ID of the deployment to delete (string)
+///
+/// # Behavior
+/// - If the deployment exists in memory, it is removed and a success message is returned.
+/// - If not found, returns a 404 with an error message.
+///
+/// # Example
+/// DELETE /deploy/my-deployment-id
+pub async fn deployment_delete(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    if delete_deployment(&deployment_id, &request_source(&req)).await {
         HttpResponse::Ok().json(json!({ 
             "status": "success",
             "message": format!("Deployment '{}' and all associated files deleted", deployment_id)
@@ -983,73 +3280,1268 @@ pub async fn deployment_delete(path: web::Path<String>) -> impl Responder {
     }
 }
 
+/// Deletes a deployment and its files, once a function of it that is running has finished.
+///
+/// `source` is who asked for the deletion, for the audit log.
+///
+/// # Returns
+/// Whether the deployment existed.
+pub async fn delete_deployment(deployment_id: &str, source: &str) -> bool {
+    let func_name = function_name!().to_string();
+
+    let log_msg = format!("Delete request for deployment: {}", deployment_id);
+    tokio::spawn(async move {
+        send_log("INFO", &log_msg, &func_name, None).await;
+    });
+
+    let removed = lock_deployments().remove(deployment_id);
+    audit("deployment.delete", json!({
+        "deploymentId": deployment_id,
+        "source": source,
+        "success": removed.is_some(),
+    }));
+    let Some(removed) = removed else {
+        return false;
+    };
+    // Let a function that is running finish before its files are removed
+    let _running = removed.lock().await;
+    remove_deployment_files(deployment_id);
+
+    let func_name = function_name!().to_string();
+    let did = deployment_id.to_string();
+    tokio::spawn(async move {
+        send_log(
+            "INFO",
+            &format!("Successfully deleted deployment '{}' and all associated files", did),
+            &func_name,
+            None
+        ).await;
+    });
+    true
+}
+
+/// Removes the saved JSON file and the module and params folders of a deployment
+/// that has already been taken out of `lock_deployments`.
+fn remove_deployment_files(deployment_id: &str) {
+    invalidate_description_cache();
+    remove_stats(deployment_id);
+    remove_memory(deployment_id);
+    forget_replicas(deployment_id);
+
+    // Delete deployment JSON file
+    let json_path = get_deployment_path(deployment_id);
+    if let Err(e) = std::fs::remove_file(&json_path) {
+        let func_name = function_name!().to_string();
+        tokio::spawn(async move {
+            send_log(
+                "WARN",
+                &format!("Failed to delete deployment JSON saved on disk {}: {}", json_path.display(), e),
+                &func_name,
+                None
+            ).await;
+        });
+    } else {
+        let func_name = function_name!().to_string();
+        tokio::spawn(async move {
+            send_log(
+                "DEBUG",
+                &format!("Deleted deployment JSON file: {}", json_path.display()),
+                &func_name,
+                None
+            ).await;
+        });
+    }
+
+    // Delete the module and params folders related to this deployment
+    let module_deployment_path = MODULE_FOLDER.join(deployment_id);
+    let params_deployment_path = PARAMS_FOLDER.join(deployment_id);
+    if module_deployment_path.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&module_deployment_path) {
+            let func_name = function_name!().to_string();
+            tokio::spawn(async move {
+                send_log(
+                    "WARN",
+                    &format!("Failed to delete module deployment folder {}: {}", module_deployment_path.display(), e),
+                    &func_name,
+                    None
+                ).await;
+            });
+        } else {
+            let func_name = function_name!().to_string();
+            tokio::spawn(async move {
+                send_log(
+                    "DEBUG",
+                    &format!("Deleted module deployment folder: {}", module_deployment_path.display()),
+                    &func_name,
+                    None
+                ).await;
+            });
+        }
+    }
+    if params_deployment_path.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&params_deployment_path) {
+            let func_name = function_name!().to_string();
+            tokio::spawn(async move {
+                send_log(
+                    "WARN",
+                    &format!("Failed to delete params deployment folder {}: {}", params_deployment_path.display(), e),
+                    &func_name,
+                    None
+                ).await;
+            });
+        } else {
+            let func_name = function_name!().to_string();
+            tokio::spawn(async move {
+                send_log(
+                    "DEBUG",
+                    &format!("Deleted params deployment folder: {}", params_deployment_path.display()),
+                    &func_name,
+                    None
+                ).await;
+            });
+        }
+    }
+}
+
+/// Tells how each import of a module is satisfied in its runtime: by WASI, wasi-nn or a host
+/// function of the supervisor, with the capability of the device it uses and the signatures
+/// the module expects and the supervisor provides, or why it is not satisfied.
+///
+/// Modules whose imports could not be linked are compiled from their file for the report, so
+/// that it also explains why a module failed to load.
+///
+/// # Example
+/// GET /deploy/my-deployment-id/modules/my-module/imports
+pub async fn module_imports(path: web::Path<(String, String)>) -> impl Responder {
+    let (deployment_id, module_name) = path.into_inner();
+    let Some(shared) = get_deployment(&deployment_id) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Deployment does not exist",
+            "deployment_id": deployment_id
+        }));
+    };
+    let mut deployment = shared.lock().await;
+    let Some(config) = deployment.modules.get(&module_name).cloned() else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Module does not exist",
+            "deployment_id": deployment_id,
+            "module": module_name
+        }));
+    };
+    let report = match deployment.runtime_of(&deployment_id, &module_name).await {
+        Ok(runtime) => runtime.compiled_module(&config).map(|module| runtime.import_report(&module)),
+        Err(e) => Err(e),
+    };
+    match report {
+        Ok(imports) => HttpResponse::Ok().json(json!({
+            "deploymentId": deployment_id,
+            "module": module_name,
+            "satisfied": imports.iter().all(|import| import.satisfied),
+            "imports": imports
+        })),
+        Err(e) => HttpResponse::InternalServerError().json(json!({"error": e})),
+    }
+}
+
+/// Pauses a deployment so that it stops accepting executions while keeping its files.
+///
+/// With `?dropRuntimes=true` the Wasm runtimes of the deployment are dropped to free memory.
+/// They are created again on the first execution after the deployment is resumed.
+///
+/// # Example
+/// POST /deploy/my-deployment-id/pause
+pub async fn deployment_pause(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    let drop_runtimes = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .is_ok_and(|q| q.get("dropRuntimes").is_some_and(|v| v == "true"));
+    set_deployment_active(path.into_inner(), false, drop_runtimes).await
+}
+
+/// Resumes a paused deployment. A standby copy of a deployment resumed this way is promoted,
+/// like with `deployment_promote`.
+///
+/// # Example
+/// POST /deploy/my-deployment-id/resume
+pub async fn deployment_resume(path: web::Path<String>) -> impl Responder {
+    set_deployment_active(path.into_inner(), true, false).await
+}
+
+/// Activates a standby copy of a deployment pushed by another supervisor (see
+/// `replication.rs`), for it to take over from that supervisor.
+///
+/// Returns:
+/// - 200 OK with the deployment active
+/// - 404 if the deployment does not exist
+/// - 409 if the deployment is not a standby copy
+///
+/// # Example
+/// POST /deploy/my-deployment-id/promote
+pub async fn deployment_promote(path: web::Path<String>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    let func_name = function_name!().to_string();
+    let standby = match get_deployment(&deployment_id) {
+        Some(shared) => shared.lock().await.standby,
+        None => {
+            return HttpResponse::NotFound().json(json!({
+                "error": "Deployment does not exist",
+                "deployment_id": deployment_id
+            }));
+        }
+    };
+    if !standby {
+        return HttpResponse::Conflict().json(json!({
+            "error": "Deployment is not a standby replica",
+            "deployment_id": deployment_id
+        }));
+    }
+    send_log("INFO", &format!("Promoting standby deployment '{}'", deployment_id), &func_name, None).await;
+    set_deployment_active(deployment_id, true, false).await
+}
+
+/// Returns the status of each replica of a deployment: whether it holds the same artifacts,
+/// could not be reached, failed to take the deployment or has been promoted, and when it was
+/// last checked and synced (see `replication.rs`).
+pub async fn deployment_replicas(path: web::Path<String>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    let Some(shared) = get_deployment(&deployment_id) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Deployment does not exist",
+            "deployment_id": deployment_id
+        }));
+    };
+    let replicas = shared.lock().await.replicas.clone();
+    HttpResponse::Ok().json(json!({
+        "deploymentId": deployment_id,
+        "replicas": replica_statuses(&deployment_id, &replicas)
+    }))
+}
+
+/// Sets whether a deployment accepts executions and saves the state to disk, so that it
+/// survives restarts.
+async fn set_deployment_active(deployment_id: String, active: bool, drop_runtimes: bool) -> HttpResponse {
+    let func_name = function_name!().to_string();
+    let saved = {
+        let Some(shared) = get_deployment(&deployment_id) else {
+            return HttpResponse::NotFound().json(json!({
+                "error": "Deployment does not exist",
+                "deployment_id": deployment_id
+            }));
+        };
+        let mut deployment = shared.lock().await;
+        deployment.active = active;
+        if active {
+            deployment.standby = false;
+        }
+        if drop_runtimes {
+            deployment.runtimes.clear();
+        }
+        save_deployment_to_disk(&deployment)
+    };
+    // The actions of paused deployments are left out of the Thing Description
+    invalidate_description_cache();
+    if let Err(e) = saved {
+        send_log("WARN", &format!("Failed to save state of deployment {}: {}", deployment_id, e), &func_name, None).await;
+    }
+
+    let state = if active { "resumed" } else { "paused" };
+    send_log("INFO", &format!("Deployment '{}' {}", deployment_id, state), &func_name, None).await;
+    HttpResponse::Ok().json(json!({
+        "status": "success",
+        "deployment_id": deployment_id,
+        "active": active
+    }))
+}
+
+/// Updates the devices the target URLs of the instructions of a deployment name in
+/// placeholders (see `device_registry.rs`), without deploying its modules again. Each device
+/// in the JSON object of the body is added or moved to the base URL given, or removed if given
+/// `null`. Executions running already resolve their chained calls with the new devices.
+///
+/// Returns:
+/// - 200 OK with all devices of the deployment
+/// - 400 if the body is not valid, changing nothing
+/// - 404 if the deployment does not exist
+///
+/// # Example
+/// PATCH /deploy/my-deployment-id/devices
+/// {"wasmiot-supervisor-2": "http://192.168.1.23:8080"}
+pub async fn deployment_devices_patch(path: web::Path<String>, body: web::Json<Value>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    let func_name = function_name!().to_string();
+    let Some(shared) = get_deployment(&deployment_id) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Deployment does not exist",
+            "deployment_id": deployment_id
+        }));
+    };
+    let (devices, saved) = {
+        let mut deployment = shared.lock().await;
+        if let Err(e) = patch_devices(&mut deployment.devices, &body) {
+            return HttpResponse::BadRequest().json(json!({ "error": e }));
+        }
+        (deployment.devices.clone(), save_deployment_to_disk(&deployment))
+    };
+    if let Err(e) = saved {
+        send_log("WARN", &format!("Failed to save state of deployment {}: {}", deployment_id, e), &func_name, None).await;
+    }
+
+    send_log("INFO", &format!("Devices of deployment '{}' updated", deployment_id), &func_name, None).await;
+    HttpResponse::Ok().json(json!({
+        "status": "success",
+        "deployment_id": deployment_id,
+        "devices": devices
+    }))
+}
+
+/// Exports a deployment as a portable bundle (see `bundle.rs`): a tar.gz archive with the
+/// manifest, module binaries and data files, and their checksums. Secrets are not included.
+///
+/// # Example
+/// GET /deploy/my-deployment-id/export
+pub async fn deployment_export(path: web::Path<String>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    let func_name = function_name!().to_string();
+    let Some(shared) = get_deployment(&deployment_id) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Deployment does not exist",
+            "deployment_id": deployment_id
+        }));
+    };
+    let (manifest, files) = export_manifest(&*shared.lock().await);
+
+    let bundle = task::spawn_blocking(move || build_bundle(&manifest, &files)).await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result);
+    match bundle {
+        Ok(bytes) => {
+            send_log("INFO", &format!("Exported deployment '{}' as a bundle", deployment_id), &func_name, None).await;
+            HttpResponse::Ok()
+                .content_type("application/gzip")
+                .insert_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.tar.gz\"", deployment_id)))
+                .body(bytes)
+        }
+        Err(e) => {
+            send_log("ERROR", &format!("Failed to export deployment '{}': {}", deployment_id, e), &func_name, None).await;
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Failed to export deployment: {}", e),
+                "deployment_id": deployment_id
+            }))
+        }
+    }
+}
+
+/// Creates a deployment from a bundle made by `deployment_export`, sent as the request body.
+///
+/// The bundle is unpacked and its schema version and checksums are verified before the
+/// deployment is created from the unpacked files, as with `file://` URLs in `deployment_create`.
+/// Accepts `?keepPartial=true` like `deployment_create`.
+///
+/// Returns:
+/// - 400 if the bundle is invalid or fails verification
+/// - 413 if the bundle is over `WASMIOT_MAX_DEPLOYMENT_BYTES`
+/// - Otherwise the same responses as `deployment_create`
+pub async fn deployment_import(req: HttpRequest, payload: web::Payload) -> impl Responder {
+    let func_name = function_name!().to_string();
+    let keep_partial = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .is_ok_and(|q| q.get("keepPartial").is_some_and(|v| v == "true"));
+
+    let staging_dir = BUNDLE_IMPORT_FOLDER.join(format!("import-{}", Utc::now().format("%Y%m%d%H%M%S%f")));
+    let contents_dir = staging_dir.join("contents");
+    let archive_path = staging_dir.join("bundle.tar.gz");
+    if let Err(e) = std::fs::create_dir_all(&contents_dir) {
+        send_log("ERROR", &format!("Failed to create bundle import directory: {}", e), &func_name, None).await;
+        return HttpResponse::InternalServerError().json(json!({ "error": format!("Failed to create import directory: {}", e) }));
+    }
+
+    // Write the uploaded bundle to disk, enforcing the deployment size cap
+    let received = save_upload(payload, &archive_path, "bundle", Some(get_max_deployment_bytes())).await;
+
+    let manifest = match received {
+        Ok(_) => {
+            let (archive, contents) = (archive_path.clone(), contents_dir.clone());
+            task::spawn_blocking(move || unpack_bundle(&archive, &contents)).await
+                .map_err(|e| e.to_string())
+                .and_then(|result| result)
+                .map_err(|e| (StatusCode::BAD_REQUEST, e))
+        }
+        Err(e) => Err(e),
+    };
+
+    let (status, body) = match manifest {
+        Ok(manifest) => create_deployment(manifest, &request_source(&req), keep_partial).await,
+        Err((status, e)) => {
+            send_log("ERROR", &format!("Failed to import bundle: {}", e), &func_name, None).await;
+            (status, json!({ "error": e }))
+        }
+    };
+    std::fs::remove_dir_all(&staging_dir).ok();
+    HttpResponse::build(status).json(body)
+}
+
+/// Serves an artifact in the shared cache by its SHA-256 digest, for other supervisors that
+/// list this one among the `peers` of a deployment (see `download.rs`).
+///
+/// Only artifacts that have been verified against their digest are kept in the cache, and
+/// peers verify what they fetch again.
+///
+/// Returns:
+/// - 400 if the digest is not 64 hex digits
+/// - 404 if no artifact with the digest is cached
+pub async fn module_by_hash(req: HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let digest = path.into_inner().to_lowercase();
+    if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return HttpResponse::BadRequest().json(json!({ "error": "Not a SHA-256 digest", "sha256": digest }));
+    }
+    match NamedFile::open(cache_path(&digest)) {
+        Ok(file) => file.set_content_type(mime::APPLICATION_OCTET_STREAM).into_response(&req),
+        Err(_) => HttpResponse::NotFound().json(json!({ "error": "No artifact with this digest", "sha256": digest })),
+    }
+}
+
+/// Removes module and params folders that belong to no loaded deployment (see `maintenance.rs`),
+/// reporting what was reclaimed with `send_log`.
+pub async fn collect_garbage(dry_run: bool) -> GcReport {
+    let func_name = function_name!().to_string();
+    let mut deployment_ids: HashSet<String> = lock_deployments().keys().cloned().collect();
+    deployment_ids.extend(loading_deployment_ids());
+    let grace = std::time::Duration::from_secs(get_gc_grace());
+    let report = task::spawn_blocking(move || collect_orphaned_folders(&deployment_ids, grace, dry_run))
+        .await
+        .unwrap_or_default();
+
+    let message = if dry_run {
+        format!("Found {} orphaned folders to remove", report.candidates.len())
+    } else {
+        format!("Removed {} orphaned folders, reclaiming {} bytes", report.directories, report.bytes)
+    };
+    send_log("INFO", &message, &func_name, None).await;
+    for e in &report.errors {
+        send_log("WARN", e, &func_name, None).await;
+    }
+    report
+}
+
+/// Removes execution outputs as configured by the result retention policy (see `maintenance.rs`),
+/// reporting what was reclaimed with `send_log`.
+///
+/// The outputs of requests in the request history that were queued within
+/// `WASMIOT_REQUEST_HISTORY_RETENTION_SECONDS` are removed last when over a byte cap. Archived
+/// request history entries older than `WASMIOT_RESULT_MAX_AGE` are removed as well.
+pub async fn enforce_result_retention_policy() -> RetentionReport {
+    let func_name = function_name!().to_string();
+    let window = i64::try_from(get_request_history_retention()).ok()
+        .and_then(chrono::Duration::try_seconds)
+        .unwrap_or(chrono::Duration::MAX);
+    let cutoff = Utc::now().checked_sub_signed(window).unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let recent_requests: HashSet<String> = lock_request_history().iter()
+        .filter(|entry| entry.work_queued_at > cutoff)
+        .map(|entry| entry.request_id.clone())
+        .collect();
+    let policy = RetentionPolicy::from_env();
+    let max_age = policy.max_age;
+    let (report, archived_removed) = task::spawn_blocking(move || {
+        let report = enforce_result_retention(&policy, &recent_requests);
+        (report, max_age.map_or(0, prune_request_archive))
+    })
+        .await
+        .unwrap_or_default();
+
+    if report.deleted_files > 0 {
+        let message = format!(
+            "Removed {} output files, reclaiming {} bytes; {} bytes of outputs left",
+            report.deleted_files, report.reclaimed_bytes, report.remaining_bytes
+        );
+        send_log("INFO", &message, &func_name, None).await;
+    }
+    if archived_removed > 0 {
+        let message = format!("Removed {} archived request history entries", archived_removed);
+        send_log("INFO", &message, &func_name, None).await;
+    }
+    for e in &report.errors {
+        send_log("WARN", e, &func_name, None).await;
+    }
+    report
+}
+
+/// Enforces the result retention policy at the configured interval, forever.
+///
+/// Meant to be spawned once at startup.
+pub async fn run_result_retention() {
+    // The interval is read again every time, as it may change when the configuration is reloaded
+    loop {
+        enforce_result_retention_policy().await;
+        tokio::time::sleep(std::time::Duration::from_secs(get_result_cleanup_interval())).await;
+    }
+}
+
+/// Runs the cleanup of orphaned module and params folders on demand.
+///
+/// With `?dryRun=true` the folders are only listed without deleting them.
+///
+/// # Example
+/// POST /maintenance/gc?dryRun=true
+pub async fn maintenance_gc(req: HttpRequest) -> impl Responder {
+    let dry_run = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .is_ok_and(|q| q.get("dryRun").is_some_and(|v| v == "true"));
+    HttpResponse::Ok().json(collect_garbage(dry_run).await)
+}
+
+/// Deployments removed because they expired, with the time they expired at.
+///
+/// Kept for `WASMIOT_EXPIRED_DEPLOYMENT_GRACE_SECONDS` so that `GET /deploy/{id}` can tell
+/// an expired deployment apart from one that never existed.
+static EXPIRED_DEPLOYMENTS: Lazy<Mutex<HashMap<String, DateTime<Utc>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns when a deployment expired, if it did so within the grace period.
+fn expired_at(deployment_id: &str) -> Option<DateTime<Utc>> {
+    let grace = chrono::Duration::seconds(get_expired_deployment_grace() as i64);
+    EXPIRED_DEPLOYMENTS.lock()
+        .get(deployment_id)
+        .copied()
+        .filter(|expired_at| *expired_at + grace > Utc::now())
+}
+
+/// Removes the deployments whose expiry time has passed, the same way as `deployment_delete`,
+/// and records a tombstone for each of them.
+///
+/// A deployment with executions in flight is left alone until the next check, so that
+/// expiry never pulls a deployment out from under a running function.
+pub async fn expire_deployments() {
+    let func_name = function_name!().to_string();
+    let now = Utc::now();
+
+    let mut expired: Vec<(String, DateTime<Utc>)> = Vec::new();
+    for deployment in all_deployments() {
+        let (id, expires_at) = {
+            let deployment = deployment.lock().await;
+            (deployment.id.clone(), deployment.expires_at)
+        };
+        let Some(expires_at) = expires_at.filter(|t| *t <= now) else { continue };
+        if in_flight_executions_of(&id) > 0 {
+            log::debug!("Deployment '{}' has expired but is still executing, waiting for it to finish", id);
+            continue;
+        }
+        lock_deployments().remove(&id);
+        expired.push((id, expires_at));
+    }
+
+    {
+        let grace = chrono::Duration::seconds(get_expired_deployment_grace() as i64);
+        let mut tombstones = EXPIRED_DEPLOYMENTS.lock();
+        tombstones.retain(|_, expired_at| *expired_at + grace > now);
+        for (id, expired_at) in &expired {
+            tombstones.insert(id.clone(), *expired_at);
+        }
+    }
+
+    for (id, expired_at) in expired {
+        audit("deployment.delete", json!({
+            "deploymentId": id,
+            "source": "expiry",
+            "success": true,
+        }));
+        remove_deployment_files(&id);
+        send_log(
+            "INFO",
+            &format!("Deployment '{}' expired at {} and was removed", id, expired_at.to_rfc3339()),
+            &func_name,
+            None
+        ).await;
+    }
+}
+
+/// Checks for expired deployments at the configured interval, forever.
+///
+/// Meant to be spawned once at startup.
+pub async fn run_deployment_expiry() {
+    // The interval is read again every time, as it may change when the configuration is reloaded
+    loop {
+        expire_deployments().await;
+        tokio::time::sleep(std::time::Duration::from_secs(get_expiry_check_interval())).await;
+    }
+}
+
+
+/// Creates a new WebAssembly deployment with modules and optional data files.
+///
+/// Expects a JSON payload with fields:
+/// - `deploymentId` (string)
+/// - `modules` (list of modules, each with `id`, `name`, and `urls`)
+/// - Optional: `endpoints`, `instructions`, `mounts`
+///
+/// Each entry in `urls` (the `binary` and every file under `other`) is either a URL string
+/// or `{"url": ..., "sha256": ..., "size": ...}`. When a digest is given, the download is verified
+/// against it and the module fails with a checksum mismatch if it differs. Declared sizes
+/// are used instead of HEAD requests when checking that the deployment fits on the device.
+/// Files whose digest matches one already on the device are not downloaded again.
+///
+/// A `file://` URL, or `{"localPath": ...}` in place of `url`, copies the file from the local
+/// filesystem instead. Such files must be inside `WASMIOT_LOCAL_ARTIFACT_DIR`.
+///
+/// Entries under `other` may also set `"optional": true`, in which case a failed download is
+/// only reported under `warnings`, and `"mountAs"` to choose the filename written into the
+/// module's params folder instead of the entry's key.
+///
+/// A module may also carry a `secrets` map of names to string values. These are given to the
+/// module as environment variables and held only in memory, so they are neither saved to disk
+/// nor returned by `GET /deploy`, and must be supplied again after a restart.
+///
+/// A module may restrict its own access to its files with `permissions`, giving `read` or
+/// `readWrite` for the `deployment`, `execution` and `output` stages (see `MountPermissions`).
+/// Undeclared stages stay `readWrite`.
+///
+/// Files of the `deployment` stage are mounted read-only at `/data` and the inputs and outputs
+/// of requests at `/work`, unless the module sets `"mountLayout": "shared"` to find all of them
+/// in its current directory instead (see `MountLayout`).
+///
+/// Downloads all binaries and additional data files concurrently (see `WASMIOT_DOWNLOAD_CONCURRENCY`)
+/// within `WASMIOT_DEPLOYMENT_DOWNLOAD_TIMEOUT_SECONDS`, sets up the execution environments
+/// of all modules in parallel, and stores the deployment in memory. The response lists how long
+/// each download took and how many times it was retried or resumed, and under `runtimes` how
+/// long the environment of each module took to set up.
+///
+/// The manifest may carry a top level `signature` and each module a `signature` over its binary
+/// (see `signing.rs`), verified against `WASMIOT_TRUSTED_PUBLIC_KEYS`. Unsigned deployments are
+/// rejected when `WASMIOT_REQUIRE_SIGNED_DEPLOYMENTS=true` and accepted with warnings otherwise.
+///
+/// Optional `ttlSeconds` or an absolute `expiresAt` (RFC 3339) give the deployment a lifetime,
+/// after which it is removed like with `deployment_delete`. `ttlSeconds` wins if both are given.
+///
+/// Output files of chained calls to other devices are copied to this device unless
+/// `mirrorChainedResults` is `false` (see `do_wasm_work`).
+///
+/// Functions listed in `mqttFunctions`, as `module/function`, `module/*` or `*`, can also be
+/// executed by publishing to their MQTT topic (see `mqtt.rs`).
+///
+/// Requests to run a function may ask for a callback once it has finished only to the hosts
+/// listed in `callbackHosts`, as `host` or `host:port` (see `callback.rs`).
+///
+/// Target URLs of the instructions may stand for the base URL of this supervisor with
+/// `{self}`, and for that of a device listed in `devices`, by name, with `{device:NAME}` (see
+/// `device_registry.rs`).
+///
+/// Output files of executions are uploaded to the object storage given by `resultSink`, if
+/// any, and served from there (see `result_sink.rs`).
+///
+/// Artifacts with a digest are fetched from the supervisors listed in `peers`, as base URLs,
+/// before their own URL (see `module_by_hash`). A module may list `peers` of its own instead.
+/// Under `downloads` of the response, `source` tells whether each file came from the `cache`,
+/// a `peer`, given in `peer`, or the `network`.
+///
+/// The deployment and each module may declare `requires`: the `capabilities`, memory
+/// (`minMemoryBytes`), free disk space (`minFreeDiskBytes`) and CPU `architectures` it needs,
+/// checked against the device before anything is downloaded.
+///
+/// Supervisors listed in `replicas`, as base URLs, are kept holding a paused copy of the
+/// deployment with the same artifacts, to be activated with `deployment_promote` if this one
+/// fails (see `replication.rs`). `"standby": true` marks such a copy.
+///
+/// Instead of a JSON manifest for the device to download the artifacts of, the deployment may
+/// be pushed as `multipart/form-data`, with the manifest in a `manifest` part and the artifacts
+/// in parts of their own (see `receive_pushed_deployment`). The artifacts then go through the
+/// same checks as downloaded ones.
+///
+/// If creating the deployment fails, the files downloaded for it are removed again so the
+/// device returns to its state before the request. Pass `?keepPartial=true` to keep them
+/// for troubleshooting.
+///
+/// The deployment is created in a background task and the request is answered right away
+/// with the `deploymentId` and a `statusUrl` to poll for progress (see `deployment_status`).
+/// Pass `?wait=true` to instead wait for the deployment to be created, answering with its
+/// outcome as listed below.
+///
+/// Returns:
+/// - 202 Accepted once the deployment is being created in the background
+/// - 409 if a deployment with the same ID is already being created
+/// - 400 if `deploymentId` is missing, or a module needs the camera (`camera` in its
+///   `capabilities` or a camera function in its `requirements`) while it is disabled
+/// - 400 if the manifest is not valid JSON, or a pushed deployment is not valid
+/// - 413 if a pushed artifact or the pushed deployment is over the configured size caps
+/// - 422 if the device does not meet the `requires` of the deployment or of its modules,
+///   listed under `unmet` (see `admission.rs`)
+/// - 409 if the deployment would take the device over its most deployments or modules, and
+///   507 if over its most artifact bytes, with the `usage` and `limits` (see `device_limits.rs`)
+///
+/// With `?wait=true`:
+/// - 200 OK if deployment succeeds
+/// - 403 if a signature is missing or fails verification
+/// - 413 if a file or the deployment is over the configured size caps
+/// - 507 if the deployment would not fit in the free space of the device
+/// - 400 if an entry of `instructions` or `mounts` is not valid, naming the entry
+/// - 400/500 with JSON error otherwise
+pub async fn deployment_create(req: HttpRequest, payload: web::Payload) -> impl Responder {
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).ok();
+    let flag = |name: &str| query.as_ref().is_some_and(|q| q.get(name).is_some_and(|v| v == "true"));
+    let keep_partial = flag("keepPartial");
+    let wait = flag("wait");
+
+    // Artifacts are pushed along with a multipart manifest, and downloaded for a JSON one
+    let pushed = req.mime_type().ok().flatten()
+        .is_some_and(|mime| mime.type_() == mime::MULTIPART && mime.subtype() == mime::FORM_DATA);
+    let (data, staging_dir) = if pushed {
+        match receive_pushed_deployment(&req, payload).await {
+            Ok((data, staging_dir)) => (data, Some(staging_dir)),
+            Err((status, e)) => return HttpResponse::build(status).json(json!({ "error": e })),
+        }
+    } else {
+        match web::Json::<Value>::from_request(&req, &mut payload.into_inner()).await {
+            Ok(data) => (data.into_inner(), None),
+            Err(e) => return HttpResponse::BadRequest().json(json!({ "error": format!("Invalid manifest: {}", e) })),
+        }
+    };
+    let remove_staged = move || {
+        if let Some(staging_dir) = &staging_dir {
+            std::fs::remove_dir_all(staging_dir).ok();
+        }
+    };
+
+    let (status, body) = start_deployment(data, &request_source(&req), keep_partial, wait, remove_staged).await;
+    let mut response = HttpResponse::build(status);
+    if status == StatusCode::ACCEPTED
+        && let Some(status_url) = body["statusUrl"].as_str()
+    {
+        response.insert_header((header::LOCATION, status_url));
+    }
+    response.json(body)
+}
+
+/// Starts creating a deployment from its manifest, as described for `deployment_create`, for
+/// the APIs that take deployments.
+///
+/// `source` is who asked for the deployment, for the audit log. `cleanup` is run once the
+/// manifest is no longer needed, e.g. to remove the artifacts pushed with it.
+///
+/// # Returns
+/// The status and body of the answer: 202 with the `deploymentId` and the `statusUrl` to poll,
+/// the outcome of `create_deployment` when waiting for it, or why the creation was refused.
+pub async fn start_deployment(
+    data: Value,
+    source: &str,
+    keep_partial: bool,
+    wait: bool,
+    cleanup: impl FnOnce() + Send + 'static,
+) -> (StatusCode, Value) {
+    let refuse = |status: StatusCode, body: Value| {
+        let updated = data["deploymentId"].as_str().is_some_and(|id| lock_deployments().contains_key(id));
+        audit_deployment(&data, source, updated, status, &body);
+        (status, body)
+    };
+    let Some(deployment_id) = data["deploymentId"].as_str().map(str::to_string) else {
+        cleanup();
+        return refuse(StatusCode::BAD_REQUEST, json!({ "error": "Missing deploymentId" }));
+    };
+    if let Err(body) = check_camera_requirements(&data) {
+        cleanup();
+        return refuse(StatusCode::BAD_REQUEST, body);
+    }
+    if let Err((status, body)) = check_admission(&data) {
+        cleanup();
+        return refuse(status, body);
+    }
+    if let Err((status, body)) = check_limits(&deployment_id, &data, 0) {
+        cleanup();
+        return refuse(status, body);
+    }
+    if !start_progress(&deployment_id) {
+        cleanup();
+        return refuse(StatusCode::CONFLICT, json!({
+            "error": "Deployment is already being created",
+            "deploymentId": deployment_id
+        }));
+    }
+
+    if wait {
+        let outcome = create_deployment(data, source, keep_partial).await;
+        cleanup();
+        return outcome;
+    }
+    let source = source.to_string();
+    tokio::spawn(in_current_app_state(async move {
+        create_deployment(data, &source, keep_partial).await;
+        cleanup();
+    }));
+    let status_url = public_url(&format!("/deploy/{}/status", urlencoding::encode(&deployment_id)));
+    (StatusCode::ACCEPTED, json!({
+        "deploymentId": deployment_id,
+        "statusUrl": status_url
+    }))
+}
+
+/// Name of the part of a pushed deployment that carries the manifest.
+const PUSH_MANIFEST_PART: &str = "manifest";
+
+/// Receives a deployment pushed as `multipart/form-data` to `deployment_create`, staging its
+/// artifacts in a folder under `BUNDLE_IMPORT_FOLDER`.
+///
+/// The `manifest` part holds the manifest as JSON. Every other part is an artifact, named
+/// after the module whose binary it is, or `<module>/<file>` for a file under `urls.other` of
+/// the module. Entries of the manifest for pushed artifacts may leave out their URL, and keep
+/// their `sha256`, `size`, `optional` and `mountAs`. Each part is held to
+/// `WASMIOT_MAX_FILE_BYTES` and all of them together to `WASMIOT_MAX_DEPLOYMENT_BYTES`.
+///
+/// # Returns
+/// The manifest with the pushed artifacts pointing to the staged files, to be created like a
+/// manifest with `localPath` entries, and the staging folder to remove once it has been. The
+/// folder is removed already if receiving the deployment fails.
+async fn receive_pushed_deployment(req: &HttpRequest, payload: web::Payload) -> Result<(Value, PathBuf), (StatusCode, String)> {
+    let staging_dir = BUNDLE_IMPORT_FOLDER.join(format!("push-{}", Utc::now().format("%Y%m%d%H%M%S%f")));
+    std::fs::create_dir_all(&staging_dir)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create push directory: {}", e)))?;
+    match stage_pushed_deployment(req, payload, &staging_dir).await {
+        Ok(manifest) => Ok((manifest, staging_dir)),
+        Err(e) => {
+            std::fs::remove_dir_all(&staging_dir).ok();
+            Err(e)
+        }
+    }
+}
+
+/// Saves the parts of a pushed deployment into `staging_dir`, as described for
+/// `receive_pushed_deployment`, and points the manifest to them.
+async fn stage_pushed_deployment(req: &HttpRequest, payload: web::Payload, staging_dir: &Path) -> Result<Value, (StatusCode, String)> {
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    let max_file_bytes = get_max_file_bytes();
+    let max_deployment_bytes = get_max_deployment_bytes();
+    let mut received_bytes: u64 = 0;
+    let mut manifest = None;
+    let mut artifacts: HashMap<String, PathBuf> = HashMap::new();
+
+    let mut multipart = Multipart::new(req.headers(), payload);
+    while let Some(field) = multipart.next().await {
+        let field = field.map_err(|e| bad_request(format!("Invalid multipart upload: {}", e)))?;
+        let name = field.content_disposition().get_name().unwrap_or_default().to_string();
+        if name.is_empty() {
+            return Err(bad_request("Every part of a pushed deployment must be named".to_string()));
+        }
+        if artifacts.contains_key(&name) || (name == PUSH_MANIFEST_PART && manifest.is_some()) {
+            return Err(bad_request(format!("Part '{}' is given more than once", name)));
+        }
+
+        if name == PUSH_MANIFEST_PART {
+            let path = staging_dir.join("manifest.json");
+            save_upload(field, &path, "manifest", Some(max_file_bytes)).await?;
+            let contents = std::fs::read(&path)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read manifest: {}", e)))?;
+            let parsed: Value = serde_json::from_slice(&contents)
+                .map_err(|e| bad_request(format!("Invalid manifest: {}", e)))?;
+            manifest = Some(parsed);
+            continue;
+        }
+
+        // Staged under a name of our own, as part names are not trusted as filenames
+        let path = staging_dir.join(format!("artifact-{}", artifacts.len()));
+        let remaining = max_deployment_bytes.saturating_sub(received_bytes);
+        let what = format!("artifact '{}'", name);
+        received_bytes += match save_upload(field, &path, &what, Some(max_file_bytes.min(remaining))).await {
+            Ok(written) => written,
+            Err((StatusCode::PAYLOAD_TOO_LARGE, _)) if remaining < max_file_bytes => {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("The deployment is over the limit of {} bytes", max_deployment_bytes),
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        artifacts.insert(name, path);
+    }
+
+    let mut manifest = manifest.ok_or_else(|| bad_request("Missing manifest part".to_string()))?;
+    let modules = manifest.get_mut("modules").and_then(Value::as_array_mut).into_iter().flatten();
+    for module in modules {
+        let Some(name) = module.get("name").and_then(Value::as_str).map(str::to_string) else { continue };
+        let Some(module) = module.as_object_mut() else { continue };
+        let Value::Object(urls) = module.entry("urls").or_insert_with(|| json!({})) else {
+            return Err(bad_request(format!("urls of module '{}' is not an object", name)));
+        };
+
+        if let Some(path) = artifacts.remove(&name) {
+            let binary = pushed_source(urls.get("binary"), &path);
+            urls.insert("binary".to_string(), binary);
+        }
+
+        let prefix = format!("{}/", name);
+        let parts: Vec<String> = artifacts.keys().filter(|part| part.starts_with(&prefix)).cloned().collect();
+        if parts.is_empty() {
+            continue;
+        }
+        let Value::Object(others) = urls.entry("other").or_insert_with(|| json!({})) else {
+            return Err(bad_request(format!("urls.other of module '{}' is not an object", name)));
+        };
+        for part in parts {
+            let file = part[prefix.len()..].to_string();
+            // Files not declared in the manifest are mounted under the name of the part
+            if !others.contains_key(&file) && (file.is_empty() || sanitize_filename::sanitize(&file) != file) {
+                return Err(bad_request(format!("Invalid filename '{}' for module '{}'", file, name)));
+            }
+            if let Some(path) = artifacts.remove(&part) {
+                let source = pushed_source(others.get(&file), &path);
+                others.insert(file, source);
+            }
+        }
+    }
+
+    let mut unmatched: Vec<String> = artifacts.into_keys().collect();
+    if !unmatched.is_empty() {
+        unmatched.sort();
+        return Err(bad_request(format!("Parts match no module of the manifest: {}", unmatched.join(", "))));
+    }
+    Ok(manifest)
+}
+
+/// Entry of a pushed artifact in the manifest: the entry given, keeping its digest, size and
+/// options, with the staged file in place of its URL.
+fn pushed_source(entry: Option<&Value>, path: &Path) -> Value {
+    let mut source = match entry {
+        Some(Value::Object(entry)) => entry.clone(),
+        _ => serde_json::Map::new(),
+    };
+    source.remove("url");
+    source.insert("localPath".to_string(), json!(path.to_string_lossy()));
+    Value::Object(source)
+}
+
+/// Returns the progress of creating a deployment: the phase it is at, the files downloaded
+/// so far out of all files, the bytes downloaded, the file started last, and any errors.
+/// Once finished, the phase is `completed` or `failed` and `result` holds the response the
+/// creation would have given with `?wait=true`.
+///
+/// Only the latest creation attempt of each deployment ID is kept, and only until restart.
+pub async fn deployment_status(path: web::Path<String>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    match get_progress(&deployment_id) {
+        Some(progress) => HttpResponse::Ok().json(progress),
+        None => HttpResponse::NotFound().json(json!({
+            "error": "No creation of this deployment is known",
+            "deployment_id": deployment_id
+        })),
+    }
+}
+
+/// Creates a deployment from its manifest, as described for `deployment_create`.
+///
+/// Kept apart from the handler so that deployments can also be applied at startup.
+/// The progress and outcome are recorded for `GET /deploy/{id}/status`.
+///
+/// # Returns
+/// The HTTP status and the JSON body describing the outcome.
+pub async fn create_deployment(data: Value, source: &str, keep_partial: bool) -> (StatusCode, Value) {
+    let func_name = function_name!().to_string();
+    send_log("INFO", "Deployment creation request received", &func_name, None).await;
+
+    let deployment_id = match data["deploymentId"].as_str() {
+        Some(s) => s.to_string(),
+        None => {
+            send_log("ERROR", "Missing deploymentId", &func_name, None).await;
+            let body = json!({ "error": "Missing deploymentId" });
+            audit_deployment(&data, source, false, StatusCode::BAD_REQUEST, &body);
+            return (StatusCode::BAD_REQUEST, body);
+        }
+    };
+
+    let updated = lock_deployments().contains_key(&deployment_id);
+    let (status, body) = build_deployment(&deployment_id, &data, keep_partial).await;
+    finish_progress(&deployment_id, status.as_u16(), &body);
+    audit_deployment(&data, source, updated, status, &body);
+    (status, body)
+}
+
+/// Who a request came from, for the audit log: the IP address of the peer, or `unix` for a
+/// request over the Unix domain socket.
+fn request_source(req: &HttpRequest) -> String {
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unix".to_string())
+}
+
+/// Records the outcome of creating a deployment in the audit log, as `deployment.update` when
+/// it replaces a deployment of the same ID.
+fn audit_deployment(data: &Value, source: &str, updated: bool, status: StatusCode, body: &Value) {
+    let deployment_id = data["deploymentId"].as_str();
+    let mut details = json!({
+        "deploymentId": deployment_id,
+        "manifestSha256": hex::encode(Sha256::digest(data.to_string().as_bytes())),
+        "source": source,
+        "status": status.as_u16(),
+        "success": status.is_success(),
+    });
+    if let Some(error) = body.get("error") {
+        details["error"] = error.clone();
+    }
+    audit(if updated { "deployment.update" } else { "deployment.create" }, details);
+}
+
+/// Checks that the camera is enabled if a module of the deployment needs it (see `camera.rs`).
+///
+/// # Returns
+/// The JSON error body naming the modules if the camera is needed but disabled.
+fn check_camera_requirements(data: &Value) -> Result<(), Value> {
+    let modules = modules_requiring_camera(data);
+    if modules.is_empty() || camera_enabled() {
+        return Ok(());
+    }
+    Err(json!({
+        "error": "The camera capability is not available on this device, but the modules need it",
+        "capability": "camera",
+        "modules": modules
+    }))
+}
+
+/// Checks the `requires` of a deployment manifest against the device (see `admission.rs`).
+///
+/// # Returns
+/// 422 listing every requirement the device does not meet under `unmet`, or 400 if a
+/// `requires` is not valid.
+fn check_admission(data: &Value) -> Result<(), (StatusCode, Value)> {
+    match unmet_requirements(data, &device_facts()) {
+        Ok(unmet) if unmet.is_empty() => Ok(()),
+        Ok(unmet) => Err((StatusCode::UNPROCESSABLE_ENTITY, json!({
+            "error": "The device does not meet the requirements of the deployment",
+            "unmet": unmet
+        }))),
+        Err(e) => Err((StatusCode::BAD_REQUEST, json!({ "error": e }))),
+    }
+}
+
+/// Checks a deployment manifest against the caps on the deployments of the device (see
+/// `device_limits.rs`), with the size of its artifacts as far as it is known.
+///
+/// # Returns
+/// 409 if the deployment would go over the most deployments or modules, or 507 if its
+/// artifacts would go over the most artifact bytes, with the current `usage` and the `limits`.
+fn check_limits(deployment_id: &str, data: &Value, artifact_bytes: u64) -> Result<(), (StatusCode, Value)> {
+    let limits = get_supervisor_config().device_limits;
+    let modules = data["modules"].as_array().map_or(0, Vec::len);
+    check_device_limits(&limits, deployment_id, modules, artifact_bytes).map_err(|(exceeded, error, usage)| {
+        let status = match exceeded {
+            LimitExceeded::Deployments | LimitExceeded::Modules => StatusCode::CONFLICT,
+            LimitExceeded::ArtifactBytes => StatusCode::INSUFFICIENT_STORAGE,
+        };
+        (status, json!({ "error": error, "usage": usage, "limits": limits }))
+    })
+}
+
+/// Does the work of `create_deployment`, reporting the phases to the deployment's progress.
+async fn build_deployment(deployment_id: &str, data: &Value, keep_partial: bool) -> (StatusCode, Value) {
+    let func_name = function_name!().to_string();
+    let deployment_id = deployment_id.to_string();
+    update_progress(&deployment_id, |progress| progress.phase = DeploymentPhase::Verifying);
+
+    let modules = match data["modules"].as_array() {
+        Some(arr) if !arr.is_empty() => arr,
+        _ => {
+            send_log("ERROR", "No modules provided", &func_name, None).await;
+            return (StatusCode::BAD_REQUEST, json!({ "error": "No modules provided in deployment request" }));
+        }
+    };
+
+    if let Err(body) = check_camera_requirements(data) {
+        send_log("ERROR", &body["error"].to_string(), &func_name, None).await;
+        return (StatusCode::BAD_REQUEST, body);
+    }
+    if let Err((status, body)) = check_admission(data) {
+        send_log("ERROR", &body.to_string(), &func_name, None).await;
+        return (status, body);
+    }
+    if let Err((status, body)) = check_limits(&deployment_id, data, 0) {
+        send_log("ERROR", &body.to_string(), &func_name, None).await;
+        return (status, body);
+    }
+
+    // Deployments may be given a lifetime after which they are removed automatically
+    let expires_at = match (data.get("ttlSeconds"), data.get("expiresAt")) {
+        (Some(ttl), _) => match ttl.as_u64()
+            .and_then(|ttl| chrono::Duration::try_seconds(i64::try_from(ttl).ok()?))
+            .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+        {
+            Some(expires_at) => Some(expires_at),
+            None => {
+                send_log("ERROR", "Invalid ttlSeconds", &func_name, None).await;
+                return (StatusCode::BAD_REQUEST, json!({ "error": "ttlSeconds must be a non-negative integer" }));
+            }
+        },
+        (None, Some(at)) => match at.as_str().and_then(|at| DateTime::parse_from_rfc3339(at).ok()) {
+            Some(expires_at) => Some(expires_at.with_timezone(&Utc)),
+            None => {
+                send_log("ERROR", "Invalid expiresAt", &func_name, None).await;
+                return (StatusCode::BAD_REQUEST, json!({ "error": "expiresAt must be an RFC 3339 timestamp" }));
+            }
+        },
+        (None, None) => None,
+    };
+
+    let mirror_chained_results = match data.get("mirrorChainedResults") {
+        None => true,
+        Some(mirror) => match mirror.as_bool() {
+            Some(mirror) => mirror,
+            None => {
+                send_log("ERROR", "Invalid mirrorChainedResults", &func_name, None).await;
+                return (StatusCode::BAD_REQUEST, json!({ "error": "mirrorChainedResults must be a boolean" }));
+            }
+        },
+    };
+
+    let mqtt_functions = match data.get("mqttFunctions") {
+        None => Vec::new(),
+        Some(functions) => match serde_json::from_value::<Vec<String>>(functions.clone()) {
+            Ok(functions) => functions,
+            Err(_) => {
+                send_log("ERROR", "Invalid mqttFunctions", &func_name, None).await;
+                return (StatusCode::BAD_REQUEST, json!({ "error": "mqttFunctions must be a list of strings" }));
+            }
+        },
+    };
 
-/// Creates a new WebAssembly deployment with modules and optional data files.
-///
-/// Expects a JSON payload with fields:
-/// - `deploymentId` (string)
-/// - `modules` (list of modules, each with `id`, `name`, and `urls`)
-/// - Optional: `endpoints`, `instructions`, `mounts`
-///
-/// Downloads all binaries and additional data files, sets up execution environments,
-/// and stores the deployment in memory.
-///
-/// Returns:
-/// - 200 OK if deployment succeeds
-/// - 400/500 with JSON error otherwise
-pub async fn deployment_create(payload: web::Json<Value>) -> impl Responder {
-    let func_name = function_name!().to_string();
-    send_log("INFO", "Deployment creation request received", &func_name, None).await;
+    let callback_hosts = match data.get("callbackHosts") {
+        None => Vec::new(),
+        Some(hosts) => match serde_json::from_value::<Vec<String>>(hosts.clone()) {
+            Ok(hosts) => hosts,
+            Err(_) => {
+                send_log("ERROR", "Invalid callbackHosts", &func_name, None).await;
+                return (StatusCode::BAD_REQUEST, json!({ "error": "callbackHosts must be a list of strings" }));
+            }
+        },
+    };
 
-    let data = payload.into_inner();
+    let devices = match parse_devices(data.get("devices")) {
+        Ok(devices) => devices,
+        Err(e) => {
+            send_log("ERROR", &e, &func_name, None).await;
+            return (StatusCode::BAD_REQUEST, json!({ "error": e }));
+        }
+    };
 
-    let deployment_id = match data["deploymentId"].as_str() {
-        Some(s) => s.to_string(),
-        None => {
-            send_log("ERROR", "Missing deploymentId", &func_name, None).await;
-            return HttpResponse::BadRequest().json(json!({ "error": "Missing deploymentId" }));
+    let healthcheck_policy = match data.get("healthcheckPolicy").cloned().map(serde_json::from_value::<HealthcheckPolicy>) {
+        None => HealthcheckPolicy::default(),
+        Some(Ok(policy)) => policy,
+        Some(Err(_)) => {
+            send_log("ERROR", "Invalid healthcheckPolicy", &func_name, None).await;
+            return (StatusCode::BAD_REQUEST, json!({ "error": "healthcheckPolicy must be 'degrade' or 'fail'" }));
         }
     };
 
-    let modules = match data["modules"].as_array() {
-        Some(arr) if !arr.is_empty() => arr,
-        _ => {
-            send_log("ERROR", "No modules provided", &func_name, None).await;
-            return HttpResponse::BadRequest().json(json!({ "error": "No modules provided in deployment request" }));
+    let replicas = match parse_replicas(data.get("replicas")) {
+        Ok(replicas) => replicas,
+        Err(e) => {
+            send_log("ERROR", &e, &func_name, None).await;
+            return (StatusCode::BAD_REQUEST, json!({ "error": e }));
         }
     };
 
-    let mut module_configs = Vec::new();
+    let standby = match data.get("standby") {
+        None => false,
+        Some(standby) => match standby.as_bool() {
+            Some(standby) => standby,
+            None => {
+                send_log("ERROR", "Invalid standby", &func_name, None).await;
+                return (StatusCode::BAD_REQUEST, json!({ "error": "standby must be a boolean" }));
+            }
+        },
+    };
+
+    let instructions = match parse_instructions(data.get("instructions")) {
+        Ok(instructions) => instructions,
+        Err(e) => {
+            send_log("ERROR", &format!("Invalid instructions: {}", e), &func_name, None).await;
+            return (StatusCode::BAD_REQUEST, json!({ "error": e }));
+        }
+    };
+
+    let mounts = match parse_mounts(data.get("mounts")) {
+        Ok(mounts) => mounts,
+        Err(e) => {
+            send_log("ERROR", &format!("Invalid mounts: {}", e), &func_name, None).await;
+            return (StatusCode::BAD_REQUEST, json!({ "error": e }));
+        }
+    };
+
+    let deployment_peers = match parse_peers(data.get("peers")) {
+        Ok(peers) => peers,
+        Err(e) => {
+            send_log("ERROR", &e, &func_name, None).await;
+            return (StatusCode::BAD_REQUEST, json!({ "error": e }));
+        }
+    };
+
+    let result_sink = match data.get("resultSink").filter(|sink| !sink.is_null()) {
+        None => None,
+        Some(sink) => match ResultSink::from_manifest(sink) {
+            Ok(sink) => Some(sink),
+            Err(e) => {
+                send_log("ERROR", &e, &func_name, None).await;
+                return (StatusCode::BAD_REQUEST, json!({ "error": e }));
+            }
+        },
+    };
+
+    // Check signatures before anything is written to disk
+    let require_signed = get_require_signed_deployments();
+    let mut warnings = Vec::new();
+    match verify_manifest(data) {
+        Ok(true) => {}
+        Ok(false) if require_signed => {
+            send_log("ERROR", "Rejected unsigned deployment manifest", &func_name, None).await;
+            return (StatusCode::FORBIDDEN, json!({ "error": "Deployment manifest is not signed", "artifact": "manifest" }));
+        }
+        Ok(false) => warnings.push(json!({ "warning": "Deployment manifest is not signed", "artifact": "manifest" })),
+        Err(e) => {
+            let msg = format!("Manifest signature verification failed: {}", e);
+            send_log("ERROR", &msg, &func_name, None).await;
+            return (StatusCode::FORBIDDEN, json!({ "error": msg, "artifact": "manifest" }));
+        }
+    }
+    let unsigned_modules: Vec<&str> = modules.iter()
+        .filter(|module| module.get("signature").and_then(Value::as_str).is_none())
+        .map(|module| module.get("name").and_then(Value::as_str).unwrap_or("unknown"))
+        .collect();
+    if require_signed && !unsigned_modules.is_empty() {
+        send_log("ERROR", &format!("Rejected unsigned modules {:?}", unsigned_modules), &func_name, None).await;
+        return (StatusCode::FORBIDDEN, json!({ "error": "Module binaries are not signed", "modules": unsigned_modules }));
+    }
+    for name in unsigned_modules {
+        warnings.push(json!({ "warning": "Module binary is not signed", "module": name }));
+    }
+
     let mut errors = Vec::new();
 
-    let module_deployment_dir = MODULE_FOLDER.join(&deployment_id);
-    let params_deployment_dir = PARAMS_FOLDER.join(&deployment_id);
+    // The files of an active deployment with the same ID stay as they are until the new ones
+    // have been downloaded, and are put back if the request fails after that. Requests to the
+    // deployment wait while its files are swapped, the lock being released only after the
+    // rollback has run.
+    let replaces_active = lock_deployments().contains_key(&deployment_id);
+    let mut replaced_guard = None;
+    let mut rollback = DeploymentRollback::new(&deployment_id, keep_partial, replaces_active);
+    let module_deployment_dir = rollback.target(&MODULE_FOLDER);
+    let params_deployment_dir = rollback.target(&PARAMS_FOLDER);
     
     if let Err(e) = std::fs::create_dir_all(&module_deployment_dir) {
         send_log("ERROR", &format!("Failed to create module directory for deployment: {}", e), &func_name, None).await;
-        return HttpResponse::InternalServerError().json(json!({ "error": format!("Failed to create deployment directories: {}", e) }));
+        return (StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": format!("Failed to create deployment directories: {}", e) }));
     }
     
     if let Err(e) = std::fs::create_dir_all(&params_deployment_dir) {
         send_log("ERROR", &format!("Failed to create params directory for deployment: {}", e), &func_name, None).await;
-        return HttpResponse::InternalServerError().json(json!({ "error": format!("Failed to create deployment directories: {}", e) }));
+        return (StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": format!("Failed to create deployment directories: {}", e) }));
     }
 
+    // Collect the artifacts of every module so that they can be downloaded concurrently
+    let mut module_names = Vec::new();
+    let mut jobs = Vec::new();
+    let mut secrets = HashMap::new();
+    let mut healthchecks = Vec::new();
     for module in modules {
         let id = module.get("id").and_then(Value::as_str).unwrap_or("unknown").to_string();
         let name = match module.get("name").and_then(Value::as_str) {
             Some(n) => n.to_string(),
             None => {
-                let err = json!({ "error": "Module missing name", "module": module });
+                let err = json!({ "error": "Module missing name", "module": redact_secrets(module) });
                 send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
                 errors.push(err);
                 continue;
             }
         };
 
-        // Fetch binary
-        let binary_url = match module.get("urls").and_then(|urls| urls.get("binary")).and_then(Value::as_str) {
-            Some(url) => url.to_string(),
+        // Binary, verified against its digest if the manifest provides one
+        let binary_source = match module.get("urls").and_then(|urls| urls.get("binary")).and_then(ArtifactSource::from_manifest) {
+            Some(source) => source,
             None => {
                 let err = json!({ "error": "Module missing binary URL", "module": name });
                 send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
@@ -1058,41 +4550,53 @@ pub async fn deployment_create(payload: web::Json<Value>) -> impl Responder {
             }
         };
 
-        let bin_response = match reqwest::get(&binary_url).await {
-            Ok(resp) if resp.status().is_success() => resp,
-            Ok(resp) => {
-                let err = json!({ "error": format!("Binary URL returned {}", resp.status()), "module": name });
+        let permissions = match module.get("permissions").cloned().map(serde_json::from_value::<MountPermissions>) {
+            None => MountPermissions::default(),
+            Some(Ok(permissions)) => permissions,
+            Some(Err(e)) => {
+                let err = json!({ "error": format!("Invalid permissions: {}", e), "module": name });
                 send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
                 errors.push(err);
                 continue;
             }
-            Err(e) => {
-                let err = json!({ "error": format!("Failed to fetch binary: {}", e), "module": name });
+        };
+
+        let mount_layout = match module.get("mountLayout").cloned().map(serde_json::from_value::<MountLayout>) {
+            None => MountLayout::Isolated,
+            Some(Ok(mount_layout)) => mount_layout,
+            Some(Err(_)) => {
+                let err = json!({ "error": "mountLayout must be 'isolated' or 'shared'", "module": name });
                 send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
                 errors.push(err);
                 continue;
             }
         };
 
-        let bin_bytes = match bin_response.bytes().await {
-            Ok(bytes) => bytes,
+        // Self-test run once the deployment has been created
+        match module.get("healthcheck").cloned().map(serde_json::from_value::<Healthcheck>) {
+            None => {}
+            Some(Ok(healthcheck)) => healthchecks.push((name.clone(), healthcheck)),
+            Some(Err(e)) => {
+                let err = json!({ "error": format!("Invalid healthcheck: {}", e), "module": name });
+                send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
+                errors.push(err);
+                continue;
+            }
+        }
+
+        // Peers of the module replace those of the whole deployment
+        let peers = match parse_peers(module.get("peers")) {
+            Ok(peers) if peers.is_empty() => deployment_peers.clone(),
+            Ok(peers) => peers,
             Err(e) => {
-                let err = json!({ "error": format!("Failed to read binary response: {}", e), "module": name });
+                let err = json!({ "error": e, "module": name });
                 send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
                 errors.push(err);
                 continue;
             }
         };
 
-        let binary_path = get_module_path(&deployment_id, &name);
-        if let Err(e) = std::fs::write(&binary_path, &bin_bytes) {
-            let err = json!({ "error": format!("Failed to write binary: {}", e), "path": binary_path });
-            send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
-            errors.push(err);
-            continue;
-        }
-
-        let module_params_path = get_params_path(&deployment_id, &name, None);
+        let module_params_path = params_deployment_dir.join(&name);
         if let Err(e) = std::fs::create_dir_all(&module_params_path) {
             let err = json!({ "error": format!("Failed to create params directory: {}", e), "module": name });
             send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
@@ -1100,110 +4604,264 @@ pub async fn deployment_create(payload: web::Json<Value>) -> impl Responder {
             continue;
         }
 
-        let mut data_files = HashMap::new();
+        jobs.push(DownloadJob {
+            module: name.clone(),
+            kind: ArtifactKind::Binary,
+            path: module_deployment_dir.join(&name),
+            source: binary_source,
+            optional: false,
+            signature: module.get("signature").and_then(Value::as_str).map(str::to_string),
+            peers: peers.clone(),
+        });
+
         if let Some(other_map) = module.get("urls")
             .and_then(|urls| urls.get("other"))
             .and_then(Value::as_object)
         {
             for (filename, url_val) in other_map {
-                if let Some(url) = url_val.as_str() {
-                    match reqwest::get(url).await {
-                        Ok(resp) if resp.status().is_success() => {
-                            match resp.bytes().await {
-                                Ok(file_bytes) => {
-                                    let path = get_params_path(&deployment_id, &name, Some(filename));
-                                    if let Some(parent) = path.parent() {
-                                        std::fs::create_dir_all(parent).ok();
-                                    }
-                                    match std::fs::write(&path, &file_bytes) {
-                                        Ok(_) => {
-                                            data_files.insert(filename.clone(), path.to_string_lossy().to_string());
-                                        }
-                                        Err(e) => {
-                                            let err = json!({
-                                                "error": format!("Failed to save extra file: {}", e),
-                                                "file": filename,
-                                                "module": name
-                                            });
-                                            send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
-                                            errors.push(err);
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    let err = json!({
-                                        "error": format!("Failed to read extra file bytes: {}", e),
-                                        "file": filename,
-                                        "module": name
-                                    });
-                                    send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
-                                    errors.push(err);
-                                }
-                            }
-                        }
-                        Ok(resp) => {
-                            let err = json!({
-                                "error": format!("Non-200 response for extra file: {}", resp.status()),
-                                "file": filename,
-                                "module": name
-                            });
-                            send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
-                            errors.push(err);
-                        }
-                        Err(e) => {
-                            let err = json!({
-                                "error": format!("Failed to fetch extra file: {}", e),
-                                "file": filename,
-                                "module": name
-                            });
-                            send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
-                            errors.push(err);
-                        }
+                let Some(source) = ArtifactSource::from_manifest(url_val) else { continue };
+
+                // The file is written under the name given in `mountAs`, or the key if not given
+                let mount_as = match url_val.get("mountAs").and_then(Value::as_str) {
+                    Some(mount_as) if mount_as.is_empty() || sanitize_filename::sanitize(mount_as) != mount_as => {
+                        let err = json!({
+                            "error": format!("Invalid mountAs filename '{}'", mount_as),
+                            "file": filename,
+                            "module": name
+                        });
+                        send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
+                        errors.push(err);
+                        continue;
+                    }
+                    Some(mount_as) => mount_as,
+                    None => filename.as_str(),
+                };
+
+                jobs.push(DownloadJob {
+                    module: name.clone(),
+                    kind: ArtifactKind::DataFile(filename.clone()),
+                    path: module_params_path.join(mount_as),
+                    source,
+                    optional: url_val.get("optional").and_then(Value::as_bool).unwrap_or(false),
+                    signature: None,
+                    peers: peers.clone(),
+                });
+            }
+        }
+
+        // Secrets are held in memory only and handed to the module as environment variables
+        if let Some(secret_map) = module.get("secrets").and_then(Value::as_object) {
+            let mut module_secrets = HashMap::new();
+            for (secret_name, value) in secret_map {
+                match value.as_str() {
+                    Some(value) => {
+                        module_secrets.insert(secret_name.clone(), SecretValue::new(value.to_string()));
+                    }
+                    None => {
+                        let err = json!({ "error": format!("Secret '{}' is not a string", secret_name), "module": name });
+                        send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
+                        errors.push(err);
                     }
                 }
             }
+            secrets.insert(name.clone(), module_secrets);
+        }
+
+        module_names.push((id, name, permissions, mount_layout));
+    }
+
+    // Make sure the deployment fits on the device before downloading anything
+    update_progress(&deployment_id, |progress| progress.phase = DeploymentPhase::Downloading);
+    let limits = DownloadLimits::from_env();
+    match check_download_space(&jobs, &limits).await {
+        Ok(total) => {
+            if let Err((status, body)) = check_limits(&deployment_id, data, total) {
+                send_log("ERROR", &body.to_string(), &func_name, None).await;
+                return (status, body);
+            }
+        }
+        Err(PreflightError::TooLarge(msg)) => {
+            send_log("ERROR", &msg, &func_name, None).await;
+            return (StatusCode::PAYLOAD_TOO_LARGE, json!({ "error": msg }));
+        }
+        Err(PreflightError::InsufficientStorage(msg)) => {
+            send_log("ERROR", &msg, &func_name, None).await;
+            return (StatusCode::INSUFFICIENT_STORAGE, json!({ "error": msg }));
+        }
+    }
+
+    let download_timeout = get_deployment_download_timeout();
+    let outcomes = match tokio::time::timeout(
+        std::time::Duration::from_secs(download_timeout),
+        download_all(jobs, get_download_concurrency(), &limits, &deployment_id),
+    ).await {
+        Ok(outcomes) => outcomes,
+        Err(_) => {
+            let msg = format!("Downloading deployment artifacts took longer than {} seconds", download_timeout);
+            send_log("ERROR", &msg, &func_name, None).await;
+            return (StatusCode::GATEWAY_TIMEOUT, json!({ "error": msg }));
+        }
+    };
+
+    // Sort the downloaded files back to their modules
+    let mut binary_sources = HashMap::new();
+    let mut data_files: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut data_file_sources: HashMap<String, HashMap<String, ArtifactSource>> = HashMap::new();
+    let mut downloads = Vec::new();
+    for DownloadOutcome { job, result, duration, stats, from_cache, peer } in outcomes {
+        let source = match (from_cache, &peer) {
+            (true, _) => "cache",
+            (false, Some(_)) => "peer",
+            (false, None) => "network",
+        };
+        let mut report = json!({
+            "module": job.module,
+            "source": source,
+            "durationMs": duration.as_millis() as u64,
+            "retries": stats.retries,
+            "resumes": stats.resumes,
+            "success": result.is_ok(),
+        });
+        if let Some(peer) = peer {
+            report["peer"] = json!(peer);
+        }
+        match (job.kind, result) {
+            (ArtifactKind::Binary, Ok(digest)) => {
+                report["artifact"] = json!("binary");
+                binary_sources.insert(job.module, ArtifactSource { sha256: Some(digest), ..job.source });
+            }
+            (ArtifactKind::Binary, Err(e)) => {
+                report["artifact"] = json!("binary");
+                let err = json!({ "error": format!("Failed to download binary: {}", e), "module": job.module });
+                send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
+                errors.push(err);
+            }
+            (ArtifactKind::DataFile(filename), Ok(digest)) => {
+                report["artifact"] = json!("other");
+                report["file"] = json!(filename);
+                data_files.entry(job.module.clone()).or_default()
+                    .insert(filename.clone(), rollback.placed(&job.path).to_string_lossy().to_string());
+                data_file_sources.entry(job.module).or_default()
+                    .insert(filename, ArtifactSource { sha256: Some(digest), ..job.source });
+            }
+            (ArtifactKind::DataFile(filename), Err(e)) if job.optional => {
+                report["artifact"] = json!("other");
+                report["file"] = json!(filename);
+                let warning = json!({
+                    "warning": format!("Skipped optional extra file: {}", e),
+                    "file": filename,
+                    "module": job.module
+                });
+                send_log("WARN", &format!("{:?}", warning), &func_name, None).await;
+                warnings.push(warning);
+            }
+            (ArtifactKind::DataFile(filename), Err(e)) => {
+                report["artifact"] = json!("other");
+                report["file"] = json!(filename);
+                let err = json!({
+                    "error": format!("Failed to download extra file: {}", e),
+                    "file": filename,
+                    "module": job.module
+                });
+                send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
+                errors.push(err);
+            }
         }
+        downloads.push(report);
+    }
+
+    if !errors.is_empty() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, json!({
+            "error": "One or more modules failed to load",
+            "details": errors,
+            "warnings": warnings,
+            "downloads": downloads
+        }));
+    }
+
+    // Put the downloaded files in place of those of the deployment being replaced
+    if let Some(replaced) = get_deployment(&deployment_id) {
+        replaced_guard = Some(replaced.lock_owned().await);
+    }
+    if let Err(e) = rollback.swap() {
+        let msg = format!("Failed to put the files of the deployment in place: {}", e);
+        send_log("ERROR", &msg, &func_name, None).await;
+        return (StatusCode::INTERNAL_SERVER_ERROR, json!({ "error": msg }));
+    }
+
+    let mut module_configs = Vec::new();
+    for (id, name, permissions, mount_layout) in module_names {
+        let Some(binary_source) = binary_sources.remove(&name) else { continue };
 
         // Construct module config
         let mut config = ModuleConfig {
             id,
             name: name.clone(),
-            path: binary_path,
-            data_files,
+            path: get_module_path(&deployment_id, &name),
+            data_files: data_files.remove(&name).unwrap_or_default(),
             ml_model: None,
             data_ptr_function_name: "get_image_ptr".to_string(),
+            binary_source: Some(binary_source),
+            data_file_sources: data_file_sources.remove(&name).unwrap_or_default(),
+            permissions,
+            mount_layout,
         };
         config.set_model_from_data_files(None);
 
-        module_configs.push(config);
-    }
+        if config.permissions.deployment == MountPermission::Read {
+            for path in config.data_files.values() {
+                if let Err(e) = protect_read_only(Path::new(path)) {
+                    send_log("WARN", &format!("Failed to make {} read-only: {}", path, e), &func_name, None).await;
+                }
+            }
+        }
 
-    if !errors.is_empty() {
-        return HttpResponse::InternalServerError().json(json!({
-            "error": "One or more modules failed to load",
-            "details": errors
-        }));
+        module_configs.push(config);
     }
 
-    // Initialize Wasmtime runtimes for each module with their param folders mounted
-    let mut runtimes = HashMap::new();
-    for config in &module_configs {
+    // Initialize Wasmtime runtimes for all modules at once with their param folders mounted
+    update_progress(&deployment_id, |progress| progress.phase = DeploymentPhase::Initializing);
+    let initializations = futures_util::future::join_all(module_configs.iter().map(|config| {
+        let name = config.name.clone();
         let module_params_dir = get_params_path(&deployment_id, &config.name, None);
-        match WasmtimeRuntime::new(vec![
-            (module_params_dir.to_string_lossy().to_string(), ".".to_string())
-        ]).await {
+        let preopens = config.preopens(&module_params_dir);
+        let env = module_secret_env(secrets.get(&config.name));
+        async move {
+            let started = std::time::Instant::now();
+            let result = init_runtime(preopens, env).await;
+            (name, result, started.elapsed())
+        }
+    })).await;
+    let mut runtimes = HashMap::new();
+    let mut runtime_reports = Vec::new();
+    for (name, result, duration) in initializations {
+        runtime_reports.push(json!({
+            "module": name,
+            "durationMs": duration.as_millis() as u64,
+            "success": result.is_ok(),
+        }));
+        match result {
             Ok(runtime) => {
-                runtimes.insert(config.name.clone(), runtime);
+                runtimes.insert(name, runtime);
             }
             Err(e) => {
-                return HttpResponse::InternalServerError().json(json!({
-                    "error": format!("Failed to initialize runtime: {}", e),
-                    "module": config.name
-                }));
+                let err = json!({ "error": format!("Failed to initialize runtime: {}", e), "module": name });
+                send_log("ERROR", &format!("{:?}", err), &func_name, None).await;
+                errors.push(err);
             }
         }
     }
 
+    if !errors.is_empty() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, json!({
+            "error": "One or more modules failed to initialize",
+            "details": errors,
+            "warnings": warnings,
+            "downloads": downloads,
+            "runtimes": runtime_reports
+        }));
+    }
+
     // Convert endpoints (nested map) to expected type
     let endpoints: ModuleEndpointMap = match data.get("endpoints") {
         Some(Value::Object(mod_map)) => {
@@ -1217,7 +4875,7 @@ pub async fn deployment_create(payload: web::Json<Value>) -> impl Responder {
                                 inner.insert(fn_name.clone(), endpoint);
                             }
                             Err(e) => {
-                                return HttpResponse::BadRequest().json(json!({
+                                return (StatusCode::BAD_REQUEST, json!({
                                     "error": format!("Invalid endpoint for '{}::{}': {}", mod_name, fn_name, e)
                                 }));
                             }
@@ -1231,18 +4889,7 @@ pub async fn deployment_create(payload: web::Json<Value>) -> impl Responder {
         _ => HashMap::new(),
     };
 
-    // Convert instructions and mounts from Map<String, Value> → HashMap<String, Value>
-    let instructions = data.get("instructions")
-        .and_then(|v| v.as_object())
-        .map(|map| map.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
-        .unwrap_or_else(HashMap::new);
-
-    let mounts = data.get("mounts")
-        .and_then(|v| v.as_object())
-        .map(|map| map.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
-        .unwrap_or_else(HashMap::new);
-
-    let deployment = Deployment::new(
+    let mut deployment = Deployment::new(
         deployment_id.clone(),
         runtimes,
         module_configs,
@@ -1250,9 +4897,64 @@ pub async fn deployment_create(payload: web::Json<Value>) -> impl Responder {
         instructions,
         mounts,
     );
+    deployment.set_secrets(secrets);
+    deployment.expires_at = expires_at;
+    deployment.mirror_chained_results = mirror_chained_results;
+    deployment.mqtt_functions = mqtt_functions;
+    deployment.callback_hosts = callback_hosts;
+    deployment.devices = devices;
+    deployment.result_sink = result_sink;
+    deployment.replicas = replicas.clone();
+    deployment.standby = standby;
+    deployment.check_module_loads(&deployment_id).await;
+    for (module_name, e) in &deployment.load_errors {
+        send_log("ERROR", &format!("Module '{}' failed to load: {}", module_name, e), &func_name, None).await;
+    }
+    let load_errors = deployment.load_errors.clone();
+
+    // Healthchecks are run like any execution, but in a state of their own holding only the new
+    // deployment, so that it serves no requests before it has passed them
+    update_progress(&deployment_id, |progress| progress.phase = DeploymentPhase::Healthchecking);
+    let shared = Arc::new(tokio::sync::Mutex::new(deployment));
+    let healthcheck_state = Arc::new(AppState::new(app_state().base_url.clone()));
+    healthcheck_state.deployments.lock().insert(deployment_id.clone(), shared.clone());
+    let mut healthcheck_reports = Vec::new();
+    let mut degraded_modules = HashMap::new();
+    for (module_name, healthcheck) in &healthchecks {
+        let (report, failure) = with_app_state(healthcheck_state.clone(), run_healthcheck(&deployment_id, module_name, healthcheck)).await;
+        if let Some(failure) = failure {
+            send_log("WARN", &format!("Healthcheck of module '{}' failed: {}", module_name, failure), &func_name, None).await;
+            degraded_modules.insert(module_name.clone(), failure);
+        }
+        healthcheck_reports.push(report);
+    }
+    // ...and recorded in the request history of the supervisor
+    let healthcheck_entries: Vec<RequestEntry> = healthcheck_state.request_history.lock().iter().cloned().collect();
+    for entry in healthcheck_entries {
+        let evicted = lock_request_history().push(entry, get_request_history_max_entries());
+        archive_evicted(evicted);
+    }
+    if !degraded_modules.is_empty() && healthcheck_policy == HealthcheckPolicy::Fail {
+        return (StatusCode::INTERNAL_SERVER_ERROR, json!({
+            "error": "One or more modules failed their healthcheck",
+            "healthchecks": healthcheck_reports,
+            "warnings": warnings,
+            "downloads": downloads,
+            "runtimes": runtime_reports
+        }));
+    }
 
     // Save deployment to disk as JSON
-    if let Err(e) = save_deployment_to_disk(&deployment) {
+    let saved = {
+        let mut deployment = shared.lock().await;
+        deployment.degraded_modules = degraded_modules;
+        // Standby copies are put in place paused once their healthchecks have run
+        if deployment.standby {
+            deployment.active = false;
+        }
+        save_deployment_to_disk(&deployment)
+    };
+    if let Err(e) = saved {
         send_log(
             "ERROR",
             &format!("Failed to save deployment {} to disk: {}", deployment_id, e),
@@ -1260,31 +4962,368 @@ pub async fn deployment_create(payload: web::Json<Value>) -> impl Responder {
             None
         ).await;
 
-        return HttpResponse::InternalServerError().json(json!({
+        return (StatusCode::INTERNAL_SERVER_ERROR, json!({
             "error": "Deployment failed to save to disk",
             "details": e
         }));
     }
 
-    DEPLOYMENTS.lock().insert(deployment_id.clone(), deployment);
+    lock_deployments().insert(deployment_id.clone(), shared);
+    EXPIRED_DEPLOYMENTS.lock().remove(&deployment_id);
+    // The statistics start over, leaving out the healthchecks and any previous deployment
+    remove_stats(&deployment_id);
+    remove_memory(&deployment_id);
+    invalidate_description_cache();
+    rollback.disarm();
+    drop(replaced_guard);
 
     send_log("INFO", &format!("Deployment created: {}", deployment_id), &func_name, None).await;
 
-    HttpResponse::Ok().json(json!({
+    if !replicas.is_empty() {
+        let deployment_id = deployment_id.clone();
+        tokio::spawn(in_current_app_state(async move { sync_replicas(&deployment_id).await }));
+    }
+
+    (StatusCode::OK, json!({
         "status": "success",
-        "deploymentId": deployment_id
+        "deploymentId": deployment_id,
+        "warnings": warnings,
+        "downloads": downloads,
+        "runtimes": runtime_reports,
+        "healthchecks": healthcheck_reports,
+        "loadErrors": load_errors
     }))
 }
 
+/// Method of the request entries of healthchecks, to tell them apart in the request history.
+pub const HEALTHCHECK_METHOD: &str = "HEALTHCHECK";
+
+/// Runs the healthcheck of a module of a deployment that has just been created, in the state
+/// holding the deployment until it is put in place (see `build_deployment`).
+///
+/// The function is run like any other execution, so that the run is recorded in the request
+/// history, with the arguments of the healthcheck as `param0`, `param1` and so on. It fails if
+/// the module does not export the function, if the execution fails, as when the function traps
+/// or runs over `WASMIOT_HEALTHCHECK_TIMEOUT_SECONDS` and is stopped, or if it returns something
+/// else than `expect`.
+///
+/// # Returns
+/// The report of the healthcheck, and why it failed if it did.
+async fn run_healthcheck(deployment_id: &str, module_name: &str, healthcheck: &Healthcheck) -> (Value, Option<String>) {
+    let mut report = json!({
+        "module": module_name,
+        "function": healthcheck.function,
+        "expected": healthcheck.expect,
+    });
+    let started = std::time::Instant::now();
+    let outcome = async {
+        if !module_exports(deployment_id, module_name, &healthcheck.function).await? {
+            return Err(format!("Module '{}' does not export function '{}'", module_name, healthcheck.function));
+        }
+        let args: serde_json::Map<String, Value> = healthcheck.args.iter().enumerate()
+            .map(|(i, arg)| (format!("param{}", i), arg.clone()))
+            .collect();
+        let entry = prepare_function_call(deployment_id, module_name, &healthcheck.function, HEALTHCHECK_METHOD, Value::Object(args), None).await
+            .map_err(|(_, e)| e)?;
+        report["requestId"] = json!(entry.request_id);
+        // The execution traps once it runs over the time limit of healthchecks (see `do_wasm_work`)
+        let (entry, _) = task::spawn(in_current_app_state(make_history(entry))).await
+            .map_err(|e| format!("Execution failed: {}", e))?;
+        // Primitive results are recorded as text
+        let returned = match entry.result {
+            Some(Value::String(text)) => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+            other => other.unwrap_or(Value::Null),
+        };
+        report["returned"] = returned.clone();
+        if !entry.success {
+            return Err(format!("Execution failed: {}", returned));
+        }
+        match &healthcheck.expect {
+            Some(expected) if *expected != returned => Err(format!("Returned {} instead of {}", returned, expected)),
+            _ => Ok(()),
+        }
+    }.await;
+    report["durationMs"] = json!(started.elapsed().as_millis() as u64);
+    report["passed"] = json!(outcome.is_ok());
+    let failure = outcome.err();
+    if let Some(failure) = &failure {
+        report["error"] = json!(failure);
+    }
+    (report, failure)
+}
+
+/// Whether a module of a deployment exports a function, compiling the module if it has not
+/// been loaded yet.
+async fn module_exports(deployment_id: &str, module_name: &str, function_name: &str) -> Result<bool, String> {
+    let shared = get_deployment(deployment_id)
+        .ok_or_else(|| format!("Deployment '{}' not found", deployment_id))?;
+    let mut deployment = shared.lock().await;
+    let config = deployment.modules.get(module_name).cloned()
+        .ok_or_else(|| format!("Module '{}' not found in deployment", module_name))?;
+    let runtime = deployment.runtime_of(deployment_id, module_name).await?;
+    let module = runtime.compiled_module(&config)?;
+    Ok(module.get_export(function_name).is_some_and(|export| export.func().is_some()))
+}
+
+/// Initializes the Wasmtime runtime of a module on a blocking thread, so that the runtimes of
+/// the modules of a deployment are set up in parallel instead of one after another.
+async fn init_runtime(
+    preopens: Vec<Preopen>,
+    env: Vec<(String, String)>,
+) -> Result<WasmtimeRuntime, String> {
+    let handle = tokio::runtime::Handle::current();
+    task::spawn_blocking(move || {
+        handle.block_on(WasmtimeRuntime::new(preopens, env)).map_err(|e| e.to_string())
+    })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+}
+
 
+/// Lists the active deployments.
+///
+/// Secrets are never included; modules that were deployed with secrets which are no longer
+/// held (e.g. after a restart) are listed under `needsSecrets`. Deployments whose files could
+/// not be restored at startup are marked `degraded`, with the problems under `missing_files`.
+/// The execution statistics of each deployment are summarized under `stats`.
 pub async fn deployment_get() -> impl Responder {
-    let deps = DEPLOYMENTS.lock();
-    let d: Vec<&Deployment> = deps.iter().map(|(_id, deployment)| deployment).collect();
+    let mut deployments = list_deployments().await;
+    for deployment in &mut deployments {
+        if let Some(id) = deployment["id"].as_str() {
+            deployment["stats"] = stats_summary(id);
+            deployment["loadState"] = json!(LoadState::Ready);
+        }
+    }
+    // Deployments saved before the restart that are still loading or failed to load
+    let mut unloaded: Vec<_> = load_states().into_values()
+        .filter(|load| load.state != LoadState::Ready && get_deployment(&load.deployment_id).is_none())
+        .collect();
+    unloaded.sort_by(|a, b| a.deployment_id.cmp(&b.deployment_id));
+    deployments.extend(unloaded.iter().map(unloaded_deployment_json));
     HttpResponse::Ok().json(json!({
-        "deployments": d
+        "deployments": deployments
     }))
 }
 
+/// Describes the active deployments as `deployment_json` does.
+pub async fn list_deployments() -> Vec<Value> {
+    let mut d: Vec<Value> = Vec::new();
+    for deployment in all_deployments() {
+        d.push(deployment_json(&*deployment.lock().await));
+    }
+    d
+}
+
+/// Describes a deployment, with the modules that `needsSecrets`, whether it is `degraded` and
+/// whether any of its modules `loadFailed`, with why in `loadErrors`.
+pub fn deployment_json(deployment: &Deployment) -> Value {
+    let mut value = json!(deployment);
+    value["needsSecrets"] = json!(deployment.modules_needing_secrets());
+    value["degraded"] = json!(deployment.is_degraded());
+    value["loadFailed"] = json!(!deployment.load_errors.is_empty());
+    for (module_name, functions) in &deployment.examples {
+        for (function_name, examples) in functions {
+            let Some(example) = examples.first() else {
+                continue;
+            };
+            let endpoint = &mut value["endpoints"][module_name][function_name];
+            if endpoint.is_object() {
+                endpoint["request"]["example"] = example.args.clone();
+                endpoint["response"]["example"] = example.result.clone();
+            }
+        }
+    }
+    value
+}
+
+/// Describes a deployment saved before the restart that is still loading or failed to load.
+fn unloaded_deployment_json(load: &DeploymentLoad) -> Value {
+    json!({
+        "id": load.deployment_id,
+        "loadState": load.state,
+        "loadError": load.error,
+    })
+}
+
+/// Returns a single deployment by its ID, with its `loadState`.
+///
+/// Deployments saved before the restart that are still loading, or failed to load, are
+/// answered with their `loadState` alone. Deployments that expired within the grace period are
+/// answered with 410 and the time they expired at, others that do not exist with 404.
+pub async fn deployment_get_by_id(path: web::Path<String>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    if let Some(deployment) = get_deployment(&deployment_id) {
+        let mut deployment = deployment_json(&*deployment.lock().await);
+        deployment["loadState"] = json!(LoadState::Ready);
+        return HttpResponse::Ok().json(deployment);
+    }
+    if let Some(load) = load_state(&deployment_id).filter(|load| load.state != LoadState::Ready) {
+        return HttpResponse::Ok().json(unloaded_deployment_json(&load));
+    }
+    match expired_at(&deployment_id) {
+        Some(expired_at) => HttpResponse::Gone().json(json!({
+            "error": format!("Deployment expired at {}", expired_at.to_rfc3339()),
+            "deployment_id": deployment_id,
+            "expiredAt": expired_at
+        })),
+        None => HttpResponse::NotFound().json(json!({
+            "error": "Deployment does not exist",
+            "deployment_id": deployment_id
+        })),
+    }
+}
+
+/// Returns the execution statistics of each function of a deployment: how many times it was
+/// invoked, how many of those succeeded and failed, when it was last invoked and percentiles
+/// of how long it took (see `stats.rs`), and the `loadErrors` of the modules that failed to
+/// load, which are never invoked. The `memory` of each module with a runtime is measured for
+/// the report (see `wasm_memory.rs`).
+pub async fn deployment_stats(path: web::Path<String>) -> impl Responder {
+    let deployment_id = path.into_inner();
+    let Some(shared) = get_deployment(&deployment_id) else {
+        return HttpResponse::NotFound().json(json!({
+            "error": "Deployment does not exist",
+            "deployment_id": deployment_id
+        }));
+    };
+    let load_errors = {
+        let mut deployment = shared.lock().await;
+        record_deployment_memory(&deployment_id, &mut deployment.runtimes);
+        deployment.load_errors.clone()
+    };
+    let mut report = stats_report(&deployment_id);
+    report["loadErrors"] = json!(load_errors);
+    report["memory"] = memory_report(&deployment_id);
+    HttpResponse::Ok().json(report)
+}
+
+/// Name of the module in the deployment a module uploaded to `dev_run` is run in.
+const DEV_RUN_MODULE: &str = "module";
+
+/// Runs a function of a module uploaded with the request, to try the module out without
+/// deploying it. Only routed when `WASMIOT_DEV_MODE` is on (see `configure_routes`).
+///
+/// The multipart upload has the module in the `module` part, the name of the function in
+/// `function` and its arguments as a JSON object in the optional `args` part. The module is run
+/// in a throwaway deployment of its own, on the execution threads with the same timeout, limits
+/// and argument coercion as deployed modules, and the deployment and its files are removed
+/// after the run. The run is not recorded in the request history.
+pub async fn dev_run(req: HttpRequest, payload: web::Payload) -> impl Responder {
+    let deployment_id = format!("dev-run-{}", Utc::now().format("%Y%m%d%H%M%S%f"));
+    let module_path = get_module_path(&deployment_id, DEV_RUN_MODULE);
+    let result = match receive_dev_run(&req, payload, &module_path).await {
+        Ok((function_name, args)) => run_dev_module(&req, &deployment_id, module_path, function_name, args).await,
+        Err(e) => Err(e),
+    };
+
+    lock_deployments().remove(&deployment_id);
+    invalidate_description_cache();
+    std::fs::remove_dir_all(MODULE_FOLDER.join(&deployment_id)).ok();
+    std::fs::remove_dir_all(PARAMS_FOLDER.join(&deployment_id)).ok();
+
+    match result {
+        Ok(final_json) => HttpResponse::Ok().json(json!({ "result": final_json })),
+        Err((status, e)) => HttpResponse::build(status).json(json!({ "error": e })),
+    }
+}
+
+/// Saves the module uploaded to `dev_run` to `module_path` and reads the function to run and its
+/// arguments.
+async fn receive_dev_run(req: &HttpRequest, payload: web::Payload, module_path: &Path) -> Result<(String, Value), (StatusCode, String)> {
+    const MAX_FIELD_BYTES: usize = 64 * 1024;
+    let bad_request = |e: String| (StatusCode::BAD_REQUEST, e);
+    if let Some(parent) = module_path.parent() {
+        tokio::fs::create_dir_all(parent).await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create module directory: {}", e)))?;
+    }
+
+    let mut has_module = false;
+    let mut function_name = None;
+    let mut args = json!({});
+    let mut multipart = Multipart::new(req.headers(), payload);
+    while let Some(field) = multipart.next().await {
+        let field = field.map_err(|e| bad_request(format!("Invalid multipart upload: {}", e)))?;
+        let name = field.content_disposition().get_name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "module" => {
+                save_upload(field, module_path, "module", Some(get_max_file_bytes())).await?;
+                has_module = true;
+            }
+            "function" => {
+                let function = read_text_field(field, "function", MAX_FIELD_BYTES).await?;
+                function_name = Some(function.trim().to_string());
+            }
+            "args" => {
+                let text = read_text_field(field, "args", MAX_FIELD_BYTES).await?;
+                args = serde_json::from_str(&text).map_err(|e| bad_request(format!("Invalid arguments: {}", e)))?;
+                if !args.is_object() {
+                    return Err(bad_request("Invalid arguments: expected a JSON object".to_string()));
+                }
+            }
+            name => return Err(bad_request(format!("Unknown part '{}'", name))),
+        }
+    }
+
+    if !has_module {
+        return Err(bad_request("Missing module part".to_string()));
+    }
+    let function_name = function_name
+        .filter(|function| !function.is_empty())
+        .ok_or_else(|| bad_request("Missing function part".to_string()))?;
+    Ok((function_name, args))
+}
+
+/// Runs a function of the module uploaded to `dev_run` in a deployment of its own, like
+/// `make_history` runs it in a deployment but without recording the run.
+async fn run_dev_module(
+    req: &HttpRequest,
+    deployment_id: &str,
+    module_path: PathBuf,
+    function_name: String,
+    args: Value,
+) -> Result<Value, (StatusCode, String)> {
+    std::fs::create_dir_all(get_params_path(deployment_id, DEV_RUN_MODULE, None))
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create params directory: {}", e)))?;
+    let mut deployment = Deployment::new(
+        deployment_id.to_string(),
+        HashMap::new(),
+        vec![ModuleConfig::new(DEV_RUN_MODULE.to_string(), DEV_RUN_MODULE.to_string(), module_path, HashMap::new(), None)],
+        HashMap::new(),
+        HashMap::new(),
+        HashMap::new(),
+    );
+    deployment.init();
+    insert_deployment(deployment);
+
+    let mut entry = RequestEntry::new(
+        deployment_id.to_string(),
+        DEV_RUN_MODULE.to_string(),
+        function_name.clone(),
+        req.method().to_string(),
+        args,
+        HashMap::new(),
+        Utc::now(),
+    );
+    entry.caller = req.peer_addr().map(|addr| addr.ip().to_string());
+    let request_id = entry.request_id.clone();
+
+    let func_name = function_name!().to_string();
+    let message = format!("Running function '{}' of an uploaded module in developer mode", function_name);
+    task::spawn(async move {
+        send_log("INFO", &message, &func_name, None).await;
+    });
+
+    let outcome = run_with_priority(entry.priority, move || async move {
+        let mut entry = entry;
+        do_wasm_work(&mut entry).await
+    }).await;
+    finish_execution(&request_id);
+    outcome
+        .and_then(|result| result)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))
+}
+
 
 /// Configures the HTTP routes for the Wasm supervisor API,
 /// and also loads deployments into memory if any are saved on disk
@@ -1295,6 +5334,14 @@ pub async fn deployment_get() -> impl Responder {
 /// - Module execution (GET and POST)
 /// - Result file access
 /// - Execution history tracking
+///
+/// The developer endpoints, such as `POST /dev/run`, are only routed when `WASMIOT_DEV_MODE` is on
+/// and are not found otherwise.
+///
+/// Responses are compressed by the `Compress` middleware the app is wrapped with in `main.rs`,
+/// as middleware cannot be added here. The `X-Wasmiot-Api-Version` header of the responses
+/// (see `orchestrator_compat.rs`) is set there as well. Result files that are compressed already opt out of it
+/// (see `serve_result_file`).
 pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg
         // Returns metadata about the device and supported host functions (WasmIoT spec)
@@ -1306,20 +5353,45 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
         // Health check for device (CPU, memory, network)
         .route("/health", web::get().to(thingi_health))
 
+        // Liveness check of the HTTP server
+        .route("/healthz", web::get().to(healthz))
+
         // Duplicate health route for compatibility (was required at some point)
         .route("//health", web::get().to(thingi_health))
 
+        // Metrics in the Prometheus text format
+        .route("/metrics", web::get().to(metrics))
+
+        // Device twin for the orchestrator
+        .route("/twin", web::get().to(device_twin))
+
+        // Periodically recorded health samples
+        .route("/health/history", web::get().to(health_history))
+
         // Registers the active orchestrator URL to the device
         .route("/register", web::post().to(register_orchestrator))
+        .route("/registration", web::get().to(registration))
+        .route("/audit", web::get().to(audit_log))
+        .route("/startup-report", web::get().to(startup_report_get))
+
+        // Read and update the runtime configuration (e.g. health thresholds)
+        .route("/config", web::get().to(supervisor_config_get))
+        .route("/config", web::patch().to(supervisor_config_patch))
+        .route("/config/reload", web::post().to(supervisor_config_reload))
 
         // Fetch result files generated by module execution
+        .route("/module_results/{deployment_id}/{module_name}/{request_id}/{filename}/meta", web::get().to(get_request_result_meta))
+        .route("/module_results/{deployment_id}/{module_name}/{filename}/meta", web::get().to(get_module_result_meta))
+        .route("/module_results/{deployment_id}/{module_name}/{request_id}/{filename}", web::get().to(get_request_result))
         .route("/module_results/{deployment_id}/{module_name}/{filename}", web::get().to(get_module_result))
+        .route("/module_results/{module_name}/{filename}", web::get().to(get_module_result_legacy))
 
         // Fetch execution history (entire list or single entry by ID)
         .route("/request-history/{request_id}", web::get().to(request_history_list))
         .route("/request-history", web::get().to(request_history_list_1))
 
         // Serve result file produced by specific function execution
+        .route("/{deployment_id}/modules/{module_name}/{function_name}/{request_id}/{filename}", web::get().to(run_module_function_5))
         .route("/{deployment_id}/modules/{module_name}/{function_name}/{filename}", web::get().to(run_module_function))
 
         // Run a module function (GET: immediate execution, no input files)
@@ -1328,13 +5400,59 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
         // Run a module function (POST: allows input files via multipart)
         .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
 
+        // Check that running a module function would be accepted, without running it
+        .route("/{deployment_id}/modules/{module_name}/{function_name}/validate", web::post().to(validate_execution))
+
+        // Invoke a module function as a WoT action, and query or cancel the action
+        .route("/{deployment_id}/actions/{module_name}/{function_name}", web::post().to(invoke_action))
+        .route("/actions/{request_id}", web::get().to(action_status))
+        .route("/actions/{request_id}", web::delete().to(cancel_action_request))
+
         // Delete an existing deployment by ID
         .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
 
+        // Get a single deployment by ID, or when it expired
+        .route("/deploy/{deployment_id}", web::get().to(deployment_get_by_id))
+
+        // How the imports of a module are satisfied
+        .route("/deploy/{deployment_id}/modules/{module_name}/imports", web::get().to(module_imports))
+
+        // Progress of a deployment being created
+        .route("/deploy/{deployment_id}/status", web::get().to(deployment_status))
+
+        // Execution statistics of the functions of a deployment
+        .route("/deploy/{deployment_id}/stats", web::get().to(deployment_stats))
+
+        // Export a deployment as a bundle, or create one from a bundle
+        .route("/deploy/{deployment_id}/export", web::get().to(deployment_export))
+        .route("/deploy/import", web::post().to(deployment_import))
+
+        // Serve verified artifacts by their digest to peer supervisors
+        .route("/modules/by-hash/{sha256}", web::get().to(module_by_hash))
+
+        // Remove module and params folders left behind by deployments that no longer exist
+        .route("/maintenance/gc", web::post().to(maintenance_gc))
+
+        // Pause or resume accepting executions for a deployment
+        .route("/deploy/{deployment_id}/pause", web::post().to(deployment_pause))
+        .route("/deploy/{deployment_id}/resume", web::post().to(deployment_resume))
+
+        // Activate a standby copy of a deployment, and the status of the copies of one
+        .route("/deploy/{deployment_id}/promote", web::post().to(deployment_promote))
+        .route("/deploy/{deployment_id}/replicas", web::get().to(deployment_replicas))
+
+        // Move the devices named in the target URLs of a deployment
+        .route("/deploy/{deployment_id}/devices", web::patch().to(deployment_devices_patch))
+
         // Get a list of all deployments currently active on this device
         .route("/deploy", web::get().to(deployment_get))
 
         // Create a new deployment with modules and optional mount/config data
         .route("/deploy", web::post().to(deployment_create))
-        .route("//deploy", web::post().to(deployment_create)); // This is needed for current version of orchestrator for some reason
+        .route(LEGACY_DEPLOY_PATH, web::post().to(deployment_create));
+
+    if get_dev_mode() {
+        // Run a function of an uploaded module without deploying it
+        cfg.route("/dev/run", web::post().to(dev_run));
+    }
 }
@@ -0,0 +1,111 @@
+//! # app_state.rs
+//!
+//! The state a supervisor keeps in memory for the requests it serves: its deployments, its
+//! request history and the base URL of the links it gives out.
+//!
+//! A supervisor running as a process has one `AppState`, `process_state`, which is what the
+//! HTTP handlers, the background tasks and the other transports use unless told otherwise. An
+//! app serving a supervisor of its own within the process, like each `TestSupervisor` of
+//! `test_support.rs`, gives its `AppState` to the handlers as `web::Data<AppState>` and wraps
+//! them with `scope_request`, so that everything a request reaches through `app_state` is that
+//! of its supervisor, down to the description documents it has cached. Executions keep the
+//! state of the request that queued them on their execution thread (see `execution.rs`), as do
+//! deployments created in the background and requests waiting in the durable queue (see
+//! `durable_queue.rs`).
+//!
+//! The rest of the state stays with the process: the instance folders, the settings and the
+//! configuration, the orchestrator the device is registered to, and what is recorded about
+//! deployments by ID, such as statistics, memory and load failures. Readings of the device by
+//! `sysinfo` are shared as well, as every supervisor runs on the same device.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::web::Data;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use crate::lib::api::{CachedDescription, SharedDeployment};
+use crate::lib::request_history::RequestHistory;
+
+/// The in-memory state of one supervisor.
+pub struct AppState {
+    /// Active deployments by ID (see `api::lock_deployments`).
+    pub deployments: Arc<Mutex<HashMap<String, SharedDeployment>>>,
+    /// History of the executions of the supervisor (see `api::make_history`).
+    pub request_history: Arc<Mutex<RequestHistory>>,
+    /// Base URL of the links the supervisor gives out, in place of the configured one (see
+    /// `configuration::public_url`).
+    pub base_url: Option<String>,
+    /// Serialized `.well-known` description documents, keyed by the document they represent
+    /// (see `api::invalidate_description_cache`).
+    pub descriptions: Mutex<HashMap<&'static str, CachedDescription>>,
+}
+
+impl AppState {
+    /// Creates the state of a supervisor without deployments or history.
+    pub fn new(base_url: Option<String>) -> Self {
+        AppState {
+            deployments: Arc::new(Mutex::new(HashMap::new())),
+            request_history: Arc::new(Mutex::new(RequestHistory::new())),
+            base_url,
+            descriptions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// State of the supervisor the process runs.
+static PROCESS_STATE: Lazy<Arc<AppState>> = Lazy::new(|| Arc::new(AppState::new(None)));
+
+tokio::task_local! {
+    /// State of the supervisor a task is serving, when it is not the one of the process.
+    static SCOPED_STATE: Arc<AppState>;
+}
+
+/// Returns the state of the supervisor the process runs.
+pub fn process_state() -> Arc<AppState> {
+    PROCESS_STATE.clone()
+}
+
+/// Returns the state of the supervisor the running task serves.
+pub fn app_state() -> Arc<AppState> {
+    scoped_app_state().unwrap_or_else(process_state)
+}
+
+/// Returns the state set with `with_app_state` for the running task, if any.
+pub fn scoped_app_state() -> Option<Arc<AppState>> {
+    SCOPED_STATE.try_with(Arc::clone).ok()
+}
+
+/// Runs `future` serving the supervisor with the given state.
+pub async fn with_app_state<F: Future>(state: Arc<AppState>, future: F) -> F::Output {
+    SCOPED_STATE.scope(state, future).await
+}
+
+/// Makes `future` serve the supervisor the running task serves, wherever it is run, e.g. in a
+/// task of its own.
+pub fn in_current_app_state<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let state = scoped_app_state();
+    async move {
+        match state {
+            Some(state) => with_app_state(state, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Middleware for `App::wrap_fn` serving each request with the `web::Data<AppState>` of the
+/// app, if it has one.
+pub fn scope_request<S, B>(req: ServiceRequest, service: &S) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>> + use<S, B>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let state = req.app_data::<Data<AppState>>().map(|state| state.clone().into_inner());
+    let response = service.call(req);
+    async move {
+        match state {
+            Some(state) => with_app_state(state, response).await,
+            None => response.await,
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! # audit.rs
+//!
+//! Append-only audit log of what was done on the device and by whom, kept apart from the debug
+//! logging so that it can be handed over as is: deployments created, updated and deleted,
+//! orchestrator registrations, and the start and finish of every execution.
+//!
+//! The log is written to `AUDIT_FOLDER` as JSON lines, one entry per line. When the current file
+//! `audit.jsonl` would grow over `WASMIOT_AUDIT_MAX_BYTES` it is rotated to `audit.1.jsonl`, the
+//! earlier rotated files are shifted up by one and those over `WASMIOT_AUDIT_MAX_FILES` removed.
+//!
+//! When `WASMIOT_AUDIT_HMAC_KEY` is set, each entry carries the HMAC-SHA256 of its contents and
+//! of the HMAC of the entry before it, so that removing or editing an entry breaks the chain.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use crate::lib::constants::{get_audit_hmac_key, get_audit_max_bytes, get_audit_max_files, AUDIT_FOLDER};
+use crate::structs::request_entry::RequestEntry;
+
+/// Name of the audit log file being written to.
+const CURRENT_FILE_NAME: &str = "audit.jsonl";
+
+/// Where the chain of the audit log is at.
+#[derive(Debug, Default)]
+struct AuditChain {
+    /// Whether the chain has been picked up from the files written before this run.
+    loaded: bool,
+    /// Sequence number of the last entry written.
+    seq: u64,
+    /// HMAC of the last entry written, if it had one.
+    hmac: Option<String>,
+}
+
+/// The chain, also held while an entry is written so that entries are never interleaved.
+static CHAIN: Lazy<Mutex<AuditChain>> = Lazy::new(|| Mutex::new(AuditChain::default()));
+
+/// Path of the audit log file rotated `n` times, or of the current file for 0.
+fn audit_file(n: usize) -> PathBuf {
+    match n {
+        0 => AUDIT_FOLDER.join(CURRENT_FILE_NAME),
+        n => AUDIT_FOLDER.join(format!("audit.{}.jsonl", n)),
+    }
+}
+
+/// Audit log files on disk, oldest first.
+fn audit_files() -> Vec<PathBuf> {
+    let Ok(dir) = fs::read_dir(&*AUDIT_FOLDER) else { return Vec::new() };
+    let mut files: Vec<(usize, PathBuf)> = dir
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let n = match name.as_str() {
+                CURRENT_FILE_NAME => 0,
+                name => name.strip_prefix("audit.")?.strip_suffix(".jsonl")?.parse().ok()?,
+            };
+            Some((n, entry.path()))
+        })
+        .collect();
+    files.sort_by_key(|(n, _)| std::cmp::Reverse(*n));
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+/// HMAC-SHA256 of an entry without its own HMAC, in hex.
+fn entry_hmac(key: &[u8], unsigned: &Value) -> Result<String, String> {
+    let key = PKey::hmac(key).map_err(|e| format!("Invalid audit key: {}", e))?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+    signer.update(unsigned.to_string().as_bytes()).map_err(|e| e.to_string())?;
+    signer.sign_to_vec().map(hex::encode).map_err(|e| e.to_string())
+}
+
+/// Picks the chain up from the last entry written before this run.
+fn load_chain(chain: &mut AuditChain) {
+    chain.loaded = true;
+    let last = audit_files().iter().rev().find_map(|path| {
+        let contents = fs::read_to_string(path).ok()?;
+        contents.lines().rev().find_map(|line| serde_json::from_str::<Value>(line).ok())
+    });
+    if let Some(last) = last {
+        chain.seq = last["seq"].as_u64().unwrap_or(0);
+        chain.hmac = last["hmac"].as_str().map(str::to_string);
+    }
+}
+
+/// Rotates the current file, removing the rotated files over `max_files`.
+fn rotate(max_files: usize) -> Result<(), String> {
+    for path in audit_files() {
+        let n = path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("audit.")?.strip_suffix(".jsonl")?.parse::<usize>().ok())
+            .unwrap_or(0);
+        let result = if n + 1 > max_files {
+            fs::remove_file(&path)
+        } else {
+            fs::rename(&path, audit_file(n + 1))
+        };
+        result.map_err(|e| format!("Failed to rotate {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Appends an entry to the audit log.
+fn write_entry(event: &str, details: Value) -> Result<(), String> {
+    let mut chain = CHAIN.lock();
+    if !chain.loaded {
+        load_chain(&mut chain);
+    }
+    let key = get_audit_hmac_key();
+    let mut entry = json!({
+        "seq": chain.seq + 1,
+        "at": Utc::now().to_rfc3339(),
+        "event": event,
+        "details": details,
+    });
+    let hmac = match &key {
+        Some(key) => {
+            entry["prevHmac"] = json!(chain.hmac);
+            let hmac = entry_hmac(key.as_bytes(), &entry)?;
+            entry["hmac"] = json!(hmac);
+            Some(hmac)
+        }
+        None => None,
+    };
+    let line = format!("{}\n", entry);
+
+    fs::create_dir_all(&*AUDIT_FOLDER)
+        .map_err(|e| format!("Failed to create {}: {}", AUDIT_FOLDER.display(), e))?;
+    let current = audit_file(0);
+    let size = fs::metadata(&current).map(|m| m.len()).unwrap_or(0);
+    if size > 0 && size + line.len() as u64 > get_audit_max_bytes() {
+        rotate(get_audit_max_files())?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&current)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {}", current.display(), e))?;
+
+    chain.seq += 1;
+    chain.hmac = hmac;
+    Ok(())
+}
+
+/// Records an event in the audit log, with the details of what was done and by whom.
+///
+/// Failing to write the entry does not fail what is being audited, and is only logged.
+pub fn audit(event: &str, details: Value) {
+    if let Err(e) = write_entry(event, details) {
+        log::warn!("Failed to write '{}' to the audit log: {}", event, e);
+    }
+}
+
+/// Records the start (`execution.start`) or finish (`execution.finish`) of an execution.
+pub fn audit_execution(event: &str, entry: &RequestEntry) {
+    let mut details = json!({
+        "requestId": entry.request_id,
+        "deployment": entry.deployment_id,
+        "module": entry.module_name,
+        "function": entry.function_name,
+        "method": entry.method,
+        "caller": entry.caller,
+    });
+    if event == "execution.finish" {
+        details["success"] = json!(entry.success);
+    }
+    audit(event, details);
+}
+
+/// Reads entries of the audit log, oldest first.
+///
+/// # Arguments
+/// * `since` - Only entries made at or after this time are read.
+/// * `limit` - Most entries read.
+pub fn read_audit(since: Option<DateTime<Utc>>, limit: usize) -> Vec<Value> {
+    let _chain = CHAIN.lock();
+    let mut entries = Vec::new();
+    for path in audit_files() {
+        let Ok(contents) = fs::read_to_string(&path) else { continue };
+        for entry in contents.lines().filter_map(|line| serde_json::from_str::<Value>(line).ok()) {
+            let at = entry["at"].as_str().and_then(|at| DateTime::parse_from_rfc3339(at).ok());
+            if let Some(since) = since && at.is_none_or(|at| at < since) {
+                continue;
+            }
+            entries.push(entry);
+            if entries.len() >= limit {
+                return entries;
+            }
+        }
+    }
+    entries
+}
+
+/// Checks the HMAC chain of consecutive audit log entries with `WASMIOT_AUDIT_HMAC_KEY`.
+///
+/// The first entry is not checked against the one before it, which may not have been read or
+/// may have been rotated away.
+///
+/// # Returns
+/// Whether every entry has a valid HMAC and points at the entry before it, or `None` when
+/// there is no key to check with.
+pub fn verify_chain(entries: &[Value]) -> Option<bool> {
+    let key = get_audit_hmac_key()?;
+    let mut previous: Option<&Value> = None;
+    for entry in entries {
+        let mut unsigned = entry.clone();
+        let Some(hmac) = unsigned.as_object_mut().and_then(|entry| entry.remove("hmac")) else {
+            return Some(false);
+        };
+        if entry_hmac(key.as_bytes(), &unsigned).ok().as_deref() != hmac.as_str() {
+            return Some(false);
+        }
+        if let Some(previous) = previous && entry["prevHmac"] != previous["hmac"] {
+            return Some(false);
+        }
+        previous = Some(entry);
+    }
+    Some(true)
+}
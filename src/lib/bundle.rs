@@ -0,0 +1,205 @@
+//! # bundle.rs
+//!
+//! Exporting a deployment as a portable bundle and creating a deployment from one.
+//!
+//! A bundle is a gzip compressed tar archive with the following contents:
+//! - `bundle.json`: the bundle schema version, the deployment manifest, and the SHA-256
+//!   digest of every other file in the bundle
+//! - `modules/<module>/<binary>`: the binary of each module
+//! - `modules/<module>/files/<filename>`: the data files of each module
+//!
+//! In the manifest, artifacts refer to the files of the bundle with `localPath`s relative to
+//! the bundle root. On import these are checked against the digests, pointed to the unpacked
+//! files, and the deployment is then created from local files like any other manifest.
+//! Secrets are never included in a bundle and have to be supplied again.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde_json::{json, Map, Value};
+use crate::lib::constants::get_max_deployment_bytes;
+use crate::lib::deployment::Deployment;
+use crate::lib::download::file_sha256;
+
+/// Version of the bundle layout, checked on import.
+pub const BUNDLE_SCHEMA_VERSION: u64 = 1;
+
+/// Name of the file in the bundle holding the manifest and digests.
+const BUNDLE_MANIFEST_NAME: &str = "bundle.json";
+
+/// Builds the manifest of a deployment for a bundle.
+///
+/// # Returns
+/// The manifest, and the files to include in the bundle as pairs of path in the bundle
+/// and path on disk.
+pub fn export_manifest(deployment: &Deployment) -> (Value, Vec<(String, PathBuf)>) {
+    let mut files = Vec::new();
+    let mut modules = Vec::new();
+    for config in &deployment._modules {
+        let module_dir = format!("modules/{}", sanitize_filename::sanitize(&config.name));
+
+        let binary_name = config.path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("{}.wasm", config.name));
+        let binary_path = format!("{}/{}", module_dir, binary_name);
+        files.push((binary_path.clone(), config.path.clone()));
+
+        let mut other = Map::new();
+        for (filename, path) in &config.data_files {
+            let path = PathBuf::from(path);
+            let mount_as = path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| filename.clone());
+            let bundle_path = format!("{}/files/{}", module_dir, mount_as);
+            other.insert(filename.clone(), json!({ "localPath": bundle_path, "mountAs": mount_as }));
+            files.push((bundle_path, path));
+        }
+
+        modules.push(json!({
+            "id": config.id,
+            "name": config.name,
+            "permissions": config.permissions,
+            "mountLayout": config.mount_layout,
+            "urls": {
+                "binary": { "localPath": binary_path },
+                "other": other
+            }
+        }));
+    }
+
+    let manifest = json!({
+        "deploymentId": deployment.id,
+        "modules": modules,
+        "endpoints": deployment.endpoints,
+        "instructions": { "modules": deployment.instructions },
+        "mounts": deployment.mounts,
+        "mirrorChainedResults": deployment.mirror_chained_results,
+        "mqttFunctions": deployment.mqtt_functions,
+        "callbackHosts": deployment.callback_hosts,
+        "devices": deployment.devices,
+        "resultSink": deployment.result_sink,
+    });
+    (manifest, files)
+}
+
+/// Writes a bundle of the given manifest and files, as listed by `export_manifest`.
+///
+/// # Returns
+/// The gzip compressed tar archive.
+pub fn build_bundle(manifest: &Value, files: &[(String, PathBuf)]) -> Result<Vec<u8>, String> {
+    let mut checksums = Map::new();
+    for (bundle_path, path) in files {
+        let digest = file_sha256(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        checksums.insert(bundle_path.clone(), json!(digest));
+    }
+    let bundle_manifest = serde_json::to_vec_pretty(&json!({
+        "schemaVersion": BUNDLE_SCHEMA_VERSION,
+        "manifest": manifest,
+        "checksums": checksums,
+    })).map_err(|e| format!("Failed to serialize bundle manifest: {}", e))?;
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bundle_manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, BUNDLE_MANIFEST_NAME, bundle_manifest.as_slice())
+        .map_err(|e| format!("Failed to write bundle: {}", e))?;
+    for (bundle_path, path) in files {
+        builder.append_path_with_name(path, bundle_path)
+            .map_err(|e| format!("Failed to add {} to bundle: {}", path.display(), e))?;
+    }
+    builder.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| format!("Failed to write bundle: {}", e))
+}
+
+/// Returns the path of a file in an unpacked bundle, refusing paths that would lead outside of it.
+fn bundle_file_path(dest: &Path, bundle_path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(bundle_path);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!("Invalid path '{}' in bundle", bundle_path));
+    }
+    Ok(dest.join(relative))
+}
+
+/// Unpacks a bundle into `dest` and verifies its schema version and the digests of its files.
+///
+/// # Returns
+/// The deployment manifest of the bundle, with every `localPath` pointing to the unpacked
+/// file and carrying its digest, ready to be given to `create_deployment`.
+pub fn unpack_bundle(archive: &Path, dest: &Path) -> Result<Value, String> {
+    let file = File::open(archive)
+        .map_err(|e| format!("Failed to open bundle: {}", e))?;
+    let mut tar = tar::Archive::new(GzDecoder::new(file));
+    let max_bytes = get_max_deployment_bytes();
+    let mut unpacked_bytes: u64 = 0;
+    for entry in tar.entries().map_err(|e| format!("Invalid bundle: {}", e))? {
+        let mut entry = entry.map_err(|e| format!("Invalid bundle: {}", e))?;
+        if !matches!(entry.header().entry_type(), tar::EntryType::Regular | tar::EntryType::Directory) {
+            return Err("Bundle may only contain regular files and directories".to_string());
+        }
+        unpacked_bytes += entry.header().size().unwrap_or(0);
+        if unpacked_bytes > max_bytes {
+            return Err(format!("Bundle is over the limit of {} bytes per deployment", max_bytes));
+        }
+        let unpacked = entry.unpack_in(dest)
+            .map_err(|e| format!("Failed to unpack bundle: {}", e))?;
+        if !unpacked {
+            return Err("Bundle contains a path outside of the bundle".to_string());
+        }
+    }
+
+    let contents = std::fs::read_to_string(dest.join(BUNDLE_MANIFEST_NAME))
+        .map_err(|e| format!("Bundle has no readable {}: {}", BUNDLE_MANIFEST_NAME, e))?;
+    let bundle: Value = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid {}: {}", BUNDLE_MANIFEST_NAME, e))?;
+
+    let version = bundle.get("schemaVersion").and_then(Value::as_u64);
+    if version != Some(BUNDLE_SCHEMA_VERSION) {
+        return Err(format!(
+            "Unsupported bundle schema version {}, expected {}",
+            bundle.get("schemaVersion").unwrap_or(&Value::Null), BUNDLE_SCHEMA_VERSION
+        ));
+    }
+
+    let checksums: HashMap<String, String> = bundle.get("checksums")
+        .cloned()
+        .and_then(|c| serde_json::from_value(c).ok())
+        .ok_or_else(|| format!("{} has no valid checksums", BUNDLE_MANIFEST_NAME))?;
+    for (bundle_path, expected) in &checksums {
+        let path = bundle_file_path(dest, bundle_path)?;
+        let actual = file_sha256(&path)
+            .map_err(|e| format!("File '{}' of the bundle is not available: {}", bundle_path, e))?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(format!("Checksum mismatch for '{}': expected {} got {}", bundle_path, expected, actual));
+        }
+    }
+
+    let mut manifest = bundle.get("manifest").cloned()
+        .ok_or_else(|| format!("{} has no manifest", BUNDLE_MANIFEST_NAME))?;
+    let modules = manifest.get_mut("modules").and_then(Value::as_array_mut).into_iter().flatten();
+    for module in modules {
+        let Some(Value::Object(urls)) = module.get_mut("urls") else { continue };
+        let mut artifacts = Vec::new();
+        for (key, value) in urls.iter_mut() {
+            match (key.as_str(), value) {
+                ("binary", binary) => artifacts.push(binary),
+                ("other", Value::Object(others)) => artifacts.extend(others.values_mut()),
+                _ => {}
+            }
+        }
+        for artifact in artifacts {
+            let Some(bundle_path) = artifact.get("localPath").and_then(Value::as_str).map(str::to_string) else { continue };
+            let digest = checksums.get(&bundle_path)
+                .ok_or_else(|| format!("File '{}' of the manifest has no checksum in the bundle", bundle_path))?;
+            artifact["localPath"] = json!(bundle_file_path(dest, &bundle_path)?.to_string_lossy());
+            artifact["sha256"] = json!(digest);
+        }
+    }
+    Ok(manifest)
+}
@@ -0,0 +1,149 @@
+//! # callback.rs
+//!
+//! Callbacks telling callers that an execution has finished, so that they do not have to poll
+//! `/request-history/{request_id}`.
+//!
+//! A request to run a function may give a `callbackUrl`. Its host must be listed in
+//! `callbackHosts` of the deployment, so that the device cannot be used to probe the networks
+//! it is in, and redirects from it are not followed. Once the execution has been recorded (see
+//! `make_history`), the request entry is posted to the URL as JSON. A failed delivery is retried
+//! `WASMIOT_CALLBACK_RETRIES` times with a doubling delay, each attempt limited to
+//! `WASMIOT_CALLBACK_TIMEOUT_SECONDS`. How the delivery went is recorded in `callback` of the
+//! entry, and does not change whether the execution succeeded.
+//!
+//! When `WASMIOT_CALLBACK_SECRET` is set, a callback carries `X-Wasmiot-Timestamp`, the Unix
+//! time it was sent at, and `X-Wasmiot-Signature: sha256=<hex>`, the HMAC-SHA256 of
+//! `<timestamp>.<body>` with the secret. The receiver can check with them that the callback
+//! came from this device and is not a replay of an old one.
+
+use std::time::Duration;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use reqwest::Url;
+use crate::function_name;
+use crate::lib::api::record_callback_delivery;
+use crate::lib::constants::{get_callback_retries, get_callback_secret, get_callback_timeout};
+use crate::lib::logging::send_log;
+use crate::structs::request_entry::{CallbackDelivery, RequestEntry};
+
+/// Header with the Unix time a callback was sent at.
+pub const TIMESTAMP_HEADER: &str = "X-Wasmiot-Timestamp";
+
+/// Header with the signature of a callback.
+pub const SIGNATURE_HEADER: &str = "X-Wasmiot-Signature";
+
+/// Client for callbacks, which does not follow redirects to hosts that are not allowed.
+static CALLBACK_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap_or_default()
+});
+
+/// Parses a callback URL, which must be an HTTP(S) URL.
+pub fn parse_callback_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid callbackUrl '{}': {}", url, e))?;
+    if parsed.scheme() != "http" && parsed.scheme() != "https" {
+        return Err(format!("callbackUrl must be an http or https URL, not {}", parsed.scheme()));
+    }
+    Ok(parsed)
+}
+
+/// Whether the host of a callback URL is one of the allowed hosts, given as `host` for any
+/// port or as `host:port`.
+pub fn callback_host_allowed(url: &Url, allowed_hosts: &[String]) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default();
+    allowed_hosts.iter().any(|allowed| {
+        match allowed.rsplit_once(':').and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?))) {
+            Some((allowed_host, allowed_port)) => allowed_host.eq_ignore_ascii_case(host) && Some(allowed_port) == port,
+            None => allowed.eq_ignore_ascii_case(host),
+        }
+    })
+}
+
+/// Returns the hex encoded signature of a callback body sent at `timestamp` with `secret`.
+pub fn callback_signature(secret: &[u8], timestamp: i64, body: &[u8]) -> Result<String, String> {
+    let key = PKey::hmac(secret).map_err(|e| format!("Invalid secret: {}", e))?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+    signer.update(format!("{}.", timestamp).as_bytes()).map_err(|e| e.to_string())?;
+    signer.update(body).map_err(|e| e.to_string())?;
+    signer.sign_to_vec().map(hex::encode).map_err(|e| e.to_string())
+}
+
+/// Posts an executed request to its callback URL, retrying failed attempts, and records how it
+/// went in the request history.
+pub async fn deliver_callback(entry: RequestEntry) {
+    let Some(url) = entry.callback_url.clone() else {
+        return;
+    };
+    let func_name = function_name!().to_string();
+    let mut delivery = CallbackDelivery::default();
+    let body = match serde_json::to_vec(&entry) {
+        Ok(body) => body,
+        Err(e) => {
+            delivery.error = Some(format!("Failed to serialize the request: {}", e));
+            record_callback_delivery(&entry.request_id, delivery);
+            return;
+        }
+    };
+
+    let mut delay = Duration::from_secs(1);
+    for attempt in 0..=get_callback_retries() {
+        if attempt > 0 {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+        delivery.attempts += 1;
+        match post_callback(&url, &body).await {
+            Ok(status) if (200..300).contains(&status) => {
+                delivery.delivered = true;
+                delivery.status = Some(status);
+                delivery.error = None;
+                break;
+            }
+            Ok(status) => {
+                delivery.status = Some(status);
+                delivery.error = Some(format!("Callback URL answered with status {}", status));
+            }
+            Err(e) => {
+                delivery.status = None;
+                delivery.error = Some(e);
+            }
+        }
+    }
+
+    if delivery.delivered {
+        send_log("DEBUG", &format!("Delivered callback of request {} to {}", entry.request_id, url), &func_name, Some(&entry)).await;
+    } else {
+        let message = format!(
+            "Failed to deliver callback of request {} to {} after {} attempts: {}",
+            entry.request_id, url, delivery.attempts, delivery.error.as_deref().unwrap_or_default()
+        );
+        send_log("WARN", &message, &func_name, Some(&entry)).await;
+    }
+    record_callback_delivery(&entry.request_id, delivery);
+}
+
+/// Posts a callback body once, signed if a callback secret is set.
+///
+/// # Returns
+/// The HTTP status of the response.
+async fn post_callback(url: &str, body: &[u8]) -> Result<u16, String> {
+    let mut request = CALLBACK_CLIENT.post(url)
+        .timeout(Duration::from_secs(get_callback_timeout()))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_vec());
+    if let Some(secret) = get_callback_secret() {
+        let timestamp = Utc::now().timestamp();
+        let signature = callback_signature(secret.as_bytes(), timestamp, body)?;
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, format!("sha256={}", signature));
+    }
+    let response = request.send().await.map_err(|e| format!("Failed to post callback: {}", e))?;
+    Ok(response.status().as_u16())
+}
@@ -0,0 +1,113 @@
+//! # camera.rs
+//!
+//! Whether the camera of the device may be used.
+//!
+//! `WASMIOT_CAMERA_ENABLED` is `true`, `false` or `auto` (the default), which enables the camera
+//! if one is found at startup. Devices without a camera, and devices whose camera must not be
+//! used, e.g. in privacy zones, then run with the camera disabled: the camera host functions
+//! give `CAMERA_UNAVAILABLE` instead of capturing, the camera functions are left out of the
+//! device description and the mDNS TXT records, and deployments that need them are rejected.
+//!
+//! The camera is used through the backend of the OS: V4L2 on Linux, AVFoundation on macOS and
+//! Media Foundation on Windows. On macOS the user is asked for access to the camera before it
+//! is used, and the camera is disabled if access is not granted. Elsewhere there is no backend
+//! and the camera is never found.
+
+use once_cell::sync::Lazy;
+use nokhwa::utils::ApiBackend;
+use serde_json::Value;
+use crate::lib::constants::CAMERA_FUNCTIONS;
+use crate::lib::settings::get_setting;
+
+/// Written by the camera host functions in place of the image size when the camera is
+/// disabled, so that modules can tell it apart from a failed capture.
+pub const CAMERA_UNAVAILABLE: u32 = u32::MAX;
+
+/// Whether the camera is enabled, decided the first time it is asked.
+static CAMERA_ENABLED: Lazy<bool> = Lazy::new(|| {
+    let setting = get_setting("WASMIOT_CAMERA_ENABLED");
+    match parse_camera_enabled(setting.as_deref().unwrap_or("auto")) {
+        Ok(Some(enabled)) => enabled && camera_access_granted(),
+        Ok(None) => detect_camera(),
+        Err(e) => {
+            log::warn!("Detecting the camera, since WASMIOT_CAMERA_ENABLED is invalid: {}", e);
+            detect_camera()
+        }
+    }
+});
+
+/// Parses the value of `WASMIOT_CAMERA_ENABLED`.
+///
+/// # Returns
+/// Whether the camera is enabled, or `None` if it is to be detected.
+pub fn parse_camera_enabled(value: &str) -> Result<Option<bool>, String> {
+    match value.trim().to_lowercase().as_str() {
+        "true" => Ok(Some(true)),
+        "false" => Ok(Some(false)),
+        "auto" | "" => Ok(None),
+        other => Err(format!("'{}' is not true, false or auto", other)),
+    }
+}
+
+/// Whether the camera may be used by modules.
+pub fn camera_enabled() -> bool {
+    *CAMERA_ENABLED
+}
+
+/// The camera backend of the OS the supervisor runs on.
+pub fn camera_backend() -> ApiBackend {
+    nokhwa::native_api_backend().unwrap_or(ApiBackend::Auto)
+}
+
+/// Asks the user for access to the camera, which macOS requires before it can be used, and
+/// waits for the answer.
+#[cfg(target_os = "macos")]
+fn camera_access_granted() -> bool {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    nokhwa::nokhwa_initialize(move |granted| {
+        let _ = sender.send(granted);
+    });
+    let granted = receiver.recv_timeout(std::time::Duration::from_secs(60)).unwrap_or(false);
+    if !granted {
+        log::warn!("Access to the camera was not granted, disabling the camera");
+    }
+    granted
+}
+
+/// Access to the camera needs no asking outside macOS.
+#[cfg(not(target_os = "macos"))]
+fn camera_access_granted() -> bool {
+    true
+}
+
+/// Looks for cameras connected to the device.
+fn detect_camera() -> bool {
+    if !camera_access_granted() {
+        return false;
+    }
+    match nokhwa::query(camera_backend()) {
+        Ok(cameras) => !cameras.is_empty(),
+        Err(e) => {
+            log::debug!("No camera detected: {}", e);
+            false
+        }
+    }
+}
+
+/// Returns the names of the modules in a deployment manifest that need the camera, i.e. have
+/// `camera` in their `capabilities` or a camera function in their `requirements`.
+pub fn modules_requiring_camera(manifest: &Value) -> Vec<String> {
+    let names = |module: &Value, field: &str| -> Vec<String> {
+        module.get(field).and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|item| item.as_str().or_else(|| item.get("name").and_then(Value::as_str)))
+            .map(str::to_string)
+            .collect()
+    };
+    manifest["modules"].as_array().into_iter().flatten()
+        .filter(|module| {
+            names(module, "capabilities").iter().any(|name| name == "camera")
+                || names(module, "requirements").iter().any(|name| CAMERA_FUNCTIONS.contains(&name.as_str()))
+        })
+        .map(|module| module.get("name").and_then(Value::as_str).unwrap_or("unknown").to_string())
+        .collect()
+}
@@ -0,0 +1,114 @@
+//! # checksum.rs
+//!
+//! Metadata and SHA-256 digests of result files.
+//!
+//! Systems pulling result files can check they got the right bytes with the digest returned
+//! by `GET /module_results/.../meta` or in the `Digest` and `ETag` headers of the download.
+//! Hashing is done on the first request for a file, and the digest is kept in a sidecar file
+//! `.<filename>.sha256` next to it together with the size and modification time it was
+//! computed for, so that later requests reuse it until the file changes.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Suffix of the sidecar files holding the digests of result files.
+pub const SIDECAR_SUFFIX: &str = ".sha256";
+
+/// Metadata of a result file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileMetadata {
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+    #[serde(rename="contentType")]
+    pub content_type: String,
+    /// Hex encoded SHA-256 digest of the contents
+    pub sha256: String,
+}
+
+/// Contents of a sidecar file, valid while the file still has the same size and modification time.
+#[derive(Debug, Serialize, Deserialize)]
+struct Sidecar {
+    sha256: String,
+    size: u64,
+    #[serde(rename="modifiedNanos")]
+    modified_nanos: u128,
+}
+
+/// Returns the path of the sidecar file holding the digest of `path`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}{}", name, SIDECAR_SUFFIX))
+}
+
+/// Whether `path` is the sidecar file of some other file.
+pub fn is_sidecar(path: &Path) -> bool {
+    path.file_name()
+        .map(|n| n.to_string_lossy())
+        .is_some_and(|n| n.starts_with('.') && n.ends_with(SIDECAR_SUFFIX))
+}
+
+/// Returns the hex encoded SHA-256 digest of a file, hashing it only if the sidecar file
+/// is missing or was written for an earlier version of the file.
+pub fn file_digest(path: &Path) -> Result<String, String> {
+    let metadata = fs::metadata(path)
+        .map_err(|e| format!("Failed to read metadata of {}: {}", path.display(), e))?;
+    let size = metadata.len();
+    let modified_nanos = metadata.modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    let sidecar = sidecar_path(path);
+    if let Ok(contents) = fs::read_to_string(&sidecar)
+        && let Ok(cached) = serde_json::from_str::<Sidecar>(&contents)
+        && cached.size == size
+        && cached.modified_nanos == modified_nanos
+    {
+        return Ok(cached.sha256);
+    }
+
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let sha256 = hex::encode(hasher.finalize());
+
+    let cached = Sidecar { sha256: sha256.clone(), size, modified_nanos };
+    // The digest is still usable if it cannot be cached, e.g. in a read-only folder
+    if let Err(e) = serde_json::to_string(&cached)
+        .map_err(|e| e.to_string())
+        .and_then(|s| fs::write(&sidecar, s).map_err(|e| e.to_string()))
+    {
+        log::warn!("Failed to write digest of {} to {}: {}", path.display(), sidecar.display(), e);
+    }
+    Ok(sha256)
+}
+
+/// Returns the size, modification time, content type and digest of a file.
+pub fn file_metadata(path: &Path) -> Result<FileMetadata, String> {
+    let metadata = fs::metadata(path)
+        .map_err(|e| format!("Failed to read metadata of {}: {}", path.display(), e))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    let extension = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(FileMetadata {
+        size: metadata.len(),
+        modified: DateTime::<Utc>::from(metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)),
+        content_type: actix_files::file_extension_to_mime(&extension).to_string(),
+        sha256: file_digest(path)?,
+    })
+}
+
+/// Formats a hex encoded SHA-256 digest as the value of a `Digest` header (RFC 3230).
+pub fn digest_header_value(sha256: &str) -> Option<String> {
+    let bytes = hex::decode(sha256).ok()?;
+    Some(format!("sha-256={}", openssl::base64::encode_block(&bytes)))
+}
@@ -0,0 +1,261 @@
+//! # cli.rs
+//!
+//! Command-line arguments of the supervisor.
+//!
+//! Every option can also be given in the environment variable shown in `--help`, in the `.env`
+//! file, or in the TOML configuration file (see `config_file.rs`). Values are taken in the order:
+//! command line, environment, `.env`, configuration file, default. Values that are invalid,
+//! wherever they come from, make the supervisor exit with a message instead of being replaced
+//! with the default.
+
+use std::ffi::OsString;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use crate::lib::config_file::{setting_sources, ConfigFile};
+use crate::lib::settings::{get_setting, get_setting_with_source, is_setting_set, load_env_file, set_setting, SettingSource};
+use crate::lib::constants::{CONFIG_FILE_NAME, DEFAULT_PORT, MAX_SERVICE_NAME_BYTES, SUPERVISOR_DEFAULT_NAME};
+use crate::structs::supervisor_config::StartupConfig;
+
+/// Runs WebAssembly modules deployed by a Wasmiot orchestrator.
+#[derive(Debug, Clone, Parser)]
+#[command(name = "supervisor", version)]
+pub struct Cli {
+    /// Port to serve the HTTP API on
+    #[arg(long, env = "WASMIOT_SUPERVISOR_PORT", default_value_t = DEFAULT_PORT, value_parser = clap::value_parser!(u16).range(1..))]
+    pub port: u16,
+
+    /// Address to listen on, e.g. the address of the management network interface. 0.0.0.0 listens on all interfaces
+    #[arg(long, env = "WASMIOT_BIND_ADDRESS", default_value = "0.0.0.0", value_parser = parse_bind_address)]
+    pub bind_address: IpAddr,
+
+    /// Address advertised over mDNS and used in the URLs given out. Defaults to the bind address,
+    /// or the address of the default network interface when listening on all interfaces
+    #[arg(long, env = "WASMIOT_ADVERTISE_ADDRESS")]
+    pub advertise_address: Option<IpAddr>,
+
+    /// Folder where deployments, modules and their files are kept
+    #[arg(long, env = "INSTANCE_PATH", default_value = "./instance")]
+    pub instance_path: PathBuf,
+
+    /// URL of an orchestrator to register to right away, instead of waiting to be discovered
+    #[arg(long, env = "WASMIOT_ORCHESTRATOR_URL", value_parser = parse_http_url)]
+    pub orchestrator_url: Option<String>,
+
+    /// Name the supervisor advertises itself with. WASMIOT_SUPERVISOR_NAME is read as well
+    #[arg(long, env = "SUPERVISOR_NAME", value_parser = parse_name)]
+    pub name: Option<String>,
+
+    /// Log level (error, warn, info, debug, trace or off) or a filter like `info,supervisor=debug`
+    #[arg(long, env = "RUST_LOG", default_value = "info", value_parser = parse_log_filter)]
+    pub log_level: String,
+
+    /// TOML file to read settings from, instead of `supervisor.toml` in the instance folder
+    #[arg(long, env = "WASMIOT_CONFIG_FILE")]
+    pub config_file: Option<PathBuf>,
+
+    /// Check the settings, the instance folders and the Wasm engine, print a report and exit
+    /// without starting the supervisor. Exits with an error if a check fails
+    #[arg(long)]
+    pub check: bool,
+
+    /// Capture a frame from the camera as part of `--check`
+    #[arg(long, requires = "check")]
+    pub check_camera: bool,
+
+    /// Print the merged settings in the format of the configuration file and exit
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+impl Cli {
+    /// Parses the command line, reading the settings not given on it from the environment,
+    /// `.env` and the configuration file. Exits with a message on `--help`, `--version` and
+    /// invalid values.
+    pub fn load() -> Self {
+        match load_env_file() {
+            Ok(()) => eprintln!("Loaded .env"),
+            Err(err) => eprintln!("Could not load .env file: {}", err),
+        }
+
+        // The configuration file may itself be given on the command line or in the environment
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let (cli, _) = Cli::parse_with_settings(args.clone()).unwrap_or_else(|e| e.exit());
+        let config_file = cli.config_file.clone().or_else(|| {
+            Some(cli.instance_path.join(CONFIG_FILE_NAME)).filter(|path| path.is_file())
+        });
+        if let Some(path) = &config_file {
+            match ConfigFile::load(path) {
+                Ok((config, unknown)) => {
+                    for key in unknown {
+                        eprintln!("Warning: ignoring unknown setting '{}' in {}", key, path.display());
+                    }
+                    config.apply();
+                    eprintln!("Loaded config file {:?}", path);
+                }
+                Err(e) => Cli::command().error(ErrorKind::Io, e).exit(),
+            }
+        }
+
+        // Parse again, now that the configuration file has been added to the settings
+        let (mut cli, given) = Cli::parse_with_settings(args).unwrap_or_else(|e| e.exit());
+        cli.config_file = config_file;
+
+        // The rest of the supervisor reads the options from the settings, which do not have the
+        // ones given on the command line or left to their defaults yet
+        for (name, value) in cli.option_settings() {
+            if given.iter().any(|given| given == name) {
+                set_setting(name, value, SettingSource::CommandLine);
+            } else if !is_setting_set(name) {
+                set_setting(name, value, SettingSource::Default);
+            }
+        }
+        if !is_setting_set("SUPERVISOR_NAME") {
+            let (name, source) = get_setting_with_source("WASMIOT_SUPERVISOR_NAME")
+                .filter(|(name, _)| !name.trim().is_empty())
+                .unwrap_or_else(|| (SUPERVISOR_DEFAULT_NAME.to_string(), SettingSource::Default));
+            set_setting("SUPERVISOR_NAME", name, source);
+        }
+        cli
+    }
+
+    /// Returns the environment variable and value of each option that has a value.
+    fn option_settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = vec![
+            ("WASMIOT_SUPERVISOR_PORT", self.port.to_string()),
+            ("WASMIOT_BIND_ADDRESS", self.bind_address.to_string()),
+            ("INSTANCE_PATH", self.instance_path.to_string_lossy().into_owned()),
+            ("RUST_LOG", self.log_level.clone()),
+        ];
+        if let Some(address) = self.advertise_address {
+            settings.push(("WASMIOT_ADVERTISE_ADDRESS", address.to_string()));
+        }
+        if let Some(url) = &self.orchestrator_url {
+            settings.push(("WASMIOT_ORCHESTRATOR_URL", url.clone()));
+        }
+        if let Some(name) = &self.name {
+            settings.push(("SUPERVISOR_NAME", name.clone()));
+        }
+        settings
+    }
+
+    /// Parses the command line `args`, taking the options not given in it from the settings
+    /// (see `settings.rs`), which hold the environment, `.env` and the configuration file.
+    ///
+    /// # Returns
+    /// The options, and the environment variables of the options given in `args`.
+    pub fn parse_with_settings<I, T>(args: I) -> Result<(Cli, Vec<String>), clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let command = Cli::command();
+        let matches = command.clone().try_get_matches_from(&args)?;
+        let mut given = Vec::new();
+        for arg in command.get_arguments() {
+            let (Some(env), Some(long)) = (arg.get_env().and_then(|env| env.to_str()), arg.get_long()) else {
+                continue;
+            };
+            if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+                given.push(env.to_string());
+            } else if let Some(value) = get_setting(env) {
+                args.push(format!("--{}={}", long, value).into());
+            }
+        }
+        let matches = command.try_get_matches_from(&args)?;
+        Ok((Cli::from_arg_matches(&matches)?, given))
+    }
+
+    /// Returns the settings the supervisor is started with.
+    pub fn startup_config(&self) -> StartupConfig {
+        let name = self.name.clone()
+            .or_else(|| get_setting("WASMIOT_SUPERVISOR_NAME").filter(|name| !name.trim().is_empty()))
+            .unwrap_or_else(|| SUPERVISOR_DEFAULT_NAME.to_string());
+        // The options on the command line are not in the settings yet
+        let mut settings = ConfigFile::from_settings();
+        settings.supervisor.name = Some(name.clone());
+        settings.network.port = Some(self.port);
+        settings.network.bind_address = Some(self.bind_address.to_string());
+        settings.network.advertise_address = self.advertise_address.map(|address| address.to_string());
+        settings.orchestrator.url = self.orchestrator_url.clone();
+        settings.logging.level = Some(self.log_level.clone());
+        StartupConfig {
+            port: self.port,
+            bind_address: self.bind_address,
+            advertise_address: self.advertise_address,
+            instance_path: self.instance_path.clone(),
+            orchestrator_url: self.orchestrator_url.clone(),
+            name,
+            log_level: self.log_level.clone(),
+            config_file: self.config_file.clone(),
+            settings,
+            sources: setting_sources(),
+        }
+    }
+}
+
+/// Checks that a value is an absolute http(s) URL.
+pub(crate) fn parse_http_url(value: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(value).map_err(|e| format!("not a valid URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("must be an http or https URL".to_string());
+    }
+    Ok(value.to_string())
+}
+
+/// Checks that an address to listen on is an IP address assigned to this device, or one of the
+/// addresses that always are (0.0.0.0, ::, loopback).
+pub(crate) fn parse_bind_address(value: &str) -> Result<IpAddr, String> {
+    let address: IpAddr = value.parse().map_err(|_| format!("'{}' is not an IP address", value))?;
+    if address.is_unspecified() || address.is_loopback() {
+        return Ok(address);
+    }
+    let interfaces = local_ip_address::list_afinet_netifas()
+        .map_err(|e| format!("could not list the network interfaces to check the address: {}", e))?;
+    if interfaces.iter().any(|(_, ip)| *ip == address) {
+        return Ok(address);
+    }
+    let available = interfaces.iter()
+        .map(|(name, ip)| format!("{} ({})", ip, name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(format!(
+        "{} is not assigned to any network interface of this device. Use one of: {}, or 0.0.0.0 to listen on all interfaces",
+        address, available
+    ))
+}
+
+/// Checks that a name can be advertised as a DNS-SD service instance name: not empty, at most
+/// 63 bytes of UTF-8 and without control characters. Dots and spaces are allowed.
+pub(crate) fn parse_name(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if value.len() > MAX_SERVICE_NAME_BYTES {
+        return Err(format!(
+            "'{}' is {} bytes long, but DNS-SD instance names are at most {} bytes",
+            value, value.len(), MAX_SERVICE_NAME_BYTES
+        ));
+    }
+    if let Some(c) = value.chars().find(|c| c.is_control()) {
+        return Err(format!(
+            "'{}' contains the control character {:?}, which DNS-SD instance names cannot have",
+            value.escape_debug(), c
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Checks that a value is a log level, or comma separated `level` and `module=level` directives.
+pub(crate) fn parse_log_filter(value: &str) -> Result<String, String> {
+    for directive in value.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let level = directive.rsplit_once('=').map(|(_, level)| level).unwrap_or(directive);
+        if level.parse::<log::LevelFilter>().is_err() {
+            return Err(format!("'{}' is not a log level or a `module=level` directive", directive));
+        }
+    }
+    Ok(value.to_string())
+}
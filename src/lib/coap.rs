@@ -0,0 +1,622 @@
+//! # coap.rs
+//!
+//! CoAP server for constrained clients, such as battery powered sensor nodes that cannot speak
+//! HTTP. Enabled by setting `WASMIOT_COAP_PORT` to the UDP port to listen on.
+//!
+//! `GET` and `POST` on `/<deployment>/<module>/<function>` run the function like the HTTP API
+//! does, with the Uri-Query options as its arguments and the payload of a `POST` as its input
+//! file (see `execute_function`). The response carries the same JSON as over HTTP, or CBOR when
+//! the request asks for it with `Accept: 60`. `GET /.well-known/core` lists the deployed
+//! functions in the CoRE Link Format.
+//!
+//! Payloads larger than `WASMIOT_COAP_BLOCK_SIZE` are transferred block-wise (RFC 7959), in
+//! blocks of that size or of the smaller size the client asks for. A response is kept for
+//! `TRANSFER_LIFETIME` for the client to fetch the rest of its blocks. Block options longer than
+//! 3 bytes or asking for a block past the end of the response are refused with 4.02 Bad Option.
+//!
+//! Executions can take longer than clients wait for an acknowledgement, so a confirmable request
+//! to run a function is acknowledged right away and answered in a separate confirmable message,
+//! which is retransmitted until the client acknowledges it. Retransmitted requests are answered
+//! again without running the function again.
+//!
+//! This is a minimal implementation of RFC 7252 over plain UDP, without DTLS or observing.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use actix_web::http::StatusCode;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use crate::function_name;
+use crate::lib::api::{deployed_functions, execute_function, execution_response};
+use crate::lib::constants::{get_coap_block_size, get_coap_port, get_max_file_bytes};
+use crate::lib::logging::send_log;
+
+/// Message types.
+pub const CONFIRMABLE: u8 = 0;
+pub const NON_CONFIRMABLE: u8 = 1;
+pub const ACKNOWLEDGEMENT: u8 = 2;
+pub const RESET: u8 = 3;
+
+/// Method and response codes, as `class << 5 | detail`.
+pub const GET: u8 = 0x01;
+pub const POST: u8 = 0x02;
+pub const CONTENT: u8 = 0x45;
+pub const CONTINUE: u8 = 0x5f;
+pub const BAD_REQUEST: u8 = 0x80;
+pub const BAD_OPTION: u8 = 0x82;
+pub const FORBIDDEN: u8 = 0x83;
+pub const NOT_FOUND: u8 = 0x84;
+pub const METHOD_NOT_ALLOWED: u8 = 0x85;
+pub const NOT_ACCEPTABLE: u8 = 0x86;
+pub const REQUEST_ENTITY_INCOMPLETE: u8 = 0x88;
+pub const REQUEST_ENTITY_TOO_LARGE: u8 = 0x8d;
+pub const INTERNAL_SERVER_ERROR: u8 = 0xa0;
+pub const SERVICE_UNAVAILABLE: u8 = 0xa3;
+
+/// Option numbers.
+pub const URI_PATH: u16 = 11;
+pub const CONTENT_FORMAT: u16 = 12;
+pub const URI_QUERY: u16 = 15;
+pub const ACCEPT: u16 = 17;
+pub const BLOCK2: u16 = 23;
+pub const BLOCK1: u16 = 27;
+pub const SIZE2: u16 = 28;
+
+/// Content formats.
+pub const LINK_FORMAT: u16 = 40;
+pub const JSON_FORMAT: u16 = 50;
+pub const CBOR_FORMAT: u16 = 60;
+
+/// Time to wait for the acknowledgement of a confirmable message before sending it again, doubled
+/// on every retransmission.
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Times a confirmable message is retransmitted.
+const MAX_RETRANSMIT: u32 = 4;
+
+/// Time a confirmable request may be retransmitted in, so that its response is kept to answer
+/// the retransmissions (`EXCHANGE_LIFETIME` of RFC 7252).
+const EXCHANGE_LIFETIME: Duration = Duration::from_secs(247);
+
+/// Time the parts of a block-wise transfer are kept for the client to continue it.
+const TRANSFER_LIFETIME: Duration = Duration::from_secs(60);
+
+/// A CoAP message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub kind: u8,
+    pub code: u8,
+    pub message_id: u16,
+    pub token: Vec<u8>,
+    /// Options by number, in the order they appear in the message.
+    pub options: Vec<(u16, Vec<u8>)>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// A message without options or payload.
+    pub fn new(kind: u8, code: u8, message_id: u16, token: &[u8]) -> Self {
+        Message { kind, code, message_id, token: token.to_vec(), options: Vec::new(), payload: Vec::new() }
+    }
+
+    /// Parses a message from a datagram.
+    pub fn parse(datagram: &[u8]) -> Result<Self, String> {
+        let [first, code, id_high, id_low, rest @ ..] = datagram else {
+            return Err("Message is shorter than its header".to_string());
+        };
+        if first >> 6 != 1 {
+            return Err(format!("Unknown CoAP version {}", first >> 6));
+        }
+        let token_len = usize::from(first & 0x0f);
+        if token_len > 8 || rest.len() < token_len {
+            return Err("Invalid token length".to_string());
+        }
+        let (token, mut rest) = rest.split_at(token_len);
+
+        let mut options = Vec::new();
+        let mut number: u16 = 0;
+        let mut payload = Vec::new();
+        while let [byte, tail @ ..] = rest {
+            if *byte == 0xff {
+                if tail.is_empty() {
+                    return Err("Payload marker without a payload".to_string());
+                }
+                payload = tail.to_vec();
+                break;
+            }
+            let (delta, tail) = option_nibble(byte >> 4, tail)?;
+            let (len, tail) = option_nibble(byte & 0x0f, tail)?;
+            if tail.len() < len {
+                return Err("Option is longer than the message".to_string());
+            }
+            number = u16::try_from(delta).ok().and_then(|delta| number.checked_add(delta)).ok_or("Invalid option number")?;
+            options.push((number, tail[..len].to_vec()));
+            rest = &tail[len..];
+        }
+        Ok(Message {
+            kind: (first >> 4) & 0x03,
+            code: *code,
+            message_id: u16::from_be_bytes([*id_high, *id_low]),
+            token: token.to_vec(),
+            options,
+            payload,
+        })
+    }
+
+    /// Encodes the message into a datagram.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut datagram = vec![0x40 | (self.kind << 4) | self.token.len() as u8, self.code];
+        datagram.extend_from_slice(&self.message_id.to_be_bytes());
+        datagram.extend_from_slice(&self.token);
+        let mut options = self.options.clone();
+        options.sort_by_key(|(number, _)| *number);
+        let mut previous = 0;
+        for (number, value) in options {
+            let (delta, delta_ext) = option_header(usize::from(number - previous));
+            let (len, len_ext) = option_header(value.len());
+            datagram.push(delta << 4 | len);
+            datagram.extend_from_slice(&delta_ext);
+            datagram.extend_from_slice(&len_ext);
+            datagram.extend_from_slice(&value);
+            previous = number;
+        }
+        if !self.payload.is_empty() {
+            datagram.push(0xff);
+            datagram.extend_from_slice(&self.payload);
+        }
+        datagram
+    }
+
+    /// Values of an option, in order.
+    pub fn option_values(&self, number: u16) -> impl Iterator<Item = &[u8]> {
+        self.options.iter().filter(move |(n, _)| *n == number).map(|(_, value)| value.as_slice())
+    }
+
+    /// Value of an unsigned integer option.
+    pub fn uint_option(&self, number: u16) -> Option<u32> {
+        let value = self.option_values(number).next()?;
+        (value.len() <= 4).then(|| value.iter().fold(0, |n, byte| n << 8 | u32::from(*byte)))
+    }
+
+    /// Value of a Block1 or Block2 option.
+    ///
+    /// # Returns
+    /// The block, `None` if the message has no such option, or an error if its value is longer
+    /// than the 3 bytes of a block or has the reserved size exponent 7.
+    pub fn block_option(&self, number: u16) -> Result<Option<Block>, String> {
+        let Some(value) = self.option_values(number).next() else {
+            return Ok(None);
+        };
+        if value.len() > 3 {
+            return Err("Block option is longer than 3 bytes".to_string());
+        }
+        let value = value.iter().fold(0, |n, byte| n << 8 | u32::from(*byte));
+        Block::decode(value).map(Some).ok_or_else(|| "Unsupported block size".to_string())
+    }
+
+    /// Adds an unsigned integer option.
+    pub fn add_uint_option(&mut self, number: u16, value: u32) {
+        self.options.push((number, encode_uint(value)));
+    }
+
+    /// The Uri-Path and Uri-Query options of a request, as a relative URI.
+    fn uri(&self) -> String {
+        let path: Vec<String> = self.option_values(URI_PATH).map(|s| String::from_utf8_lossy(s).to_string()).collect();
+        let query: Vec<String> = self.option_values(URI_QUERY).map(|s| String::from_utf8_lossy(s).to_string()).collect();
+        if query.is_empty() {
+            format!("/{}", path.join("/"))
+        } else {
+            format!("/{}?{}", path.join("/"), query.join("&"))
+        }
+    }
+}
+
+/// Encodes the value of an unsigned integer option in the fewest bytes.
+pub fn encode_uint(value: u32) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+    bytes[skip..].to_vec()
+}
+
+/// Reads the extended option delta or length that a nibble of 13 or 14 announces.
+fn option_nibble(nibble: u8, bytes: &[u8]) -> Result<(usize, &[u8]), String> {
+    match (nibble, bytes) {
+        (0..=12, _) => Ok((usize::from(nibble), bytes)),
+        (13, [ext, rest @ ..]) => Ok((usize::from(*ext) + 13, rest)),
+        (14, [high, low, rest @ ..]) => Ok((usize::from(u16::from_be_bytes([*high, *low])) + 269, rest)),
+        _ => Err("Invalid option header".to_string()),
+    }
+}
+
+/// The nibble and extended bytes of an option delta or length.
+fn option_header(value: usize) -> (u8, Vec<u8>) {
+    match value {
+        0..=12 => (value as u8, Vec::new()),
+        13..=268 => (13, vec![(value - 13) as u8]),
+        _ => (14, ((value - 269) as u16).to_be_bytes().to_vec()),
+    }
+}
+
+/// The value of a Block1 or Block2 option.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Block {
+    pub num: u32,
+    pub more: bool,
+    pub size: usize,
+}
+
+impl Block {
+    /// Decodes an option value, `None` for the reserved size exponent 7.
+    pub fn decode(value: u32) -> Option<Block> {
+        let szx = value & 0x07;
+        (szx < 7).then(|| Block { num: value >> 4, more: value & 0x08 != 0, size: 16 << szx })
+    }
+
+    pub fn encode(&self) -> u32 {
+        let szx = self.size.trailing_zeros().saturating_sub(4).min(6);
+        self.num << 4 | u32::from(self.more) << 3 | szx
+    }
+}
+
+/// Responses to recent confirmable requests by client and message ID, `None` while the request
+/// is handled or when it was answered separately.
+type Exchanges = HashMap<(SocketAddr, u16), (Instant, Option<Vec<u8>>)>;
+
+/// Payloads of requests being received block-wise, by client and URI.
+type Uploads = HashMap<(SocketAddr, String), (Instant, Vec<u8>)>;
+
+/// The code, content format, body and other options of a response.
+type Reply = (u8, u16, Vec<u8>, Vec<(u16, Vec<u8>)>);
+
+/// A response being transferred block-wise.
+struct Transfer {
+    started: Instant,
+    code: u8,
+    content_format: u16,
+    body: Vec<u8>,
+}
+
+/// State of the server shared by the tasks handling requests.
+struct Server {
+    socket: UdpSocket,
+    next_message_id: AtomicU16,
+    exchanges: Mutex<Exchanges>,
+    uploads: Mutex<Uploads>,
+    /// Responses being sent block-wise, by client and URI.
+    transfers: Mutex<HashMap<(SocketAddr, String), Transfer>>,
+    /// Separate responses waiting for acknowledgement, by client and message ID.
+    unacknowledged: Mutex<HashMap<(SocketAddr, u16), oneshot::Sender<()>>>,
+}
+
+/// Serves CoAP on `WASMIOT_COAP_PORT` of `bind_address`. Returns right away when no port is set.
+pub async fn run_coap_server(bind_address: IpAddr) {
+    let Some(port) = get_coap_port() else {
+        return;
+    };
+    let func_name = function_name!().to_string();
+    let socket = match UdpSocket::bind((bind_address, port)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            send_log("ERROR", &format!("Failed to listen for CoAP on {}:{}: {}", bind_address, port, e), &func_name, None).await;
+            return;
+        }
+    };
+    send_log("INFO", &format!("Serving CoAP at coap://{}:{}/", bind_address, port), &func_name, None).await;
+
+    // Start from an unpredictable message ID, as clients remember the recent ones
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+    let server = Arc::new(Server {
+        socket,
+        next_message_id: AtomicU16::new(seed as u16),
+        exchanges: Mutex::new(HashMap::new()),
+        uploads: Mutex::new(HashMap::new()),
+        transfers: Mutex::new(HashMap::new()),
+        unacknowledged: Mutex::new(HashMap::new()),
+    });
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let (len, peer) = match server.socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                log::warn!("Failed to receive a CoAP message: {}", e);
+                continue;
+            }
+        };
+        let Ok(message) = Message::parse(&buffer[..len]) else {
+            log::debug!("Ignoring a malformed CoAP message from {}", peer);
+            continue;
+        };
+        match (message.kind, message.code) {
+            (ACKNOWLEDGEMENT | RESET, _) => {
+                if let Some(acknowledged) = server.unacknowledged.lock().remove(&(peer, message.message_id)) {
+                    acknowledged.send(()).ok();
+                }
+            }
+            // An empty confirmable message is a ping, answered with a reset
+            (CONFIRMABLE, 0) => {
+                server.send(peer, &Message::new(RESET, 0, message.message_id, &[])).await;
+            }
+            (CONFIRMABLE | NON_CONFIRMABLE, 1..=31) => {
+                tokio::spawn(server.clone().handle_request(peer, message));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Server {
+    async fn send(&self, peer: SocketAddr, message: &Message) {
+        if let Err(e) = self.socket.send_to(&message.encode(), peer).await {
+            log::warn!("Failed to send a CoAP message to {}: {}", peer, e);
+        }
+    }
+
+    fn next_message_id(&self) -> u16 {
+        self.next_message_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Answers a request, running a function if it is for one.
+    async fn handle_request(self: Arc<Self>, peer: SocketAddr, request: Message) {
+        let confirmable = request.kind == CONFIRMABLE;
+        if confirmable {
+            let now = Instant::now();
+            let retransmitted = {
+                let mut exchanges = self.exchanges.lock();
+                exchanges.retain(|_, (received, _)| now.duration_since(*received) < EXCHANGE_LIFETIME);
+                match exchanges.entry((peer, request.message_id)) {
+                    Entry::Occupied(exchange) => Some(exchange.get().1.clone()),
+                    Entry::Vacant(exchange) => {
+                        exchange.insert((now, None));
+                        None
+                    }
+                }
+            };
+            // A retransmission is answered like the original, or just acknowledged again
+            match retransmitted {
+                Some(Some(datagram)) => {
+                    self.socket.send_to(&datagram, peer).await.ok();
+                    return;
+                }
+                Some(None) => {
+                    self.send(peer, &Message::new(ACKNOWLEDGEMENT, 0, request.message_id, &[])).await;
+                    return;
+                }
+                None => {}
+            }
+        }
+
+        let uri = request.uri();
+        match self.respond(peer, &request, &uri).await {
+            Some((code, content_format, body, options)) => {
+                // Answered right away, piggybacked on the acknowledgement of a confirmable request
+                let mut response = if confirmable {
+                    Message::new(ACKNOWLEDGEMENT, code, request.message_id, &request.token)
+                } else {
+                    Message::new(NON_CONFIRMABLE, code, self.next_message_id(), &request.token)
+                };
+                response.options = options;
+                self.add_body(peer, &request, &uri, &mut response, content_format, body);
+                if confirmable && let Some(exchange) = self.exchanges.lock().get_mut(&(peer, request.message_id)) {
+                    exchange.1 = Some(response.encode());
+                }
+                self.send(peer, &response).await;
+            }
+            None => {
+                // The function has to run, so acknowledge the request before running it
+                if confirmable {
+                    self.send(peer, &Message::new(ACKNOWLEDGEMENT, 0, request.message_id, &[])).await;
+                }
+                let (code, content_format, body, options) = self.execute(peer, &request, &uri).await;
+                let kind = if confirmable { CONFIRMABLE } else { NON_CONFIRMABLE };
+                let mut response = Message::new(kind, code, self.next_message_id(), &request.token);
+                response.options = options;
+                self.add_body(peer, &request, &uri, &mut response, content_format, body);
+                if confirmable {
+                    self.send_confirmable(peer, response).await;
+                } else {
+                    self.send(peer, &response).await;
+                }
+            }
+        }
+    }
+
+    /// Builds the response to a request that needs no function to run.
+    ///
+    /// # Returns
+    /// The response, or `None` when the request is to run a function.
+    async fn respond(&self, peer: SocketAddr, request: &Message, uri: &str) -> Option<Reply> {
+        let error = |code: u8, message: &str| Some((code, JSON_FORMAT, json!({ "error": message }).to_string().into_bytes(), Vec::new()));
+
+        let (block1, block2) = match (request.block_option(BLOCK1), request.block_option(BLOCK2)) {
+            (Ok(block1), Ok(block2)) => (block1, block2),
+            (Err(e), _) | (_, Err(e)) => return error(BAD_OPTION, &e),
+        };
+
+        // The rest of the blocks of a response
+        if let Some(block) = block2
+            && block.num > 0
+        {
+            let transfers = self.transfers.lock();
+            let size = block.size.min(get_coap_block_size());
+            return match transfers.get(&(peer, uri.to_string())) {
+                Some(transfer) if block.num as usize * size >= transfer.body.len() => {
+                    error(BAD_OPTION, "Block is past the end of the response")
+                }
+                Some(transfer) => Some((transfer.code, transfer.content_format, transfer.body.clone(), Vec::new())),
+                None => error(REQUEST_ENTITY_INCOMPLETE, "No response being transferred for the block"),
+            };
+        }
+
+        // The blocks of a request, collected until the last one
+        if let Some(block) = block1 {
+            let now = Instant::now();
+            let mut uploads = self.uploads.lock();
+            uploads.retain(|_, (started, _)| now.duration_since(*started) < TRANSFER_LIFETIME);
+            let key = (peer, uri.to_string());
+            if block.num == 0 {
+                uploads.insert(key.clone(), (now, Vec::new()));
+            }
+            let Some((_, payload)) = uploads.get_mut(&key) else {
+                return error(REQUEST_ENTITY_INCOMPLETE, "Block received before the first block");
+            };
+            if payload.len() != block.num as usize * block.size {
+                uploads.remove(&key);
+                return error(REQUEST_ENTITY_INCOMPLETE, "Block received out of order");
+            }
+            payload.extend_from_slice(&request.payload);
+            if payload.len() as u64 > get_max_file_bytes() {
+                uploads.remove(&key);
+                return error(REQUEST_ENTITY_TOO_LARGE, "Payload is larger than the largest allowed file");
+            }
+            if block.more {
+                return Some((CONTINUE, JSON_FORMAT, Vec::new(), vec![(BLOCK1, encode_uint(block.encode()))]));
+            }
+        }
+
+        let path: Vec<&[u8]> = request.option_values(URI_PATH).collect();
+        match (path.as_slice(), request.code) {
+            ([b".well-known", b"core"], GET) => {
+                let links: Vec<String> = deployed_functions().await.into_iter()
+                    .map(|(deployment_id, module_name, function_name)| format!(
+                        "</{}/{}/{}>;rt=\"wasmiot.function\";ct=\"{} {}\"",
+                        deployment_id, module_name, function_name, JSON_FORMAT, CBOR_FORMAT
+                    ))
+                    .collect();
+                Some((CONTENT, LINK_FORMAT, links.join(",").into_bytes(), Vec::new()))
+            }
+            ([b".well-known", b"core"], _) => error(METHOD_NOT_ALLOWED, "Only GET is allowed"),
+            ([_, _, _], GET | POST) => None,
+            ([_, _, _], _) => error(METHOD_NOT_ALLOWED, "Only GET and POST are allowed"),
+            _ => error(NOT_FOUND, "Not found"),
+        }
+    }
+
+    /// Runs the function a request is for.
+    async fn execute(&self, peer: SocketAddr, request: &Message, uri: &str) -> Reply {
+        let options: Vec<(u16, Vec<u8>)> = request.block_option(BLOCK1).ok().flatten()
+            .map(|block| (BLOCK1, encode_uint(block.encode())))
+            .into_iter()
+            .collect();
+        let content_format = match request.uint_option(ACCEPT) {
+            None => JSON_FORMAT,
+            Some(format) if format == u32::from(JSON_FORMAT) || format == u32::from(CBOR_FORMAT) => format as u16,
+            Some(_) => {
+                let body = json!({ "error": "Only application/json and application/cbor are supported" });
+                return (NOT_ACCEPTABLE, JSON_FORMAT, body.to_string().into_bytes(), options);
+            }
+        };
+
+        let path: Vec<String> = request.option_values(URI_PATH).map(|s| String::from_utf8_lossy(s).to_string()).collect();
+        let [deployment_id, module_name, function_name] = path.as_slice() else {
+            return (NOT_FOUND, JSON_FORMAT, json!({ "error": "Not found" }).to_string().into_bytes(), options);
+        };
+        let request_args: serde_json::Map<String, Value> = request.option_values(URI_QUERY)
+            .map(|query| {
+                let query = String::from_utf8_lossy(query);
+                match query.split_once('=') {
+                    Some((key, value)) => (key.to_string(), json!(value)),
+                    None => (query.to_string(), json!("")),
+                }
+            })
+            .collect();
+        let input = if request.uint_option(BLOCK1).is_some() {
+            self.uploads.lock().remove(&(peer, uri.to_string())).map(|(_, payload)| payload)
+        } else {
+            Some(request.payload.clone())
+        };
+        let input = input.filter(|payload| request.code == POST && !payload.is_empty());
+
+        let (code, response) = match execute_function(deployment_id, module_name, function_name, "COAP", Value::Object(request_args), input).await {
+            Ok((entry, final_opt)) => (CONTENT, execution_response(&entry, final_opt)),
+            Err((status, e)) => {
+                let func_name = function_name!().to_string();
+                send_log("WARN", &format!("Not executing {} from CoAP: {}", uri, e), &func_name, None).await;
+                (response_code(status), json!({ "error": e }))
+            }
+        };
+        let body = if content_format == CBOR_FORMAT {
+            let mut body = Vec::new();
+            match ciborium::into_writer(&response, &mut body) {
+                Ok(()) => body,
+                Err(e) => {
+                    let body = json!({ "error": format!("Failed to encode the response as CBOR: {}", e) });
+                    return (INTERNAL_SERVER_ERROR, JSON_FORMAT, body.to_string().into_bytes(), options);
+                }
+            }
+        } else {
+            response.to_string().into_bytes()
+        };
+        (code, content_format, body, options)
+    }
+
+    /// Sets the body of a response, or the block of it the client asked for when it does not
+    /// fit in one block. The whole body is kept for the client to fetch the rest of the blocks.
+    fn add_body(&self, peer: SocketAddr, request: &Message, uri: &str, response: &mut Message, content_format: u16, body: Vec<u8>) {
+        if response.code == CONTINUE {
+            return;
+        }
+        response.add_uint_option(CONTENT_FORMAT, u32::from(content_format));
+        let requested = request.block_option(BLOCK2).ok().flatten();
+        let size = requested.map_or(get_coap_block_size(), |block| block.size.min(get_coap_block_size()));
+        // Errors that fit in the largest block, like refusals of the requested block, are sent
+        // whole and leave the transfer of the response alone
+        let is_small_error = response.code >= BAD_REQUEST && body.len() <= get_coap_block_size();
+        if (requested.is_none() && body.len() <= size) || is_small_error {
+            response.payload = body;
+            return;
+        }
+
+        let num = requested.map_or(0, |block| block.num);
+        let start = (num as usize * size).min(body.len());
+        let end = (start + size).min(body.len());
+        let more = end < body.len();
+        response.add_uint_option(BLOCK2, Block { num, more, size }.encode());
+        if num == 0 {
+            response.add_uint_option(SIZE2, body.len() as u32);
+        }
+        response.payload = body[start..end].to_vec();
+
+        let now = Instant::now();
+        let mut transfers = self.transfers.lock();
+        transfers.retain(|_, transfer| now.duration_since(transfer.started) < TRANSFER_LIFETIME);
+        let key = (peer, uri.to_string());
+        if !more {
+            transfers.remove(&key);
+        } else if num == 0 {
+            transfers.insert(key, Transfer { started: now, code: response.code, content_format, body });
+        }
+    }
+
+    /// Sends a confirmable message until the client acknowledges it.
+    async fn send_confirmable(&self, peer: SocketAddr, message: Message) {
+        let (acknowledged, mut acknowledgement) = oneshot::channel();
+        self.unacknowledged.lock().insert((peer, message.message_id), acknowledged);
+        let mut timeout = ACK_TIMEOUT;
+        for _ in 0..=MAX_RETRANSMIT {
+            self.send(peer, &message).await;
+            if tokio::time::timeout(timeout, &mut acknowledgement).await.is_ok() {
+                return;
+            }
+            timeout *= 2;
+        }
+        self.unacknowledged.lock().remove(&(peer, message.message_id));
+        log::warn!("CoAP client {} did not acknowledge the response {}", peer, message.message_id);
+    }
+}
+
+/// The CoAP response code closest to an HTTP status.
+fn response_code(status: StatusCode) -> u8 {
+    match status {
+        StatusCode::BAD_REQUEST => BAD_REQUEST,
+        StatusCode::FORBIDDEN => FORBIDDEN,
+        StatusCode::NOT_FOUND => NOT_FOUND,
+        StatusCode::LOCKED | StatusCode::SERVICE_UNAVAILABLE => SERVICE_UNAVAILABLE,
+        _ => INTERNAL_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,463 @@
+//! # config_file.rs
+//!
+//! TOML configuration file of the supervisor.
+//!
+//! The file is read from the path given with `--config-file`, or from `supervisor.toml` in the
+//! instance folder if it exists. Every setting in it corresponds to an environment variable, and
+//! is only used when that variable is not set, so the order of precedence is: command line,
+//! environment, `.env`, configuration file, default. For example:
+//!
+//! ```toml
+//! [supervisor]
+//! name = "camera-1"
+//!
+//! [network]
+//! port = 3005
+//!
+//! [orchestrator]
+//! url = "http://orchestrator.local:3000"
+//!
+//! [limits]
+//! max_file_bytes = 10_000_000
+//! ```
+//!
+//! Unknown keys are warned about and ignored, while values of the wrong type make the file
+//! invalid. The merged settings are printed with `--print-config` and returned by `GET /config`.
+//! The file can be read again while the supervisor runs, see `reload.rs`. Where the value of
+//! each setting came from is returned by `setting_sources`. A name given to the supervisor
+//! through `PATCH /config` is saved to the file with `save_setting`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::lib::camera::parse_camera_enabled;
+use crate::lib::cli::{parse_http_url, parse_log_filter, parse_name};
+use crate::lib::settings::{get_setting, remove_setting, set_setting, setting_source, SettingSource};
+use crate::structs::supervisor_config::validate_public_base_url;
+
+/// Conversion of a setting to and from the value of its environment variable.
+trait SettingValue: Sized {
+    fn to_env(&self) -> String;
+    fn from_env(value: &str) -> Option<Self>;
+}
+
+impl SettingValue for String {
+    fn to_env(&self) -> String {
+        self.clone()
+    }
+    fn from_env(value: &str) -> Option<Self> {
+        Some(value.to_string()).filter(|v| !v.is_empty())
+    }
+}
+
+impl SettingValue for bool {
+    fn to_env(&self) -> String {
+        self.to_string()
+    }
+    fn from_env(value: &str) -> Option<Self> {
+        Some(value == "true")
+    }
+}
+
+/// Lists are kept comma separated in the environment.
+impl SettingValue for Vec<String> {
+    fn to_env(&self) -> String {
+        self.join(",")
+    }
+    fn from_env(value: &str) -> Option<Self> {
+        Some(value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect())
+    }
+}
+
+macro_rules! number_setting {
+    ($($ty:ty),*) => {
+        $(
+            impl SettingValue for $ty {
+                fn to_env(&self) -> String {
+                    self.to_string()
+                }
+                fn from_env(value: &str) -> Option<Self> {
+                    value.parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+number_setting!(u16, u32, u64, i64, usize, f32, f64);
+
+/// Defines the sections of the configuration file, with the environment variable of each setting.
+macro_rules! config_sections {
+    ($(
+        $(#[doc = $section_doc:literal])*
+        $section:ident: $Section:ident {
+            $( $field:ident: $ty:ty = $env:literal, )*
+        }
+    )*) => {
+        $(
+            $(#[doc = $section_doc])*
+            #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+            #[serde(default)]
+            pub struct $Section {
+                $(
+                    #[doc = concat!("`", $env, "`")]
+                    #[serde(skip_serializing_if = "Option::is_none")]
+                    pub $field: Option<$ty>,
+                )*
+            }
+        )*
+
+        /// Settings of the supervisor as written in the configuration file.
+        #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+        #[serde(default)]
+        pub struct ConfigFile {
+            $( pub $section: $Section, )*
+        }
+
+        impl ConfigFile {
+            /// Environment variables of all the settings.
+            pub const ENV_NAMES: &'static [&'static str] = &[$($($env,)*)*];
+
+            /// Dotted key and environment variable of every setting.
+            pub const KEYS: &'static [(&'static str, &'static str)] = &[
+                $($( (concat!(stringify!($section), ".", stringify!($field)), $env), )*)*
+            ];
+
+            /// Returns the environment variable and its value for every setting that is given.
+            pub fn env_values(&self) -> Vec<(&'static str, String)> {
+                let mut values = Vec::new();
+                $($(
+                    if let Some(value) = &self.$section.$field {
+                        values.push(($env, value.to_env()));
+                    }
+                )*)*
+                values
+            }
+
+            /// Returns the environment variable of the setting with the dotted key `key`.
+            pub fn setting_env(key: &str) -> Option<&'static str> {
+                $($(
+                    if key == concat!(stringify!($section), ".", stringify!($field)) {
+                        return Some($env);
+                    }
+                )*)*
+                None
+            }
+
+            /// Returns the dotted key and environment variable of every setting that differs
+            /// between `self` and `other`.
+            pub fn changed_settings(&self, other: &Self) -> Vec<(&'static str, &'static str)> {
+                let mut changed = Vec::new();
+                $($(
+                    if self.$section.$field != other.$section.$field {
+                        changed.push((concat!(stringify!($section), ".", stringify!($field)), $env));
+                    }
+                )*)*
+                changed
+            }
+
+            /// Reads every setting from the settings of the supervisor. Settings that are not
+            /// set, or not valid, are left out, as the supervisor uses the default for them.
+            pub fn from_settings() -> Self {
+                ConfigFile {
+                    $(
+                        $section: $Section {
+                            $( $field: get_setting($env).and_then(|v| SettingValue::from_env(&v)), )*
+                        },
+                    )*
+                }
+            }
+        }
+    };
+}
+
+config_sections! {
+    /// Identity of the supervisor
+    supervisor: SupervisorSection {
+        name: String = "SUPERVISOR_NAME",
+    }
+    /// How the supervisor is reached
+    network: NetworkSection {
+        port: u16 = "WASMIOT_SUPERVISOR_PORT",
+        bind_address: String = "WASMIOT_BIND_ADDRESS",
+        advertise_address: String = "WASMIOT_ADVERTISE_ADDRESS",
+        public_base_url: String = "WASMIOT_PUBLIC_BASE_URL",
+    }
+    /// Orchestrator the supervisor registers to
+    orchestrator: OrchestratorSection {
+        url: String = "WASMIOT_ORCHESTRATOR_URL",
+        register_renewal_seconds: i64 = "WASMIOT_REGISTER_RENEWAL_TIME",
+    }
+    /// Local and external logging
+    logging: LoggingSection {
+        level: String = "RUST_LOG",
+        external: bool = "EXTERNAL_LOGGING_ENABLED",
+        endpoint: String = "WASMIOT_LOGGING_ENDPOINT",
+    }
+    /// Timeouts and intervals, in seconds
+    timeouts: TimeoutsSection {
+        module_seconds: u64 = "WASMIOT_MODULE_TIMEOUT_SECONDS",
+        deployment_download_seconds: u64 = "WASMIOT_DEPLOYMENT_DOWNLOAD_TIMEOUT_SECONDS",
+        description_max_age_seconds: u64 = "WASMIOT_DESCRIPTION_MAX_AGE_SECONDS",
+        expiry_check_interval_seconds: u64 = "WASMIOT_EXPIRY_CHECK_INTERVAL_SECONDS",
+        expired_deployment_grace_seconds: u64 = "WASMIOT_EXPIRED_DEPLOYMENT_GRACE_SECONDS",
+        gc_grace_seconds: u64 = "WASMIOT_GC_GRACE_SECONDS",
+        result_cleanup_interval_seconds: u64 = "WASMIOT_RESULT_CLEANUP_INTERVAL_SECONDS",
+        request_history_retention_seconds: u64 = "WASMIOT_REQUEST_HISTORY_RETENTION_SECONDS",
+        result_url_ttl_seconds: u64 = "WASMIOT_RESULT_URL_TTL_SECONDS",
+        drain_seconds: u64 = "WASMIOT_DRAIN_TIMEOUT_SECS",
+        callback_seconds: u64 = "WASMIOT_CALLBACK_TIMEOUT_SECONDS",
+        healthcheck_seconds: u64 = "WASMIOT_HEALTHCHECK_TIMEOUT_SECONDS",
+        replica_sync_interval_seconds: u64 = "WASMIOT_REPLICA_SYNC_INTERVAL_SECONDS",
+    }
+    /// Limits on downloads, disk use and results
+    limits: LimitsSection {
+        max_file_bytes: u64 = "WASMIOT_MAX_FILE_BYTES",
+        max_deployment_bytes: u64 = "WASMIOT_MAX_DEPLOYMENT_BYTES",
+        disk_reserve_bytes: u64 = "WASMIOT_DISK_RESERVE_BYTES",
+        download_concurrency: usize = "WASMIOT_DOWNLOAD_CONCURRENCY",
+        download_retries: u32 = "WASMIOT_DOWNLOAD_RETRIES",
+        callback_retries: u32 = "WASMIOT_CALLBACK_RETRIES",
+        result_max_age_seconds: u64 = "WASMIOT_RESULT_MAX_AGE",
+        result_max_bytes: u64 = "WASMIOT_RESULT_MAX_BYTES",
+        result_max_deployment_bytes: u64 = "WASMIOT_RESULT_MAX_DEPLOYMENT_BYTES",
+        inline_result_max_bytes: u64 = "WASMIOT_INLINE_RESULT_MAX_BYTES",
+        chain_mirror_max_bytes: u64 = "WASMIOT_CHAIN_MIRROR_MAX_BYTES",
+        request_history_max_entries: usize = "WASMIOT_REQUEST_HISTORY_MAX_ENTRIES",
+        http_workers: usize = "WASMIOT_HTTP_WORKERS",
+        execution_threads: usize = "WASMIOT_EXECUTION_THREADS",
+        shed_low_priority: bool = "WASMIOT_SHED_LOW_PRIORITY",
+        execution_queue_max_depth: usize = "WASMIOT_EXECUTION_QUEUE_MAX_DEPTH",
+        max_deployments: usize = "WASMIOT_MAX_DEPLOYMENTS",
+        max_modules: usize = "WASMIOT_MAX_MODULES",
+        max_artifact_bytes: u64 = "WASMIOT_MAX_ARTIFACT_BYTES",
+        endpoint_examples: usize = "WASMIOT_ENDPOINT_EXAMPLES",
+    }
+    /// Camera used by modules
+    camera: CameraSection {
+        device: u32 = "DEFAULT_CAMERA_DEVICE",
+        enabled: String = "WASMIOT_CAMERA_ENABLED",
+    }
+    /// Features that can be turned on or off
+    capabilities: CapabilitiesSection {
+        apply_preloaded_deployments: bool = "WASMIOT_APPLY_PRELOADED_DEPLOYMENTS",
+        require_signed_deployments: bool = "WASMIOT_REQUIRE_SIGNED_DEPLOYMENTS",
+        trusted_public_keys: Vec<String> = "WASMIOT_TRUSTED_PUBLIC_KEYS",
+        sign_result_urls: bool = "WASMIOT_SIGN_RESULT_URLS",
+        local_artifact_dir: String = "WASMIOT_LOCAL_ARTIFACT_DIR",
+        dev_mode: bool = "WASMIOT_DEV_MODE",
+        force_pulley: bool = "WASMIOT_FORCE_PULLEY",
+    }
+    /// Health sampling and the initial health thresholds
+    health: HealthSection {
+        sample_interval_seconds: u64 = "WASMIOT_HEALTH_SAMPLE_INTERVAL_SECONDS",
+        refresh_interval_ms: u64 = "WASMIOT_HEALTH_REFRESH_INTERVAL_MS",
+        history_size: usize = "WASMIOT_HEALTH_HISTORY_SIZE",
+        cpu_degraded: f32 = "WASMIOT_HEALTH_CPU_DEGRADED",
+        cpu_critical: f32 = "WASMIOT_HEALTH_CPU_CRITICAL",
+        memory_degraded: f32 = "WASMIOT_HEALTH_MEMORY_DEGRADED",
+        memory_critical: f32 = "WASMIOT_HEALTH_MEMORY_CRITICAL",
+        disk_degraded: f32 = "WASMIOT_HEALTH_DISK_DEGRADED",
+        disk_critical: f32 = "WASMIOT_HEALTH_DISK_CRITICAL",
+        temperature_degraded: f32 = "WASMIOT_HEALTH_TEMPERATURE_DEGRADED",
+        temperature_critical: f32 = "WASMIOT_HEALTH_TEMPERATURE_CRITICAL",
+        log_queue_degraded: f32 = "WASMIOT_HEALTH_LOG_QUEUE_DEGRADED",
+        log_queue_critical: f32 = "WASMIOT_HEALTH_LOG_QUEUE_CRITICAL",
+    }
+    /// MQTT broker executions are taken from and results published to
+    mqtt: MqttSection {
+        broker: String = "WASMIOT_MQTT_BROKER",
+        username: String = "WASMIOT_MQTT_USERNAME",
+        topic_prefix: String = "WASMIOT_MQTT_TOPIC_PREFIX",
+        keep_alive_seconds: u16 = "WASMIOT_MQTT_KEEP_ALIVE_SECONDS",
+    }
+    /// CoAP server for constrained clients
+    coap: CoapSection {
+        port: u16 = "WASMIOT_COAP_PORT",
+        block_size: usize = "WASMIOT_COAP_BLOCK_SIZE",
+    }
+    /// Traces of executions, exported when built with the `otel` feature
+    telemetry: TelemetrySection {
+        endpoint: String = "WASMIOT_OTEL_ENDPOINT",
+        sampling_ratio: f64 = "WASMIOT_OTEL_SAMPLING_RATIO",
+    }
+    /// S3-compatible storage the outputs of deployments with a result sink are uploaded to
+    s3: S3Section {
+        access_key_id: String = "WASMIOT_S3_ACCESS_KEY_ID",
+        region: String = "WASMIOT_S3_REGION",
+        upload_retries: u32 = "WASMIOT_S3_UPLOAD_RETRIES",
+    }
+    /// Unix domain socket the HTTP server also listens on, for agents on the same host
+    unix_socket: UnixSocketSection {
+        path: String = "WASMIOT_UNIX_SOCKET",
+        mode: String = "WASMIOT_UNIX_SOCKET_MODE",
+        trusted: bool = "WASMIOT_UNIX_SOCKET_TRUSTED",
+    }
+    /// Telegraf HTTP listener the metrics are pushed to
+    telegraf: TelegrafSection {
+        url: String = "WASMIOT_TELEGRAF_URL",
+        interval_seconds: u64 = "WASMIOT_TELEGRAF_INTERVAL_SECONDS",
+    }
+    /// gRPC server next to the HTTP server, when built with the `grpc` feature
+    grpc: GrpcSection {
+        port: u16 = "WASMIOT_GRPC_PORT",
+    }
+    /// Audit log of deployments, registrations and executions
+    audit: AuditSection {
+        max_bytes: u64 = "WASMIOT_AUDIT_MAX_BYTES",
+        max_files: usize = "WASMIOT_AUDIT_MAX_FILES",
+    }
+}
+
+impl ConfigFile {
+    /// Parses the contents of a configuration file.
+    ///
+    /// # Returns
+    /// The settings and the keys in the file that are not settings, or an error pointing at
+    /// the line of a value of the wrong type.
+    pub fn parse(contents: &str) -> Result<(ConfigFile, Vec<String>), String> {
+        let raw: toml::Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let config: ConfigFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let known = toml::Value::try_from(&config).map_err(|e| e.to_string())?;
+        let mut unknown = Vec::new();
+        if let toml::Value::Table(known) = known {
+            unknown_keys(&raw, &known, "", &mut unknown);
+        }
+        config.validate()?;
+        Ok((config, unknown))
+    }
+
+    /// Reads and parses the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<(ConfigFile, Vec<String>), String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read config file {}: {}", path.display(), e))?;
+        ConfigFile::parse(&contents)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Sets the settings in the file that are not set elsewhere (see `settings.rs`).
+    ///
+    /// When a file is applied again on reload, the settings no longer in the file are unset,
+    /// so that the defaults are used for them.
+    pub fn apply(&self) {
+        let values: HashMap<&'static str, String> = self.env_values().into_iter().collect();
+        for name in ConfigFile::ENV_NAMES.iter().filter(|name| !is_external(name)) {
+            match values.get(name) {
+                Some(value) => set_setting(name, value.as_str(), SettingSource::ConfigFile),
+                None => remove_setting(name),
+            }
+        }
+    }
+
+    /// Checks the settings that deserializing does not check.
+    fn validate(&self) -> Result<(), String> {
+        let invalid = |key: &str, e: String| format!("Invalid {}: {}", key, e);
+        if self.network.port == Some(0) {
+            return Err(invalid("network.port", "must be between 1 and 65535".to_string()));
+        }
+        for (key, address) in [
+            ("network.bind_address", &self.network.bind_address),
+            ("network.advertise_address", &self.network.advertise_address),
+        ] {
+            if let Some(address) = address && address.parse::<IpAddr>().is_err() {
+                return Err(invalid(key, format!("'{}' is not an IP address", address)));
+            }
+        }
+        if let Some(enabled) = &self.camera.enabled {
+            parse_camera_enabled(enabled).map_err(|e| invalid("camera.enabled", e))?;
+        }
+        if let Some(url) = &self.network.public_base_url {
+            validate_public_base_url(url).map_err(|e| invalid("network.public_base_url", e))?;
+        }
+        if let Some(url) = &self.orchestrator.url {
+            parse_http_url(url).map_err(|e| invalid("orchestrator.url", e))?;
+        }
+        if let Some(name) = &self.supervisor.name {
+            parse_name(name).map_err(|e| invalid("supervisor.name", e))?;
+        }
+        if let Some(level) = &self.logging.level {
+            parse_log_filter(level).map_err(|e| invalid("logging.level", e))?;
+        }
+        if self.coap.port == Some(0) {
+            return Err(invalid("coap.port", "must be between 1 and 65535".to_string()));
+        }
+        if self.grpc.port == Some(0) {
+            return Err(invalid("grpc.port", "must be between 1 and 65535".to_string()));
+        }
+        if let Some(size) = self.coap.block_size
+            && !(size.is_power_of_two() && (16..=1024).contains(&size))
+        {
+            return Err(invalid("coap.block_size", "must be a power of two from 16 to 1024".to_string()));
+        }
+        if let Some(mode) = &self.unix_socket.mode
+            && !u32::from_str_radix(mode, 8).is_ok_and(|mode| mode <= 0o777)
+        {
+            return Err(invalid("unix_socket.mode", format!("'{}' is not an octal file mode like 660", mode)));
+        }
+        if let Some(url) = &self.telemetry.endpoint {
+            parse_http_url(url).map_err(|e| invalid("telemetry.endpoint", e))?;
+        }
+        if let Some(ratio) = self.telemetry.sampling_ratio && !(0.0..=1.0).contains(&ratio) {
+            return Err(invalid("telemetry.sampling_ratio", "must be from 0 to 1".to_string()));
+        }
+        if let Some(url) = &self.telegraf.url {
+            parse_http_url(url).map_err(|e| invalid("telegraf.url", e))?;
+        }
+        if self.telegraf.interval_seconds == Some(0) {
+            return Err(invalid("telegraf.interval_seconds", "must be at least 1".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Returns where the value of every setting came from, by its dotted key.
+pub fn setting_sources() -> BTreeMap<String, SettingSource> {
+    ConfigFile::KEYS.iter()
+        .map(|(key, name)| (key.to_string(), setting_source(name)))
+        .collect()
+}
+
+/// Writes `value` to the setting at the dotted `key`, e.g. `supervisor.name`, in the
+/// configuration file at `path`. The file is created if it is missing, and the rest of it,
+/// comments included, is kept as it was.
+pub fn save_setting(path: &Path, key: &str, value: &str) -> Result<(), String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Could not read config file {}: {}", path.display(), e)),
+    };
+    let mut document: toml_edit::DocumentMut = contents.parse()
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+    let (section, field) = key.split_once('.')
+        .ok_or_else(|| format!("'{}' is not a setting of the configuration file", key))?;
+    let table = document.entry(section)
+        .or_insert_with(toml_edit::table)
+        .as_table_mut()
+        .ok_or_else(|| format!("[{}] in config file {} is not a table", section, path.display()))?;
+    table[field] = toml_edit::value(value);
+    fs::write(path, document.to_string())
+        .map_err(|e| format!("Could not write config file {}: {}", path.display(), e))
+}
+
+/// Whether a setting was given outside the configuration file, which the file does not replace.
+fn is_external(name: &str) -> bool {
+    // The name may also be given in the older WASMIOT_SUPERVISOR_NAME
+    setting_source(name).is_external()
+        || (name == "SUPERVISOR_NAME" && setting_source("WASMIOT_SUPERVISOR_NAME").is_external())
+}
+
+/// Collects the dotted paths of the keys in `raw` that are missing from `known`.
+fn unknown_keys(raw: &toml::Table, known: &toml::Table, prefix: &str, unknown: &mut Vec<String>) {
+    for (key, value) in raw {
+        let path = format!("{}{}", prefix, key);
+        match (known.get(key), value) {
+            (None, _) => unknown.push(path),
+            (Some(toml::Value::Table(known)), toml::Value::Table(raw)) => {
+                unknown_keys(raw, known, &format!("{}.", path), unknown);
+            }
+            _ => {}
+        }
+    }
+}
@@ -8,6 +8,7 @@
 //! - Loading structured device metadata, including system information and network interfaces
 //! - Integrating static configuration (e.g., `remote_functions.json`, `modules.json`) with
 //!   dynamic system information via `sysinfo`
+//! - Holding the runtime-adjustable `SupervisorConfig`
 
 use serde_json::{json, Value};
 use std::collections::HashMap;
@@ -15,9 +16,29 @@ use std::env;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use sysinfo::System;
-use crate::lib::constants::SUPERVISOR_INTERFACES;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use sysinfo::{System, Disk, Disks, Components};
+use crate::lib::constants::{CAMERA_FUNCTIONS, CONFIG_FILE_NAME, SUPERVISOR_INTERFACES};
+use crate::lib::camera::camera_enabled;
+use crate::lib::admission::device_facts;
+use crate::lib::wasmtime::MODULE_FORMATS;
 use crate::lib::constants::{SYSTEM, NETWORKS, DISKS};
+use crate::lib::constants::{
+    DEFAULT_CPU_THRESHOLDS,
+    DEFAULT_MEMORY_THRESHOLDS,
+    DEFAULT_DISK_THRESHOLDS,
+    DEFAULT_TEMPERATURE_THRESHOLDS,
+    DEFAULT_LOG_QUEUE_THRESHOLDS,
+    get_max_artifact_bytes,
+    get_max_deployments,
+    get_max_modules,
+};
+use crate::lib::app_state::app_state;
+use crate::lib::cli::Cli;
+use crate::lib::config_file::{save_setting, setting_sources, ConfigFile};
+use crate::lib::settings::{get_setting, set_setting, SettingSource};
+use crate::structs::supervisor_config::{SupervisorConfig, StartupConfig, DeviceLimits, HealthThresholds, Threshold, validate_public_base_url};
 use crate::structs::device::{
     CpuInfo, 
     MemoryInfo, 
@@ -28,11 +49,11 @@ use crate::structs::device::{
 
 /// Returns the absolute path to the instance directory.
 ///
-/// Uses the environment variable `INSTANCE_PATH` if set, otherwise defaults to:
+/// Uses the `INSTANCE_PATH` setting if set, otherwise defaults to:
 /// `<current_working_directory>/instance`.
 pub fn get_instance_path() -> PathBuf {
-    let instance_str = env::var("INSTANCE_PATH")
-        .unwrap_or_else(|_| {
+    let instance_str = get_setting("INSTANCE_PATH")
+        .unwrap_or_else(|| {
             let cwd = env::current_dir().expect("Failed to get current working directory");
             cwd.join("instance").to_string_lossy().to_string()
         });
@@ -102,7 +123,18 @@ pub fn get_modules() -> Value {
 pub fn get_device_description() -> Value {
     let mut description: Value = json!({});
     description["platform"] = get_device_platform_info();
-    description["supervisorInterfaces"] = json!(SUPERVISOR_INTERFACES.to_vec());
+    // The camera functions are only offered when the camera may be used
+    let interfaces: Vec<&str> = SUPERVISOR_INTERFACES.iter().copied()
+        .filter(|name| camera_enabled() || !CAMERA_FUNCTIONS.contains(name))
+        .collect();
+    description["supervisorInterfaces"] = json!(interfaces);
+    // What the requirements of deployments are checked against (see `admission.rs`)
+    description["admission"] = device_facts().to_json();
+    // What the deployments of the device may use (see `device_limits.rs`). What they use is added
+    // when the description is served (see `api::wasmiot_device_description`)
+    description["deviceLimits"] = json!(SUPERVISOR_CONFIG.read().device_limits);
+    // Core modules, and components of the component model where WASI preview 2 is available
+    description["moduleFormats"] = json!(MODULE_FORMATS);
     description
 }
 
@@ -200,3 +232,256 @@ pub fn get_device_platform_info() -> Value {
         },
     })
 }
+
+/// Current runtime configuration of the supervisor.
+///
+/// Initialized from the settings on first use and changed through `patch_supervisor_config`.
+pub static SUPERVISOR_CONFIG: Lazy<RwLock<SupervisorConfig>> = Lazy::new(|| RwLock::new(load_supervisor_config()));
+
+/// Reads a health threshold pair from `WASMIOT_HEALTH_<METRIC>_DEGRADED` and
+/// `WASMIOT_HEALTH_<METRIC>_CRITICAL`, falling back to the given defaults.
+fn threshold_from_settings(metric: &str, default: (f32, f32)) -> Threshold {
+    let read = |level: &str, fallback: f32| {
+        get_setting(&format!("WASMIOT_HEALTH_{}_{}", metric, level))
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(fallback)
+    };
+    Threshold {
+        degraded: read("DEGRADED", default.0),
+        critical: read("CRITICAL", default.1),
+    }
+}
+
+/// Reads the caps on the deployments of the device from the settings, where 0 is no cap.
+fn device_limits_from_settings() -> DeviceLimits {
+    DeviceLimits {
+        max_deployments: Some(get_max_deployments()).filter(|&n| n > 0),
+        max_modules: Some(get_max_modules()).filter(|&n| n > 0),
+        max_artifact_bytes: Some(get_max_artifact_bytes()).filter(|&b| b > 0),
+    }
+}
+
+/// Builds the initial supervisor configuration from the settings and defaults.
+///
+/// The `startup` settings are read like `Cli` would, and replaced with
+/// the ones from the command line by `set_startup_config` when the supervisor starts.
+pub fn load_supervisor_config() -> SupervisorConfig {
+    let startup = Cli::parse_with_settings(["supervisor"])
+        .map(|(cli, _)| cli.startup_config())
+        .unwrap_or_default();
+    SupervisorConfig {
+        name: startup.name.clone(),
+        health_thresholds: HealthThresholds {
+            cpu: threshold_from_settings("CPU", DEFAULT_CPU_THRESHOLDS),
+            memory: threshold_from_settings("MEMORY", DEFAULT_MEMORY_THRESHOLDS),
+            disk: threshold_from_settings("DISK", DEFAULT_DISK_THRESHOLDS),
+            temperature: threshold_from_settings("TEMPERATURE", DEFAULT_TEMPERATURE_THRESHOLDS),
+            log_queue: threshold_from_settings("LOG_QUEUE", DEFAULT_LOG_QUEUE_THRESHOLDS),
+        },
+        public_base_url: get_setting("WASMIOT_PUBLIC_BASE_URL")
+            .filter(|url| !url.is_empty())
+            .filter(|url| match validate_public_base_url(url) {
+                Ok(_) => true,
+                Err(e) => {
+                    log::error!("Ignoring WASMIOT_PUBLIC_BASE_URL: {}", e);
+                    false
+                }
+            }),
+        device_limits: device_limits_from_settings(),
+        startup,
+    }
+}
+
+/// Records the settings the supervisor was started with.
+pub fn set_startup_config(startup: StartupConfig) {
+    SUPERVISOR_CONFIG.write().startup = startup;
+}
+
+/// Applies settings read again from the configuration file and the environment to the
+/// configuration, replacing the runtime values only of the settings that changed.
+///
+/// `previous` are the settings the configuration was last built from, so that values changed
+/// through the `/config` endpoint are kept unless the same setting changed in the file too.
+pub fn apply_reloaded_settings(previous: &ConfigFile, settings: &ConfigFile) -> Result<(), String> {
+    let mut config = SUPERVISOR_CONFIG.write();
+    let mut updated = config.clone();
+    if previous.network.public_base_url != settings.network.public_base_url {
+        updated.public_base_url = settings.network.public_base_url.clone();
+    }
+    let (old, new) = (&previous.health, &settings.health);
+    if (old.cpu_degraded, old.cpu_critical) != (new.cpu_degraded, new.cpu_critical) {
+        updated.health_thresholds.cpu = threshold_from_settings("CPU", DEFAULT_CPU_THRESHOLDS);
+    }
+    if (old.memory_degraded, old.memory_critical) != (new.memory_degraded, new.memory_critical) {
+        updated.health_thresholds.memory = threshold_from_settings("MEMORY", DEFAULT_MEMORY_THRESHOLDS);
+    }
+    if (old.disk_degraded, old.disk_critical) != (new.disk_degraded, new.disk_critical) {
+        updated.health_thresholds.disk = threshold_from_settings("DISK", DEFAULT_DISK_THRESHOLDS);
+    }
+    if (old.temperature_degraded, old.temperature_critical) != (new.temperature_degraded, new.temperature_critical) {
+        updated.health_thresholds.temperature = threshold_from_settings("TEMPERATURE", DEFAULT_TEMPERATURE_THRESHOLDS);
+    }
+    if (old.log_queue_degraded, old.log_queue_critical) != (new.log_queue_degraded, new.log_queue_critical) {
+        updated.health_thresholds.log_queue = threshold_from_settings("LOG_QUEUE", DEFAULT_LOG_QUEUE_THRESHOLDS);
+    }
+    let (old, new) = (&previous.limits, &settings.limits);
+    let limits = device_limits_from_settings();
+    if old.max_deployments != new.max_deployments {
+        updated.device_limits.max_deployments = limits.max_deployments;
+    }
+    if old.max_modules != new.max_modules {
+        updated.device_limits.max_modules = limits.max_modules;
+    }
+    if old.max_artifact_bytes != new.max_artifact_bytes {
+        updated.device_limits.max_artifact_bytes = limits.max_artifact_bytes;
+    }
+    updated.validate()?;
+
+    updated.startup.orchestrator_url = settings.orchestrator.url.clone();
+    updated.startup.log_level = settings.logging.level.clone().unwrap_or_else(|| "info".to_string());
+    updated.startup.settings = settings.clone();
+    updated.startup.sources = setting_sources();
+    *config = updated;
+    Ok(())
+}
+
+/// Returns a copy of the current supervisor configuration.
+pub fn get_supervisor_config() -> SupervisorConfig {
+    SUPERVISOR_CONFIG.read().clone()
+}
+
+/// Applies a JSON merge patch (RFC 7396) to the supervisor configuration.
+///
+/// The patched configuration is validated before it replaces the current one,
+/// so a rejected patch leaves the configuration untouched.
+///
+/// # Returns
+/// The new configuration, or a description of why the patch was rejected.
+pub fn patch_supervisor_config(patch: &Value) -> Result<SupervisorConfig, String> {
+    let mut config = SUPERVISOR_CONFIG.write();
+    let mut merged = serde_json::to_value(&*config)
+        .map_err(|e| format!("Failed to serialize current configuration: {}", e))?;
+    merge_patch(&mut merged, patch);
+
+    let mut updated: SupervisorConfig = serde_json::from_value(merged)
+        .map_err(|e| format!("Invalid configuration: {}", e))?;
+    updated.validate()?;
+    if updated.startup != config.startup {
+        return Err("Startup settings cannot be changed at runtime".to_string());
+    }
+    if updated.name != config.name {
+        rename_supervisor(&mut updated)?;
+    }
+
+    *config = updated.clone();
+    Ok(updated)
+}
+
+/// Saves the new `name` of `config` to the configuration file, so that the supervisor keeps it
+/// after a restart, and updates the `SUPERVISOR_NAME` setting to it.
+///
+/// The name is saved to the file the supervisor was started with, or to `supervisor.toml` in
+/// the instance folder, which is created if missing.
+fn rename_supervisor(config: &mut SupervisorConfig) -> Result<(), String> {
+    let startup = &mut config.startup;
+    let path = startup.config_file.clone()
+        .unwrap_or_else(|| startup.instance_path.join(CONFIG_FILE_NAME));
+    save_setting(&path, "supervisor.name", &config.name)?;
+    if let Some(source) = startup.sources.get("supervisor.name")
+        && *source != SettingSource::Api
+        && source.is_external()
+    {
+        log::warn!(
+            "The name was saved to {}, but the name given in the {} replaces it after a restart",
+            path.display(), source
+        );
+    }
+    set_setting("SUPERVISOR_NAME", &config.name, SettingSource::Api);
+    startup.settings.supervisor.name = Some(config.name.clone());
+    startup.sources = setting_sources();
+    Ok(())
+}
+
+/// Builds the absolute URL of `path` on this supervisor, for links given out to clients.
+///
+/// The `publicBaseUrl` of the configuration (`WASMIOT_PUBLIC_BASE_URL`) is used as the prefix
+/// when set, for when the supervisor is reached through a reverse proxy or NAT. Otherwise the
+/// URL is made of `DEFAULT_URL_SCHEME`, `WASMIOT_SUPERVISOR_IP` and `WASMIOT_SUPERVISOR_PORT`.
+/// The `base_url` of the `AppState` being served is used instead of either, for a supervisor
+/// that is one of several in the process (see `app_state.rs`).
+pub fn public_url(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    if let Some(base) = &app_state().base_url {
+        return format!("{}/{}", base.trim_end_matches('/'), path);
+    }
+    if let Some(base) = &SUPERVISOR_CONFIG.read().public_base_url {
+        return format!("{}/{}", base.trim_end_matches('/'), path);
+    }
+    let scheme = get_setting("DEFAULT_URL_SCHEME").unwrap_or_else(|| "http".to_string());
+    let host = get_setting("WASMIOT_SUPERVISOR_IP").unwrap_or_else(|| "localhost".to_string());
+    let port = get_setting("WASMIOT_SUPERVISOR_PORT").unwrap_or_else(|| "8080".to_string());
+    format!("{scheme}://{host}:{port}/{path}")
+}
+
+/// Merges `patch` into `target` following JSON merge patch semantics.
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Some(patch_obj) = patch.as_object() else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = json!({});
+    }
+    let target_obj = target.as_object_mut().expect("target was just made an object");
+    for (key, value) in patch_obj {
+        if value.is_null() {
+            target_obj.remove(key);
+        } else {
+            merge_patch(target_obj.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// Finds the disk holding the instance directory, i.e. the one with the longest
+/// mount point that prefixes the instance path.
+fn find_instance_disk(disks: &Disks) -> Option<&Disk> {
+    let instance_path = get_instance_path();
+    disks
+        .list()
+        .iter()
+        .filter(|d| instance_path.starts_with(d.mount_point()))
+        .max_by_key(|d| d.mount_point().as_os_str().len())
+}
+
+/// Returns the used fraction (0..1) of the filesystem that holds the instance directory.
+///
+/// Returns `None` if no matching disk is found.
+pub fn instance_disk_usage(disks: &Disks) -> Option<f32> {
+    find_instance_disk(disks)
+        .map(|d| {
+            let total = d.total_space();
+            if total > 0 {
+                total.saturating_sub(d.available_space()) as f32 / total as f32
+            } else {
+                0.0
+            }
+        })
+}
+
+/// Returns the free bytes on the filesystem that holds the instance directory.
+///
+/// Returns `None` if no matching disk is found.
+pub fn instance_disk_available(disks: &Disks) -> Option<u64> {
+    find_instance_disk(disks).map(|d| d.available_space())
+}
+
+/// Returns the temperature of the hottest sensor in Celsius, or `None` if the
+/// device exposes no temperature sensors.
+pub fn max_temperature(components: &Components) -> Option<f32> {
+    components
+        .list()
+        .iter()
+        .filter_map(|c| c.temperature())
+        .filter(|t| t.is_finite())
+        .reduce(f32::max)
+}
@@ -12,10 +12,11 @@
 //! This module is intended to centralize all shared, immutable configuration used across the system.
 
 use std::path::PathBuf;
-use std::fs;
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
-use sysinfo::{System, Networks, Disks};
+use sysinfo::{System, Networks, Disks, Components};
+use crate::lib::settings::get_setting;
+use crate::lib::self_check::{check_folder, required_folders};
 
 /// Default port used when running the service.
 pub const DEFAULT_PORT: u16 = 8080;
@@ -29,6 +30,9 @@ pub const URL_BASE_PATH: &str = "/file/device/discovery/register";
 /// Name for the supervisor that is given to orchestrator.
 pub const SUPERVISOR_DEFAULT_NAME: &str = "supervisor";
 
+/// Longest name the supervisor can be advertised with, the limit of a DNS-SD instance name.
+pub const MAX_SERVICE_NAME_BYTES: usize = 63;
+
 /// Folder name where deployed Wasm modules are stored under the instance path.
 pub const MODULE_FOLDER_NAME: &str = "wasm-modules";
 
@@ -38,12 +42,56 @@ pub const PARAMS_FOLDER_NAME: &str = "wasm-params";
 /// Folder name where deployments are stored.
 pub const DEPLOYMENTS_FOLDER_NAME: &str = "deployments";
 
+/// Folder name of the content-addressed cache of downloaded artifacts and compiled modules.
+pub const ARTIFACT_CACHE_FOLDER_NAME: &str = "artifact-cache";
+
+/// Folder name where deployment manifests to apply at startup are placed, e.g. at factory provisioning.
+pub const PRELOADED_DEPLOYMENTS_FOLDER_NAME: &str = "preloaded_deployments";
+
+/// Folder name where imported deployment bundles are unpacked, and the artifacts of pushed
+/// deployments are staged, while the deployment is created.
+pub const BUNDLE_IMPORT_FOLDER_NAME: &str = "bundle-imports";
+
+/// Folder name where the entries evicted from the request history are kept, one file per request.
+pub const REQUEST_ARCHIVE_FOLDER_NAME: &str = "request-archive";
+
+/// Folder name where the audit log of deployments, registrations and executions is written.
+pub const AUDIT_FOLDER_NAME: &str = "audit";
+
+/// Folder name inside a module's params folder where the outputs of each request are kept,
+/// in a subfolder named after the request ID.
+pub const OUTPUTS_FOLDER_NAME: &str = "outputs";
+
+/// Folder name inside a module's params folder where the input files of each request are
+/// uploaded, in a subfolder named after the request ID that is removed after the execution.
+pub const INPUTS_FOLDER_NAME: &str = "inputs";
+
+/// Folder name inside a module's params folder that is mounted at `/work` for modules with the
+/// isolated mount layout, holding the input files of the running request and its outputs.
+pub const WORK_FOLDER_NAME: &str = "work";
+
+/// File name inside the instance folder of the device secret that result URLs are signed with,
+/// unless one is given in `WASMIOT_RESULT_URL_SECRET`.
+pub const RESULT_URL_SECRET_FILE_NAME: &str = "result-url.secret";
+
+/// File name inside the instance folder of the TOML configuration file read at startup,
+/// unless another file is given with `--config-file`.
+pub const CONFIG_FILE_NAME: &str = "supervisor.toml";
+
+/// File name inside the instance folder where the request history is saved on shutdown and
+/// read back at startup.
+pub const REQUEST_HISTORY_FILE_NAME: &str = "request-history.json";
+
+/// File name inside the instance folder of the durable execution queue, one queued request
+/// per line, read back at startup.
+pub const EXECUTION_QUEUE_FILE_NAME: &str = "execution-queue.jsonl";
+
 /// Root path where everything related to this instance of service are stored into
 ///
 /// This is typically configured via the `INSTANCE_PATH` environment variable.
 /// Defaults to `./instance` if not set.
 pub static INSTANCE_PATH: Lazy<PathBuf> = Lazy::new(|| {
-    PathBuf::from(std::env::var("INSTANCE_PATH").unwrap_or_else(|_| "./instance".into()))
+    PathBuf::from(get_setting("INSTANCE_PATH").unwrap_or_else(|| "./instance".into()))
 });
 
 /// Full path to the directory containing Wasm modules.
@@ -61,6 +109,37 @@ pub static PARAMS_FOLDER: Lazy<PathBuf> = Lazy::new(|| INSTANCE_PATH.join(PARAMS
 /// This is derived from the `INSTANCE_PATH` and `DEPLOYMENTS_FOLDER_NAME`.
 pub static DEPLOYMENTS_FOLDER: Lazy<PathBuf> = Lazy::new(|| INSTANCE_PATH.join(DEPLOYMENTS_FOLDER_NAME));
 
+/// Full path to the directory where the execution statistics of deployments are saved.
+///
+/// This is a folder inside `DEPLOYMENTS_FOLDER`, so that the statistics are not loaded as deployments.
+pub static DEPLOYMENT_STATS_FOLDER: Lazy<PathBuf> = Lazy::new(|| DEPLOYMENTS_FOLDER.join("stats"));
+
+/// Full path to the content-addressed artifact cache, where files are named by their SHA-256 digest.
+///
+/// This is derived from the `INSTANCE_PATH` and `ARTIFACT_CACHE_FOLDER_NAME`.
+pub static ARTIFACT_CACHE_FOLDER: Lazy<PathBuf> = Lazy::new(|| INSTANCE_PATH.join(ARTIFACT_CACHE_FOLDER_NAME));
+
+/// Full path to the directory of deployment manifests applied at startup.
+///
+/// This is derived from the `INSTANCE_PATH` and `PRELOADED_DEPLOYMENTS_FOLDER_NAME`.
+pub static PRELOADED_DEPLOYMENTS_FOLDER: Lazy<PathBuf> = Lazy::new(|| INSTANCE_PATH.join(PRELOADED_DEPLOYMENTS_FOLDER_NAME));
+
+/// Full path to the directory where imported deployment bundles are unpacked, and the
+/// artifacts of pushed deployments are staged.
+///
+/// This is derived from the `INSTANCE_PATH` and `BUNDLE_IMPORT_FOLDER_NAME`.
+pub static BUNDLE_IMPORT_FOLDER: Lazy<PathBuf> = Lazy::new(|| INSTANCE_PATH.join(BUNDLE_IMPORT_FOLDER_NAME));
+
+/// Full path to the directory where the entries evicted from the request history are kept.
+///
+/// This is derived from the `INSTANCE_PATH` and `REQUEST_ARCHIVE_FOLDER_NAME`.
+pub static REQUEST_ARCHIVE_FOLDER: Lazy<PathBuf> = Lazy::new(|| INSTANCE_PATH.join(REQUEST_ARCHIVE_FOLDER_NAME));
+
+/// Full path to the directory where the audit log is written.
+///
+/// This is derived from the `INSTANCE_PATH` and `AUDIT_FOLDER_NAME`.
+pub static AUDIT_FOLDER: Lazy<PathBuf> = Lazy::new(|| INSTANCE_PATH.join(AUDIT_FOLDER_NAME));
+
 /// Functions provided for the camera module
 pub const CAMERA_FUNCTIONS: &[&str] = &[
     "takeImageDynamicSize",
@@ -176,27 +255,644 @@ pub const PULLEY_MODULE_POSTFIX: &str = "PULLEY.wasm";
 // Name of the memory related to each module
 pub const MEMORY_NAME: &str = "memory";
 
-/// Ensures that all required directories for modules and parameter mounts exist.
+/// Ensures that all required directories for modules, parameter mounts and deployments exist
+/// and are writable.
 ///
 /// This function should be ran in the main function before anything else.
-pub fn ensure_required_folders() {
-    fs::create_dir_all(&*MODULE_FOLDER).expect("Failed to create module folder");
-    fs::create_dir_all(&*PARAMS_FOLDER).expect("Failed to create params folder");
+///
+/// # Returns
+/// An error naming the first folder that cannot be used
+pub fn ensure_required_folders() -> Result<(), String> {
+    for (_, path) in required_folders() {
+        check_folder(&path).map_err(|e| format!(
+            "{}. Check the permissions of the folder, or use another instance folder with --instance-path or INSTANCE_PATH",
+            e
+        ))?;
+    }
+    Ok(())
+}
+
+/// Helper function to get how long running executions are waited for on shutdown from env
+pub fn get_drain_timeout() -> u64 {
+    get_setting("WASMIOT_DRAIN_TIMEOUT_SECS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT_SECS)
 }
 
 /// Helper function to get timeout from env
 pub fn get_module_timeout() -> u64 {
-    std::env::var("WASMIOT_MODULE_TIMEOUT_SECONDS")
-        .ok()
+    get_setting("WASMIOT_MODULE_TIMEOUT_SECONDS")
         .and_then(|s| s.parse().ok())
         .unwrap_or(DEFAULT_MODULE_TIMEOUT_SECONDS)
 }
 
+/// Helper function to get the `Cache-Control: max-age` of the description endpoints from env
+pub fn get_description_max_age() -> u64 {
+    get_setting("WASMIOT_DESCRIPTION_MAX_AGE_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_DESCRIPTION_MAX_AGE_SECONDS)
+}
+
+/// Helper function to get the interval between health history samples from env
+pub fn get_health_sample_interval() -> u64 {
+    get_setting("WASMIOT_HEALTH_SAMPLE_INTERVAL_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(DEFAULT_HEALTH_SAMPLE_INTERVAL_SECONDS)
+}
+
+/// Helper function to get the interval in milliseconds between refreshes of the system metrics
+/// served by the health endpoint from env
+pub fn get_health_refresh_interval() -> u64 {
+    get_setting("WASMIOT_HEALTH_REFRESH_INTERVAL_MS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&ms| ms > 0)
+        .unwrap_or(DEFAULT_HEALTH_REFRESH_INTERVAL_MS)
+}
+
+/// Helper function to get the number of samples kept in the health history from env
+pub fn get_health_history_size() -> usize {
+    get_setting("WASMIOT_HEALTH_HISTORY_SIZE")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_HEALTH_HISTORY_SIZE)
+}
+
+/// Helper function to get the number of threads serving the HTTP API from env, if set.
+/// Otherwise the server uses one per CPU core
+pub fn get_http_workers() -> Option<usize> {
+    get_setting("WASMIOT_HTTP_WORKERS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+}
+
+/// Helper function to get the number of threads running Wasm functions from env
+pub fn get_execution_threads() -> usize {
+    get_setting("WASMIOT_EXECUTION_THREADS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_EXECUTION_THREADS)
+}
+
+/// Helper function to get from env whether low priority requests are refused while every execution thread is busy
+pub fn get_shed_low_priority() -> bool {
+    get_setting("WASMIOT_SHED_LOW_PRIORITY")
+        .map(|s| s == "true")
+        .unwrap_or(false)
+}
+
+/// Helper function to get from env whether Wasm modules are run on the Pulley interpreter instead of as native code.
+/// Builds with the `pulley` feature run them on the interpreter unless it is set to `false`.
+pub fn get_force_pulley() -> bool {
+    get_setting("WASMIOT_FORCE_PULLEY")
+        .map(|s| s == "true")
+        .unwrap_or(cfg!(feature = "pulley"))
+}
+
+/// Helper function to get from env whether the developer endpoints, such as `POST /dev/run`, are routed
+pub fn get_dev_mode() -> bool {
+    get_setting("WASMIOT_DEV_MODE")
+        .map(|s| s == "true")
+        .unwrap_or(false)
+}
+
+/// Helper function to get the most requests waiting in the durable execution queue from env
+pub fn get_execution_queue_max_depth() -> usize {
+    get_setting("WASMIOT_EXECUTION_QUEUE_MAX_DEPTH")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_EXECUTION_QUEUE_MAX_DEPTH)
+}
+
+/// Helper function to get the most examples captured for the endpoint of each function from env
+pub fn get_endpoint_examples() -> usize {
+    get_setting("WASMIOT_ENDPOINT_EXAMPLES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_ENDPOINT_EXAMPLES)
+}
+
+/// Helper function to get the number of concurrent deployment downloads from env
+pub fn get_download_concurrency() -> usize {
+    get_setting("WASMIOT_DOWNLOAD_CONCURRENCY")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_DOWNLOAD_CONCURRENCY)
+}
+
+/// Helper function to get how many times a failed artifact download is retried from env
+pub fn get_download_retries() -> u32 {
+    get_setting("WASMIOT_DOWNLOAD_RETRIES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_DOWNLOAD_RETRIES)
+}
+
+/// Helper function to get the largest allowed size of a single deployment file from env
+pub fn get_max_file_bytes() -> u64 {
+    get_setting("WASMIOT_MAX_FILE_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_FILE_BYTES)
+}
+
+/// Helper function to get the largest allowed total size of a deployment's files from env
+pub fn get_max_deployment_bytes() -> u64 {
+    get_setting("WASMIOT_MAX_DEPLOYMENT_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_DEPLOYMENT_BYTES)
+}
+
+/// Helper function to get how many bytes must be left free on the instance filesystem from env
+pub fn get_disk_reserve_bytes() -> u64 {
+    get_setting("WASMIOT_DISK_RESERVE_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_DISK_RESERVE_BYTES)
+}
+
+/// Helper function to get the time limit for downloading all artifacts of a deployment from env
+pub fn get_deployment_download_timeout() -> u64 {
+    get_setting("WASMIOT_DEPLOYMENT_DOWNLOAD_TIMEOUT_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_DEPLOYMENT_DOWNLOAD_TIMEOUT_SECONDS)
+}
+
+/// Helper function to get the only directory deployments may use local files (`file://` URLs) from
+///
+/// Read from `WASMIOT_LOCAL_ARTIFACT_DIR`, defaulting to the preloaded deployments folder.
+pub fn get_local_artifact_dir() -> PathBuf {
+    get_setting("WASMIOT_LOCAL_ARTIFACT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PRELOADED_DEPLOYMENTS_FOLDER.clone())
+}
+
+/// Helper function to check from env whether preloaded deployments are applied at startup
+pub fn get_apply_preloaded_deployments() -> bool {
+    get_setting("WASMIOT_APPLY_PRELOADED_DEPLOYMENTS")
+        .map(|s| s == "true")
+        .unwrap_or(true)
+}
+
+/// Helper function to get the hex encoded Ed25519 public keys trusted to sign deployments from env
+pub fn get_trusted_public_keys() -> Vec<String> {
+    get_setting("WASMIOT_TRUSTED_PUBLIC_KEYS")
+        .map(|s| s.split(',').map(|k| k.trim().to_string()).filter(|k| !k.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Helper function to check from env whether unsigned deployments are rejected
+pub fn get_require_signed_deployments() -> bool {
+    get_setting("WASMIOT_REQUIRE_SIGNED_DEPLOYMENTS")
+        .map(|s| s == "true")
+        .unwrap_or(false)
+}
+
+/// Helper function to get the cap on the size of outputs of chained calls copied to this device from env, 0 to never copy them
+pub fn get_chain_mirror_max_bytes() -> u64 {
+    get_setting("WASMIOT_CHAIN_MIRROR_MAX_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_CHAIN_MIRROR_MAX_BYTES)
+}
+
+/// Helper function to get whether result file URLs are signed and checked from env
+pub fn get_sign_result_urls() -> bool {
+    get_setting("WASMIOT_SIGN_RESULT_URLS")
+        .map(|s| s == "true")
+        .unwrap_or(false)
+}
+
+/// Helper function to get the secret result file URLs are signed with from env, if set
+pub fn get_result_url_secret() -> Option<String> {
+    get_setting("WASMIOT_RESULT_URL_SECRET").filter(|s| !s.is_empty())
+}
+
+/// Helper function to get how long signed result file URLs stay valid from env
+pub fn get_result_url_ttl() -> u64 {
+    get_setting("WASMIOT_RESULT_URL_TTL_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RESULT_URL_TTL_SECONDS)
+}
+
+/// Helper function to get how often expired deployments are looked for from env
+pub fn get_expiry_check_interval() -> u64 {
+    get_setting("WASMIOT_EXPIRY_CHECK_INTERVAL_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&s| s > 0)
+        .unwrap_or(DEFAULT_EXPIRY_CHECK_INTERVAL_SECONDS)
+}
+
+/// Helper function to get how long an expired deployment is still reported as expired from env
+pub fn get_expired_deployment_grace() -> u64 {
+    get_setting("WASMIOT_EXPIRED_DEPLOYMENT_GRACE_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_EXPIRED_DEPLOYMENT_GRACE_SECONDS)
+}
+
+/// Helper function to get how old an orphaned module or params folder must be to be removed from env
+pub fn get_gc_grace() -> u64 {
+    get_setting("WASMIOT_GC_GRACE_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_GC_GRACE_SECONDS)
+}
+
+/// Helper function to get the age in seconds after which execution outputs are removed from env, 0 to keep them
+pub fn get_result_max_age() -> u64 {
+    get_setting("WASMIOT_RESULT_MAX_AGE")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RESULT_MAX_AGE_SECONDS)
+}
+
+/// Helper function to get the cap on the total size of execution outputs from env, 0 for no cap
+pub fn get_result_max_bytes() -> u64 {
+    get_setting("WASMIOT_RESULT_MAX_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RESULT_MAX_BYTES)
+}
+
+/// Helper function to get the cap on the size of execution outputs of one deployment from env, 0 for no cap
+pub fn get_result_max_deployment_bytes() -> u64 {
+    get_setting("WASMIOT_RESULT_MAX_DEPLOYMENT_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RESULT_MAX_DEPLOYMENT_BYTES)
+}
+
+/// Helper function to get the most deployments on the device from env, 0 for no cap
+pub fn get_max_deployments() -> usize {
+    get_setting("WASMIOT_MAX_DEPLOYMENTS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_DEPLOYMENTS)
+}
+
+/// Helper function to get the most modules in all deployments on the device from env, 0 for no cap
+pub fn get_max_modules() -> usize {
+    get_setting("WASMIOT_MAX_MODULES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_MODULES)
+}
+
+/// Helper function to get the cap on the size of the artifacts of all deployments from env, 0 for no cap
+pub fn get_max_artifact_bytes() -> u64 {
+    get_setting("WASMIOT_MAX_ARTIFACT_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ARTIFACT_BYTES)
+}
+
+/// Helper function to get how often the result retention policy is enforced from env
+pub fn get_result_cleanup_interval() -> u64 {
+    get_setting("WASMIOT_RESULT_CLEANUP_INTERVAL_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&s| s > 0)
+        .unwrap_or(DEFAULT_RESULT_CLEANUP_INTERVAL_SECONDS)
+}
+
+/// Helper function to get for how long the outputs of requests in the request history are kept
+/// in preference to others when results are over their size caps, from env
+pub fn get_request_history_retention() -> u64 {
+    get_setting("WASMIOT_REQUEST_HISTORY_RETENTION_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_REQUEST_HISTORY_RETENTION_SECONDS)
+}
+
+/// Helper function to get how many requests are kept in the in-memory request history from env
+pub fn get_request_history_max_entries() -> usize {
+    get_setting("WASMIOT_REQUEST_HISTORY_MAX_ENTRIES")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_REQUEST_HISTORY_MAX_ENTRIES)
+}
+
+/// Helper function to get the size below which output files are included in execution responses from env, 0 to never include them
+pub fn get_inline_result_max_bytes() -> u64 {
+    get_setting("WASMIOT_INLINE_RESULT_MAX_BYTES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_INLINE_RESULT_MAX_BYTES)
+}
+
+/// Helper function to get the MQTT broker executions are taken from from env, as `host:port`. MQTT is off when not set
+pub fn get_mqtt_broker() -> Option<String> {
+    get_setting("WASMIOT_MQTT_BROKER").filter(|s| !s.is_empty())
+}
+
+/// Helper function to get the user name and password the MQTT broker is connected with from env, if set
+pub fn get_mqtt_credentials() -> Option<(String, Option<String>)> {
+    let username = get_setting("WASMIOT_MQTT_USERNAME").filter(|s| !s.is_empty())?;
+    Some((username, get_setting("WASMIOT_MQTT_PASSWORD")))
+}
+
+/// Helper function to get the first level of the MQTT topics of the supervisor from env
+pub fn get_mqtt_topic_prefix() -> String {
+    get_setting("WASMIOT_MQTT_TOPIC_PREFIX")
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| DEFAULT_MQTT_TOPIC_PREFIX.to_string())
+}
+
+/// Helper function to get the MQTT keep alive interval in seconds from env
+pub fn get_mqtt_keep_alive() -> u16 {
+    get_setting("WASMIOT_MQTT_KEEP_ALIVE_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MQTT_KEEP_ALIVE_SECONDS)
+}
+
+/// Helper function to get the UDP port of the CoAP server from env. CoAP is off when not set
+pub fn get_coap_port() -> Option<u16> {
+    get_setting("WASMIOT_COAP_PORT").and_then(|s| s.parse().ok())
+}
+
+/// Helper function to get the TCP port of the gRPC server from env. gRPC is off when not set
+pub fn get_grpc_port() -> Option<u16> {
+    get_setting("WASMIOT_GRPC_PORT").and_then(|s| s.parse().ok())
+}
+
+/// Helper function to get the largest block of a CoAP block-wise transfer from env, a power of two from 16 to 1024
+pub fn get_coap_block_size() -> usize {
+    get_setting("WASMIOT_COAP_BLOCK_SIZE")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n: &usize| n.is_power_of_two() && (16..=1024).contains(&n))
+        .unwrap_or(DEFAULT_COAP_BLOCK_SIZE)
+}
+
+/// Helper function to get the secret execution callbacks are signed with from env, if set
+pub fn get_callback_secret() -> Option<String> {
+    get_setting("WASMIOT_CALLBACK_SECRET").filter(|s| !s.is_empty())
+}
+
+/// Helper function to get the token required by the protected endpoints from env, if set
+pub fn get_api_token() -> Option<String> {
+    get_setting("WASMIOT_API_TOKEN").filter(|s| !s.is_empty())
+}
+
+/// Helper function to get the key the entries of the audit log are chained with from env, if set
+pub fn get_audit_hmac_key() -> Option<String> {
+    get_setting("WASMIOT_AUDIT_HMAC_KEY").filter(|s| !s.is_empty())
+}
+
+/// Helper function to get the size in bytes the audit log is rotated at from env
+pub fn get_audit_max_bytes() -> u64 {
+    get_setting("WASMIOT_AUDIT_MAX_BYTES")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_AUDIT_MAX_BYTES)
+}
+
+/// Helper function to get how many rotated audit log files are kept from env
+pub fn get_audit_max_files() -> usize {
+    get_setting("WASMIOT_AUDIT_MAX_FILES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_AUDIT_MAX_FILES)
+}
+
+/// Helper function to get how many times a failed execution callback is retried from env
+pub fn get_callback_retries() -> u32 {
+    get_setting("WASMIOT_CALLBACK_RETRIES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_CALLBACK_RETRIES)
+}
+
+/// Helper function to get the time limit in seconds of one attempt to deliver an execution callback from env
+pub fn get_callback_timeout() -> u64 {
+    get_setting("WASMIOT_CALLBACK_TIMEOUT_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_CALLBACK_TIMEOUT_SECONDS)
+}
+
+/// Helper function to get the time limit in seconds of the healthcheck of a module run when it is deployed from env
+pub fn get_healthcheck_timeout() -> u64 {
+    get_setting("WASMIOT_HEALTHCHECK_TIMEOUT_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_HEALTHCHECK_TIMEOUT_SECONDS)
+}
+
+/// Helper function to get how often the standby replicas of deployments are checked and synced from env
+pub fn get_replica_sync_interval() -> u64 {
+    get_setting("WASMIOT_REPLICA_SYNC_INTERVAL_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&s| s > 0)
+        .unwrap_or(DEFAULT_REPLICA_SYNC_INTERVAL_SECONDS)
+}
+
+/// Helper function to get the OTLP/HTTP endpoint traces of executions are exported to from env, if set
+pub fn get_otel_endpoint() -> Option<String> {
+    get_setting("WASMIOT_OTEL_ENDPOINT").filter(|s| !s.is_empty())
+}
+
+/// Helper function to get the share of traces started here that are exported from env, from 0 to 1
+pub fn get_otel_sampling_ratio() -> f64 {
+    get_setting("WASMIOT_OTEL_SAMPLING_RATIO")
+        .and_then(|s| s.parse().ok())
+        .filter(|n: &f64| (0.0..=1.0).contains(n))
+        .unwrap_or(DEFAULT_OTEL_SAMPLING_RATIO)
+}
+
+/// Helper function to get the URL of the Telegraf HTTP listener metrics are pushed to from env, if set
+pub fn get_telegraf_url() -> Option<String> {
+    get_setting("WASMIOT_TELEGRAF_URL").filter(|s| !s.is_empty())
+}
+
+/// Helper function to get the interval in seconds between pushes of metrics to Telegraf from env
+pub fn get_telegraf_interval() -> u64 {
+    get_setting("WASMIOT_TELEGRAF_INTERVAL_SECONDS")
+        .and_then(|s| s.parse().ok())
+        .filter(|&s| s > 0)
+        .unwrap_or(DEFAULT_TELEGRAF_INTERVAL_SECONDS)
+}
+
+/// Helper function to get the key the S3 result sinks of deployments are accessed with from env, if set
+pub fn get_s3_credentials() -> Option<(String, String)> {
+    let access_key_id = get_setting("WASMIOT_S3_ACCESS_KEY_ID").filter(|s| !s.is_empty())?;
+    let secret_access_key = get_setting("WASMIOT_S3_SECRET_ACCESS_KEY").filter(|s| !s.is_empty())?;
+    Some((access_key_id, secret_access_key))
+}
+
+/// Helper function to get the region of the S3 result sinks of deployments from env
+pub fn get_s3_region() -> String {
+    get_setting("WASMIOT_S3_REGION")
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| DEFAULT_S3_REGION.to_string())
+}
+
+/// Helper function to get how many times a failed upload to a result sink is retried from env
+pub fn get_s3_upload_retries() -> u32 {
+    get_setting("WASMIOT_S3_UPLOAD_RETRIES")
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_S3_UPLOAD_RETRIES)
+}
+
+/// Helper function to get the path of the Unix domain socket the HTTP server also listens on from env, if set
+pub fn get_unix_socket_path() -> Option<PathBuf> {
+    get_setting("WASMIOT_UNIX_SOCKET").filter(|s| !s.is_empty()).map(PathBuf::from)
+}
+
+/// Helper function to get the permissions of the Unix domain socket from env, given in octal
+pub fn get_unix_socket_mode() -> u32 {
+    get_setting("WASMIOT_UNIX_SOCKET_MODE")
+        .and_then(|s| u32::from_str_radix(&s, 8).ok())
+        .filter(|&mode| mode <= 0o777)
+        .unwrap_or(DEFAULT_UNIX_SOCKET_MODE)
+}
+
+/// Helper function to get from env whether clients of the Unix domain socket skip the checks of requests over the network
+pub fn get_unix_socket_trusted() -> bool {
+    get_setting("WASMIOT_UNIX_SOCKET_TRUSTED")
+        .map(|s| s == "true")
+        .unwrap_or(false)
+}
+
 pub const DEFAULT_SERVICE_RENEWAL_TIME: i64 = 900;  // 15 minutes in seconds
 
 pub(crate) static SYSTEM: Lazy<Mutex<System>> = Lazy::new(|| Mutex::new(System::new_all()));
 pub(crate) static NETWORKS: Lazy<Mutex<Networks>> = Lazy::new(|| Mutex::new(Networks::new_with_refreshed_list()));
 pub(crate) static DISKS: Lazy<Mutex<Disks>> = Lazy::new(|| Mutex::new(Disks::new_with_refreshed_list()));
+/// HTTP client shared by everything the supervisor downloads, so connections can be reused.
+pub static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+pub(crate) static COMPONENTS: Lazy<Mutex<Components>> = Lazy::new(|| Mutex::new(Components::new_with_refreshed_list()));
 
 /// Default timeout for module execution in seconds
-pub const DEFAULT_MODULE_TIMEOUT_SECONDS: u64 = 10;
\ No newline at end of file
+pub const DEFAULT_MODULE_TIMEOUT_SECONDS: u64 = 10;
+
+/// Default `Cache-Control: max-age` in seconds for the `.well-known` description endpoints
+pub const DEFAULT_DESCRIPTION_MAX_AGE_SECONDS: u64 = 60;
+
+/// Default (degraded, critical) health thresholds for CPU usage as a fraction of total
+pub const DEFAULT_CPU_THRESHOLDS: (f32, f32) = (0.85, 0.95);
+
+/// Default (degraded, critical) health thresholds for memory usage as a fraction of total
+pub const DEFAULT_MEMORY_THRESHOLDS: (f32, f32) = (0.85, 0.95);
+
+/// Default (degraded, critical) health thresholds for usage of the instance filesystem as a fraction of total
+pub const DEFAULT_DISK_THRESHOLDS: (f32, f32) = (0.90, 0.97);
+
+/// Default (degraded, critical) health thresholds for the hottest temperature sensor in Celsius
+pub const DEFAULT_TEMPERATURE_THRESHOLDS: (f32, f32) = (70.0, 80.0);
+
+/// Default (degraded, critical) health thresholds for the number of log messages waiting to be sent
+pub const DEFAULT_LOG_QUEUE_THRESHOLDS: (f32, f32) = (100.0, 1000.0);
+
+/// Default interval in seconds between health history samples
+pub const DEFAULT_HEALTH_SAMPLE_INTERVAL_SECONDS: u64 = 60;
+
+/// Default interval in milliseconds between refreshes of the system metrics of the health endpoint
+pub const DEFAULT_HEALTH_REFRESH_INTERVAL_MS: u64 = 2000;
+
+/// Default number of samples kept in the health history (a day at the default interval)
+pub const DEFAULT_HEALTH_HISTORY_SIZE: usize = 1440;
+
+/// Default number of threads running Wasm functions
+#[cfg(not(feature = "armv6"))]
+pub const DEFAULT_EXECUTION_THREADS: usize = 2;
+
+/// Default number of threads running Wasm functions on armv6 devices, which have a single core
+#[cfg(feature = "armv6")]
+pub const DEFAULT_EXECUTION_THREADS: usize = 1;
+
+/// Default time in seconds running executions are waited for on shutdown
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+/// Default number of deployment artifacts downloaded at the same time
+pub const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 4;
+
+/// Default time limit in seconds for downloading all artifacts of a deployment
+pub const DEFAULT_DEPLOYMENT_DOWNLOAD_TIMEOUT_SECONDS: u64 = 600;
+
+/// Default number of times a failed artifact download is retried
+pub const DEFAULT_DOWNLOAD_RETRIES: u32 = 3;
+
+/// Default largest allowed size of a single deployment file (512 MiB)
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Default largest allowed total size of a deployment's files (1 GiB)
+pub const DEFAULT_MAX_DEPLOYMENT_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Default number of bytes kept free on the instance filesystem when deploying (64 MiB)
+pub const DEFAULT_DISK_RESERVE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Default interval for looking for expired deployments
+pub const DEFAULT_EXPIRY_CHECK_INTERVAL_SECONDS: u64 = 30;
+
+/// Default time an expired deployment is still reported as expired instead of not found (1 day)
+pub const DEFAULT_EXPIRED_DEPLOYMENT_GRACE_SECONDS: u64 = 24 * 60 * 60;
+
+/// Default age after which orphaned module and params folders are removed (1 hour)
+pub const DEFAULT_GC_GRACE_SECONDS: u64 = 60 * 60;
+
+/// Default age after which execution outputs are removed (1 week)
+pub const DEFAULT_RESULT_MAX_AGE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Default cap on the total size of execution outputs (1 GiB)
+pub const DEFAULT_RESULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Default cap on the size of execution outputs of one deployment (no cap)
+pub const DEFAULT_RESULT_MAX_DEPLOYMENT_BYTES: u64 = 0;
+
+/// Default interval for enforcing the result retention policy (10 minutes)
+pub const DEFAULT_RESULT_CLEANUP_INTERVAL_SECONDS: u64 = 10 * 60;
+
+/// Default time the outputs of recent requests are preferred when results are over their caps (1 hour)
+pub const DEFAULT_REQUEST_HISTORY_RETENTION_SECONDS: u64 = 60 * 60;
+
+/// Default number of requests kept in the in-memory request history
+pub const DEFAULT_REQUEST_HISTORY_MAX_ENTRIES: usize = 1000;
+
+/// Default size below which output files are included in execution responses (32 KiB)
+pub const DEFAULT_INLINE_RESULT_MAX_BYTES: u64 = 32 * 1024;
+
+/// Default time signed result file URLs stay valid (1 hour)
+pub const DEFAULT_RESULT_URL_TTL_SECONDS: u64 = 60 * 60;
+
+/// Default cap on the size of outputs of chained calls copied to this device (64 MiB)
+pub const DEFAULT_CHAIN_MIRROR_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Default first level of the MQTT topics of the supervisor
+pub const DEFAULT_MQTT_TOPIC_PREFIX: &str = "wasmiot";
+
+/// Default MQTT keep alive interval in seconds
+pub const DEFAULT_MQTT_KEEP_ALIVE_SECONDS: u16 = 30;
+
+/// Default largest block of a CoAP block-wise transfer
+pub const DEFAULT_COAP_BLOCK_SIZE: usize = 512;
+
+/// Default number of times a failed execution callback is retried
+pub const DEFAULT_CALLBACK_RETRIES: u32 = 3;
+
+/// Default time limit in seconds of one attempt to deliver an execution callback
+pub const DEFAULT_CALLBACK_TIMEOUT_SECONDS: u64 = 10;
+
+/// Default time limit in seconds of the healthcheck of a module run when it is deployed
+pub const DEFAULT_HEALTHCHECK_TIMEOUT_SECONDS: u64 = 10;
+
+/// Default interval for checking and syncing the standby replicas of deployments
+pub const DEFAULT_REPLICA_SYNC_INTERVAL_SECONDS: u64 = 30;
+
+/// Default share of traces started here that are exported
+pub const DEFAULT_OTEL_SAMPLING_RATIO: f64 = 1.0;
+
+/// Default region of the S3 result sinks of deployments
+pub const DEFAULT_S3_REGION: &str = "us-east-1";
+
+/// Default number of times a failed upload to a result sink is retried
+pub const DEFAULT_S3_UPLOAD_RETRIES: u32 = 3;
+
+/// Default permissions of the Unix domain socket, for its owner and group
+pub const DEFAULT_UNIX_SOCKET_MODE: u32 = 0o660;
+
+/// Default interval in seconds between pushes of metrics to Telegraf
+pub const DEFAULT_TELEGRAF_INTERVAL_SECONDS: u64 = 10;
+
+/// Default size in bytes the audit log is rotated at (10 MiB)
+pub const DEFAULT_AUDIT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Default number of rotated audit log files kept
+pub const DEFAULT_AUDIT_MAX_FILES: usize = 5;
+
+/// Default most deployments on the device (no cap)
+pub const DEFAULT_MAX_DEPLOYMENTS: usize = 0;
+
+/// Default most modules in all deployments on the device (no cap)
+pub const DEFAULT_MAX_MODULES: usize = 0;
+
+/// Default cap on the size of the artifacts of all deployments (no cap)
+pub const DEFAULT_MAX_ARTIFACT_BYTES: u64 = 0;
+
+/// Default most requests waiting in the durable execution queue
+pub const DEFAULT_EXECUTION_QUEUE_MAX_DEPTH: usize = 1000;
+
+/// Default most examples captured for the endpoint of each function
+pub const DEFAULT_ENDPOINT_EXAMPLES: usize = 3;
@@ -18,108 +18,56 @@ use std::fmt::Debug;
 use std::str::FromStr;
 use std::fs::File;
 use std::fs;
-use serde_json::Value;
+use serde_json::{json, Value};
 use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
 use log::{error, warn};
 use serde_json::Map;
 use std::iter::Iterator;
 use strum_macros::{EnumString, AsRefStr};
 use wasmtime::{Val, ValType};
+use wasmtime::component::{Type as ComponentType, Val as ComponentVal};
 use crate::lib::constants::{PARAMS_FOLDER, FILE_TYPES};
-use crate::lib::wasmtime::{WasmtimeRuntime, WasmtimeModule, ModuleConfig};
+use crate::lib::wasmtime::{component_type_name, function_signatures, is_component, WasmtimeRuntime, WasmtimeModule, ModuleConfig, MountLayout};
+use crate::lib::result_sink::ResultSink;
 use indexmap::IndexMap;
-
-/// Represents the lifecycle stage at which a file is mounted into a module's execution context.
-///
-/// Mounts can occur during:
-/// - `DEPLOYMENT`: Static data available before execution.
-/// - `EXECUTION`: Runtime input files (e.g. POSTed by client).
-/// - `OUTPUT`: Expected output paths generated by the module.
-/// - `UNKNOWN`: Fallback when parsing fails.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, AsRefStr, Serialize, Deserialize)]
-pub enum MountStage {
-    #[strum(serialize = "deployment")]
-    #[serde(rename = "deployment")]
-    DEPLOYMENT,
-    #[strum(serialize = "execution")]
-    #[serde(rename = "execution")]
-    EXECUTION,
-    #[strum(serialize = "output")]
-    #[serde(rename = "output")]
-    OUTPUT,
-    #[strum(serialize = "unknown")]
-    #[serde(rename = "unknown")]
-    UNKNOWN,
-}
-
-impl From<String> for MountStage {
-    fn from(s: String) -> Self {
-        MountStage::from_str(&s).unwrap_or_else(|_| {
-            error!("Invalid Stage: '{}', defaulting to UNKNOWN", s);
-            MountStage::UNKNOWN
-        })
-    }
-}
-
-/// Represents a file path and its metadata, used for mounting into a Wasm module.
-///
-/// Each mount includes:
-/// - `path`: Virtual mount path inside module
-/// - `media_type`: MIME type (e.g. `image/png`)
-/// - `stage`: Lifecycle stage this file is used in
-/// - `required`: Whether the file is mandatory
-/// - `encoding`: Data encoding (e.g. `base64`)
-/// - `_type`: Type name (e.g. `string`, `binary`)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MountPathFile {
+use crate::structs::deployment_supervisor::{parse_instructions, parse_mounts};
+use crate::structs::request_entry::{Priority, RequestEntry};
+pub use crate::structs::deployment_supervisor::{
+    FunctionLink, FunctionLinkMap, FunctionMountMap, ModuleLinkMap, ModuleMountMap, MountPathFile, MountStage, MountStageMap,
+};
+
+
+/// A mount of a function resolved to the file it is given, see `Deployment::resolve_mounts`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedMount {
+    /// Mount path declared in the manifest
     pub path: String,
-    pub media_type: String,
     pub stage: MountStage,
-    #[serde(default = "default_required")]
-    pub required: bool,
-    #[serde(default = "default_encoding")]
-    pub encoding: String,
-    #[serde(default = "default_type")]
-    pub r#type: String,
-}
-
-impl MountPathFile {
-    /// Constructs a new `MountPathFile` with default fallbacks for optional fields.
-    pub fn new<S: Into<MountStage>>(
-        path: String,
-        media_type: String,
-        stage: S,
-        required: Option<bool>,
-        encoding: Option<String>,
-        r#type: Option<String>,
-    ) -> Self {
-        MountPathFile {
-            path,
-            media_type,
-            stage: stage.into(),
-            required: required.unwrap_or(true),
-            encoding: encoding.unwrap_or_else(|| "base64".to_string()),
-            r#type: r#type.unwrap_or_else(|| "string".to_string()),
-        }
-    }
-
-    /// Placeholder for validation logic (currently a passthrough).
-    ///
-    /// TODO: Determine if this function is necessary or should be removed.
-    pub fn validate(&self, x: HashMap<String, Value>) -> HashMap<String, Value> {
-        x
-    }
+    /// File the mount is given from
+    pub source: PathBuf,
+    /// Where the file is put for the module on the host
+    pub host_path: PathBuf,
+    /// Where the module finds the file
+    pub guest_path: String,
 }
 
-// Helper default functions for serde
-fn default_required() -> bool {
-    true
-}
-fn default_encoding() -> String {
-    "base64".to_string()
-}
-fn default_type() -> String {
-    "string".to_string()
+/// What `Deployment::validate_call` found out about running a function.
+#[derive(Debug, Clone, Serialize)]
+pub struct CallValidation {
+    /// Whether the function would be run
+    pub valid: bool,
+    /// Why the function would not be run, or would not be run as asked
+    pub problems: Vec<String>,
+    /// The arguments converted to the types of the parameters of the function
+    pub args: Vec<Value>,
+    /// Types of the results of the function
+    pub results: Vec<String>,
+    /// Files given to the function
+    pub mounts: Vec<ResolvedMount>,
+    /// Endpoint the result would be sent to next in the chain
+    pub next: Option<Value>,
 }
 
 /// Represents the high-level type of a schema field (OpenAPI-compatible).
@@ -212,6 +160,13 @@ impl Schema {
     }
 }
 
+impl Default for Schema {
+    /// A schema of unknown type, for media types declared without one.
+    fn default() -> Self {
+        Schema::new(SchemaType::UNKNOWN, None, None)
+    }
+}
+
 impl From<HashMap<String, Value>> for Schema {
     /// Parses a schema from a raw JSON map (typically from deserialized input).
     ///
@@ -237,10 +192,24 @@ pub struct MediaTypeObject {
     pub media_type: String,
 
     /// Schema describing the payload structure
+    #[serde(default)]
     pub schema: Schema,
 
     /// Optional encoding hints (e.g. base64, gzip, etc.)
     pub encoding: Option<HashMap<String, HashMap<String, String>>>,
+
+    /// Where the result of a function comes from, for responses. Without it the result is the
+    /// value the function returns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ResultSource>,
+}
+
+/// Where the result of a function comes from, besides the value it returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultSource {
+    /// The function returns a pointer and a length (two `i32`) of a UTF-8 string in its memory.
+    Memory,
 }
 
 impl MediaTypeObject {
@@ -254,6 +223,7 @@ impl MediaTypeObject {
             media_type,
             schema: schema.into(),
             encoding,
+            source: None,
         }
     }
 }
@@ -288,6 +258,11 @@ pub struct EndpointRequest {
 
     /// Optional body schema for POST requests or structured inputs.
     pub request_body: Option<MediaTypeObject>,
+
+    /// Whether arguments not declared in `parameters` are passed to the function after the
+    /// declared ones, instead of being refused.
+    #[serde(rename = "additionalParameters", default)]
+    pub additional_parameters: bool,
 }
 
 impl EndpointRequest {
@@ -302,7 +277,96 @@ impl EndpointRequest {
         EndpointRequest {
             parameters: parameters.into(),
             request_body: body_option,
+            additional_parameters: false,
+        }
+    }
+
+    /// Matches the arguments of a call to the declared `parameters` by their `name`, whatever
+    /// order they were given in, and coerces each to the `type` and `format` of its `schema`
+    /// (see `coerce_parameter`).
+    ///
+    /// Parameters left out are refused if `required`, and passed as the `default` of their
+    /// schema, or zero, otherwise. Arguments that match no parameter are refused unless
+    /// `additionalParameters` is set. Without declared parameters, the arguments are passed as
+    /// they were given.
+    ///
+    /// # Returns
+    /// The arguments in the order of the parameters, or why they do not fit them.
+    pub fn bind_arguments(&self, args: &IndexMap<String, Value>) -> Result<IndexMap<String, Value>, String> {
+        if self.parameters.is_empty() {
+            return Ok(args.clone());
+        }
+        let mut bound = IndexMap::new();
+        for parameter in &self.parameters {
+            let Some(name) = parameter.get("name").and_then(Value::as_str) else { continue };
+            let schema = parameter.get("schema").cloned().unwrap_or(Value::Null);
+            let value = match args.get(name) {
+                Some(value) => coerce_parameter(name, value, &schema)?,
+                None if parameter.get("required").and_then(Value::as_bool).unwrap_or(false) => {
+                    return Err(format!("Missing required parameter '{}'", name));
+                }
+                None => match schema.get("default") {
+                    Some(default) => coerce_parameter(name, default, &schema)?,
+                    None => json!(0),
+                },
+            };
+            bound.insert(name.to_string(), value);
+        }
+
+        let unknown: Vec<&String> = args.keys().filter(|name| !bound.contains_key(*name)).collect();
+        if !unknown.is_empty() && !self.additional_parameters {
+            let mut unknown: Vec<&str> = unknown.iter().map(|name| name.as_str()).collect();
+            unknown.sort();
+            return Err(format!("Unknown parameters: {}", unknown.join(", ")));
+        }
+        for name in unknown {
+            bound.insert(name.clone(), args[name].clone());
+        }
+        Ok(bound)
+    }
+}
+
+/// Coerces an argument to the `type` and `format` of the schema of its parameter: `int32`
+/// and `int64` integers, `float` and `double` numbers, and booleans as 1 or 0. Numbers may
+/// also be given as strings, as query parameters are. Arguments of other types are passed
+/// as they are.
+fn coerce_parameter(name: &str, value: &Value, schema: &Value) -> Result<Value, String> {
+    let typ = schema.get("type").and_then(Value::as_str).unwrap_or_default();
+    let format = schema.get("format").and_then(Value::as_str).unwrap_or_default();
+    let invalid = |what: &str| format!("Parameter '{}' must be {} but is {}", name, what, value);
+    let integer = || match value {
+        Value::Number(num) => num.as_i64(),
+        Value::String(s) => s.trim().parse::<i64>().ok(),
+        _ => None,
+    };
+    let number = || match value {
+        Value::Number(num) => num.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+        _ => None,
+    };
+    match (typ, format) {
+        (_, "int32") => integer()
+            .and_then(|n| i32::try_from(n).ok())
+            .map(|n| json!(n))
+            .ok_or_else(|| invalid("a 32-bit integer")),
+        ("integer", _) | (_, "int64") => integer()
+            .map(|n| json!(n))
+            .ok_or_else(|| invalid("a 64-bit integer")),
+        (_, "float") => number()
+            .filter(|n| (*n as f32).is_finite())
+            .map(|n| json!(n))
+            .ok_or_else(|| invalid("a 32-bit float")),
+        ("number", _) | (_, "double") => number()
+            .map(|n| json!(n))
+            .ok_or_else(|| invalid("a 64-bit float")),
+        ("boolean", _) => match value {
+            Value::Bool(b) => Some(*b),
+            Value::String(s) => s.trim().parse::<bool>().ok(),
+            _ => None,
         }
+            .map(|b| json!(b as i32))
+            .ok_or_else(|| invalid("a boolean")),
+        _ => Ok(value.clone()),
     }
 }
 
@@ -343,6 +407,65 @@ pub struct Endpoint {
 
     /// Response schema including type and optional encoding.
     pub response: EndpointResponse,
+
+    /// Keeps the input files of each request in its inputs folder after the execution, for debugging.
+    #[serde(rename = "keepInputs", default)]
+    pub keep_inputs: bool,
+
+    /// Accepts parts of multipart uploads that match no execution stage mount, which are
+    /// dropped, instead of refusing the request.
+    #[serde(rename = "allowExtraFiles", default)]
+    pub allow_extra_files: bool,
+
+    /// Priority of requests to the function that do not send `X-Priority`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+
+    /// How requests to the function are handled while every execution thread is busy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queueing: Option<Queueing>,
+
+    /// Captures the first successful calls to the function as examples of it. Turned off for
+    /// functions whose arguments or results must not be kept.
+    #[serde(rename = "captureExamples", default = "default_capture_examples")]
+    pub capture_examples: bool,
+}
+
+fn default_capture_examples() -> bool {
+    true
+}
+
+/// Largest size of the arguments or the result of an example as JSON, in bytes.
+const EXAMPLE_MAX_BYTES: usize = 1024;
+
+/// A successful call to the endpoint of a function, captured as an example of what calling it
+/// looks like (see `Deployment::record_example`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointExample {
+    /// Arguments of the call, with the secrets of the module redacted.
+    pub args: Value,
+    /// Result of the call.
+    pub result: Value,
+    /// Names of the output files of the call.
+    pub outputs: Vec<String>,
+    /// Whether the arguments or the result were cut at `EXAMPLE_MAX_BYTES`, replacing them with
+    /// the start of their JSON.
+    pub truncated: bool,
+    #[serde(rename = "capturedAt")]
+    pub captured_at: DateTime<Utc>,
+}
+
+/// How requests to a function are handled while every execution thread is busy (see
+/// `durable_queue.rs`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Queueing {
+    /// The request waits for a thread and is answered once it has been run.
+    #[default]
+    Immediate,
+    /// The request is queued on disk and answered with 202 at once, and run in order as
+    /// threads free up, also after a restart.
+    Durable,
 }
 
 impl Endpoint {
@@ -364,6 +487,11 @@ impl Endpoint {
             method,
             request: request.into(),
             response: response.into(),
+            keep_inputs: false,
+            allow_extra_files: false,
+            priority: None,
+            queueing: None,
+            capture_examples: true,
         }
     }
 
@@ -496,13 +624,17 @@ pub struct CallData {
     pub method: String,
     /// Output files from the previous function call to be sent with this one.
     pub files: EndpointData,
+    /// JSON body to send instead of a form, when the result is a string and the endpoint
+    /// declares a JSON request body.
+    pub body: Option<Value>,
 }
 
 impl CallData {
     /// Builds a `CallData` instance from a given `Endpoint`, arguments, and output files.
     ///
     /// Arguments are converted into query parameters depending on their type:
-    /// - `Str` → single query string
+    /// - `Str` → single query string, or a JSON body with the first parameter if the endpoint
+    ///   declares an `application/json` request body
     /// - `StrList` → sequential param-value pairs
     /// - `Dict` → key-value mapping to query parameters
     pub fn from_endpoint(
@@ -512,6 +644,7 @@ impl CallData {
     ) -> Self {
         let mut url = format!("{}{}", endpoint.url.trim_end_matches('/'), endpoint.path);
         let headers = HashMap::new();
+        let mut body = None;
 
         if let Some(args) = args {
             let query = match args {
@@ -520,7 +653,14 @@ impl CallData {
                         .and_then(|p| p.get("name"))
                         .and_then(|n| n.as_str())
                         .unwrap_or("param");
-                    format!("?{}={}", param_name, s)
+                    let json_body = endpoint.request.request_body.as_ref()
+                        .is_some_and(|body| body.media_type == "application/json");
+                    if json_body {
+                        body = Some(json!({ param_name: s }));
+                        String::new()
+                    } else {
+                        format!("?{}={}", param_name, urlencoding::encode(&s))
+                    }
                 }
                 EndpointArgs::StrList(lst) => {
                     let params: Vec<String> = endpoint.request.parameters.iter()
@@ -569,58 +709,11 @@ impl CallData {
             headers,
             method,
             files: file_list,
+            body,
         }
     }
 }
 
-/// Represents a link (call chain) between two Wasm function endpoints.
-///
-/// Used in instruction graphs to describe function chaining behavior.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct FunctionLink {
-    /// The source function that triggers the next one.
-    pub from: Endpoint,
-    /// The destination function (optional if terminal call).
-    pub to: Option<Endpoint>,
-}
-
-impl FunctionLink {
-    /// Constructs a `FunctionLink` from two endpoints.
-    pub fn new(from: Endpoint, to: Option<Endpoint>) -> Self {
-        FunctionLink { from, to }
-    }
-}
-
-impl From<HashMap<String, Value>> for FunctionLink {
-    /// Attempts to deserialize a `FunctionLink` from a JSON-style map.
-    ///
-    /// The map must contain at least a `"from_"` key with valid `Endpoint` data.
-    /// If `from_` is missing or invalid, a fallback default is used and logged.
-    fn from(map: HashMap<String, Value>) -> Self {
-        let from = map.get("from_")
-            .and_then(|v| serde_json::from_value::<Endpoint>(v.clone()).ok())
-            .unwrap_or_else(|| {
-                error!("Invalid or missing 'from_' field in FunctionLink");
-                Endpoint::new(
-                    "".to_string(),
-                    "".to_string(),
-                    "".to_string(),
-                    EndpointRequest::new(vec![], None),
-                    EndpointResponse::new(
-                        "application/octet-stream".to_string(),
-                        Schema::new(SchemaType::UNKNOWN, None, None),
-                        None,
-                    ),
-                )
-            });
-
-        let to = map.get("to")
-            .and_then(|v| serde_json::from_value::<Endpoint>(v.clone()).ok());
-
-        FunctionLink { from, to }
-    }
-}
-
 // --------------------------------------------------------------------------------------------------
 // Type Aliases - Simplify nested map structures representing modules and their metadata.
 // --------------------------------------------------------------------------------------------------
@@ -631,21 +724,6 @@ pub type FunctionEndpointMap = HashMap<String, Endpoint>;
 /// Maps module names to function endpoint maps.
 pub type ModuleEndpointMap = HashMap<String, FunctionEndpointMap>;
 
-/// Maps function names to how they are linked to other functions.
-pub type FunctionLinkMap = HashMap<String, FunctionLink>;
-
-/// Maps module names to function link maps (i.e. call graphs).
-pub type ModuleLinkMap = HashMap<String, FunctionLinkMap>;
-
-/// Maps mount stages to mount path definitions.
-pub type MountStageMap = HashMap<MountStage, Vec<MountPathFile>>;
-
-/// Maps function names to stage-based mount definitions.
-pub type FunctionMountMap = HashMap<String, MountStageMap>;
-
-/// Maps module names to their function mount definitions.
-pub type ModuleMountMap = HashMap<String, FunctionMountMap>;
-
 /// Represents a complete WebAssembly deployment, including all modules, runtimes,
 /// mount definitions, and function call graphs.
 ///
@@ -671,23 +749,179 @@ pub struct Deployment {
     /// HTTP endpoints defined for each function in each module.
     pub endpoints: ModuleEndpointMap,
 
-    /// Raw instruction data (usually parsed into `instructions`).
-    pub _instructions: HashMap<String, Value>,
-
-    /// Raw mount data (usually parsed into `mounts`).
-    pub _mounts: HashMap<String, Value>,
-
     /// Parsed module configs by name.
     #[serde(skip)]
     pub modules: HashMap<String, ModuleConfig>,
 
-    /// Parsed call graph of module functions.
-    #[serde(skip)]
+    /// Call graph of module functions, parsed from `instructions` of the manifest.
+    #[serde(default)]
     pub instructions: ModuleLinkMap,
 
-    /// Parsed mapping of all mount paths for all functions.
-    #[serde(skip)]
+    /// Mount paths of all functions by stage, parsed from `mounts` of the manifest.
+    #[serde(default)]
     pub mounts: ModuleMountMap,
+
+    /// Raw instructions as saved by earlier versions, parsed into `instructions` by `init`.
+    #[serde(default, rename = "_instructions", skip_serializing)]
+    legacy_instructions: Option<Value>,
+
+    /// Raw mounts as saved by earlier versions, parsed into `mounts` by `init`.
+    #[serde(default, rename = "_mounts", skip_serializing)]
+    legacy_mounts: Option<Value>,
+
+    /// Secrets of each module, given to the module as environment variables.
+    /// Held only in memory: never saved to disk nor returned by the API.
+    #[serde(skip)]
+    pub secrets: HashMap<String, HashMap<String, SecretValue>>,
+
+    /// Names of the secrets each module was deployed with. Persisted so that after a restart
+    /// the modules waiting for their secrets to be supplied again can be told apart.
+    #[serde(default)]
+    pub secret_names: HashMap<String, Vec<String>>,
+
+    /// When the deployment expires and is removed automatically, if ever.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Whether the deployment accepts executions. Paused deployments keep their files.
+    #[serde(default = "default_active")]
+    pub active: bool,
+
+    /// Whether output files of chained calls to other devices are copied to this device
+    /// (see `do_wasm_work`), so that they can be fetched from here without another hop.
+    #[serde(default = "default_mirror_chained_results")]
+    pub mirror_chained_results: bool,
+
+    /// Functions that may be executed over MQTT (see `mqtt.rs`), as `module/function`,
+    /// `module/*` or `*`. None by default.
+    #[serde(default)]
+    pub mqtt_functions: Vec<String>,
+
+    /// Hosts that requests to run a function may ask for a callback to (see `callback.rs`),
+    /// as `host` or `host:port`. None by default.
+    #[serde(default)]
+    pub callback_hosts: Vec<String>,
+
+    /// Base URLs of the devices the target URLs of the instructions name in placeholders, by
+    /// name (see `device_registry.rs`). None by default.
+    #[serde(default)]
+    pub devices: HashMap<String, String>,
+
+    /// Object storage the outputs of executions are uploaded to (see `result_sink.rs`), if any.
+    #[serde(default)]
+    pub result_sink: Option<ResultSink>,
+
+    /// Supervisors kept holding a standby copy of the deployment (see `replication.rs`), as
+    /// base URLs. None by default.
+    #[serde(default)]
+    pub replicas: Vec<String>,
+
+    /// Whether the deployment is a standby copy pushed by another supervisor, held paused
+    /// until it is promoted.
+    #[serde(default)]
+    pub standby: bool,
+
+    /// Artifacts found missing or corrupted when the deployment was loaded at startup
+    /// that could not be restored. A deployment with any of these is degraded.
+    #[serde(skip_deserializing)]
+    pub missing_files: Vec<String>,
+
+    /// Modules whose healthcheck failed when the deployment was created, with why. They can
+    /// still be run, but should not be relied on.
+    #[serde(default)]
+    pub degraded_modules: HashMap<String, String>,
+
+    /// Modules that failed to load when the deployment was created or loaded at startup, with
+    /// why. They cannot run until the deployment is deployed again with the modules fixed.
+    #[serde(rename = "loadErrors", skip_deserializing)]
+    pub load_errors: HashMap<String, String>,
+
+    /// Examples of successful calls to each function, by module and function name, captured
+    /// from the first executions of their endpoints.
+    #[serde(default)]
+    pub examples: HashMap<String, HashMap<String, Vec<EndpointExample>>>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+fn default_mirror_chained_results() -> bool {
+    true
+}
+
+/// Self-test of a module, declared as `healthcheck` of the module in the manifest and run right
+/// after the deployment is created (see `run_healthcheck` in `api.rs`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Healthcheck {
+    pub function: String,
+    /// Arguments of the function, in order
+    #[serde(default)]
+    pub args: Vec<Value>,
+    /// Value the function must return, or anything if missing
+    #[serde(default)]
+    pub expect: Option<Value>,
+}
+
+/// What a failed healthcheck does to a deployment, from `healthcheckPolicy` of the manifest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthcheckPolicy {
+    /// The module is marked degraded and the deployment is created
+    #[default]
+    Degrade,
+    /// The deployment fails
+    Fail,
+}
+
+/// Replaces the strings of a value that contain any of `secrets` with `<redacted>`.
+fn redact_secret_values(value: Value, secrets: &[&str]) -> Value {
+    match value {
+        Value::String(s) if secrets.iter().any(|secret| s.contains(secret)) => json!("<redacted>"),
+        Value::Array(values) => Value::Array(values.into_iter().map(|value| redact_secret_values(value, secrets)).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter()
+            .map(|(key, value)| (key, redact_secret_values(value, secrets)))
+            .collect()),
+        other => other,
+    }
+}
+
+/// A value of an example, or the start of its JSON if it is over `EXAMPLE_MAX_BYTES`.
+///
+/// # Returns
+/// The value and whether it was cut.
+fn capped_example_value(value: Value) -> (Value, bool) {
+    let text = value.to_string();
+    if text.len() <= EXAMPLE_MAX_BYTES {
+        return (value, false);
+    }
+    let mut end = EXAMPLE_MAX_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (Value::String(format!("{}...", &text[..end])), true)
+}
+
+/// A secret value that is kept out of `Debug` output and therefore out of logs.
+#[derive(Clone)]
+pub struct SecretValue(String);
+
+impl SecretValue {
+    pub fn new(value: String) -> Self {
+        SecretValue(value)
+    }
+
+    /// Returns the actual secret. Only to be used when handing it to the module.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for SecretValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
 }
 
 impl Deployment {
@@ -704,84 +938,271 @@ impl Deployment {
         runtimes: HashMap<String, WasmtimeRuntime>,
         module_configs: Vec<ModuleConfig>,
         endpoints: ModuleEndpointMap,
-        instructions: HashMap<String, Value>,
-        mounts: HashMap<String, Value>,
+        instructions: ModuleLinkMap,
+        mounts: ModuleMountMap,
     ) -> Self {
         let mut this = Deployment {
             id,
             runtimes,
             _modules: module_configs,
             endpoints,
-            _instructions: instructions,
-            _mounts: mounts,
             modules: HashMap::new(),
-            instructions: HashMap::new(),
-            mounts: HashMap::new(),
+            instructions,
+            mounts,
+            legacy_instructions: None,
+            legacy_mounts: None,
+            secrets: HashMap::new(),
+            secret_names: HashMap::new(),
+            expires_at: None,
+            active: true,
+            mirror_chained_results: true,
+            mqtt_functions: Vec::new(),
+            callback_hosts: Vec::new(),
+            devices: HashMap::new(),
+            result_sink: None,
+            replicas: Vec::new(),
+            standby: false,
+            missing_files: Vec::new(),
+            degraded_modules: HashMap::new(),
+            load_errors: HashMap::new(),
+            examples: HashMap::new(),
         };
         this.init();
         this
     }
 
+    /// Sets the secrets of the modules, remembering their names for after a restart.
+    pub fn set_secrets(&mut self, secrets: HashMap<String, HashMap<String, SecretValue>>) {
+        self.secret_names = secrets
+            .iter()
+            .filter(|(_, module_secrets)| !module_secrets.is_empty())
+            .map(|(module_name, module_secrets)| {
+                let mut names: Vec<String> = module_secrets.keys().cloned().collect();
+                names.sort();
+                (module_name.clone(), names)
+            })
+            .collect();
+        self.secrets = secrets;
+    }
+
+    /// Captures a successful execution as an example of the endpoint of its function, unless the
+    /// endpoint has `captureExamples` turned off or already has `max_examples` of them. Secrets
+    /// of the module are redacted from the arguments, as they are when logged.
+    ///
+    /// # Returns
+    /// Whether the example was captured.
+    pub fn record_example(&mut self, entry: &RequestEntry, max_examples: usize) -> bool {
+        let captures = self.endpoints.get(&entry.module_name)
+            .and_then(|functions| functions.get(&entry.function_name))
+            .is_some_and(|endpoint| endpoint.capture_examples);
+        let captured = self.examples.get(&entry.module_name)
+            .and_then(|functions| functions.get(&entry.function_name))
+            .map_or(0, Vec::len);
+        if !captures || captured >= max_examples {
+            return false;
+        }
+        let secrets: Vec<&str> = self.secrets.get(&entry.module_name)
+            .map(|secrets| secrets.values().map(SecretValue::expose).filter(|secret| !secret.is_empty()).collect())
+            .unwrap_or_default();
+        let (args, args_truncated) = capped_example_value(redact_secret_values(entry.request_args.clone(), &secrets));
+        let (result, result_truncated) = capped_example_value(entry.result.clone().unwrap_or(Value::Null));
+        let outputs = entry.outputs.iter()
+            .filter_map(|url| url.split('?').next()?.rsplit('/').next())
+            .map(str::to_string)
+            .collect();
+        self.examples
+            .entry(entry.module_name.clone())
+            .or_default()
+            .entry(entry.function_name.clone())
+            .or_default()
+            .push(EndpointExample {
+                args,
+                result,
+                outputs,
+                truncated: args_truncated || result_truncated,
+                captured_at: Utc::now(),
+            });
+        true
+    }
+
+    /// Whether artifacts of the deployment are missing, so that it cannot run.
+    pub fn is_degraded(&self) -> bool {
+        !self.missing_files.is_empty()
+    }
+
+    /// Priority of the requests to a function that do not ask for one, from its endpoint.
+    pub fn endpoint_priority(&self, module_name: &str, function_name: &str) -> Priority {
+        self.endpoints.get(module_name)
+            .and_then(|functions| functions.get(function_name))
+            .and_then(|endpoint| endpoint.priority)
+            .unwrap_or_default()
+    }
+
+    /// How the requests to a function are handled while every execution thread is busy, from
+    /// its endpoint.
+    pub fn endpoint_queueing(&self, module_name: &str, function_name: &str) -> Queueing {
+        self.endpoints.get(module_name)
+            .and_then(|functions| functions.get(function_name))
+            .and_then(|endpoint| endpoint.queueing)
+            .unwrap_or_default()
+    }
+
+    /// Mounts of a function that are given as the input files of each request, or `None` if
+    /// the manifest does not describe the mounts of the function.
+    pub fn execution_mounts(&self, module_name: &str, function_name: &str) -> Option<Vec<MountPathFile>> {
+        self.mounts.get(module_name)
+            .and_then(|functions| functions.get(function_name))
+            .map(|stages| stages.get(&MountStage::EXECUTION).cloned().unwrap_or_default())
+    }
+
+    /// Whether the endpoint of a function accepts uploaded files that match none of its mounts.
+    pub fn endpoint_allows_extra_files(&self, module_name: &str, function_name: &str) -> bool {
+        self.endpoints.get(module_name)
+            .and_then(|functions| functions.get(function_name))
+            .is_some_and(|endpoint| endpoint.allow_extra_files)
+    }
+
+    /// Where the result of a function comes from, from the response of its endpoint.
+    pub fn endpoint_result_source(&self, module_name: &str, function_name: &str) -> Option<ResultSource> {
+        self.endpoints.get(module_name)
+            .and_then(|functions| functions.get(function_name))
+            .and_then(|endpoint| endpoint.response.source)
+    }
+
+    /// Whether a function of the deployment may be executed over MQTT.
+    pub fn allows_mqtt(&self, module_name: &str, function_name: &str) -> bool {
+        self.mqtt_functions.iter().any(|allowed| match allowed.split_once('/') {
+            Some((module, function)) => module == module_name && (function == "*" || function == function_name),
+            None => allowed == "*",
+        })
+    }
+
+    /// Returns the modules that were deployed with secrets that are not currently held in
+    /// memory, e.g. after a restart. These cannot run until the secrets are supplied again.
+    pub fn modules_needing_secrets(&self) -> Vec<String> {
+        let mut modules: Vec<String> = self.secret_names
+            .iter()
+            .filter(|(module_name, names)| {
+                let held = self.secrets.get(*module_name);
+                names.iter().any(|name| !held.is_some_and(|secrets| secrets.contains_key(name)))
+            })
+            .map(|(module_name, _)| module_name.clone())
+            .collect();
+        modules.sort();
+        modules
+    }
+
     /// Initializes the deployment:
     /// - Maps `_modules` to the `modules` field.
-    /// - Parses raw `_mounts` into `MountPathFile`s for each function stage.
-    /// - Parses `_instructions` into a call graph of `FunctionLink`s.
+    /// - Parses `_instructions` and `_mounts` saved by earlier versions into `instructions` and
+    ///   `mounts`, which are otherwise parsed when the deployment is created.
     pub fn init(&mut self) {
         // Build module name → config map
         for m in &self._modules {
             self.modules.insert(m.name.clone(), m.clone());
         }
 
-        // Parse file mounts for each function and stage (DEPLOYMENT, EXECUTION, OUTPUT)
-        for (module_name, functions) in &self._mounts {
-            if let Value::Object(fn_map) = functions {
-                let mut function_mounts = HashMap::new();
-                for (function_name, stage_mounts) in fn_map {
-                    if let Value::Object(stage_map) = stage_mounts {
-                        let mut stage_mounts_parsed = HashMap::new();
-                        for (stage, mounts) in stage_map {
-                            if let Value::Array(mount_list) = mounts {
-                                let parsed_mounts: Vec<MountPathFile> = mount_list
-                                    .iter()
-                                    .filter_map(|m| serde_json::from_value::<MountPathFile>(m.clone()).ok())
-                                    .collect();
-                                stage_mounts_parsed.insert(MountStage::from(stage.clone()), parsed_mounts);
-                            }
-                        }
-                        function_mounts.insert(function_name.clone(), stage_mounts_parsed);
-                    }
-                }
-                self.mounts.insert(module_name.clone(), function_mounts);
+        if let Some(instructions) = self.legacy_instructions.take() {
+            match parse_instructions(Some(&instructions)) {
+                Ok(instructions) => self.instructions = instructions,
+                Err(e) => error!("Failed to parse instructions of deployment '{}': {}", self.id, e),
+            }
+        }
+        if let Some(mounts) = self.legacy_mounts.take() {
+            match parse_mounts(Some(&mounts)) {
+                Ok(mounts) => self.mounts = mounts,
+                Err(e) => error!("Failed to parse mounts of deployment '{}': {}", self.id, e),
             }
         }
+    }
+
+    /// Builds W3C WoT Thing Description actions for every endpoint in this deployment.
+    ///
+    /// Each action is keyed as `{deployment_id}/{module_name}/{function_name}` and contains:
+    /// - An `input` object schema built from the endpoint's declared parameters
+    /// - An `output` schema built from the endpoint's response declaration
+    /// - An `invokeaction` form pointing at the supervisor's execution route
+    /// - Forms to invoke it asynchronously and to query and cancel the action (see `actions.rs`)
+    pub fn wot_actions(&self) -> Map<String, Value> {
+        let mut actions = Map::new();
+        for (module_name, functions) in &self.endpoints {
+            for (function_name, endpoint) in functions {
+                let mut properties = Map::new();
+                let mut required: Vec<String> = Vec::new();
+                for param in &endpoint.request.parameters {
+                    let Some(name) = param.get("name").and_then(Value::as_str) else {
+                        continue;
+                    };
+                    let schema = param.get("schema").cloned().unwrap_or_else(|| json!({}));
+                    properties.insert(name.to_string(), schema);
+                    if param.get("required").and_then(Value::as_bool).unwrap_or(false) {
+                        required.push(name.to_string());
+                    }
+                }
 
-        // Parse instruction links (how to chain calls between modules/functions)
-        if let Some(Value::Object(modules)) = self._instructions.get("modules") {
-            for (module_name, functions) in modules {
-                if let Value::Object(fn_map) = functions {
-                    let mut function_links = HashMap::new();
-                    for (function_name, link) in fn_map {
-                        if let Value::Object(link_map) = link {
-                            let from = link_map.get("from")
-                                .and_then(|v| serde_json::from_value::<Endpoint>(v.clone()).ok());
-                            let to = link_map.get("to")
-                                .and_then(|v| serde_json::from_value::<Endpoint>(v.clone()).ok());
-                            if let Some(from_endpoint) = from {
-                                function_links.insert(
-                                    function_name.clone(),
-                                    FunctionLink { from: from_endpoint, to },
-                                );
-                            } else {
-                                error!("Skipping function link for {}/{}: Missing 'from' field", module_name, function_name);
-                            }
+                // Input files are described by the multipart request body, if any
+                let content_type = match &endpoint.request.request_body {
+                    Some(body) => {
+                        for (path, _schema) in get_supported_file_schemas(&body.schema, &body.encoding) {
+                            properties.insert(path, json!({ "type": "string", "contentMediaType": body.media_type }));
                         }
+                        body.media_type.clone()
                     }
-                    self.instructions.insert(module_name.clone(), function_links);
+                    None => "application/json".to_string(),
+                };
+
+                let mut output = json!({ "type": endpoint.response.schema.r#type.as_ref() });
+                if endpoint.response.media_type != "application/json" {
+                    output["contentMediaType"] = json!(endpoint.response.media_type);
                 }
+
+                actions.insert(
+                    format!("{}/{}/{}", self.id, module_name, function_name),
+                    json!({
+                        "title": function_name,
+                        "description": format!(
+                            "Function '{}' of module '{}' in deployment '{}'",
+                            function_name, module_name, self.id
+                        ),
+                        "input": {
+                            "type": "object",
+                            "properties": properties,
+                            "required": required,
+                        },
+                        "output": output,
+                        "uriVariables": {
+                            "actionId": { "type": "string" },
+                        },
+                        "forms": [
+                            {
+                                "op": "invokeaction",
+                                "href": format!("/{}/modules/{}/{}", self.id, module_name, function_name),
+                                "htv:methodName": endpoint.method.to_uppercase(),
+                                "contentType": content_type,
+                            },
+                            {
+                                "op": "invokeaction",
+                                "href": format!("/{}/actions/{}/{}", self.id, module_name, function_name),
+                                "htv:methodName": "POST",
+                                "contentType": content_type,
+                                "response": { "contentType": "application/json" },
+                            },
+                            {
+                                "op": "queryaction",
+                                "href": "/actions/{actionId}",
+                                "htv:methodName": "GET",
+                            },
+                            {
+                                "op": "cancelaction",
+                                "href": "/actions/{actionId}",
+                                "htv:methodName": "DELETE",
+                            },
+                        ]
+                    }),
+                );
             }
-        } else {
-            warn!("No 'modules' key found in `_instructions`. Using empty instructions.");
         }
+        actions
     }
 
     /// Return the next function's endpoint (if any) that this function is supposed to call.
@@ -795,23 +1216,28 @@ impl Deployment {
         link.to.as_ref()
     }
 
-    /// Validates and sets up file mounts for a module function before execution.
+    /// Validates the file mounts of a module function and resolves each to the file it is given:
     ///
     /// - Checks that required files are present.
     /// - Ensures correct mount paths and stages (DEPLOYMENT, EXECUTION).
-    /// - Copies input files into their correct mounted location if necessary.
+    /// - Finds where each file goes on the host, which depends on the stage of the file in the
+    ///   mount layout of the module (see `MountLayout`).
     ///
-    /// This sets up the module's environment so it can access inputs via WASI.
-    pub fn _connect_request_files_to_mounts(
+    /// Nothing is copied, see `_connect_request_files_to_mounts`.
+    pub fn resolve_mounts(
         &self,
         deployment_id: &str,
         module_name: &str,
         function_name: &str,
         request_filepaths: &HashMap<String, PathBuf>,
-    ) -> Result<(), String> {
-        let mounts = self.mounts.get(module_name)
-            .and_then(|mod_map| mod_map.get(function_name))
-            .ok_or_else(|| format!("No mounts found for module '{}/{}'", module_name, function_name))?;
+    ) -> Result<Vec<ResolvedMount>, String> {
+        let Some(mounts) = self.mounts.get(module_name).and_then(|mod_map| mod_map.get(function_name)) else {
+            // Functions the manifest does not describe, like healthchecks, take no files
+            if request_filepaths.is_empty() {
+                return Ok(Vec::new());
+            }
+            return Err(format!("No mounts found for module '{}/{}'", module_name, function_name));
+        };
 
         let empty_vec: Vec<MountPathFile> = vec![];
 
@@ -850,12 +1276,13 @@ impl Deployment {
             return Err(format!("Required input files missing: {:?}", missing_files));
         }
 
+        let layout = self.modules.get(module_name).map(|module| module.mount_layout).unwrap_or_default();
         let output_stage_mount_paths = mounts.get(&MountStage::OUTPUT).unwrap_or(&empty_vec);
         let all_mounts = deployment_stage_mount_paths.iter()
             .chain(execution_stage_mount_paths.iter())
             .chain(output_stage_mount_paths.iter());
 
-        // Copy input files to expected mount paths
+        let mut resolved = Vec::new();
         for mount in all_mounts {
             let temp_source_path = match mount.stage {
                 MountStage::DEPLOYMENT => {
@@ -875,10 +1302,47 @@ impl Deployment {
                 return Err(format!("Missing input file: {}", mount.path));
             };
 
-            let host_path = module_mount_path(deployment_id, module_name, &mount.path);
+            resolved.push(ResolvedMount {
+                path: mount.path.clone(),
+                stage: mount.stage,
+                source: temp_source_path,
+                host_path: module_mount_path(deployment_id, module_name, layout, mount.stage, &mount.path),
+                guest_path: layout.guest_path(mount.stage, &mount.path),
+            });
+        }
+        Ok(resolved)
+    }
+
+    /// Validates and sets up file mounts for a module function before execution.
+    ///
+    /// - Resolves the mounts with `resolve_mounts`.
+    /// - Copies input files into their correct mounted location if necessary.
+    ///
+    /// This sets up the module's environment so it can access inputs via WASI.
+    pub fn _connect_request_files_to_mounts(
+        &self,
+        deployment_id: &str,
+        module_name: &str,
+        function_name: &str,
+        request_filepaths: &HashMap<String, PathBuf>,
+    ) -> Result<(), String> {
+        for mount in self.resolve_mounts(deployment_id, module_name, function_name, request_filepaths)? {
+            let (temp_source_path, host_path) = (&mount.source, &mount.host_path);
             if host_path != temp_source_path {
-                match fs::copy(&temp_source_path, &host_path) {
-                    Ok(_) => {},
+                let connected = if mount.stage == MountStage::EXECUTION {
+                    // Inputs of a request are linked rather than copied, as they are removed
+                    // after the execution. The input of an earlier request may still be there.
+                    fs::remove_file(host_path).ok();
+                    if let Some(stage_dir) = host_path.parent() {
+                        fs::create_dir_all(stage_dir).ok();
+                    }
+                    fs::hard_link(temp_source_path, host_path)
+                        .or_else(|_| fs::copy(temp_source_path, host_path).map(|_| ()))
+                } else {
+                    fs::copy(temp_source_path, host_path).map(|_| ())
+                };
+                match connected {
+                    Ok(()) => {},
                     Err(e) => {
                         error!("Failed to copy '{}' to '{}': {}", temp_source_path.display(), host_path.display(), e);
                         return Err(format!("Failed to move file: {}", temp_source_path.display()));
@@ -891,12 +1355,72 @@ impl Deployment {
         Ok(())
     }
 
+    /// Returns the runtime of a module, creating it if the module has not been run since the
+    /// deployment was created or its runtimes were dropped.
+    pub async fn runtime_of(&mut self, deployment_id: &str, module_name: &str) -> Result<&mut WasmtimeRuntime, String> {
+        if !self.runtimes.contains_key(module_name) {
+            let config = self.modules
+                .get(module_name)
+                .ok_or_else(|| format!("Module '{}' not found in self.modules", module_name))?;
+            let host_dir = PARAMS_FOLDER.join(deployment_id).join(module_name);
+            let env = module_secret_env(self.secrets.get(module_name));
+
+            let runtime = WasmtimeRuntime::new(config.preopens(&host_dir), env).await
+                .map_err(|e| format!("Failed to initialize runtime for module '{}': {}", module_name, e))?;
+
+            self.runtimes.insert(module_name.to_string(), runtime);
+        }
+
+        Ok(self.runtimes
+            .get_mut(module_name)
+            .expect("Runtime must exist after initialization"))
+    }
+
+    /// Checks that each module of the deployment can be loaded, compiling it and linking its
+    /// imports, and records in `load_errors` why those that cannot failed.
+    pub async fn check_module_loads(&mut self, deployment_id: &str) {
+        let mut load_errors = HashMap::new();
+        let mut module_names: Vec<String> = self.modules.keys().cloned().collect();
+        module_names.sort();
+        for module_name in module_names {
+            let config = self.modules[&module_name].clone();
+            // Core modules cannot be compiled without loading them on armv6 devices
+            #[cfg(feature = "armv6")]
+            if !is_component(&config.path) {
+                continue;
+            }
+            let error = match self.runtime_of(deployment_id, &module_name).await {
+                Err(e) => Some(e),
+                Ok(runtime) if is_component(&config.path) => runtime.component_signatures_of(&config).err(),
+                Ok(runtime) => match runtime.compiled_module(&config) {
+                    Err(e) => Some(e),
+                    Ok(module) => {
+                        let problems: Vec<String> = runtime.import_report(&module)
+                            .into_iter()
+                            .filter(|import| !import.satisfied)
+                            .map(|import| format!(
+                                "Import '{}/{}' is not satisfied: {}",
+                                import.module, import.name, import.problem.unwrap_or_default()
+                            ))
+                            .collect();
+                        (!problems.is_empty()).then(|| problems.join("; "))
+                    }
+                },
+            };
+            if let Some(error) = error {
+                load_errors.insert(module_name, error);
+            }
+        }
+        self.load_errors = load_errors;
+    }
+
     /// Prepares a module and its function for execution:
     /// - Ensures mounts are connected correctly.
     /// - Loads the module into its runtime.
     /// - Converts function arguments into WebAssembly primitive values.
     ///
-    /// Returns the instantiated module and list of WASM `Val`s as arguments.
+    /// Returns the instantiated module and list of WASM `Val`s as arguments. A component gets
+    /// no `Val`s, as its arguments are converted when it is called (see `run_component_function`).
     pub async fn prepare_for_running(
         &mut self,
         deployment_id: &str,
@@ -910,33 +1434,22 @@ impl Deployment {
             .map(|(k, v)| (k.clone(), PathBuf::from(v)))
             .collect();
 
+        let args = self.bind_arguments(module_name, function_name, args)?;
         self._connect_request_files_to_mounts(deployment_id, module_name, function_name, &path_map)
             .map_err(|e| format!("Mount error: {}", e))?;
 
         let config = self.modules
             .get(module_name)
+            .cloned()
             .ok_or_else(|| format!("Module '{}' not found in self.modules", module_name))?;
 
-        if !self.runtimes.contains_key(module_name) {
-            let host_dir = PARAMS_FOLDER
-                .join(deployment_id)
-                .join(module_name)
-                .to_string_lossy()
-                .to_string();
-
-            let mounts = vec![(host_dir, ".".to_string())];
-
-            let runtime = WasmtimeRuntime::new(mounts).await
-                .map_err(|e| format!("Failed to initialize runtime for module '{}': {}", module_name, e))?;
-
-            self.runtimes.insert(module_name.to_string(), runtime);
+        if self.modules_needing_secrets().iter().any(|m| m == module_name) {
+            return Err(format!("Module '{}' needs secrets; deploy it again to supply them", module_name));
         }
 
-        let runtime = self.runtimes
-            .get_mut(module_name)
-            .expect("Runtime must exist after initialization");
+        let runtime = self.runtime_of(deployment_id, module_name).await?;
 
-        runtime.load_module(config.clone()).await
+        runtime.load_module(config).await
             .map_err(|e| format!("Failed to load module: {}", e))?;
 
         let arg_types = runtime.get_arg_types(module_name, function_name).await;
@@ -947,58 +1460,148 @@ impl Deployment {
         // Convert arguments from serde_json::Value → wasmtime::Val based on type hints.
         let primitive_args: Vec<Val> = args.values()
             .zip(arg_types.iter())
-            .map(|(value, typ)| match typ {
-                ValType::I32 => {
-                    match value {
-                        Value::Number(num) => Val::I32(num.as_i64().unwrap_or_default() as i32),
-                        Value::String(s) => s.parse::<i32>().map(Val::I32).unwrap_or(Val::I32(0)),
-                        _ => Val::I32(0),
-                    }
-                }
-                ValType::I64 => {
-                    match value {
-                        Value::Number(num) => Val::I64(num.as_i64().unwrap_or_default()),
-                        Value::String(s) => s.parse::<i64>().map(Val::I64).unwrap_or(Val::I64(0)),
-                        _ => Val::I64(0),
-                    }
-                }
-                ValType::F32 => {
-                    match value {
-                        Value::Number(num) => Val::F32((num.as_f64().unwrap_or_default() as f32).to_bits()),
-                        Value::String(s) => s.parse::<f32>().map(|f| Val::F32(f.to_bits())).unwrap_or(Val::F32(0)),
-                        _ => Val::F32(0),
-                    }
-                }
-                ValType::F64 => {
-                    match value {
-                        Value::Number(num) => Val::F64(num.as_f64().unwrap_or_default().to_bits()),
-                        Value::String(s) => s.parse::<f64>().map(|f| Val::F64(f.to_bits())).unwrap_or(Val::F64(0)),
-                        _ => Val::F64(0),
-                    }
-                }
-                _ => {
-                    error!("Unsupported argument type: {:?}", typ);
-                    Val::I32(0)
-                }
-            })
+            .map(|(value, typ)| wasm_arg(value, typ).unwrap_or_else(|| default_wasm_arg(typ)))
             .collect();
         Ok((module.clone(), primitive_args))
     }
 
 
+    /// Binds the arguments of a call to the declared parameters of the endpoint of the function
+    /// (see `EndpointRequest::bind_arguments`). Functions without an endpoint take the arguments
+    /// as they were given.
+    pub fn bind_arguments(
+        &self,
+        module_name: &str,
+        function_name: &str,
+        args: &IndexMap<String, Value>,
+    ) -> Result<IndexMap<String, Value>, String> {
+        match self.endpoints.get(module_name).and_then(|functions| functions.get(function_name)) {
+            Some(endpoint) => endpoint.request.bind_arguments(args),
+            None => Ok(args.clone()),
+        }
+    }
+
+    /// Checks that a function would be accepted for running with the given arguments and files,
+    /// the way `prepare_for_running` prepares it, and reports what would be run.
+    ///
+    /// The files are resolved to their mounts but not linked, and the module is compiled but
+    /// not instantiated, so no code of the module runs.
+    pub async fn validate_call(
+        &mut self,
+        deployment_id: &str,
+        module_name: &str,
+        function_name: &str,
+        args: &IndexMap<String, Value>,
+        request_filepaths: &HashMap<String, String>,
+    ) -> CallValidation {
+        let mut problems = Vec::new();
+        let args = self.bind_arguments(module_name, function_name, args).unwrap_or_else(|e| {
+            problems.push(e);
+            args.clone()
+        });
+        let path_map: HashMap<String, PathBuf> = request_filepaths
+            .iter()
+            .map(|(k, v)| (k.clone(), PathBuf::from(v)))
+            .collect();
+
+        let mounts = match self.resolve_mounts(deployment_id, module_name, function_name, &path_map) {
+            Ok(mounts) => mounts,
+            Err(e) => {
+                problems.push(format!("Mount error: {}", e));
+                Vec::new()
+            }
+        };
+        for mount in &mounts {
+            if !mount.source.is_file() {
+                problems.push(format!("File of mount '{}' not found at {}", mount.path, mount.source.display()));
+            }
+        }
+
+        if self.modules_needing_secrets().iter().any(|m| m == module_name) {
+            problems.push(format!("Module '{}' needs secrets; deploy it again to supply them", module_name));
+        }
+
+        let next = self.instructions.get(module_name)
+            .and_then(|functions| functions.get(function_name))
+            .and_then(|link| link.to.as_ref())
+            .map(|endpoint| json!({ "url": endpoint.url, "path": endpoint.path, "method": endpoint.method }));
+
+        let mut validation = CallValidation { valid: false, problems, args: Vec::new(), results: Vec::new(), mounts, next };
+        let Some(config) = self.modules.get(module_name).cloned() else {
+            validation.problems.push(format!("Module '{}' not found in self.modules", module_name));
+            return validation;
+        };
+        let runtime = match self.runtime_of(deployment_id, module_name).await {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                validation.problems.push(e);
+                return validation;
+            }
+        };
+        if is_component(&config.path) {
+            validate_component_call(runtime, &config, function_name, &args, &mut validation);
+            validation.valid = validation.problems.is_empty();
+            return validation;
+        }
+        let module = match runtime.compiled_module(&config) {
+            Ok(module) => module,
+            Err(e) => {
+                validation.problems.push(e);
+                return validation;
+            }
+        };
+        for import in runtime.import_report(&module).into_iter().filter(|import| !import.satisfied) {
+            validation.problems.push(format!(
+                "Import '{}/{}' is not satisfied: {}",
+                import.module, import.name, import.problem.unwrap_or_default()
+            ));
+        }
+
+        let Some(signature) = function_signatures(&module).remove(function_name) else {
+            validation.problems.push(format!("Module '{}' does not export function '{}'", module_name, function_name));
+            return validation;
+        };
+        if args.len() < signature.params.len() {
+            validation.problems.push(format!(
+                "Function '{}' has {} parameters but {} arguments were given",
+                function_name, signature.params.len(), args.len()
+            ));
+        }
+        for ((name, value), typ) in args.iter().zip(signature.params.iter()) {
+            let converted = wasm_arg(value, typ);
+            if converted.is_none() {
+                validation.problems.push(format!("Argument '{}' ({}) is not a valid {}, 0 would be passed instead", name, value, typ));
+            }
+            validation.args.push(json!({
+                "name": name,
+                "type": typ.to_string(),
+                "value": wasm_val_json(&converted.unwrap_or_else(|| default_wasm_arg(typ))),
+            }));
+        }
+        validation.results = signature.results.iter().map(ValType::to_string).collect();
+        validation.valid = validation.problems.is_empty();
+        validation
+    }
+
     /// Interprets the output from a Wasm function call and determines the next call (if any).
+    /// The output is `None` for a function that returns nothing, which passes on only the
+    /// output files it declares.
     ///
     /// Returns:
     /// - Parsed result as `(EndpointArgs, EndpointData)`
-    /// - Optional `CallData` describing how to invoke the next endpoint.
+    /// - `CallData` describing how to invoke the next endpoint, followed by how to invoke each of
+    ///   its fallback targets in order, or nothing if there is no next endpoint.
     pub fn interpret_call_from(
         &self,
         _deployment_id: &str,
         module_name: &str,
         function_name: &str,
-        wasm_output: Value,
-    ) -> (EndpointOutput, Option<CallData>) {
-        let endpoint = &self.endpoints[module_name][function_name];
+        wasm_output: Option<Value>,
+    ) -> (EndpointOutput, Vec<CallData>) {
+        // Functions without an endpoint, like healthchecks, are only run on this device
+        let Some(endpoint) = self.endpoints.get(module_name).and_then(|functions| functions.get(function_name)) else {
+            return ((wasm_output.map(|output| EndpointArgs::Str(output.to_string())), None), Vec::new());
+        };
         let output_mounts = self
             .mounts
             .get(module_name)
@@ -1009,26 +1612,46 @@ impl Deployment {
 
         let parsed = self.parse_endpoint_result(wasm_output, &endpoint.response, &output_mounts);
 
-        if let Some(next_ep) = self._next_target(module_name, function_name) {
-            let cd = CallData::from_endpoint(next_ep, parsed.0.clone(), parsed.1.clone());
-            (parsed, Some(cd))
-        } else {
-            (parsed, None)
-        }
+        let Some(next_ep) = self._next_target(module_name, function_name) else {
+            return (parsed, Vec::new());
+        };
+        let fallbacks = &self.instructions[module_name][function_name].fallback_targets;
+        let calls = std::iter::once(next_ep)
+            .chain(fallbacks)
+            .map(|target| CallData::from_endpoint(target, parsed.0.clone(), parsed.1.clone()))
+            .collect();
+        (parsed, calls)
     }
 
     /// Translates a WebAssembly function's output value into the expected OpenAPI-like format.
     ///
     /// If the output is:
+    /// - Nothing, as the function returns nothing: return only the output files of a file type.
+    /// - A string read from memory (`source: memory`): return the string.
     /// - JSON: return as string or empty object.
     /// - Binary (e.g., image): return file path(s) from output mount.
     /// - Anything else: log error and return empty.
     pub fn parse_endpoint_result(
         &self,
-        wasm_output: Value,
+        wasm_output: Option<Value>,
         response_endpoint: &MediaTypeObject,
         output_mounts: &Vec<MountPathFile>,
     ) -> EndpointOutput {
+        // Nothing returned is not interpreted as a primitive, only the output files are passed on
+        let Some(wasm_output) = wasm_output else {
+            if !FILE_TYPES.contains(&response_endpoint.media_type.as_str()) {
+                return (None, None);
+            }
+            return self.parse_endpoint_result(Some(Value::Null), response_endpoint, output_mounts);
+        };
+        // A string read from the memory of the module is the result as it is
+        if response_endpoint.source == Some(ResultSource::Memory) {
+            let text = match wasm_output {
+                Value::String(text) => text,
+                other => other.to_string(),
+            };
+            return (Some(EndpointArgs::Str(text)), None);
+        }
         match response_endpoint.media_type.as_str() {
             "application/json" => {
                 if can_be_represented_as_wasm_primitive(&response_endpoint.schema) {
@@ -1079,6 +1702,165 @@ pub fn can_be_represented_as_wasm_primitive(schema: &Schema) -> bool {
     matches!(schema.r#type, SchemaType::INTEGER)
 }
 
+/// Converts an argument of a function into a Wasm value of the given type.
+///
+/// # Returns
+/// `None` if the argument is not a number, or a string of one, that fits the type.
+pub fn wasm_arg(value: &Value, typ: &ValType) -> Option<Val> {
+    match (typ, value) {
+        (ValType::I32, Value::Number(num)) => num.as_i64().map(|n| Val::I32(n as i32)),
+        (ValType::I32, Value::String(s)) => s.parse::<i32>().ok().map(Val::I32),
+        (ValType::I64, Value::Number(num)) => num.as_i64().map(Val::I64),
+        (ValType::I64, Value::String(s)) => s.parse::<i64>().ok().map(Val::I64),
+        (ValType::F32, Value::Number(num)) => num.as_f64().map(|f| Val::F32((f as f32).to_bits())),
+        (ValType::F32, Value::String(s)) => s.parse::<f32>().ok().map(|f| Val::F32(f.to_bits())),
+        (ValType::F64, Value::Number(num)) => num.as_f64().map(|f| Val::F64(f.to_bits())),
+        (ValType::F64, Value::String(s)) => s.parse::<f64>().ok().map(|f| Val::F64(f.to_bits())),
+        _ => None,
+    }
+}
+
+/// A Wasm value as JSON.
+pub fn wasm_val_json(val: &Val) -> Value {
+    match val {
+        Val::I32(i) => json!(i),
+        Val::I64(i) => json!(i),
+        Val::F32(f) => json!(f32::from_bits(*f)),
+        Val::F64(f) => json!(f64::from_bits(*f)),
+        _ => Value::Null,
+    }
+}
+
+/// Checks the arguments of a call to a function of a component against its signature, for
+/// `validate_call`. Components have no imports to check, as they are linked with WASI preview 2.
+fn validate_component_call(
+    runtime: &WasmtimeRuntime,
+    config: &ModuleConfig,
+    function_name: &str,
+    args: &IndexMap<String, Value>,
+    validation: &mut CallValidation,
+) {
+    let signatures = match runtime.component_signatures_of(config) {
+        Ok(signatures) => signatures,
+        Err(e) => {
+            validation.problems.push(e);
+            return;
+        }
+    };
+    let Some(signature) = signatures.get(function_name) else {
+        validation.problems.push(format!("Component '{}' does not export function '{}'", config.name, function_name));
+        return;
+    };
+    if args.len() < signature.params.len() {
+        validation.problems.push(format!(
+            "Function '{}' has {} parameters but {} arguments were given",
+            function_name, signature.params.len(), args.len()
+        ));
+    }
+    for (name, value, typ) in signature.bind(args) {
+        let converted = component_arg(value, typ);
+        if converted.is_none() {
+            validation.problems.push(format!("Argument '{}' ({}) is not a valid {}", name, value, component_type_name(typ)));
+        }
+        validation.args.push(json!({
+            "name": name,
+            "type": component_type_name(typ),
+            "value": converted.as_ref().map(component_val_json),
+        }));
+    }
+    validation.results = signature.results.iter().map(component_type_name).collect();
+}
+
+/// Converts an argument of a function of a component into a value of the type of its
+/// parameter. Numbers and booleans may be given as strings, like for `wasm_arg`.
+///
+/// # Returns
+/// `None` if the argument does not fit the type, or the type is not a scalar or a string.
+pub fn component_arg(value: &Value, typ: &ComponentType) -> Option<ComponentVal> {
+    let signed = || match value {
+        Value::Number(num) => num.as_i64(),
+        Value::String(s) => s.parse::<i64>().ok(),
+        _ => None,
+    };
+    let unsigned = || match value {
+        Value::Number(num) => num.as_u64(),
+        Value::String(s) => s.parse::<u64>().ok(),
+        _ => None,
+    };
+    let float = || match value {
+        Value::Number(num) => num.as_f64(),
+        Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    };
+    match typ {
+        ComponentType::Bool => match value {
+            Value::Bool(b) => Some(ComponentVal::Bool(*b)),
+            Value::String(s) => s.parse::<bool>().ok().map(ComponentVal::Bool),
+            _ => None,
+        },
+        ComponentType::S8 => signed().and_then(|n| i8::try_from(n).ok()).map(ComponentVal::S8),
+        ComponentType::S16 => signed().and_then(|n| i16::try_from(n).ok()).map(ComponentVal::S16),
+        ComponentType::S32 => signed().and_then(|n| i32::try_from(n).ok()).map(ComponentVal::S32),
+        ComponentType::S64 => signed().map(ComponentVal::S64),
+        ComponentType::U8 => unsigned().and_then(|n| u8::try_from(n).ok()).map(ComponentVal::U8),
+        ComponentType::U16 => unsigned().and_then(|n| u16::try_from(n).ok()).map(ComponentVal::U16),
+        ComponentType::U32 => unsigned().and_then(|n| u32::try_from(n).ok()).map(ComponentVal::U32),
+        ComponentType::U64 => unsigned().map(ComponentVal::U64),
+        ComponentType::Float32 => float().map(|f| ComponentVal::Float32(f as f32)),
+        ComponentType::Float64 => float().map(ComponentVal::Float64),
+        ComponentType::Char => {
+            let mut chars = value.as_str()?.chars();
+            let c = chars.next()?;
+            chars.next().is_none().then_some(ComponentVal::Char(c))
+        }
+        ComponentType::String => value.as_str().map(|s| ComponentVal::String(s.to_string())),
+        _ => None,
+    }
+}
+
+/// A value of the component model as JSON. Lists, tuples, records, options and enums are
+/// converted as far as their contents are, other values are `null`.
+pub fn component_val_json(val: &ComponentVal) -> Value {
+    match val {
+        ComponentVal::Bool(b) => json!(b),
+        ComponentVal::S8(n) => json!(n),
+        ComponentVal::S16(n) => json!(n),
+        ComponentVal::S32(n) => json!(n),
+        ComponentVal::S64(n) => json!(n),
+        ComponentVal::U8(n) => json!(n),
+        ComponentVal::U16(n) => json!(n),
+        ComponentVal::U32(n) => json!(n),
+        ComponentVal::U64(n) => json!(n),
+        ComponentVal::Float32(f) => json!(f),
+        ComponentVal::Float64(f) => json!(f),
+        ComponentVal::Char(c) => json!(c.to_string()),
+        ComponentVal::String(s) => json!(s),
+        ComponentVal::List(vals) | ComponentVal::Tuple(vals) => {
+            Value::Array(vals.iter().map(component_val_json).collect())
+        }
+        ComponentVal::Record(fields) => Value::Object(
+            fields.iter().map(|(name, val)| (name.clone(), component_val_json(val))).collect()
+        ),
+        ComponentVal::Option(val) => val.as_deref().map_or(Value::Null, component_val_json),
+        ComponentVal::Enum(name) => json!(name),
+        _ => Value::Null,
+    }
+}
+
+/// The zero of a Wasm type, passed in place of an argument `wasm_arg` cannot convert.
+pub fn default_wasm_arg(typ: &ValType) -> Val {
+    match typ {
+        ValType::I32 => Val::I32(0),
+        ValType::I64 => Val::I64(0),
+        ValType::F32 => Val::F32(0),
+        ValType::F64 => Val::F64(0),
+        _ => {
+            error!("Unsupported argument type: {:?}", typ);
+            Val::I32(0)
+        }
+    }
+}
+
 /// Builds the absolute host path for a file mounted into a specific module.
 ///
 /// Used to resolve where a mounted file should live on the host filesystem (e.g. under `params/`).
@@ -1086,10 +1868,28 @@ pub fn can_be_represented_as_wasm_primitive(schema: &Schema) -> bool {
 /// # Arguments
 /// * `deployment_id` - ID of the deployment the module belongs to
 /// * `module_name` - Name of the module the file belongs to.
+/// * `layout` - Mount layout of the module, deciding the folder of each stage.
+/// * `stage` - Mount stage of the file.
 /// * `filename` - The relative path (mount path) used within the module.
 ///
 /// # Returns
 /// * `PathBuf` pointing to the correct location on disk.
-pub fn module_mount_path(deployment_id: &str, module_name: &str, filename: &str) -> PathBuf {
-    PARAMS_FOLDER.join(deployment_id).join(module_name).join(filename)
+pub fn module_mount_path(deployment_id: &str, module_name: &str, layout: MountLayout, stage: MountStage, filename: &str) -> PathBuf {
+    let module_dir = PARAMS_FOLDER.join(deployment_id).join(module_name);
+    match layout.stage_folder(stage) {
+        Some(folder) => module_dir.join(folder).join(filename),
+        None => module_dir.join(filename),
+    }
+}
+
+/// Converts the secrets of a module into environment variables for its runtime.
+pub fn module_secret_env(secrets: Option<&HashMap<String, SecretValue>>) -> Vec<(String, String)> {
+    secrets
+        .map(|secrets| {
+            secrets
+                .iter()
+                .map(|(name, value)| (name.clone(), value.expose().to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
 }
@@ -0,0 +1,131 @@
+//! # device_limits.rs
+//!
+//! Caps on what a device takes on, enforced on the device when deployments are created rather
+//! than trusting the orchestrator to keep to them: the number of deployments, the number of
+//! modules in them and the size of their artifacts, as set in `deviceLimits` of the supervisor
+//! configuration (`WASMIOT_MAX_DEPLOYMENTS`, `WASMIOT_MAX_MODULES` and
+//! `WASMIOT_MAX_ARTIFACT_BYTES`).
+//!
+//! The usage they are checked against is measured from the instance directory, and reported in
+//! `/health` and the device description so that the orchestrator can place deployments on
+//! devices with room for them.
+
+use std::collections::HashSet;
+use std::fs;
+use serde::{Deserialize, Serialize};
+use crate::lib::api::lock_deployments;
+use crate::lib::checksum::is_sidecar;
+use crate::lib::constants::{INPUTS_FOLDER_NAME, MODULE_FOLDER, OUTPUTS_FOLDER_NAME, PARAMS_FOLDER};
+use crate::lib::maintenance::tree_size;
+use crate::lib::startup::loading_deployment_ids;
+use crate::structs::supervisor_config::DeviceLimits;
+
+/// What the deployments of the device use.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceUsage {
+    /// Deployments on the device, including those still being loaded at startup
+    pub deployments: usize,
+    /// Modules in all deployments
+    pub modules: usize,
+    /// Size of the module binaries and their files
+    #[serde(rename="artifactBytes")]
+    pub artifact_bytes: u64,
+    /// Size of the outputs of executions
+    #[serde(rename="resultBytes")]
+    pub result_bytes: u64,
+}
+
+/// Which cap a deployment would go over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Deployments,
+    Modules,
+    ArtifactBytes,
+}
+
+/// Measures what a deployment uses, adding it to `usage`.
+fn add_deployment_usage(deployment_id: &str, usage: &mut DeviceUsage) {
+    usage.deployments += 1;
+    let module_dir = MODULE_FOLDER.join(deployment_id);
+    if let Ok(modules) = fs::read_dir(&module_dir) {
+        usage.modules += modules
+            .filter_map(Result::ok)
+            .filter(|module| !is_sidecar(&module.path()))
+            .count();
+    }
+    usage.artifact_bytes += tree_size(&module_dir);
+
+    // The files of each module are artifacts, except for the inputs and outputs of requests
+    let Ok(modules) = fs::read_dir(PARAMS_FOLDER.join(deployment_id)) else { return };
+    for module in modules.filter_map(Result::ok) {
+        let Ok(entries) = fs::read_dir(module.path()) else { continue };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name();
+            if name == OUTPUTS_FOLDER_NAME {
+                usage.result_bytes += tree_size(&entry.path());
+            } else if name != INPUTS_FOLDER_NAME {
+                usage.artifact_bytes += tree_size(&entry.path());
+            }
+        }
+    }
+}
+
+/// Measures what the deployments of the device use, leaving out `except`, e.g. a deployment
+/// about to be replaced.
+pub fn device_usage_except(except: Option<&str>) -> DeviceUsage {
+    let mut deployment_ids: HashSet<String> = lock_deployments().keys().cloned().collect();
+    deployment_ids.extend(loading_deployment_ids());
+    let mut usage = DeviceUsage::default();
+    for deployment_id in deployment_ids.iter().filter(|id| Some(id.as_str()) != except) {
+        add_deployment_usage(deployment_id, &mut usage);
+    }
+    usage
+}
+
+/// Measures what the deployments of the device use.
+pub fn device_usage() -> DeviceUsage {
+    device_usage_except(None)
+}
+
+/// Checks that a deployment fits within `limits`, counting what the other deployments use.
+///
+/// # Arguments
+/// * `deployment_id` - The deployment, which replaces a deployment of the same ID.
+/// * `modules` - Number of modules in the deployment.
+/// * `artifact_bytes` - Size of the artifacts of the deployment, as far as it is known.
+///
+/// # Returns
+/// The cap the deployment would go over, with a description of it and the usage measured.
+pub fn check_device_limits(
+    limits: &DeviceLimits,
+    deployment_id: &str,
+    modules: usize,
+    artifact_bytes: u64,
+) -> Result<(), (LimitExceeded, String, DeviceUsage)> {
+    let usage = device_usage_except(Some(deployment_id));
+    if let Some(max) = limits.max_deployments
+        && usage.deployments + 1 > max
+    {
+        let error = format!("The device already has {} deployments, the most allowed is {}", usage.deployments, max);
+        return Err((LimitExceeded::Deployments, error, usage));
+    }
+    if let Some(max) = limits.max_modules
+        && usage.modules + modules > max
+    {
+        let error = format!(
+            "The {} modules of the deployment would take the device over the limit of {} modules, {} are in use",
+            modules, max, usage.modules
+        );
+        return Err((LimitExceeded::Modules, error, usage));
+    }
+    if let Some(max) = limits.max_artifact_bytes
+        && usage.artifact_bytes.saturating_add(artifact_bytes) > max
+    {
+        let error = format!(
+            "The deployment needs {} bytes of artifacts, but only {} of the {} bytes allowed are free",
+            artifact_bytes, max.saturating_sub(usage.artifact_bytes), max
+        );
+        return Err((LimitExceeded::ArtifactBytes, error, usage));
+    }
+    Ok(())
+}
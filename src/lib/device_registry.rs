@@ -0,0 +1,117 @@
+//! # device_registry.rs
+//!
+//! Placeholders in the target URLs of the instructions of a deployment, so that a manifest
+//! need not hardcode the addresses of the devices a chain runs on, and a device getting a new
+//! address does not mean deploying the modules again.
+//!
+//! The `url` of a `to` or fallback target may contain `{self}`, the public base URL of this
+//! supervisor (see `configuration::public_url`), or `{device:NAME}`, the base URL given for the
+//! device `NAME` under `devices` of the manifest, e.g.
+//! `{"url": "{device:wasmiot-supervisor-2}", "path": "/deploymentId/modules/..."}`. The
+//! placeholders are resolved each time a chained call is made, so the devices can be moved
+//! with `PATCH /deploy/{id}/devices` between executions. A target with a placeholder that
+//! cannot be resolved fails like one that cannot be reached, with an error naming the
+//! placeholder, and the next fallback target is tried.
+
+use std::collections::HashMap;
+use serde_json::Value;
+use crate::lib::cli::parse_http_url;
+
+/// Placeholder of the public base URL of this supervisor.
+const SELF_PLACEHOLDER: &str = "{self}";
+
+/// Start of a placeholder of the base URL of a named device.
+const DEVICE_PLACEHOLDER_PREFIX: &str = "{device:";
+
+/// Parses the `devices` of a deployment manifest: the base URL of each device its target URLs
+/// name, by name.
+pub fn parse_devices(value: Option<&Value>) -> Result<HashMap<String, String>, String> {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        return Ok(HashMap::new());
+    };
+    let devices: HashMap<String, String> = serde_json::from_value(value.clone())
+        .map_err(|_| "devices must be an object of device names and base URLs".to_string())?;
+    devices.into_iter()
+        .map(|(name, url)| Ok((check_device_name(&name)?, check_device_url(&name, &url)?)))
+        .collect()
+}
+
+/// Applies a `PATCH /deploy/{id}/devices` body to the devices of a deployment: each device
+/// given a URL is added or moved to it, and each given `null` is removed.
+///
+/// Nothing is changed if any entry is not valid.
+pub fn patch_devices(devices: &mut HashMap<String, String>, patch: &Value) -> Result<(), String> {
+    let Some(patch) = patch.as_object() else {
+        return Err("devices must be an object of device names and base URLs".to_string());
+    };
+    let mut patched = devices.clone();
+    for (name, url) in patch {
+        let name = check_device_name(name)?;
+        match url {
+            Value::Null => {
+                patched.remove(&name);
+            }
+            Value::String(url) => {
+                let url = check_device_url(&name, url)?;
+                patched.insert(name, url);
+            }
+            _ => return Err(format!("The URL of device '{}' must be a string or null", name)),
+        }
+    }
+    *devices = patched;
+    Ok(())
+}
+
+/// Checks that a device name can be written in a placeholder.
+fn check_device_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.contains(['{', '}']) {
+        return Err(format!("Invalid device name '{}'", name));
+    }
+    Ok(name.to_string())
+}
+
+/// Checks that the URL of a device is an http(s) URL, without the trailing slash.
+fn check_device_url(name: &str, url: &str) -> Result<String, String> {
+    parse_http_url(url)
+        .map(|url| url.trim_end_matches('/').to_string())
+        .map_err(|e| format!("Invalid URL of device '{}': {}", name, e))
+}
+
+/// Replaces the placeholders in the part of a target URL before its query with the base URLs
+/// they stand for. The query holds the arguments of the call, which are never resolved.
+///
+/// # Returns
+/// The URL to call, or which placeholder could not be resolved.
+pub fn resolve_target_url(url: &str, devices: &HashMap<String, String>, self_url: &str) -> Result<String, String> {
+    let (base, query) = match url.find('?') {
+        Some(at) => url.split_at(at),
+        None => (url, ""),
+    };
+    let mut resolved = String::with_capacity(url.len());
+    let mut rest = base;
+    while let Some(start) = rest.find('{') {
+        resolved.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix(SELF_PLACEHOLDER) {
+            resolved.push_str(self_url.trim_end_matches('/'));
+            rest = after;
+        } else if rest.starts_with(DEVICE_PLACEHOLDER_PREFIX) {
+            let Some(end) = rest.find('}') else {
+                return Err(format!("Unterminated placeholder in target URL {}", url));
+            };
+            let placeholder = &rest[..=end];
+            let name = &placeholder[DEVICE_PLACEHOLDER_PREFIX.len()..placeholder.len() - 1];
+            let device_url = devices.get(name).ok_or_else(|| {
+                format!("Unresolved placeholder {} in target URL {}: no device '{}' in the devices of the deployment", placeholder, url, name)
+            })?;
+            resolved.push_str(device_url);
+            rest = &rest[end + 1..];
+        } else {
+            resolved.push('{');
+            rest = &rest[1..];
+        }
+    }
+    resolved.push_str(rest);
+    resolved.push_str(query);
+    Ok(resolved)
+}
@@ -0,0 +1,739 @@
+//! # download.rs
+//!
+//! Downloading and verifying deployment artifacts (module binaries and their data files).
+//!
+//! Artifacts may come with an expected SHA-256 digest from the orchestrator. The digest is
+//! computed while the response is streamed to disk, so verification needs no second read
+//! of the file. Verified digests are stored with the deployment and checked again when
+//! deployments are reloaded at startup.
+//!
+//! All artifacts of a deployment are downloaded concurrently through the shared HTTP client,
+//! with at most `WASMIOT_DOWNLOAD_CONCURRENCY` downloads running at once. A download that
+//! fails partway is retried up to `WASMIOT_DOWNLOAD_RETRIES` times, resuming with a `Range`
+//! request from where it stopped when the server supports it.
+//!
+//! Artifacts with a known digest are also kept in a content-addressed cache. If a file with
+//! the expected digest is already on the device, it is used instead of downloading it again.
+//! Other supervisors can fetch the cached artifacts by their digest (see `module_by_hash`), so
+//! that a deployment may list peers on the same network to fetch its artifacts from first. The
+//! peers are tried all at once and the first to deliver an artifact that matches its digest is
+//! used, falling back to the URL of the artifact when none does.
+//!
+//! Artifacts may also be local files given as `file://` URLs (or `localPath`), which are
+//! copied instead of downloaded. Only files inside `WASMIOT_LOCAL_ARTIFACT_DIR` are allowed.
+//!
+//! Before anything is downloaded, the total size of a deployment is estimated and checked
+//! against the free space on the instance filesystem. Size caps per file and per deployment
+//! are also enforced while streaming, so a wrong `Content-Length` cannot get past them.
+//!
+//! Files are written with `tokio::fs`, and the work that reads whole files (hashing, copying
+//! local and cached artifacts, verifying signatures) runs on the blocking thread pool, so that a
+//! slow disk does not stall the HTTP workers while a deployment is being created.
+
+use std::fs::{self, File};
+use std::io::{self, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use futures_util::FutureExt;
+use futures_util::stream::{self, StreamExt};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use crate::lib::wasmtime::ModuleConfig;
+use crate::lib::cli::parse_http_url;
+use crate::lib::configuration::instance_disk_available;
+use crate::lib::signing::verify_file;
+use crate::lib::progress::update_progress;
+use crate::lib::constants::{
+    HTTP_CLIENT,
+    DISKS,
+    ARTIFACT_CACHE_FOLDER,
+    BUNDLE_IMPORT_FOLDER,
+    get_download_retries,
+    get_download_concurrency,
+    get_max_file_bytes,
+    get_max_deployment_bytes,
+    get_disk_reserve_bytes,
+    get_local_artifact_dir,
+};
+
+/// Where an artifact was downloaded from, and the digest it was verified against.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArtifactSource {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Size in bytes declared in the manifest, used for the disk space preflight
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+impl ArtifactSource {
+    /// Parses an artifact entry of a deployment manifest.
+    ///
+    /// Accepts either a plain URL string or an object of the form
+    /// `{"url": "...", "sha256": "...", "size": ...}` where the digest and size are optional.
+    /// Instead of `url`, the object may have a `localPath`, which is the same as a `file://` URL.
+    pub fn from_manifest(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(url) => Some(ArtifactSource { url: url.clone(), sha256: None, size: None }),
+            Value::Object(obj) => Some(ArtifactSource {
+                url: match obj.get("url").and_then(Value::as_str) {
+                    Some(url) => url.to_string(),
+                    None => format!("{}{}", FILE_URL_PREFIX, obj.get("localPath")?.as_str()?),
+                },
+                sha256: obj.get("sha256").and_then(Value::as_str).map(str::to_lowercase),
+                size: obj.get("size").and_then(Value::as_u64),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// URL prefix of artifacts that are copied from the local filesystem.
+const FILE_URL_PREFIX: &str = "file://";
+
+impl ArtifactSource {
+    /// Returns the path of a local artifact, or `None` if the artifact is downloaded.
+    pub fn local_path(&self) -> Option<&Path> {
+        self.url.strip_prefix(FILE_URL_PREFIX).map(Path::new)
+    }
+}
+
+/// Resolves the path of a local artifact, making sure it is inside the allowed directory
+/// or the folder where imported bundles are unpacked and pushed artifacts are staged.
+fn resolve_local_path(path: &Path) -> Result<PathBuf, String> {
+    let allowed_dir = get_local_artifact_dir();
+    let resolved = path.canonicalize()
+        .map_err(|e| format!("Local file {} is not available: {}", path.display(), e))?;
+    let allowed = [allowed_dir.as_path(), BUNDLE_IMPORT_FOLDER.as_path()]
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| resolved.starts_with(dir));
+    if !allowed {
+        return Err(format!("Local file {} is outside of {}", path.display(), allowed_dir.display()));
+    }
+    Ok(resolved)
+}
+
+/// Returns an error describing the mismatch if `actual` differs from the expected digest.
+fn check_digest(expected: Option<&str>, actual: &str) -> Result<(), String> {
+    match expected {
+        Some(expected) if !expected.eq_ignore_ascii_case(actual) => {
+            Err(format!("checksum mismatch: expected {} got {}", expected, actual))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Counts of how many times a download had to be retried, and how many of the
+/// retries could continue from where the previous attempt stopped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DownloadStats {
+    pub retries: u32,
+    pub resumes: u32,
+}
+
+/// Size caps enforced while downloading the artifacts of one deployment.
+#[derive(Clone, Debug)]
+pub struct DownloadLimits {
+    pub max_file_bytes: u64,
+    pub max_deployment_bytes: u64,
+    /// Bytes written so far by all downloads of the deployment
+    pub deployment_bytes: Arc<AtomicU64>,
+}
+
+impl DownloadLimits {
+    /// Creates limits for a new deployment using the caps configured in env.
+    pub fn from_env() -> Self {
+        DownloadLimits {
+            max_file_bytes: get_max_file_bytes(),
+            max_deployment_bytes: get_max_deployment_bytes(),
+            deployment_bytes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+/// Why a deployment was rejected before downloading.
+pub enum PreflightError {
+    /// A file or the whole deployment is over the configured size caps.
+    TooLarge(String),
+    /// The deployment does not fit in the free space of the instance filesystem.
+    InsufficientStorage(String),
+}
+
+/// Returns the size of an artifact, from the manifest if declared there and otherwise
+/// from the `Content-Length` of a HEAD request. `None` if the size cannot be known.
+async fn estimate_size(source: &ArtifactSource) -> Option<u64> {
+    if source.size.is_some() {
+        return source.size;
+    }
+    if let Some(local) = source.local_path() {
+        return fs::metadata(resolve_local_path(local).ok()?).ok().map(|m| m.len());
+    }
+    let response = HTTP_CLIENT.head(&source.url).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response.content_length()
+}
+
+/// Estimates the total size of the given downloads and checks it against the size caps and
+/// the free space on the instance filesystem, keeping `WASMIOT_DISK_RESERVE_BYTES` free.
+///
+/// Artifacts of unknown size are left out of the estimate; the caps still apply to them
+/// while they are downloaded.
+///
+/// # Returns
+/// The estimated total size in bytes.
+pub async fn check_download_space(jobs: &[DownloadJob], limits: &DownloadLimits) -> Result<u64, PreflightError> {
+    // The futures are made before streaming them, so that the stream is `Send` for any lifetime
+    let estimates: Vec<_> = jobs.iter()
+        .map(|job| async move { (job, estimate_size(&job.source).await) })
+        .collect();
+    let sizes: Vec<(&DownloadJob, Option<u64>)> = stream::iter(estimates)
+        .buffer_unordered(get_download_concurrency())
+        .collect()
+        .await;
+
+    let mut total: u64 = 0;
+    for (job, size) in sizes {
+        let Some(size) = size else { continue };
+        if size > limits.max_file_bytes {
+            return Err(PreflightError::TooLarge(format!(
+                "{} of module '{}' is {} bytes, over the limit of {} bytes per file",
+                job.source.url, job.module, size, limits.max_file_bytes
+            )));
+        }
+        total = total.saturating_add(size);
+    }
+    if total > limits.max_deployment_bytes {
+        return Err(PreflightError::TooLarge(format!(
+            "Deployment is {} bytes, over the limit of {} bytes per deployment",
+            total, limits.max_deployment_bytes
+        )));
+    }
+
+    let available = {
+        let mut disks = DISKS.lock();
+        disks.refresh(false);
+        instance_disk_available(&disks)
+    };
+    if let Some(available) = available {
+        let usable = available.saturating_sub(get_disk_reserve_bytes());
+        if total > usable {
+            return Err(PreflightError::InsufficientStorage(format!(
+                "Deployment needs {} bytes but only {} bytes are available",
+                total, usable
+            )));
+        }
+    }
+    Ok(total)
+}
+
+/// Why a single download attempt failed.
+enum AttemptError {
+    /// The connection failed or broke, so trying again may help.
+    Retryable(String),
+    /// Trying again would not help, e.g. the file does not exist.
+    Fatal(String),
+}
+
+/// Makes one attempt at fetching the rest of an artifact into `file`.
+///
+/// If `written` bytes are already in the file, only the remaining bytes are requested. When the
+/// server ignores the range and sends the whole file, the file and hasher are started over.
+async fn fetch_remaining(
+    source: &ArtifactSource,
+    file: &mut tokio::fs::File,
+    hasher: &mut Sha256,
+    written: &mut u64,
+    limits: &DownloadLimits,
+    stats: &mut DownloadStats,
+) -> Result<(), AttemptError> {
+    let mut request = HTTP_CLIENT.get(&source.url);
+    if *written > 0 {
+        request = request.header(RANGE, format!("bytes={}-", written));
+    }
+    let mut response = request.send().await
+        .map_err(|e| AttemptError::Retryable(format!("Failed to fetch {}: {}", source.url, e)))?;
+
+    let status = response.status();
+    if status == StatusCode::PARTIAL_CONTENT && *written > 0 {
+        // Make sure the server continues exactly where the previous attempt stopped
+        let expected_start = format!("bytes {}-", written);
+        let resumes_here = response.headers().get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with(&expected_start));
+        if !resumes_here {
+            return Err(AttemptError::Fatal(format!("{} returned an unexpected range", source.url)));
+        }
+        stats.resumes += 1;
+    } else if status.is_success() {
+        if *written > 0 {
+            // No range support, start over from the beginning
+            file.set_len(0).await
+                .map_err(|e| AttemptError::Fatal(format!("Failed to truncate partial file: {}", e)))?;
+            file.seek(SeekFrom::Start(0)).await
+                .map_err(|e| AttemptError::Fatal(format!("Failed to truncate partial file: {}", e)))?;
+            *hasher = Sha256::new();
+            limits.deployment_bytes.fetch_sub(*written, Ordering::Relaxed);
+            *written = 0;
+        }
+    } else if status.is_server_error() {
+        return Err(AttemptError::Retryable(format!("{} returned {}", source.url, status)));
+    } else {
+        return Err(AttemptError::Fatal(format!("{} returned {}", source.url, status)));
+    }
+
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                let chunk_len = chunk.len() as u64;
+                if *written + chunk_len > limits.max_file_bytes {
+                    return Err(AttemptError::Fatal(format!(
+                        "{} is over the limit of {} bytes per file", source.url, limits.max_file_bytes
+                    )));
+                }
+                let deployment_total = limits.deployment_bytes.fetch_add(chunk_len, Ordering::Relaxed) + chunk_len;
+                if deployment_total > limits.max_deployment_bytes {
+                    return Err(AttemptError::Fatal(format!(
+                        "Deployment is over the limit of {} bytes per deployment", limits.max_deployment_bytes
+                    )));
+                }
+                file.write_all(&chunk).await
+                    .map_err(|e| AttemptError::Fatal(format!("Failed to write file: {}", e)))?;
+                hasher.update(&chunk);
+                *written += chunk_len;
+            }
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                return Err(AttemptError::Retryable(format!("Failed to read response from {}: {}", source.url, e)));
+            }
+        }
+    }
+}
+
+/// Copies a local artifact to `path`, hashing it while it is written and enforcing `limits`.
+fn copy_local_artifact(local: &Path, path: &Path, limits: &DownloadLimits) -> Result<String, String> {
+    let resolved = resolve_local_path(local)?;
+    let mut input = File::open(&resolved)
+        .map_err(|e| format!("Failed to open local file {}: {}", resolved.display(), e))?;
+    let mut output = File::create(path)
+        .map_err(|e| format!("Failed to create file {}: {}", path.display(), e))?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut written: u64 = 0;
+    loop {
+        let read = io::Read::read(&mut input, &mut buffer)
+            .map_err(|e| format!("Failed to read local file {}: {}", resolved.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        written += read as u64;
+        if written > limits.max_file_bytes {
+            return Err(format!("{} is over the limit of {} bytes per file", local.display(), limits.max_file_bytes));
+        }
+        let deployment_total = limits.deployment_bytes.fetch_add(read as u64, Ordering::Relaxed) + read as u64;
+        if deployment_total > limits.max_deployment_bytes {
+            return Err(format!("Deployment is over the limit of {} bytes per deployment", limits.max_deployment_bytes));
+        }
+        hasher.update(&buffer[..read]);
+        output.write_all(&buffer[..read])
+            .map_err(|e| format!("Failed to write file {}: {}", path.display(), e))?;
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Downloads an artifact to `path`, hashing it while it is written.
+///
+/// Failed attempts are retried up to the configured retry count, resuming the partially
+/// written file where possible. The download is stopped as soon as it goes over the size
+/// caps of `limits`. If the source has an expected digest and the downloaded content does
+/// not match it, the written file is removed and an error is returned.
+///
+/// # Returns
+/// The hex encoded SHA-256 digest of the downloaded file.
+pub async fn download_artifact(
+    source: &ArtifactSource,
+    path: &Path,
+    limits: &DownloadLimits,
+    stats: &mut DownloadStats,
+) -> Result<String, String> {
+    download_with_retries(source, path, limits, stats, get_download_retries()).await
+}
+
+/// Downloads an artifact like `download_artifact`, retrying failed attempts `max_retries` times.
+async fn download_with_retries(
+    source: &ArtifactSource,
+    path: &Path,
+    limits: &DownloadLimits,
+    stats: &mut DownloadStats,
+    max_retries: u32,
+) -> Result<String, String> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await
+            .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+    }
+    // The previous version of the file may have been made read-only
+    tokio::fs::remove_file(path).await.ok();
+    if let Some(local) = source.local_path() {
+        let (local, target, copy_limits) = (local.to_path_buf(), path.to_path_buf(), limits.clone());
+        let result = run_blocking(move || copy_local_artifact(&local, &target, &copy_limits)).await
+            .and_then(|result| result)
+            .and_then(|digest| check_digest(source.sha256.as_deref(), &digest).map(|_| digest));
+        if result.is_err() {
+            tokio::fs::remove_file(path).await.ok();
+        }
+        return result;
+    }
+
+    let mut file = tokio::fs::File::create(path).await
+        .map_err(|e| format!("Failed to create file {}: {}", path.display(), e))?;
+
+    let mut hasher = Sha256::new();
+    let mut written = 0;
+    loop {
+        match fetch_remaining(source, &mut file, &mut hasher, &mut written, limits, stats).await {
+            Ok(()) => break,
+            Err(AttemptError::Retryable(e)) if stats.retries < max_retries => {
+                stats.retries += 1;
+                log::warn!(
+                    "Download of {} failed after {} bytes ({}), retry {}/{}",
+                    source.url, written, e, stats.retries, max_retries
+                );
+                tokio::time::sleep(Duration::from_secs(stats.retries as u64)).await;
+            }
+            Err(AttemptError::Retryable(e)) | Err(AttemptError::Fatal(e)) => {
+                drop(file);
+                tokio::fs::remove_file(path).await.ok();
+                return Err(e);
+            }
+        }
+    }
+    // Wait for the last writes to reach the file before it is verified or used
+    if let Err(e) = file.flush().await {
+        drop(file);
+        tokio::fs::remove_file(path).await.ok();
+        return Err(format!("Failed to write file {}: {}", path.display(), e));
+    }
+    drop(file);
+
+    let digest = hex::encode(hasher.finalize());
+    if let Err(e) = check_digest(source.sha256.as_deref(), &digest) {
+        tokio::fs::remove_file(path).await.ok();
+        return Err(e);
+    }
+    Ok(digest)
+}
+
+/// Runs file work that blocks on the blocking thread pool instead of the calling task.
+async fn run_blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Result<T, String> {
+    tokio::task::spawn_blocking(work).await
+        .map_err(|e| format!("File operation failed: {}", e))
+}
+
+/// Path of the artifact with the given digest in the shared cache.
+pub fn cache_path(digest: &str) -> PathBuf {
+    ARTIFACT_CACHE_FOLDER.join(digest)
+}
+
+/// Provides an artifact from disk instead of the network, if possible.
+///
+/// Succeeds when the file at `path` already has the expected digest, or when the shared
+/// cache has a file with it, which is then copied to `path`.
+fn restore_from_cache(expected: &str, path: &Path) -> bool {
+    if file_sha256(path).is_ok_and(|digest| digest == expected) {
+        return true;
+    }
+    let cached = cache_path(expected);
+    if !file_sha256(&cached).is_ok_and(|digest| digest == expected) {
+        return false;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    // The previous version of the file may have been made read-only
+    fs::remove_file(path).ok();
+    fs::copy(&cached, path).is_ok()
+}
+
+/// Stores a copy of a verified artifact in the shared cache.
+///
+/// A copy rather than a hard link is used, since a later download to `path` rewrites the file in place.
+fn store_in_cache(digest: &str, path: &Path) {
+    let cached = cache_path(digest);
+    if cached.exists() {
+        return;
+    }
+    if let Err(e) = fs::create_dir_all(&*ARTIFACT_CACHE_FOLDER).and_then(|_| fs::copy(path, &cached)) {
+        log::warn!("Failed to store {} in the artifact cache: {}", path.display(), e);
+    }
+}
+
+/// Parses a `peers` list of a deployment manifest, the base URLs of other supervisors to fetch
+/// artifacts from.
+pub fn parse_peers(value: Option<&Value>) -> Result<Vec<String>, String> {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        return Ok(Vec::new());
+    };
+    let peers: Vec<String> = serde_json::from_value(value.clone())
+        .map_err(|_| "peers must be a list of URLs".to_string())?;
+    peers.iter()
+        .map(|peer| parse_http_url(peer).map_err(|e| format!("Invalid peer '{}': {}", peer, e)))
+        .collect()
+}
+
+/// URL an artifact with the given digest is served at by a peer supervisor.
+pub fn peer_artifact_url(peer: &str, digest: &str) -> String {
+    format!("{}/modules/by-hash/{}", peer.trim_end_matches('/'), digest)
+}
+
+/// Fetches an artifact with the expected digest from the first of the peers of `job` to deliver
+/// it, asking all of them at once. Attempts are not retried, as the URL of the artifact is
+/// there to fall back to.
+///
+/// Each peer is downloaded into a file of its own next to `job.path`, and the first one that
+/// matches the digest is moved in place while the rest are stopped and removed. Only that one
+/// is counted towards the deployment size cap of `limits`.
+///
+/// # Returns
+/// The peer the artifact was fetched from, or `None` if none of them had it.
+async fn fetch_from_peers(job: &DownloadJob, expected: &str, limits: &DownloadLimits) -> Option<String> {
+    if job.peers.is_empty() {
+        return None;
+    }
+    let filename = job.path.file_name()?.to_string_lossy().to_string();
+    let paths: Vec<PathBuf> = (0..job.peers.len())
+        .map(|i| job.path.with_file_name(format!(".{}.peer{}", filename, i)))
+        .collect();
+    let remaining = limits.max_deployment_bytes.saturating_sub(limits.deployment_bytes.load(Ordering::Relaxed));
+    let attempts = job.peers.iter().zip(&paths).map(|(peer, path)| {
+        let source = ArtifactSource { url: peer_artifact_url(peer, expected), sha256: Some(expected.to_string()), size: None };
+        let peer_limits = DownloadLimits {
+            max_file_bytes: limits.max_file_bytes,
+            max_deployment_bytes: remaining,
+            deployment_bytes: Arc::new(AtomicU64::new(0)),
+        };
+        async move {
+            let mut stats = DownloadStats::default();
+            match download_with_retries(&source, path, &peer_limits, &mut stats, 0).await {
+                Ok(_) => Ok((peer.clone(), path.clone())),
+                Err(e) => {
+                    log::debug!("Peer {} could not provide {}: {}", peer, job.path.display(), e);
+                    Err(e)
+                }
+            }
+        }.boxed()
+    });
+    let fetched = futures_util::future::select_ok(attempts).await.ok().map(|(fetched, _)| fetched);
+
+    for path in &paths {
+        if fetched.as_ref().is_none_or(|(_, fetched_path)| fetched_path != path) {
+            tokio::fs::remove_file(path).await.ok();
+        }
+    }
+    let (peer, path) = fetched?;
+    // The previous version of the file may have been made read-only
+    tokio::fs::remove_file(&job.path).await.ok();
+    if let Err(e) = tokio::fs::rename(&path, &job.path).await {
+        log::warn!("Failed to move {} from peer {} in place: {}", job.path.display(), peer, e);
+        tokio::fs::remove_file(&path).await.ok();
+        return None;
+    }
+    let size = tokio::fs::metadata(&job.path).await.map(|m| m.len()).unwrap_or(0);
+    limits.deployment_bytes.fetch_add(size, Ordering::Relaxed);
+    Some(peer)
+}
+
+/// Which artifact of a module a download is for.
+#[derive(Clone, Debug)]
+pub enum ArtifactKind {
+    Binary,
+    DataFile(String),
+}
+
+/// A single artifact to download as part of a deployment.
+#[derive(Clone, Debug)]
+pub struct DownloadJob {
+    pub module: String,
+    pub kind: ArtifactKind,
+    pub source: ArtifactSource,
+    pub path: PathBuf,
+    /// Failing to download an optional artifact does not fail the deployment
+    pub optional: bool,
+    /// Hex encoded Ed25519 signature the downloaded file must match, see `signing.rs`
+    pub signature: Option<String>,
+    /// Base URLs of other supervisors to try before `source`, if the digest of the artifact is known
+    pub peers: Vec<String>,
+}
+
+/// The result of a `DownloadJob`: the digest of the file or an error, how long it took,
+/// how many times it was retried, whether the network could be skipped altogether, and the
+/// peer it was fetched from instead of its URL, if any.
+pub struct DownloadOutcome {
+    pub job: DownloadJob,
+    pub result: Result<String, String>,
+    pub duration: Duration,
+    pub stats: DownloadStats,
+    pub from_cache: bool,
+    pub peer: Option<String>,
+}
+
+/// Downloads all given artifacts with at most `concurrency` downloads running at once,
+/// all of them counting towards the same `limits`.
+///
+/// Artifacts with an expected digest that are already on the device are not downloaded,
+/// are fetched from the peers of the job if one has them, and newly downloaded ones are added
+/// to the shared cache. Artifacts with a signature are
+/// verified before they are cached, and removed if verification fails.
+///
+/// The files done and the file started last are reported to the progress of `deployment_id`.
+///
+/// Outcomes are returned in the order the downloads finish.
+pub async fn download_all(jobs: Vec<DownloadJob>, concurrency: usize, limits: &DownloadLimits, deployment_id: &str) -> Vec<DownloadOutcome> {
+    update_progress(deployment_id, |progress| {
+        progress.files_total = jobs.len();
+        progress.track_bytes(limits.deployment_bytes.clone());
+    });
+    stream::iter(jobs)
+        .map(|job| async move {
+            update_progress(deployment_id, |progress| {
+                progress.current_file = Some(job.path.to_string_lossy().to_string());
+            });
+            let started = Instant::now();
+            let mut stats = DownloadStats::default();
+            let cached = match job.source.sha256.clone() {
+                Some(expected) => {
+                    let path = job.path.clone();
+                    run_blocking(move || restore_from_cache(&expected, &path).then_some(expected)).await
+                        .ok()
+                        .flatten()
+                }
+                None => None,
+            };
+            let from_cache = cached.is_some();
+            // Peers serve artifacts by their digest, so only artifacts with one can come from them
+            let peer = match (&cached, &job.source.sha256) {
+                (None, Some(expected)) => fetch_from_peers(&job, expected, limits).await,
+                _ => None,
+            };
+            let mut result = match (cached, &peer) {
+                (Some(expected), _) => Ok(expected),
+                (None, Some(_)) => Ok(job.source.sha256.clone().unwrap_or_default()),
+                (None, None) => download_artifact(&job.source, &job.path, limits, &mut stats).await,
+            };
+            // Verify the signature before the file is cached or used for anything
+            if result.is_ok()
+                && let Some(signature) = job.signature.clone()
+            {
+                let path = job.path.clone();
+                let verified = run_blocking(move || verify_file(&path, &signature)).await
+                    .and_then(|verified| verified);
+                if let Err(e) = verified {
+                    tokio::fs::remove_file(&job.path).await.ok();
+                    result = Err(format!("Signature verification failed: {}", e));
+                }
+            }
+            if let Ok(digest) = result.clone()
+                && job.source.sha256.is_some()
+                && !from_cache
+            {
+                let path = job.path.clone();
+                run_blocking(move || store_in_cache(&digest, &path)).await.ok();
+            }
+            update_progress(deployment_id, |progress| {
+                progress.files_done += 1;
+                if let Err(e) = &result
+                    && !job.optional
+                {
+                    progress.errors.push(json!({ "module": job.module, "file": job.path, "error": e }));
+                }
+            });
+            DownloadOutcome { job, result, duration: started.elapsed(), stats, from_cache, peer }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Computes the hex encoded SHA-256 digest of a file on disk.
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Checks an already downloaded artifact against its stored digest and downloads it
+/// again if the file is missing or does not match.
+///
+/// Artifacts without a stored digest are only checked to exist.
+pub async fn verify_or_redownload(source: &ArtifactSource, path: &Path) -> Result<(), String> {
+    let Some(expected) = source.sha256.as_deref() else {
+        if path.exists() {
+            return Ok(());
+        }
+        log::warn!("Artifact {} is missing, downloading it again", path.display());
+        return download_artifact(source, path, &DownloadLimits::from_env(), &mut DownloadStats::default()).await.map(|_| ());
+    };
+    match file_sha256(path) {
+        Ok(actual) if check_digest(Some(expected), &actual).is_ok() => Ok(()),
+        _ if restore_from_cache(expected, path) => Ok(()),
+        Ok(actual) => {
+            log::warn!(
+                "Artifact {} does not match its stored digest (expected {} got {}), downloading it again",
+                path.display(), expected, actual
+            );
+            download_artifact(source, path, &DownloadLimits::from_env(), &mut DownloadStats::default()).await.map(|_| ())
+        }
+        Err(e) => {
+            log::warn!("Failed to read artifact {} ({}), downloading it again", path.display(), e);
+            download_artifact(source, path, &DownloadLimits::from_env(), &mut DownloadStats::default()).await.map(|_| ())
+        }
+    }
+}
+
+/// Verifies the binary and data files of a module, downloading again any that are missing
+/// or fail verification. Files without a known source can only be checked to exist.
+///
+/// # Returns
+/// A list of errors for the artifacts that could not be restored.
+pub async fn verify_module_artifacts(config: &ModuleConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    match &config.binary_source {
+        Some(source) => {
+            if let Err(e) = verify_or_redownload(source, &config.path).await {
+                errors.push(format!("Module '{}' binary: {}", config.name, e));
+            }
+        }
+        None if !config.path.exists() => {
+            errors.push(format!("Module '{}' binary: {} is missing and has no known source", config.name, config.path.display()));
+        }
+        None => {}
+    }
+
+    let mut filenames: Vec<&String> = config.data_files.keys().collect();
+    filenames.sort();
+    for filename in filenames {
+        let path = Path::new(&config.data_files[filename]);
+        match config.data_file_sources.get(filename) {
+            Some(source) => {
+                if let Err(e) = verify_or_redownload(source, path).await {
+                    errors.push(format!("Module '{}' file '{}': {}", config.name, filename, e));
+                }
+            }
+            None if !path.exists() => {
+                errors.push(format!("Module '{}' file '{}': {} is missing and has no known source", config.name, filename, path.display()));
+            }
+            None => {}
+        }
+    }
+    errors
+}
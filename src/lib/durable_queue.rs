@@ -0,0 +1,273 @@
+//! # durable_queue.rs
+//!
+//! Queue on disk for the requests to functions whose endpoint has `queueing: durable`, for
+//! pipelines that would rather have their requests run later than refused or piled onto a busy
+//! device.
+//!
+//! While every execution thread is busy, or earlier requests are still queued, such a request is
+//! appended to `EXECUTION_QUEUE_FILE_NAME` in the instance folder with the paths of its saved
+//! input files, and answered with 202 and its request ID. A worker runs the queued requests in
+//! order as threads free up. A request stays in the file until it has been run, so the queue is
+//! read back and run from the start after a restart, once the saved deployments are loaded.
+//! Beyond `WASMIOT_EXECUTION_QUEUE_MAX_DEPTH` waiting requests new ones are refused.
+//!
+//! As the execution threads are those of the process, so is the queue: requests queued by every
+//! supervisor the process serves wait in the same line. Each request keeps the `AppState` of the
+//! supervisor it was made to and is run and looked up in it. Only the requests to the supervisor
+//! of the process are written to the file, as the others' deployments do not outlive it.
+
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use crate::lib::api::make_history;
+use crate::lib::app_state::{process_state, scoped_app_state, with_app_state, AppState};
+use crate::lib::constants::{get_execution_queue_max_depth, get_execution_threads, EXECUTION_QUEUE_FILE_NAME, INSTANCE_PATH};
+use crate::lib::execution::execution_saturated;
+use crate::lib::startup::wait_for_startup;
+use crate::structs::request_entry::RequestEntry;
+
+/// How often the worker looks for free execution threads when nothing wakes it up.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A request in the queue.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QueuedRequest {
+    pub entry: RequestEntry,
+    #[serde(rename = "queuedAt")]
+    pub queued_at: DateTime<Utc>,
+    /// State of the supervisor the request was made to, unless it is the one of the process.
+    #[serde(skip)]
+    pub state: Option<Arc<AppState>>,
+}
+
+impl QueuedRequest {
+    /// Whether the request was made to the supervisor with the given state (see `current_state`).
+    fn made_to(&self, state: &Option<Arc<AppState>>) -> bool {
+        match (&self.state, state) {
+            (None, None) => true,
+            (Some(own), Some(other)) => Arc::ptr_eq(own, other),
+            _ => false,
+        }
+    }
+}
+
+/// Depth of the queue and how long its oldest request has waited, for `/health` and the metrics.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueueStats {
+    /// Requests waiting to be run
+    pub depth: usize,
+    /// Requests taken from the queue that are being run
+    pub running: usize,
+    /// Seconds the oldest waiting request has waited, if any are waiting
+    #[serde(rename = "oldestAgeSeconds")]
+    pub oldest_age_seconds: Option<f64>,
+}
+
+/// Why a request could not be queued.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnqueueError {
+    /// The queue already has the most requests allowed waiting.
+    Full(usize),
+    /// The queue file could not be written.
+    Failed(String),
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Whether the queue has been read from its file.
+    loaded: bool,
+    waiting: VecDeque<QueuedRequest>,
+    running: Vec<QueuedRequest>,
+}
+
+/// The queue, also held while its file is written so that writes are never interleaved.
+static QUEUE: Lazy<Mutex<QueueState>> = Lazy::new(|| Mutex::new(QueueState::default()));
+
+/// Wakes the worker when a request is queued or a queued request has been run.
+static CHANGED: Lazy<Notify> = Lazy::new(Notify::new);
+
+fn queue_file() -> PathBuf {
+    INSTANCE_PATH.join(EXECUTION_QUEUE_FILE_NAME)
+}
+
+/// State of the supervisor the running task serves, or `None` for the one of the process.
+fn current_state() -> Option<Arc<AppState>> {
+    scoped_app_state().filter(|state| !Arc::ptr_eq(state, &process_state()))
+}
+
+/// Reads the requests in the queue file, skipping lines that cannot be read.
+fn read_queue_file() -> VecDeque<QueuedRequest> {
+    let path = queue_file();
+    let Ok(contents) = fs::read_to_string(&path) else { return VecDeque::new() };
+    contents.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str::<QueuedRequest>(line) {
+            Ok(queued) => Some(queued),
+            Err(e) => {
+                log::warn!("Skipping an unreadable request in {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Writes the requests still in the queue over the queue file, those being run first.
+fn write_queue_file(state: &QueueState) -> Result<(), String> {
+    let path = queue_file();
+    let mut contents = String::new();
+    for queued in state.running.iter().chain(state.waiting.iter()).filter(|queued| queued.state.is_none()) {
+        let line = serde_json::to_string(queued).map_err(|e| e.to_string())?;
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+    let temporary = path.with_extension("jsonl.tmp");
+    fs::write(&temporary, contents)
+        .and_then(|_| fs::rename(&temporary, &path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn ensure_loaded(state: &mut QueueState) {
+    if !state.loaded {
+        state.loaded = true;
+        state.waiting = read_queue_file();
+    }
+}
+
+/// Reads the queue from its file, replacing the requests to the supervisor of the process in
+/// memory. The requests that were being run when the queue was written are run again, ahead of
+/// the others.
+///
+/// # Returns
+/// The number of requests waiting.
+pub fn load_queue() -> usize {
+    let mut state = QUEUE.lock();
+    state.loaded = true;
+    let others: Vec<QueuedRequest> = state.waiting.drain(..).filter(|queued| queued.state.is_some()).collect();
+    state.waiting = read_queue_file();
+    state.waiting.extend(others);
+    state.running.retain(|running| running.state.is_some());
+    state.waiting.len()
+}
+
+/// Whether a request to a function with durable queueing is queued rather than run now: every
+/// execution thread is busy, or earlier requests are still waiting and it must not overtake them.
+pub fn should_queue() -> bool {
+    let mut state = QUEUE.lock();
+    ensure_loaded(&mut state);
+    !state.waiting.is_empty() || execution_saturated()
+}
+
+/// Appends a request to the queue. Its input files are left where they were saved, and are
+/// removed once it has been run.
+///
+/// # Returns
+/// The position of the request in the queue, from 1.
+pub fn enqueue(entry: RequestEntry) -> Result<usize, EnqueueError> {
+    let mut state = QUEUE.lock();
+    ensure_loaded(&mut state);
+    let max_depth = get_execution_queue_max_depth();
+    if state.waiting.len() >= max_depth {
+        return Err(EnqueueError::Full(max_depth));
+    }
+    let queued = QueuedRequest { entry, queued_at: Utc::now(), state: current_state() };
+    if queued.state.is_none() {
+        let path = queue_file();
+        let line = serde_json::to_string(&queued).map_err(|e| EnqueueError::Failed(e.to_string()))?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(format!("{}\n", line).as_bytes()))
+            .map_err(|e| EnqueueError::Failed(format!("Failed to write {}: {}", path.display(), e)))?;
+    }
+    state.waiting.push_back(queued);
+    let position = state.waiting.len();
+    drop(state);
+    CHANGED.notify_one();
+    Ok(position)
+}
+
+/// A request to the supervisor the running task serves waiting in the queue, with its position
+/// from 1.
+pub fn queued_request(request_id: &str) -> Option<(usize, QueuedRequest)> {
+    let current = current_state();
+    let mut state = QUEUE.lock();
+    ensure_loaded(&mut state);
+    state.waiting.iter()
+        .position(|queued| queued.entry.request_id == request_id && queued.made_to(&current))
+        .map(|index| (index + 1, state.waiting[index].clone()))
+}
+
+/// Depth of the queue and the age of its oldest request.
+pub fn queue_stats() -> QueueStats {
+    let mut state = QUEUE.lock();
+    ensure_loaded(&mut state);
+    QueueStats {
+        depth: state.waiting.len(),
+        running: state.running.len(),
+        oldest_age_seconds: state.waiting.front()
+            .map(|queued| (Utc::now() - queued.queued_at).num_milliseconds().max(0) as f64 / 1000.0),
+    }
+}
+
+/// Runs a request taken from the queue in the state it was made in, then removes it from the
+/// queue file.
+async fn run_queued(queued: QueuedRequest) {
+    let request_id = queued.entry.request_id.clone();
+    let app = queued.state.clone();
+    with_app_state(app.clone().unwrap_or_else(process_state), make_history(queued.entry)).await;
+    let mut state = QUEUE.lock();
+    state.running.retain(|running| !(running.entry.request_id == request_id && running.made_to(&app)));
+    if let Err(e) = write_queue_file(&state) {
+        log::error!("{}", e);
+    }
+    drop(state);
+    CHANGED.notify_one();
+}
+
+/// Starts running queued requests in order, as long as there are execution threads free and
+/// no more queued requests are running than there are threads.
+///
+/// # Returns
+/// The number of requests started.
+pub fn drain_queue() -> usize {
+    let mut started = 0;
+    let threads = get_execution_threads();
+    let mut state = QUEUE.lock();
+    ensure_loaded(&mut state);
+    while state.running.len() < threads && !execution_saturated() {
+        let Some(queued) = state.waiting.pop_front() else { break };
+        state.running.push(queued.clone());
+        tokio::spawn(run_queued(queued));
+        started += 1;
+    }
+    if started > 0
+        && let Err(e) = write_queue_file(&state)
+    {
+        log::error!("{}", e);
+    }
+    started
+}
+
+/// Reads the queue left from before the last shutdown and runs the queued requests as execution
+/// threads free up, once the saved deployments have been loaded.
+pub async fn run_queue_worker() {
+    let waiting = load_queue();
+    wait_for_startup().await;
+    if waiting > 0 {
+        log::info!("Running {} requests left in the execution queue", waiting);
+    }
+    loop {
+        let changed = CHANGED.notified();
+        drain_queue();
+        let _ = tokio::time::timeout(POLL_INTERVAL, changed).await;
+    }
+}
@@ -0,0 +1,162 @@
+//! # execution.rs
+//!
+//! Threads that run Wasm functions apart from the HTTP workers.
+//!
+//! A Wasm function runs until it returns, so running it in an HTTP worker keeps the worker from
+//! answering other requests, such as health checks and file downloads, for as long. Executions
+//! are instead queued to `WASMIOT_EXECUTION_THREADS` threads started on first use, each with a
+//! runtime of its own, and the handler waits for the result without holding up its worker.
+//!
+//! Jobs wait for a thread in a queue per priority (see `Priority`), and the threads take the
+//! oldest job of the highest priority first, so that a latency-critical request does not wait
+//! behind a batch of bulk ones. With `WASMIOT_SHED_LOW_PRIORITY` set, low priority requests are
+//! refused instead of queued while every thread is busy.
+//!
+//! The `armv6` build runs its Wasm calls synchronously on a single HTTP worker, so running them
+//! there would leave the device unable to answer anything, `/healthz` included, until a long
+//! inference returns. It runs them on an execution thread as well, one by default, and the
+//! operating system time-slices the single core between it and the HTTP worker, which keeps
+//! answering while the handler of the execution waits for its result on a channel.
+
+use std::future::Future;
+use std::{collections::VecDeque, panic::AssertUnwindSafe, pin::Pin, thread};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use futures_util::FutureExt;
+use log::{debug, error, info};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::sync::{oneshot, Notify};
+use crate::lib::app_state::{scoped_app_state, with_app_state};
+use crate::lib::constants::{get_execution_threads, get_shed_low_priority};
+use crate::structs::request_entry::Priority;
+
+/// Name of the header a request asks for a priority with: `high`, `normal` or `low`.
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+/// Work queued to the execution threads. The future is created on the thread that runs it,
+/// so it does not need to be `Send`.
+type Job = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+/// Jobs waiting for an execution thread, a queue per priority from low to high.
+#[derive(Default)]
+struct ExecutionQueue {
+    jobs: Mutex<[VecDeque<Job>; 3]>,
+    /// Wakes an idle thread when a job is queued.
+    queued: Notify,
+    /// Number of threads running a job.
+    busy: AtomicUsize,
+    /// Number of threads started.
+    threads: AtomicUsize,
+}
+
+impl ExecutionQueue {
+    fn push(&self, priority: Priority, job: Job) {
+        self.jobs.lock()[priority as usize].push_back(job);
+        self.queued.notify_one();
+    }
+
+    /// Takes the oldest job of the highest priority.
+    fn pop(&self) -> Option<Job> {
+        self.jobs.lock().iter_mut().rev().find_map(VecDeque::pop_front)
+    }
+}
+
+/// Queue of the execution threads, started the first time something is executed.
+static EXECUTION_QUEUE: Lazy<&'static ExecutionQueue> = Lazy::new(start_execution_threads);
+
+/// Starts the execution threads, which take jobs from the returned queue one at a time.
+fn start_execution_threads() -> &'static ExecutionQueue {
+    let queue: &'static ExecutionQueue = Box::leak(Box::default());
+    let threads = get_execution_threads();
+    for i in 0..threads {
+        let started = thread::Builder::new()
+            .name(format!("wasm-execution-{}", i))
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        error!("Failed to start the runtime of execution thread {}: {}", i, e);
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    loop {
+                        let Some(job) = queue.pop() else {
+                            queue.queued.notified().await;
+                            continue;
+                        };
+                        queue.busy.fetch_add(1, Ordering::SeqCst);
+                        if AssertUnwindSafe(job()).catch_unwind().await.is_err() {
+                            error!("Execution on thread {} panicked", i);
+                        }
+                        queue.busy.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+                debug!("Execution thread {} stopped", i);
+            });
+        match started {
+            Ok(_) => {
+                queue.threads.fetch_add(1, Ordering::SeqCst);
+            }
+            Err(e) => error!("Failed to start execution thread {}: {}", i, e),
+        }
+    }
+    info!("Running Wasm functions on {} execution threads", threads);
+    queue
+}
+
+/// Starts the execution threads now instead of on the first execution.
+pub fn init_execution_threads() {
+    Lazy::force(&EXECUTION_QUEUE);
+}
+
+/// Runs the future made by `make` on an execution thread with normal priority and returns its
+/// output, as described for `run_with_priority`.
+pub async fn run_on_execution_thread<F, Fut, T>(make: F) -> Result<T, String>
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = T> + 'static,
+    T: Send + 'static,
+{
+    run_with_priority(Priority::Normal, make).await
+}
+
+/// Runs the future made by `make` on an execution thread and returns its output. The job is
+/// run before the jobs of lower priority that are waiting for a thread, serving the supervisor
+/// of the caller (see `app_state.rs`).
+///
+/// # Returns
+/// The output of the future, or an error if there are no execution threads to run it or it
+/// panicked.
+pub async fn run_with_priority<F, Fut, T>(priority: Priority, make: F) -> Result<T, String>
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = T> + 'static,
+    T: Send + 'static,
+{
+    if EXECUTION_QUEUE.threads.load(Ordering::SeqCst) == 0 {
+        return Err("No execution threads are running".to_string());
+    }
+    let (sender, receiver) = oneshot::channel();
+    let state = scoped_app_state();
+    let job: Job = Box::new(move || Box::pin(async move {
+        let output = match state {
+            Some(state) => with_app_state(state, make()).await,
+            None => make().await,
+        };
+        let _ = sender.send(output);
+    }));
+    EXECUTION_QUEUE.push(priority, job);
+    receiver.await.map_err(|_| "Execution stopped unexpectedly".to_string())
+}
+
+/// Whether every execution thread is running a function, so that a new request would wait.
+pub fn execution_saturated() -> bool {
+    EXECUTION_QUEUE.busy.load(Ordering::SeqCst) >= EXECUTION_QUEUE.threads.load(Ordering::SeqCst)
+}
+
+/// Whether a request of the given priority is refused rather than queued, because it is of low
+/// priority, `WASMIOT_SHED_LOW_PRIORITY` is set and every execution thread is busy.
+pub fn should_shed(priority: Priority) -> bool {
+    priority == Priority::Low && get_shed_low_priority() && execution_saturated()
+}
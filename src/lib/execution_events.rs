@@ -0,0 +1,57 @@
+//! # execution_events.rs
+//!
+//! Progress of executions, for callers that follow an execution as it runs instead of waiting
+//! for its result, like the streaming `RunFunctionStream` of the gRPC API (see `grpc.rs`).
+//!
+//! A caller watches a request by its ID before executing it, and `do_wasm_work` tells the
+//! watcher when the function starts, when it has returned, and each chained call made after it.
+//! Nothing is recorded for requests that nobody watches.
+
+use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use crate::structs::request_entry::ChainStep;
+
+/// Something that happened during an execution.
+#[derive(Debug, Clone)]
+pub enum ExecutionEvent {
+    /// The function started running.
+    Started,
+    /// The function returned this value.
+    Returned(Value),
+    /// A chained call to this URL is being made.
+    ChainedCall(String),
+    /// A chained call was made and recorded in `chain_trace` of the request.
+    ChainStep(ChainStep),
+}
+
+/// Watchers of executions, by request ID.
+static WATCHERS: Lazy<Mutex<HashMap<String, mpsc::UnboundedSender<ExecutionEvent>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Starts watching the execution of a request, replacing an earlier watcher of it.
+///
+/// # Returns
+/// The events of the execution, until `unwatch_execution` is called.
+pub fn watch_execution(request_id: &str) -> mpsc::UnboundedReceiver<ExecutionEvent> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    WATCHERS.lock().insert(request_id.to_string(), sender);
+    receiver
+}
+
+/// Stops watching the execution of a request.
+pub fn unwatch_execution(request_id: &str) {
+    WATCHERS.lock().remove(request_id);
+}
+
+/// Tells the watcher of a request, if any, about something that happened in its execution.
+pub fn emit_execution_event(request_id: &str, event: ExecutionEvent) {
+    let mut watchers = WATCHERS.lock();
+    if let Some(watcher) = watchers.get(request_id)
+        && watcher.send(event).is_err()
+    {
+        // Nobody is listening anymore
+        watchers.remove(request_id);
+    }
+}
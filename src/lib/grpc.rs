@@ -0,0 +1,323 @@
+//! # grpc.rs
+//!
+//! gRPC API next to the HTTP API, for control planes that speak gRPC. Enabled by setting
+//! `WASMIOT_GRPC_PORT` to the TCP port to listen on, in a supervisor built with the `grpc`
+//! feature.
+//!
+//! The `Supervisor` service of `proto/supervisor.proto` creates, deletes and lists deployments,
+//! runs functions and returns requests from the request history. Each call delegates to the
+//! function the HTTP handler uses, like `start_deployment` and `execute_function`, so that the
+//! two APIs cannot behave differently. Requests are sent as typed messages instead of JSON, and
+//! only the free-form manifests, arguments and results are carried as JSON text. Requests the
+//! HTTP API refuses are answered with the gRPC status closest to its HTTP status.
+//!
+//! `RunFunctionStream` runs a function like `RunFunction`, streaming the events of its
+//! execution (see `execution_events.rs`) as they happen, and the executed request last.
+
+use std::net::IpAddr;
+use crate::function_name;
+use crate::lib::constants::get_grpc_port;
+use crate::lib::logging::send_log;
+#[cfg(feature = "grpc")]
+use std::pin::Pin;
+#[cfg(feature = "grpc")]
+use actix_web::http::StatusCode;
+#[cfg(feature = "grpc")]
+use futures_util::Stream;
+#[cfg(feature = "grpc")]
+use serde_json::{json, Value};
+#[cfg(feature = "grpc")]
+use tokio::sync::mpsc;
+#[cfg(feature = "grpc")]
+use tonic::{Request, Response, Status};
+#[cfg(feature = "grpc")]
+use crate::lib::api::{
+    delete_deployment,
+    execute_function,
+    execution_response,
+    find_request,
+    list_deployments,
+    make_history,
+    prepare_function_call,
+    recent_requests,
+    start_deployment,
+};
+#[cfg(feature = "grpc")]
+use crate::lib::constants::get_max_file_bytes;
+#[cfg(feature = "grpc")]
+use crate::lib::execution_events::{unwatch_execution, watch_execution, ExecutionEvent};
+#[cfg(feature = "grpc")]
+use crate::structs::request_entry::{ChainStep, RequestEntry};
+
+/// Method requests executed over gRPC are recorded with in the request history.
+#[cfg(feature = "grpc")]
+const GRPC_METHOD: &str = "GRPC";
+
+/// Code generated from `proto/supervisor.proto` by `build.rs`.
+#[cfg(feature = "grpc")]
+#[allow(clippy::large_enum_variant)]
+pub mod proto {
+    tonic::include_proto!("supervisor.v1");
+}
+
+/// Serves gRPC on `WASMIOT_GRPC_PORT` of `bind_address`. Returns right away when no port is set.
+pub async fn run_grpc_server(bind_address: IpAddr) {
+    let Some(port) = get_grpc_port() else {
+        return;
+    };
+    let func_name = function_name!().to_string();
+    #[cfg(feature = "grpc")]
+    {
+        let listener = match tokio::net::TcpListener::bind((bind_address, port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                send_log("ERROR", &format!("Failed to listen for gRPC on {}:{}: {}", bind_address, port, e), &func_name, None).await;
+                return;
+            }
+        };
+        send_log("INFO", &format!("Serving gRPC at {}:{}", bind_address, port), &func_name, None).await;
+        if let Err(e) = serve_grpc(listener).await {
+            send_log("ERROR", &format!("gRPC server stopped: {}", e), &func_name, None).await;
+        }
+    }
+    #[cfg(not(feature = "grpc"))]
+    send_log(
+        "WARN",
+        &format!("Not serving gRPC on {}:{}, the supervisor was built without the grpc feature", bind_address, port),
+        &func_name,
+        None,
+    ).await;
+}
+
+/// Serves the `Supervisor` service on the connections of `listener`.
+#[cfg(feature = "grpc")]
+pub async fn serve_grpc(listener: tokio::net::TcpListener) -> Result<(), String> {
+    // Input files are sent in the messages, so they are held to the same limit as uploads
+    let max_message_bytes = usize::try_from(get_max_file_bytes()).unwrap_or(usize::MAX).saturating_add(64 * 1024);
+    let service = proto::supervisor_server::SupervisorServer::new(SupervisorService)
+        .max_decoding_message_size(max_message_bytes);
+    tonic::transport::Server::builder()
+        .add_service(service)
+        .serve_with_incoming(tonic::transport::server::TcpIncoming::from(listener))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The `Supervisor` service of `proto/supervisor.proto`.
+#[cfg(feature = "grpc")]
+#[derive(Debug, Default)]
+pub struct SupervisorService;
+
+#[cfg(feature = "grpc")]
+#[tonic::async_trait]
+impl proto::supervisor_server::Supervisor for SupervisorService {
+    async fn deploy(&self, request: Request<proto::DeployRequest>) -> Result<Response<proto::DeployResponse>, Status> {
+        let source = grpc_source(&request);
+        let request = request.into_inner();
+        let manifest: Value = serde_json::from_str(&request.manifest_json)
+            .map_err(|e| Status::invalid_argument(format!("Invalid manifest: {}", e)))?;
+        let deployment_id = manifest["deploymentId"].as_str().unwrap_or_default().to_string();
+        let (status, body) = start_deployment(manifest, &source, request.keep_partial, request.wait, || {}).await;
+        if !status.is_success() {
+            return Err(grpc_status(status, &body));
+        }
+        Ok(Response::new(proto::DeployResponse {
+            status: status.as_u16().into(),
+            deployment_id,
+            status_url: body["statusUrl"].as_str().unwrap_or_default().to_string(),
+            body_json: body.to_string(),
+        }))
+    }
+
+    async fn delete_deployment(&self, request: Request<proto::DeleteDeploymentRequest>) -> Result<Response<proto::DeleteDeploymentResponse>, Status> {
+        let source = grpc_source(&request);
+        let deployment_id = request.into_inner().deployment_id;
+        if !delete_deployment(&deployment_id, &source).await {
+            return Err(Status::not_found(format!("Deployment '{}' does not exist", deployment_id)));
+        }
+        Ok(Response::new(proto::DeleteDeploymentResponse {}))
+    }
+
+    async fn list_deployments(&self, _request: Request<proto::ListDeploymentsRequest>) -> Result<Response<proto::ListDeploymentsResponse>, Status> {
+        let deployments = list_deployments().await.into_iter()
+            .map(|deployment| proto::Deployment {
+                deployment_id: deployment["id"].as_str().unwrap_or_default().to_string(),
+                active: deployment["active"].as_bool().unwrap_or_default(),
+                degraded: deployment["degraded"].as_bool().unwrap_or_default(),
+                needs_secrets: deployment["needsSecrets"].as_array().into_iter().flatten()
+                    .filter_map(Value::as_str)
+                    .map(str::to_string)
+                    .collect(),
+                description_json: deployment.to_string(),
+            })
+            .collect();
+        Ok(Response::new(proto::ListDeploymentsResponse { deployments }))
+    }
+
+    async fn run_function(&self, request: Request<proto::RunFunctionRequest>) -> Result<Response<proto::RunFunctionResponse>, Status> {
+        let request = request.into_inner();
+        let args = function_args(&request.args_json)?;
+        let (entry, final_opt) = execute_function(
+            &request.deployment_id,
+            &request.module_name,
+            &request.function_name,
+            GRPC_METHOD,
+            args,
+            request.input,
+        ).await.map_err(|(status, e)| grpc_status(status, &json!({ "error": e })))?;
+        Ok(Response::new(run_function_response(&entry, final_opt)))
+    }
+
+    type RunFunctionStreamStream = Pin<Box<dyn Stream<Item = Result<proto::ExecutionEvent, Status>> + Send>>;
+
+    async fn run_function_stream(&self, request: Request<proto::RunFunctionRequest>) -> Result<Response<Self::RunFunctionStreamStream>, Status> {
+        let request = request.into_inner();
+        let args = function_args(&request.args_json)?;
+        let entry = prepare_function_call(
+            &request.deployment_id,
+            &request.module_name,
+            &request.function_name,
+            GRPC_METHOD,
+            args,
+            request.input,
+        ).await.map_err(|(status, e)| grpc_status(status, &json!({ "error": e })))?;
+
+        let request_id = entry.request_id.clone();
+        let mut events = watch_execution(&request_id);
+        // Stopping to watch once the execution has finished ends the events
+        let execution = tokio::spawn(async move {
+            let executed = make_history(entry).await;
+            unwatch_execution(&executed.0.request_id);
+            executed
+        });
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(async move {
+            // The execution goes on if the client stops listening, like over HTTP
+            while let Some(event) = events.recv().await {
+                let _ = sender.send(Ok(event_message(&request_id, event))).await;
+            }
+            let finished = match execution.await {
+                Ok((entry, final_opt)) => Ok(proto::ExecutionEvent {
+                    request_id,
+                    event: Some(proto::execution_event::Event::Finished(run_function_response(&entry, final_opt))),
+                }),
+                Err(e) => Err(Status::internal(format!("Execution stopped unexpectedly: {}", e))),
+            };
+            let _ = sender.send(finished).await;
+        });
+        let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|event| (event, receiver))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_request_history(&self, request: Request<proto::GetRequestHistoryRequest>) -> Result<Response<proto::GetRequestHistoryResponse>, Status> {
+        let request_id = request.into_inner().request_id;
+        let entries = match request_id.as_str() {
+            "" => recent_requests(),
+            request_id => {
+                let entry = find_request(request_id).await
+                    .ok_or_else(|| Status::not_found(format!("No request with ID '{}'", request_id)))?;
+                vec![entry]
+            }
+        };
+        Ok(Response::new(proto::GetRequestHistoryResponse {
+            entries: entries.iter().map(entry_message).collect(),
+        }))
+    }
+}
+
+/// Parses the arguments of a function, a JSON object or nothing.
+#[cfg(feature = "grpc")]
+fn function_args(args_json: &str) -> Result<Value, Status> {
+    if args_json.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    match serde_json::from_str(args_json) {
+        Ok(args @ Value::Object(_)) => Ok(args),
+        Ok(_) => Err(Status::invalid_argument("Invalid arguments: expected a JSON object")),
+        Err(e) => Err(Status::invalid_argument(format!("Invalid arguments: {}", e))),
+    }
+}
+
+/// Who a gRPC request came from, for the audit log: the IP address of the peer, if known.
+#[cfg(feature = "grpc")]
+fn grpc_source<T>(request: &Request<T>) -> String {
+    request.remote_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "grpc".to_string())
+}
+
+/// gRPC status of an HTTP status the HTTP API refuses a request with, with the `error` of its
+/// body as the message.
+#[cfg(feature = "grpc")]
+fn grpc_status(status: StatusCode, body: &Value) -> Status {
+    let message = body["error"].as_str().map(str::to_string).unwrap_or_else(|| body.to_string());
+    let code = match status {
+        StatusCode::BAD_REQUEST => tonic::Code::InvalidArgument,
+        StatusCode::FORBIDDEN => tonic::Code::PermissionDenied,
+        StatusCode::NOT_FOUND | StatusCode::GONE => tonic::Code::NotFound,
+        StatusCode::CONFLICT => tonic::Code::AlreadyExists,
+        StatusCode::LOCKED => tonic::Code::FailedPrecondition,
+        StatusCode::PAYLOAD_TOO_LARGE | StatusCode::INSUFFICIENT_STORAGE => tonic::Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE => tonic::Code::Unavailable,
+        _ => tonic::Code::Internal,
+    };
+    Status::new(code, message)
+}
+
+/// Answer to an executed request, with what `execution_response` answers over HTTP.
+#[cfg(feature = "grpc")]
+fn run_function_response(entry: &RequestEntry, final_opt: Option<Value>) -> proto::RunFunctionResponse {
+    let response = execution_response(entry, final_opt);
+    proto::RunFunctionResponse {
+        entry: Some(entry_message(entry)),
+        result_url: response["resultUrl"].as_str().unwrap_or_default().to_string(),
+        result_json: response.get("result").map(Value::to_string),
+    }
+}
+
+/// Message of an event of an execution.
+#[cfg(feature = "grpc")]
+fn event_message(request_id: &str, event: ExecutionEvent) -> proto::ExecutionEvent {
+    use proto::execution_event::Event;
+    let event = match event {
+        ExecutionEvent::Started => Event::Started(proto::Started {}),
+        ExecutionEvent::Returned(value) => Event::ReturnedJson(value.to_string()),
+        ExecutionEvent::ChainedCall(url) => Event::ChainedCall(url),
+        ExecutionEvent::ChainStep(step) => Event::ChainStep(chain_step_message(&step)),
+    };
+    proto::ExecutionEvent { request_id: request_id.to_string(), event: Some(event) }
+}
+
+/// Message of a request in the request history.
+#[cfg(feature = "grpc")]
+fn entry_message(entry: &RequestEntry) -> proto::RequestEntry {
+    proto::RequestEntry {
+        request_id: entry.request_id.clone(),
+        deployment_id: entry.deployment_id.clone(),
+        module_name: entry.module_name.clone(),
+        function_name: entry.function_name.clone(),
+        method: entry.method.clone(),
+        request_args_json: entry.request_args.to_string(),
+        request_files: entry.request_files.clone(),
+        work_queued_at: entry.work_queued_at.to_rfc3339(),
+        result_json: entry.result.as_ref().map(Value::to_string),
+        outputs: entry.outputs.clone(),
+        success: entry.success,
+        aborted: entry.aborted,
+        chain_trace: entry.chain_trace.iter().map(chain_step_message).collect(),
+        traceparent: entry.traceparent.clone(),
+    }
+}
+
+/// Message of a chained call of a request.
+#[cfg(feature = "grpc")]
+fn chain_step_message(step: &ChainStep) -> proto::ChainStep {
+    proto::ChainStep {
+        url: step.url.clone(),
+        outputs: step.outputs.clone(),
+        mirrored: step.mirrored,
+        mirror_error: step.mirror_error.clone(),
+    }
+}
@@ -0,0 +1,255 @@
+//! # health.rs
+//!
+//! Periodic health sampling for the supervisor.
+//!
+//! A background task records a compact `HealthSample` every
+//! `WASMIOT_HEALTH_SAMPLE_INTERVAL_SECONDS` into a bounded ring buffer holding at most
+//! `WASMIOT_HEALTH_HISTORY_SIZE` samples, so the recent history of a device can be
+//! inspected without any external scraping.
+//!
+//! The same task refreshes the system metrics of the health endpoint every
+//! `WASMIOT_HEALTH_REFRESH_INTERVAL_MS` into an immutable `HealthSnapshot`, held in an
+//! `ArcSwap`. Health checks only load the `Arc` of the latest snapshot, without a lock, and
+//! report how old it is, so however often the device is polled, or however far the sampler has
+//! fallen behind, the `sysinfo` handles are only locked by the sampler. The first snapshot is
+//! taken at startup, before the HTTP server binds (see `seed_health_snapshot`). The snapshot
+//! also holds what the deployments use of the instance directory (see `device_limits.rs`), as
+//! measuring it walks the files of every deployment.
+//!
+//! The sampler reuses the shared `sysinfo` handles from `constants.rs` and never holds
+//! their locks across an `.await`.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use arc_swap::ArcSwap;
+use parking_lot::Mutex;
+use crate::lib::configuration::{instance_disk_available, instance_disk_usage, max_temperature};
+use crate::lib::device_limits::{device_usage, DeviceUsage};
+use crate::lib::constants::{
+    SYSTEM, DISKS, COMPONENTS, NETWORKS,
+    get_health_sample_interval, get_health_history_size, get_health_refresh_interval,
+};
+use crate::structs::device::{HealthSample, NetworkInterfaceUsage};
+
+/// Recorded health samples, oldest first.
+static HEALTH_HISTORY: Lazy<Mutex<VecDeque<HealthSample>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// The latest health snapshot, the first of which is taken when it is first needed.
+static HEALTH_SNAPSHOT: Lazy<ArcSwap<HealthSnapshot>> = Lazy::new(|| ArcSwap::from_pointee(take_health_snapshot()));
+
+/// Number of Wasm function executions currently running.
+static IN_FLIGHT_EXECUTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of Wasm function executions currently running, by deployment ID.
+static IN_FLIGHT_BY_DEPLOYMENT: Lazy<Mutex<HashMap<String, usize>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Marks a Wasm function execution of a deployment as in flight for as long as the guard is alive.
+pub struct ExecutionGuard {
+    deployment_id: String,
+}
+
+impl ExecutionGuard {
+    pub fn new(deployment_id: &str) -> Self {
+        IN_FLIGHT_EXECUTIONS.fetch_add(1, Ordering::Relaxed);
+        *IN_FLIGHT_BY_DEPLOYMENT.lock().entry(deployment_id.to_string()).or_default() += 1;
+        ExecutionGuard { deployment_id: deployment_id.to_string() }
+    }
+}
+
+impl Drop for ExecutionGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT_EXECUTIONS.fetch_sub(1, Ordering::Relaxed);
+        let mut by_deployment = IN_FLIGHT_BY_DEPLOYMENT.lock();
+        if let Some(count) = by_deployment.get_mut(&self.deployment_id) {
+            *count -= 1;
+            if *count == 0 {
+                by_deployment.remove(&self.deployment_id);
+            }
+        }
+    }
+}
+
+/// Returns the number of Wasm function executions currently running.
+pub fn in_flight_executions() -> usize {
+    IN_FLIGHT_EXECUTIONS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of Wasm function executions of a deployment currently running.
+pub fn in_flight_executions_of(deployment_id: &str) -> usize {
+    IN_FLIGHT_BY_DEPLOYMENT.lock().get(deployment_id).copied().unwrap_or(0)
+}
+
+/// System metrics of the device at one point in time, served by the health endpoint.
+#[derive(Debug, Clone)]
+pub struct HealthSnapshot {
+    pub taken_at: DateTime<Utc>,
+    /// CPU usage fraction (0..1)
+    pub cpu_usage: f32,
+    /// Memory usage fraction (0..1)
+    pub memory_usage: f32,
+    pub network_usage: HashMap<String, NetworkInterfaceUsage>,
+    /// Used fraction (0..1) of each disk
+    pub storage_usage: HashMap<String, f32>,
+    /// Used fraction (0..1) of the filesystem holding the instance path
+    pub instance_disk_usage: Option<f32>,
+    pub instance_disk_free_bytes: Option<u64>,
+    /// Hottest sensor in Celsius, if the device has any
+    pub temperature: Option<f32>,
+    /// What the deployments of the supervisor of the process use
+    pub device_usage: DeviceUsage,
+}
+
+impl HealthSnapshot {
+    /// How long ago the snapshot was taken.
+    pub fn age(&self) -> Duration {
+        (Utc::now() - self.taken_at).to_std().unwrap_or_default()
+    }
+
+    /// The compact sample of the snapshot recorded into the health history.
+    pub fn sample(&self) -> HealthSample {
+        HealthSample {
+            timestamp: self.taken_at,
+            cpu_usage: self.cpu_usage,
+            memory_usage: self.memory_usage,
+            instance_disk_free_bytes: self.instance_disk_free_bytes,
+            temperature: self.temperature,
+            in_flight_executions: in_flight_executions(),
+        }
+    }
+}
+
+/// Takes a health snapshot of the device.
+///
+/// Each shared handle is locked only for the duration of its refresh.
+pub fn take_health_snapshot() -> HealthSnapshot {
+    let (cpu_usage, memory_usage) = {
+        let mut sys = SYSTEM.lock();
+        sys.refresh_cpu_usage();
+        sys.refresh_memory();
+        let total = sys.total_memory() as f32;
+        let mem = if total > 0.0 { sys.used_memory() as f32 / total } else { 0.0 };
+        (sys.global_cpu_usage() / 100.0, mem)
+    };
+
+    let network_usage = {
+        let mut networks = NETWORKS.lock();
+        networks.refresh(true);
+        networks.iter()
+            .map(|(if_name, data)| (if_name.clone(), NetworkInterfaceUsage {
+                down_bytes: data.total_received(),
+                up_bytes: data.total_transmitted(),
+            }))
+            .collect()
+    };
+
+    let (storage_usage, instance_disk_usage, instance_disk_free_bytes) = {
+        let mut disks = DISKS.lock();
+        disks.refresh(true);
+        let storage_usage = disks.list().iter()
+            .map(|disk| {
+                let total = disk.total_space();
+                let used = if total > 0 {
+                    (total - disk.available_space()) as f32 / total as f32
+                } else {
+                    0.0
+                };
+                (disk.name().to_string_lossy().to_string(), used)
+            })
+            .collect();
+        (storage_usage, instance_disk_usage(&disks), instance_disk_available(&disks))
+    };
+
+    let temperature = {
+        let mut components = COMPONENTS.lock();
+        components.refresh(false);
+        max_temperature(&components)
+    };
+
+    HealthSnapshot {
+        taken_at: Utc::now(),
+        cpu_usage,
+        memory_usage,
+        network_usage,
+        storage_usage,
+        instance_disk_usage,
+        instance_disk_free_bytes,
+        temperature,
+        device_usage: device_usage(),
+    }
+}
+
+/// Takes a health sample of the device.
+pub fn take_health_sample() -> HealthSample {
+    take_health_snapshot().sample()
+}
+
+/// Takes a health snapshot and makes it the one served by the health endpoint.
+pub fn refresh_health_snapshot() -> Arc<HealthSnapshot> {
+    let snapshot = Arc::new(take_health_snapshot());
+    HEALTH_SNAPSHOT.store(snapshot.clone());
+    snapshot
+}
+
+/// Takes the first health snapshot, if it has not been taken yet. Called at startup before the
+/// HTTP server binds, so that no health check has to wait for one.
+pub fn seed_health_snapshot() {
+    Lazy::force(&HEALTH_SNAPSHOT);
+}
+
+/// Returns the latest health snapshot, however old it is. Its `age` tells how long ago it was
+/// taken.
+pub fn current_health_snapshot() -> Arc<HealthSnapshot> {
+    HEALTH_SNAPSHOT.load_full()
+}
+
+/// Appends a sample to the health history, dropping the oldest samples once the
+/// configured history size is reached.
+pub fn record_health_sample(sample: HealthSample) {
+    let capacity = get_health_history_size();
+    let mut history = HEALTH_HISTORY.lock();
+    while !history.is_empty() && history.len() >= capacity {
+        history.pop_front();
+    }
+    if capacity > 0 {
+        history.push_back(sample);
+    }
+}
+
+/// Returns recorded samples taken after `since` (if given), oldest first.
+///
+/// If `limit` is given, only the most recent `limit` of those samples are returned.
+pub fn get_health_history(since: Option<DateTime<Utc>>, limit: Option<usize>) -> Vec<HealthSample> {
+    let history = HEALTH_HISTORY.lock();
+    let matching: Vec<&HealthSample> = history
+        .iter()
+        .filter(|sample| since.is_none_or(|since| sample.timestamp > since))
+        .collect();
+    let skip = limit.map_or(0, |limit| matching.len().saturating_sub(limit));
+    matching.into_iter().skip(skip).cloned().collect()
+}
+
+/// Refreshes the health snapshot at the configured refresh interval, and records a sample of
+/// it into the health history at the configured sample interval, forever.
+///
+/// Meant to be spawned once at startup.
+pub async fn run_health_sampler() {
+    let mut last_sample: Option<Instant> = None;
+    // The intervals are read again every time, as they may change when the configuration is reloaded
+    loop {
+        match tokio::task::spawn_blocking(refresh_health_snapshot).await {
+            Ok(snapshot) => {
+                let sample_interval = Duration::from_secs(get_health_sample_interval());
+                if last_sample.is_none_or(|sampled| sampled.elapsed() >= sample_interval) {
+                    record_health_sample(snapshot.sample());
+                    last_sample = Some(Instant::now());
+                }
+            }
+            Err(e) => log::error!("Failed to refresh the health snapshot: {}", e),
+        }
+        tokio::time::sleep(Duration::from_millis(get_health_refresh_interval())).await;
+    }
+}
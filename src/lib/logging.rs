@@ -7,14 +7,71 @@
 //!
 //! It supports optional integration with `RequestEntry` to add metadata
 //! such as request ID, deployment ID, and module name.
+//!
+//! It also installs the local logger, whose level can be changed while the supervisor runs.
 
 use chrono::Utc;
 use serde_json::json;
-use std::env;
 use reqwest::Client;
 use crate::structs::request_entry::RequestEntry;
+use crate::lib::settings::get_setting;
 use std::collections::HashMap;
 use log::{info, debug, warn, error};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+
+/// Number of log messages currently waiting to be delivered to the logging server.
+static PENDING_LOGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns how many log messages are currently waiting to be delivered.
+pub fn pending_log_count() -> usize {
+    PENDING_LOGS.load(Ordering::Relaxed)
+}
+
+/// Local logger, set by `init_logger`.
+static LOGGER: OnceCell<&'static ReloadableLogger> = OnceCell::new();
+
+/// `env_logger` logger whose filter can be replaced at runtime.
+struct ReloadableLogger {
+    inner: RwLock<env_logger::Logger>,
+}
+
+impl log::Log for ReloadableLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.read().enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.inner.read().log(record)
+    }
+
+    fn flush(&self) {
+        self.inner.read().flush()
+    }
+}
+
+/// Installs the local logger with a level or filter like `info,supervisor=debug`.
+///
+/// This should be called once, before anything is logged.
+pub fn init_logger(filter: &str) {
+    let inner = env_logger::Builder::new().parse_filters(filter).build();
+    let max_level = inner.filter();
+    let logger: &'static ReloadableLogger = Box::leak(Box::new(ReloadableLogger { inner: RwLock::new(inner) }));
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+        let _ = LOGGER.set(logger);
+    }
+}
+
+/// Replaces the level or filter of the local logger, if it has been installed.
+pub fn set_log_filter(filter: &str) {
+    if let Some(logger) = LOGGER.get() {
+        let inner = env_logger::Builder::new().parse_filters(filter).build();
+        log::set_max_level(inner.filter());
+        *logger.inner.write() = inner;
+    }
+}
 
 /// Sends a structured log message to the configured external logging server,
 /// if remote logging is enabled via the `EXTERNAL_LOGGING_ENABLED` env var.
@@ -39,7 +96,7 @@ pub async fn send_log(
     entry: Option<&RequestEntry>,
 ) {
     let remote_logging_enabled =
-        env::var("EXTERNAL_LOGGING_ENABLED").unwrap_or_else(|_| "false".to_string());
+        get_setting("EXTERNAL_LOGGING_ENABLED").unwrap_or_else(|| "false".to_string());
 
     if remote_logging_enabled == "true" {
         // Build base log payload
@@ -48,7 +105,7 @@ pub async fn send_log(
             "loglevel": level,
             "message": message,
             "funcName": func_name,
-            "deviceName": env::var("SUPERVISOR_NAME").unwrap_or_else(|_| "unknown".to_string()),
+            "deviceName": get_setting("SUPERVISOR_NAME").unwrap_or_else(|| "unknown".to_string()),
             "deviceIP": get_device_ip(),
         });
 
@@ -72,19 +129,21 @@ pub async fn send_log(
 
         // Send log
         let client = Client::new();
-        let endpoint = env::var("WASMIOT_LOGGING_ENDPOINT")
-            .unwrap_or_else(|_| "http://localhost:3000/device/logs".to_string());
+        let endpoint = get_setting("WASMIOT_LOGGING_ENDPOINT")
+            .unwrap_or_else(|| "http://localhost:3000/device/logs".to_string());
 
         let mut form_data = HashMap::new(); // The orhchestrator expects logs as form data instead of json
         let log_data_string = serde_json::to_string(&log_data).unwrap();
         form_data.insert("logData", log_data_string);
 
-        if let Err(e) = client
+        PENDING_LOGS.fetch_add(1, Ordering::Relaxed);
+        let result = client
             .post(&endpoint)
             .form(&form_data)
             .send()
-            .await
-        {
+            .await;
+        PENDING_LOGS.fetch_sub(1, Ordering::Relaxed);
+        if let Err(e) = result {
             eprintln!("Failed to send log: {:?}", e);
         }
     } else {
@@ -94,9 +153,9 @@ pub async fn send_log(
 
 /// Determines the current IP address of the device, falling back to localhost if unavailable.
 ///
-/// Checks the `WASMIOT_SUPERVISOR_IP` environment variable first.
+/// Checks the `WASMIOT_SUPERVISOR_IP` setting first.
 pub fn get_device_ip() -> String {
-    env::var("WASMIOT_SUPERVISOR_IP").unwrap_or_else(|_| {
+    get_setting("WASMIOT_SUPERVISOR_IP").unwrap_or_else(|| {
         local_ip_address::local_ip()
             .map(|ip| ip.to_string())
             .unwrap_or_else(|_| "127.0.0.1".to_string())
@@ -0,0 +1,317 @@
+//! # maintenance.rs
+//!
+//! Housekeeping of the instance directory.
+//!
+//! Crashes and failed deployments can leave folders under `modules/` and `params/` that no
+//! deployment refers to anymore. `collect_orphaned_folders` removes the folders whose name
+//! does not match any loaded deployment, once they are older than `WASMIOT_GC_GRACE_SECONDS`
+//! so that deployments still being created are left alone.
+//!
+//! Execution outputs, kept per request under `params/<deployment>/<module>/outputs/`, are
+//! limited by `enforce_result_retention`: outputs older than `WASMIOT_RESULT_MAX_AGE` are
+//! removed, and then the oldest outputs until the outputs of each deployment and of all
+//! deployments are under their byte caps. Other files in the params folders, such as the
+//! ML models given at deployment time, are never touched.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::lib::checksum::{is_sidecar, sidecar_path};
+use crate::lib::constants::{
+    MODULE_FOLDER,
+    PARAMS_FOLDER,
+    OUTPUTS_FOLDER_NAME,
+    get_result_max_age,
+    get_result_max_bytes,
+    get_result_max_deployment_bytes,
+};
+
+/// A folder without a matching deployment.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedFolder {
+    pub path: PathBuf,
+    pub bytes: u64,
+    #[serde(rename="ageSeconds")]
+    pub age_seconds: u64,
+}
+
+/// Outcome of a garbage collection pass.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    #[serde(rename="dryRun")]
+    pub dry_run: bool,
+    /// Folders removed, or that would be removed on a dry run
+    pub candidates: Vec<OrphanedFolder>,
+    /// Number of folders actually removed
+    pub directories: usize,
+    /// Number of bytes actually reclaimed
+    pub bytes: u64,
+    /// Folders that could not be removed
+    pub errors: Vec<String>,
+}
+
+/// Returns the total size of the files under `path`.
+pub fn tree_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => tree_size(&entry.path()),
+            _ => entry.metadata().map(|m| m.len()).unwrap_or(0),
+        })
+        .sum()
+}
+
+/// Removes the folders under `MODULE_FOLDER` and `PARAMS_FOLDER` that do not belong to any
+/// of `deployment_ids` and were last modified more than `grace` ago.
+///
+/// With `dry_run`, the folders are only listed as candidates.
+pub fn collect_orphaned_folders(deployment_ids: &HashSet<String>, grace: Duration, dry_run: bool) -> GcReport {
+    let mut report = GcReport { dry_run, ..Default::default() };
+    let now = SystemTime::now();
+    for root in [&*MODULE_FOLDER, &*PARAMS_FOLDER] {
+        let Ok(entries) = fs::read_dir(root) else { continue };
+        for entry in entries.filter_map(Result::ok) {
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            if deployment_ids.contains(&*entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let age = entry.metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .unwrap_or_default();
+            if age < grace {
+                continue;
+            }
+
+            let path = entry.path();
+            let candidate = OrphanedFolder { bytes: tree_size(&path), age_seconds: age.as_secs(), path };
+            if !dry_run {
+                match fs::remove_dir_all(&candidate.path) {
+                    Ok(_) => {
+                        report.directories += 1;
+                        report.bytes += candidate.bytes;
+                    }
+                    Err(e) => report.errors.push(format!("Failed to remove {}: {}", candidate.path.display(), e)),
+                }
+            }
+            report.candidates.push(candidate);
+        }
+    }
+    report
+}
+
+/// Limits on the storage used by execution outputs.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// Outputs older than this are removed, if set
+    pub max_age: Option<Duration>,
+    /// Cap on the total size of the outputs of all deployments, if set
+    pub max_bytes: Option<u64>,
+    /// Cap on the size of the outputs of each deployment, if set
+    pub max_deployment_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Creates the policy configured in env, where 0 disables a limit.
+    pub fn from_env() -> Self {
+        RetentionPolicy {
+            max_age: Some(get_result_max_age()).filter(|&s| s > 0).map(Duration::from_secs),
+            max_bytes: Some(get_result_max_bytes()).filter(|&b| b > 0),
+            max_deployment_bytes: Some(get_result_max_deployment_bytes()).filter(|&b| b > 0),
+        }
+    }
+}
+
+/// Outcome of enforcing the result retention policy.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RetentionReport {
+    #[serde(rename="deletedFiles")]
+    pub deleted_files: usize,
+    #[serde(rename="reclaimedBytes")]
+    pub reclaimed_bytes: u64,
+    /// Number of output files left
+    #[serde(rename="remainingFiles")]
+    pub remaining_files: usize,
+    /// Size of the output files left
+    #[serde(rename="remainingBytes")]
+    pub remaining_bytes: u64,
+    /// Files that could not be removed
+    pub errors: Vec<String>,
+}
+
+/// Totals of result retention since startup, reported in the health of the device.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResultStorageStats {
+    /// Size of the output files left by the latest cleanup
+    pub bytes: u64,
+    /// Number of output files left by the latest cleanup
+    pub files: usize,
+    #[serde(rename="deletedFiles")]
+    pub deleted_files: usize,
+    #[serde(rename="reclaimedBytes")]
+    pub reclaimed_bytes: u64,
+    #[serde(rename="lastCleanupAt")]
+    pub last_cleanup_at: Option<DateTime<Utc>>,
+}
+
+/// Result retention totals since startup.
+static RESULT_STORAGE_STATS: Lazy<Mutex<ResultStorageStats>> = Lazy::new(|| Mutex::new(ResultStorageStats::default()));
+
+/// Returns the result retention totals since startup.
+pub fn result_storage_stats() -> ResultStorageStats {
+    RESULT_STORAGE_STATS.lock().clone()
+}
+
+/// An output file of a request.
+struct OutputFile {
+    path: PathBuf,
+    deployment_id: String,
+    bytes: u64,
+    modified: SystemTime,
+    /// Whether the request is recent enough to keep the file in preference to others
+    preferred: bool,
+}
+
+/// Collects the files under `dir` into `files`.
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, fs::Metadata)>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.filter_map(Result::ok) {
+        let Ok(metadata) = entry.metadata() else { continue };
+        if metadata.is_dir() {
+            collect_files(&entry.path(), files);
+        } else if !is_sidecar(&entry.path()) {
+            files.push((entry.path(), metadata));
+        }
+    }
+}
+
+/// Lists the output files of every request of every deployment.
+fn collect_output_files(preferred_requests: &HashSet<String>) -> Vec<OutputFile> {
+    let mut outputs = Vec::new();
+    let Ok(deployments) = fs::read_dir(&*PARAMS_FOLDER) else { return outputs };
+    for deployment in deployments.filter_map(Result::ok) {
+        let deployment_id = deployment.file_name().to_string_lossy().to_string();
+        let Ok(modules) = fs::read_dir(deployment.path()) else { continue };
+        for module in modules.filter_map(Result::ok) {
+            let Ok(requests) = fs::read_dir(module.path().join(OUTPUTS_FOLDER_NAME)) else { continue };
+            for request in requests.filter_map(Result::ok) {
+                let preferred = preferred_requests.contains(&*request.file_name().to_string_lossy());
+                let mut files = Vec::new();
+                collect_files(&request.path(), &mut files);
+                outputs.extend(files.into_iter().map(|(path, metadata)| OutputFile {
+                    path,
+                    deployment_id: deployment_id.clone(),
+                    bytes: metadata.len(),
+                    modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    preferred,
+                }));
+            }
+        }
+    }
+    outputs
+}
+
+/// Removes an output file with its digest sidecar, and the folder of its request once it is empty.
+///
+/// # Returns
+/// Whether the file was removed.
+fn remove_output(output: &OutputFile, report: &mut RetentionReport) -> bool {
+    match fs::remove_file(&output.path) {
+        Ok(_) => {
+            fs::remove_file(sidecar_path(&output.path)).ok();
+            report.deleted_files += 1;
+            report.reclaimed_bytes += output.bytes;
+            if let Some(request_dir) = output.path.parent() {
+                // Only succeeds once the folder is empty
+                fs::remove_dir(request_dir).ok();
+            }
+            true
+        }
+        Err(e) => {
+            report.errors.push(format!("Failed to remove {}: {}", output.path.display(), e));
+            false
+        }
+    }
+}
+
+/// Removes the output files of requests as needed to satisfy `policy`.
+///
+/// Files older than the maximum age are removed first. Then, while the outputs of a deployment
+/// or of all deployments are over their caps, files are removed oldest first, leaving the files
+/// of `preferred_requests` for last. Request folders left empty are removed as well.
+pub fn enforce_result_retention(policy: &RetentionPolicy, preferred_requests: &HashSet<String>) -> RetentionReport {
+    let mut report = RetentionReport::default();
+    let now = SystemTime::now();
+    let mut outputs = collect_output_files(preferred_requests);
+    // Deletion order for the caps: files of other requests before preferred ones, oldest first
+    outputs.sort_by_key(|output| (output.preferred, output.modified));
+
+    let mut removed = vec![false; outputs.len()];
+
+    if let Some(max_age) = policy.max_age {
+        for (index, output) in outputs.iter().enumerate() {
+            let age = now.duration_since(output.modified).unwrap_or_default();
+            if age > max_age {
+                removed[index] = remove_output(output, &mut report);
+            }
+        }
+    }
+
+    if let Some(max_deployment_bytes) = policy.max_deployment_bytes {
+        let mut deployment_bytes: HashMap<&str, u64> = HashMap::new();
+        for (index, output) in outputs.iter().enumerate() {
+            if !removed[index] {
+                *deployment_bytes.entry(output.deployment_id.as_str()).or_default() += output.bytes;
+            }
+        }
+        for (index, output) in outputs.iter().enumerate() {
+            if removed[index] {
+                continue;
+            }
+            let bytes = deployment_bytes.entry(output.deployment_id.as_str()).or_default();
+            if *bytes > max_deployment_bytes && remove_output(output, &mut report) {
+                removed[index] = true;
+                *bytes -= output.bytes;
+            }
+        }
+    }
+
+    if let Some(max_bytes) = policy.max_bytes {
+        let mut total: u64 = outputs.iter().enumerate()
+            .filter(|(index, _)| !removed[*index])
+            .map(|(_, output)| output.bytes)
+            .sum();
+        for (index, output) in outputs.iter().enumerate() {
+            if !removed[index] && total > max_bytes && remove_output(output, &mut report) {
+                removed[index] = true;
+                total -= output.bytes;
+            }
+        }
+    }
+
+    for (index, output) in outputs.iter().enumerate() {
+        if !removed[index] {
+            report.remaining_files += 1;
+            report.remaining_bytes += output.bytes;
+        }
+    }
+
+    let mut stats = RESULT_STORAGE_STATS.lock();
+    stats.bytes = report.remaining_bytes;
+    stats.files = report.remaining_files;
+    stats.deleted_files += report.deleted_files;
+    stats.reclaimed_bytes += report.reclaimed_bytes;
+    stats.last_cleanup_at = Some(Utc::now());
+    report
+}
@@ -0,0 +1,252 @@
+//! # metrics.rs
+//!
+//! Metrics of the supervisor, served in the Prometheus text format on `GET /metrics` and pushed
+//! in the InfluxDB line protocol to a Telegraf HTTP listener.
+//!
+//! Both outputs are rendered from `collect_metrics`, the one place the metrics are listed and
+//! read, so that the two cannot disagree. Most values come from where the rest of the
+//! supervisor already keeps them, like the health snapshot, the result retention totals and the
+//! module cache. The executions of each deployment are counted here, by `record_execution`, and
+//! so are the bytes received and sent for requests, by `record_transferred`.
+//!
+//! When `WASMIOT_TELEGRAF_URL` is set, the metrics are posted there every
+//! `WASMIOT_TELEGRAF_INTERVAL_SECONDS`, tagged with the name of the supervisor as `device` and
+//! the deployment they are about as `deployment`. Failed posts are retried at a growing
+//! interval, and only the first failure and the recovery are logged at the default level.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sysinfo::System;
+use crate::lib::api::lock_deployments;
+use crate::lib::durable_queue::queue_stats;
+use crate::lib::constants::{get_telegraf_interval, get_telegraf_url, HTTP_CLIENT, SUPERVISOR_DEFAULT_NAME};
+use crate::lib::health::{current_health_snapshot, in_flight_executions_of};
+use crate::lib::logging::pending_log_count;
+use crate::lib::maintenance::result_storage_stats;
+use crate::lib::settings::get_setting;
+use crate::lib::shutdown::in_flight_requests;
+use crate::lib::wasmtime::module_cache_stats;
+
+/// Longest time between attempts to push metrics to a listener that keeps failing.
+const MAX_PUSH_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Whether a metric only grows, or goes up and down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+/// A metric and its current values, one for each set of labels.
+#[derive(Debug, Clone)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+    pub samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Metric {
+    fn new(name: &'static str, help: &'static str, kind: MetricKind) -> Self {
+        Metric { name, help, kind, samples: Vec::new() }
+    }
+
+    /// Adds a value without labels.
+    fn value(mut self, value: f64) -> Self {
+        self.samples.push((Vec::new(), value));
+        self
+    }
+
+    /// Adds a value with labels.
+    fn labelled(mut self, labels: Vec<(&'static str, String)>, value: f64) -> Self {
+        self.samples.push((labels, value));
+        self
+    }
+}
+
+/// Executions of each deployment since startup, successful and failed.
+static EXECUTIONS: Lazy<Mutex<BTreeMap<String, [u64; 2]>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Counts a finished execution of a deployment.
+pub fn record_execution(deployment_id: &str, success: bool) {
+    let mut executions = EXECUTIONS.lock();
+    let counts = executions.entry(deployment_id.to_string()).or_default();
+    counts[usize::from(!success)] += 1;
+}
+
+/// Bytes received and sent for requests since startup.
+static TRANSFERRED: Lazy<Mutex<[u64; 2]>> = Lazy::new(|| Mutex::new([0, 0]));
+
+/// Counts bytes received and sent for a request.
+pub fn record_transferred(bytes_in: u64, bytes_out: u64) {
+    let mut transferred = TRANSFERRED.lock();
+    transferred[0] += bytes_in;
+    transferred[1] += bytes_out;
+}
+
+/// Reads the current values of all metrics.
+pub fn collect_metrics() -> Vec<Metric> {
+    let snapshot = current_health_snapshot();
+    let result_storage = result_storage_stats();
+    let module_cache = module_cache_stats();
+    let execution_queue = queue_stats();
+    let mut deployment_ids: Vec<String> = lock_deployments().keys().cloned().collect();
+    deployment_ids.sort();
+    let [received_bytes, sent_bytes] = *TRANSFERRED.lock();
+
+    let mut executions = Metric::new(
+        "supervisor_executions_total", "Finished executions by deployment and outcome", MetricKind::Counter,
+    );
+    for (deployment_id, [succeeded, failed]) in EXECUTIONS.lock().iter() {
+        for (outcome, count) in [("success", succeeded), ("failure", failed)] {
+            let labels = vec![("deployment", deployment_id.clone()), ("outcome", outcome.to_string())];
+            executions = executions.labelled(labels, *count as f64);
+        }
+    }
+    let mut deployment_in_flight = Metric::new(
+        "supervisor_deployment_in_flight_executions", "Executions running by deployment", MetricKind::Gauge,
+    );
+    for deployment_id in &deployment_ids {
+        let running = in_flight_executions_of(deployment_id) as f64;
+        deployment_in_flight = deployment_in_flight.labelled(vec![("deployment", deployment_id.clone())], running);
+    }
+
+    vec![
+        Metric::new("supervisor_cpu_usage", "CPU usage of the device from 0 to 1", MetricKind::Gauge)
+            .value(f64::from(snapshot.cpu_usage)),
+        Metric::new("supervisor_memory_usage", "Memory usage of the device from 0 to 1", MetricKind::Gauge)
+            .value(f64::from(snapshot.memory_usage)),
+        Metric::new("supervisor_uptime_seconds", "Uptime of the device", MetricKind::Counter)
+            .value(System::uptime() as f64),
+        Metric::new("supervisor_deployments", "Deployments loaded", MetricKind::Gauge)
+            .value(deployment_ids.len() as f64),
+        Metric::new("supervisor_in_flight_executions", "Executions running", MetricKind::Gauge)
+            .value(in_flight_requests() as f64),
+        deployment_in_flight,
+        executions,
+        Metric::new("supervisor_received_bytes_total", "Bytes received for requests: bodies, chained call responses and fetched results", MetricKind::Counter)
+            .value(received_bytes as f64),
+        Metric::new("supervisor_sent_bytes_total", "Bytes sent for requests: chained call payloads, result files and request entries", MetricKind::Counter)
+            .value(sent_bytes as f64),
+        Metric::new("supervisor_execution_queue_depth", "Requests waiting in the durable execution queue", MetricKind::Gauge)
+            .value(execution_queue.depth as f64),
+        Metric::new("supervisor_execution_queue_oldest_age_seconds", "How long the oldest request in the durable execution queue has waited", MetricKind::Gauge)
+            .value(execution_queue.oldest_age_seconds.unwrap_or(0.0)),
+        Metric::new("supervisor_pending_logs", "Log entries waiting to be sent", MetricKind::Gauge)
+            .value(pending_log_count() as f64),
+        Metric::new("supervisor_result_storage_bytes", "Size of the execution outputs on the device", MetricKind::Gauge)
+            .value(result_storage.bytes as f64),
+        Metric::new("supervisor_result_storage_files", "Execution outputs on the device", MetricKind::Gauge)
+            .value(result_storage.files as f64),
+        Metric::new("supervisor_result_reclaimed_bytes_total", "Bytes of execution outputs removed by result retention", MetricKind::Counter)
+            .value(result_storage.reclaimed_bytes as f64),
+        Metric::new("supervisor_result_deleted_files_total", "Execution outputs removed by result retention", MetricKind::Counter)
+            .value(result_storage.deleted_files as f64),
+        Metric::new("supervisor_module_cache_modules", "Compiled modules shared by deployments", MetricKind::Gauge)
+            .value(module_cache.modules as f64),
+        Metric::new("supervisor_module_cache_hits_total", "Module loads served from the module cache", MetricKind::Counter)
+            .value(module_cache.hits as f64),
+    ]
+}
+
+/// Renders metrics in the Prometheus text format.
+pub fn render_prometheus(metrics: &[Metric]) -> String {
+    let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    let mut text = String::new();
+    for metric in metrics {
+        let kind = match metric.kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+        let _ = writeln!(text, "# HELP {} {}", metric.name, metric.help);
+        let _ = writeln!(text, "# TYPE {} {}", metric.name, kind);
+        for (labels, value) in &metric.samples {
+            let labels: Vec<String> = labels.iter()
+                .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                .collect();
+            if labels.is_empty() {
+                let _ = writeln!(text, "{} {}", metric.name, value);
+            } else {
+                let _ = writeln!(text, "{}{{{}}} {}", metric.name, labels.join(","), value);
+            }
+        }
+    }
+    text
+}
+
+/// Renders metrics in the InfluxDB line protocol, one line for each value with its labels as
+/// tags and `device` as an additional tag, timestamped at `timestamp_ns`.
+///
+/// Values that are not finite numbers are left out, as the line protocol cannot carry them.
+pub fn render_line_protocol(metrics: &[Metric], device: &str, timestamp_ns: i64) -> String {
+    let escape = |value: &str| value.replace('\\', "\\\\").replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ");
+    let mut text = String::new();
+    for metric in metrics {
+        for (labels, value) in metric.samples.iter().filter(|(_, value)| value.is_finite()) {
+            let mut tags: Vec<(&str, &str)> = labels.iter().map(|(name, value)| (*name, value.as_str())).collect();
+            tags.push(("device", device));
+            // Tags sorted by key are what InfluxDB handles best
+            tags.sort();
+            let tags: String = tags.iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(name, value)| format!(",{}={}", name, escape(value)))
+                .collect();
+            let _ = writeln!(text, "{}{} value={} {}", metric.name, tags, value, timestamp_ns);
+        }
+    }
+    text
+}
+
+/// Posts the current metrics to the Telegraf HTTP listener at `url`.
+async fn push_metrics(url: &str, timeout: Duration) -> Result<(), String> {
+    let device = get_setting("SUPERVISOR_NAME").unwrap_or_else(|| SUPERVISOR_DEFAULT_NAME.to_string());
+    let timestamp_ns = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    let body = render_line_protocol(&collect_metrics(), &device, timestamp_ns);
+    let response = HTTP_CLIENT.post(url)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .timeout(timeout)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        status => Err(format!("listener answered {}", status)),
+    }
+}
+
+/// Pushes the metrics to `WASMIOT_TELEGRAF_URL` for as long as it is set.
+///
+/// Meant to be spawned once at startup.
+pub async fn run_metrics_reporter() {
+    let mut failures: u32 = 0;
+    // The settings are read again every time, as they may change when the configuration is reloaded
+    while let Some(url) = get_telegraf_url() {
+        let interval = Duration::from_secs(get_telegraf_interval());
+        match push_metrics(&url, interval).await {
+            Ok(()) => {
+                if failures > 0 {
+                    log::info!("Pushing metrics to {} again after {} failed attempts", url, failures);
+                }
+                failures = 0;
+            }
+            Err(e) => {
+                failures += 1;
+                if failures == 1 {
+                    log::warn!("Failed to push metrics to {}, backing off: {}", url, e);
+                } else {
+                    log::debug!("Failed to push metrics to {} ({} attempts): {}", url, failures, e);
+                }
+            }
+        }
+        let delay = match failures {
+            0 => interval,
+            failures => (interval * 2u32.pow(failures.min(8))).min(MAX_PUSH_BACKOFF.max(interval)),
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
@@ -0,0 +1,245 @@
+//! # mqtt.rs
+//!
+//! MQTT transport for executions, for systems that only speak MQTT. Enabled by setting
+//! `WASMIOT_MQTT_BROKER` to the `host:port` of a broker.
+//!
+//! The supervisor subscribes to `<prefix>/<device>/exec/<deployment>/<module>/<function>`, where
+//! `<prefix>` is `WASMIOT_MQTT_TOPIC_PREFIX` and `<device>` the name of the supervisor. The
+//! payload of a message is either a JSON object of the arguments of the function, or the raw
+//! contents of the input file of a function with a single execution stage mount. The function is
+//! executed like a request to `run_module_function`, and the same response, together with the
+//! `requestId` and whether it succeeded, is published to
+//! `<prefix>/<device>/results/<deployment>/<module>/<function>`. Small output files are
+//! included in it (see `inline_output`), larger ones are linked to.
+//!
+//! Only the functions listed in `mqttFunctions` of their deployment are executed, for the others
+//! an error is published. Deployments themselves are only managed with the HTTP API.
+//!
+//! `<prefix>/<device>/status` is `online` while the supervisor is connected. The broker sets it
+//! to `offline` with the last will of the connection when the supervisor goes away. A lost
+//! connection is retried with a growing delay, and the settings are read again on every attempt.
+//!
+//! The client is `rumqttc`, speaking MQTT 3.1.1 over plain TCP. The session is kept by the broker
+//! under a client ID made of the topics, rather than started clean on every connection, so the
+//! results still waiting for their PUBACK when the connection is lost are published again after
+//! the reconnect, and executions published at QoS 1 while the supervisor was away are delivered
+//! once it is back. Executions are subscribed to, and results published, at QoS 1; the QoS 2
+//! handshake is left to `rumqttc` in case a broker sends it anyway. A message larger than
+//! `WASMIOT_MAX_FILE_BYTES` cannot be read, so the session is started over to have the broker
+//! drop it, along with the results it had not acknowledged yet.
+
+use std::time::Duration;
+use rumqttc::mqttbytes::Error as PacketError;
+use rumqttc::{AsyncClient, ConnectionError, Event, Incoming, LastWill, MqttOptions, QoS, StateError, SubscribeReasonCode};
+use serde_json::{json, Value};
+use crate::function_name;
+use crate::lib::api::{execute_function, execution_response, get_deployment};
+use crate::lib::constants::{
+    SUPERVISOR_DEFAULT_NAME,
+    get_max_file_bytes, get_mqtt_broker, get_mqtt_credentials, get_mqtt_keep_alive, get_mqtt_topic_prefix,
+};
+use crate::lib::logging::send_log;
+use crate::lib::settings::get_setting;
+
+/// Port of the broker when `WASMIOT_MQTT_BROKER` does not give one.
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Time to wait for the broker to accept a connection, in seconds.
+const CONNECT_TIMEOUT_SECONDS: u64 = 10;
+
+/// Delay before the first reconnect, doubled on every failed attempt up to `MAX_RECONNECT_DELAY`.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Publishes and subscriptions waiting to be sent before new ones wait for room.
+const REQUEST_CAPACITY: usize = 64;
+
+/// The topics of this supervisor.
+#[derive(Debug, Clone)]
+pub struct Topics {
+    /// `<prefix>/<device>`, which the other topics start with.
+    pub base: String,
+}
+
+impl Topics {
+    /// The topics of the current topic prefix and supervisor name.
+    pub fn current() -> Self {
+        let device = get_setting("SUPERVISOR_NAME").unwrap_or_else(|| SUPERVISOR_DEFAULT_NAME.to_string());
+        Topics { base: format!("{}/{}", get_mqtt_topic_prefix(), device) }
+    }
+
+    /// The filter subscribed to for executions.
+    pub fn exec_filter(&self) -> String {
+        format!("{}/exec/+/+/+", self.base)
+    }
+
+    /// The topic of the availability of the supervisor, `online` or `offline`.
+    pub fn status(&self) -> String {
+        format!("{}/status", self.base)
+    }
+
+    /// The topic the results of executions of a function are published to.
+    pub fn result(&self, deployment_id: &str, module_name: &str, function_name: &str) -> String {
+        format!("{}/results/{}/{}/{}", self.base, deployment_id, module_name, function_name)
+    }
+
+    /// Splits an execution topic into the deployment, module and function it is for.
+    pub fn parse_exec(&self, topic: &str) -> Option<(String, String, String)> {
+        let rest = topic.strip_prefix(&self.base)?.strip_prefix("/exec/")?;
+        match rest.split('/').collect::<Vec<_>>().as_slice() {
+            [deployment_id, module_name, function_name]
+                if !deployment_id.is_empty() && !module_name.is_empty() && !function_name.is_empty() =>
+            {
+                Some((deployment_id.to_string(), module_name.to_string(), function_name.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What the connection to the broker is made with.
+#[derive(Debug, Clone, PartialEq)]
+struct Session {
+    broker: String,
+    credentials: Option<(String, Option<String>)>,
+    keep_alive: u16,
+    topics: String,
+}
+
+impl Session {
+    /// The settings of the connection, or `None` if no broker is set.
+    fn current() -> Option<Self> {
+        Some(Session {
+            broker: get_mqtt_broker()?,
+            credentials: get_mqtt_credentials(),
+            keep_alive: get_mqtt_keep_alive(),
+            topics: Topics::current().base,
+        })
+    }
+
+    /// Options of a persistent session with a retained `offline` as the last will.
+    fn options(&self) -> MqttOptions {
+        let (host, port) = broker_address(&self.broker);
+        let topics = Topics { base: self.topics.clone() };
+        let client_id = format!("wasmiot-{}", topics.base.replace('/', "-"));
+        let max_packet_bytes = usize::try_from(get_max_file_bytes().saturating_add(u64::from(u16::MAX) + 4))
+            .unwrap_or(usize::MAX);
+        let mut options = MqttOptions::new(client_id, host, port);
+        options
+            .set_clean_session(false)
+            .set_keep_alive(Duration::from_secs(u64::from(self.keep_alive)))
+            .set_max_packet_size(max_packet_bytes, max_packet_bytes)
+            .set_last_will(LastWill::new(topics.status(), "offline", QoS::AtLeastOnce, true));
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username, password.clone().unwrap_or_default());
+        }
+        options
+    }
+}
+
+/// Keeps the supervisor connected to the MQTT broker of `WASMIOT_MQTT_BROKER`, executing the
+/// functions published to it. Returns when no broker is set.
+pub async fn run_mqtt_client() {
+    let func_name = function_name!().to_string();
+    let mut delay = MIN_RECONNECT_DELAY;
+    while let Some(session) = Session::current() {
+        let topics = Topics { base: session.topics.clone() };
+        let (client, mut eventloop) = AsyncClient::new(session.options(), REQUEST_CAPACITY);
+        eventloop.network_options.set_connection_timeout(CONNECT_TIMEOUT_SECONDS);
+        loop {
+            let error = match eventloop.poll().await {
+                Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                    delay = MIN_RECONNECT_DELAY;
+                    eventloop.mqtt_options.set_clean_session(false);
+                    send_log("INFO", &format!("Connected to MQTT broker {}, subscribing to {}", session.broker, topics.exec_filter()), &func_name, None).await;
+                    // Sent by the event loop, which must not wait for room for them
+                    let subscribed = client.try_subscribe(topics.exec_filter(), QoS::AtLeastOnce);
+                    let online = client.try_publish(topics.status(), QoS::AtLeastOnce, true, "online");
+                    match subscribed.and(online) {
+                        Ok(()) => continue,
+                        Err(e) => e.to_string(),
+                    }
+                }
+                Ok(Event::Incoming(Incoming::SubAck(ack))) if ack.return_codes.contains(&SubscribeReasonCode::Failure) => {
+                    send_log("WARN", &format!("MQTT broker {} refused the subscription to {}", session.broker, topics.exec_filter()), &func_name, None).await;
+                    continue;
+                }
+                Ok(Event::Incoming(Incoming::Publish(publish))) => {
+                    tokio::spawn(handle_message(topics.clone(), publish.topic, publish.payload.to_vec(), client.clone()));
+                    continue;
+                }
+                Ok(_) => continue,
+                Err(ConnectionError::MqttState(StateError::Deserialization(PacketError::PayloadSizeLimitExceeded(size)))) => {
+                    eventloop.mqtt_options.set_clean_session(true);
+                    format!("Dropping a message of {} bytes, larger than the largest allowed file, by starting the session over", size)
+                }
+                Err(e) => e.to_string(),
+            };
+            send_log("WARN", &format!("MQTT connection to {} failed: {}", session.broker, error), &func_name, None).await;
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            // Changed settings take a session of their own
+            if Session::current().as_ref() != Some(&session) {
+                break;
+            }
+        }
+    }
+}
+
+/// Executes the function a message was published for, and publishes the result.
+async fn handle_message(topics: Topics, topic: String, payload: Vec<u8>, client: AsyncClient) {
+    let func_name = function_name!().to_string();
+    let Some((deployment_id, module_name, function_name)) = topics.parse_exec(&topic) else {
+        send_log("WARN", &format!("Ignoring MQTT message to unexpected topic {}", topic), &func_name, None).await;
+        return;
+    };
+    let response = match execute(&deployment_id, &module_name, &function_name, payload).await {
+        Ok(response) => response,
+        Err(e) => {
+            send_log("WARN", &format!("Not executing {} from MQTT: {}", topic, e), &func_name, None).await;
+            json!({ "success": false, "error": e })
+        }
+    };
+    let result_topic = topics.result(&deployment_id, &module_name, &function_name);
+    if let Err(e) = client.publish(&result_topic, QoS::AtLeastOnce, false, response.to_string()).await {
+        send_log("WARN", &format!("Failed to publish to {}: {}", result_topic, e), &func_name, None).await;
+    }
+}
+
+/// Runs a function for a message if its deployment allows it.
+///
+/// # Returns
+/// The response to the execution with its `requestId` and `success`, and `error` if it failed,
+/// or an error if the function could not be executed at all.
+async fn execute(deployment_id: &str, module_name: &str, function_name: &str, payload: Vec<u8>) -> Result<Value, String> {
+    let shared = get_deployment(deployment_id)
+        .ok_or_else(|| format!("Deployment '{}' not found", deployment_id))?;
+    if !shared.lock().await.allows_mqtt(module_name, function_name) {
+        return Err(format!("Function '{}/{}' of deployment '{}' is not executable over MQTT", module_name, function_name, deployment_id));
+    }
+
+    // Anything but arguments is the input file of the function
+    let (request_args, input) = match serde_json::from_slice::<Value>(&payload) {
+        Ok(args) if args.is_object() => (args, None),
+        _ if payload.is_empty() => (json!({}), None),
+        _ => (json!({}), Some(payload)),
+    };
+    let (entry, final_opt) = execute_function(deployment_id, module_name, function_name, "MQTT", request_args, input).await
+        .map_err(|(_, e)| e)?;
+    let mut response = execution_response(&entry, final_opt);
+    response["requestId"] = json!(entry.request_id);
+    response["success"] = json!(entry.success);
+    if !entry.success {
+        response["error"] = entry.result.clone().unwrap_or(Value::Null);
+    }
+    Ok(response)
+}
+
+/// The host and port to connect to for a broker, which may be given as `mqtt://host[:port]`.
+fn broker_address(broker: &str) -> (String, u16) {
+    let address = broker.strip_prefix("mqtt://").unwrap_or(broker).trim_end_matches('/');
+    match address.rsplit_once(':').and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?))) {
+        Some((host, port)) => (host.to_string(), port),
+        None => (address.to_string(), DEFAULT_MQTT_PORT),
+    }
+}
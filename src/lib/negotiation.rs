@@ -0,0 +1,151 @@
+//! # negotiation.rs
+//!
+//! Content negotiation of responses by their `Accept` header, so that the same response can be
+//! read by machines as JSON or CBOR and by humans poking the device with a browser as HTML.
+//!
+//! JSON is the default, and is also answered for `Accept` values that are not understood
+//! instead of `406`, so that existing callers keep working. CBOR is the same structure as the
+//! JSON, encoded with `ciborium`. HTML is a minimal page laying the structure out as tables, and
+//! is only offered by the endpoints that list it.
+
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE, VARY};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Representations a response can be negotiated to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    Json,
+    Cbor,
+    Html,
+}
+
+impl Representation {
+    /// Media type of the representation.
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Representation::Json => "application/json",
+            Representation::Cbor => "application/cbor",
+            Representation::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    /// Whether the representation is acceptable for a media range of an `Accept` header.
+    fn matches(&self, range: &str) -> bool {
+        let essence = self.media_type().split(';').next().unwrap_or_default();
+        let (kind, _) = essence.split_once('/').unwrap_or_default();
+        range == essence || range == format!("{}/*", kind) || range == "*/*"
+    }
+}
+
+/// Picks the representation of a response from the `Accept` header of a request, among the
+/// `offered` ones in order of preference.
+///
+/// The offered representation with the highest quality in the header wins, and the one first
+/// in `offered` when there is a tie. JSON is picked when the header is missing or nothing
+/// offered is acceptable.
+pub fn negotiate_representation(req: &HttpRequest, offered: &[Representation]) -> Representation {
+    let Some(accept) = req.headers().get(ACCEPT).and_then(|accept| accept.to_str().ok()) else {
+        return Representation::Json;
+    };
+    let ranges: Vec<(String, f32)> = accept.split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_range = parts.next().filter(|media_range| !media_range.is_empty())?.to_ascii_lowercase();
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((media_range, quality))
+        })
+        .collect();
+
+    let mut best = (Representation::Json, 0.0);
+    for representation in offered {
+        // The most specific range matching the representation sets its quality
+        let quality = ranges.iter()
+            .filter(|(range, _)| representation.matches(range))
+            .min_by_key(|(range, _)| range.matches('*').count())
+            .map(|(_, quality)| *quality)
+            .unwrap_or(0.0);
+        if quality > best.1 {
+            best = (*representation, quality);
+        }
+    }
+    best.0
+}
+
+/// Builds a response of `body` in the given representation, with `title` as the heading of the
+/// HTML page.
+///
+/// Responses are marked to vary by `Accept`, so that caches keep the representations apart.
+pub fn negotiated_response<T: Serialize>(status: StatusCode, representation: Representation, title: &str, body: &T) -> HttpResponse {
+    let value = match serde_json::to_value(body) {
+        Ok(value) => value,
+        Err(e) => return HttpResponse::InternalServerError().json(json!({ "error": e.to_string() })),
+    };
+    let body = match representation {
+        Representation::Json => value.to_string().into_bytes(),
+        Representation::Cbor => {
+            let mut encoded = Vec::new();
+            if let Err(e) = ciborium::into_writer(&value, &mut encoded) {
+                return HttpResponse::InternalServerError().json(json!({
+                    "error": format!("Failed to encode the response as CBOR: {}", e)
+                }));
+            }
+            encoded
+        }
+        Representation::Html => html_page(title, &value).into_bytes(),
+    };
+    HttpResponse::build(status)
+        .insert_header((CONTENT_TYPE, representation.media_type()))
+        .insert_header((VARY, "Accept"))
+        .body(body)
+}
+
+/// A minimal HTML page showing a JSON value.
+fn html_page(title: &str, value: &Value) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+        <style>body{{font-family:sans-serif}}table{{border-collapse:collapse;margin:4px 0}}\
+        th,td{{border:1px solid #ccc;padding:2px 6px;text-align:left;vertical-align:top}}</style>\n\
+        </head>\n<body>\n<h1>{title}</h1>\n{body}\n</body>\n</html>\n",
+        title = escape_html(title),
+        body = html_value(value),
+    )
+}
+
+/// Lays a JSON value out as HTML: objects as tables, lists as numbered lists and URLs as links.
+fn html_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) if s.starts_with("http://") || s.starts_with("https://") => {
+            format!("<a href=\"{0}\">{0}</a>", escape_html(s))
+        }
+        Value::String(s) => escape_html(s),
+        Value::Object(map) if map.is_empty() => String::new(),
+        Value::Object(map) => {
+            let rows: String = map.iter()
+                .map(|(key, value)| format!("<tr><th>{}</th><td>{}</td></tr>", escape_html(key), html_value(value)))
+                .collect();
+            format!("<table>{}</table>", rows)
+        }
+        Value::Array(items) if items.is_empty() => String::new(),
+        Value::Array(items) => {
+            let items: String = items.iter().map(|item| format!("<li>{}</li>", html_value(item))).collect();
+            format!("<ol>{}</ol>", items)
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Escapes the characters that are special in HTML.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
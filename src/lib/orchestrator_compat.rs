@@ -0,0 +1,175 @@
+//! # orchestrator_compat.rs
+//!
+//! Differences between the versions of the orchestrator in use, kept in one place instead of
+//! as special cases around the supervisor.
+//!
+//! The supervisor and an orchestrator tell each other the version of the API they speak in
+//! the `X-Wasmiot-Api-Version` header. The supervisor sends the latest version it speaks on its
+//! responses and registrations, and the version an orchestrator sends, on `POST /register` or
+//! on its answer to a registration, is recorded for it in `OrchestratorState`. What is then
+//! sent to that orchestrator is shaped for the older of the two versions. An orchestrator that
+//! has not sent the header is assumed to speak `ApiVersion::CURRENT`, the version the
+//! supervisor has always spoken.
+//!
+//! | Version | Registration payload                                   | Logs posted to   | Twin pushed to |
+//! |---------|--------------------------------------------------------|------------------|----------------|
+//! | 1       | flat, without `previousName`                           | `/device/logs`   | not pushed     |
+//! | 2       | flat, with `previousName` after a rename               | `/device/logs`   | not pushed     |
+//! | 3       | `name`, `type`, `previousName`, `properties` and `endpoint: {host, port, addresses}` | `/api/logs` | `/api/device/twin` |
+//!
+//! Version 1 also posts deployments to `//deploy` (see `LEGACY_DEPLOY_PATH`).
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Header carrying the version of the API the sender speaks.
+pub const API_VERSION_HEADER: &str = "X-Wasmiot-Api-Version";
+
+/// Path version 1 of the orchestrator posts deployments to, served like `/deploy`.
+pub const LEGACY_DEPLOY_PATH: &str = "//deploy";
+
+/// Version of the API between the supervisor and the orchestrator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum ApiVersion {
+    V1 = 1,
+    V2 = 2,
+    V3 = 3,
+}
+
+impl ApiVersion {
+    /// Version assumed for orchestrators that do not say which one they speak.
+    pub const CURRENT: ApiVersion = ApiVersion::V2;
+
+    /// Latest version the supervisor speaks.
+    pub const LATEST: ApiVersion = ApiVersion::V3;
+
+    /// Parses the value of `X-Wasmiot-Api-Version`. Versions newer than the supervisor knows
+    /// of are taken as the latest one it does.
+    pub fn parse(value: &str) -> Option<ApiVersion> {
+        match value.trim().parse::<u32>().ok()? {
+            0 => None,
+            1 => Some(ApiVersion::V1),
+            2 => Some(ApiVersion::V2),
+            _ => Some(ApiVersion::V3),
+        }
+    }
+
+    /// Value of `X-Wasmiot-Api-Version` for this version.
+    pub fn header_value(self) -> String {
+        (self as u32).to_string()
+    }
+}
+
+/// What is known of an orchestrator the supervisor talks to.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrchestratorState {
+    /// Origin of the orchestrator, like `http://orchestrator:3000`.
+    pub origin: String,
+    /// Version agreed on with the orchestrator.
+    pub api_version: ApiVersion,
+    /// When the orchestrator last told its version.
+    pub negotiated_at: DateTime<Utc>,
+}
+
+/// Orchestrators that have told their version, by origin.
+static ORCHESTRATORS: Lazy<RwLock<HashMap<String, OrchestratorState>>> = Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Origin of an orchestrator URL, which its state is kept by, so that its endpoints share it.
+fn origin_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .map(|url| url.origin().ascii_serialization())
+        .unwrap_or_else(|_| url.trim_end_matches('/').to_string())
+}
+
+/// Records the version an orchestrator at `url` sent in `X-Wasmiot-Api-Version`, if any.
+///
+/// # Returns
+/// The version agreed on, the older of the one sent and `ApiVersion::LATEST`, or the
+/// version already known for the orchestrator when the header is missing or invalid.
+pub fn negotiate(url: &str, header: Option<&str>) -> ApiVersion {
+    let Some(version) = header.and_then(ApiVersion::parse) else {
+        return api_version_of(url);
+    };
+    let version = version.min(ApiVersion::LATEST);
+    let origin = origin_of(url);
+    ORCHESTRATORS.write().insert(origin.clone(), OrchestratorState {
+        origin,
+        api_version: version,
+        negotiated_at: Utc::now(),
+    });
+    version
+}
+
+/// Returns the version agreed on with the orchestrator at `url`, or `ApiVersion::CURRENT`.
+pub fn api_version_of(url: &str) -> ApiVersion {
+    orchestrator_state(url).map_or(ApiVersion::CURRENT, |state| state.api_version)
+}
+
+/// Returns what is known of the orchestrator at `url`, if it has told its version.
+pub fn orchestrator_state(url: &str) -> Option<OrchestratorState> {
+    ORCHESTRATORS.read().get(&origin_of(url)).cloned()
+}
+
+/// Endpoint logs are posted to on an orchestrator at `orchestrator_url`.
+pub fn logging_endpoint(orchestrator_url: &str, version: ApiVersion) -> String {
+    let path = match version {
+        ApiVersion::V1 | ApiVersion::V2 => "device/logs",
+        ApiVersion::V3 => "api/logs",
+    };
+    format!("{}/{}", orchestrator_url.trim_end_matches('/'), path)
+}
+
+/// Endpoint the device twin is pushed to on an orchestrator at `orchestrator_url`, if its
+/// version takes twins (see `twin.rs`).
+pub fn twin_endpoint(orchestrator_url: &str, version: ApiVersion) -> Option<String> {
+    match version {
+        ApiVersion::V1 | ApiVersion::V2 => None,
+        ApiVersion::V3 => Some(format!("{}/api/device/twin", orchestrator_url.trim_end_matches('/'))),
+    }
+}
+
+/// Endpoint the startup report is sent to on an orchestrator at `orchestrator_url`, if its
+/// version takes startup reports (see `startup.rs`).
+pub fn startup_report_endpoint(orchestrator_url: &str, version: ApiVersion) -> Option<String> {
+    match version {
+        ApiVersion::V1 | ApiVersion::V2 => None,
+        ApiVersion::V3 => Some(format!("{}/api/device/startup-report", orchestrator_url.trim_end_matches('/'))),
+    }
+}
+
+/// Shapes a registration, given in the flat form of version 2, for an orchestrator of
+/// `version`.
+pub fn registration_payload(registration: Value, version: ApiVersion) -> Value {
+    let Value::Object(mut fields) = registration else {
+        return registration;
+    };
+    match version {
+        ApiVersion::V1 => {
+            // Version 1 refuses fields it does not know
+            fields.remove("previousName");
+            Value::Object(fields)
+        }
+        ApiVersion::V2 => Value::Object(fields),
+        ApiVersion::V3 => {
+            let mut payload = json!({
+                "name": fields.remove("name"),
+                "type": fields.remove("type"),
+                "properties": fields.remove("properties"),
+                "endpoint": {
+                    "host": fields.remove("host"),
+                    "port": fields.remove("port"),
+                    "addresses": fields.remove("addresses"),
+                },
+            });
+            if let Some(previous_name) = fields.remove("previousName") {
+                payload["previousName"] = previous_name;
+            }
+            payload
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! # progress.rs
+//!
+//! Progress of deployments being created.
+//!
+//! Deployments are created in a background task, so the orchestrator polls
+//! `GET /deploy/{id}/status` for the progress recorded here instead of keeping the
+//! `POST /deploy` request open until every artifact has been downloaded. The progress of
+//! the latest creation attempt of each deployment ID is kept after it finishes, so that
+//! its outcome can still be read.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Progress of the latest creation attempt of each deployment, by deployment ID.
+static DEPLOYMENT_PROGRESS: Lazy<Mutex<HashMap<String, DeploymentProgress>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Step a deployment creation is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeploymentPhase {
+    /// Accepted, waiting for the background task to start
+    Queued,
+    /// Checking the manifest and its signatures
+    Verifying,
+    /// Downloading the artifacts of the modules
+    Downloading,
+    /// Setting up the runtimes of the modules
+    Initializing,
+    /// Running the healthchecks of the modules
+    Healthchecking,
+    /// The deployment was created
+    Completed,
+    /// The deployment could not be created, see `errors` and `result`
+    Failed,
+}
+
+impl DeploymentPhase {
+    /// Whether the creation has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, DeploymentPhase::Completed | DeploymentPhase::Failed)
+    }
+}
+
+/// Progress of creating one deployment.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeploymentProgress {
+    pub phase: DeploymentPhase,
+    #[serde(rename="filesDone")]
+    pub files_done: usize,
+    #[serde(rename="filesTotal")]
+    pub files_total: usize,
+    #[serde(rename="bytesDownloaded")]
+    pub bytes_downloaded: u64,
+    /// The artifact that was started last, while downloading
+    #[serde(rename="currentFile")]
+    pub current_file: Option<String>,
+    pub errors: Vec<Value>,
+    #[serde(rename="startedAt")]
+    pub started_at: DateTime<Utc>,
+    #[serde(rename="updatedAt")]
+    pub updated_at: DateTime<Utc>,
+    /// The HTTP status the creation finished with
+    #[serde(rename="statusCode")]
+    pub status_code: Option<u16>,
+    /// The response body the creation finished with
+    pub result: Option<Value>,
+    /// Bytes written by the downloads, read into `bytes_downloaded` when the progress is read
+    #[serde(skip)]
+    bytes_counter: Option<Arc<AtomicU64>>,
+}
+
+impl DeploymentProgress {
+    fn new() -> Self {
+        let now = Utc::now();
+        DeploymentProgress {
+            phase: DeploymentPhase::Queued,
+            files_done: 0,
+            files_total: 0,
+            bytes_downloaded: 0,
+            current_file: None,
+            errors: Vec::new(),
+            started_at: now,
+            updated_at: now,
+            status_code: None,
+            result: None,
+            bytes_counter: None,
+        }
+    }
+
+    /// Counts downloaded bytes from `counter`, shared with the downloads of the deployment.
+    pub fn track_bytes(&mut self, counter: Arc<AtomicU64>) {
+        self.bytes_counter = Some(counter);
+    }
+}
+
+/// Starts tracking a new creation attempt of a deployment, replacing the progress of any
+/// earlier attempt.
+///
+/// # Returns
+/// `false` without changing anything if the deployment is already being created.
+pub fn start_progress(deployment_id: &str) -> bool {
+    let mut progress = DEPLOYMENT_PROGRESS.lock();
+    if progress.get(deployment_id).is_some_and(|p| !p.phase.is_finished()) {
+        return false;
+    }
+    progress.insert(deployment_id.to_string(), DeploymentProgress::new());
+    true
+}
+
+/// Updates the progress of a deployment, starting to track it if it is not tracked yet.
+pub fn update_progress(deployment_id: &str, update: impl FnOnce(&mut DeploymentProgress)) {
+    let mut progress = DEPLOYMENT_PROGRESS.lock();
+    let entry = progress.entry(deployment_id.to_string()).or_insert_with(DeploymentProgress::new);
+    update(entry);
+    entry.updated_at = Utc::now();
+}
+
+/// Records the outcome of creating a deployment.
+pub fn finish_progress(deployment_id: &str, status: u16, result: &Value) {
+    update_progress(deployment_id, |progress| {
+        let succeeded = (200..300).contains(&status);
+        progress.phase = if succeeded { DeploymentPhase::Completed } else { DeploymentPhase::Failed };
+        if !succeeded && progress.errors.is_empty() {
+            progress.errors.push(result.get("error").cloned().unwrap_or(Value::Null));
+        }
+        progress.current_file = None;
+        progress.status_code = Some(status);
+        progress.result = Some(result.clone());
+    });
+}
+
+/// Returns the progress of the latest creation attempt of a deployment.
+pub fn get_progress(deployment_id: &str) -> Option<DeploymentProgress> {
+    let progress = DEPLOYMENT_PROGRESS.lock();
+    let mut progress = progress.get(deployment_id)?.clone();
+    if let Some(counter) = progress.bytes_counter.take() {
+        progress.bytes_downloaded = counter.load(Ordering::Relaxed);
+    }
+    Some(progress)
+}
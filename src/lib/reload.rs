@@ -0,0 +1,156 @@
+//! # reload.rs
+//!
+//! Reloading the configuration of a running supervisor.
+//!
+//! On `SIGHUP` or `POST /config/reload` the configuration file is read again, and the settings
+//! that changed in it are applied without a restart, so that running executions and the mDNS
+//! registration are kept. Most settings are read whenever they are used, so updating them in
+//! `settings.rs` is enough. The log level, health thresholds and public
+//! base URL are replaced in the running configuration, and the supervisor registers again to
+//! the orchestrator if its URL changed.
+//!
+//! Settings the server is started with (`RESTART_REQUIRED_SETTINGS`) keep their old value, and
+//! are reported as needing a restart. Environment variables given to the process and options
+//! given on the command line are not replaced by the file.
+
+use std::sync::Arc;
+use parking_lot::Mutex;
+use serde::Serialize;
+use crate::lib::api::invalidate_description_cache;
+use crate::lib::cli::Cli;
+use crate::lib::config_file::ConfigFile;
+use crate::lib::configuration::{apply_reloaded_settings, get_supervisor_config};
+use crate::lib::constants::{CONFIG_FILE_NAME, DEFAULT_SERVICE_RENEWAL_TIME};
+use crate::lib::logging::set_log_filter;
+use crate::lib::settings::{get_setting_with_source, remove_setting, set_setting};
+use crate::lib::zeroconf::{WebthingZeroconf, force_supervisor_registration};
+
+/// Settings that only take effect when the supervisor is restarted.
+pub const RESTART_REQUIRED_SETTINGS: &[&str] = &[
+    "supervisor.name",
+    "network.port",
+    "network.bind_address",
+    "network.advertise_address",
+    "limits.http_workers",
+    "limits.execution_threads",
+    "timeouts.drain_seconds",
+    "camera.enabled",
+];
+
+/// Outcome of reloading the configuration.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReloadReport {
+    /// Settings that changed and are in use now
+    pub applied: Vec<String>,
+    /// Settings that changed but are used only after a restart
+    #[serde(rename="requiresRestart")]
+    pub requires_restart: Vec<String>,
+    /// Keys in the configuration file that are not settings
+    #[serde(rename="unknownKeys")]
+    pub unknown_keys: Vec<String>,
+}
+
+/// Reads the configuration file again and applies the settings that changed.
+///
+/// `zc` is the service advertised over mDNS, refreshed when the orchestrator settings change.
+///
+/// # Returns
+/// The settings that changed, or an error if the configuration file cannot be read or is
+/// invalid, in which case the settings are left as they were.
+pub fn reload_configuration(zc: Option<&Arc<Mutex<WebthingZeroconf>>>) -> Result<ReloadReport, String> {
+    let startup = get_supervisor_config().startup;
+    let path = startup.config_file.clone()
+        .or_else(|| Some(startup.instance_path.join(CONFIG_FILE_NAME)).filter(|path| path.is_file()));
+    let mut report = ReloadReport::default();
+    let file = match &path {
+        Some(path) => {
+            let (file, unknown) = ConfigFile::load(path)?;
+            report.unknown_keys = unknown;
+            file
+        }
+        None => ConfigFile::default(),
+    };
+    // Keep the values the supervisor is running with for the settings used only at startup
+    let kept: Vec<_> = RESTART_REQUIRED_SETTINGS.iter()
+        .filter_map(|key| ConfigFile::setting_env(key))
+        .map(|name| (name, get_setting_with_source(name)))
+        .collect();
+    file.apply();
+    let changed = current_settings().map(|settings| startup.settings.changed_settings(&settings));
+    for (name, value) in kept {
+        match value {
+            Some((value, source)) => set_setting(name, value, source),
+            None => remove_setting(name),
+        }
+    }
+    for (key, _) in changed? {
+        if RESTART_REQUIRED_SETTINGS.contains(&key) {
+            report.requires_restart.push(key.to_string());
+        } else {
+            report.applied.push(key.to_string());
+        }
+    }
+
+    let settings = current_settings()?;
+    apply_reloaded_settings(&startup.settings, &settings)?;
+
+    let applied = |key: &str| report.applied.iter().any(|k| k == key);
+    if applied("logging.level") {
+        set_log_filter(settings.logging.level.as_deref().unwrap_or("info"));
+    }
+    if let Some(zc) = zc {
+        if applied("orchestrator.register_renewal_seconds") {
+            zc.lock().register_renewal_time = settings.orchestrator.register_renewal_seconds
+                .unwrap_or(DEFAULT_SERVICE_RENEWAL_TIME);
+        }
+        if applied("orchestrator.url") {
+            force_supervisor_registration(zc.clone());
+        }
+    }
+
+    // The description documents and the twin show the configuration
+    if !report.applied.is_empty() {
+        invalidate_description_cache();
+    }
+
+    if report.applied.is_empty() && report.requires_restart.is_empty() {
+        log::info!("Reloaded configuration, nothing changed");
+    } else if !report.applied.is_empty() {
+        log::info!("Reloaded configuration, applied changes to: {}", report.applied.join(", "));
+    }
+    if !report.requires_restart.is_empty() {
+        log::warn!("Changes to {} take effect only after a restart", report.requires_restart.join(", "));
+    }
+    for key in &report.unknown_keys {
+        log::warn!("Ignoring unknown setting '{}' in the configuration file", key);
+    }
+    Ok(report)
+}
+
+/// Returns the settings in the environment, read the same way as when the supervisor started.
+fn current_settings() -> Result<ConfigFile, String> {
+    Cli::parse_with_settings(["supervisor"])
+        .map(|(cli, _)| cli.startup_config().settings)
+        .map_err(|e| format!("Invalid settings: {}", e))
+}
+
+/// Reloads the configuration every time the process receives `SIGHUP`.
+///
+/// Meant to be spawned once at startup.
+#[cfg(unix)]
+pub async fn run_reload_on_sighup(zc: Arc<Mutex<WebthingZeroconf>>) {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            log::error!("Failed to listen for SIGHUP, the configuration can only be reloaded through the API: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        log::info!("Received SIGHUP, reloading configuration");
+        if let Err(e) = reload_configuration(Some(&zc)) {
+            log::error!("Failed to reload configuration: {}", e);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! # replication.rs
+//!
+//! Warm standby copies of deployments on other supervisors, so that when this device fails
+//! another one already has the deployment in place and only has to be told to take over.
+//!
+//! A deployment lists the supervisors to keep a copy on in `replicas`. After the deployment is
+//! created, and every `WASMIOT_REPLICA_SYNC_INTERVAL_SECONDS` after that, each replica is asked
+//! for its copy with `GET /deploy/{id}` and the SHA-256 digests it verified its artifacts
+//! against are compared with those of the artifacts here. A replica that does not have the
+//! deployment, or has other artifacts, is pushed the deployment like any pushed deployment
+//! (see `receive_pushed_deployment`), with the digests in the manifest for the replica to verify
+//! the artifacts against and `"standby": true`. Replicas that could not be reached are tried
+//! again on the next round, so that they are synced once they come back online.
+//!
+//! A standby copy is held paused until `POST /deploy/{id}/promote` activates it. Switching the
+//! traffic to it is up to the orchestrator. Copies that were promoted are left alone, and
+//! deleting the deployment here does not delete its copies, as they may have taken over.
+//! Secrets are never pushed to replicas and have to be supplied to them again.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::function_name;
+use crate::lib::api::{file_part, get_deployment, lock_deployments};
+use crate::lib::bundle::export_manifest;
+use crate::lib::cli::parse_http_url;
+use crate::lib::constants::{get_deployment_download_timeout, get_replica_sync_interval, HTTP_CLIENT};
+use crate::lib::deployment::Deployment;
+use crate::lib::download::file_sha256;
+use crate::lib::logging::send_log;
+
+/// How long a replica is given to answer for its copy of a deployment.
+const REPLICA_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Latest known status of the replicas of each deployment, by deployment ID.
+static REPLICAS: Lazy<Mutex<HashMap<String, Vec<ReplicaStatus>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Deployments whose replicas are being synced, so that rounds do not overlap.
+static SYNCING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// State of the copy of a deployment on a replica.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplicaState {
+    /// Not checked yet
+    Pending,
+    /// Holds the deployment with the same artifacts
+    Synced,
+    /// Could not be reached
+    Unreachable,
+    /// Refused the deployment or answered with an error
+    Failed,
+    /// Took over the deployment, and is no longer synced
+    Promoted,
+}
+
+/// Status of a replica of a deployment, as served at `GET /deploy/{id}/replicas`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicaStatus {
+    pub url: String,
+    pub state: ReplicaState,
+    /// When the replica last acknowledged holding the deployment
+    pub synced_at: Option<DateTime<Utc>>,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+impl ReplicaStatus {
+    fn pending(url: &str) -> Self {
+        ReplicaStatus {
+            url: url.to_string(),
+            state: ReplicaState::Pending,
+            synced_at: None,
+            checked_at: None,
+            error: None,
+        }
+    }
+}
+
+/// An artifact of a deployment, pushed in the part named after its module, or `<module>/<file>`
+/// for a data file.
+struct Artifact {
+    part: String,
+    path: PathBuf,
+    sha256: Option<String>,
+}
+
+/// Parses the `replicas` of a deployment manifest: a list of base URLs of supervisors.
+pub fn parse_replicas(value: Option<&Value>) -> Result<Vec<String>, String> {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        return Ok(Vec::new());
+    };
+    let replicas: Vec<String> = serde_json::from_value(value.clone())
+        .map_err(|_| "replicas must be a list of URLs".to_string())?;
+    replicas.iter()
+        .map(|replica| parse_http_url(replica)
+            .map(|url| url.trim_end_matches('/').to_string())
+            .map_err(|e| format!("Invalid replica '{}': {}", replica, e)))
+        .collect()
+}
+
+/// Latest known status of each of the given replicas of a deployment.
+pub fn replica_statuses(deployment_id: &str, replicas: &[String]) -> Vec<ReplicaStatus> {
+    let known = REPLICAS.lock().get(deployment_id).cloned().unwrap_or_default();
+    replicas.iter()
+        .map(|url| known.iter().find(|status| &status.url == url).cloned()
+            .unwrap_or_else(|| ReplicaStatus::pending(url)))
+        .collect()
+}
+
+/// Forgets the status of the replicas of a deployment, when it is deleted.
+pub fn forget_replicas(deployment_id: &str) {
+    REPLICAS.lock().remove(deployment_id);
+}
+
+/// Checks each replica of a deployment and pushes the deployment to those that do not hold
+/// the same artifacts, as described at the top of this module.
+pub async fn sync_replicas(deployment_id: &str) {
+    let func_name = function_name!().to_string();
+    if !SYNCING.lock().insert(deployment_id.to_string()) {
+        return;
+    }
+    let result = push_targets(deployment_id).await;
+    let (replicas, manifest, artifacts) = match result {
+        Ok(Some(targets)) => targets,
+        Ok(None) => {
+            SYNCING.lock().remove(deployment_id);
+            return;
+        }
+        Err(e) => {
+            SYNCING.lock().remove(deployment_id);
+            send_log("ERROR", &format!("Failed to sync the replicas of deployment '{}': {}", deployment_id, e), &func_name, None).await;
+            return;
+        }
+    };
+
+    for url in replicas {
+        let (state, error) = sync_replica(&url, deployment_id, &manifest, &artifacts).await;
+        let previous = {
+            let mut statuses = REPLICAS.lock();
+            let statuses = statuses.entry(deployment_id.to_string()).or_default();
+            let index = match statuses.iter().position(|status| status.url == url) {
+                Some(index) => index,
+                None => {
+                    statuses.push(ReplicaStatus::pending(&url));
+                    statuses.len() - 1
+                }
+            };
+            let status = &mut statuses[index];
+            let previous = status.state;
+            let now = Utc::now();
+            status.state = state;
+            status.checked_at = Some(now);
+            status.error = error.clone();
+            if state == ReplicaState::Synced {
+                status.synced_at = Some(now);
+            }
+            previous
+        };
+        // Only changes are logged, as replicas are checked over and over
+        if state != previous {
+            let (level, message) = match (&state, error) {
+                (ReplicaState::Synced, _) => ("INFO", format!("Replica {} of deployment '{}' is synced", url, deployment_id)),
+                (ReplicaState::Promoted, _) => ("WARN", format!("Replica {} of deployment '{}' has been promoted", url, deployment_id)),
+                (_, error) => ("WARN", format!(
+                    "Failed to sync replica {} of deployment '{}': {}",
+                    url, deployment_id, error.unwrap_or_default()
+                )),
+            };
+            send_log(level, &message, &func_name, None).await;
+        }
+    }
+    SYNCING.lock().remove(deployment_id);
+}
+
+/// Replicas of a deployment, and the manifest and artifacts to push to them, with the digest
+/// of each artifact in the manifest.
+///
+/// # Returns
+/// `None` if the deployment no longer exists or has no replicas.
+async fn push_targets(deployment_id: &str) -> Result<Option<(Vec<String>, Value, Vec<Artifact>)>, String> {
+    let Some(shared) = get_deployment(deployment_id) else {
+        return Ok(None);
+    };
+    let (replicas, mut manifest, artifacts) = {
+        let deployment = shared.lock().await;
+        if deployment.replicas.is_empty() {
+            return Ok(None);
+        }
+        let (manifest, _) = export_manifest(&deployment);
+        (deployment.replicas.clone(), manifest, artifacts_of(&deployment))
+    };
+
+    // Artifacts without a recorded digest are hashed, which may take a while for large files
+    let artifacts = tokio::task::spawn_blocking(move || {
+        artifacts.into_iter()
+            .map(|mut artifact| {
+                if artifact.sha256.is_none() {
+                    let digest = file_sha256(&artifact.path)
+                        .map_err(|e| format!("Failed to read {}: {}", artifact.path.display(), e))?;
+                    artifact.sha256 = Some(digest);
+                }
+                Ok(artifact)
+            })
+            .collect::<Result<Vec<Artifact>, String>>()
+    }).await.map_err(|e| e.to_string())??;
+
+    let digests: HashMap<&str, &str> = artifacts.iter()
+        .filter_map(|artifact| Some((artifact.part.as_str(), artifact.sha256.as_deref()?)))
+        .collect();
+    for module in manifest["modules"].as_array_mut().into_iter().flatten() {
+        let name = module["name"].as_str().unwrap_or_default().to_string();
+        let urls = &mut module["urls"];
+        urls["binary"] = json!({ "sha256": digests.get(name.as_str()) });
+        for (file, entry) in urls["other"].as_object_mut().into_iter().flatten() {
+            let Some(entry) = entry.as_object_mut() else { continue };
+            entry.remove("localPath");
+            entry.insert("sha256".to_string(), json!(digests.get(format!("{}/{}", name, file).as_str())));
+        }
+    }
+    manifest["standby"] = json!(true);
+    Ok(Some((replicas, manifest, artifacts)))
+}
+
+/// The binaries and data files of a deployment, with the digests they were verified against.
+fn artifacts_of(deployment: &Deployment) -> Vec<Artifact> {
+    let mut artifacts = Vec::new();
+    for config in &deployment._modules {
+        artifacts.push(Artifact {
+            part: config.name.clone(),
+            path: config.path.clone(),
+            sha256: config.binary_source.as_ref().and_then(|source| source.sha256.clone()),
+        });
+        for (filename, path) in &config.data_files {
+            artifacts.push(Artifact {
+                part: format!("{}/{}", config.name, filename),
+                path: PathBuf::from(path),
+                sha256: config.data_file_sources.get(filename).and_then(|source| source.sha256.clone()),
+            });
+        }
+    }
+    artifacts
+}
+
+/// Digests of the artifacts of a deployment as described by a supervisor, by part name.
+fn described_digests(deployment: &Value) -> HashMap<String, String> {
+    let mut digests = HashMap::new();
+    for module in deployment["_modules"].as_array().into_iter().flatten() {
+        let name = module["name"].as_str().unwrap_or_default();
+        if let Some(digest) = module["binary_source"]["sha256"].as_str() {
+            digests.insert(name.to_string(), digest.to_string());
+        }
+        for (file, source) in module["data_file_sources"].as_object().into_iter().flatten() {
+            if let Some(digest) = source["sha256"].as_str() {
+                digests.insert(format!("{}/{}", name, file), digest.to_string());
+            }
+        }
+    }
+    digests
+}
+
+/// Checks the copy of a deployment on one replica and pushes the deployment if it is missing
+/// or has other artifacts.
+///
+/// # Returns
+/// The state of the replica, and the error if it could not be synced.
+async fn sync_replica(url: &str, deployment_id: &str, manifest: &Value, artifacts: &[Artifact]) -> (ReplicaState, Option<String>) {
+    let endpoint = format!("{}/deploy/{}", url, urlencoding::encode(deployment_id));
+    let response = match HTTP_CLIENT.get(&endpoint).timeout(REPLICA_CHECK_TIMEOUT).send().await {
+        Ok(response) => response,
+        Err(e) => return (ReplicaState::Unreachable, Some(format!("Request to {} failed: {}", endpoint, e))),
+    };
+    let status = response.status();
+    if status.is_success() {
+        let copy: Value = match response.json().await {
+            Ok(copy) => copy,
+            Err(e) => return (ReplicaState::Failed, Some(format!("Invalid answer from {}: {}", endpoint, e))),
+        };
+        if copy["standby"] != json!(true) {
+            return (ReplicaState::Promoted, None);
+        }
+        let expected: HashMap<String, String> = artifacts.iter()
+            .filter_map(|artifact| Some((artifact.part.clone(), artifact.sha256.clone()?)))
+            .collect();
+        if described_digests(&copy) == expected {
+            return (ReplicaState::Synced, None);
+        }
+    } else if status != reqwest::StatusCode::NOT_FOUND && status != reqwest::StatusCode::GONE {
+        return (ReplicaState::Failed, Some(format!("{} answered {}", endpoint, status)));
+    }
+
+    match push_deployment(url, manifest, artifacts).await {
+        Ok(()) => (ReplicaState::Synced, None),
+        Err(e) => e,
+    }
+}
+
+/// Pushes a deployment to a replica and waits for the replica to create it.
+async fn push_deployment(url: &str, manifest: &Value, artifacts: &[Artifact]) -> Result<(), (ReplicaState, Option<String>)> {
+    let failed = |e: String| (ReplicaState::Failed, Some(e));
+    let mut form = reqwest::multipart::Form::new()
+        .text("manifest", manifest.to_string());
+    for artifact in artifacts {
+        form = form.part(artifact.part.clone(), file_part(&artifact.path).await.map_err(failed)?);
+    }
+
+    let endpoint = format!("{}/deploy?wait=true", url);
+    let response = HTTP_CLIENT.post(&endpoint)
+        .multipart(form)
+        .timeout(Duration::from_secs(get_deployment_download_timeout()))
+        .send()
+        .await
+        .map_err(|e| (ReplicaState::Unreachable, Some(format!("Request to {} failed: {}", endpoint, e))))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body: Value = response.json().await.unwrap_or_default();
+    Err(failed(match body["error"].as_str() {
+        Some(error) => format!("{} answered {}: {}", endpoint, status, error),
+        None => format!("{} answered {}", endpoint, status),
+    }))
+}
+
+/// Syncs the replicas of every deployment that has any every
+/// `WASMIOT_REPLICA_SYNC_INTERVAL_SECONDS`.
+pub async fn run_replica_sync() {
+    loop {
+        tokio::time::sleep(Duration::from_secs(get_replica_sync_interval())).await;
+        let deployment_ids: Vec<String> = lock_deployments().keys().cloned().collect();
+        for deployment_id in deployment_ids {
+            sync_replicas(&deployment_id).await;
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! # request_history.rs
+//!
+//! The history of request executions served at `/request-history`.
+//!
+//! The orchestrator polls `/request-history/{request_id}` while it waits for the results of
+//! chained calls, so the entries are indexed by request ID for lookups that cost the same no
+//! matter how long the history is, while keeping the order they were recorded in for listing.
+//!
+//! At most `WASMIOT_REQUEST_HISTORY_MAX_ENTRIES` entries are kept in memory. The oldest ones
+//! over that are evicted to `REQUEST_ARCHIVE_FOLDER`, one file per request named after its ID,
+//! so that result URLs given out earlier still resolve. Archived entries are removed along with
+//! the outputs once they are older than `WASMIOT_RESULT_MAX_AGE`, when the result retention
+//! policy is enforced.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use indexmap::IndexMap;
+use crate::lib::constants::REQUEST_ARCHIVE_FOLDER;
+use crate::structs::request_entry::RequestEntry;
+
+/// Request entries by request ID, in the order they were recorded.
+#[derive(Debug, Default)]
+pub struct RequestHistory {
+    entries: IndexMap<String, RequestEntry>,
+}
+
+impl RequestHistory {
+    pub fn new() -> Self {
+        RequestHistory::default()
+    }
+
+    /// Returns the entry of the given request, if it is in memory.
+    pub fn get(&self, request_id: &str) -> Option<&RequestEntry> {
+        self.entries.get(request_id)
+    }
+
+    /// Returns the entry of the given request for updating it, if it is in memory.
+    pub fn get_mut(&mut self, request_id: &str) -> Option<&mut RequestEntry> {
+        self.entries.get_mut(request_id)
+    }
+
+    /// Iterates over the entries, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &RequestEntry> {
+        self.entries.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records an entry as the newest, replacing an earlier entry of the same request.
+    ///
+    /// # Returns
+    /// The oldest entries that no longer fit in `max_entries`, for `archive_requests`.
+    pub fn push(&mut self, entry: RequestEntry, max_entries: usize) -> Vec<RequestEntry> {
+        self.entries.shift_remove(&entry.request_id);
+        self.entries.insert(entry.request_id.clone(), entry);
+        self.evict(max_entries)
+    }
+
+    /// Records entries older than the ones in the history, such as those saved on the last shutdown.
+    ///
+    /// # Returns
+    /// The oldest entries that no longer fit in `max_entries`, for `archive_requests`.
+    pub fn prepend(&mut self, entries: Vec<RequestEntry>, max_entries: usize) -> Vec<RequestEntry> {
+        let mut merged: IndexMap<String, RequestEntry> = entries.into_iter()
+            .map(|entry| (entry.request_id.clone(), entry))
+            .collect();
+        for (request_id, entry) in self.entries.drain(..) {
+            merged.shift_remove(&request_id);
+            merged.insert(request_id, entry);
+        }
+        self.entries = merged;
+        self.evict(max_entries)
+    }
+
+    /// Removes the oldest entries over `max_entries`.
+    fn evict(&mut self, max_entries: usize) -> Vec<RequestEntry> {
+        let excess = self.entries.len().saturating_sub(max_entries);
+        self.entries.drain(..excess).map(|(_, entry)| entry).collect()
+    }
+}
+
+/// Path of the archived entry of a request, or `None` if the ID cannot be a file name.
+fn archive_path(request_id: &str) -> Option<PathBuf> {
+    let valid = !request_id.is_empty() && request_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| REQUEST_ARCHIVE_FOLDER.join(format!("{}.json", request_id)))
+}
+
+/// Writes entries evicted from the request history to `REQUEST_ARCHIVE_FOLDER`.
+pub fn archive_requests(entries: &[RequestEntry]) -> Result<(), String> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(&*REQUEST_ARCHIVE_FOLDER).map_err(|e| {
+        format!("Failed to create request archive {}: {}", REQUEST_ARCHIVE_FOLDER.display(), e)
+    })?;
+    for entry in entries {
+        let Some(path) = archive_path(&entry.request_id) else {
+            return Err(format!("Cannot archive request with ID '{}'", entry.request_id));
+        };
+        let contents = serde_json::to_vec(entry)
+            .map_err(|e| format!("Failed to serialize request {}: {}", entry.request_id, e))?;
+        fs::write(&path, contents)
+            .map_err(|e| format!("Failed to archive request to {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Reads the archived entry of a request, if it was evicted from the request history.
+pub fn read_archived_request(request_id: &str) -> Option<RequestEntry> {
+    let contents = fs::read(archive_path(request_id)?).ok()?;
+    serde_json::from_slice(&contents).ok()
+}
+
+/// Removes the archived entries older than `max_age`.
+///
+/// # Returns
+/// The number of entries removed.
+pub fn prune_request_archive(max_age: Duration) -> usize {
+    let Ok(archived) = fs::read_dir(&*REQUEST_ARCHIVE_FOLDER) else { return 0 };
+    let now = SystemTime::now();
+    archived
+        .flatten()
+        .filter(|file| {
+            file.metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > max_age)
+        })
+        .filter(|file| fs::remove_file(file.path()).is_ok())
+        .count()
+}
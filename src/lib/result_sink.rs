@@ -0,0 +1,233 @@
+//! # result_sink.rs
+//!
+//! Uploads of execution outputs to S3-compatible object storage, such as MinIO on a site
+//! server, so that they need not be kept on the device and fetched from it.
+//!
+//! A deployment opts in with `resultSink: { "type": "s3", "bucket", "prefix", "endpoint",
+//! "deleteLocal" }`. Once an execution has finished (see `make_history`), the files in the
+//! outputs folder of the request are streamed to `<prefix>/<deployment>/<module>/<request>/<file>`
+//! in the bucket, and `outputs` of the request point to the objects instead of this device.
+//! With `deleteLocal`, the uploaded files are then removed from the device.
+//!
+//! The bucket is accessed with `WASMIOT_S3_ACCESS_KEY_ID` and `WASMIOT_S3_SECRET_ACCESS_KEY`
+//! in `WASMIOT_S3_REGION`, and a failed upload is retried `WASMIOT_S3_UPLOAD_RETRIES` times.
+//! Without `endpoint`, the bucket is on AWS. Files that cannot be uploaded are served from here
+//! as before, and why is recorded as a step in `chain_trace` of the request. Uploading needs a
+//! supervisor built with the `s3` feature.
+
+use std::collections::HashMap;
+use std::path::Path;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::function_name;
+use crate::lib::api::get_output_path;
+use crate::lib::cli::parse_http_url;
+use crate::lib::constants::get_s3_region;
+#[cfg(feature = "s3")]
+use crate::lib::constants::{get_s3_credentials, get_s3_upload_retries};
+use crate::lib::logging::send_log;
+use crate::structs::request_entry::{ChainStep, RequestEntry};
+
+/// Where the outputs of the executions of a deployment are uploaded to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultSink {
+    /// Kind of the storage, only `s3` for now.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Bucket the outputs are uploaded to.
+    pub bucket: String,
+    /// Prefix of the keys of the uploaded outputs.
+    #[serde(default)]
+    pub prefix: String,
+    /// URL of the S3-compatible service, AWS if not set.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Whether uploaded outputs are removed from the device.
+    #[serde(default)]
+    pub delete_local: bool,
+}
+
+impl ResultSink {
+    /// Parses `resultSink` of a deployment manifest.
+    pub fn from_manifest(value: &Value) -> Result<Self, String> {
+        let sink: ResultSink = serde_json::from_value(value.clone())
+            .map_err(|e| format!("Invalid resultSink: {}", e))?;
+        if sink.kind != "s3" {
+            return Err(format!("Unsupported resultSink type '{}', only 's3' is supported", sink.kind));
+        }
+        if sink.bucket.is_empty() {
+            return Err("resultSink must name a bucket".to_string());
+        }
+        if let Some(endpoint) = &sink.endpoint {
+            parse_http_url(endpoint).map_err(|e| format!("Invalid resultSink endpoint: {}", e))?;
+        }
+        Ok(sink)
+    }
+
+    /// Key an output file of a request is uploaded as.
+    pub fn object_key(&self, entry: &RequestEntry, filename: &str) -> String {
+        let key = format!("{}/{}/{}/{}", entry.deployment_id, entry.module_name, entry.request_id, filename);
+        match self.prefix.trim_matches('/') {
+            "" => key,
+            prefix => format!("{}/{}", prefix, key),
+        }
+    }
+
+    /// URL of the bucket, addressed by path on other services than AWS.
+    pub fn bucket_url(&self) -> String {
+        match &self.endpoint {
+            Some(endpoint) => format!("{}/{}", endpoint.trim_end_matches('/'), self.bucket),
+            None => format!("https://{}.s3.{}.amazonaws.com", self.bucket, get_s3_region()),
+        }
+    }
+
+    /// URL of an uploaded object.
+    pub fn object_url(&self, key: &str) -> String {
+        let key: Vec<String> = key.split('/').map(|part| urlencoding::encode(part).into_owned()).collect();
+        format!("{}/{}", self.bucket_url(), key.join("/"))
+    }
+}
+
+/// Uploads the output files of an executed request to `sink`, pointing its `outputs` to the
+/// uploaded objects. Files that cannot be uploaded are left to be served from here.
+pub async fn upload_outputs(entry: &mut RequestEntry, sink: &ResultSink) {
+    let folder = get_output_path(&entry.deployment_id, &entry.module_name, &entry.request_id, None);
+    let Ok(dir) = std::fs::read_dir(&folder) else {
+        return;
+    };
+    let mut filenames: Vec<String> = dir
+        .filter_map(Result::ok)
+        .filter(|file| file.path().is_file())
+        .filter_map(|file| file.file_name().into_string().ok())
+        .collect();
+    if filenames.is_empty() {
+        return;
+    }
+    filenames.sort();
+
+    let mut uploaded = HashMap::new();
+    let mut errors = Vec::new();
+    match Uploader::new(sink) {
+        Ok(uploader) => {
+            for filename in filenames {
+                let key = sink.object_key(entry, &filename);
+                let path = folder.join(&filename);
+                match uploader.put(&key, &path).await {
+                    Ok(()) => {
+                        uploaded.insert(filename, sink.object_url(&key));
+                        if sink.delete_local && let Err(e) = std::fs::remove_file(&path) {
+                            log::warn!("Failed to remove uploaded output {}: {}", path.display(), e);
+                        }
+                    }
+                    Err(e) => errors.push(format!("{}: {}", filename, e)),
+                }
+            }
+        }
+        Err(e) => errors.push(e),
+    }
+
+    let request = [entry.deployment_id.clone(), entry.module_name.clone(), entry.request_id.clone()];
+    for output in entry.outputs.iter_mut() {
+        if let Some(url) = local_output_name(&request, output).and_then(|filename| uploaded.get(&filename)) {
+            *output = url.clone();
+        }
+    }
+
+    let func_name = function_name!().to_string();
+    if errors.is_empty() {
+        let message = format!("Uploaded {} outputs to {}", uploaded.len(), sink.bucket_url());
+        send_log("DEBUG", &message, &func_name, Some(entry)).await;
+    } else {
+        let error = format!("Serving outputs from this device, failed to upload them: {}", errors.join("; "));
+        send_log("WARN", &format!("Result sink {}: {}", sink.bucket_url(), error), &func_name, Some(entry)).await;
+        let mut outputs: Vec<String> = uploaded.into_values().collect();
+        outputs.sort();
+        entry.chain_trace.push(ChainStep {
+            url: sink.bucket_url(),
+            outputs,
+            mirrored: false,
+            mirror_error: Some(error),
+            error: None,
+        });
+    }
+}
+
+/// Name of the output file that a URL made by `make_output_url` points to, if it is one of
+/// the request given by its deployment, module and request ID.
+fn local_output_name(request: &[String; 3], url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let segments: Vec<String> = url.path_segments()?
+        .map(|segment| urlencoding::decode(segment).map(|s| s.into_owned()))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let [.., folder, deployment_id, module_name, request_id, filename] = segments.as_slice() else {
+        return None;
+    };
+    (folder == "module_results" && [deployment_id, module_name, request_id] == [&request[0], &request[1], &request[2]])
+        .then(|| filename.clone())
+}
+
+/// Client of the storage of a result sink.
+#[cfg(feature = "s3")]
+struct Uploader {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+#[cfg(feature = "s3")]
+impl Uploader {
+    fn new(sink: &ResultSink) -> Result<Self, String> {
+        use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+        use aws_sdk_s3::config::retry::RetryConfig;
+        let (access_key_id, secret_access_key) = get_s3_credentials()
+            .ok_or("WASMIOT_S3_ACCESS_KEY_ID and WASMIOT_S3_SECRET_ACCESS_KEY are not set")?;
+        let mut config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(get_s3_region()))
+            .credentials_provider(Credentials::new(access_key_id, secret_access_key, None, None, "supervisor"))
+            .retry_config(RetryConfig::standard().with_max_attempts(get_s3_upload_retries() + 1));
+        if let Some(endpoint) = &sink.endpoint {
+            config = config.endpoint_url(endpoint).force_path_style(true);
+        }
+        Ok(Uploader {
+            client: aws_sdk_s3::Client::from_conf(config.build()),
+            bucket: sink.bucket.clone(),
+        })
+    }
+
+    /// Streams a file to the object `key`.
+    async fn put(&self, key: &str, path: &Path) -> Result<(), String> {
+        let body = aws_sdk_s3::primitives::ByteStream::from_path(path).await
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let content_type = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| actix_files::file_extension_to_mime(extension).to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        self.client.put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| aws_sdk_s3::error::DisplayErrorContext(e).to_string())?;
+        Ok(())
+    }
+}
+
+/// Stands in for the client when built without the `s3` feature, failing every upload.
+#[cfg(not(feature = "s3"))]
+struct Uploader;
+
+#[cfg(not(feature = "s3"))]
+impl Uploader {
+    fn new(_sink: &ResultSink) -> Result<Self, String> {
+        Err("the supervisor was built without the s3 feature".to_string())
+    }
+
+    async fn put(&self, _key: &str, _path: &Path) -> Result<(), String> {
+        Err("the supervisor was built without the s3 feature".to_string())
+    }
+}
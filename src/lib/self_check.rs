@@ -0,0 +1,139 @@
+//! # self_check.rs
+//!
+//! Checks that the supervisor can run on this device.
+//!
+//! The instance folder and the folders in it are created if missing, and probed by writing and
+//! removing a file in each, at every startup. An unusable instance directory then stops the
+//! supervisor with a message naming the folder, instead of failing the first deployment with an
+//! IO error. `--check` runs the same probes, compiles and runs a small Wasm module to test the
+//! engine, and captures a frame from the camera with `--check-camera`, without starting the
+//! supervisor.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::lib::constants::{
+    ARTIFACT_CACHE_FOLDER,
+    DEPLOYMENTS_FOLDER,
+    INSTANCE_PATH,
+    MODULE_FOLDER,
+    PARAMS_FOLDER,
+};
+use crate::lib::wasmtime_imports::capture_image;
+
+/// A module exporting `add(i32, i32) -> i32`, run to test the Wasm engine.
+const SELF_TEST_MODULE: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+    // Type section: (i32, i32) -> i32
+    0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f,
+    // Function section
+    0x03, 0x02, 0x01, 0x00,
+    // Export section: "add"
+    0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00,
+    // Code section: local.get 0, local.get 1, i32.add
+    0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b,
+];
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub ok: bool,
+    pub message: String,
+}
+
+impl CheckResult {
+    fn new(name: impl Into<String>, result: Result<String, String>) -> Self {
+        let (ok, message) = match result {
+            Ok(message) => (true, message),
+            Err(message) => (false, message),
+        };
+        Self { name: name.into(), ok, message }
+    }
+}
+
+/// Outcome of `--check`.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    /// Whether every check passed
+    pub ok: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+/// The folders the supervisor writes to, by the name they are reported with.
+pub fn required_folders() -> Vec<(&'static str, PathBuf)> {
+    vec![
+        ("instance", INSTANCE_PATH.clone()),
+        ("modules", MODULE_FOLDER.clone()),
+        ("params", PARAMS_FOLDER.clone()),
+        ("deployments", DEPLOYMENTS_FOLDER.clone()),
+        ("artifact cache", ARTIFACT_CACHE_FOLDER.clone()),
+    ]
+}
+
+/// Creates a folder if it is missing, and checks that files can be created in it and removed.
+pub fn check_folder(path: &Path) -> Result<(), String> {
+    fs::create_dir_all(path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+    let probe = path.join(format!(".write-probe-{}", std::process::id()));
+    fs::write(&probe, b"probe").map_err(|e| format!("Cannot write to {}: {}", path.display(), e))?;
+    fs::remove_file(&probe).map_err(|e| format!("Cannot remove files from {}: {}", path.display(), e))
+}
+
+/// Creates and probes each of the `required_folders`.
+pub fn check_folders() -> Vec<CheckResult> {
+    required_folders().into_iter()
+        .map(|(name, path)| {
+            let result = check_folder(&path).map(|()| format!("{} is writable", path.display()));
+            CheckResult::new(format!("{} folder", name), result)
+        })
+        .collect()
+}
+
+/// Compiles and runs `SELF_TEST_MODULE`.
+#[cfg(not(feature = "armv6"))]
+pub fn check_wasm_engine() -> CheckResult {
+    use wasmtime::{Engine, Instance, Module, Store};
+    use crate::lib::constants::get_force_pulley;
+    use crate::lib::wasmtime::engine_config;
+    let result = (|| {
+        // The same kind of code as the modules of deployments, native or Pulley bytecode
+        let engine = Engine::new(&engine_config(get_force_pulley())?)
+            .map_err(|e| format!("Failed to create an engine: {}", e))?;
+        let module = Module::new(&engine, SELF_TEST_MODULE).map_err(|e| format!("Failed to compile a module: {}", e))?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])
+            .map_err(|e| format!("Failed to instantiate a module: {}", e))?;
+        let add = instance.get_typed_func::<(i32, i32), i32>(&mut store, "add")
+            .map_err(|e| format!("Failed to find the function of a module: {}", e))?;
+        match add.call(&mut store, (3, 4)) {
+            Ok(7) => Ok("Compiled and ran a module".to_string()),
+            Ok(other) => Err(format!("A module returned {} instead of 7", other)),
+            Err(e) => Err(format!("Failed to run a module: {}", e)),
+        }
+    })();
+    CheckResult::new("wasm engine", result)
+}
+
+/// Creates a Wasm engine. Modules cannot be compiled on armv6, so none is run.
+#[cfg(feature = "armv6")]
+pub fn check_wasm_engine() -> CheckResult {
+    let _ = SELF_TEST_MODULE;
+    let _engine = wasmtime::Engine::default();
+    CheckResult::new("wasm engine", Ok("Created an engine, compilation is not supported on armv6".to_string()))
+}
+
+/// Captures a frame from the camera in `DEFAULT_CAMERA_DEVICE`.
+pub fn check_camera() -> CheckResult {
+    let result = capture_image().map(|image| format!("Captured a frame of {} bytes", image.len()));
+    CheckResult::new("camera", result)
+}
+
+/// Runs the checks of `--check`, including the camera if `camera` is set.
+pub fn run_checks(camera: bool) -> CheckReport {
+    let mut checks = check_folders();
+    checks.push(check_wasm_engine());
+    if camera {
+        checks.push(check_camera());
+    }
+    CheckReport { ok: checks.iter().all(|check| check.ok), checks }
+}
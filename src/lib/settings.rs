@@ -0,0 +1,107 @@
+//! # settings.rs
+//!
+//! Settings of the supervisor, read from the environment once at startup.
+//!
+//! The environment is copied into a shared store the first time a setting is read, and the
+//! `.env` file is added to it with `load_env_file`. The rest of the supervisor reads its settings
+//! from the store instead of the environment, and the settings from the configuration file, the
+//! command line and the API are written into the store as well, because changing the environment
+//! of a running process while other threads read it is not safe.
+//!
+//! Each value is kept with the source it came from. When a setting is given in several sources,
+//! the one used is, in order: the command line, the environment, `.env`, the configuration file
+//! and the default. Values set through the API replace any of them.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+/// Where the value of a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SettingSource {
+    CommandLine,
+    Environment,
+    EnvFile,
+    ConfigFile,
+    /// Set through the API, e.g. when an orchestrator registers itself
+    Api,
+    /// Worked out by the supervisor, e.g. its own IP address
+    Detected,
+    Default,
+}
+
+impl SettingSource {
+    /// Whether the value was given outside the configuration file, so that the file does not
+    /// replace it.
+    pub fn is_external(&self) -> bool {
+        matches!(self, Self::CommandLine | Self::Environment | Self::EnvFile | Self::Api)
+    }
+}
+
+impl fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::CommandLine => "command line",
+            Self::Environment => "environment",
+            Self::EnvFile => ".env",
+            Self::ConfigFile => "config file",
+            Self::Api => "API",
+            Self::Detected => "detected",
+            Self::Default => "default",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Settings by the name of their environment variable, with their source.
+static SETTINGS: Lazy<RwLock<HashMap<String, (String, SettingSource)>>> = Lazy::new(|| {
+    RwLock::new(env::vars().map(|(name, value)| (name, (value, SettingSource::Environment))).collect())
+});
+
+/// Returns the value of a setting, if it is set.
+pub fn get_setting(name: &str) -> Option<String> {
+    SETTINGS.read().get(name).map(|(value, _)| value.clone())
+}
+
+/// Returns the value of a setting and where it came from, if it is set.
+pub fn get_setting_with_source(name: &str) -> Option<(String, SettingSource)> {
+    SETTINGS.read().get(name).cloned()
+}
+
+/// Returns where the value of a setting came from, `Default` if it is not set.
+pub fn setting_source(name: &str) -> SettingSource {
+    SETTINGS.read().get(name).map(|(_, source)| *source).unwrap_or(SettingSource::Default)
+}
+
+/// Whether a setting is set.
+pub fn is_setting_set(name: &str) -> bool {
+    SETTINGS.read().contains_key(name)
+}
+
+/// Sets the value of a setting, given in `source`.
+pub fn set_setting(name: &str, value: impl Into<String>, source: SettingSource) {
+    SETTINGS.write().insert(name.to_string(), (value.into(), source));
+}
+
+/// Unsets a setting, so that its default is used.
+pub fn remove_setting(name: &str) {
+    SETTINGS.write().remove(name);
+}
+
+/// Adds the settings in the `.env` file of the working directory (or one of its parents) that
+/// are not set in the environment.
+pub fn load_env_file() -> Result<(), String> {
+    // The iterator is deprecated in favour of loading into the environment, which is what is avoided here
+    #[allow(deprecated)]
+    let entries = dotenv::dotenv_iter().map_err(|e| format!("{:?}", e))?;
+    let mut settings = SETTINGS.write();
+    for entry in entries {
+        let (name, value) = entry.map_err(|e| format!("{:?}", e))?;
+        settings.entry(name).or_insert((value, SettingSource::EnvFile));
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+//! # shutdown.rs
+//!
+//! Graceful shutdown of the supervisor.
+//!
+//! On `SIGINT` or `SIGTERM` the HTTP server stops accepting connections, and the Wasm functions
+//! that are running are given `WASMIOT_DRAIN_TIMEOUT_SECS` to finish, so that their results are
+//! recorded instead of being left half-written when the process exits. The executions are
+//! tracked by their `RequestEntry` from the start of `do_wasm_work` until `make_history` records
+//! the result. The ones still running at the deadline are recorded as `aborted` in the request
+//! history, which is saved to the instance folder, so that the orchestrator finds a definitive
+//! state behind their result URLs instead of waiting for them forever.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use actix_web::dev::ServerHandle;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use crate::lib::constants::get_drain_timeout;
+use crate::structs::request_entry::RequestEntry;
+
+/// How often the executions are checked while waiting for them to finish.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Requests whose Wasm function is running, by request ID.
+static IN_FLIGHT_REQUESTS: Lazy<Mutex<HashMap<String, RequestEntry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// When the supervisor was asked to shut down.
+static SHUTDOWN_STARTED: OnceCell<Instant> = OnceCell::new();
+
+/// Marks the execution of a request as running.
+pub fn track_execution(entry: &RequestEntry) {
+    IN_FLIGHT_REQUESTS.lock().insert(entry.request_id.clone(), entry.clone());
+}
+
+/// Marks the execution of a request as finished, with its result recorded.
+pub fn finish_execution(request_id: &str) {
+    IN_FLIGHT_REQUESTS.lock().remove(request_id);
+}
+
+/// Returns the number of requests whose Wasm function is running.
+pub fn in_flight_requests() -> usize {
+    IN_FLIGHT_REQUESTS.lock().len()
+}
+
+/// Stops the HTTP server of `handle` gracefully when the process receives `SIGINT` or `SIGTERM`.
+///
+/// The server is given `WASMIOT_DRAIN_TIMEOUT_SECS` to answer the requests it is handling, the
+/// same time `drain_executions` waits for the executions to finish.
+pub async fn stop_on_signal(handle: ServerHandle) {
+    let signal = wait_for_signal().await;
+    SHUTDOWN_STARTED.get_or_init(Instant::now);
+    log::info!(
+        "Received {}, stopping after the {} running executions finish, for at most {} seconds",
+        signal, in_flight_requests(), get_drain_timeout()
+    );
+    handle.stop(true).await;
+}
+
+/// Waits for `SIGINT` or `SIGTERM`, returning the name of the signal received.
+#[cfg(unix)]
+async fn wait_for_signal() -> &'static str {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+    use futures_util::future::Either;
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            log::error!("Failed to listen for SIGTERM, only stopping on SIGINT: {}", e);
+            let _ = actix_web::rt::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    let interrupt = std::pin::pin!(actix_web::rt::signal::ctrl_c());
+    let terminate = std::pin::pin!(terminate.recv());
+    match futures_util::future::select(interrupt, terminate).await {
+        Either::Left(_) => "SIGINT",
+        Either::Right(_) => "SIGTERM",
+    }
+}
+
+/// Waits for Ctrl-C, returning the name of the signal received.
+#[cfg(not(unix))]
+async fn wait_for_signal() -> &'static str {
+    let _ = actix_web::rt::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+/// Waits for the running executions to finish, until `WASMIOT_DRAIN_TIMEOUT_SECS` have passed
+/// since the supervisor was asked to shut down.
+///
+/// # Returns
+/// The requests whose execution did not finish in time.
+pub async fn drain_executions() -> Vec<RequestEntry> {
+    let started = *SHUTDOWN_STARTED.get_or_init(Instant::now);
+    let deadline = started + Duration::from_secs(get_drain_timeout());
+    while in_flight_requests() > 0 && Instant::now() < deadline {
+        actix_web::rt::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+    IN_FLIGHT_REQUESTS.lock().drain().map(|(_, entry)| entry).collect()
+}
@@ -0,0 +1,99 @@
+//! # signing.rs
+//!
+//! Ed25519 signature verification of deployment manifests and module binaries.
+//!
+//! The supervisor trusts the public keys listed in `WASMIOT_TRUSTED_PUBLIC_KEYS` (comma
+//! separated, hex encoded raw 32 byte keys). A signature is valid if any trusted key verifies it.
+//!
+//! A deployment manifest may carry a top level `signature` over its canonical form: the
+//! manifest without the `signature` field, serialized as compact JSON with object keys sorted.
+//! Each module may carry a `signature` over the bytes of its binary. Signatures are hex encoded.
+//!
+//! When `WASMIOT_REQUIRE_SIGNED_DEPLOYMENTS=true`, unsigned manifests and modules are rejected.
+
+use std::path::Path;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+use serde_json::{Map, Value};
+use crate::lib::constants::get_trusted_public_keys;
+
+/// Parses the configured trusted public keys, skipping (and logging) any that are invalid.
+fn trusted_keys() -> Vec<PKey<Public>> {
+    get_trusted_public_keys()
+        .iter()
+        .filter_map(|key| {
+            let parsed = hex::decode(key)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| PKey::public_key_from_raw_bytes(&bytes, Id::ED25519).map_err(|e| e.to_string()));
+            match parsed {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    log::error!("Ignoring invalid trusted public key '{}': {}", key, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Returns a copy of a JSON value with the keys of every object in sorted order.
+fn sort_keys(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let sorted: Map<String, Value> = keys.into_iter()
+                .map(|key| (key.clone(), sort_keys(&map[key])))
+                .collect();
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(sort_keys).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Returns the canonical form of a manifest that its signature is computed over.
+pub fn canonical_manifest(manifest: &Value) -> Vec<u8> {
+    let mut unsigned = manifest.clone();
+    if let Some(map) = unsigned.as_object_mut() {
+        map.remove("signature");
+    }
+    serde_json::to_vec(&sort_keys(&unsigned)).unwrap_or_default()
+}
+
+/// Verifies a hex encoded signature over `message` against the trusted public keys.
+pub fn verify_signature(message: &[u8], signature: &str) -> Result<(), String> {
+    let signature = hex::decode(signature).map_err(|e| format!("Signature is not valid hex: {}", e))?;
+    let keys = trusted_keys();
+    if keys.is_empty() {
+        return Err("No trusted public keys are configured".to_string());
+    }
+    for key in &keys {
+        let verified = Verifier::new_without_digest(key)
+            .and_then(|mut verifier| verifier.verify_oneshot(&signature, message))
+            .unwrap_or(false);
+        if verified {
+            return Ok(());
+        }
+    }
+    Err("Signature does not match any trusted public key".to_string())
+}
+
+/// Verifies the signature of a deployment manifest.
+///
+/// # Returns
+/// `Ok(true)` if the manifest is signed by a trusted key, `Ok(false)` if it is not signed.
+pub fn verify_manifest(manifest: &Value) -> Result<bool, String> {
+    match manifest.get("signature") {
+        None => Ok(false),
+        Some(Value::String(signature)) => verify_signature(&canonical_manifest(manifest), signature).map(|_| true),
+        Some(_) => Err("Signature must be a string".to_string()),
+    }
+}
+
+/// Verifies a hex encoded signature over the contents of a file.
+pub fn verify_file(path: &Path, signature: &str) -> Result<(), String> {
+    let contents = std::fs::read(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    verify_signature(&contents, signature)
+}
@@ -0,0 +1,340 @@
+//! # startup.rs
+//!
+//! Loading of the saved deployments at startup, in the background once the HTTP server is
+//! listening, so that a device with many deployments, or with modules that need compiling again
+//! after an upgrade, answers the orchestrator from the start instead of looking dead.
+//!
+//! The saved deployments are marked `loading` before the server starts, and each becomes
+//! `ready` or `failed` once it has been loaded. Executions of deployments still loading are
+//! answered with 503 and `Retry-After`. Preloaded deployments are applied after the saved ones.
+//!
+//! How the loading went is kept as the startup report served at `/startup-report`, which is
+//! sent once to the orchestrator when the supervisor has registered to it.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::Notify;
+use crate::function_name;
+use crate::lib::api::{collect_garbage, create_deployment, insert_deployment, lock_deployments};
+use crate::lib::constants::{get_apply_preloaded_deployments, DEPLOYMENTS_FOLDER, HTTP_CLIENT, PRELOADED_DEPLOYMENTS_FOLDER};
+use crate::lib::deployment::Deployment;
+use crate::lib::download::verify_module_artifacts;
+use crate::lib::logging::send_log;
+use crate::lib::orchestrator_compat::{api_version_of, startup_report_endpoint, ApiVersion, API_VERSION_HEADER};
+use crate::lib::settings::get_setting;
+use crate::lib::stats::load_stats;
+
+/// Where a deployment is in loading at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadState {
+    Loading,
+    Ready,
+    Failed,
+}
+
+/// How loading a deployment at startup went.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentLoad {
+    pub deployment_id: String,
+    /// The saved deployment or preloaded manifest the deployment was loaded from.
+    pub file: String,
+    pub preloaded: bool,
+    pub state: LoadState,
+    /// How long loading took, once it has finished.
+    pub duration_ms: Option<u64>,
+    /// Why loading failed, or the files missing from a deployment loaded as degraded.
+    pub error: Option<String>,
+}
+
+/// How loading the deployments at startup went.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupReport {
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    pub loaded: usize,
+    pub failed: usize,
+    pub deployments: Vec<DeploymentLoad>,
+}
+
+/// The startup report, filled in as the deployments are loaded.
+static REPORT: Lazy<Mutex<StartupReport>> = Lazy::new(|| Mutex::new(StartupReport::default()));
+
+/// Wakes the ones waiting for the loading to finish.
+static FINISHED: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Whether the startup report has been sent to the orchestrator, or is being sent.
+static REPORT_SENT: AtomicBool = AtomicBool::new(false);
+
+/// Returns the startup report as it is now.
+pub fn startup_report() -> StartupReport {
+    REPORT.lock().clone()
+}
+
+/// Where the deployment is in loading at startup, if it was loaded then.
+pub fn load_state(deployment_id: &str) -> Option<DeploymentLoad> {
+    REPORT.lock().deployments.iter().rev().find(|load| load.deployment_id == deployment_id).cloned()
+}
+
+/// Whether the deployment is saved but has not been loaded yet.
+pub fn is_loading(deployment_id: &str) -> bool {
+    load_state(deployment_id).is_some_and(|load| load.state == LoadState::Loading)
+}
+
+/// IDs of the deployments that are still being loaded, so that their folders are not taken
+/// for those of removed deployments.
+pub fn loading_deployment_ids() -> Vec<String> {
+    REPORT.lock().deployments.iter()
+        .filter(|load| load.state == LoadState::Loading)
+        .map(|load| load.deployment_id.clone())
+        .collect()
+}
+
+/// Returns the files of the deployments saved in `DEPLOYMENTS_FOLDER`.
+pub fn saved_deployment_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&*DEPLOYMENTS_FOLDER)
+        .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    files.retain(|path| path.extension().and_then(|s| s.to_str()) == Some("json"));
+    files.sort();
+    files
+}
+
+/// Marks the deployments saved in `files` as loading, before anything can ask for them. The
+/// deployment IDs are taken from the file names.
+pub fn mark_loading(files: &[PathBuf]) {
+    let mut report = REPORT.lock();
+    report.started_at.get_or_insert_with(Utc::now);
+    for path in files {
+        let deployment_id = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        report.deployments.push(DeploymentLoad {
+            deployment_id,
+            file: path.display().to_string(),
+            preloaded: false,
+            state: LoadState::Loading,
+            duration_ms: None,
+            error: None,
+        });
+    }
+}
+
+/// Records that loading a deployment has finished.
+fn finish_load(file: &str, deployment_id: &str, started: Instant, error: Option<String>, failed: bool) {
+    let mut report = REPORT.lock();
+    let state = if failed { LoadState::Failed } else { LoadState::Ready };
+    match report.deployments.iter_mut().find(|load| load.file == file) {
+        Some(load) => {
+            load.deployment_id = deployment_id.to_string();
+            load.state = state;
+            load.duration_ms = Some(started.elapsed().as_millis() as u64);
+            load.error = error;
+        }
+        None => report.deployments.push(DeploymentLoad {
+            deployment_id: deployment_id.to_string(),
+            file: file.to_string(),
+            preloaded: true,
+            state,
+            duration_ms: Some(started.elapsed().as_millis() as u64),
+            error,
+        }),
+    }
+    match state {
+        LoadState::Failed => report.failed += 1,
+        _ => report.loaded += 1,
+    }
+}
+
+/// Loads a saved deployment, making sure its downloaded files are still intact and fetching
+/// them again if not. A deployment with files that cannot be restored is loaded as degraded.
+///
+/// # Returns
+/// The ID of the deployment and the files missing from it, or why it could not be loaded.
+async fn load_saved_deployment(path: &PathBuf) -> Result<(String, Vec<String>), String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read deployment JSON file {}: {}", path.display(), e))?;
+    let mut deployment: Deployment = serde_json::from_str(&contents)
+        .map_err(|e| format!("Failed to deserialize deployment JSON from {}: {}", path.display(), e))?;
+    for module in &deployment._modules {
+        for e in verify_module_artifacts(module).await {
+            log::error!("Failed to restore artifact of deployment '{}': {}", deployment.id, e);
+            deployment.missing_files.push(e);
+        }
+    }
+    let id = deployment.id.clone();
+    let missing_files = deployment.missing_files.clone();
+    // A deployment created while this one was loading is newer than the saved one
+    if lock_deployments().contains_key(&id) {
+        return Ok((id, missing_files));
+    }
+    let mut deployment = tokio::task::spawn_blocking(move || {
+        deployment.init();
+        deployment
+    }).await.map_err(|e| format!("Failed to initialize deployment: {}", e))?;
+    // Modules with missing files are not checked, as the deployment cannot run anyway
+    if !deployment.is_degraded() {
+        deployment.check_module_loads(&id).await;
+        for (module_name, e) in &deployment.load_errors {
+            log::error!("Module '{}' of deployment '{}' failed to load: {}", module_name, id, e);
+        }
+    }
+    insert_deployment(deployment);
+    // Continue the execution statistics of the deployment from where they were saved
+    load_stats(std::slice::from_ref(&id));
+    Ok((id, missing_files))
+}
+
+/// Loads the saved deployments marked by `mark_loading`, then applies the preloaded
+/// deployment manifests that have not been deployed yet and removes the folders left behind by
+/// deployments that no longer exist.
+pub async fn load_deployments(files: Vec<PathBuf>) {
+    let func_name = function_name!().to_string();
+    let started = Instant::now();
+
+    for path in files {
+        let file = path.display().to_string();
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default().to_string();
+        let load_started = Instant::now();
+        match load_saved_deployment(&path).await {
+            Ok((id, missing_files)) => {
+                log::info!("Loaded saved deployment '{}' from {}", id, file);
+                let error = (!missing_files.is_empty()).then(|| format!("Missing files: {}", missing_files.join("; ")));
+                finish_load(&file, &id, load_started, error, false);
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                finish_load(&file, &stem, load_started, Some(e), true);
+            }
+        }
+    }
+
+    // Apply preloaded deployment manifests that have not been deployed yet, so that the
+    // device can run its applications without an orchestrator
+    if get_apply_preloaded_deployments() {
+        apply_preloaded_deployments().await;
+    }
+
+    // Remove folders left behind by deployments that no longer exist
+    collect_garbage(false).await;
+
+    let report = {
+        let mut report = REPORT.lock();
+        report.finished_at = Some(Utc::now());
+        report.duration_ms = Some(started.elapsed().as_millis() as u64);
+        report.clone()
+    };
+    FINISHED.notify_waiters();
+    send_log(
+        "INFO",
+        &format!("Loaded {} deployments at startup, {} failed, in {} ms", report.loaded, report.failed, report.duration_ms.unwrap_or_default()),
+        &func_name,
+        None
+    ).await;
+}
+
+/// Creates the deployments of the manifests in `PRELOADED_DEPLOYMENTS_FOLDER` that are not
+/// deployed yet.
+async fn apply_preloaded_deployments() {
+    let Ok(entries) = std::fs::read_dir(&*PRELOADED_DEPLOYMENTS_FOLDER) else { return };
+    for entry_res in entries {
+        let Ok(entry) = entry_res else { continue };
+        let path = entry.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("json") {
+            continue;
+        }
+        let file = path.display().to_string();
+        let started = Instant::now();
+        let manifest: serde_json::Value = match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+        {
+            Ok(m) => m,
+            Err(e) => {
+                log::error!("Failed to read preloaded deployment {}: {}", file, e);
+                finish_load(&file, "", started, Some(e), true);
+                continue;
+            }
+        };
+        let Some(id) = manifest.get("deploymentId").and_then(|v| v.as_str()).map(str::to_string) else {
+            log::error!("Preloaded deployment {} has no deploymentId", file);
+            finish_load(&file, "", started, Some("No deploymentId".to_string()), true);
+            continue;
+        };
+        if lock_deployments().contains_key(&id) {
+            continue;
+        }
+        let (status, body) = create_deployment(manifest, "preloaded", false).await;
+        if status.is_success() {
+            log::info!("Applied preloaded deployment '{}' from {}", id, file);
+            finish_load(&file, &id, started, None, false);
+        } else {
+            log::error!("Failed to apply preloaded deployment '{}' from {}: {}", id, file, body);
+            let error = body["error"].as_str().map(str::to_string).unwrap_or_else(|| body.to_string());
+            finish_load(&file, &id, started, Some(error), true);
+        }
+    }
+}
+
+/// Waits until the deployments have been loaded.
+pub async fn wait_for_startup() {
+    let finished = FINISHED.notified();
+    if REPORT.lock().finished_at.is_some() {
+        return;
+    }
+    finished.await;
+}
+
+/// Sends the startup report to the orchestrator, once the deployments have been loaded, if it
+/// has not been sent yet. Called when the supervisor has registered to the orchestrator.
+pub async fn send_startup_report() {
+    let func_name = function_name!().to_string();
+    if REPORT_SENT.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    wait_for_startup().await;
+    let Some(orchestrator_url) = get_setting("WASMIOT_ORCHESTRATOR_URL") else {
+        REPORT_SENT.store(false, Ordering::SeqCst);
+        return;
+    };
+    if let Err(e) = push_startup_report(&orchestrator_url).await {
+        // Try again on the next registration
+        REPORT_SENT.store(false, Ordering::SeqCst);
+        send_log("WARN", &format!("Failed to send the startup report: {}", e), &func_name, None).await;
+    }
+}
+
+/// Posts the startup report to the orchestrator at `orchestrator_url`, if it takes them.
+async fn push_startup_report(orchestrator_url: &str) -> Result<(), String> {
+    let version = api_version_of(orchestrator_url);
+    let Some(endpoint) = startup_report_endpoint(orchestrator_url, version) else {
+        log::debug!("Not sending the startup report to an orchestrator of API version {}", version.header_value());
+        return Ok(());
+    };
+    let response = HTTP_CLIENT.post(&endpoint)
+        .header(API_VERSION_HEADER, ApiVersion::LATEST.header_value())
+        .json(&startup_report())
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("Request to {} failed: {}", endpoint, e))?;
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", endpoint, response.status()));
+    }
+    log::debug!("Sent the startup report to {}", endpoint);
+    Ok(())
+}
+
+/// Load states of the deployments loaded at startup, by deployment ID.
+pub fn load_states() -> HashMap<String, DeploymentLoad> {
+    REPORT.lock().deployments.iter()
+        .map(|load| (load.deployment_id.clone(), load.clone()))
+        .collect()
+}
@@ -0,0 +1,308 @@
+//! # stats.rs
+//!
+//! Execution statistics of each deployment, so that the orchestrator can ask a device how a
+//! deployment is doing instead of working it out from the request history.
+//!
+//! For each function of a deployment, `make_history` records how many times it was invoked,
+//! how many of those succeeded and failed, when it was last invoked and how long the
+//! executions took, along with the bytes received and sent for the requests (see
+//! `RequestEntry::bytes_in`), which downloads of results made later add to with
+//! `record_transfer`. The durations are kept in a `LatencySketch`, which answers percentiles
+//! within `SKETCH_ACCURACY` of the exact ones in a small, fixed amount of memory.
+//!
+//! The statistics are served at `GET /deploy/{id}/stats` and summarized in `GET /deploy`. They
+//! are saved every `STATS_SAVE_INTERVAL` and on shutdown to `DEPLOYMENT_STATS_FOLDER`, one file
+//! for each deployment, and read back at startup. Deleting or creating a deployment again
+//! starts its statistics over.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::lib::constants::DEPLOYMENT_STATS_FOLDER;
+use crate::structs::request_entry::RequestEntry;
+
+/// Relative error of the percentiles answered by `LatencySketch`.
+const SKETCH_ACCURACY: f64 = 0.02;
+
+/// How often the statistics that changed are saved to disk.
+const STATS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Statistics of each deployment, by deployment ID.
+static STATS: Lazy<Mutex<HashMap<String, DeploymentStats>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Deployments whose statistics changed since they were last saved.
+static UNSAVED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Streaming sketch of durations, answering percentiles with a relative error of at most
+/// `SKETCH_ACCURACY`.
+///
+/// Durations are counted in buckets whose bounds grow geometrically, so that a bucket is never
+/// wider than the accuracy allows, and a percentile is answered with the middle of its bucket.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencySketch {
+    /// Number of durations in each bucket, by its index
+    buckets: BTreeMap<i32, u64>,
+    /// Durations too short to have a bucket
+    zeros: u64,
+    count: u64,
+    sum_ms: f64,
+    max_ms: f64,
+}
+
+impl LatencySketch {
+    /// Ratio of the upper and lower bound of a bucket.
+    fn gamma() -> f64 {
+        (1.0 + SKETCH_ACCURACY) / (1.0 - SKETCH_ACCURACY)
+    }
+
+    /// Adds a duration to the sketch.
+    pub fn add(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1000.0;
+        self.count += 1;
+        self.sum_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+        if ms < 1e-3 {
+            self.zeros += 1;
+            return;
+        }
+        let index = (ms.ln() / Self::gamma().ln()).ceil() as i32;
+        *self.buckets.entry(index).or_default() += 1;
+    }
+
+    /// Adds the durations of another sketch to this one.
+    pub fn merge(&mut self, other: &LatencySketch) {
+        for (index, count) in &other.buckets {
+            *self.buckets.entry(*index).or_default() += count;
+        }
+        self.zeros += other.zeros;
+        self.count += other.count;
+        self.sum_ms += other.sum_ms;
+        self.max_ms = self.max_ms.max(other.max_ms);
+    }
+
+    /// Returns the duration in milliseconds that the fraction `q` (0..1) of the durations do
+    /// not exceed, or `None` if there are none.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (q.clamp(0.0, 1.0) * (self.count - 1) as f64).round() as u64;
+        if rank < self.zeros {
+            return Some(0.0);
+        }
+        let gamma = Self::gamma();
+        let mut seen = self.zeros;
+        for (index, count) in &self.buckets {
+            seen += count;
+            if rank < seen {
+                let estimate = 2.0 * gamma.powi(*index) / (gamma + 1.0);
+                return Some(estimate.min(self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+
+    /// Mean, percentiles and maximum of the durations, in milliseconds.
+    pub fn summary(&self) -> Value {
+        if self.count == 0 {
+            return Value::Null;
+        }
+        json!({
+            "mean": self.sum_ms / self.count as f64,
+            "p50": self.quantile(0.5),
+            "p90": self.quantile(0.9),
+            "p99": self.quantile(0.99),
+            "max": self.max_ms,
+        })
+    }
+}
+
+/// Statistics of one function of a deployment.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionStats {
+    pub invocations: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub last_invoked_at: Option<DateTime<Utc>>,
+    pub latency: LatencySketch,
+    #[serde(default)]
+    pub bytes_in: u64,
+    #[serde(default)]
+    pub bytes_out: u64,
+}
+
+/// Statistics of the functions of a deployment, by `module/function`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeploymentStats {
+    pub functions: BTreeMap<String, FunctionStats>,
+}
+
+impl DeploymentStats {
+    /// The statistics of every function added up.
+    fn total(&self) -> FunctionStats {
+        let mut total = FunctionStats::default();
+        for stats in self.functions.values() {
+            total.invocations += stats.invocations;
+            total.successes += stats.successes;
+            total.failures += stats.failures;
+            total.last_invoked_at = total.last_invoked_at.max(stats.last_invoked_at);
+            total.latency.merge(&stats.latency);
+            total.bytes_in += stats.bytes_in;
+            total.bytes_out += stats.bytes_out;
+        }
+        total
+    }
+}
+
+/// Records a finished execution in the statistics of its deployment.
+pub fn record_invocation(entry: &RequestEntry, duration: Duration) {
+    let function = format!("{}/{}", entry.module_name, entry.function_name);
+    {
+        let mut stats = STATS.lock();
+        let stats = stats.entry(entry.deployment_id.clone()).or_default()
+            .functions.entry(function).or_default();
+        stats.invocations += 1;
+        match entry.success {
+            true => stats.successes += 1,
+            false => stats.failures += 1,
+        }
+        stats.last_invoked_at = Some(entry.work_queued_at);
+        stats.latency.add(duration);
+        stats.bytes_in += entry.bytes_in;
+        stats.bytes_out += entry.bytes_out;
+    }
+    UNSAVED.lock().insert(entry.deployment_id.clone());
+}
+
+/// Adds bytes transferred for a request after its execution was recorded, like a download of
+/// its result, to the statistics of its function.
+pub fn record_transfer(entry: &RequestEntry, bytes_in: u64, bytes_out: u64) {
+    let function = format!("{}/{}", entry.module_name, entry.function_name);
+    {
+        let mut stats = STATS.lock();
+        let stats = stats.entry(entry.deployment_id.clone()).or_default()
+            .functions.entry(function).or_default();
+        stats.bytes_in += bytes_in;
+        stats.bytes_out += bytes_out;
+    }
+    UNSAVED.lock().insert(entry.deployment_id.clone());
+}
+
+/// The statistics of each function of a deployment, as served at `GET /deploy/{id}/stats`.
+pub fn stats_report(deployment_id: &str) -> Value {
+    let stats = STATS.lock().get(deployment_id).cloned().unwrap_or_default();
+    let functions: serde_json::Map<String, Value> = stats.functions.iter()
+        .map(|(function, stats)| (function.clone(), stats_json(stats)))
+        .collect();
+    json!({
+        "deploymentId": deployment_id,
+        "total": stats_json(&stats.total()),
+        "functions": functions,
+    })
+}
+
+/// The statistics of all functions of a deployment added up, as summarized in `GET /deploy`.
+pub fn stats_summary(deployment_id: &str) -> Value {
+    let total = STATS.lock().get(deployment_id).map(DeploymentStats::total).unwrap_or_default();
+    stats_json(&total)
+}
+
+/// Statistics of a function with the percentiles of its durations instead of the sketch.
+fn stats_json(stats: &FunctionStats) -> Value {
+    json!({
+        "invocations": stats.invocations,
+        "successes": stats.successes,
+        "failures": stats.failures,
+        "lastInvokedAt": stats.last_invoked_at,
+        "latencyMs": stats.latency.summary(),
+        "bytesIn": stats.bytes_in,
+        "bytesOut": stats.bytes_out,
+    })
+}
+
+/// Forgets the statistics of a deployment and removes their file, when the deployment is
+/// deleted or created again.
+pub fn remove_stats(deployment_id: &str) {
+    STATS.lock().remove(deployment_id);
+    UNSAVED.lock().remove(deployment_id);
+    let path = stats_path(deployment_id);
+    if let Err(e) = std::fs::remove_file(&path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        log::warn!("Failed to remove statistics file {}: {}", path.display(), e);
+    }
+}
+
+/// Path of the file the statistics of a deployment are saved to.
+fn stats_path(deployment_id: &str) -> std::path::PathBuf {
+    DEPLOYMENT_STATS_FOLDER.join(format!("{}.json", deployment_id))
+}
+
+/// Saves the statistics that changed since they were last saved.
+pub fn save_stats() -> Result<(), String> {
+    let unsaved: Vec<String> = UNSAVED.lock().drain().collect();
+    if unsaved.is_empty() {
+        return Ok(());
+    }
+    std::fs::create_dir_all(&*DEPLOYMENT_STATS_FOLDER).map_err(|e| {
+        format!("Failed to create statistics folder {}: {}", DEPLOYMENT_STATS_FOLDER.display(), e)
+    })?;
+    let mut errors = Vec::new();
+    for deployment_id in unsaved {
+        let Some(stats) = STATS.lock().get(&deployment_id).cloned() else { continue };
+        let path = stats_path(&deployment_id);
+        let saved = serde_json::to_vec(&stats).map_err(|e| e.to_string())
+            .and_then(|contents| std::fs::write(&path, contents).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            errors.push(format!("Failed to save statistics to {}: {}", path.display(), e));
+            // Tried again on the next save
+            UNSAVED.lock().insert(deployment_id);
+        }
+    }
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors.join("; ")),
+    }
+}
+
+/// Reads the statistics saved for the deployments that are loaded.
+///
+/// # Returns
+/// The number of deployments whose statistics were read.
+pub fn load_stats(deployment_ids: &[String]) -> usize {
+    let mut loaded = 0;
+    for deployment_id in deployment_ids {
+        let path = stats_path(deployment_id);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                log::warn!("Failed to read statistics from {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        match serde_json::from_slice::<DeploymentStats>(&contents) {
+            Ok(stats) => {
+                STATS.lock().insert(deployment_id.clone(), stats);
+                loaded += 1;
+            }
+            Err(e) => log::warn!("Failed to parse statistics in {}: {}", path.display(), e),
+        }
+    }
+    loaded
+}
+
+/// Saves the statistics that changed every `STATS_SAVE_INTERVAL`.
+pub async fn run_stats_saver() {
+    loop {
+        tokio::time::sleep(STATS_SAVE_INTERVAL).await;
+        if let Err(e) = tokio::task::spawn_blocking(save_stats).await.unwrap_or_else(|e| Err(e.to_string())) {
+            log::error!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! # systemd.rs
+//!
+//! Readiness and watchdog notifications to systemd, for running the supervisor as a
+//! `Type=notify` service. Built with the `systemd` feature.
+//!
+//! Notifications are sent with the `sd-notify` crate to the socket systemd gives in
+//! `NOTIFY_SOCKET`. When the variable is not set, e.g. when not running under systemd, or when
+//! built without the feature, nothing is sent. `READY=1` is sent once the HTTP server is bound
+//! and the persisted deployments are loaded, and `STATUS=` describes the phase of the startup.
+//! If `WatchdogSec` is configured, `run_watchdog` pings the watchdog as long as the HTTP server
+//! answers `/healthz`, so that a wedged server gets the process restarted.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+#[cfg(all(feature = "systemd", unix))]
+use sd_notify::NotifyState;
+
+/// Sends a notification to systemd, if the supervisor runs under it.
+#[cfg(all(feature = "systemd", unix))]
+fn notify(state: NotifyState) {
+    if let Err(e) = sd_notify::notify(false, std::slice::from_ref(&state)) {
+        log::warn!("Failed to notify systemd of '{}': {}", state, e);
+    }
+}
+
+/// Tells systemd that the supervisor has started and serves requests.
+pub fn notify_ready() {
+    #[cfg(all(feature = "systemd", unix))]
+    notify(NotifyState::Ready);
+}
+
+/// Tells systemd that the supervisor is shutting down.
+pub fn notify_stopping() {
+    #[cfg(all(feature = "systemd", unix))]
+    notify(NotifyState::Stopping);
+}
+
+/// Tells systemd what the supervisor is doing, shown by `systemctl status`.
+pub fn notify_status(status: &str) {
+    #[cfg(all(feature = "systemd", unix))]
+    notify(NotifyState::Status(status));
+    #[cfg(not(all(feature = "systemd", unix)))]
+    let _ = status;
+}
+
+/// Returns how often systemd expects the watchdog to be pinged, if it watches this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    #[cfg(all(feature = "systemd", unix))]
+    {
+        let mut usec = 0;
+        if sd_notify::watchdog_enabled(false, &mut usec) && usec > 0 {
+            return Some(Duration::from_micros(usec));
+        }
+    }
+    None
+}
+
+/// Pings the systemd watchdog at half of its interval for as long as the HTTP server listening
+/// on `bind_address` and `port` answers `/healthz` in time. Returns right away if the watchdog
+/// is not enabled.
+///
+/// Meant to be spawned once the HTTP server is started.
+pub async fn run_watchdog(bind_address: IpAddr, port: u16) {
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+    let period = interval / 2;
+    // The server listens on every interface, so it can be reached on the loopback one
+    let host = match bind_address {
+        IpAddr::V4(address) if address.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(address) if address.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        address => address,
+    };
+    let url = format!("http://{}/healthz", SocketAddr::new(host, port));
+    let client = match reqwest::Client::builder().timeout(period).build() {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("Failed to create the client of the systemd watchdog: {}", e);
+            return;
+        }
+    };
+    log::info!("Pinging the systemd watchdog every {:?} while {} answers", period, url);
+    loop {
+        actix_web::rt::time::sleep(period).await;
+        match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => {
+                #[cfg(all(feature = "systemd", unix))]
+                notify(NotifyState::Watchdog);
+            }
+            Ok(response) => log::warn!("Not pinging the systemd watchdog, {} answered {}", url, response.status()),
+            Err(e) => log::warn!("Not pinging the systemd watchdog, {} did not answer: {}", url, e),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! # telemetry.rs
+//!
+//! Traces of executions, for following a pipeline run across the supervisors it passes through.
+//! Exported over OTLP/HTTP when built with the `otel` feature.
+//!
+//! Each execution gets an `execution` span (see `make_history`), with `prepare` and `run` spans
+//! for preparing and running the Wasm function and a `chained_call` span for each call to the
+//! next function (see `do_wasm_work`). A request that comes with a W3C `traceparent` header
+//! continues that trace, and chained calls send one on, so that the supervisor further down the
+//! chain continues the same trace.
+//!
+//! The spans are `tracing` spans. Unless `WASMIOT_OTEL_ENDPOINT` is set in a build with the
+//! feature, nothing collects them and creating them costs next to nothing. `traceparent` of a
+//! request is then passed on to chained calls as is, so that a supervisor without tracing does
+//! not break the trace between two that have it. `WASMIOT_OTEL_SAMPLING_RATIO` is the share of
+//! the traces started here that are exported. Traces continued from a caller follow the
+//! sampling decision of the caller.
+
+use tracing::Span;
+use crate::structs::request_entry::RequestEntry;
+#[cfg(feature = "otel")]
+use std::collections::HashMap;
+#[cfg(feature = "otel")]
+use once_cell::sync::OnceCell;
+#[cfg(feature = "otel")]
+use opentelemetry::propagation::TextMapPropagator;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::TracerProvider;
+#[cfg(feature = "otel")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+#[cfg(feature = "otel")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+#[cfg(feature = "otel")]
+use tracing_subscriber::layer::SubscriberExt;
+#[cfg(feature = "otel")]
+use crate::lib::constants::{get_otel_sampling_ratio, SUPERVISOR_DEFAULT_NAME};
+use crate::lib::constants::get_otel_endpoint;
+#[cfg(feature = "otel")]
+use crate::lib::settings::get_setting;
+
+/// Name of the header carrying the W3C trace context.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Exporter of the traces, kept to flush the last spans on shutdown.
+#[cfg(feature = "otel")]
+static TRACER_PROVIDER: OnceCell<SdkTracerProvider> = OnceCell::new();
+
+/// Starts exporting traces to `WASMIOT_OTEL_ENDPOINT`, if it is set.
+pub fn init_tracing() {
+    let Some(endpoint) = get_otel_endpoint() else {
+        return;
+    };
+    #[cfg(feature = "otel")]
+    match start_exporter(&endpoint) {
+        Ok(()) => log::info!(
+            "Exporting traces to {} with sampling ratio {}",
+            endpoint, get_otel_sampling_ratio()
+        ),
+        Err(e) => log::error!("Not exporting traces: {}", e),
+    }
+    #[cfg(not(feature = "otel"))]
+    log::warn!("Not exporting traces to {}, the supervisor was built without the otel feature", endpoint);
+}
+
+/// Builds the exporter and installs it as the collector of the spans.
+#[cfg(feature = "otel")]
+fn start_exporter(endpoint: &str) -> Result<(), String> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| format!("Failed to build the exporter: {}", e))?;
+    let name = get_setting("SUPERVISOR_NAME").unwrap_or_else(|| SUPERVISOR_DEFAULT_NAME.to_string());
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(get_otel_sampling_ratio()))))
+        .with_resource(opentelemetry_sdk::Resource::builder().with_service_name(name).build())
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("supervisor"));
+    tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+        .map_err(|e| format!("Failed to install the exporter: {}", e))?;
+    let _ = TRACER_PROVIDER.set(provider);
+    Ok(())
+}
+
+/// Exports the spans not yet exported and stops the exporter. Blocks until done.
+pub fn shutdown_tracing() {
+    #[cfg(feature = "otel")]
+    if let Some(provider) = TRACER_PROVIDER.get()
+        && let Err(e) = provider.shutdown()
+    {
+        log::warn!("Failed to export the last traces: {}", e);
+    }
+}
+
+/// Creates the span of the execution of a request, continuing the trace of its `traceparent`.
+pub fn execution_span(entry: &RequestEntry) -> Span {
+    let span = tracing::info_span!(
+        "execution",
+        deployment = %entry.deployment_id,
+        module = %entry.module_name,
+        function = %entry.function_name,
+        request_id = %entry.request_id,
+        method = %entry.method,
+        otel.status_code = tracing::field::Empty,
+        error = tracing::field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    if let Some(traceparent) = &entry.traceparent {
+        let carrier = HashMap::from([(TRACEPARENT_HEADER.to_string(), traceparent.clone())]);
+        let parent = TraceContextPropagator::new().extract(&carrier);
+        let _ = span.set_parent(parent);
+    }
+    span
+}
+
+/// Returns the `traceparent` to send on a call made within `span`.
+///
+/// That is the context of `span` when traces are exported, and `incoming`, the `traceparent`
+/// of the request being executed, otherwise.
+pub fn outgoing_traceparent(span: &Span, incoming: Option<&str>) -> Option<String> {
+    #[cfg(feature = "otel")]
+    {
+        let mut carrier = HashMap::new();
+        TraceContextPropagator::new().inject_context(&span.context(), &mut carrier);
+        if let Some(traceparent) = carrier.remove(TRACEPARENT_HEADER) {
+            return Some(traceparent);
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = span;
+    incoming.map(str::to_string)
+}
+
+/// Whether a `traceparent` header value is well-formed: `version-traceid-parentid-flags` in
+/// lowercase hex, with neither id all zeros.
+pub fn valid_traceparent(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('-').collect();
+    let hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    parts.len() >= 4
+        && hex(parts[0], 2) && parts[0] != "ff"
+        && hex(parts[1], 32) && parts[1].bytes().any(|b| b != b'0')
+        && hex(parts[2], 16) && parts[2].bytes().any(|b| b != b'0')
+        && hex(parts[3], 2)
+}
@@ -0,0 +1,149 @@
+//! # test_support.rs
+//!
+//! Supervisors started within the process of an integration test, so that chains of functions
+//! can be tested over the real HTTP chaining path without an orchestrator or containers. Built
+//! with the `test-util` feature, which the tests of this crate turn on.
+//!
+//! Each `TestSupervisor` serves every route of `api::configure_routes` on a port of its own on
+//! the loopback interface, and is deployed to and run through HTTP like a supervisor on a
+//! device. It has an `AppState` of its own, so its deployments and request history are apart
+//! from those of the others, and the links it gives out, such as the `resultUrl` a chained call
+//! fetches, point to its own port. The instance folders are still those of the process, though
+//! (see `app_state.rs`), so the supervisors of one test get their parts of a chain under
+//! deployment IDs of their own, where an orchestrator would give the same one to every device.
+
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use actix_web::dev::ServerHandle;
+use actix_web::web::Data;
+use actix_web::{App, HttpServer};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use crate::lib::api::configure_routes;
+use crate::lib::app_state::{scope_request, AppState};
+use crate::lib::zeroconf::WebthingZeroconf;
+
+/// A supervisor serving HTTP on a free port of the loopback interface.
+pub struct TestSupervisor {
+    /// Address the supervisor listens on.
+    pub address: SocketAddr,
+    /// IDs of the deployments made through `deploy`.
+    deployment_ids: Mutex<Vec<String>>,
+    handle: ServerHandle,
+    client: reqwest::Client,
+}
+
+impl TestSupervisor {
+    /// Starts a supervisor with the routes of `configure_routes` and one HTTP worker.
+    pub async fn start() -> Result<Self, String> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))
+            .map_err(|e| format!("Failed to listen for a test supervisor: {}", e))?;
+        let address = listener.local_addr().map_err(|e| format!("Failed to listen for a test supervisor: {}", e))?;
+        let state = Data::new(AppState::new(Some(format!("http://{}", address))));
+        let zc = Arc::new(Mutex::new(WebthingZeroconf::new()));
+        let server = HttpServer::new(move || {
+            App::new()
+                .wrap_fn(scope_request)
+                .app_data(state.clone())
+                .app_data(Data::new(zc.clone()))
+                .configure(configure_routes)
+        })
+        .workers(1)
+        .disable_signals()
+        .listen(listener)
+        .map_err(|e| format!("Failed to listen for a test supervisor: {}", e))?;
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        Ok(TestSupervisor {
+            address,
+            deployment_ids: Mutex::new(Vec::new()),
+            handle,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// Base URL of the supervisor, e.g. `http://127.0.0.1:41234`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// The endpoint of a function of a deployment on this supervisor, as an orchestrator writes
+    /// it in `endpoints` and `instructions`, taking its arguments as the named query parameters.
+    pub fn endpoint(&self, deployment_id: &str, module_name: &str, function_name: &str, parameters: &[&str]) -> Value {
+        json!({
+            "url": self.url(),
+            "path": format!("/{}/modules/{}/{}", deployment_id, module_name, function_name),
+            "method": "POST",
+            "request": {
+                "parameters": parameters.iter().map(|name| json!({ "name": name })).collect::<Vec<_>>(),
+                "request_body": null
+            },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        })
+    }
+
+    /// Deploys a manifest with the binaries of its modules pushed along with it, by module
+    /// name, and waits for the deployment to be ready.
+    ///
+    /// # Returns
+    /// The answer of `POST /deploy`, or why the deployment failed.
+    pub async fn deploy(&self, manifest: &Value, binaries: &[(&str, &[u8])]) -> Result<Value, String> {
+        let mut form = reqwest::multipart::Form::new().text("manifest", manifest.to_string());
+        for (module_name, binary) in binaries {
+            form = form.part(module_name.to_string(), reqwest::multipart::Part::bytes(binary.to_vec()));
+        }
+        let response = self.client.post(format!("{}/deploy?wait=true", self.url()))
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to deploy to {}: {}", self.url(), e))?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|e| format!("Invalid answer to deployment: {}", e))?;
+        if !status.is_success() {
+            return Err(format!("Deployment failed ({}): {}", status, body));
+        }
+        if let Some(deployment_id) = manifest.get("deploymentId").and_then(Value::as_str) {
+            self.deployment_ids.lock().push(deployment_id.to_string());
+        }
+        Ok(body)
+    }
+
+    /// Runs a function of a deployment with the given query arguments.
+    ///
+    /// # Returns
+    /// The answer to the execution request, or why it could not be sent.
+    pub async fn execute(&self, deployment_id: &str, module_name: &str, function_name: &str, args: &[(&str, &str)]) -> Result<Value, String> {
+        self.client.post(format!("{}/{}/modules/{}/{}", self.url(), deployment_id, module_name, function_name))
+            .query(args)
+            .json(&json!({}))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to execute {}/{}: {}", module_name, function_name, e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid answer to execution of {}/{}: {}", module_name, function_name, e))
+    }
+
+    /// The entries of the request history of this supervisor.
+    pub async fn history(&self) -> Result<Vec<Value>, String> {
+        self.client.get(format!("{}/request-history", self.url()))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get the request history: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Invalid request history: {}", e))
+    }
+
+    /// Removes the deployments made on this supervisor and stops it.
+    pub async fn stop(self) {
+        let deployment_ids = self.deployment_ids.lock().clone();
+        for deployment_id in deployment_ids {
+            if let Err(e) = self.client.delete(format!("{}/deploy/{}", self.url(), deployment_id)).send().await {
+                log::warn!("Failed to remove test deployment {}: {}", deployment_id, e);
+            }
+        }
+        self.handle.stop(false).await;
+    }
+}
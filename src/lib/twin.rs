@@ -0,0 +1,141 @@
+//! # twin.rs
+//!
+//! The device twin: one JSON document describing the device to the orchestrator, combining its
+//! identity, capabilities, configuration, active deployments and a summary of its health,
+//! instead of the orchestrator assembling it from the endpoints each of them is served at.
+//!
+//! The twin is served at `GET /twin` with its `revision`, which grows whenever the deployments,
+//! the configuration or the health status of the device change, and is used as its `ETag`, so
+//! pollers sending `If-None-Match` get a `304` when nothing changed. Revisions start from the
+//! time the supervisor started, in milliseconds, so that they keep growing across restarts.
+//!
+//! When the deployments or the configuration change, the twin is pushed to the orchestrator, if
+//! it speaks a version of the API that takes twins (see `orchestrator_compat.rs`). Changes close
+//! together are pushed as one twin.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use crate::function_name;
+use crate::lib::api::list_deployments;
+use crate::lib::configuration::{get_device_description, get_supervisor_config, public_url};
+use crate::lib::constants::HTTP_CLIENT;
+use crate::lib::health::current_health_snapshot;
+use crate::lib::logging::{send_log, pending_log_count};
+use crate::lib::orchestrator_compat::{api_version_of, twin_endpoint, ApiVersion, API_VERSION_HEADER};
+use crate::lib::settings::get_setting;
+use crate::structs::device::HealthStatus;
+
+/// How long changes are gathered before the twin is pushed to the orchestrator.
+const TWIN_PUSH_DELAY: Duration = Duration::from_millis(500);
+
+/// Revision of the twin, bumped by `twin_changed`.
+static TWIN_REVISION: Lazy<AtomicU64> = Lazy::new(|| AtomicU64::new(chrono::Utc::now().timestamp_millis() as u64));
+
+/// Health status of the device when the revision was last taken.
+static TWIN_HEALTH_STATUS: Mutex<Option<HealthStatus>> = Mutex::new(None);
+
+/// Whether a push of the twin is waiting for `TWIN_PUSH_DELAY` to pass.
+static PUSH_SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+/// Bumps the revision of the twin and pushes the twin to the orchestrator.
+///
+/// Should be called whenever deployments or configuration change.
+pub fn twin_changed() {
+    TWIN_REVISION.fetch_add(1, Ordering::SeqCst);
+    schedule_twin_push();
+}
+
+/// Returns the current revision of the twin, bumping it first if the health status of the
+/// device has changed since it was last taken.
+pub fn twin_revision() -> u64 {
+    let (status, _) = health_summary();
+    let mut last_status = TWIN_HEALTH_STATUS.lock();
+    if last_status.is_some_and(|last| last != status) {
+        TWIN_REVISION.fetch_add(1, Ordering::SeqCst);
+    }
+    *last_status = Some(status);
+    TWIN_REVISION.load(Ordering::SeqCst)
+}
+
+/// Health status of the device from the latest health snapshot, and the reasons for it.
+fn health_summary() -> (HealthStatus, Vec<String>) {
+    let snapshot = current_health_snapshot();
+    get_supervisor_config().health_thresholds.evaluate(
+        snapshot.cpu_usage,
+        snapshot.memory_usage,
+        snapshot.instance_disk_usage,
+        snapshot.temperature,
+        pending_log_count(),
+    )
+}
+
+/// Builds the twin document of the device at its current revision.
+pub async fn build_twin() -> Value {
+    let revision = twin_revision();
+    let config = get_supervisor_config();
+    let snapshot = current_health_snapshot();
+    let (status, reasons) = health_summary();
+    json!({
+        "revision": revision,
+        "identity": {
+            "name": config.name,
+            "url": public_url(""),
+            "supervisorVersion": env!("CARGO_PKG_VERSION"),
+        },
+        "capabilities": get_device_description(),
+        "config": config,
+        "deployments": list_deployments().await,
+        "health": {
+            "status": status,
+            "reasons": reasons,
+            "sampledAt": snapshot.taken_at,
+        },
+    })
+}
+
+/// Pushes the twin to the orchestrator after `TWIN_PUSH_DELAY`, unless a push is already
+/// waiting, which then sends the latest twin.
+fn schedule_twin_push() {
+    let Some(orchestrator_url) = get_setting("WASMIOT_ORCHESTRATOR_URL") else {
+        return;
+    };
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    if PUSH_SCHEDULED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let func_name = function_name!().to_string();
+    runtime.spawn(async move {
+        tokio::time::sleep(TWIN_PUSH_DELAY).await;
+        PUSH_SCHEDULED.store(false, Ordering::SeqCst);
+        if let Err(e) = push_twin(&orchestrator_url).await {
+            send_log("WARN", &format!("Failed to push the device twin: {}", e), &func_name, None).await;
+        }
+    });
+}
+
+/// Posts the current twin to the orchestrator at `orchestrator_url`, if it takes twins.
+async fn push_twin(orchestrator_url: &str) -> Result<(), String> {
+    let version = api_version_of(orchestrator_url);
+    let Some(endpoint) = twin_endpoint(orchestrator_url, version) else {
+        log::debug!("Not pushing the device twin to an orchestrator of API version {}", version.header_value());
+        return Ok(());
+    };
+    let twin = build_twin().await;
+    let response = HTTP_CLIENT.post(&endpoint)
+        .header(API_VERSION_HEADER, ApiVersion::LATEST.header_value())
+        .json(&twin)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("Request to {} failed: {}", endpoint, e))?;
+    if !response.status().is_success() {
+        return Err(format!("{} answered {}", endpoint, response.status()));
+    }
+    log::debug!("Pushed revision {} of the device twin to {}", twin["revision"], endpoint);
+    Ok(())
+}
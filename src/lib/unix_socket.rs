@@ -0,0 +1,103 @@
+//! # unix_socket.rs
+//!
+//! An additional listener on a Unix domain socket, for agents running next to the supervisor,
+//! e.g. in the same pod, that should not need to go through the network to call it.
+//!
+//! When `WASMIOT_UNIX_SOCKET` is set to a path, the HTTP server serves the same routes on a
+//! socket there as well. A socket file left behind by an earlier run is replaced, but a file
+//! that is not a socket, or a socket another process still answers on, is not touched. The
+//! socket gets the permissions `WASMIOT_UNIX_SOCKET_MODE` (`660` by default), so that access to
+//! it is controlled by the owner and group of the file, and it is removed on shutdown.
+//!
+//! With `WASMIOT_UNIX_SOCKET_TRUSTED`, clients connected over the socket skip the checks that
+//! requests over the network go through, like the signatures of result URLs (see
+//! `url_signing.rs`).
+//!
+//! Unix domain sockets are only available on Unix, elsewhere the settings are ignored.
+
+use std::any::Any;
+use actix_web::dev::Extensions;
+use actix_web::HttpRequest;
+use crate::lib::constants::get_unix_socket_trusted;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::Path;
+
+/// Marks connections accepted on the Unix domain socket (see `mark_connection`).
+#[derive(Debug, Clone, Copy)]
+pub struct UnixPeer;
+
+/// Marks connections made over a Unix domain socket, for `HttpServer::on_connect`.
+pub fn mark_connection(connection: &dyn Any, data: &mut Extensions) {
+    #[cfg(unix)]
+    if connection.is::<tokio::net::UnixStream>() {
+        data.insert(UnixPeer);
+    }
+    #[cfg(not(unix))]
+    let _ = (connection, data);
+}
+
+/// Whether a request came over the Unix domain socket.
+pub fn is_unix_peer(req: &HttpRequest) -> bool {
+    req.conn_data::<UnixPeer>().is_some()
+}
+
+/// Whether a request skips the checks of requests over the network, because it came over the
+/// Unix domain socket and clients of the socket are trusted.
+pub fn is_trusted_peer(req: &HttpRequest) -> bool {
+    is_unix_peer(req) && get_unix_socket_trusted()
+}
+
+/// Makes way for a socket at `path`, removing a socket file nothing listens on anymore.
+///
+/// # Returns
+/// An error if `path` is something else than a socket, or a socket in use.
+#[cfg(unix)]
+pub fn prepare_socket_path(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Ok(_) => match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another process", path.display()),
+            )),
+            Err(_) => {
+                log::info!("Removing stale socket {}", path.display());
+                std::fs::remove_file(path)
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => std::fs::create_dir_all(parent),
+            _ => Ok(()),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Listens on a socket at `path` with the permissions `mode`, replacing a stale socket file
+/// (see `prepare_socket_path`).
+///
+/// The listener is handed to `HttpServer::listen_uds`, as `bind_uds` does not run the
+/// `on_connect` callback that marks the connections of the socket.
+#[cfg(unix)]
+pub fn bind_socket(path: &Path, mode: u32) -> io::Result<std::os::unix::net::UnixListener> {
+    prepare_socket_path(path)?;
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// Removes the socket file at `path` on shutdown, if it is still a socket.
+#[cfg(unix)]
+pub fn remove_socket(path: &Path) {
+    let is_socket = std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket());
+    if is_socket && let Err(e) = std::fs::remove_file(path) {
+        log::warn!("Failed to remove socket {}: {}", path.display(), e);
+    }
+}
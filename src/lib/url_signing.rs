@@ -0,0 +1,102 @@
+//! # url_signing.rs
+//!
+//! Expiring signatures of result file URLs.
+//!
+//! When `WASMIOT_SIGN_RESULT_URLS=true`, the URLs of result files given out by the supervisor
+//! carry `exp` (expiry as a Unix timestamp) and `sig` query parameters, and the routes serving
+//! result files refuse requests without a valid, unexpired signature. The signature is the hex
+//! encoded HMAC-SHA256 of `<path>\n<exp>` with the device secret: `WASMIOT_RESULT_URL_SECRET`,
+//! or a random secret generated into `result-url.secret` in the instance folder on first use,
+//! so that URLs stay valid over restarts. URLs are valid for `WASMIOT_RESULT_URL_TTL_SECONDS`.
+//!
+//! Only the path is signed, not the public base URL in front of it, because a proxy may
+//! rewrite the base before the request reaches the supervisor.
+
+use std::collections::HashMap;
+use std::fs;
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use crate::lib::constants::{
+    INSTANCE_PATH,
+    RESULT_URL_SECRET_FILE_NAME,
+    get_sign_result_urls,
+    get_result_url_secret,
+    get_result_url_ttl,
+};
+
+/// Secret result file URLs are signed with, loaded on first use.
+static DEVICE_SECRET: Lazy<Vec<u8>> = Lazy::new(load_device_secret);
+
+/// Reads the device secret from env or the instance folder, generating one if there is none.
+fn load_device_secret() -> Vec<u8> {
+    if let Some(secret) = get_result_url_secret() {
+        return secret.into_bytes();
+    }
+    let path = INSTANCE_PATH.join(RESULT_URL_SECRET_FILE_NAME);
+    if let Ok(contents) = fs::read_to_string(&path)
+        && let Ok(secret) = hex::decode(contents.trim())
+        && !secret.is_empty()
+    {
+        return secret;
+    }
+    let mut secret = vec![0u8; 32];
+    openssl::rand::rand_bytes(&mut secret).expect("Failed to generate secret for result URLs");
+    if let Err(e) = fs::write(&path, hex::encode(&secret)) {
+        log::error!(
+            "Failed to save secret for result URLs to {}, signed URLs will not be valid after a restart: {}",
+            path.display(), e
+        );
+    }
+    secret
+}
+
+/// Returns the signature of `path` expiring at `expires` (Unix timestamp) with `secret`.
+pub fn signature(secret: &[u8], path: &str, expires: i64) -> Result<String, String> {
+    let key = PKey::hmac(secret).map_err(|e| format!("Invalid secret: {}", e))?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+    signer.update(format!("{}\n{}", path, expires).as_bytes()).map_err(|e| e.to_string())?;
+    signer.sign_to_vec().map(hex::encode).map_err(|e| e.to_string())
+}
+
+/// Checks that `query` carries a signature of `path` with `secret` that has not expired by `now`.
+pub fn verify_signature(secret: &[u8], path: &str, query: &str, now: i64) -> Result<(), String> {
+    let params: HashMap<String, String> = serde_urlencoded::from_str(query).unwrap_or_default();
+    let (Some(expires), Some(given)) = (params.get("exp"), params.get("sig")) else {
+        return Err("URL is not signed".to_string());
+    };
+    let expires: i64 = expires.parse().map_err(|_| "Invalid expiry of signed URL".to_string())?;
+    if expires < now {
+        return Err("Signed URL has expired".to_string());
+    }
+    let expected = signature(secret, path, expires)?;
+    if given.len() != expected.len() || !openssl::memcmp::eq(given.as_bytes(), expected.as_bytes()) {
+        return Err("Invalid signature of URL".to_string());
+    }
+    Ok(())
+}
+
+/// Appends an expiring signature to the path of a result file URL, if result URLs are signed.
+pub fn sign_path(path: &str) -> String {
+    if !get_sign_result_urls() {
+        return path.to_string();
+    }
+    let expires = Utc::now().timestamp() + get_result_url_ttl() as i64;
+    match signature(&DEVICE_SECRET, path, expires) {
+        Ok(sig) => format!("{}?exp={}&sig={}", path, expires, sig),
+        Err(e) => {
+            log::error!("Failed to sign result URL {}: {}", path, e);
+            path.to_string()
+        }
+    }
+}
+
+/// Checks the signature of a request to a result file URL, if result URLs are signed.
+pub fn verify_path(path: &str, query: &str) -> Result<(), String> {
+    if !get_sign_result_urls() {
+        return Ok(());
+    }
+    verify_signature(&DEVICE_SECRET, path, query, Utc::now().timestamp())
+}
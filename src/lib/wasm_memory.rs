@@ -0,0 +1,93 @@
+//! # wasm_memory.rs
+//!
+//! Memory used by the Wasm modules of each deployment, so that the orchestrator can tell which
+//! deployment takes up the memory of a device, and how much of the memory of the supervisor
+//! process is its Wasm modules.
+//!
+//! For each module, the size of the linear memories it exports is recorded after each execution
+//! of one of its functions and whenever `GET /deploy/{id}/stats` is asked for, along with how
+//! much the resident set of the process grew when the module was instantiated. Both are cheap
+//! to read: the size of a memory is kept by Wasmtime, and the resident set is read for this
+//! process alone. The growth of the resident set is approximate, as other threads may allocate
+//! at the same time.
+//!
+//! The recorded sizes of all deployments are added up as `wasm_memory_bytes` of `/health`,
+//! which does not wait for running executions to measure them again.
+
+use std::collections::{BTreeMap, HashMap};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+use crate::lib::wasmtime::WasmtimeRuntime;
+
+/// Memory used by a module of a deployment, when it was last measured.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleMemory {
+    /// Size of the linear memories the module exports, or `None` for a component, whose
+    /// memories cannot be reached from the host.
+    #[serde(rename = "linearMemoryBytes")]
+    pub linear_memory_bytes: Option<u64>,
+    /// How much the resident set of the process grew when the module was instantiated.
+    #[serde(rename = "instantiationRssDeltaBytes")]
+    pub instantiation_rss_delta_bytes: Option<i64>,
+    /// When the memory was measured.
+    #[serde(rename = "measuredAt")]
+    pub measured_at: DateTime<Utc>,
+}
+
+/// Memory of each module with a runtime, by deployment ID and module name.
+static MEMORY: Lazy<Mutex<HashMap<String, BTreeMap<String, ModuleMemory>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Resident set size of the supervisor process in bytes, if it can be read.
+pub fn resident_set_bytes() -> Option<u64> {
+    let pid = sysinfo::get_current_pid().ok()?;
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::Some(&[pid]), false, ProcessRefreshKind::nothing().with_memory());
+    system.process(pid).map(|process| process.memory())
+}
+
+/// Measures the memory of a module of a deployment in its runtime and records it.
+pub fn record_module_memory(deployment_id: &str, module_name: &str, runtime: &mut WasmtimeRuntime) {
+    let memory = ModuleMemory {
+        linear_memory_bytes: runtime.linear_memory_bytes(module_name),
+        instantiation_rss_delta_bytes: runtime.modules.get(module_name).and_then(|module| module.instantiation_rss_delta),
+        measured_at: Utc::now(),
+    };
+    MEMORY.lock().entry(deployment_id.to_string()).or_default().insert(module_name.to_string(), memory);
+}
+
+/// Measures the memory of each module of a deployment that has a runtime and records it in
+/// place of what was recorded before, forgetting modules whose runtimes were dropped.
+pub fn record_deployment_memory(deployment_id: &str, runtimes: &mut HashMap<String, WasmtimeRuntime>) {
+    MEMORY.lock().remove(deployment_id);
+    for (module_name, runtime) in runtimes.iter_mut() {
+        record_module_memory(deployment_id, module_name, runtime);
+    }
+}
+
+/// The memory of each module of a deployment and their total, as served at
+/// `GET /deploy/{id}/stats`.
+pub fn memory_report(deployment_id: &str) -> Value {
+    let modules = MEMORY.lock().get(deployment_id).cloned().unwrap_or_default();
+    let total: u64 = modules.values().filter_map(|module| module.linear_memory_bytes).sum();
+    json!({
+        "linearMemoryBytes": total,
+        "modules": modules,
+    })
+}
+
+/// Total size of the linear memories of all deployments, as last recorded.
+pub fn total_wasm_memory_bytes() -> u64 {
+    MEMORY.lock().values()
+        .flat_map(BTreeMap::values)
+        .filter_map(|module| module.linear_memory_bytes)
+        .sum()
+}
+
+/// Forgets the memory of a deployment, when it is deleted or created again.
+pub fn remove_memory(deployment_id: &str) {
+    MEMORY.lock().remove(deployment_id);
+}
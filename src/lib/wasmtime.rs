@@ -15,21 +15,50 @@
 //! It also defines:
 //! - `WasmtimeRuntime`: The central runtime manager
 //! - `WasmtimeModule`: A single Wasm module instance
+//! - `FunctionSignature`: Parameter and result types of an exported function, cached per module
 //! - `ModuleConfig`: Configuration structure used to load modules
 //! - `MLModel`: Structure representing an associated machine learning model
+//!
+//! Besides core modules, binaries may be components of the component model (WASI preview 2),
+//! as built by recent toolchains such as `cargo component`. `is_component` tells them apart
+//! from the header of the binary, and components are instantiated with a component `Linker`
+//! and WASI preview 2 in the same store as the core modules. Their exported functions are
+//! called with arguments converted from JSON to the types of the component model (see
+//! `ComponentSignature` and `run_component_function`).
+//!
+//! All runtimes share one `Engine`, so that the runtimes of identical binaries, such as ten
+//! deployments of the same module, share one compiled `Module` from `MODULE_CACHE` while each
+//! still gets a `Store` and instance of its own. Modules are cached by the SHA-256 digest of
+//! their binary for as long as a runtime uses them.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::io::Read;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use anyhow::Result;
-use wasmtime::{Config, Engine, Func, FuncType, Instance, Linker, Memory, MemoryAccessError, Module, Store, Val, ValType};
+use wasmtime::{Config, Engine, Extern, ExternType, Func, FuncType, Instance, Linker, Memory, MemoryAccessError, Module, Store, Val, ValType};
+use wasmtime::component::{self, Component, Type as ComponentType, Val as ComponentVal};
+use wasmtime::component::types::ComponentItem;
+use indexmap::IndexMap;
+use serde_json::Value;
 #[cfg(not(feature="armv6"))]
 use wasmtime_wasi::p1::{self, WasiP1Ctx};
 #[cfg(not(feature="armv6"))]
-use wasmtime_wasi::{WasiCtxBuilder, DirPerms, FilePerms};
+use wasmtime_wasi::{WasiCtxBuilder, DirPerms, FilePerms, ResourceTable, WasiCtx, WasiCtxView, WasiView};
 use log::{info, error};
 use crate::lib::wasmtime_imports;
-use crate::lib::constants::{SERIALIZED_MODULE_POSTFIX, MEMORY_NAME};
+use crate::lib::camera::camera_enabled;
+use crate::lib::download::ArtifactSource;
+use crate::lib::constants::{get_force_pulley, SERIALIZED_MODULE_POSTFIX, PULLEY_MODULE_POSTFIX, MEMORY_NAME, WORK_FOLDER_NAME};
+use crate::lib::deployment::{component_arg, component_val_json, MountStage};
+use crate::lib::wasm_memory::resident_set_bytes;
+#[cfg(not(feature="armv6"))]
+use crate::lib::constants::ARTIFACT_CACHE_FOLDER;
 use std::fmt;
 use wasmtime_wasi_nn::witx;
 use wasmtime_wasi_nn::witx::WasiNnCtx;
@@ -42,11 +71,134 @@ use wasmtime_wasi_nn::backend;
 
 // ----------------------- Wasmtime Runtime related functionality ----------------------- //
 
+/// The engine of all runtimes. Compiled modules can only be used with the engine they were
+/// compiled with, so sharing it is what lets runtimes share them.
+///
+/// With `WASMIOT_FORCE_PULLEY` set, or when built with the `pulley` feature, modules are
+/// compiled to Pulley bytecode and run on its interpreter as on armv6 devices, so that the
+/// portable path can be tested on other hosts.
+static ENGINE: Lazy<Engine> = Lazy::new(|| {
+    let pulley = get_force_pulley();
+    let engine = engine_config(pulley)
+        .and_then(|mut config| {
+            config.async_support(true);
+            config.epoch_interruption(true);
+            Engine::new(&config).map_err(|e| e.to_string())
+        })
+        .unwrap();
+    if engine.is_pulley() {
+        info!("Running Wasm modules on the Pulley interpreter ({}).", pulley_target());
+    }
+
+    // Spawn a thread to increment the store epochs and eventually trigger timeouts
+    let ticking_engine = engine.clone();
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+            ticking_engine.increment_epoch();
+        }
+    });
+    engine
+});
+
+/// Target of the Pulley interpreter on this host. Pulley bytecode has the pointer width of the
+/// host it runs on, so the `pulley32` artifacts of armv6 devices run on 32-bit hosts only.
+pub fn pulley_target() -> &'static str {
+    if cfg!(target_pointer_width = "64") { "pulley64" } else { "pulley32" }
+}
+
+/// Configuration of an engine compiling modules to native code, or to Pulley bytecode for the
+/// interpreter if `pulley` is set.
+pub fn engine_config(pulley: bool) -> Result<Config, String> {
+    let mut config = Config::default();
+    if pulley {
+        config.target(pulley_target())
+            .map_err(|e| format!("Failed to target the Pulley interpreter: {}", e))?;
+    }
+    Ok(config)
+}
+
+/// A compiled module in `MODULE_CACHE`, with the lease of the runtimes using it.
+struct CachedModule {
+    module: Module,
+    lease: Weak<ModuleCacheLease>,
+}
+
+/// Compiled modules by the SHA-256 digest of their binary.
+static MODULE_CACHE: Lazy<Mutex<HashMap<String, CachedModule>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Number of times a module was taken from `MODULE_CACHE` instead of being read from disk.
+static MODULE_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+
+/// Keeps a compiled module in `MODULE_CACHE`, shared by the modules using it. The module is
+/// evicted once the last of them is dropped, i.e. the last deployment using it is deleted.
+#[derive(Debug)]
+pub struct ModuleCacheLease {
+    digest: String,
+}
+
+impl Drop for ModuleCacheLease {
+    fn drop(&mut self) {
+        let mut cache = MODULE_CACHE.lock();
+        // The binary may have been loaded again since
+        if cache.get(&self.digest).is_some_and(|cached| cached.lease.strong_count() == 0) {
+            cache.remove(&self.digest);
+        }
+    }
+}
+
+/// Occupancy of the shared module cache, reported in the health of the device.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleCacheStats {
+    /// Compiled modules in the cache
+    pub modules: usize,
+    /// Loads served from the cache since startup
+    pub hits: u64,
+}
+
+/// Takes the compiled module of the binary with the given digest from the cache, if a runtime uses it.
+fn cached_module(digest: &str) -> Option<(Module, Arc<ModuleCacheLease>)> {
+    let cache = MODULE_CACHE.lock();
+    let cached = cache.get(digest)?;
+    let lease = cached.lease.upgrade()?;
+    MODULE_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+    Some((cached.module.clone(), lease))
+}
+
+/// Adds a compiled module to the cache, or takes the cached one if another runtime added it meanwhile.
+fn share_module(digest: &str, module: Module) -> (Module, Arc<ModuleCacheLease>) {
+    let mut cache = MODULE_CACHE.lock();
+    if let Some(cached) = cache.get(digest)
+        && let Some(lease) = cached.lease.upgrade()
+    {
+        return (cached.module.clone(), lease);
+    }
+    let lease = Arc::new(ModuleCacheLease { digest: digest.to_string() });
+    cache.insert(digest.to_string(), CachedModule { module: module.clone(), lease: Arc::downgrade(&lease) });
+    (module, lease)
+}
+
+/// Returns whether the compiled module of the binary with the given digest is in the cache.
+pub fn is_module_cached(digest: &str) -> bool {
+    MODULE_CACHE.lock().contains_key(digest)
+}
+
+/// Returns the occupancy of the shared module cache.
+pub fn module_cache_stats() -> ModuleCacheStats {
+    ModuleCacheStats {
+        modules: MODULE_CACHE.lock().len(),
+        hits: MODULE_CACHE_HITS.load(Ordering::Relaxed),
+    }
+}
+
+
 #[cfg(not(feature="armv6"))]
 pub struct WasmtimeRuntime {
     pub engine: Engine,
     pub store: Store<Ctx>,
     pub linker: Linker<Ctx>,
+    /// Linker of the components, with WASI preview 2
+    pub component_linker: component::Linker<Ctx>,
     pub modules: HashMap<String, WasmtimeModule>,
     pub functions: Option<HashMap<String, WasmtimeModule>>,
 }
@@ -67,6 +219,7 @@ impl fmt::Debug for WasmtimeRuntime {
             .field("engine", &"<Engine>")
             .field("store", &"<Store<Ctx>>")
             .field("linker", &"<Linker<Ctx>>")
+            .field("component_linker", &"<component::Linker<Ctx>>")
             .field("modules", &self.modules)
             .field("functions", &self.functions)
             .finish()
@@ -89,6 +242,9 @@ impl fmt::Debug for WasmtimeRuntime {
 pub struct Ctx {
     wasi: WasiP1Ctx,
     nn: WasiNnCtx,
+    /// WASI preview 2 of the components, with the same files and environment as `wasi`
+    wasi_p2: WasiCtx,
+    table: ResourceTable,
 }
 
 impl Ctx {
@@ -96,56 +252,57 @@ impl Ctx {
     fn nn(&mut self) -> &mut WasiNnCtx { &mut self.nn }
 }
 
+#[cfg(not(feature="armv6"))]
+impl WasiView for Ctx {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView { ctx: &mut self.wasi_p2, table: &mut self.table }
+    }
+}
+
 
 impl WasmtimeRuntime {
 
     // #[cfg(not(feature="armv6"))]
-    /// Initializes a new wasmtime runtime
-    pub async fn new(data_dirs: Vec<(String, String)>) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Initializes a new wasmtime runtime with the given directories preopened, creating the
+    /// ones that do not exist yet.
+    pub async fn new(preopens: Vec<Preopen>, env: Vec<(String, String)>) -> Result<Self, Box<dyn std::error::Error>> {
         
-        let mut config: Config = Config::default();
-        config.async_support(true);
-        config.epoch_interruption(true);
-        let engine: Engine = Engine::new(&config).unwrap();
+        let engine: Engine = ENGINE.clone();
         let args = std::env::args().skip(1).collect::<Vec<_>>();
         let mut linker: Linker<Ctx> = Linker::new(&engine);
-        let mut wasi_ctx = WasiCtxBuilder::new();
-        wasi_ctx.inherit_stdio();
-        wasi_ctx.inherit_env();
-        wasi_ctx.args(&args);
-        // let preopened_dirs = [("./tests", ".")];
-        let preopened_dirs = data_dirs;
-        for (source, target) in preopened_dirs {
-            wasi_ctx.preopened_dir(&source, &target, DirPerms::all(), FilePerms::all())?;
-        }
-        let wasi_p1 = wasi_ctx.build_p1();
+        // Core modules and components each get a WASI context built the same way
+        let wasi_builder = || -> Result<WasiCtxBuilder, Box<dyn std::error::Error>> {
+            let mut wasi_ctx = WasiCtxBuilder::new();
+            wasi_ctx.inherit_stdio();
+            wasi_ctx.inherit_env();
+            // Module specific variables, such as secrets, on top of the inherited ones
+            wasi_ctx.envs(&env);
+            wasi_ctx.args(&args);
+            for preopen in &preopens {
+                fs::create_dir_all(&preopen.host_path)?;
+                wasi_ctx.preopened_dir(&preopen.host_path, preopen.guest_path, preopen.dir_perms, preopen.file_perms)?;
+            }
+            Ok(wasi_ctx)
+        };
+        let wasi_p1 = wasi_builder()?.build_p1();
+        let wasi_p2 = wasi_builder()?.build();
         let backends = backend::list();
         let registry = InMemoryRegistry::new();
         let nn_ctx = WasiNnCtx::new(backends, registry.into());
-        let store = Store::new(&engine, Ctx { wasi: wasi_p1, nn: nn_ctx });
+        let store = Store::new(&engine, Ctx { wasi: wasi_p1, nn: nn_ctx, wasi_p2, table: ResourceTable::new() });
         p1::add_to_linker_async(&mut linker, |cx: &mut Ctx| cx.wasi())?;
         witx::add_to_linker(&mut linker, |cx: &mut Ctx| cx.nn())?;
+        let mut component_linker: component::Linker<Ctx> = component::Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_async(&mut component_linker)?;
 
         let modules: HashMap<String, WasmtimeModule> = HashMap::new();
         let functions = None; // TODO: What exactly should this be?
 
-        // Spawn a task to increment the store epochs and eventually trigger timeouts
-        let engine_reference = store.engine().weak();
-        let _timeout_task = std::thread::spawn(move || {
-            loop {
-                std::thread::sleep(std::time::Duration::from_secs(1));
-                if let Some(engine) = engine_reference.upgrade() {
-                    engine.increment_epoch();
-                } else {
-                    break;
-                }
-            }
-        });
-
         let mut runtime: WasmtimeRuntime = Self {
             engine,
             store,
             linker,
+            component_linker,
             modules,
             functions
         };
@@ -177,13 +334,43 @@ impl WasmtimeRuntime {
 
     pub async fn load_module(&mut self, config: ModuleConfig) -> Result<(), Box<dyn std::error::Error>>{
         if !self.modules.contains_key(&config.name){
+            // Components are loaded apart from core modules
+            #[cfg(not(feature = "armv6"))]
+            if is_component(&config.path) {
+                return self.load_component(config).await;
+            }
             let module_name: String = config.name.clone();
+            // Modules with a verified digest are compiled into the shared artifact cache by content,
+            // so redeploying an unchanged module does not compile it again
+            #[cfg(not(feature = "armv6"))]
+            let digest = config.binary_source.as_ref().and_then(|source| source.sha256.clone());
+            // A module another runtime has loaded already is not read again
+            #[cfg(not(feature = "armv6"))]
+            if let Some(digest) = &digest
+                && let Some((module, lease)) = cached_module(digest)
+            {
+                info!("Module {} shares its compilation with another deployment.", module_name);
+                return self.instantiate_module(config, module, Some(lease)).await;
+            }
+            // Pulley bytecode is kept apart from native code, where armv6 devices look for it
+            #[cfg(not(feature = "armv6"))]
+            let postfix = if self.engine.is_pulley() { PULLEY_MODULE_POSTFIX } else { SERIALIZED_MODULE_POSTFIX };
             #[cfg(not(feature = "armv6"))]
-            let path_serial = config.path.clone().with_extension(SERIALIZED_MODULE_POSTFIX);
+            let path_serial = match &digest {
+                Some(digest) => ARTIFACT_CACHE_FOLDER.join(format!("{}.{}", digest, postfix)),
+                None => config.path.clone().with_extension(postfix),
+            };
             #[cfg(feature = "armv6")]
             let path_serial = config.path.clone().with_extension(PULLEY_MODULE_POSTFIX);
             let should_compile: bool;
-            if fs::metadata(&path_serial).is_ok() {
+            #[cfg(not(feature = "armv6"))]
+            let cached_by_digest = digest.is_some();
+            #[cfg(feature = "armv6")]
+            let cached_by_digest = false;
+            if cached_by_digest {
+                // The content of a digest never changes, so an existing compilation is always current
+                should_compile = fs::metadata(&path_serial).is_err();
+            } else if fs::metadata(&path_serial).is_ok() {
                 // Serialized version of the module exists already, check timestamps. If serialized version is older, recompile.
                 let unserialized_module_modified = fs::metadata(&config.path)?.modified()?;
                 let serialized_module_modified = fs::metadata(&path_serial)?.modified()?;
@@ -197,6 +384,9 @@ impl WasmtimeRuntime {
                 // Compile and save a serialized version of the module
                 let module = wasmtime::Module::from_file(&self.engine, config.path.clone())?;
                 let module_bytes = module.serialize()?;
+                if let Some(parent) = path_serial.parent() {
+                    fs::create_dir_all(parent)?;
+                }
                 fs::write(&path_serial, module_bytes)?;
             }
             #[cfg(feature = "armv6")]
@@ -209,15 +399,30 @@ impl WasmtimeRuntime {
                 // NOTE: The serialized module file being loaded can be tampered with, which could cause issues, which makes it unsafe.
                 // For more info: https://docs.wasmtime.dev/api/wasmtime/struct.Module.html#method.deserialize_file
                 wasmtime::Module::deserialize_file(&self.engine, &path_serial)
-            }?;
+            };
             #[cfg(not(feature = "armv6"))]
-            let instance = self.linker.instantiate_async(&mut self.store, &deserialized_module).await?;
+            let deserialized_module = match deserialized_module {
+                // A cached compilation made by another version of the supervisor is not compatible
+                Err(e) if cached_by_digest => {
+                    info!("Recompiling module {} since its cached compilation could not be loaded: {}", module_name, e);
+                    let module = wasmtime::Module::from_file(&self.engine, config.path.clone())?;
+                    fs::write(&path_serial, module.serialize()?)?;
+                    Ok(module)
+                }
+                other => other,
+            };
+            let deserialized_module = deserialized_module?;
+            #[cfg(not(feature = "armv6"))]
+            let (deserialized_module, lease) = match &digest {
+                Some(digest) => {
+                    let (module, lease) = share_module(digest, deserialized_module);
+                    (module, Some(lease))
+                }
+                None => (deserialized_module, None),
+            };
             #[cfg(feature = "armv6")]
-            let instance = self.linker.instantiate(&mut self.store, &deserialized_module)?;
-            let mut wasmtime_module = WasmtimeModule::new(config)?;
-            wasmtime_module.module = Some(deserialized_module);
-            wasmtime_module.instance = Some(instance);
-            self.modules.insert(module_name.clone(), wasmtime_module);
+            let lease = None;
+            self.instantiate_module(config, deserialized_module, lease).await?;
         } else {
             info!("Module {} is already loaded.", &config.name);
         }
@@ -225,6 +430,46 @@ impl WasmtimeRuntime {
     }
 
 
+    /// Compiles a component of the component model and instantiates it in this runtime's store
+    /// with WASI preview 2.
+    ///
+    /// The host functions of the supervisor, such as the camera, are only linked for core
+    /// modules. Components are compiled on every load, and not shared between runtimes.
+    #[cfg(not(feature = "armv6"))]
+    async fn load_component(&mut self, config: ModuleConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let component = Component::from_file(&self.engine, &config.path)?;
+        let rss_before = resident_set_bytes();
+        let instance = self.component_linker.instantiate_async(&mut self.store, &component).await?;
+        let module_name = config.name.clone();
+        let mut wasmtime_module = WasmtimeModule::new(config)?;
+        wasmtime_module.instantiation_rss_delta = rss_delta(rss_before);
+        wasmtime_module.component_signatures = component_signatures(&self.engine, &component);
+        wasmtime_module.component = Some(LoadedComponent { component, instance });
+        info!("Module {} is a component.", module_name);
+        self.modules.insert(module_name, wasmtime_module);
+        Ok(())
+    }
+
+
+    /// Instantiates a compiled module in this runtime's store
+    async fn instantiate_module(&mut self, config: ModuleConfig, module: Module, lease: Option<Arc<ModuleCacheLease>>) -> Result<(), Box<dyn std::error::Error>> {
+        let rss_before = resident_set_bytes();
+        #[cfg(not(feature = "armv6"))]
+        let instance = self.linker.instantiate_async(&mut self.store, &module).await?;
+        #[cfg(feature = "armv6")]
+        let instance = self.linker.instantiate(&mut self.store, &module)?;
+        let module_name = config.name.clone();
+        let mut wasmtime_module = WasmtimeModule::new(config)?;
+        wasmtime_module.signatures = function_signatures(&module);
+        wasmtime_module.module = Some(module);
+        wasmtime_module.instance = Some(instance);
+        wasmtime_module.cache_lease = lease;
+        wasmtime_module.instantiation_rss_delta = rss_delta(rss_before);
+        self.modules.insert(module_name, wasmtime_module);
+        Ok(())
+    }
+
+
     /// Read from wasmtime default runtime memory and save results to buffer
     pub async fn read_from_memory(&mut self, module_name: &str, offset: usize, buffer: &mut [u8] ) -> Result<(), MemoryAccessError> {
         // Attempt to fill the given buffer by reading memory, starting from offset
@@ -236,6 +481,30 @@ impl WasmtimeRuntime {
     }
 
 
+    /// Reads a UTF-8 string the module returned as a pointer and a length into its memory.
+    ///
+    /// # Returns
+    /// The string, and whether it was not valid UTF-8 so that the invalid bytes were replaced.
+    pub async fn read_string(&mut self, module_name: &str, ptr: u32, len: u32) -> Result<(String, bool), String> {
+        let memory = self.get_memory(module_name, MEMORY_NAME).await
+            .ok_or_else(|| format!("Module '{}' does not export its memory as '{}'", module_name, MEMORY_NAME))?;
+        let end = ptr as usize + len as usize;
+        if end > memory.data_size(&self.store) {
+            return Err(format!(
+                "The result at {} ({} bytes) is outside the memory of module '{}'",
+                ptr, len, module_name
+            ));
+        }
+        let mut buffer = vec![0u8; len as usize];
+        self.read_from_memory(module_name, ptr as usize, &mut buffer).await
+            .map_err(|e| format!("Failed to read the result from the memory of module '{}': {}", module_name, e))?;
+        Ok(match String::from_utf8(buffer) {
+            Ok(text) => (text, false),
+            Err(e) => (String::from_utf8_lossy(e.as_bytes()).into_owned(), true),
+        })
+    }
+
+
     /// Write buffer into default wasmtime runtime memory
     pub async fn write_to_memory(&mut self, module_name: &str, offset: usize, buffer: &mut [u8]) -> Result<(), MemoryAccessError> {
         // Attempt to write the contents of given buffer into memory, with offset being the starting position
@@ -247,6 +516,42 @@ impl WasmtimeRuntime {
     }
 
 
+    /// Copies a file straight into module memory starting from offset, without buffering it
+    /// on the host first, for large inputs like ML models and images.
+    ///
+    /// # Returns
+    /// The number of bytes copied, or an error if the file does not fit in the memory as it
+    /// currently is.
+    pub async fn write_file_to_memory(&mut self, module_name: &str, offset: usize, path: &Path) -> Result<usize, String> {
+        let memory = self.get_memory(module_name, MEMORY_NAME).await
+            .ok_or_else(|| format!("Module {} has no memory", module_name))?;
+        let mut file = fs::File::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let size = file.metadata()
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?
+            .len() as usize;
+        let range = memory_range(memory.data_size(&self.store), offset, size)?;
+        let mut target = &mut memory.data_mut(&mut self.store)[range];
+        let copied = std::io::copy(&mut file, &mut target)
+            .map_err(|e| format!("Failed to copy {} into memory: {}", path.display(), e))?;
+        if copied as usize != size {
+            return Err(format!("{} changed while it was copied into memory", path.display()));
+        }
+        Ok(size)
+    }
+
+
+    /// Writes `len` bytes of module memory starting from offset straight to a file, without
+    /// copying them to the host first.
+    pub async fn read_memory_to_file(&mut self, module_name: &str, offset: usize, len: usize, path: &Path) -> Result<(), String> {
+        let memory = self.get_memory(module_name, MEMORY_NAME).await
+            .ok_or_else(|| format!("Module {} has no memory", module_name))?;
+        let range = memory_range(memory.data_size(&self.store), offset, len)?;
+        fs::write(path, &memory.data(&self.store)[range])
+            .map_err(|e| format!("Failed to write memory to {}: {}", path.display(), e))
+    }
+
+
     /// Get a module from the list of modules in this runtime, if it exists there
     pub async fn get_module(&self, module_name: &str) -> Option<&WasmtimeModule> {
         let _ = match self.modules.get(module_name) {
@@ -311,17 +616,21 @@ impl WasmtimeRuntime {
     }
 
 
+    /// Gets the signature of a given function in a given module, as cached when the module was loaded
+    pub fn get_signature(&self, module_name: &str, func_name: &str) -> Option<&FunctionSignature> {
+        let signature = self.modules.get(module_name)?.signatures.get(func_name);
+        if signature.is_none() {
+            error!("Function '{}' not found in module '{}'", func_name, module_name);
+        }
+        signature
+    }
+
+
     /// Gets the function parameters and returns of a given function in a given module
     pub async fn get_func_params(&mut self, module_name: &str, func_name: &str) -> (Vec<ValType>, Vec<ValType>) {
-        let func = self.get_function(&module_name, &func_name).await;
-        match func {
-            Some(f) => {
-                let func_ty = f.ty(&self.store);
-                let param_types: Vec<ValType> = func_ty.params().collect();
-                let return_types: Vec<ValType> = func_ty.results().collect();
-                return (param_types, return_types)
-            },
-            None => return (vec![], vec![])
+        match self.get_signature(module_name, func_name) {
+            Some(signature) => (signature.params.clone(), signature.results.clone()),
+            None => (vec![], vec![])
         }
     }
 
@@ -355,17 +664,35 @@ impl WasmtimeRuntime {
     /// Gets the wasmtime linear memory
     pub async fn get_memory(&mut self, module_name: &str, memory_name: &str) -> Option<Memory> {
         let _ = match self.get_instance(module_name).await {
-            Some(i) => return i.get_export(&mut self.store, memory_name).unwrap().into_memory(),
+            Some(i) => return i.get_export(&mut self.store, memory_name).and_then(Extern::into_memory),
             None => return None
         };
     }
 
 
+    /// Total size of the linear memories a module exports, in bytes, or `None` for a component,
+    /// whose memories cannot be reached from the host.
+    pub fn linear_memory_bytes(&mut self, module_name: &str) -> Option<u64> {
+        let instance = self.modules.get(module_name)?.instance?;
+        let memories: Vec<Memory> = instance.exports(&mut self.store)
+            .filter_map(|export| export.into_memory())
+            .collect();
+        Some(memories.iter().map(|memory| memory.data_size(&self.store) as u64).sum())
+    }
+
+
     /// Run a function in the current wasm module with given parameters and return a given number of results
-    pub async fn run_function(&mut self, module_name: &str, func_name: &str, params: Vec<Val>, returns: usize) -> Vec<Val>{
-        // Timeout for wasm module execution in seconds
+    ///
+    /// Traps, including the one of running over `WASMIOT_MODULE_TIMEOUT_SECONDS`, fail the call
+    /// with the trap and its Wasm backtrace.
+    pub async fn run_function(&mut self, module_name: &str, func_name: &str, params: Vec<Val>, returns: usize) -> Result<Vec<Val>, String> {
         let timeout = crate::lib::constants::get_module_timeout();
-        
+        self.run_function_within(module_name, func_name, params, returns, timeout).await
+    }
+
+
+    /// Like `run_function`, but trapping once the function has run for `timeout` seconds.
+    pub async fn run_function_within(&mut self, module_name: &str, func_name: &str, params: Vec<Val>, returns: usize, timeout: u64) -> Result<Vec<Val>, String> {
         // Set store deadline and behaviour to trap once deadline is reached
         self.store.set_epoch_deadline(timeout);
         self.store.epoch_deadline_trap();
@@ -373,24 +700,84 @@ impl WasmtimeRuntime {
         let params_thingy: &[Val] = &params;
         let returns_thingy: &mut[Val] = &mut vec![Val::I32(0); returns];
         info!("Attempting to run function {} from module {}...", func_name, module_name);
-        let _ = match &self.get_function(module_name, func_name).await {
-            Some(func) => {
-                #[cfg(not(feature="armv6"))]
-                {
-                    // Execute, and return result
-                    let res = func.call_async(&mut self.store, params_thingy, returns_thingy).await;
-                    res
-                }
-                #[cfg(feature="armv6")]
-                func.call(&mut self.store, params_thingy, returns_thingy)
-            }
-            None => {
-                error!("Failed to run function {} from module {}.", func_name, module_name);
-                Ok(())
-            }
+        let Some(func) = self.get_function(module_name, func_name).await else {
+            error!("Failed to run function {} from module {}.", func_name, module_name);
+            return Err(format!("Module '{}' does not export function '{}'", module_name, func_name));
         };
+        #[cfg(not(feature="armv6"))]
+        let called = func.call_async(&mut self.store, params_thingy, returns_thingy).await;
+        #[cfg(feature="armv6")]
+        let called = func.call(&mut self.store, params_thingy, returns_thingy);
+        called.map_err(|e| format!("Function '{}' of module '{}' failed: {:#}", func_name, module_name, e))?;
         info!("Ran module {:?} with function {:?} with params {:?}, result was {:?}.", module_name, func_name, params, returns_thingy.to_vec());
-        return returns_thingy.to_vec();
+        Ok(returns_thingy.to_vec())
+    }
+
+
+    /// Runs a function a component exports with the given arguments, converted from JSON to the
+    /// types of its parameters (see `ComponentSignature::bind` and `component_arg`).
+    ///
+    /// As with `run_function`, traps fail the call, and so do arguments that cannot be converted.
+    ///
+    /// # Returns
+    /// The first result of the function as JSON, or `null` if it has none.
+    pub async fn run_component_function(&mut self, module_name: &str, func_name: &str, args: &IndexMap<String, Value>) -> Result<Value, String> {
+        // The same timeout as for core modules
+        let timeout = crate::lib::constants::get_module_timeout();
+        self.run_component_function_within(module_name, func_name, args, timeout).await
+    }
+
+
+    /// Like `run_component_function`, but trapping once the function has run for `timeout`
+    /// seconds.
+    pub async fn run_component_function_within(&mut self, module_name: &str, func_name: &str, args: &IndexMap<String, Value>, timeout: u64) -> Result<Value, String> {
+        self.store.set_epoch_deadline(timeout);
+        self.store.epoch_deadline_trap();
+
+        let module = self.modules.get(module_name)
+            .ok_or_else(|| format!("Module '{}' not found", module_name))?;
+        let instance = module.component.as_ref()
+            .map(|component| component.instance)
+            .ok_or_else(|| format!("Module '{}' is not a component", module_name))?;
+        let signature = module.component_signatures.get(func_name).cloned()
+            .ok_or_else(|| format!("Component '{}' does not export function '{}'", module_name, func_name))?;
+        if args.len() < signature.params.len() {
+            return Err(format!(
+                "Function '{}' has {} parameters but {} arguments were given",
+                func_name, signature.params.len(), args.len()
+            ));
+        }
+        let params = signature.bind(args)
+            .into_iter()
+            .map(|(name, value, typ)| component_arg(value, typ).ok_or_else(|| {
+                format!("Argument '{}' ({}) is not a valid {}", name, value, component_type_name(typ))
+            }))
+            .collect::<Result<Vec<ComponentVal>, String>>()?;
+
+        info!("Attempting to run function {} from component {}...", func_name, module_name);
+        let func = instance.get_func(&mut self.store, func_name)
+            .ok_or_else(|| format!("Component '{}' does not export function '{}'", module_name, func_name))?;
+        let mut results = vec![ComponentVal::Bool(false); signature.results.len()];
+        func.call_async(&mut self.store, &params, &mut results).await
+            .map_err(|e| format!("Function '{}' of component '{}' failed: {:#}", func_name, module_name, e))?;
+        func.post_return_async(&mut self.store).await
+            .map_err(|e| format!("Function '{}' of component '{}' failed: {:#}", func_name, module_name, e))?;
+        info!("Ran component {:?} with function {:?}, result was {:?}.", module_name, func_name, results);
+        Ok(results.first().map(component_val_json).unwrap_or(Value::Null))
+    }
+
+
+    /// Gets the signatures of the functions a component exports, from the component as loaded in
+    /// this runtime, or compiled from its file if it has not been loaded.
+    pub fn component_signatures_of(&self, config: &ModuleConfig) -> Result<HashMap<String, ComponentSignature>, String> {
+        if let Some(module) = self.modules.get(&config.name)
+            && module.component.is_some()
+        {
+            return Ok(module.component_signatures.clone());
+        }
+        let component = Component::from_file(&self.engine, &config.path)
+            .map_err(|e| format!("Failed to compile component '{}': {}", config.name, e))?;
+        Ok(component_signatures(&self.engine, &component))
     }
 
 
@@ -407,6 +794,71 @@ impl WasmtimeRuntime {
         };
     }
 
+
+    /// Gets the compiled module of a module, as loaded in this runtime, or compiled from its
+    /// file if it has not been loaded, e.g. because its imports could not be linked
+    pub fn compiled_module(&self, config: &ModuleConfig) -> Result<Module, String> {
+        if is_component(&config.path) {
+            return Err(format!("Module '{}' is a component, not a core module", config.name));
+        }
+        if let Some(module) = self.modules.get(&config.name).and_then(|module| module.module.clone()) {
+            return Ok(module);
+        }
+        #[cfg(not(feature = "armv6"))]
+        return Module::from_file(&self.engine, &config.path)
+            .map_err(|e| format!("Failed to compile module '{}': {}", config.name, e));
+        #[cfg(feature = "armv6")]
+        Err(format!("Module '{}' has not been loaded, and cannot be compiled on armv6 devices", config.name))
+    }
+
+
+    /// Tells how each import of a module is satisfied by the functions linked into this runtime
+    pub fn import_report(&mut self, module: &Module) -> Vec<ImportReport> {
+        module.imports().map(|import| {
+            let (provider, capability) = import_provider(import.module());
+            let granted = capability.is_none_or(capability_granted);
+            let expected = import.ty().func().map(ImportSignature::from);
+            let linked = self.linker.get(&mut self.store, import.module(), import.name());
+            let provided = match &linked {
+                Some(Extern::Func(func)) => Some(func.ty(&self.store)),
+                _ => None,
+            };
+            let signature_mismatch = match (import.ty().func(), &provided) {
+                (Some(expected), Some(provided)) => !provided.matches(expected),
+                _ => false,
+            };
+            let kind = extern_type_kind(&import.ty());
+            let linked_kind = linked.as_ref().map(|linked| extern_type_kind(&linked.ty(&self.store)));
+            let satisfied = linked_kind == Some(kind) && !signature_mismatch;
+            let problem = match linked_kind {
+                None => Some(format!("No {} '{}' is provided in '{}'", kind, import.name(), import.module())),
+                Some(linked_kind) if linked_kind != kind => Some(format!("A {} is provided instead of a {}", linked_kind, kind)),
+                Some(_) if signature_mismatch => Some("The provided function does not have the signature the module expects".to_string()),
+                Some(_) if !granted => Some(format!("The {} capability is not available on this device", capability.unwrap_or_default())),
+                Some(_) => None,
+            };
+            ImportReport {
+                module: import.module().to_string(),
+                name: import.name().to_string(),
+                kind,
+                provider: if satisfied { provider } else { "unsatisfied" },
+                capability,
+                granted,
+                satisfied,
+                expected,
+                provided: provided.as_ref().map(ImportSignature::from),
+                signature_mismatch,
+                problem,
+            }
+        }).collect()
+    }
+
+}
+
+
+/// How much the resident set of the process has grown since it was `before`.
+fn rss_delta(before: Option<u64>) -> Option<i64> {
+    Some(resident_set_bytes()? as i64 - before? as i64)
 }
 
 
@@ -416,10 +868,20 @@ impl WasmtimeRuntime {
 pub struct WasmtimeModule {
     pub module: Option<Module>,
     pub instance: Option<Instance>,
+    /// The component and its instance, in place of `module` and `instance` for a component
+    pub component: Option<LoadedComponent>,
+    /// Signatures of the functions a component exports, by function name
+    pub component_signatures: HashMap<String, ComponentSignature>,
     pub id: String,
     pub name: String,
     pub path: PathBuf,
-    pub functions: Option<Vec<String>>
+    pub functions: Option<Vec<String>>,
+    /// Signatures of the exported functions, by function name
+    pub signatures: HashMap<String, FunctionSignature>,
+    /// Keeps the compiled module in `MODULE_CACHE` while this module uses it
+    pub cache_lease: Option<Arc<ModuleCacheLease>>,
+    /// How much the resident set of the process grew when the module was instantiated
+    pub instantiation_rss_delta: Option<i64>,
 }
 
 
@@ -433,10 +895,15 @@ impl WasmtimeModule {
         let wasmtime_module = WasmtimeModule {
             module: module,
             instance: instance,
+            component: None,
+            component_signatures: HashMap::new(),
             id: config.id,
             name: config.name,
             path: config.path,
-            functions: functions
+            functions: functions,
+            signatures: HashMap::new(),
+            cache_lease: None,
+            instantiation_rss_delta: None,
         };
         Ok(wasmtime_module)
     }
@@ -459,8 +926,17 @@ impl WasmtimeModule {
     }
 
 
+    /// Whether the module is a component of the component model.
+    pub fn is_component(&self) -> bool {
+        self.component.is_some()
+    }
+
+
     /// Gets a list of all known exported functions from the module
     pub fn get_all_exports(&self) -> Vec<String> {
+        if self.is_component() {
+            return self.component_signatures.keys().cloned().collect();
+        }
         if let Some(module_reference) = &self.module {
             let mut funcs: Vec<String> = vec![];
             let imports = module_reference.exports();
@@ -476,6 +952,221 @@ impl WasmtimeModule {
     }
 }
 
+/// Parameter and result types of an exported function.
+///
+/// Computed once when the module is loaded, instead of looking the function up from the
+/// instance for every call. A redeployment creates new runtimes, so they are never stale.
+#[derive(Debug, Clone)]
+pub struct FunctionSignature {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// Gets the signatures of all functions a module exports.
+pub fn function_signatures(module: &Module) -> HashMap<String, FunctionSignature> {
+    module.exports()
+        .filter_map(|export| {
+            let func_ty = export.ty().func()?.clone();
+            Some((export.name().to_string(), FunctionSignature {
+                params: func_ty.params().collect(),
+                results: func_ty.results().collect(),
+            }))
+        })
+        .collect()
+}
+
+/// Binary formats modules can be deployed in, advertised in the device description.
+#[cfg(not(feature="armv6"))]
+pub const MODULE_FORMATS: &[&str] = &["core", "component"];
+#[cfg(feature="armv6")]
+pub const MODULE_FORMATS: &[&str] = &["core"];
+
+/// A component of the component model loaded into a runtime.
+#[derive(Clone)]
+pub struct LoadedComponent {
+    pub component: Component,
+    pub instance: component::Instance,
+}
+
+impl fmt::Debug for LoadedComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadedComponent")
+            .field("component", &"<Component>")
+            .field("instance", &self.instance)
+            .finish()
+    }
+}
+
+/// Parameters and results of a function a component exports, in the types of the component model.
+#[derive(Debug, Clone)]
+pub struct ComponentSignature {
+    /// Names and types of the parameters, in order
+    pub params: Vec<(String, ComponentType)>,
+    pub results: Vec<ComponentType>,
+}
+
+impl ComponentSignature {
+    /// Pairs the arguments of a call with the parameters they are passed as: by the names of the
+    /// parameters when the arguments have all of them, and otherwise in order.
+    ///
+    /// # Returns
+    /// The name of each argument with its value and the type of its parameter, for as many
+    /// parameters as there are arguments.
+    pub fn bind<'a>(&'a self, args: &'a IndexMap<String, Value>) -> Vec<(&'a str, &'a Value, &'a ComponentType)> {
+        if self.params.iter().all(|(name, _)| args.contains_key(name)) {
+            return self.params.iter()
+                .map(|(name, typ)| (name.as_str(), &args[name], typ))
+                .collect();
+        }
+        args.iter()
+            .zip(self.params.iter())
+            .map(|((name, value), (_, typ))| (name.as_str(), value, typ))
+            .collect()
+    }
+}
+
+/// Gets the signatures of the functions a component exports at its top level.
+pub fn component_signatures(engine: &Engine, component: &Component) -> HashMap<String, ComponentSignature> {
+    component.component_type()
+        .exports(engine)
+        .filter_map(|(name, item)| match item {
+            ComponentItem::ComponentFunc(func) => Some((name.to_string(), ComponentSignature {
+                params: func.params().map(|(name, typ)| (name.to_string(), typ)).collect(),
+                results: func.results().collect(),
+            })),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Name of a type of the component model as in WIT, e.g. `s32` or `string`.
+pub fn component_type_name(typ: &ComponentType) -> String {
+    let name = match typ {
+        ComponentType::Bool => "bool",
+        ComponentType::S8 => "s8",
+        ComponentType::U8 => "u8",
+        ComponentType::S16 => "s16",
+        ComponentType::U16 => "u16",
+        ComponentType::S32 => "s32",
+        ComponentType::U32 => "u32",
+        ComponentType::S64 => "s64",
+        ComponentType::U64 => "u64",
+        ComponentType::Float32 => "f32",
+        ComponentType::Float64 => "f64",
+        ComponentType::Char => "char",
+        ComponentType::String => "string",
+        ComponentType::List(_) => "list",
+        ComponentType::Record(_) => "record",
+        ComponentType::Tuple(_) => "tuple",
+        ComponentType::Variant(_) => "variant",
+        ComponentType::Enum(_) => "enum",
+        ComponentType::Option(_) => "option",
+        ComponentType::Result(_) => "result",
+        ComponentType::Flags(_) => "flags",
+        ComponentType::Own(_) => "own",
+        ComponentType::Borrow(_) => "borrow",
+        ComponentType::Future(_) => "future",
+        ComponentType::Stream(_) => "stream",
+        ComponentType::ErrorContext => "error-context",
+    };
+    name.to_string()
+}
+
+/// Tells whether the binary at `path` is a component of the component model rather than a core
+/// module. Both start with `\0asm`, followed by a version and a layer that is 1 for components
+/// and 0 for core modules. In the text format, components start with `(component`.
+pub fn is_component(path: &Path) -> bool {
+    let mut header = [0u8; 64];
+    let Ok(read) = fs::File::open(path).and_then(|mut file| file.read(&mut header)) else {
+        return false;
+    };
+    let header = &header[..read];
+    match header.strip_prefix(b"\0asm") {
+        Some(rest) => rest.get(2..4) == Some(&[1, 0][..]),
+        None => String::from_utf8_lossy(header).trim_start().starts_with("(component"),
+    }
+}
+
+/// Parameter and result types of a function on either side of an import, as WebAssembly text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportSignature {
+    pub params: Vec<String>,
+    pub results: Vec<String>,
+}
+
+impl From<&FuncType> for ImportSignature {
+    fn from(ty: &FuncType) -> Self {
+        ImportSignature {
+            params: ty.params().map(|ty| ty.to_string()).collect(),
+            results: ty.results().map(|ty| ty.to_string()).collect(),
+        }
+    }
+}
+
+/// How an import of a module is satisfied in its runtime.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    /// Module namespace of the import, e.g. `wasi_snapshot_preview1` or `camera`
+    pub module: String,
+    pub name: String,
+    /// `function`, `global`, `table`, `memory` or `tag`
+    pub kind: &'static str,
+    /// `wasi`, `wasi-nn` or `host` for the host functions of the supervisor, or `unsatisfied`
+    pub provider: &'static str,
+    /// Capability of the device the import uses, if any
+    pub capability: Option<&'static str>,
+    /// Whether the capability may be used on this device
+    pub granted: bool,
+    pub satisfied: bool,
+    /// Signature the module declares for an imported function
+    pub expected: Option<ImportSignature>,
+    /// Signature of the function linked for the import
+    pub provided: Option<ImportSignature>,
+    pub signature_mismatch: bool,
+    /// Why the import fails or is going to fail when called
+    pub problem: Option<String>,
+}
+
+/// Where the imports of a module namespace come from, and the capability of the device they use.
+fn import_provider(namespace: &str) -> (&'static str, Option<&'static str>) {
+    match namespace {
+        "wasi_snapshot_preview1" | "wasi_unstable" => ("wasi", Some("wasi")),
+        "wasi_ephemeral_nn" => ("wasi-nn", Some("wasi-nn")),
+        "camera" => ("host", Some("camera")),
+        "network" => ("host", Some("network")),
+        _ => ("host", None),
+    }
+}
+
+/// Whether a capability may be used by modules on this device.
+fn capability_granted(capability: &str) -> bool {
+    match capability {
+        "camera" => camera_enabled(),
+        _ => true,
+    }
+}
+
+/// Name of the kind of an import or export.
+fn extern_type_kind(ty: &ExternType) -> &'static str {
+    match ty {
+        ExternType::Func(_) => "function",
+        ExternType::Global(_) => "global",
+        ExternType::Table(_) => "table",
+        ExternType::Memory(_) => "memory",
+        ExternType::Tag(_) => "tag",
+    }
+}
+
+/// Range of `len` bytes from `offset` in a memory of `memory_size` bytes, or an error if it
+/// does not fit in the memory.
+fn memory_range(memory_size: usize, offset: usize, len: usize) -> Result<std::ops::Range<usize>, String> {
+    offset.checked_add(len)
+        .filter(|&end| end <= memory_size)
+        .map(|end| offset..end)
+        .ok_or_else(|| format!("{} bytes at offset {} are out of bounds of memory of {} bytes", len, offset, memory_size))
+}
+
 // ----------------------- Miscellaneous module related things ----------------------- //
 
 /// Struct for containing module name, file location and associated files referred to as 'mounts'.
@@ -487,7 +1178,19 @@ pub struct ModuleConfig {
     pub path: PathBuf,
     pub data_files: HashMap<String, String>,
     pub ml_model: Option<MLModel>,
-    pub data_ptr_function_name: String
+    pub data_ptr_function_name: String,
+    /// Where the binary was downloaded from and its verified digest
+    #[serde(default)]
+    pub binary_source: Option<ArtifactSource>,
+    /// Where each data file was downloaded from and its verified digest, by filename
+    #[serde(default)]
+    pub data_file_sources: HashMap<String, ArtifactSource>,
+    /// Access the module has to the files of each mount stage
+    #[serde(default)]
+    pub permissions: MountPermissions,
+    /// Where the files of each mount stage are in the filesystem of the module
+    #[serde(default)]
+    pub mount_layout: MountLayout,
 }
 
 
@@ -499,7 +1202,39 @@ impl ModuleConfig {
             path,
             data_files,
             ml_model,
-            data_ptr_function_name: "get_image_ptr".to_string()
+            data_ptr_function_name: "get_image_ptr".to_string(),
+            binary_source: None,
+            data_file_sources: HashMap::new(),
+            permissions: MountPermissions::default(),
+            mount_layout: MountLayout::default(),
+        }
+    }
+
+    /// The directories preopened for the module, whose files are in `params_dir` on the host,
+    /// as laid out by its `mount_layout`.
+    pub fn preopens(&self, params_dir: &Path) -> Vec<Preopen> {
+        match self.mount_layout {
+            MountLayout::Shared => {
+                let (dir_perms, file_perms) = self.permissions.wasi_perms();
+                vec![Preopen { host_path: params_dir.to_path_buf(), guest_path: ".", dir_perms, file_perms }]
+            }
+            MountLayout::Isolated => {
+                let (dir_perms, file_perms) = self.permissions.work_perms();
+                vec![
+                    Preopen {
+                        host_path: params_dir.to_path_buf(),
+                        guest_path: DATA_GUEST_PATH,
+                        dir_perms: DirPerms::READ,
+                        file_perms: FilePerms::READ,
+                    },
+                    Preopen {
+                        host_path: params_dir.join(WORK_FOLDER_NAME),
+                        guest_path: WORK_GUEST_PATH,
+                        dir_perms,
+                        file_perms,
+                    },
+                ]
+            }
         }
     }
 
@@ -514,6 +1249,128 @@ impl ModuleConfig {
     }
 }
 
+/// Path that the files of the deployment stage are mounted at in the isolated layout.
+pub const DATA_GUEST_PATH: &str = "/data";
+
+/// Path that the files of the execution and output stages are mounted at in the isolated layout.
+pub const WORK_GUEST_PATH: &str = "/work";
+
+/// How the files of a module are laid out in its filesystem, chosen with `mountLayout` of the
+/// module in the deployment manifest.
+///
+/// In the `isolated` layout, files given at deployment time are read-only under `/data`, and the
+/// inputs and outputs of requests are under `/work`, so that an input cannot replace a file of
+/// the deployment that has the same name. In the `shared` layout all of them are in the current
+/// directory of the module, as before the layouts existed, for modules that hardcode their paths.
+///
+/// Manifests get the `isolated` layout unless they ask for `shared`, but deployments saved
+/// without a layout keep the `shared` one they were deployed with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MountLayout {
+    #[serde(rename = "isolated")]
+    Isolated,
+    #[default]
+    #[serde(rename = "shared")]
+    Shared,
+}
+
+impl MountLayout {
+    /// Folder inside the params folder of a module that the files of a mount stage are in.
+    pub fn stage_folder(&self, stage: MountStage) -> Option<&'static str> {
+        match (self, stage) {
+            (MountLayout::Isolated, MountStage::EXECUTION | MountStage::OUTPUT) => Some(WORK_FOLDER_NAME),
+            _ => None,
+        }
+    }
+
+    /// Path the module finds a file of a mount stage at.
+    pub fn guest_path(&self, stage: MountStage, filename: &str) -> String {
+        match (self, stage) {
+            (MountLayout::Shared, _) => filename.to_string(),
+            (MountLayout::Isolated, MountStage::DEPLOYMENT) => format!("{}/{}", DATA_GUEST_PATH, filename),
+            (MountLayout::Isolated, _) => format!("{}/{}", WORK_GUEST_PATH, filename),
+        }
+    }
+}
+
+/// A host directory preopened for a module, and the path the module sees it at.
+#[derive(Clone, Debug)]
+pub struct Preopen {
+    pub host_path: PathBuf,
+    pub guest_path: &'static str,
+    pub dir_perms: DirPerms,
+    pub file_perms: FilePerms,
+}
+
+/// Access a module has to the files of a mount stage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MountPermission {
+    #[serde(rename = "read")]
+    Read,
+    #[default]
+    #[serde(rename = "readWrite")]
+    ReadWrite,
+}
+
+/// Access a module has to its files, declared per mount stage in the `permissions` field of
+/// the module in the deployment manifest, e.g.
+/// `{ "deployment": "read", "execution": "read", "output": "readWrite" }`.
+///
+/// Stages that are not declared default to `readWrite`, so that deployments not declaring
+/// any permissions keep full access to their params directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MountPermissions {
+    /// Files given to the module at deployment time, such as ML models
+    pub deployment: MountPermission,
+    /// Input files of a request
+    pub execution: MountPermission,
+    /// Files written by the module
+    pub output: MountPermission,
+}
+
+impl MountPermissions {
+    /// Maps the permissions to those of the preopened params directory of the module in the
+    /// shared layout.
+    ///
+    /// The files of all stages share the same directory, so the directory is only mutable
+    /// when outputs may be written, and files are only writable when some stage allows it.
+    /// Within a writable directory, the files of read-only stages are protected from being
+    /// written on the host instead, see `protect_read_only`, but they can still be removed.
+    pub fn wasi_perms(&self) -> (DirPerms, FilePerms) {
+        let dir_perms = match self.output {
+            MountPermission::ReadWrite => DirPerms::all(),
+            MountPermission::Read => DirPerms::READ,
+        };
+        let any_writable = [self.deployment, self.execution, self.output]
+            .contains(&MountPermission::ReadWrite);
+        let file_perms = if any_writable { FilePerms::all() } else { FilePerms::READ };
+        (dir_perms, file_perms)
+    }
+
+    /// Maps the permissions to those of the `/work` directory of the isolated layout, which
+    /// only has the files of the execution and output stages.
+    pub fn work_perms(&self) -> (DirPerms, FilePerms) {
+        let dir_perms = match self.output {
+            MountPermission::ReadWrite => DirPerms::all(),
+            MountPermission::Read => DirPerms::READ,
+        };
+        let any_writable = [self.execution, self.output].contains(&MountPermission::ReadWrite);
+        let file_perms = if any_writable { FilePerms::all() } else { FilePerms::READ };
+        (dir_perms, file_perms)
+    }
+}
+
+/// Marks a file of a read-only mount stage as read-only on the host, so that the module gets
+/// `EACCES` when it tries to open the file for writing.
+///
+/// This does not apply to a supervisor running as root, which ignores file modes.
+pub fn protect_read_only(path: &std::path::Path) -> std::io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(path, permissions)
+}
+
 /// Struct for ML models
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MLModel {
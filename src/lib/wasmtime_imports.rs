@@ -7,7 +7,6 @@ use nokhwa::utils::{CameraIndex, RequestedFormat, RequestedFormatType};
 use nokhwa::pixel_format::RgbFormat;
 use image::codecs::jpeg::JpegEncoder;
 use image::ColorType;
-use std::env;
 use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr};
 use std::time::Duration;
@@ -15,6 +14,8 @@ use surge_ping::{Client, Config, PingIdentifier, PingSequence};
 
 #[cfg(not(feature = "armv6"))]
 use crate::lib::wasmtime::Ctx;
+use crate::lib::settings::get_setting;
+use crate::lib::camera::{camera_backend, camera_enabled, CAMERA_UNAVAILABLE};
 
 /// Host function import: captures a JPEG image with a statically defined size in memory.
 ///
@@ -28,6 +29,9 @@ use crate::lib::wasmtime::Ctx;
 /// * `args[0]`: pointer to buffer location where image should be written (u32)
 /// * `args[1]`: pointer to 4-byte location containing the desired size (u32)
 ///
+/// When the camera is disabled (see `camera.rs`), `CAMERA_UNAVAILABLE` is written to the size
+/// location instead of capturing.
+///
 /// # Returns
 /// * `Ok(())` if successful, or error if arguments or memory access fails
 #[cfg(not(feature="armv6"))]
@@ -47,6 +51,10 @@ pub fn takeImageStaticSize(
     };
 
     let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+    if !camera_enabled() {
+        memory.write(&mut caller, size_ptr as usize, &CAMERA_UNAVAILABLE.to_le_bytes())?;
+        return Ok(());
+    }
     let mut size_bytes = [0u8; 4];
     memory.read(&mut caller, size_ptr as usize, &mut size_bytes)?;
     let expected_size = u32::from_le_bytes(size_bytes) as usize;
@@ -75,6 +83,10 @@ pub fn takeImageStaticSize(
     };
 
     let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+    if !camera_enabled() {
+        memory.write(&mut caller, size_ptr as usize, &CAMERA_UNAVAILABLE.to_le_bytes())?;
+        return Ok(());
+    }
     let mut size_bytes = [0u8; 4];
     memory.read(&mut caller, size_ptr as usize, &mut size_bytes)?;
     let expected_size = u32::from_le_bytes(size_bytes) as usize;
@@ -97,6 +109,9 @@ pub fn takeImageStaticSize(
 /// * `args[0]`: memory location to write buffer pointer (u32)
 /// * `args[1]`: memory location to write buffer length (u32)
 ///
+/// When the camera is disabled (see `camera.rs`), the pointer is set to `0` and the length to
+/// `CAMERA_UNAVAILABLE` instead of capturing.
+///
 /// # Returns
 /// * `Ok(())` if successful, or an error if arguments are missing or memory access fails
 ///
@@ -122,10 +137,15 @@ pub fn takeImageDynamicSize(
         _ => return Err(anyhow::anyhow!("Expected second argument to be i32").into()),
     };
 
+    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+    if !camera_enabled() {
+        memory.write(&mut caller, out_ptr_ptr as usize, &0u32.to_le_bytes())?;
+        memory.write(&mut caller, out_size_ptr as usize, &CAMERA_UNAVAILABLE.to_le_bytes())?;
+        return Ok(());
+    }
     let image_data = capture_image().map_err(|e| anyhow::anyhow!(e))?;
     let data_len = image_data.len();
 
-    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
     let offset = 0;
     memory.write(&mut caller, offset, &image_data)?;
     memory.write(&mut caller, out_ptr_ptr as usize, &(offset as u32).to_le_bytes())?;
@@ -150,10 +170,15 @@ pub fn takeImageDynamicSize(
         _ => return Err(anyhow::anyhow!("Expected second argument to be i32").into()),
     };
 
+    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+    if !camera_enabled() {
+        memory.write(&mut caller, out_ptr_ptr as usize, &0u32.to_le_bytes())?;
+        memory.write(&mut caller, out_size_ptr as usize, &CAMERA_UNAVAILABLE.to_le_bytes())?;
+        return Ok(());
+    }
     let image_data = capture_image().map_err(|e| anyhow::anyhow!(e))?;
     let data_len = image_data.len();
 
-    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
     let offset = 0;
     memory.write(&mut caller, offset, &image_data)?;
     memory.write(&mut caller, out_ptr_ptr as usize, &(offset as u32).to_le_bytes())?;
@@ -174,13 +199,12 @@ pub fn takeImageDynamicSize(
 /// - Capture failure
 /// - Frame is empty
 pub fn capture_image() -> Result<Vec<u8>, String> {
-    let device = env::var("DEFAULT_CAMERA_DEVICE")
-        .ok()
+    let device = get_setting("DEFAULT_CAMERA_DEVICE")
         .and_then(|val| val.parse::<u32>().ok())
         .unwrap_or(0);
     let cam_index = CameraIndex::Index(device);
     let requested = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
-    let mut camera = Camera::new(cam_index, requested)
+    let mut camera = Camera::with_backend(cam_index, requested, camera_backend())
         .map_err(|e| format!("Failed to initialize camera: {}", e))?;
 
     camera.open_stream().map_err(|e| format!("Failed to open stream: {}", e))?;
@@ -204,6 +228,9 @@ pub fn takeImage(
     _args: &[Val],
     _results: &mut [Val],
 ) -> Result<()> {
+    if !camera_enabled() {
+        return Err(anyhow::anyhow!("Camera capability unavailable ({})", CAMERA_UNAVAILABLE));
+    }
     unimplemented!();
 }
 
@@ -214,6 +241,9 @@ pub fn takeImage(
     _args: &[Val],
     _results: &mut [Val],
 ) -> Result<()> {
+    if !camera_enabled() {
+        return Err(anyhow::anyhow!("Camera capability unavailable ({})", CAMERA_UNAVAILABLE));
+    }
     unimplemented!();
 }
 
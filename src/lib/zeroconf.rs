@@ -7,22 +7,31 @@
 //! - Building and managing a service identity (`WebthingZeroconf`)
 //! - Registering that service with a remote orchestrator, if configured
 //! - Advertising the service with mDNS
+//! - Restarting the advertisement from a watchdog when its thread dies or stops renewing it
 //!
 //! This allows services to self-register into the orchestrator.
 
 
 use parking_lot::Mutex;
 use serde::Serialize;
+use serde_json::{json, Value};
 use tokio::runtime::Runtime;
-use std::env;
-use std::net::TcpStream;
+use chrono::{DateTime, Utc};
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::sync::Arc;
-use std::thread;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 use reqwest::Client;
 use log::{error, debug, info};
 use local_ip_address;
 use actix_web::rt::System;
+use crate::function_name;
+use crate::lib::logging::send_log;
+use crate::lib::settings::get_setting;
+use crate::lib::camera::camera_enabled;
+use crate::lib::startup::send_startup_report;
+use crate::lib::orchestrator_compat::{api_version_of, negotiate, registration_payload, ApiVersion, API_VERSION_HEADER};
 use crate::lib::constants::{
     DEFAULT_URL_SCHEME,
     SUPERVISOR_DEFAULT_NAME,
@@ -33,6 +42,44 @@ use crate::lib::constants::{
 use zeroconf::prelude::*;
 use zeroconf::{MdnsService, ServiceType, TxtRecord};
 
+/// How often the watchdog checks the mDNS advertisement.
+const REGISTRATION_WATCHDOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The thread advertising the service over mDNS, and the restarts of it by the watchdog.
+struct Advertiser {
+    /// Thread advertising the service, once it has been started
+    handle: Option<JoinHandle<()>>,
+    /// Whether the thread has finished to hand the advertisement over to a renewed one
+    renewing: bool,
+    /// Bumped on every restart, so that advertisements of earlier threads stop
+    generation: u64,
+    restarts: u64,
+    last_restart_at: Option<DateTime<Utc>>,
+    last_restart_reason: Option<String>,
+}
+
+static ADVERTISER: Mutex<Advertiser> = Mutex::new(Advertiser {
+    handle: None,
+    renewing: false,
+    generation: 0,
+    restarts: 0,
+    last_restart_at: None,
+    last_restart_reason: None,
+});
+
+/// When the advertising loop last ran, as a Unix timestamp in seconds.
+static ADVERTISER_HEARTBEAT: AtomicI64 = AtomicI64::new(0);
+
+/// How an advertisement of the service ended.
+enum AdvertisementEnd {
+    /// The registration is to be renewed
+    Renew,
+    /// The service was renamed and is to be advertised with the new name
+    Renamed,
+    /// The watchdog started another advertisement in its place
+    Superseded,
+}
+
 
 /// Represents a service that is advertised on the network.
 ///
@@ -50,6 +97,8 @@ pub struct WebthingZeroconf {
     pub properties: Vec<(String, String)>,
     pub register_renewal_time: i64,
     pub last_register_time: i64,
+    /// Name the orchestrator knows the supervisor by, until it is told of a new name
+    pub previous_name: Option<String>,
 }
 
 impl WebthingZeroconf {
@@ -57,11 +106,11 @@ impl WebthingZeroconf {
     ///
     /// Populates host and port using `get_listening_address()`, reads environment variables
     /// like `PREFERRED_URL_SCHEME` and `SUPERVISOR_NAME`, and sets standard `_webthing._tcp`
-    /// service type.
+    /// service type. The TXT records include `camera=1` when the camera is enabled.
     pub fn new() -> Self {
         let (host, port) = get_listening_address();
-        let preferred_url_scheme = env::var("PREFERRED_URL_SCHEME")
-            .unwrap_or_else(|_| DEFAULT_URL_SCHEME.to_string());
+        let preferred_url_scheme = get_setting("PREFERRED_URL_SCHEME")
+            .unwrap_or_else(|| DEFAULT_URL_SCHEME.to_string());
         let tls_flag = if preferred_url_scheme.to_lowercase() == "https" {
             "1"
         } else {
@@ -71,17 +120,20 @@ impl WebthingZeroconf {
         // service name = supervisor._webthing._tcp.local.
         let service_type = "webthing".to_string();
         let service_protocol = "tcp".to_string();
-        let service_name = env::var("SUPERVISOR_NAME")
-            .unwrap_or_else(|_| SUPERVISOR_DEFAULT_NAME.to_string());
+        let service_name = get_setting("SUPERVISOR_NAME")
+            .unwrap_or_else(|| SUPERVISOR_DEFAULT_NAME.to_string());
 
-        let properties = vec![
+        let mut properties = vec![
             ("path".to_string(), "/".to_string()),
             ("tls".to_string(), tls_flag.to_string()),
             ("address".to_string(), host.clone()),
         ];
-        let register_renewal_time = match env::var("WASMIOT_REGISTER_RENEWAL_TIME") {
-            Ok(val) => val.parse().unwrap_or(DEFAULT_SERVICE_RENEWAL_TIME),
-            Err(_) => DEFAULT_SERVICE_RENEWAL_TIME,
+        if camera_enabled() {
+            properties.push(("camera".to_string(), "1".to_string()));
+        }
+        let register_renewal_time = match get_setting("WASMIOT_REGISTER_RENEWAL_TIME") {
+            Some(val) => val.parse().unwrap_or(DEFAULT_SERVICE_RENEWAL_TIME),
+            None => DEFAULT_SERVICE_RENEWAL_TIME,
         };
         WebthingZeroconf {
             service_name,
@@ -92,6 +144,7 @@ impl WebthingZeroconf {
             properties,
             register_renewal_time,
             last_register_time: chrono::Utc::now().timestamp(),
+            previous_name: None,
         }
     }
 }
@@ -107,6 +160,9 @@ pub struct ZeroconfRegistrationData<'a> {
     properties: serde_json::Value,
     addresses: Vec<String>,
     host: String,
+    /// Name the supervisor was registered with before, so the orchestrator can merge the records
+    #[serde(rename = "previousName", skip_serializing_if = "Option::is_none")]
+    previous_name: Option<String>,
 }
 
 /// Force registration of the supervisor to orchestrator.
@@ -116,10 +172,8 @@ pub struct ZeroconfRegistrationData<'a> {
 ///   - WASMIOT_ORCHESTRATOR_URL
 pub fn force_supervisor_registration(zc: Arc<Mutex<WebthingZeroconf>>) {
     thread::spawn(move || {
-        if let Ok(mut orchestrator_url) = env::var("WASMIOT_ORCHESTRATOR_URL") {
-            let zc_lock = zc.lock();
-            let addr = format!("{}:{}", zc_lock.host, zc_lock.port);
-            drop(zc_lock);
+        if let Some(mut orchestrator_url) = get_setting("WASMIOT_ORCHESTRATOR_URL") {
+            let addr = readiness_address(&zc.lock());
 
             loop {
                 match TcpStream::connect(&addr) {
@@ -136,7 +190,9 @@ pub fn force_supervisor_registration(zc: Arc<Mutex<WebthingZeroconf>>) {
 
             orchestrator_url.push_str(URL_BASE_PATH);
             let result = System::new().block_on(async {
-                register_services_to_orchestrator(zc, &orchestrator_url).await
+                register_services_to_orchestrator(zc, &orchestrator_url).await?;
+                send_startup_report().await;
+                Ok::<(), anyhow::Error>(())
             });
 
             if let Err(e) = result {
@@ -154,6 +210,9 @@ pub fn force_supervisor_registration(zc: Arc<Mutex<WebthingZeroconf>>) {
 ///
 /// Converts the `WebthingZeroconf` instance into the proper payload and sends it
 /// to the configured `orchestrator_url`. Logs and returns errors if any occur.
+///
+/// The payload is shaped for the API version agreed on with the orchestrator, and the
+/// version the orchestrator answers with is recorded (see `orchestrator_compat.rs`).
 pub async fn register_services_to_orchestrator(
     zc: Arc<Mutex<WebthingZeroconf>>,
     orchestrator_url: &str,
@@ -171,16 +230,20 @@ pub async fn register_services_to_orchestrator(
         properties: serde_json::Value::Object(props_map),
         addresses: vec![zc_lock.host.clone()],
         host: zc_lock.host.clone(),
+        previous_name: zc_lock.previous_name.clone(),
     };
     drop(zc_lock);
 
-    info!("Sending registration to: {}", orchestrator_url);
-    info!("Payload: {:?}", data);
+    let version = api_version_of(orchestrator_url);
+    let payload = registration_payload(serde_json::to_value(&data)?, version);
+    info!("Sending registration to: {} (API version {})", orchestrator_url, version.header_value());
+    info!("Payload: {}", payload);
 
     let client = Client::new();
     let req = client
     .post(orchestrator_url)
-    .json(&data)
+    .header(API_VERSION_HEADER, ApiVersion::LATEST.header_value())
+    .json(&payload)
     .timeout(Duration::from_secs(10));
 
     let resp = match req.send().await {
@@ -191,6 +254,9 @@ pub async fn register_services_to_orchestrator(
         }
     };
 
+    let answered_version = resp.headers().get(API_VERSION_HEADER).and_then(|value| value.to_str().ok());
+    negotiate(orchestrator_url, answered_version);
+
     if !resp.status().is_success() {
         let text = resp.text().await?;
         error!("Failed to register service: {}", text);
@@ -198,9 +264,35 @@ pub async fn register_services_to_orchestrator(
     }
 
     debug!("Service registered to orchestrator: {:?}", data);
+    // The orchestrator knows the current name now
+    let mut zc_lock = zc.lock();
+    if zc_lock.previous_name.as_deref() == data.previous_name.as_deref() {
+        zc_lock.previous_name = None;
+    }
     Ok(())
 }
 
+/// Changes the name the supervisor is advertised with.
+///
+/// The mDNS advertisement is dropped and created again with the new name, and the supervisor
+/// registers again to the orchestrator, if one is configured, with the old name as
+/// `previousName`.
+pub fn rename_service(zc: Arc<Mutex<WebthingZeroconf>>, name: &str) {
+    {
+        let mut zc_lock = zc.lock();
+        if zc_lock.service_name == name {
+            return;
+        }
+        info!("Renaming the service from '{}' to '{}'", zc_lock.service_name, name);
+        // Keep the name the orchestrator has, if it has not been told of an earlier rename
+        if zc_lock.previous_name.is_none() {
+            zc_lock.previous_name = Some(zc_lock.service_name.clone());
+        }
+        zc_lock.service_name = name.to_string();
+    }
+    force_supervisor_registration(zc);
+}
+
 /// Waits for supervisor to be up, the starts listening to mdns requests
 ///
 /// Spawns a background thread that:
@@ -209,9 +301,7 @@ pub async fn register_services_to_orchestrator(
 pub fn wait_until_ready_and_register(zc: Arc<Mutex<WebthingZeroconf>>) {
     thread::spawn(move || {
         {
-            let zc_lock = zc.lock();
-            let addr = format!("{}:{}", zc_lock.host, zc_lock.port);
-            drop(zc_lock);
+            let addr = readiness_address(&zc.lock());
 
             loop {
                 match TcpStream::connect(&addr) {
@@ -221,6 +311,8 @@ pub fn wait_until_ready_and_register(zc: Arc<Mutex<WebthingZeroconf>>) {
                     }
                     Err(err) => {
                         debug!("Waiting for server at {}: {:?}", addr, err);
+                        // Waiting is not a failure of the advertisement
+                        ADVERTISER_HEARTBEAT.store(Utc::now().timestamp(), Ordering::SeqCst);
                         thread::sleep(Duration::from_secs(1));
                     }
                 }
@@ -237,64 +329,78 @@ pub fn wait_until_ready_and_register(zc: Arc<Mutex<WebthingZeroconf>>) {
     });
 }
 
-/// Determines the IP address and port this supervisor instance should bind to.
-/// Defaults to 127.0.0.1 and port 8080
+/// Determines the IP address and port this supervisor instance is reached at, which are
+/// advertised over mDNS and used in the URLs it gives out.
+/// Defaults to the address of the default network interface and port 8080
 ///
 /// Reads:
+/// - `WASMIOT_ADVERTISE_ADDRESS` (the address to advertise, if set)
+/// - `WASMIOT_BIND_ADDRESS` (advertised when it is a specific address and no advertise address is set)
 /// - `WASMIOT_SUPERVISOR_PORT` (falls back to default 8080)
 pub fn get_listening_address() -> (String, u16) {
-    let host = local_ip_address::local_ip()
+    let advertised = get_setting("WASMIOT_ADVERTISE_ADDRESS")
+        .and_then(|address| address.parse::<IpAddr>().ok())
+        .or_else(|| bind_address().filter(|address| !address.is_unspecified()));
+    let host = match advertised {
+        Some(address) => address.to_string(),
+        None => local_ip_address::local_ip()
             .map(|ip| ip.to_string())
-            .unwrap_or_else(|_| "127.0.0.1".to_string());
+            .unwrap_or_else(|_| "127.0.0.1".to_string()),
+    };
 
-    let port_str = env::var("WASMIOT_SUPERVISOR_PORT")
-        .unwrap_or_else(|_| DEFAULT_PORT.to_string());
+    let port_str = get_setting("WASMIOT_SUPERVISOR_PORT")
+        .unwrap_or_else(|| DEFAULT_PORT.to_string());
 
     let port: u16 = port_str.parse().unwrap_or(DEFAULT_PORT);
     (host, port)
 }
 
+/// The address the HTTP server listens on, from `WASMIOT_BIND_ADDRESS`.
+fn bind_address() -> Option<IpAddr> {
+    get_setting("WASMIOT_BIND_ADDRESS").and_then(|address| address.parse().ok())
+}
+
+/// The address to connect to when checking that the HTTP server is up: the bind address when
+/// the server listens on a specific one, else the advertised address.
+fn readiness_address(zc: &WebthingZeroconf) -> String {
+    match bind_address().filter(|address| !address.is_unspecified()) {
+        Some(address) => SocketAddr::new(address, zc.port).to_string(),
+        None => format!("{}:{}", zc.host, zc.port),
+    }
+}
+
 /// Spawn a separate thread that continuously listens for mdns requests, and
 /// responds with supervisor data when requested.
+///
+/// The thread is watched by `run_registration_watchdog`.
 pub fn register_service(zc: Arc<Mutex<WebthingZeroconf>>) -> anyhow::Result<()> {
-    let zc_clone = zc.clone();
-
-    std::thread::spawn(move || {
-        let zc_clone = zc_clone.clone();
-        let zc_lock = zc_clone.lock();
-        let service_type = ServiceType::new(zc_lock.service_type.as_str(), zc_lock.service_protocol.as_str()).unwrap();
-        let mut service = MdnsService::new(service_type, zc_lock.port);
-        let mut txt_record = TxtRecord::new();
-        zc_lock.properties
-            .iter()
-            .for_each(|(key, value)| {
-                txt_record.insert(key, value).unwrap();
-            });
-        service.set_name(&zc_lock.service_name);
-        drop(zc_lock);
-        service.set_txt_record(txt_record);
-
-        service.set_registered_callback(Box::new(|r, _| {
-            if let Ok(svc) = r {
-                info!("✅ Responded to mDNS query with: {:?}", svc);
+    let mut advertiser = ADVERTISER.lock();
+    let generation = advertiser.generation;
+    advertiser.renewing = false;
+    ADVERTISER_HEARTBEAT.store(Utc::now().timestamp(), Ordering::SeqCst);
+    advertiser.handle = Some(std::thread::spawn(move || {
+        match advertise_service(&zc, generation) {
+            AdvertisementEnd::Renew => {}
+            AdvertisementEnd::Renamed => {
+                info!("Service renamed, advertising it again");
+                if let Err(e) = register_service(zc) {
+                    error!("Failed to advertise the renamed service: {}", e);
+                }
+                return;
             }
-        }));
-
-        let event_loop = service.register().unwrap();
-        loop {
-            event_loop.poll(Duration::from_secs(1)).unwrap();
-
-            let zc_lock = zc_clone.lock();
-            let time_since_last_register = chrono::Utc::now().timestamp() - zc_lock.last_register_time;
-            let time_check = time_since_last_register > zc_lock.register_renewal_time;
-            drop(zc_lock);
-
-            if time_check {
-                info!("Health check timeout exceeded, re-registering service");
-                break;
+            AdvertisementEnd::Superseded => {
+                info!("Advertisement was restarted by the watchdog, stopping this one");
+                return;
             }
         }
 
+        {
+            let mut advertiser = ADVERTISER.lock();
+            if advertiser.generation != generation {
+                return;
+            }
+            advertiser.renewing = true;
+        }
         match Runtime::new() {
             Ok(rt) => rt.block_on(async move {
                 update_service_registration(zc.clone()).await;
@@ -303,10 +409,58 @@ pub fn register_service(zc: Arc<Mutex<WebthingZeroconf>>) -> anyhow::Result<()>
                 error!("Failed to create Tokio runtime: {}", e);
             }
         }
-    });
+    }));
     Ok(())
 }
 
+/// Advertises the service over mDNS until the registration is to be renewed, the service is
+/// renamed, or the watchdog restarts the advertisement after `generation`. The advertisement
+/// is withdrawn when this returns.
+fn advertise_service(zc: &Arc<Mutex<WebthingZeroconf>>, generation: u64) -> AdvertisementEnd {
+    let zc_lock = zc.lock();
+    let service_type = ServiceType::new(zc_lock.service_type.as_str(), zc_lock.service_protocol.as_str()).unwrap();
+    let mut service = MdnsService::new(service_type, zc_lock.port);
+    let mut txt_record = TxtRecord::new();
+    zc_lock.properties
+        .iter()
+        .for_each(|(key, value)| {
+            txt_record.insert(key, value).unwrap();
+        });
+    let registered_name = zc_lock.service_name.clone();
+    service.set_name(&registered_name);
+    drop(zc_lock);
+    service.set_txt_record(txt_record);
+
+    service.set_registered_callback(Box::new(|r, _| {
+        if let Ok(svc) = r {
+            info!("✅ Responded to mDNS query with: {:?}", svc);
+        }
+    }));
+
+    let event_loop = service.register().unwrap();
+    loop {
+        event_loop.poll(Duration::from_secs(1)).unwrap();
+        ADVERTISER_HEARTBEAT.store(Utc::now().timestamp(), Ordering::SeqCst);
+        if ADVERTISER.lock().generation != generation {
+            return AdvertisementEnd::Superseded;
+        }
+
+        let zc_lock = zc.lock();
+        let time_since_last_register = chrono::Utc::now().timestamp() - zc_lock.last_register_time;
+        let time_check = time_since_last_register > zc_lock.register_renewal_time;
+        let renamed = zc_lock.service_name != registered_name;
+        drop(zc_lock);
+
+        if renamed {
+            return AdvertisementEnd::Renamed;
+        }
+        if time_check {
+            info!("Health check timeout exceeded, re-registering service");
+            return AdvertisementEnd::Renew;
+        }
+    }
+}
+
 pub fn register_health_check(zc: Arc<Mutex<WebthingZeroconf>>) {
     let mut zc_lock = zc.lock();
     zc_lock.last_register_time = chrono::Utc::now().timestamp();
@@ -318,3 +472,78 @@ pub async fn update_service_registration(zc: Arc<Mutex<WebthingZeroconf>>) {
     wait_until_ready_and_register(zc.clone());
     force_supervisor_registration(zc);
 }
+
+/// Checks that the thread advertising the service is alive and has run within twice the
+/// renewal interval.
+///
+/// # Returns
+/// Why the advertisement has to be restarted, if it does. Nothing before it has been started.
+fn advertiser_failure(renewal_time: i64) -> Option<String> {
+    let advertiser = ADVERTISER.lock();
+    let handle = advertiser.handle.as_ref()?;
+    if handle.is_finished() && !advertiser.renewing {
+        return Some("the advertising thread has exited".to_string());
+    }
+    let since_heartbeat = Utc::now().timestamp() - ADVERTISER_HEARTBEAT.load(Ordering::SeqCst);
+    if since_heartbeat > 2 * renewal_time {
+        return Some(format!("the advertisement has not been renewed in {} seconds", since_heartbeat));
+    }
+    None
+}
+
+/// Starts the advertisement of the service and the registration to the orchestrator again,
+/// stopping the advertisement that failed if it is still running.
+fn restart_advertiser(zc: Arc<Mutex<WebthingZeroconf>>, reason: String) {
+    {
+        let mut advertiser = ADVERTISER.lock();
+        advertiser.generation += 1;
+        advertiser.restarts += 1;
+        advertiser.last_restart_at = Some(Utc::now());
+        advertiser.last_restart_reason = Some(reason);
+        advertiser.handle = None;
+        advertiser.renewing = false;
+    }
+    ADVERTISER_HEARTBEAT.store(Utc::now().timestamp(), Ordering::SeqCst);
+    register_health_check(zc.clone());
+    wait_until_ready_and_register(zc.clone());
+    force_supervisor_registration(zc);
+}
+
+/// Watches the mDNS advertisement every `REGISTRATION_WATCHDOG_INTERVAL`, and restarts it
+/// when its thread has died or it has not been renewed within twice the renewal interval.
+pub async fn run_registration_watchdog(zc: Arc<Mutex<WebthingZeroconf>>) {
+    let func_name = function_name!().to_string();
+    loop {
+        tokio::time::sleep(REGISTRATION_WATCHDOG_INTERVAL).await;
+        let renewal_time = zc.lock().register_renewal_time;
+        if let Some(reason) = advertiser_failure(renewal_time) {
+            send_log("ERROR", &format!("mDNS advertisement failed: {}, restarting it", reason), &func_name, None).await;
+            restart_advertiser(zc.clone(), reason);
+        }
+    }
+}
+
+/// The registration of the supervisor, as served at `GET /registration`: the advertised
+/// service, when it was last registered and how often the watchdog has restarted it.
+pub fn registration_status(zc: &Arc<Mutex<WebthingZeroconf>>) -> Value {
+    let zc = zc.lock().clone();
+    let advertiser = ADVERTISER.lock();
+    let advertising = advertiser.handle.as_ref().is_some_and(|handle| !handle.is_finished() || advertiser.renewing);
+    let heartbeat = ADVERTISER_HEARTBEAT.load(Ordering::SeqCst);
+    json!({
+        "serviceName": zc.service_name,
+        "serviceType": format!("_{}._{}", zc.service_type, zc.service_protocol),
+        "host": zc.host,
+        "port": zc.port,
+        "orchestratorUrl": get_setting("WASMIOT_ORCHESTRATOR_URL"),
+        "renewalIntervalSeconds": zc.register_renewal_time,
+        "lastRegisteredAt": DateTime::from_timestamp(zc.last_register_time, 0),
+        "advertising": advertising,
+        "lastHeartbeatAt": (heartbeat > 0).then(|| DateTime::from_timestamp(heartbeat, 0)).flatten(),
+        "watchdog": {
+            "restarts": advertiser.restarts,
+            "lastRestartAt": advertiser.last_restart_at,
+            "lastRestartReason": advertiser.last_restart_reason,
+        },
+    })
+}
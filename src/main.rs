@@ -3,20 +3,38 @@
 //! This is the main executable entry point for the Wasmiot supervisor.
 //!
 //! This performs the following startup tasks:
+//! - Reads the settings from the command line, the environment and the configuration file
+//!   (see `supervisor --help`)
 //! - Initializes loggers and instance directories
-//! - Starts the Actix-Web server for HTTP endpoints
+//! - Starts the Actix-Web server for HTTP endpoints, also on a Unix domain socket if one is set
 //! - Registers the device with Zeroconf (mDNS/Bonjour)
 //! - Spawns a background worker thread for executing WebAssembly tasks asynchronously
+//! - Loads the saved deployments in the background once the server is listening, then removes
+//!   module and params folders of deployments that no longer exist
+//! - Spawns a background task recording the health history
+//! - Spawns a background task removing expired deployments
+//! - Spawns a background task removing old execution outputs
+//! - Spawns a background task running the requests of the durable execution queue, also those
+//!   left from before the last shutdown
+//! - Spawns a background task reloading the configuration file on SIGHUP
+//! - Spawns a background task executing functions published to the MQTT broker, if one is set
+//! - Serves CoAP for constrained clients, if a CoAP port is set
+//! - Serves gRPC, if built with the `grpc` feature and a gRPC port is set
+//! - Spawns a background task pushing metrics to Telegraf, if a listener is set
+//! - Exports traces of executions over OTLP, if built with the `otel` feature and an endpoint is set
+//! - Applies deployment manifests found in `preloaded_deployments/` under the instance path
 
 use actix_web::{App, HttpServer, web::Data};
 use actix_cors::Cors;
-use log::info;
+use log::{debug, info};
 use parking_lot::Mutex;
+use std::collections::HashMap;
 use std::sync::Arc;
-use supervisor::lib::{api, zeroconf, constants};
-use supervisor::lib::constants::DEPLOYMENTS_FOLDER;
-use supervisor::lib::deployment::Deployment;
-use supervisor::lib::api::DEPLOYMENTS;
+use supervisor::lib::{api, zeroconf, constants, configuration, coap, durable_queue, grpc, health, logging, metrics, mqtt, replication, self_check, shutdown, startup, stats, systemd, telemetry, unix_socket};
+use supervisor::lib::cli::Cli;
+use supervisor::lib::orchestrator_compat::{ApiVersion, API_VERSION_HEADER};
+use supervisor::lib::config_file::ConfigFile;
+use supervisor::lib::settings::{set_setting, SettingSource};
 
 /// Main entry point for the supervisor service.
 ///
@@ -28,42 +46,62 @@ use supervisor::lib::api::DEPLOYMENTS;
 /// - Any `std::io::Error` that occurs during HTTP server setup
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Load environment variables from .env if present
-    match dotenv::dotenv() {
-        Ok(path) => println!("Loaded .env from {:?}", path),
-        Err(err) => println!("Could not load .env file: {:?}", err),
+    // Read the settings from the command line, the environment, .env and supervisor.toml, in that order
+    let cli = Cli::load();
+    let startup = cli.startup_config();
+    if cli.print_config {
+        match toml::to_string_pretty(&startup.settings) {
+            Ok(config) => println!("{}", config),
+            Err(e) => eprintln!("Failed to print configuration: {}", e),
+        }
+        return Ok(());
     }
 
-    // Ensure required folders like `params/` and `modules/` exist
-    constants::ensure_required_folders();
-
-    // Initialize logging with default level = info (unless overridden by env)
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-
-    // Ensure that the environment variable for supervisor name is set
-    // - order: SUPERVISOR_NAME, WASMIOT_SUPERVISOR_NAME, or the default name
-    if std::env::var("SUPERVISOR_NAME").is_err() {
-        unsafe {
-            std::env::set_var(
-                "SUPERVISOR_NAME",
-                match std::env::var("WASMIOT_SUPERVISOR_NAME") {
-                    Ok(name) => name,
-                    Err(_) => constants::SUPERVISOR_DEFAULT_NAME.to_string(),
-                }
-            );
-        }
+    if cli.check {
+        let report = self_check::run_checks(cli.check_camera);
+        let output = serde_json::json!({ "ok": report.ok, "checks": report.checks, "startup": startup });
+        println!("{}", serde_json::to_string_pretty(&output).unwrap_or_default());
+        std::process::exit(if report.ok { 0 } else { 1 });
+    }
 
+    // Ensure required folders like `params/` and `modules/` exist and are writable
+    if let Err(e) = constants::ensure_required_folders() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
-    info!("Supervisor name: {}", std::env::var("SUPERVISOR_NAME").unwrap());
+
+    // Initialize logging with the configured level or filter, which can be changed on reload
+    logging::init_logger(&startup.log_level);
+
+    info!("Supervisor name: {}", startup.name);
+    if supervisor::lib::camera::camera_enabled() {
+        info!("Camera is enabled");
+    } else {
+        info!("Camera is disabled, modules that need it cannot be deployed");
+    }
+    // Show where each setting came from, the ones left to their defaults only when debugging
+    let values: HashMap<&str, String> = startup.settings.env_values().into_iter().collect();
+    for (key, name) in ConfigFile::KEYS {
+        let source = startup.sources.get(*key).copied().unwrap_or(SettingSource::Default);
+        let value = values.get(name).map(String::as_str).unwrap_or("<unset>");
+        if source == SettingSource::Default {
+            debug!("Setting {} = {} (default)", key, value);
+        } else {
+            info!("Setting {} = {} (from {})", key, value, source);
+        }
+    }
+    let bind_address = startup.bind_address;
+    configuration::set_startup_config(startup);
+
+    // Export traces of executions, if an OTLP endpoint is set
+    telemetry::init_tracing();
 
     // Start Zeroconf discovery and determine host/port
     let zc = zeroconf::WebthingZeroconf::new();
     let (host, port) = (zc.host.clone(), zc.port);
     info!("host:{}, port:{}", host, port);
-    unsafe {
-        std::env::set_var("WASMIOT_SUPERVISOR_IP", &host);
-        std::env::set_var("DEFAULT_URL_SCHEME", "http");
-    }
+    set_setting("WASMIOT_SUPERVISOR_IP", &host, SettingSource::Detected);
+    set_setting("DEFAULT_URL_SCHEME", "http", SettingSource::Default);
 
     let zc_arc = Arc::new(Mutex::new(zc.clone()));
     // Wait for the server to be ready before advertising over Zeroconf
@@ -72,57 +110,61 @@ async fn main() -> std::io::Result<()> {
     // if the WASMIOT_ORCHESTRATOR_URL environment variable is set.
     zeroconf::force_supervisor_registration(zc_arc.clone());
 
-    // Before initializing the server, load the currently existing deployments into memory
-    if let Err(e) = std::fs::create_dir_all(&*DEPLOYMENTS_FOLDER) {
-        log::error!(
-            "Failed to create deployments folder {}: {}",
-            DEPLOYMENTS_FOLDER.display(),
-            e
-        );
-    } else if let Ok(entries) = std::fs::read_dir(&*DEPLOYMENTS_FOLDER) {
-        for entry_res in entries {
-            let Ok(entry) = entry_res else { continue };
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) != Some("json") {
-                continue;
-            }
-            let file_name = path
-                .file_name()
-                .and_then(|s| s.to_str())
-                .unwrap_or("<unknown>");
-            let contents = match std::fs::read_to_string(&path) {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!(
-                        "Failed to read deployment JSON file {}: {}",
-                        path.display(),
-                        e
-                    );
-                    continue;
-                }
-            };
-            let mut deployment: Deployment = match serde_json::from_str(&contents) {
-                Ok(d) => d,
-                Err(e) => {
-                    log::error!(
-                        "Failed to deserialize deployment JSON from {}: {}",
-                        path.display(),
-                        e
-                    );
-                    continue;
-                }
-            };
-            deployment.init();
-            let id = deployment.id.clone();
-            {
-                let mut deps = DEPLOYMENTS.lock();
-                deps.insert(id.clone(), deployment);
-            }
-            log::info!("Loaded saved deployment '{}' from {}", id, file_name);
-        }
+    // The saved deployments are loaded once the server is up, and answered as loading until then
+    let saved_deployments = startup::saved_deployment_files();
+    startup::mark_loading(&saved_deployments);
+
+    // Serve the results of the requests made before the last shutdown
+    match api::load_request_history() {
+        Ok(0) => {}
+        Ok(count) => log::info!("Loaded {} requests of the saved request history", count),
+        Err(e) => log::error!("{}", e),
     }
 
+    // Take the first health snapshot before serving, then keep refreshing it and recording
+    // samples of it into the health history
+    health::seed_health_snapshot();
+    tokio::spawn(health::run_health_sampler());
+
+    // Start removing deployments once they expire
+    tokio::spawn(api::run_deployment_expiry());
+
+    // Start removing old execution outputs as configured by the result retention policy
+    tokio::spawn(api::run_result_retention());
+
+    // Save the execution statistics of deployments now and then
+    tokio::spawn(stats::run_stats_saver());
+
+    // Keep the standby replicas of deployments synced
+    tokio::spawn(replication::run_replica_sync());
+
+    // Restart the mDNS advertisement if it dies or stops being renewed
+    tokio::spawn(zeroconf::run_registration_watchdog(zc_arc.clone()));
+
+    // Reload the configuration file on SIGHUP
+    #[cfg(unix)]
+    tokio::spawn(supervisor::lib::reload::run_reload_on_sighup(zc_arc.clone()));
+
+    // Run Wasm functions on threads of their own, so that they do not hold up the HTTP workers
+    supervisor::lib::execution::init_execution_threads();
+
+    // Run the requests queued on disk as execution threads free up, once deployments are loaded
+    tokio::spawn(durable_queue::run_queue_worker());
+
+    // Take executions from the MQTT broker as well, if one is configured
+    tokio::spawn(mqtt::run_mqtt_client());
+
+    // Run functions for constrained clients speaking CoAP as well, if a port is configured
+    tokio::spawn(coap::run_coap_server(bind_address));
+
+    // Serve the gRPC API as well, if a port is configured
+    tokio::spawn(grpc::run_grpc_server(bind_address));
+
+    // Push metrics to a Telegraf listener as well, if one is configured
+    tokio::spawn(metrics::run_metrics_reporter());
+
     // Initialize the HTTP server.
+    systemd::notify_status("Starting the HTTP server");
     let server = HttpServer::new(move || {
         App::new()
         .wrap(
@@ -132,13 +174,91 @@ async fn main() -> std::io::Result<()> {
                 .allow_any_header()
                 .max_age(3600)
         )
+        // The liveness check is polled by the watchdog, which would flood the log
+        .wrap(
+            actix_web::middleware::Logger::default().exclude("/healthz")
+        )
+        // Tell clients the latest version of the orchestrator API the supervisor speaks
         .wrap(
-            actix_web::middleware::Logger::default()
+            actix_web::middleware::DefaultHeaders::new()
+                .add((API_VERSION_HEADER, ApiVersion::LATEST.header_value()))
+        )
+        // Compress JSON and other compressible responses for clients that accept it
+        .wrap(
+            actix_web::middleware::Compress::default()
         )
         .app_data(Data::new(zc_arc.clone()))  // Pass the Zeroconf instance to the app
         .configure(api::configure_routes)
-    })
-    .bind(("0.0.0.0", port))?;
-    info!("Starting supervisor service at http://{}:{}/", host, port);
-    server.run().await
+    });
+    // Use the default of one worker per CPU core unless configured
+    let server = match constants::get_http_workers() {
+        Some(workers) => server.workers(workers),
+        None => server,
+    }
+    // Signals are handled in shutdown.rs, which also waits for the running executions
+    .disable_signals()
+    .shutdown_timeout(constants::get_drain_timeout())
+    // Tell the requests over the Unix domain socket apart from the ones over the network
+    .on_connect(unix_socket::mark_connection)
+    .bind((bind_address, port))
+    .map_err(|e| std::io::Error::new(e.kind(), format!("Failed to listen on {}:{}: {}", bind_address, port, e)))?;
+    info!("Starting supervisor service at http://{}:{}/ (listening on {})", host, port, bind_address);
+
+    // Serve agents on the same host over a Unix domain socket as well, if one is configured
+    let socket_path = constants::get_unix_socket_path();
+    #[cfg(unix)]
+    let server = match &socket_path {
+        Some(path) => {
+            let listen_error = |e: std::io::Error| std::io::Error::new(e.kind(), format!("Failed to listen on {}: {}", path.display(), e));
+            let listener = unix_socket::bind_socket(path, constants::get_unix_socket_mode()).map_err(listen_error)?;
+            info!("Also listening on {}", path.display());
+            server.listen_uds(listener)?
+        }
+        None => server,
+    };
+    #[cfg(not(unix))]
+    if let Some(path) = &socket_path {
+        log::warn!("Not listening on {}, Unix domain sockets are not supported on this platform", path.display());
+    }
+    let server = server.run();
+    tokio::spawn(shutdown::stop_on_signal(server.handle()));
+
+    // Load the saved deployments and apply the preloaded ones while the server answers
+    systemd::notify_status("Loading saved deployments");
+    tokio::spawn(startup::load_deployments(saved_deployments));
+
+    // Tell systemd that the supervisor is up once the deployments are loaded, and keep telling
+    // it while the server answers
+    let serving_at = format!("Serving at http://{}:{}/", host, port);
+    tokio::spawn(async move {
+        startup::wait_for_startup().await;
+        systemd::notify_ready();
+        systemd::notify_status(&serving_at);
+    });
+    tokio::spawn(systemd::run_watchdog(bind_address, port));
+
+    let result = server.await;
+    systemd::notify_stopping();
+
+    // Give the executions still running the rest of the drain timeout, and record the ones that
+    // do not finish as aborted, so that their results do not stay pending forever
+    if shutdown::in_flight_requests() > 0 {
+        systemd::notify_status(&format!("Waiting for {} running executions", shutdown::in_flight_requests()));
+    }
+    let aborted = shutdown::drain_executions().await;
+    if !aborted.is_empty() {
+        api::record_aborted_requests(aborted);
+    }
+    if let Err(e) = api::save_request_history() {
+        log::error!("{}", e);
+    }
+    if let Err(e) = stats::save_stats() {
+        log::error!("{}", e);
+    }
+    tokio::task::spawn_blocking(telemetry::shutdown_tracing).await.ok();
+    #[cfg(unix)]
+    if let Some(path) = &socket_path {
+        unix_socket::remove_socket(path);
+    }
+    result
 }
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use strum_macros::{EnumString, AsRefStr};
+use crate::lib::deployment::{Endpoint, EndpointRequest, EndpointResponse, Schema, SchemaType};
+
+/// Represents the lifecycle stage at which a file is mounted into a module's execution context.
+///
+/// Mounts can occur during:
+/// - `DEPLOYMENT`: Static data available before execution.
+/// - `EXECUTION`: Runtime input files (e.g. POSTed by client).
+/// - `OUTPUT`: Expected output paths generated by the module.
+/// - `UNKNOWN`: Fallback when parsing fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, AsRefStr, Serialize, Deserialize)]
+pub enum MountStage {
+    #[strum(serialize = "deployment")]
+    #[serde(rename = "deployment")]
+    DEPLOYMENT,
+    #[strum(serialize = "execution")]
+    #[serde(rename = "execution")]
+    EXECUTION,
+    #[strum(serialize = "output")]
+    #[serde(rename = "output")]
+    OUTPUT,
+    #[strum(serialize = "unknown")]
+    #[serde(rename = "unknown")]
+    UNKNOWN,
+}
+
+impl From<String> for MountStage {
+    fn from(s: String) -> Self {
+        MountStage::from_str(&s).unwrap_or_else(|_| {
+            error!("Invalid Stage: '{}', defaulting to UNKNOWN", s);
+            MountStage::UNKNOWN
+        })
+    }
+}
+
+/// Represents a file path and its metadata, used for mounting into a Wasm module.
+///
+/// Each mount includes:
+/// - `path`: Virtual mount path inside module
+/// - `media_type`: MIME type (e.g. `image/png`)
+/// - `stage`: Lifecycle stage this file is used in
+/// - `required`: Whether the file is mandatory
+/// - `encoding`: Data encoding (e.g. `base64`)
+/// - `_type`: Type name (e.g. `string`, `binary`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountPathFile {
+    pub path: String,
+    pub media_type: String,
+    pub stage: MountStage,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    #[serde(default = "default_type")]
+    pub r#type: String,
+}
+
+impl MountPathFile {
+    /// Constructs a new `MountPathFile` with default fallbacks for optional fields.
+    pub fn new<S: Into<MountStage>>(
+        path: String,
+        media_type: String,
+        stage: S,
+        required: Option<bool>,
+        encoding: Option<String>,
+        r#type: Option<String>,
+    ) -> Self {
+        MountPathFile {
+            path,
+            media_type,
+            stage: stage.into(),
+            required: required.unwrap_or(true),
+            encoding: encoding.unwrap_or_else(|| "base64".to_string()),
+            r#type: r#type.unwrap_or_else(|| "string".to_string()),
+        }
+    }
+
+    /// Placeholder for validation logic (currently a passthrough).
+    ///
+    /// TODO: Determine if this function is necessary or should be removed.
+    pub fn validate(&self, x: HashMap<String, Value>) -> HashMap<String, Value> {
+        x
+    }
+}
+
+// Helper default functions for serde
+fn default_required() -> bool {
+    true
+}
+fn default_encoding() -> String {
+    "base64".to_string()
+}
+fn default_type() -> String {
+    "string".to_string()
+}
+
+/// Represents a link (call chain) between two Wasm function endpoints.
+///
+/// Used in instruction graphs to describe function chaining behavior.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionLink {
+    /// The source function that triggers the next one.
+    pub from: Endpoint,
+    /// The destination function (optional if terminal call).
+    pub to: Option<Endpoint>,
+    /// Targets tried in order, with the same payload, when calling `to` fails with a
+    /// connection error or a server error.
+    #[serde(rename = "fallbackTargets", default)]
+    pub fallback_targets: Vec<Endpoint>,
+}
+
+impl FunctionLink {
+    /// Constructs a `FunctionLink` from two endpoints.
+    pub fn new(from: Endpoint, to: Option<Endpoint>) -> Self {
+        FunctionLink { from, to, fallback_targets: Vec::new() }
+    }
+}
+
+impl From<HashMap<String, Value>> for FunctionLink {
+    /// Attempts to deserialize a `FunctionLink` from a JSON-style map.
+    ///
+    /// The map must contain at least a `"from_"` key with valid `Endpoint` data.
+    /// If `from_` is missing or invalid, a fallback default is used and logged.
+    fn from(map: HashMap<String, Value>) -> Self {
+        let from = map.get("from_")
+            .and_then(|v| serde_json::from_value::<Endpoint>(v.clone()).ok())
+            .unwrap_or_else(|| {
+                error!("Invalid or missing 'from_' field in FunctionLink");
+                Endpoint::new(
+                    "".to_string(),
+                    "".to_string(),
+                    "".to_string(),
+                    EndpointRequest::new(vec![], None),
+                    EndpointResponse::new(
+                        "application/octet-stream".to_string(),
+                        Schema::new(SchemaType::UNKNOWN, None, None),
+                        None,
+                    ),
+                )
+            });
+
+        let to = map.get("to")
+            .and_then(|v| serde_json::from_value::<Endpoint>(v.clone()).ok());
+
+        let fallback_targets = map.get("fallbackTargets")
+            .and_then(|v| serde_json::from_value::<Vec<Endpoint>>(v.clone()).ok())
+            .unwrap_or_default();
+
+        FunctionLink { from, to, fallback_targets }
+    }
+}
+
+/// Maps function names to how they are linked to other functions.
+pub type FunctionLinkMap = HashMap<String, FunctionLink>;
+
+/// Maps module names to function link maps (i.e. call graphs).
+pub type ModuleLinkMap = HashMap<String, FunctionLinkMap>;
+
+/// Maps mount stages to mount path definitions.
+pub type MountStageMap = HashMap<MountStage, Vec<MountPathFile>>;
+
+/// Maps function names to stage-based mount definitions.
+pub type FunctionMountMap = HashMap<String, MountStageMap>;
+
+/// Maps module names to their function mount definitions.
+pub type ModuleMountMap = HashMap<String, FunctionMountMap>;
+
+/// Parses the `instructions` of a deployment manifest: for each function of each module under
+/// `modules`, the endpoint it is called at (`from`) and the one its result is sent to (`to`), if
+/// any.
+///
+/// # Returns
+/// The call graph, or which entry is not valid and why.
+pub fn parse_instructions(value: Option<&Value>) -> Result<ModuleLinkMap, String> {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        return Ok(HashMap::new());
+    };
+    let Some(instructions) = value.as_object() else {
+        return Err("instructions must be an object".to_string());
+    };
+    let Some(modules) = instructions.get("modules").filter(|modules| !modules.is_null()) else {
+        return Ok(HashMap::new());
+    };
+    let mut links = HashMap::new();
+    for (module_name, functions) in object(modules, "instructions.modules")? {
+        let mut function_links = HashMap::new();
+        for (function_name, link) in object(functions, &format!("instructions.modules.{}", module_name))? {
+            let at = format!("instructions.modules.{}.{}", module_name, function_name);
+            let link = object(link, &at)?;
+            let from = link.get("from").ok_or_else(|| format!("{}: missing field `from`", at))?;
+            let from = serde_json::from_value::<Endpoint>(from.clone())
+                .map_err(|e| format!("{}.from: {}", at, e))?;
+            let to = match link.get("to").filter(|to| !to.is_null()) {
+                Some(to) => Some(serde_json::from_value::<Endpoint>(to.clone()).map_err(|e| format!("{}.to: {}", at, e))?),
+                None => None,
+            };
+            let fallback_targets = match link.get("fallbackTargets").filter(|targets| !targets.is_null()) {
+                Some(targets) => serde_json::from_value::<Vec<Endpoint>>(targets.clone())
+                    .map_err(|e| format!("{}.fallbackTargets: {}", at, e))?,
+                None => Vec::new(),
+            };
+            function_links.insert(function_name.clone(), FunctionLink { from, to, fallback_targets });
+        }
+        links.insert(module_name.clone(), function_links);
+    }
+    Ok(links)
+}
+
+/// Parses the `mounts` of a deployment manifest: for each function of each module, the files
+/// mounted at each stage. The `stage` of each mount must be the stage it is listed under.
+///
+/// # Returns
+/// The mounts, or which entry is not valid and why.
+pub fn parse_mounts(value: Option<&Value>) -> Result<ModuleMountMap, String> {
+    let Some(value) = value.filter(|value| !value.is_null()) else {
+        return Ok(HashMap::new());
+    };
+    let mut mounts = HashMap::new();
+    for (module_name, functions) in object(value, "mounts")? {
+        let mut function_mounts = HashMap::new();
+        for (function_name, stages) in object(functions, &format!("mounts.{}", module_name))? {
+            let mut stage_mounts = HashMap::new();
+            for (stage_name, files) in object(stages, &format!("mounts.{}.{}", module_name, function_name))? {
+                let at = format!("mounts.{}.{}.{}", module_name, function_name, stage_name);
+                let stage = MountStage::from_str(stage_name)
+                    .ok()
+                    .filter(|stage| *stage != MountStage::UNKNOWN)
+                    .ok_or_else(|| format!("{}: unknown stage, expected deployment, execution or output", at))?;
+                let files = files.as_array().ok_or_else(|| format!("{}: must be a list", at))?;
+                let mut parsed = Vec::new();
+                for (index, file) in files.iter().enumerate() {
+                    let mount = serde_json::from_value::<MountPathFile>(file.clone())
+                        .map_err(|e| format!("{}[{}]: {}", at, index, e))?;
+                    if mount.stage != stage {
+                        return Err(format!(
+                            "{}[{}]: stage '{}' of the mount differs from the stage it is listed under",
+                            at, index, mount.stage.as_ref()
+                        ));
+                    }
+                    parsed.push(mount);
+                }
+                stage_mounts.insert(stage, parsed);
+            }
+            function_mounts.insert(function_name.clone(), stage_mounts);
+        }
+        mounts.insert(module_name.clone(), function_mounts);
+    }
+    Ok(mounts)
+}
+
+/// The fields of a JSON object, or an error naming where in the manifest it should have been.
+fn object<'a>(value: &'a Value, at: &str) -> Result<&'a serde_json::Map<String, Value>, String> {
+    value.as_object().ok_or_else(|| format!("{}: must be an object", at))
+}
@@ -1,6 +1,11 @@
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 use mongodb::bson::oid::ObjectId;
+use crate::lib::maintenance::ResultStorageStats;
+use crate::lib::wasmtime::ModuleCacheStats;
+use crate::lib::device_limits::DeviceUsage;
+use crate::lib::durable_queue::QueueStats;
+use crate::structs::supervisor_config::DeviceLimits;
 
 
 /// Communication details for a device. Includes addresses and port.
@@ -101,6 +106,18 @@ pub struct NetworkInterfaceUsage {
     pub up_bytes: u64, // Total bytes received since last system start
 }
 
+/// Overall health of a device, derived from the configured health thresholds.
+///
+/// Variants are ordered from best to worst so the worst of several can be picked with `max`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    #[default]
+    Ok,
+    Degraded,
+    Critical,
+}
+
 /// The structure of a health report sent by the supervisor.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthReport {
@@ -113,8 +130,42 @@ pub struct HealthReport {
     pub uptime: u64,          // Uptime in seconds
     #[serde(rename="networkUsage")]
     pub network_usage: HashMap<String, NetworkInterfaceUsage>, // Network usage per interface
+    #[serde(default)]
+    pub status: HealthStatus, // Overall status derived from the health thresholds
+    #[serde(default)]
+    pub reasons: Vec<String>, // Thresholds that were tripped, empty when status is ok
+    #[serde(rename="resultStorage", default)]
+    pub result_storage: ResultStorageStats, // Storage used by execution outputs and reclaimed from them
+    #[serde(rename="deviceUsage", default)]
+    pub device_usage: DeviceUsage, // Deployments, modules and bytes of artifacts and results on the device
+    #[serde(rename="deviceLimits", default)]
+    pub device_limits: DeviceLimits, // Caps on the deployments of the device, checked when deployments are created
+    #[serde(rename="inFlightExecutions", default)]
+    pub in_flight_executions: usize, // Wasm function calls running, which shutdown waits for
+    #[serde(rename="executionQueue", default)]
+    pub execution_queue: QueueStats, // Requests waiting in the durable execution queue and the age of the oldest
+    #[serde(rename="moduleCache", default)]
+    pub module_cache: ModuleCacheStats, // Compiled modules shared by deployments of identical binaries
+    #[serde(default)]
+    pub wasm_memory_bytes: u64, // Linear memory of the Wasm modules of all deployments, as last measured
+    #[serde(rename="snapshotAgeMs", default)]
+    pub snapshot_age_ms: u64, // How long ago the system metrics above were sampled
 }
 
+/// A compact health sample recorded periodically into the health history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthSample {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(rename="cpuUsage")]
+    pub cpu_usage: f32,    // CPU usage fraction (0..1)
+    #[serde(rename="memoryUsage")]
+    pub memory_usage: f32, // Memory usage fraction (0..1)
+    #[serde(rename="instanceDiskFreeBytes")]
+    pub instance_disk_free_bytes: Option<u64>, // Free space on the filesystem holding the instance path
+    pub temperature: Option<f32>, // Hottest sensor in Celsius, if the device has any
+    #[serde(rename="inFlightExecutions")]
+    pub in_flight_executions: usize, // Wasm function calls running when the sample was taken
+}
 
 
 /// Represents a device document from the "device" collection in MongoDB.
@@ -9,30 +9,181 @@ use sha2::{Digest, Sha256};
 ///
 /// Tracks metadata like request time, parameters, function name, execution status, and result.
 /// The `request_id` is a hash based on module/function identifiers and time for uniqueness.
-#[derive(Debug, Clone, serde::Serialize)]
+///
+/// Entries are serialized with camelCase keys, as the orchestrator expects, and tell the
+/// version of the format as `schemaVersion`. Entries saved in the snake_case format of
+/// version 1 are still read, and `legacy_json` serializes an entry in that format.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RequestEntry {
+    /// Version of the format the entry is serialized in, always `REQUEST_ENTRY_SCHEMA_VERSION`.
+    #[serde(skip_deserializing, default = "current_schema_version")]
+    pub schema_version: u32,
     /// Unique identifier for this request.
+    #[serde(alias = "request_id")]
     pub request_id: String,
     /// Deployment ID this request belongs to.
+    #[serde(alias = "deployment_id")]
     pub deployment_id: String,
     /// The module name whose function is being executed.
+    #[serde(alias = "module_name")]
     pub module_name: String,
     /// The function name within the module to run.
+    #[serde(alias = "function_name")]
     pub function_name: String,
     /// The HTTP method used for this request.
     pub method: String,
     /// Query or JSON arguments passed to the function.
+    #[serde(alias = "request_args")]
     pub request_args: Value,
     /// Mapping from mount path -> local file path for input files.
+    #[serde(alias = "request_files")]
     pub request_files: HashMap<String, String>,
     /// Timestamp when this request was queued for execution.
+    #[serde(alias = "work_queued_at")]
     pub work_queued_at: DateTime<Utc>,
     /// Optional result value (primitive output or result path).
     pub result: Option<Value>,
+    /// Whether the function returned a value. The result of a function returning nothing is
+    /// `{"status": "completed"}`.
+    #[serde(default = "default_has_result", alias = "has_result")]
+    pub has_result: bool,
+    /// Whether the result string read from the memory of the module was not valid UTF-8, so
+    /// that the invalid bytes were replaced.
+    #[serde(default, alias = "result_lossy")]
+    pub result_lossy: bool,
     /// List that contains links to possible output mount files
     pub outputs: Vec<String>,
     /// Indicates whether the execution succeeded.
     pub success: bool,
+    /// Indicates whether the execution was still running when the supervisor shut down, so
+    /// it never finished.
+    #[serde(default)]
+    pub aborted: bool,
+    /// Indicates whether the execution was refused because the module failed to load, so that
+    /// it never ran. One entry is recorded for each burst of such refusals.
+    #[serde(default, alias = "load_error")]
+    pub load_error: bool,
+    /// Chained calls made to other functions after this one, in order.
+    #[serde(alias = "chain_trace")]
+    pub chain_trace: Vec<ChainStep>,
+    /// URL of the target that served the chained call, if one was made. It is one of the
+    /// fallback targets of the link when calling the targets before it failed.
+    #[serde(default, alias = "served_by")]
+    pub served_by: Option<String>,
+    /// Whether the input files of the request were kept in its inputs folder after the
+    /// execution, because the endpoint has `keepInputs` set.
+    #[serde(default, alias = "inputs_retained")]
+    pub inputs_retained: bool,
+    /// URL the entry is posted to once the execution has finished, if the caller gave one.
+    #[serde(default, alias = "callback_url")]
+    pub callback_url: Option<String>,
+    /// How posting the entry to `callback_url` went, once it has been tried.
+    #[serde(default)]
+    pub callback: Option<CallbackDelivery>,
+    /// W3C `traceparent` of the trace the request is part of, if the caller sent one.
+    #[serde(default)]
+    pub traceparent: Option<String>,
+    /// Address the request came from, if it came over HTTP.
+    #[serde(default)]
+    pub caller: Option<String>,
+    /// Priority the request waits for an execution thread with.
+    #[serde(default)]
+    pub priority: Priority,
+    /// How long the request waited for an execution thread, in milliseconds, once it got one.
+    #[serde(default, alias = "queue_wait_ms")]
+    pub queue_wait_ms: Option<u64>,
+    /// Bytes received for the request: its body, the responses of its chained calls and the
+    /// results and outputs fetched from them.
+    #[serde(default, alias = "bytes_in")]
+    pub bytes_in: u64,
+    /// Bytes sent for the request: the payload of its chained call, and its result files and
+    /// entry whenever they are downloaded.
+    #[serde(default, alias = "bytes_out")]
+    pub bytes_out: u64,
+}
+
+/// Version of the format request entries are serialized in. Version 1 had snake_case keys
+/// and no `schemaVersion`.
+pub const REQUEST_ENTRY_SCHEMA_VERSION: u32 = 2;
+
+fn current_schema_version() -> u32 {
+    REQUEST_ENTRY_SCHEMA_VERSION
+}
+
+fn default_has_result() -> bool {
+    true
+}
+
+/// A chained call made after executing a function, or a failed upload of its outputs to the
+/// result sink of the deployment (see `result_sink.rs`).
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainStep {
+    /// URL the chained call was made to.
+    pub url: String,
+    /// URLs of the output files the chained call returned.
+    pub outputs: Vec<String>,
+    /// Whether the output files were copied to this device, replacing the URLs in `outputs`
+    /// of the request with ones of this device.
+    pub mirrored: bool,
+    /// Why the output files were not copied, if there were any.
+    #[serde(alias = "mirror_error")]
+    pub mirror_error: Option<String>,
+    /// Why the chained call failed, if it did. The next fallback target of the link is then
+    /// tried with the same payload.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The delivery of a request entry to its callback URL.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallbackDelivery {
+    /// Whether the callback URL accepted the entry.
+    pub delivered: bool,
+    /// How many times the entry was posted.
+    pub attempts: u32,
+    /// HTTP status of the last response, if there was one.
+    pub status: Option<u16>,
+    /// Why the last attempt failed.
+    pub error: Option<String>,
+}
+
+/// Priority of a request for an execution thread, from the `X-Priority` header of the request
+/// or the `priority` of its endpoint. Higher priority requests are run first when every
+/// execution thread is busy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// Name of the priority, as in the `X-Priority` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Ok(Priority::Low),
+            "normal" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            other => Err(format!("Invalid priority '{}', expected high, normal or low", other)),
+        }
+    }
 }
 
 impl RequestEntry {
@@ -47,6 +198,7 @@ impl RequestEntry {
         work_queued_at: DateTime<Utc>,
     ) -> Self {
         let mut entry = RequestEntry {
+            schema_version: REQUEST_ENTRY_SCHEMA_VERSION,
             request_id: String::new(),
             deployment_id,
             module_name,
@@ -56,13 +208,46 @@ impl RequestEntry {
             request_files,
             work_queued_at,
             result: None,
+            has_result: true,
+            result_lossy: false,
             outputs: Vec::new(),
             success: false,
+            aborted: false,
+            load_error: false,
+            chain_trace: Vec::new(),
+            served_by: None,
+            inputs_retained: false,
+            callback_url: None,
+            callback: None,
+            traceparent: None,
+            caller: None,
+            priority: Priority::Normal,
+            queue_wait_ms: None,
+            bytes_in: 0,
+            bytes_out: 0,
         };
         entry.init_request_id();
         entry
     }
 
+    /// The entry in the snake_case format of schema version 1, for clients that have not
+    /// moved to the current format yet. The keys of its arguments and result are left as is.
+    pub fn legacy_json(&self) -> Value {
+        let Ok(Value::Object(entry)) = serde_json::to_value(self) else {
+            return Value::Null;
+        };
+        let mut legacy = snake_case_keys(entry);
+        legacy.remove("schema_version");
+        if let Some(Value::Array(steps)) = legacy.get_mut("chain_trace") {
+            for step in steps.iter_mut() {
+                if let Value::Object(fields) = step.take() {
+                    *step = Value::Object(snake_case_keys(fields));
+                }
+            }
+        }
+        Value::Object(legacy)
+    }
+
     /// Initializes `request_id` by hashing the module/function and timestamp.
     fn init_request_id(&mut self) {
         let key = format!("{}:{}:{}", self.deployment_id, self.module_name, self.function_name);
@@ -73,4 +258,20 @@ impl RequestEntry {
         let hash_bytes = hasher.finalize();
         self.request_id = hex::encode(hash_bytes);
     }
-}
\ No newline at end of file
+}
+
+/// Renames the keys of a JSON object from camelCase to snake_case.
+fn snake_case_keys(object: serde_json::Map<String, Value>) -> serde_json::Map<String, Value> {
+    object.into_iter()
+        .map(|(key, value)| {
+            let mut snake = String::with_capacity(key.len() + 4);
+            for c in key.chars() {
+                if c.is_ascii_uppercase() {
+                    snake.push('_');
+                }
+                snake.push(c.to_ascii_lowercase());
+            }
+            (snake, value)
+        })
+        .collect()
+}
@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use crate::lib::cli::parse_name;
+use crate::lib::config_file::ConfigFile;
+use crate::lib::settings::SettingSource;
+use crate::lib::constants::{DEFAULT_PORT, SUPERVISOR_DEFAULT_NAME};
+use crate::structs::device::HealthStatus;
+
+/// A pair of limits for a single health metric.
+///
+/// Reaching `degraded` marks the device degraded, reaching `critical` marks it critical.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub degraded: f32,
+    pub critical: f32,
+}
+
+/// Thresholds used to derive the overall `status` of a health report.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthThresholds {
+    pub cpu: Threshold,         // Fraction (0..1) of total CPU in use
+    pub memory: Threshold,      // Fraction (0..1) of memory in use
+    pub disk: Threshold,        // Fraction (0..1) used of the filesystem holding the instance path
+    pub temperature: Threshold, // Degrees Celsius of the hottest sensor
+    #[serde(rename="logQueue")]
+    pub log_queue: Threshold,   // Number of log messages waiting to be sent
+}
+
+/// Supervisor settings that can be changed at runtime through the `/config` endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SupervisorConfig {
+    /// Name the supervisor is advertised and registered with
+    #[serde(default = "default_name")]
+    pub name: String,
+    #[serde(rename="healthThresholds")]
+    pub health_thresholds: HealthThresholds,
+    /// Prefix of every URL the supervisor gives out about itself, e.g. when behind a reverse proxy
+    #[serde(rename="publicBaseUrl", default)]
+    pub public_base_url: Option<String>,
+    /// Caps on what the device takes on, checked when deployments are created
+    #[serde(rename="deviceLimits", default)]
+    pub device_limits: DeviceLimits,
+    /// Settings the supervisor was started with, which cannot be changed at runtime
+    #[serde(default)]
+    pub startup: StartupConfig,
+}
+
+/// Caps on the deployments of the device, each unset for no cap (see `device_limits.rs`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceLimits {
+    /// Most deployments on the device
+    #[serde(rename="maxDeployments", default)]
+    pub max_deployments: Option<usize>,
+    /// Most modules in all deployments on the device
+    #[serde(rename="maxModules", default)]
+    pub max_modules: Option<usize>,
+    /// Most bytes of module binaries and their files in all deployments on the device
+    #[serde(rename="maxArtifactBytes", default)]
+    pub max_artifact_bytes: Option<u64>,
+}
+
+/// Settings given on the command line, in the environment or in the configuration file when
+/// starting the supervisor (see `cli.rs` and `config_file.rs`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StartupConfig {
+    pub port: u16,
+    #[serde(rename="bindAddress")]
+    pub bind_address: IpAddr,
+    #[serde(rename="advertiseAddress")]
+    pub advertise_address: Option<IpAddr>,
+    #[serde(rename="instancePath")]
+    pub instance_path: PathBuf,
+    #[serde(rename="orchestratorUrl")]
+    pub orchestrator_url: Option<String>,
+    pub name: String,
+    #[serde(rename="logLevel")]
+    pub log_level: String,
+    #[serde(rename="configFile")]
+    pub config_file: Option<PathBuf>,
+    /// Every setting given in any of them, in the sections of the configuration file
+    #[serde(default)]
+    pub settings: ConfigFile,
+    /// Where the value of each setting came from, by its key in the configuration file
+    #[serde(default)]
+    pub sources: BTreeMap<String, SettingSource>,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        StartupConfig {
+            port: DEFAULT_PORT,
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            advertise_address: None,
+            instance_path: PathBuf::from("./instance"),
+            orchestrator_url: None,
+            name: SUPERVISOR_DEFAULT_NAME.to_string(),
+            log_level: "info".to_string(),
+            config_file: None,
+            settings: ConfigFile::default(),
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_name() -> String {
+    SUPERVISOR_DEFAULT_NAME.to_string()
+}
+
+impl SupervisorConfig {
+    /// Checks the settings that deserializing does not check.
+    pub fn validate(&self) -> Result<(), String> {
+        parse_name(&self.name).map_err(|e| format!("Invalid name: {}", e))?;
+        self.health_thresholds.validate()?;
+        if let Some(url) = &self.public_base_url {
+            validate_public_base_url(url)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a public base URL is an absolute http(s) URL that paths can be appended to.
+pub fn validate_public_base_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| format!("Invalid publicBaseUrl '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("publicBaseUrl '{}' must be an http or https URL", url));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(format!("publicBaseUrl '{}' must not have a query or fragment", url));
+    }
+    Ok(())
+}
+
+impl Threshold {
+    /// Classifies a metric value against this threshold.
+    pub fn classify(&self, value: f32) -> HealthStatus {
+        if value >= self.critical {
+            HealthStatus::Critical
+        } else if value >= self.degraded {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Ok
+        }
+    }
+}
+
+impl HealthThresholds {
+    /// Checks that every threshold is a finite, non-negative number and that
+    /// the degraded limit does not exceed the critical one.
+    pub fn validate(&self) -> Result<(), String> {
+        let named = [
+            ("cpu", &self.cpu),
+            ("memory", &self.memory),
+            ("disk", &self.disk),
+            ("temperature", &self.temperature),
+            ("logQueue", &self.log_queue),
+        ];
+        for (name, threshold) in named {
+            if !threshold.degraded.is_finite() || !threshold.critical.is_finite()
+                || threshold.degraded < 0.0 || threshold.critical < 0.0 {
+                return Err(format!("Threshold '{}' must be a non-negative number", name));
+            }
+            if threshold.degraded > threshold.critical {
+                return Err(format!("Threshold '{}' has degraded limit above the critical limit", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates the given metrics and returns the worst status found, along with
+    /// a human readable reason for every threshold that was tripped.
+    ///
+    /// Metrics that could not be measured on this device (`None`) are skipped.
+    pub fn evaluate(
+        &self,
+        cpu_usage: f32,
+        memory_usage: f32,
+        disk_usage: Option<f32>,
+        temperature: Option<f32>,
+        log_queue: usize,
+    ) -> (HealthStatus, Vec<String>) {
+        let metrics = [
+            ("cpu", Some(cpu_usage), &self.cpu),
+            ("memory", Some(memory_usage), &self.memory),
+            ("disk", disk_usage, &self.disk),
+            ("temperature", temperature, &self.temperature),
+            ("logQueue", Some(log_queue as f32), &self.log_queue),
+        ];
+
+        let mut status = HealthStatus::Ok;
+        let mut reasons = Vec::new();
+        for (name, value, threshold) in metrics {
+            let Some(value) = value else { continue };
+            let (level, limit) = match threshold.classify(value) {
+                HealthStatus::Ok => continue,
+                HealthStatus::Degraded => ("degraded", threshold.degraded),
+                HealthStatus::Critical => ("critical", threshold.critical),
+            };
+            reasons.push(format!("{} at {} reached {} threshold {}", name, value, level, limit));
+            status = status.max(threshold.classify(value));
+        }
+        (status, reasons)
+    }
+}
@@ -5,9 +5,36 @@
 use actix_web::{test, App, web, http::StatusCode, HttpServer, HttpResponse, Responder, post};
 use serde_json::Value;
 use supervisor::lib::api::*;
+use supervisor::lib::deployment::{Deployment, Endpoint, MountStage, SecretValue};
+use supervisor::lib::wasmtime::{is_module_cached, module_cache_stats, ModuleConfig, MountLayout, MountPermission, WasmtimeRuntime};
+use wasmtime::ValType;
+use supervisor::lib::download::{cache_path, file_sha256, verify_module_artifacts, ArtifactSource};
+use supervisor::lib::maintenance::{collect_orphaned_folders, enforce_result_retention, RetentionPolicy};
+use supervisor::lib::health::{current_health_snapshot, record_health_sample, refresh_health_snapshot, take_health_sample, ExecutionGuard};
+use supervisor::lib::configuration::{public_url, get_supervisor_config, set_startup_config};
+use supervisor::lib::checksum::sidecar_path;
+use supervisor::lib::url_signing::{signature, verify_signature};
+use supervisor::lib::cli::Cli;
+use supervisor::lib::execution::run_on_execution_thread;
+use supervisor::lib::request_history::{archive_requests, prune_request_archive, read_archived_request, RequestHistory};
+use supervisor::lib::shutdown::{drain_executions, finish_execution, in_flight_requests, track_execution};
+use supervisor::lib::self_check::{check_folder, run_checks};
+use supervisor::lib::camera::{camera_enabled, modules_requiring_camera, parse_camera_enabled};
+use supervisor::lib::config_file::ConfigFile;
+use supervisor::lib::zeroconf::WebthingZeroconf;
+use supervisor::lib::settings::{get_setting, get_setting_with_source, is_setting_set, remove_setting, set_setting, setting_source, SettingSource};
+use supervisor::lib::mqtt::run_mqtt_client;
+use supervisor::lib::metrics::{collect_metrics, record_execution, render_line_protocol, run_metrics_reporter};
+use supervisor::lib::actions::{finish_action, register_action, start_action};
+use supervisor::lib::stats::{record_invocation, remove_stats};
+use supervisor::lib::replication::sync_replicas;
+use clap::Parser;
+use supervisor::structs::request_entry::RequestEntry;
+use supervisor::lib::constants::{get_health_refresh_interval, BUNDLE_IMPORT_FOLDER, MODULE_FOLDER, PARAMS_FOLDER, PRELOADED_DEPLOYMENTS_FOLDER};
+use sha2::{Digest, Sha256};
 use log::{debug, info};
 
-use std::{sync::{Arc, Mutex}, env, time::Duration};
+use std::{collections::HashMap, sync::{Arc, Mutex}, env, time::Duration};
 use tokio::time::sleep;
 
 
@@ -21,6 +48,39 @@ mod wasmtime_tests {
     // const DEFAULT_LOGGING_LEVEL: &str = "info"; // Value can be trace/debug/info/warn/error
     type LogStorage = Arc<Mutex<Vec<Value>>>; // Type for storing logs
 
+    /// Builds a deployment of the module "answerer", whose function "answer" returns 42, and
+    /// writes the module to its folder. `extra_endpoint_fields` are added to the endpoint of the
+    /// function, e.g. `{"priority": "low"}`.
+    fn answerer_deployment(deployment_id: &str, extra_endpoint_fields: Value) -> Deployment {
+        let module_path = get_module_path(deployment_id, "answerer");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module (func (export "answer") (result i32) (i32.const 42)))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "answerer", None)).unwrap();
+        let mut endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/answerer/answer", deployment_id),
+            "method": "GET",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        if let (Some(endpoint), Some(extra)) = (endpoint.as_object_mut(), extra_endpoint_fields.as_object()) {
+            endpoint.extend(extra.clone());
+        }
+        Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("answerer-id".to_string(), "answerer".to_string(), module_path, HashMap::new(), None)],
+            HashMap::from([("answerer".to_string(), HashMap::from([("answer".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "answerer": { "answer": { "from": endpoint, "to": null } } })).unwrap(),
+            serde_json::from_value(serde_json::json!({ "answerer": { "answer": { "execution": [] } } })).unwrap(),
+        )
+    }
+
+    /// Adds the deployment of `answerer_deployment` to the supervisor of the process.
+    fn deploy_answerer(deployment_id: &str, extra_endpoint_fields: Value) {
+        insert_deployment(answerer_deployment(deployment_id, extra_endpoint_fields));
+    }
+
     /// Helper function to print test results
     async fn print_test_response(s: &str, status: StatusCode, _body: &[u8]) {
         debug!("Test Case: {}, Status code: {}", s, status);
@@ -133,6 +193,87 @@ mod wasmtime_tests {
         assert_eq!(status, StatusCode::OK);
     }
     
+    #[actix_web::test]
+    async fn api_test_wasmiot_device_description_not_modified() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().route("/.well-known/wasmiot-device-description", web::get().to(wasmiot_device_description))).await;
+        let req = test::TestRequest::get().uri("/.well-known/wasmiot-device-description").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers().get("ETag").expect("ETag header missing").to_str().unwrap().to_string();
+        assert!(resp.headers().get("Cache-Control").is_some());
+
+        let req = test::TestRequest::get()
+            .uri("/.well-known/wasmiot-device-description")
+            .insert_header(("If-None-Match", etag.clone()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+        print_test_response("wasmiot_device_description_not_modified", status, &body).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn api_test_thingi_description_lists_deployed_actions() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let endpoint: Endpoint = serde_json::from_value(serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": "/wot-test-deployment/modules/fibo/fibo",
+            "method": "GET",
+            "request": {
+                "parameters": [
+                    { "name": "param0", "in": "query", "required": true, "schema": { "type": "integer", "format": "int64" } }
+                ],
+                "request_body": null
+            },
+            "response": {
+                "media_type": "application/json",
+                "schema": { "type": "integer" },
+                "encoding": null
+            }
+        })).unwrap();
+        let endpoints = HashMap::from([
+            ("fibo".to_string(), HashMap::from([("fibo".to_string(), endpoint)]))
+        ]);
+        let deployment = Deployment::new(
+            "wot-test-deployment".to_string(),
+            HashMap::new(),
+            vec![],
+            endpoints,
+            HashMap::new(),
+            HashMap::new(),
+        );
+        insert_deployment(deployment);
+        invalidate_description_cache();
+
+        let app = test::init_service(App::new().route("/.well-known/wot-thing-description", web::get().to(thingi_description))).await;
+        let req = test::TestRequest::get().uri("/.well-known/wot-thing-description").to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let body = test::read_body(resp).await;
+        lock_deployments().remove("wot-test-deployment");
+        invalidate_description_cache();
+        print_test_response("thingi_description_lists_deployed_actions", status, &body).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let td: Value = serde_json::from_slice(&body).unwrap();
+        let action = &td["actions"]["wot-test-deployment/fibo/fibo"];
+        assert_eq!(action["forms"][0]["href"], "/wot-test-deployment/modules/fibo/fibo");
+        assert_eq!(action["forms"][0]["htv:methodName"], "GET");
+        assert_eq!(action["input"]["properties"]["param0"]["type"], "integer");
+        assert_eq!(action["output"]["type"], "integer");
+        // Static actions from the base document are kept
+        assert!(td["actions"].get("takePicture").is_some());
+    }
+
     #[actix_web::test]
     async fn api_test_thingi_health() {
         if SUPPRESS_STACKTRACE {
@@ -155,6 +296,333 @@ mod wasmtime_tests {
         // assert!(json_body.get("memoryUsage").is_some());
     }
     
+    #[actix_web::test]
+    async fn api_test_config_patch_updates_health_status() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(
+            App::new()
+                .route("/health", web::get().to(thingi_health))
+                .route("/config", web::get().to(supervisor_config_get))
+                .route("/config", web::patch().to(supervisor_config_patch))
+        ).await;
+
+        let req = test::TestRequest::get().uri("/config").to_request();
+        let original: Value = test::call_and_read_body_json(&app, req).await;
+
+        // Degraded above critical is rejected and leaves the configuration untouched
+        let req = test::TestRequest::patch().uri("/config")
+            .set_json(serde_json::json!({"healthThresholds": {"cpu": {"degraded": 0.9, "critical": 0.5}}}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // A zero CPU threshold is always reached
+        let req = test::TestRequest::patch().uri("/config")
+            .set_json(serde_json::json!({"healthThresholds": {"cpu": {"degraded": 0.0, "critical": 0.0}}}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let patched: Value = test::read_body_json(resp).await;
+        assert_eq!(patched["healthThresholds"]["cpu"]["critical"], 0.0);
+        assert_eq!(patched["healthThresholds"]["memory"], original["healthThresholds"]["memory"]);
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let report: Value = test::read_body_json(resp).await;
+        assert_eq!(report["status"], "critical");
+        assert!(report["reasons"].as_array().unwrap().iter().any(|r| r.as_str().unwrap().starts_with("cpu")));
+
+        let req = test::TestRequest::patch().uri("/config").set_json(original).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn api_test_health_history() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        record_health_sample(take_health_sample());
+        record_health_sample(take_health_sample());
+
+        let app = test::init_service(App::new().route("/health/history", web::get().to(health_history))).await;
+        let req = test::TestRequest::get().uri("/health/history?limit=1").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let samples: Value = test::read_body_json(resp).await;
+        let samples = samples.as_array().unwrap();
+        assert_eq!(samples.len(), 1);
+        assert!(samples[0].get("cpuUsage").is_some());
+        assert!(samples[0].get("inFlightExecutions").is_some());
+
+        let req = test::TestRequest::get().uri("/health/history?since=2999-01-01T00:00:00Z").to_request();
+        let samples: Value = test::call_and_read_body_json(&app, req).await;
+        assert!(samples.as_array().unwrap().is_empty());
+
+        let req = test::TestRequest::get().uri("/health/history?since=yesterday").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_get_hides_secrets() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let mut deployment = Deployment::new(
+            "secrets-test-deployment".to_string(),
+            HashMap::new(),
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        deployment.set_secrets(HashMap::from([
+            ("fetcher".to_string(), HashMap::from([("API_KEY".to_string(), SecretValue::new("hunter2".to_string()))]))
+        ]));
+        insert_deployment(deployment);
+
+        let app = test::init_service(App::new().route("/deploy", web::get().to(deployment_get))).await;
+        let req = test::TestRequest::get().uri("/deploy").to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert!(!String::from_utf8_lossy(&body).contains("hunter2"));
+        let listing: Value = serde_json::from_slice(&body).unwrap();
+        let listed = listing["deployments"].as_array().unwrap().iter()
+            .find(|d| d["id"] == "secrets-test-deployment").unwrap();
+        assert_eq!(listed["secret_names"]["fetcher"][0], "API_KEY");
+        assert!(listed["needsSecrets"].as_array().unwrap().is_empty());
+
+        // Secrets are lost on a restart, leaving only their names
+        get_deployment("secrets-test-deployment").unwrap().lock().await.secrets.clear();
+        let req = test::TestRequest::get().uri("/deploy").to_request();
+        let listing: Value = test::call_and_read_body_json(&app, req).await;
+        lock_deployments().remove("secrets-test-deployment");
+        let listed = listing["deployments"].as_array().unwrap().iter()
+            .find(|d| d["id"] == "secrets-test-deployment").unwrap();
+        assert_eq!(listed["needsSecrets"][0], "fetcher");
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_expiry() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let mut deployment = Deployment::new(
+            "expiry-test-deployment".to_string(),
+            HashMap::new(),
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        deployment.expires_at = Some(chrono::Utc::now() - chrono::Duration::seconds(1));
+        insert_deployment(deployment);
+
+        let app = test::init_service(App::new().route("/deploy/{deployment_id}", web::get().to(deployment_get_by_id))).await;
+        let req = test::TestRequest::get().uri("/deploy/expiry-test-deployment").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // Expiry waits for executions in flight to finish
+        let guard = ExecutionGuard::new("expiry-test-deployment");
+        expire_deployments().await;
+        assert!(lock_deployments().contains_key("expiry-test-deployment"));
+        drop(guard);
+        expire_deployments().await;
+        assert!(!lock_deployments().contains_key("expiry-test-deployment"));
+
+        let req = test::TestRequest::get().uri("/deploy/expiry-test-deployment").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::GONE);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["expiredAt"].is_string());
+
+        let req = test::TestRequest::get().uri("/deploy/never-existed-deployment").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_pause_and_resume() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment = Deployment::new(
+            "pause-test-deployment".to_string(),
+            HashMap::new(),
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        insert_deployment(deployment);
+
+        let app = test::init_service(
+            App::new()
+                .route("/deploy/{deployment_id}/pause", web::post().to(deployment_pause))
+                .route("/deploy/{deployment_id}/resume", web::post().to(deployment_resume))
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+        ).await;
+        let req = test::TestRequest::post().uri("/deploy/pause-test-deployment/pause?dropRuntimes=true").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["active"], false);
+
+        // The saved state survives a restart
+        let saved: Value = serde_json::from_str(&std::fs::read_to_string(get_deployment_path("pause-test-deployment")).unwrap()).unwrap();
+        assert_eq!(saved["active"], false);
+
+        let req = test::TestRequest::get().uri("/pause-test-deployment/modules/fibo/fibo").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::LOCKED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "deployment paused");
+
+        let req = test::TestRequest::post().uri("/deploy/pause-test-deployment/resume").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["active"], true);
+        assert!(get_deployment("pause-test-deployment").unwrap().lock().await.active);
+
+        lock_deployments().remove("pause-test-deployment");
+        std::fs::remove_file(get_deployment_path("pause-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_export_and_import() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let binary_path = get_module_path("bundle-test-deployment", "echo");
+        let data_path = get_params_path("bundle-test-deployment", "echo", Some("model.bin"));
+        std::fs::create_dir_all(binary_path.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(data_path.parent().unwrap()).unwrap();
+        std::fs::write(&binary_path, b"\0asm not really a module").unwrap();
+        std::fs::write(&data_path, b"model weights").unwrap();
+        let config = ModuleConfig::new(
+            "echo-id".to_string(),
+            "echo".to_string(),
+            binary_path.clone(),
+            HashMap::from([("model".to_string(), data_path.to_string_lossy().to_string())]),
+            None,
+        );
+        let deployment = Deployment::new(
+            "bundle-test-deployment".to_string(),
+            HashMap::new(),
+            vec![config],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        insert_deployment(deployment);
+
+        let app = test::init_service(
+            App::new()
+                .route("/deploy/{deployment_id}/export", web::get().to(deployment_export))
+                .route("/deploy/import", web::post().to(deployment_import))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+        ).await;
+        let req = test::TestRequest::get().uri("/deploy/bundle-test-deployment/export").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bundle = test::read_body(resp).await;
+
+        // Restore the deployment from the bundle after it has been deleted
+        let req = test::TestRequest::delete().uri("/deploy/bundle-test-deployment").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        assert!(!binary_path.exists());
+        let req = test::TestRequest::post().uri("/deploy/import").set_payload(bundle).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(std::fs::read(&binary_path).unwrap(), b"\0asm not really a module");
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"model weights");
+
+        // Anything else than a valid bundle is refused
+        let req = test::TestRequest::post().uri("/deploy/import").set_payload("not a bundle").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::delete().uri("/deploy/bundle-test-deployment").to_request();
+        test::call_service(&app, req).await;
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_with_missing_files_is_degraded() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let config = ModuleConfig::new(
+            "lost-id".to_string(),
+            "lost".to_string(),
+            get_module_path("degraded-test-deployment", "lost"),
+            HashMap::new(),
+            None,
+        );
+        // Without a known source the lost binary cannot be restored
+        let errors = verify_module_artifacts(&config).await;
+        assert_eq!(errors.len(), 1);
+        let mut deployment = Deployment::new(
+            "degraded-test-deployment".to_string(),
+            HashMap::new(),
+            vec![config],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        deployment.missing_files = errors;
+        insert_deployment(deployment);
+
+        let app = test::init_service(
+            App::new()
+                .route("/deploy/{deployment_id}", web::get().to(deployment_get_by_id))
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+        ).await;
+        let req = test::TestRequest::get().uri("/deploy/degraded-test-deployment").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["degraded"], true);
+        assert_eq!(body["missing_files"].as_array().unwrap().len(), 1);
+
+        let req = test::TestRequest::get().uri("/degraded-test-deployment/modules/lost/run").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["missingFiles"][0].as_str().unwrap().contains("lost"));
+        lock_deployments().remove("degraded-test-deployment");
+    }
+
+    #[actix_web::test]
+    async fn api_test_maintenance_gc() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let orphan = MODULE_FOLDER.join("gc-orphan-test-deployment");
+        std::fs::create_dir_all(&orphan).unwrap();
+        std::fs::write(orphan.join("orphan.wasm"), b"left behind").unwrap();
+
+        // A dry run lists the orphaned folder without deleting it
+        let report = collect_orphaned_folders(&std::collections::HashSet::new(), Duration::ZERO, true);
+        let candidate = report.candidates.iter().find(|c| c.path == orphan).expect("orphan not listed");
+        assert_eq!(candidate.bytes, 11);
+        assert_eq!(report.directories, 0);
+        assert!(orphan.exists());
+
+        // Folders younger than the grace age are left alone
+        let app = test::init_service(App::new().route("/maintenance/gc", web::post().to(maintenance_gc))).await;
+        let req = test::TestRequest::post().uri("/maintenance/gc").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["dryRun"], false);
+        assert!(orphan.exists());
+        std::fs::remove_dir_all(&orphan).ok();
+    }
+
     #[actix_web::test]
     async fn api_test_get_module_result() {
         if SUPPRESS_STACKTRACE {
@@ -164,13 +632,18 @@ mod wasmtime_tests {
         if INITIAL_WAIT {
             sleep(Duration::from_secs(2)).await;
         }
-        let app = test::init_service(App::new().route("/module_results/{module_name}/{filename}", web::get().to(get_module_result))).await;
-        let req = test::TestRequest::get().uri("/module_results/test_module/test_file").to_request();
+        let path = get_params_path("results-test-deployment", "test_module", Some("test_file"));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "result").unwrap();
+        let app = test::init_service(App::new().route("/module_results/{deployment_id}/{module_name}/{filename}", web::get().to(get_module_result))).await;
+        let req = test::TestRequest::get().uri("/module_results/results-test-deployment/test_module/test_file").to_request();
         let resp = test::call_service(&app, req).await;
         let status = resp.status();
         let body = test::read_body(resp).await;
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("results-test-deployment")).ok();
         print_test_response("get_module_results", status, &body).await;
         assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "result");
     }
     
     #[actix_web::test]
@@ -255,12 +728,5761 @@ mod wasmtime_tests {
             sleep(Duration::from_secs(2)).await;
         }
         let app = test::init_service(App::new().route("/deploy", web::post().to(deployment_create))).await;
-        let req = test::TestRequest::post().uri("/deploy").to_request();
+        let req = test::TestRequest::post().uri("/deploy?wait=true").to_request();
         let resp = test::call_service(&app, req).await;
         let status = resp.status();
         let body = test::read_body(resp).await;
         print_test_response("deployment_create", status, &body).await;
         assert_eq!(status, StatusCode::OK);
     }
+
+    #[actix_web::test]
+    async fn api_test_deployment_create_rejects_oversized_files() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().route("/deploy", web::post().to(deployment_create))).await;
+        // The declared size is used instead of a HEAD request, so nothing is fetched
+        let manifest = serde_json::json!({
+            "deploymentId": "oversized-test-deployment",
+            "modules": [{
+                "id": "oversized",
+                "name": "oversized",
+                "urls": {
+                    "binary": { "url": "http://127.0.0.1:9/oversized.wasm", "size": u64::MAX }
+                }
+            }]
+        });
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest.clone()).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["error"].as_str().unwrap().contains("oversized"));
+
+        // The failed deployment is rolled back unless asked to keep the partial files
+        let module_dir = MODULE_FOLDER.join("oversized-test-deployment");
+        assert!(!module_dir.exists());
+        let req = test::TestRequest::post().uri("/deploy?wait=true&keepPartial=true").set_json(manifest).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(module_dir.exists());
+        std::fs::remove_dir_all(&module_dir).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("oversized-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_create_rejects_bad_manifest_signature() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().route("/deploy", web::post().to(deployment_create))).await;
+        let manifest = serde_json::json!({
+            "deploymentId": "signed-test-deployment",
+            "signature": "00".repeat(64),
+            "modules": [{
+                "id": "signed",
+                "name": "signed",
+                "urls": { "binary": "http://127.0.0.1:9/signed.wasm" }
+            }]
+        });
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["artifact"], "manifest");
+        // Nothing is written to disk for a rejected deployment
+        assert!(!MODULE_FOLDER.join("signed-test-deployment").exists());
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_create_rejects_local_files_outside_allowed_dir() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        std::fs::create_dir_all(&*PRELOADED_DEPLOYMENTS_FOLDER).unwrap();
+        let app = test::init_service(App::new().route("/deploy", web::post().to(deployment_create))).await;
+        let outside = std::fs::canonicalize("Cargo.toml").unwrap();
+        let manifest = serde_json::json!({
+            "deploymentId": "local-file-test-deployment",
+            "modules": [{
+                "id": "local",
+                "name": "local",
+                "urls": {
+                    "binary": { "localPath": outside.to_str().unwrap() }
+                }
+            }]
+        });
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["details"][0]["error"].as_str().unwrap().contains("outside"));
+        assert!(!MODULE_FOLDER.join("local-file-test-deployment").exists());
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_create_with_read_only_mounts() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let local_dir = PRELOADED_DEPLOYMENTS_FOLDER.join("read-only-test");
+        std::fs::create_dir_all(&local_dir).unwrap();
+        std::fs::write(local_dir.join("module.wasm"), b"\0asm not really a module").unwrap();
+        std::fs::write(local_dir.join("model.bin"), b"model weights").unwrap();
+        let app = test::init_service(
+            App::new()
+                .route("/deploy", web::post().to(deployment_create))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+        ).await;
+        let mut manifest = serde_json::json!({
+            "deploymentId": "read-only-test-deployment",
+            "modules": [{
+                "id": "reader",
+                "name": "reader",
+                "permissions": { "deployment": "read", "execution": "read" },
+                "urls": {
+                    "binary": { "localPath": local_dir.join("module.wasm").to_str().unwrap() },
+                    "other": { "model": { "localPath": local_dir.join("model.bin").to_str().unwrap() } }
+                }
+            }]
+        });
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest.clone()).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let permissions = get_deployment("read-only-test-deployment").unwrap().lock().await.modules["reader"].permissions.clone();
+        assert_eq!(permissions.deployment, MountPermission::Read);
+        assert_eq!(permissions.output, MountPermission::ReadWrite);
+        let model_path = get_params_path("read-only-test-deployment", "reader", Some("model"));
+        assert!(std::fs::metadata(&model_path).unwrap().permissions().readonly());
+
+        // Deploying again replaces the read-only file
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest.clone()).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        // Unknown permission values are refused
+        manifest["deploymentId"] = serde_json::json!("bad-permissions-test-deployment");
+        manifest["modules"][0]["permissions"] = serde_json::json!({ "deployment": "none" });
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["details"][0]["error"].as_str().unwrap().contains("Invalid permissions"));
+
+        let req = test::TestRequest::delete().uri("/deploy/read-only-test-deployment").to_request();
+        test::call_service(&app, req).await;
+        std::fs::remove_dir_all(&local_dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_create_in_background() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let local_dir = PRELOADED_DEPLOYMENTS_FOLDER.join("background-test");
+        std::fs::create_dir_all(&local_dir).unwrap();
+        std::fs::write(local_dir.join("module.wasm"), b"\0asm not really a module").unwrap();
+        let app = test::init_service(
+            App::new()
+                .route("/deploy", web::post().to(deployment_create))
+                .route("/deploy/{deployment_id}/status", web::get().to(deployment_status))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+        ).await;
+        let manifest = serde_json::json!({
+            "deploymentId": "background-test-deployment",
+            "modules": [{
+                "id": "background",
+                "name": "background",
+                "urls": { "binary": { "localPath": local_dir.join("module.wasm").to_str().unwrap() } }
+            }]
+        });
+        let req = test::TestRequest::post().uri("/deploy").set_json(manifest).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["deploymentId"], "background-test-deployment");
+        let status_url = "/deploy/background-test-deployment/status";
+        assert!(body["statusUrl"].as_str().unwrap().ends_with(status_url));
+
+        // Poll until the background task has finished
+        let mut progress = Value::Null;
+        for _ in 0..100 {
+            let req = test::TestRequest::get().uri(status_url).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            progress = test::read_body_json(resp).await;
+            if progress["phase"] == "completed" || progress["phase"] == "failed" {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(progress["phase"], "completed", "{}", progress);
+        assert_eq!(progress["filesDone"], 1);
+        assert_eq!(progress["filesTotal"], 1);
+        assert_eq!(progress["statusCode"], 200);
+        assert!(lock_deployments().contains_key("background-test-deployment"));
+
+        let req = test::TestRequest::get().uri("/deploy/never-created-deployment/status").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::delete().uri("/deploy/background-test-deployment").to_request();
+        test::call_service(&app, req).await;
+        std::fs::remove_dir_all(&local_dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_get_module_result_per_request() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Outputs of two requests writing a file with the same name
+        for (request_id, content) in [("first-request", "first"), ("second-request", "second")] {
+            let path = get_output_path("outputs-test-deployment", "writer", request_id, Some("result.txt"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, content).unwrap();
+            sleep(Duration::from_millis(20)).await;
+        }
+        let app = test::init_service(
+            App::new()
+                .route("/module_results/{deployment_id}/{module_name}/{request_id}/{filename}", web::get().to(get_request_result))
+                .route("/module_results/{deployment_id}/{module_name}/{filename}", web::get().to(get_module_result))
+        ).await;
+
+        // Each request keeps serving its own file
+        for (request_id, content) in [("first-request", "first"), ("second-request", "second")] {
+            let uri = format!("/module_results/outputs-test-deployment/writer/{}/result.txt", request_id);
+            let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(test::read_body(resp).await, content);
+        }
+
+        // The flat URL of earlier versions redirects to the latest request's file
+        let req = test::TestRequest::get().uri("/module_results/outputs-test-deployment/writer/result.txt").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+        let location = resp.headers().get("location").unwrap().to_str().unwrap();
+        assert!(location.ends_with("/module_results/outputs-test-deployment/writer/second-request/result.txt"), "{}", location);
+
+        let req = test::TestRequest::get().uri("/module_results/outputs-test-deployment/writer/..%2F..%2Fresult.txt/result.txt").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("outputs-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_result_retention() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let model_path = get_params_path("retention-test-deployment", "writer", Some("model.bin"));
+        std::fs::create_dir_all(model_path.parent().unwrap()).unwrap();
+        std::fs::write(&model_path, vec![0u8; 1000]).unwrap();
+        for request_id in ["recent-request", "old-request", "new-request"] {
+            let path = get_output_path("retention-test-deployment", "writer", request_id, Some("out.bin"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, vec![0u8; 100]).unwrap();
+            sleep(Duration::from_millis(20)).await;
+        }
+
+        // Over the cap, the oldest output goes first unless its request is still in the history window
+        let policy = RetentionPolicy { max_age: None, max_bytes: None, max_deployment_bytes: Some(250) };
+        let preferred = std::collections::HashSet::from(["recent-request".to_string()]);
+        let report = enforce_result_retention(&policy, &preferred);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.deleted_files, 1);
+        assert_eq!(report.reclaimed_bytes, 100);
+        assert!(!get_output_path("retention-test-deployment", "writer", "old-request", None).exists());
+        assert!(get_output_path("retention-test-deployment", "writer", "recent-request", Some("out.bin")).exists());
+        assert!(get_output_path("retention-test-deployment", "writer", "new-request", Some("out.bin")).exists());
+        // Files given at deployment time are never removed
+        assert!(model_path.exists());
+
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("retention-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_public_base_url() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().route("/config", web::patch().to(supervisor_config_patch))).await;
+        let patch = |body: Value| test::TestRequest::patch().uri("/config").set_json(body).to_request();
+
+        let resp = test::call_service(&app, patch(serde_json::json!({ "publicBaseUrl": "https://proxy.example/device1/" }))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(public_url("/request-history/abc"), "https://proxy.example/device1/request-history/abc");
+
+        let resp = test::call_service(&app, patch(serde_json::json!({ "publicBaseUrl": "ftp://proxy.example" }))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(public_url("request-history/abc"), "https://proxy.example/device1/request-history/abc");
+
+        // Without the setting, links are made of the scheme, address and port as before
+        let resp = test::call_service(&app, patch(serde_json::json!({ "publicBaseUrl": null }))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(!public_url("/request-history/abc").starts_with("https://proxy.example"));
+        assert!(public_url("/request-history/abc").ends_with("/request-history/abc"));
+    }
+
+    #[actix_web::test]
+    async fn api_test_get_module_result_legacy() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let module = |deployment_id: &str, name: &str| ModuleConfig::new(
+            format!("{}-id", name),
+            name.to_string(),
+            get_module_path(deployment_id, name),
+            HashMap::new(),
+            None,
+        );
+        for (deployment_id, modules) in [
+            ("legacy-results-a", vec![module("legacy-results-a", "legacy_writer"), module("legacy-results-a", "legacy_shared")]),
+            ("legacy-results-b", vec![module("legacy-results-b", "legacy_shared")]),
+        ] {
+            let deployment = Deployment::new(deployment_id.to_string(), HashMap::new(), modules, HashMap::new(), HashMap::new(), HashMap::new());
+            insert_deployment(deployment);
+        }
+        let path = get_params_path("legacy-results-a", "legacy_writer", Some("out.txt"));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "legacy").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .route("/module_results/{deployment_id}/{module_name}/{filename}", web::get().to(get_module_result))
+                .route("/module_results/{module_name}/{filename}", web::get().to(get_module_result_legacy))
+        ).await;
+
+        // The module is in one deployment only, so both URL forms serve the same file
+        for uri in ["/module_results/legacy_writer/out.txt", "/module_results/legacy-results-a/legacy_writer/out.txt"] {
+            let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+            assert_eq!(test::read_body(resp).await, "legacy");
+        }
+
+        // A module name used by several deployments cannot be resolved
+        let req = test::TestRequest::get().uri("/module_results/legacy_shared/out.txt").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["deployments"], serde_json::json!(["legacy-results-a", "legacy-results-b"]));
+
+        let req = test::TestRequest::get().uri("/module_results/legacy_missing/out.txt").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        for deployment_id in ["legacy-results-a", "legacy-results-b"] {
+            lock_deployments().remove(deployment_id);
+        }
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("legacy-results-a")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_module_result_meta() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let path = get_output_path("meta-test-deployment", "writer", "meta-request", Some("result.json"));
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "{\"value\": 1}").unwrap();
+        // sha256 of the contents above
+        let expected = "e1d70a18cc129fcc812ebbe309bc5197df6ffa2228c77a4a7b98653ec5605354";
+
+        let app = test::init_service(
+            App::new()
+                .route("/module_results/{deployment_id}/{module_name}/{request_id}/{filename}/meta", web::get().to(get_request_result_meta))
+                .route("/module_results/{deployment_id}/{module_name}/{filename}/meta", web::get().to(get_module_result_meta))
+                .route("/module_results/{deployment_id}/{module_name}/{request_id}/{filename}", web::get().to(get_request_result))
+        ).await;
+
+        // The flat form resolves to the latest request's file
+        for uri in [
+            "/module_results/meta-test-deployment/writer/result.json/meta",
+            "/module_results/meta-test-deployment/writer/meta-request/result.json/meta",
+        ] {
+            let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::OK, "{}", uri);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["size"], 12);
+            assert_eq!(body["contentType"], "application/json");
+            assert_eq!(body["sha256"], expected);
+            assert_eq!(body["requestId"], "meta-request");
+        }
+        // The digest is cached next to the file
+        assert!(sidecar_path(&path).exists());
+
+        // The download carries the same digest
+        let uri = "/module_results/meta-test-deployment/writer/meta-request/result.json";
+        let resp = test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers().get("etag").unwrap().to_str().unwrap().to_string();
+        assert_eq!(etag, format!("\"{}\"", expected));
+        assert!(resp.headers().get("digest").unwrap().to_str().unwrap().starts_with("sha-256="));
+        let req = test::TestRequest::get().uri(uri).insert_header(("If-None-Match", etag.as_str())).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_MODIFIED);
+
+        // A changed file is hashed again
+        sleep(Duration::from_millis(20)).await;
+        std::fs::write(&path, "{\"value\": 22}").unwrap();
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/module_results/meta-test-deployment/writer/result.json/meta").to_request()).await;
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["size"], 13);
+        assert_ne!(body["sha256"], expected);
+
+        let req = test::TestRequest::get().uri("/module_results/meta-test-deployment/writer/missing.json/meta").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("meta-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_request_history_compressed() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Failed requests end up in the history as well, which is enough to make it large
+        for i in 0..50 {
+            let entry = RequestEntry::new(
+                "compress-test-deployment".to_string(),
+                "compress".to_string(),
+                "run".to_string(),
+                "GET".to_string(),
+                serde_json::json!({ "index": i, "padding": "x".repeat(200) }),
+                HashMap::new(),
+                chrono::Utc::now(),
+            );
+            make_history(entry).await;
+        }
+        let app = test::init_service(
+            App::new()
+                .wrap(actix_web::middleware::Compress::default())
+                .route("/request-history", web::get().to(request_history_list_1))
+        ).await;
+
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/request-history").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let plain = test::read_body(resp).await;
+
+        let req = test::TestRequest::get().uri("/request-history").insert_header(("Accept-Encoding", "gzip")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("content-encoding").unwrap(), "gzip");
+        let compressed = test::read_body(resp).await;
+        assert!(compressed.len() < plain.len() / 2, "{} >= {} / 2", compressed.len(), plain.len());
+
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&compressed[..]), &mut decoded).unwrap();
+        // Other tests may add to the history in between, so only the entries added here are compared
+        let entries = |history: Value| -> Vec<Value> {
+            history.as_array().unwrap().iter()
+                .filter(|entry| entry["deploymentId"] == "compress-test-deployment")
+                .cloned()
+                .collect()
+        };
+        let decoded = entries(serde_json::from_str(&decoded).unwrap());
+        assert_eq!(decoded.len(), 50);
+        assert_eq!(decoded, entries(serde_json::from_slice(&plain).unwrap()));
+    }
+
+    #[actix_web::test]
+    async fn api_test_inline_output() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let small = get_output_path("inline-test-deployment", "camera", "inline-request", Some("thermal.png"));
+        std::fs::create_dir_all(small.parent().unwrap()).unwrap();
+        std::fs::write(&small, [0x89, b'P', b'N', b'G', 0, 1, 2, 3]).unwrap();
+        let large = get_output_path("inline-test-deployment", "camera", "inline-request", Some("frame.png"));
+        std::fs::write(&large, vec![0u8; 64 * 1024]).unwrap();
+
+        // Small outputs are included with base64 content
+        let inline = inline_output("inline-test-deployment", "camera", "inline-request", "thermal.png", "image/png").unwrap();
+        assert_eq!(inline["filename"], "thermal.png");
+        assert_eq!(inline["contentType"], "image/png");
+        assert_eq!(inline["size"], 8);
+        assert_eq!(inline["encoding"], "base64");
+        assert_eq!(inline["content"], "iVBORwABAgM=");
+
+        // Large and missing outputs are only available by their URL
+        assert!(inline_output("inline-test-deployment", "camera", "inline-request", "frame.png", "image/png").is_none());
+        assert!(inline_output("inline-test-deployment", "camera", "inline-request", "missing.png", "image/png").is_none());
+
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("inline-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_signed_result_urls() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let secret = b"device-secret";
+        let path = "/module_results/camera-deployment/camera/some-request/image.jpg";
+        let now = chrono::Utc::now().timestamp();
+        let sig = signature(secret, path, now + 60).unwrap();
+        let query = format!("exp={}&sig={}", now + 60, sig);
+
+        assert!(verify_signature(secret, path, &query, now).is_ok());
+        // The signature covers the path, the expiry and the secret
+        assert!(verify_signature(secret, "/module_results/camera-deployment/camera/other-request/image.jpg", &query, now).is_err());
+        assert!(verify_signature(secret, path, &format!("exp={}&sig={}", now + 120, sig), now).is_err());
+        assert!(verify_signature(b"other-secret", path, &query, now).is_err());
+        // Expired and unsigned URLs are refused
+        assert_eq!(verify_signature(secret, path, &query, now + 61), Err("Signed URL has expired".to_string()));
+        assert_eq!(verify_signature(secret, path, "", now), Err("URL is not signed".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn api_test_mirror_chained_outputs() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // A supervisor further down the chain, serving the outputs of its request
+        let server = HttpServer::new(|| {
+            App::new().route("/module_results/remote/{module_name}/{request_id}/{filename}", web::get().to(
+                |path: web::Path<(String, String, String)>| async move {
+                    match path.into_inner().2.as_str() {
+                        "detections.json" => HttpResponse::Ok().body("{\"count\": 3}"),
+                        "frame.jpg" => HttpResponse::Ok().body(vec![0xffu8; 4096]),
+                        _ => HttpResponse::NotFound().finish(),
+                    }
+                }
+            ))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let remote = |filename: &str| format!("http://{}/module_results/remote/detector/remote-request/{}", address, filename);
+        let client = reqwest::Client::new();
+        let urls = vec![remote("detections.json"), remote("frame.jpg")];
+        let local = mirror_outputs(&client, "mirror-test-deployment", "camera", "mirror-request", &urls, &mut 0).await.unwrap();
+        assert_eq!(local.len(), 2);
+        assert!(local[0].ends_with("/module_results/mirror-test-deployment/camera/mirror-request/detections.json"), "{}", local[0]);
+        let copied = get_output_path("mirror-test-deployment", "camera", "mirror-request", Some("detections.json"));
+        assert_eq!(std::fs::read_to_string(copied).unwrap(), "{\"count\": 3}");
+        let copied = get_output_path("mirror-test-deployment", "camera", "mirror-request", Some("frame.jpg"));
+        assert_eq!(std::fs::metadata(copied).unwrap().len(), 4096);
+
+        // Nothing is kept if any of the outputs cannot be copied
+        let urls = vec![remote("detections.json"), remote("missing.png")];
+        assert!(mirror_outputs(&client, "mirror-test-deployment", "camera", "failed-request", &urls, &mut 0).await.is_err());
+        assert!(!get_output_path("mirror-test-deployment", "camera", "failed-request", Some("detections.json")).exists());
+
+        handle.stop(false).await;
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("mirror-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_command_line_arguments() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let cli = Cli::try_parse_from([
+            "supervisor", "--port", "9000", "--name", "camera-1",
+            "--orchestrator-url", "http://orchestrator.local:3000", "--log-level", "warn,supervisor=debug",
+        ]).unwrap();
+        let startup = cli.startup_config();
+        assert_eq!(startup.port, 9000);
+        assert_eq!(startup.name, "camera-1");
+        assert_eq!(startup.orchestrator_url.as_deref(), Some("http://orchestrator.local:3000"));
+        assert_eq!(startup.log_level, "warn,supervisor=debug");
+        assert!(startup.bind_address.is_unspecified());
+        assert_eq!(startup.advertise_address, None);
+
+        // Loopback can always be listened on, and is advertised only if asked to
+        let cli = Cli::try_parse_from([
+            "supervisor", "--bind-address", "127.0.0.1", "--advertise-address", "192.0.2.10",
+        ]).unwrap();
+        let startup = cli.startup_config();
+        assert_eq!(startup.bind_address.to_string(), "127.0.0.1");
+        assert_eq!(startup.advertise_address.map(|a| a.to_string()).as_deref(), Some("192.0.2.10"));
+        assert_eq!(startup.settings.network.bind_address.as_deref(), Some("127.0.0.1"));
+
+        // Addresses not assigned to this device are refused with the ones that are
+        let err = Cli::try_parse_from(["supervisor", "--bind-address", "203.0.113.77"]).unwrap_err().to_string();
+        assert!(err.contains("0.0.0.0"), "{}", err);
+
+        // Invalid values are refused instead of falling back to the defaults
+        for args in [
+            vec!["supervisor", "--port", "80a"],
+            vec!["supervisor", "--port", "0"],
+            vec!["supervisor", "--orchestrator-url", "orchestrator.local"],
+            vec!["supervisor", "--log-level", "loud"],
+            vec!["supervisor", "--name", " "],
+            vec!["supervisor", "--bind-address", "nonsense"],
+            vec!["supervisor", "--advertise-address", "camera.local"],
+        ] {
+            assert!(Cli::try_parse_from(&args).is_err(), "{:?}", args);
+        }
+
+        // The startup settings are shown but cannot be changed through the configuration endpoint
+        let app = test::init_service(App::new().route("/config", web::patch().to(supervisor_config_patch))).await;
+        let req = test::TestRequest::patch().uri("/config").set_json(serde_json::json!({ "startup": { "port": 1 } })).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn api_test_config_file() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let contents = r#"
+[supervisor]
+name = "camera-1"
+colour = "blue"
+
+[network]
+port = 3005
+
+[limits]
+max_file_bytes = 10_000_000
+
+[capabilities]
+trusted_public_keys = ["aa", "bb"]
+
+[extras]
+enabled = true
+"#;
+        let (config, unknown) = ConfigFile::parse(contents).unwrap();
+        assert_eq!(config.supervisor.name.as_deref(), Some("camera-1"));
+        assert_eq!(config.network.port, Some(3005));
+        assert_eq!(config.limits.max_file_bytes, Some(10_000_000));
+        assert_eq!(config.orchestrator.url, None);
+        assert_eq!(unknown, vec!["extras".to_string(), "supervisor.colour".to_string()]);
+
+        // Settings are mapped to the environment variables the supervisor reads
+        let values = config.env_values();
+        assert!(values.contains(&("WASMIOT_SUPERVISOR_PORT", "3005".to_string())));
+        assert!(values.contains(&("WASMIOT_MAX_FILE_BYTES", "10000000".to_string())));
+        assert!(values.contains(&("WASMIOT_TRUSTED_PUBLIC_KEYS", "aa,bb".to_string())));
+
+        // Values of the wrong type are refused with the line they are on
+        let err = ConfigFile::parse("[network]\nport = \"eighty\"\n").unwrap_err();
+        assert!(err.contains("line 2"), "{}", err);
+        let err = ConfigFile::parse("[orchestrator]\nurl = \"orchestrator.local\"\n").unwrap_err();
+        assert!(err.contains("orchestrator.url"), "{}", err);
+        assert!(ConfigFile::parse("[network]\nport = 0\n").is_err());
+        assert!(ConfigFile::parse("[network\n").is_err());
+    }
+
+    #[actix_web::test]
+    async fn api_test_config_reload() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let path = env::temp_dir().join("supervisor-reload-test.toml");
+        let original = get_supervisor_config().startup;
+        let mut startup = original.clone();
+        startup.config_file = Some(path.clone());
+        set_startup_config(startup);
+        let port_given = is_setting_set("WASMIOT_SUPERVISOR_PORT");
+        let port_before = get_setting("WASMIOT_SUPERVISOR_PORT");
+
+        let app = test::init_service(App::new().route("/config/reload", web::post().to(supervisor_config_reload))).await;
+
+        // Changed settings are applied, except the ones used when the server starts
+        std::fs::write(&path, "[camera]\ndevice = 3\nlens = \"wide\"\n\n[network]\nport = 4321\n").unwrap();
+        let req = test::TestRequest::post().uri("/config/reload").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let report: Value = test::read_body_json(resp).await;
+        assert_eq!(report["applied"], serde_json::json!(["camera.device"]));
+        assert_eq!(report["unknownKeys"], serde_json::json!(["camera.lens"]));
+        if !port_given {
+            assert_eq!(report["requiresRestart"], serde_json::json!(["network.port"]));
+        }
+        assert_eq!(get_setting("DEFAULT_CAMERA_DEVICE").as_deref(), Some("3"));
+        assert_eq!(get_setting("WASMIOT_SUPERVISOR_PORT"), port_before);
+        assert_eq!(get_supervisor_config().startup.settings.camera.device, Some(3));
+
+        // An invalid file leaves the settings as they were
+        std::fs::write(&path, "[camera]\ndevice = \"front\"\n").unwrap();
+        let req = test::TestRequest::post().uri("/config/reload").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(get_setting("DEFAULT_CAMERA_DEVICE").as_deref(), Some("3"));
+
+        // Settings removed from the file go back to their defaults
+        std::fs::write(&path, "").unwrap();
+        let req = test::TestRequest::post().uri("/config/reload").to_request();
+        let report: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(report["applied"], serde_json::json!(["camera.device"]));
+        assert!(!is_setting_set("DEFAULT_CAMERA_DEVICE"));
+
+        set_startup_config(original);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[actix_web::test]
+    async fn api_test_execution_threads() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Executions run on threads of their own, and their futures do not need to be Send
+        let (thread_name, value) = run_on_execution_thread(|| async {
+            let value = std::rc::Rc::new(21);
+            sleep(Duration::from_millis(10)).await;
+            (std::thread::current().name().map(str::to_string), *value * 2)
+        }).await.unwrap();
+        assert!(thread_name.unwrap_or_default().starts_with("wasm-execution-"));
+        assert_eq!(value, 42);
+
+        // A panicking execution is reported as an error, and the threads keep running
+        let result = run_on_execution_thread(|| async { panic!("execution failed") }).await;
+        assert!(result.is_err());
+        assert_eq!(run_on_execution_thread(|| async { 1 }).await, Ok(1));
+    }
+
+    #[actix_web::test]
+    async fn api_test_self_check() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let dir = env::temp_dir().join(format!("supervisor-self-check-{}", std::process::id()));
+        let folder = dir.join("instance").join("modules");
+        assert!(check_folder(&folder).is_ok());
+        assert_eq!(std::fs::read_dir(&folder).unwrap().count(), 0, "the probe file is removed");
+
+        // A folder that cannot be created is reported by its path
+        let blocked = dir.join("file");
+        std::fs::write(&blocked, b"not a folder").unwrap();
+        let err = check_folder(&blocked.join("modules")).unwrap_err();
+        assert!(err.contains(&blocked.display().to_string()), "{}", err);
+
+        let report = run_checks(false);
+        assert!(report.ok, "{:?}", report);
+        assert!(report.checks.iter().any(|check| check.name == "wasm engine" && check.ok));
+        assert!(report.checks.iter().all(|check| check.name != "camera"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_setting_sources() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // The environment of the process is read as is
+        let (_, source) = env::vars().next().and_then(|(name, _)| get_setting_with_source(&name)).unwrap();
+        assert_eq!(source, SettingSource::Environment);
+
+        set_setting("WASMIOT_TEST_SOURCE", "1", SettingSource::ConfigFile);
+        assert_eq!(setting_source("WASMIOT_TEST_SOURCE"), SettingSource::ConfigFile);
+        assert_eq!(setting_source("WASMIOT_TEST_UNSET_SOURCE"), SettingSource::Default);
+        assert!(!SettingSource::ConfigFile.is_external());
+        assert!(SettingSource::EnvFile.is_external() && SettingSource::CommandLine.is_external());
+        assert_eq!(SettingSource::EnvFile.to_string(), ".env");
+        assert_eq!(serde_json::to_value(SettingSource::CommandLine).unwrap(), serde_json::json!("commandLine"));
+
+        // Every setting has a source, shown with the startup settings
+        let startup = Cli::try_parse_from(["supervisor"]).unwrap().startup_config();
+        assert_eq!(startup.sources.len(), ConfigFile::KEYS.len());
+        let config = serde_json::to_value(&startup).unwrap();
+        assert!(config["sources"]["network.port"].is_string(), "{}", config);
+    }
+
+    #[actix_web::test]
+    async fn api_test_camera_toggle() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        assert_eq!(parse_camera_enabled("true"), Ok(Some(true)));
+        assert_eq!(parse_camera_enabled("False"), Ok(Some(false)));
+        assert_eq!(parse_camera_enabled("auto"), Ok(None));
+        assert!(parse_camera_enabled("sometimes").is_err());
+        assert!(ConfigFile::parse("[camera]\nenabled = \"sometimes\"\n").is_err());
+
+        let manifest = serde_json::json!({
+            "deploymentId": "camera-test-deployment",
+            "modules": [
+                { "name": "capture", "requirements": ["takeImageStaticSize"] },
+                { "name": "detect", "capabilities": ["camera"] },
+                { "name": "infer", "requirements": [{ "name": "compute" }] }
+            ]
+        });
+        assert_eq!(modules_requiring_camera(&manifest), vec!["capture".to_string(), "detect".to_string()]);
+
+        // Without a camera, the camera functions are not offered and deployments needing them are refused
+        if !camera_enabled() {
+            let app = test::init_service(App::new()
+                .route("/deploy", web::post().to(deployment_create))
+                .route("/.well-known/wasmiot-device-description", web::get().to(wasmiot_device_description))
+            ).await;
+            let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(&manifest).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(body["capability"], "camera");
+            assert_eq!(body["modules"], serde_json::json!(["capture", "detect"]));
+
+            let req = test::TestRequest::get().uri("/.well-known/wasmiot-device-description").to_request();
+            let body: Value = test::read_body_json(test::call_service(&app, req).await).await;
+            let interfaces = body["supervisorInterfaces"].as_array().unwrap();
+            assert!(!interfaces.iter().any(|name| name == "takeImageStaticSize"), "{:?}", interfaces);
+        }
+    }
+
+    #[actix_web::test]
+    async fn api_test_healthz() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().route("/healthz", web::get().to(healthz))).await;
+        let req = test::TestRequest::get().uri("/healthz").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["status"], "ok");
+    }
+
+    #[actix_web::test]
+    async fn api_test_rename_supervisor() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let path = env::temp_dir().join("supervisor-rename-test.toml");
+        std::fs::write(&path, "# Managed by hand\n[network]\nport = 3005\n").unwrap();
+        let original = get_supervisor_config();
+        let mut startup = original.startup.clone();
+        startup.config_file = Some(path.clone());
+        set_startup_config(startup);
+
+        let zc = Arc::new(parking_lot::Mutex::new(WebthingZeroconf::new()));
+        zc.lock().service_name = original.name.clone();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(zc.clone()))
+                .route("/config", web::patch().to(supervisor_config_patch))
+        ).await;
+        let patch = |body: Value| test::TestRequest::patch().uri("/config").set_json(body).to_request();
+
+        // Names that cannot be DNS-SD instance names are rejected
+        for name in ["  ", "camera\u{7}1", &"x".repeat(64)] {
+            let resp = test::call_service(&app, patch(serde_json::json!({"name": name}))).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{:?}", name);
+            let body: Value = test::read_body_json(resp).await;
+            assert!(body["error"].as_str().unwrap().starts_with("Invalid name"));
+        }
+        assert_eq!(zc.lock().service_name, original.name);
+
+        // A new name is saved to the configuration file and advertised with the old one as a hint
+        let resp = test::call_service(&app, patch(serde_json::json!({"name": "camera room 2"}))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let config: Value = test::read_body_json(resp).await;
+        assert_eq!(config["name"], "camera room 2");
+        assert_eq!(config["startup"]["name"], original.startup.name.as_str());
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("# Managed by hand\n"));
+        let (file, _) = ConfigFile::parse(&saved).unwrap();
+        assert_eq!(file.supervisor.name.as_deref(), Some("camera room 2"));
+        assert_eq!(file.network.port, Some(3005));
+        assert_eq!(get_setting_with_source("SUPERVISOR_NAME"), Some(("camera room 2".to_string(), SettingSource::Api)));
+        assert_eq!(zc.lock().service_name, "camera room 2");
+        assert_eq!(zc.lock().previous_name.as_deref(), Some(original.name.as_str()));
+
+        let resp = test::call_service(&app, patch(serde_json::json!({"name": original.name}))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        set_startup_config(original.startup);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[actix_web::test]
+    async fn api_test_drain_executions() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let new_entry = |function: &str| RequestEntry::new(
+            "drain-test-deployment".to_string(),
+            "drain".to_string(),
+            function.to_string(),
+            "GET".to_string(),
+            serde_json::json!({}),
+            HashMap::new(),
+            chrono::Utc::now(),
+        );
+        let app = test::init_service(
+            App::new()
+                .route("/health", web::get().to(thingi_health))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+
+        // Running executions are counted on /health
+        let finishing = new_entry("finishing");
+        track_execution(&finishing);
+        let report: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/health").to_request()).await;
+        assert!(report["inFlightExecutions"].as_u64().unwrap() >= 1);
+        assert!(in_flight_requests() >= 1);
+
+        // Executions that finish within the drain timeout are waited for
+        set_setting("WASMIOT_DRAIN_TIMEOUT_SECS", "2", SettingSource::Environment);
+        let id = finishing.request_id.clone();
+        actix_web::rt::spawn(async move {
+            sleep(Duration::from_millis(200)).await;
+            finish_execution(&id);
+        });
+        let aborted = drain_executions().await;
+        assert!(!aborted.iter().any(|entry| entry.request_id == finishing.request_id));
+
+        // The ones that do not are recorded as aborted, once the timeout has passed
+        let stuck = new_entry("stuck");
+        track_execution(&stuck);
+        sleep(Duration::from_secs(2)).await;
+        let aborted: Vec<RequestEntry> = drain_executions().await.into_iter()
+            .filter(|entry| entry.deployment_id == "drain-test-deployment")
+            .collect();
+        assert_eq!(aborted.len(), 1);
+        assert_eq!(aborted[0].request_id, stuck.request_id);
+        record_aborted_requests(aborted);
+
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", stuck.request_id)).to_request();
+        let recorded: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(recorded["aborted"], true);
+        assert_eq!(recorded["success"], false);
+    }
+
+    #[actix_web::test]
+    async fn api_test_concurrent_deployments() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // A function that sleeps for half a second in poll_oneoff, leaving the thread free meanwhile
+        let napper = r#"(module
+            (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll (param i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "\01\00\00\00\00\00\00\00\00\65\cd\1d\00\00\00\00")
+            (func (export "nap") (result i32)
+                (drop (call $poll (i32.const 0) (i32.const 64) (i32.const 1) (i32.const 128)))
+                (i32.const 1)))"#;
+        let deployment_ids = ["concurrent-test-a", "concurrent-test-b"];
+        for deployment_id in deployment_ids {
+            let path = get_module_path(deployment_id, "napper");
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, napper).unwrap();
+            std::fs::create_dir_all(PARAMS_FOLDER.join(deployment_id).join("napper")).unwrap();
+            let endpoint = serde_json::json!({
+                "url": "http://localhost:8080",
+                "path": format!("/{}/modules/napper/nap", deployment_id),
+                "method": "GET",
+                "request": { "parameters": [], "request_body": null },
+                "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+            });
+            let deployment = Deployment::new(
+                deployment_id.to_string(),
+                HashMap::new(),
+                vec![ModuleConfig::new("napper-id".to_string(), "napper".to_string(), path, HashMap::new(), None)],
+                HashMap::from([("napper".to_string(), HashMap::from([("nap".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+                serde_json::from_value(serde_json::json!({ "napper": { "nap": { "from": endpoint, "to": null } } })).unwrap(),
+                serde_json::from_value(serde_json::json!({ "napper": { "nap": {} } })).unwrap(),
+            );
+            insert_deployment(deployment);
+        }
+        let new_entry = |deployment_id: &str| RequestEntry::new(
+            deployment_id.to_string(),
+            "napper".to_string(),
+            "nap".to_string(),
+            "GET".to_string(),
+            serde_json::json!({}),
+            HashMap::new(),
+            chrono::Utc::now(),
+        );
+        let run = |deployment_id: &'static str| async move {
+            let mut entry = new_entry(deployment_id);
+            let started = std::time::Instant::now();
+            let result = do_wasm_work(&mut entry).await;
+            (result, started.elapsed())
+        };
+
+        // Compile and instantiate the modules first, so that only the executions are timed
+        for deployment_id in deployment_ids {
+            let (result, _) = run(deployment_id).await;
+            assert!(result.is_ok(), "{:?}", result);
+        }
+
+        // Executions of different deployments do not wait for each other
+        let started = std::time::Instant::now();
+        let ((result_a, elapsed_a), (result_b, elapsed_b)) =
+            futures_util::future::join(run(deployment_ids[0]), run(deployment_ids[1])).await;
+        let elapsed = started.elapsed();
+        for deployment_id in deployment_ids {
+            lock_deployments().remove(deployment_id);
+            std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+            std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+        }
+        assert!(result_a.is_ok(), "{:?}", result_a);
+        assert!(result_b.is_ok(), "{:?}", result_b);
+        assert!(elapsed_a >= Duration::from_millis(500) && elapsed_b >= Duration::from_millis(500));
+        assert!(elapsed < elapsed_a + elapsed_b, "The executions did not overlap: {:?} + {:?} took {:?}", elapsed_a, elapsed_b, elapsed);
+    }
+
+    #[actix_web::test]
+    async fn api_test_slow_upload_does_not_block_health() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // The inputs are kept, so that what was saved of the upload can be checked
+        let endpoint: Endpoint = serde_json::from_value(serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": "/upload-test-deployment/modules/uploader/run",
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null },
+            "keepInputs": true
+        })).unwrap();
+        let deployment = Deployment::new(
+            "upload-test-deployment".to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new(
+                "uploader-id".to_string(),
+                "uploader".to_string(),
+                get_module_path("upload-test-deployment", "uploader"),
+                HashMap::new(),
+                None,
+            )],
+            HashMap::from([("uploader".to_string(), HashMap::from([("run".to_string(), endpoint)]))]),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        insert_deployment(deployment);
+
+        // A single worker, so that the upload and the health checks are served by the same one
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/healthz", web::get().to(healthz))
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let head = "--upload-boundary\r\nContent-Disposition: form-data; name=\"data\"; filename=\"input.bin\"\r\n\r\n";
+        let tail = "\r\n--upload-boundary--\r\n";
+        let chunk = vec![b'x'; 16 * 1024];
+        let chunks = 8;
+        let request = |content_length: usize| format!(
+            "POST /upload-test-deployment/modules/uploader/run HTTP/1.1\r\nHost: {}\r\n\
+             Content-Type: multipart/form-data; boundary=upload-boundary\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            address, content_length, head
+        );
+
+        // The upload trickles in while the health of the supervisor is checked
+        let upload = async {
+            let mut stream = actix_web::rt::net::TcpStream::connect(address).await.unwrap();
+            stream.write_all(request(head.len() + chunk.len() * chunks + tail.len()).as_bytes()).await.unwrap();
+            for _ in 0..chunks {
+                stream.write_all(&chunk).await.unwrap();
+                sleep(Duration::from_millis(100)).await;
+            }
+            stream.write_all(tail.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let health = async {
+            let client = reqwest::Client::new();
+            let mut slowest = Duration::ZERO;
+            for _ in 0..8 {
+                let started = std::time::Instant::now();
+                let resp = client.get(format!("http://{}/healthz", address)).send().await.unwrap();
+                assert_eq!(resp.status(), reqwest::StatusCode::OK);
+                slowest = slowest.max(started.elapsed());
+                sleep(Duration::from_millis(50)).await;
+            }
+            slowest
+        };
+        let (response, slowest) = futures_util::future::join(upload, health).await;
+        // The upload is received in full, and the execution fails as there is no module to run
+        assert!(response.starts_with("HTTP/1.1 500"), "{}", response);
+        assert!(slowest < Duration::from_millis(500), "Health check took {:?}", slowest);
+        let inputs = get_params_path("upload-test-deployment", "uploader", Some("inputs"));
+        let saved_inputs = || std::fs::read_dir(&inputs).unwrap().flatten().map(|dir| dir.path().join("input.bin")).collect::<Vec<_>>();
+        let saved = saved_inputs().pop().unwrap();
+        assert_eq!(std::fs::metadata(&saved).unwrap().len(), (chunk.len() * chunks) as u64);
+
+        // An upload cut short is not left behind half-written
+        std::fs::remove_dir_all(&inputs).unwrap();
+        std::fs::create_dir_all(&inputs).unwrap();
+        let mut stream = actix_web::rt::net::TcpStream::connect(address).await.unwrap();
+        stream.write_all(request(head.len() + chunk.len() * chunks + tail.len()).as_bytes()).await.unwrap();
+        stream.write_all(&chunk).await.unwrap();
+        sleep(Duration::from_millis(200)).await;
+        drop(stream);
+        sleep(Duration::from_millis(500)).await;
+        assert!(saved_inputs().is_empty());
+        let resp = reqwest::get(format!("http://{}/healthz", address)).await.unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+
+        handle.stop(false).await;
+        lock_deployments().remove("upload-test-deployment");
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("upload-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_request_history_bounded_and_indexed() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let new_entry = |i: usize| RequestEntry::new(
+            "history-test-deployment".to_string(),
+            "history".to_string(),
+            format!("function{}", i),
+            "GET".to_string(),
+            serde_json::json!({}),
+            HashMap::new(),
+            chrono::Utc::now(),
+        );
+
+        // The oldest entries over the limit are evicted, and the rest keep their order
+        let mut history = RequestHistory::new();
+        let entries: Vec<RequestEntry> = (0..5).map(new_entry).collect();
+        let mut evicted = Vec::new();
+        for entry in &entries {
+            evicted.extend(history.push(entry.clone(), 3));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(evicted.iter().map(|e| &e.request_id).collect::<Vec<_>>(), vec![&entries[0].request_id, &entries[1].request_id]);
+        assert_eq!(history.iter().map(|e| &e.request_id).collect::<Vec<_>>(), entries[2..].iter().map(|e| &e.request_id).collect::<Vec<_>>());
+        assert!(history.get(&entries[4].request_id).is_some());
+        assert!(history.get(&entries[0].request_id).is_none());
+
+        // Recording a request again replaces its entry
+        let mut aborted = entries[2].clone();
+        aborted.aborted = true;
+        assert!(history.push(aborted, 3).is_empty());
+        assert_eq!(history.len(), 3);
+        assert!(history.iter().last().unwrap().aborted);
+
+        // Looking an entry up costs about the same however long the history is
+        let lookup_time = |size: usize| {
+            let mut history = RequestHistory::new();
+            for i in 0..size {
+                history.push(new_entry(i), size);
+            }
+            let newest = history.iter().last().unwrap().request_id.clone();
+            (0..5).map(|_| {
+                let started = std::time::Instant::now();
+                for _ in 0..10_000 {
+                    assert!(history.get(std::hint::black_box(&newest)).is_some());
+                }
+                started.elapsed()
+            }).min().unwrap()
+        };
+        let small = lookup_time(100);
+        let large = lookup_time(50_000);
+        assert!(large < small * 20, "Lookups took {:?} with 100 entries and {:?} with 50000", small, large);
+
+        // Evicted entries are still served from the archive
+        let mut archived = new_entry(5);
+        archived.success = true;
+        archive_requests(std::slice::from_ref(&archived)).unwrap();
+        let app = test::init_service(App::new().route("/request-history/{request_id}", web::get().to(request_history_list))).await;
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", archived.request_id)).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["requestId"], archived.request_id);
+        assert_eq!(read_archived_request(&archived.request_id).unwrap().function_name, "function5");
+
+        // IDs that cannot be file names are not looked up from the archive
+        assert!(read_archived_request("../request-history").is_none());
+        sleep(Duration::from_millis(10)).await;
+        assert!(prune_request_archive(Duration::ZERO) >= 1);
+        assert!(read_archived_request(&archived.request_id).is_none());
+    }
+
+    #[actix_web::test]
+    async fn api_test_function_signatures_cached() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let path = get_module_path("signature-test-deployment", "typed");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, r#"(module
+            (memory (export "memory") 1)
+            (func (export "scale") (param i32 f64) (result f32) (f32.const 1))
+            (func (export "tick")))"#).unwrap();
+        let mut runtime = WasmtimeRuntime::new(vec![], vec![]).await.unwrap();
+        let config = ModuleConfig::new("typed-id".to_string(), "typed".to_string(), path, HashMap::new(), None);
+        runtime.load_module(config).await.unwrap();
+
+        // Every exported function has its signature cached when the module is loaded
+        let signatures = &runtime.get_module("typed").await.unwrap().signatures;
+        assert_eq!(signatures.len(), 2);
+        let scale = &signatures["scale"];
+        assert!(matches!(scale.params.as_slice(), [ValType::I32, ValType::F64]));
+        assert!(matches!(scale.results.as_slice(), [ValType::F32]));
+        assert!(signatures["tick"].params.is_empty() && signatures["tick"].results.is_empty());
+
+        // Type lookups are answered from the cache
+        assert_eq!(runtime.get_arg_types("typed", "scale").await.len(), 2);
+        assert_eq!(runtime.get_return_types("typed", "scale").await.len(), 1);
+        assert!(runtime.get_signature("typed", "missing").is_none());
+        assert!(runtime.get_signature("missing", "scale").is_none());
+
+        std::fs::remove_dir_all(MODULE_FOLDER.join("signature-test-deployment")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_create_reports_runtime_init() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let local_dir = PRELOADED_DEPLOYMENTS_FOLDER.join("runtime-init-test");
+        std::fs::create_dir_all(&local_dir).unwrap();
+        std::fs::write(local_dir.join("module.wasm"), b"\0asm not really a module").unwrap();
+        let app = test::init_service(
+            App::new()
+                .route("/deploy", web::post().to(deployment_create))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+        ).await;
+        let binary = serde_json::json!({ "localPath": local_dir.join("module.wasm").to_str().unwrap() });
+        let modules: Vec<Value> = ["first", "second", "third"].iter()
+            .map(|name| serde_json::json!({ "id": name, "name": name, "urls": { "binary": binary } }))
+            .collect();
+        let manifest = serde_json::json!({ "deploymentId": "runtime-init-test-deployment", "modules": modules });
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+
+        // Every module got a runtime, and the time it took is reported
+        let mut modules: Vec<&str> = body["runtimes"].as_array().unwrap().iter()
+            .map(|report| {
+                assert_eq!(report["success"], true);
+                assert!(report["durationMs"].is_u64());
+                report["module"].as_str().unwrap()
+            })
+            .collect();
+        modules.sort();
+        assert_eq!(modules, vec!["first", "second", "third"]);
+        let deployment = get_deployment("runtime-init-test-deployment").unwrap();
+        assert_eq!(deployment.lock().await.runtimes.len(), 3);
+
+        let req = test::TestRequest::delete().uri("/deploy/runtime-init-test-deployment").to_request();
+        test::call_service(&app, req).await;
+        std::fs::remove_dir_all(&local_dir).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_shared_module_cache() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let wat = r#"(module
+            (memory (export "memory") 1)
+            (func (export "shared_cache_answer") (result i32) (i32.const 42)))"#;
+        let mut configs = Vec::new();
+        for deployment_id in ["shared-module-test-a", "shared-module-test-b"] {
+            let path = get_module_path(deployment_id, "answer");
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, wat).unwrap();
+            let mut config = ModuleConfig::new("answer-id".to_string(), "answer".to_string(), path.clone(), HashMap::new(), None);
+            config.binary_source = Some(ArtifactSource { url: "file://answer.wasm".to_string(), sha256: Some(file_sha256(&path).unwrap()), size: None });
+            configs.push(config);
+        }
+        let digest = configs[0].binary_source.as_ref().unwrap().sha256.clone().unwrap();
+
+        // The second deployment of the same binary takes the compiled module of the first
+        let mut first = WasmtimeRuntime::new(vec![], vec![]).await.unwrap();
+        first.load_module(configs[0].clone()).await.unwrap();
+        assert!(is_module_cached(&digest));
+        let hits = module_cache_stats().hits;
+        let mut second = WasmtimeRuntime::new(vec![], vec![]).await.unwrap();
+        second.load_module(configs[1].clone()).await.unwrap();
+        assert!(module_cache_stats().hits > hits);
+        let compiled = |runtime: &WasmtimeRuntime| runtime.modules["answer"].module.clone().unwrap();
+        assert_eq!(compiled(&first).image_range(), compiled(&second).image_range());
+
+        // Each still runs in a store of its own
+        for runtime in [&mut first, &mut second] {
+            let result = runtime.run_function("answer", "shared_cache_answer", vec![], 1).await.unwrap();
+            assert!(matches!(result.as_slice(), [wasmtime::Val::I32(42)]));
+        }
+
+        // The cached module is reported, and evicted once no deployment uses it
+        let app = test::init_service(App::new().route("/health", web::get().to(thingi_health))).await;
+        let report: Value = test::call_and_read_body_json(&app, test::TestRequest::get().uri("/health").to_request()).await;
+        assert!(report["moduleCache"]["modules"].as_u64().unwrap() >= 1);
+        drop(first);
+        assert!(is_module_cached(&digest));
+        drop(second);
+        assert!(!is_module_cached(&digest));
+
+        for deployment_id in ["shared-module-test-a", "shared-module-test-b"] {
+            std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        }
+    }
+
+    #[actix_web::test]
+    async fn api_test_chained_result_file_streamed() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // A supervisor further down the chain, whose result is either JSON or a large file
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/request-history/json-request", web::get().to(|| async {
+                    HttpResponse::Ok().json(serde_json::json!({ "result": 7, "outputs": [] }))
+                }))
+                .route("/module_results/remote/detector/file-request/model.bin", web::get().to(|| async {
+                    HttpResponse::Ok().content_type("application/octet-stream").body(vec![0x5au8; 3 * 1024 * 1024])
+                }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/request-history/json-request", address);
+        match fetch_chained_result(&client, &url, &mut 0).await.unwrap() {
+            ChainedResult::Json(fetched) => assert_eq!(fetched["result"], 7),
+            ChainedResult::File(_) => panic!("JSON result was taken for a file"),
+        }
+
+        // A file is not read by the fetch, but streamed to the outputs of the request
+        let url = format!("http://{}/module_results/remote/detector/file-request/model.bin", address);
+        let ChainedResult::File(response) = fetch_chained_result(&client, &url, &mut 0).await.unwrap() else {
+            panic!("File result was taken for JSON");
+        };
+        let local = save_chained_result(response, "chained-file-test", "camera", "file-request", &mut 0).await.unwrap();
+        assert!(local.ends_with("/module_results/chained-file-test/camera/file-request/model.bin"), "{}", local);
+        let saved = get_output_path("chained-file-test", "camera", "file-request", Some("model.bin"));
+        assert_eq!(std::fs::metadata(saved).unwrap().len(), 3 * 1024 * 1024);
+
+        // Failed fetches are errors
+        let url = format!("http://{}/request-history/missing-request", address);
+        assert!(fetch_chained_result(&client, &url, &mut 0).await.is_err());
+
+        handle.stop(false).await;
+        std::fs::remove_dir_all(PARAMS_FOLDER.join("chained-file-test")).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_chained_files_streamed_from_disk() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use futures_util::StreamExt;
+        // The next supervisor in the chain, counting what it receives without keeping it
+        let server = HttpServer::new(|| {
+            App::new().route("/chained", web::post().to(|req: actix_web::HttpRequest, mut payload: web::Payload| async move {
+                let content_length = req.headers().get("content-length")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok());
+                let mut received: u64 = 0;
+                while let Some(chunk) = payload.next().await {
+                    received += chunk.unwrap().len() as u64;
+                }
+                HttpResponse::Ok().json(serde_json::json!({ "contentLength": content_length, "received": received }))
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        // A sparse file takes no space on disk, but is read in full when sent
+        let size: u64 = 100 * 1024 * 1024;
+        let path = std::env::temp_dir().join("chained-sparse-input.bin");
+        std::fs::File::create(&path).unwrap().set_len(size).unwrap();
+        let peak_rss = || std::fs::read_to_string("/proc/self/status").ok()
+            .and_then(|status| status.lines()
+                .find_map(|line| line.strip_prefix("VmHWM:"))
+                .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok()))
+            .map(|kb| kb * 1024);
+        let peak_before = peak_rss();
+
+        let form = reqwest::multipart::Form::new()
+            .part("data", file_part(&path).await.unwrap().file_name("data"));
+        let response: Value = reqwest::Client::new()
+            .post(format!("http://{}/chained", address))
+            .multipart(form)
+            .send().await.unwrap()
+            .json().await.unwrap();
+
+        // The whole file went through with its length known up front
+        let received = response["received"].as_u64().unwrap();
+        assert!(received > size, "{}", received);
+        assert_eq!(response["contentLength"].as_u64(), Some(received));
+        // ...without being held in memory on either side
+        if let (Some(before), Some(after)) = (peak_before, peak_rss()) {
+            assert!(after - before < size / 2, "Peak RSS grew by {} bytes", after - before);
+        }
+        assert!(file_part(&std::env::temp_dir().join("chained-missing-input.bin")).await.is_err());
+
+        handle.stop(false).await;
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_memory_file_transfer() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let module_path = std::env::temp_dir().join("memory-transfer-test.wat");
+        std::fs::write(&module_path, r#"(module (memory (export "memory") 384))"#).unwrap();
+        let config = ModuleConfig::new("transfer-id".to_string(), "transfer".to_string(), module_path.clone(), HashMap::new(), None);
+        let mut runtime = WasmtimeRuntime::new(vec![], vec![]).await.unwrap();
+        runtime.load_module(config).await.unwrap();
+
+        // A 20 MB payload goes into memory and back out as it was
+        let size = 20 * 1024 * 1024;
+        let payload: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let input = std::env::temp_dir().join("memory-transfer-input.bin");
+        let output = std::env::temp_dir().join("memory-transfer-output.bin");
+        std::fs::write(&input, &payload).unwrap();
+        assert_eq!(runtime.write_file_to_memory("transfer", 4096, &input).await, Ok(size));
+        let mut copy = vec![0u8; 16];
+        runtime.read_from_memory("transfer", 4096 + 1000, &mut copy).await.unwrap();
+        assert_eq!(copy, payload[1000..1016]);
+        runtime.read_memory_to_file("transfer", 4096, size, &output).await.unwrap();
+        assert!(std::fs::read(&output).unwrap() == payload);
+
+        // Nothing is copied past the end of the memory
+        let memory_size = 384 * 65536;
+        assert!(runtime.write_file_to_memory("transfer", memory_size - size + 1, &input).await.is_err());
+        assert!(runtime.read_memory_to_file("transfer", memory_size - 10, 11, &output).await.is_err());
+        assert!(runtime.read_memory_to_file("transfer", usize::MAX, 2, &output).await.is_err());
+        assert!(runtime.write_file_to_memory("missing", 0, &input).await.is_err());
+
+        for path in [module_path, input, output] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+
+    #[actix_web::test]
+    async fn api_test_request_inputs_removed_after_execution() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Returns the WASI errno of opening the input, i.e. 0 if the module can see it
+        let opener = r#"(module
+            (import "wasi_snapshot_preview1" "path_open" (func $open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "input.txt")
+            (func (export "open") (result i32)
+                (call $open (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 9) (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64))))"#;
+        let deployment_id = "inputs-test-deployment";
+        let module_path = get_module_path(deployment_id, "opener");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, opener).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "opener", None)).unwrap();
+        let deploy = |keep_inputs: bool| {
+            let endpoint = serde_json::json!({
+                "url": "http://localhost:8080",
+                "path": format!("/{}/modules/opener/open", deployment_id),
+                "method": "POST",
+                "request": { "parameters": [], "request_body": null },
+                "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null },
+                "keepInputs": keep_inputs
+            });
+            let mount = serde_json::json!({ "path": "input.txt", "media_type": "text/plain", "stage": "execution" });
+            insert_deployment(Deployment::new(
+                deployment_id.to_string(),
+                HashMap::new(),
+                vec![ModuleConfig::new("opener-id".to_string(), "opener".to_string(), module_path.clone(), HashMap::new(), None)],
+                HashMap::from([("opener".to_string(), HashMap::from([("open".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+                serde_json::from_value(serde_json::json!({ "opener": { "open": { "from": endpoint, "to": null } } })).unwrap(),
+                serde_json::from_value(serde_json::json!({ "opener": { "open": { "execution": [mount] } } })).unwrap(),
+            ));
+        };
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let run = || async {
+            let body = "--input-boundary\r\nContent-Disposition: form-data; name=\"input.txt\"; filename=\"upload.txt\"\r\n\r\nhello\r\n--input-boundary--\r\n";
+            let req = test::TestRequest::post()
+                .uri(&format!("/{}/modules/opener/open", deployment_id))
+                .insert_header(("content-type", "multipart/form-data; boundary=input-boundary"))
+                .set_payload(body)
+                .to_request();
+            let resp: Value = test::call_and_read_body_json(&app, req).await;
+            let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+            let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+            let entry: Value = test::call_and_read_body_json(&app, req).await;
+            (request_id, entry)
+        };
+        let mounted = get_params_path(deployment_id, "opener", Some("input.txt"));
+
+        // The module sees the input at its mount, and nothing of it is left afterwards
+        deploy(false);
+        let (request_id, entry) = run().await;
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert_eq!(entry["result"], "0", "{}", entry);
+        assert_eq!(entry["inputsRetained"], false);
+        assert!(!mounted.exists());
+        assert!(!get_input_path(deployment_id, "opener", &request_id, None).exists());
+
+        // Unless the endpoint keeps the inputs for debugging
+        deploy(true);
+        let (request_id, entry) = run().await;
+        assert_eq!(entry["result"], "0", "{}", entry);
+        assert_eq!(entry["inputsRetained"], true);
+        assert!(!mounted.exists());
+        let kept = get_input_path(deployment_id, "opener", &request_id, Some("input.txt"));
+        assert_eq!(std::fs::read_to_string(kept).unwrap(), "hello");
+
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_health_served_from_snapshot() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Health checks share the latest snapshot instead of sampling the system themselves
+        let snapshot = refresh_health_snapshot();
+        assert!(std::sync::Arc::ptr_eq(&snapshot, &current_health_snapshot()));
+        assert!(std::sync::Arc::ptr_eq(&current_health_snapshot(), &current_health_snapshot()));
+        assert_eq!(snapshot.sample().timestamp, snapshot.taken_at);
+
+        // ...and tell how old it is
+        sleep(Duration::from_millis(100)).await;
+        let app = test::init_service(App::new().route("/health", web::get().to(thingi_health))).await;
+        let requests = (0..16).map(|_| test::call_and_read_body_json::<_, _, Value>(&app, test::TestRequest::get().uri("/health").to_request()));
+        for report in futures_util::future::join_all(requests).await {
+            let age = report["snapshotAgeMs"].as_u64().unwrap();
+            assert!(age <= 2 * get_health_refresh_interval(), "{}", report);
+            assert!(report["cpuUsage"].is_number() && report["storageUsage"].is_object(), "{}", report);
+        }
+        assert!(snapshot.age() >= Duration::from_millis(100));
+        // Serving it never takes a new one, only the sampler does
+        assert!(std::sync::Arc::ptr_eq(&snapshot, &current_health_snapshot()));
+    }
+
+    #[actix_web::test]
+    async fn api_test_mqtt_execution() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use bytes::BytesMut;
+        use rumqttc::{ConnAck, Connect, ConnectReturnCode, Packet as MqttPacket, PubAck, Publish, QoS, SubAck, SubscribeReasonCode};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        // Returns the WASI errno of opening the input, i.e. 0 if the module can see it
+        let opener = r#"(module
+            (import "wasi_snapshot_preview1" "path_open" (func $open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "input.txt")
+            (func (export "open") (result i32)
+                (call $open (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 9) (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64))))"#;
+        let deployment_id = "mqtt-test-deployment";
+        let module_path = get_module_path(deployment_id, "opener");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, opener).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "opener", None)).unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/opener/open", deployment_id),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let mount = serde_json::json!({ "path": "input.txt", "media_type": "text/plain", "stage": "execution" });
+        let mut deployment = Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("opener-id".to_string(), "opener".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::from([("opener".to_string(), HashMap::from([("open".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "opener": { "open": { "from": endpoint, "to": null } } })).unwrap(),
+            serde_json::from_value(serde_json::json!({ "opener": { "open": { "execution": [mount] } } })).unwrap(),
+        );
+        deployment.mqtt_functions = vec!["opener/open".to_string()];
+        assert!(deployment.allows_mqtt("opener", "open"));
+        assert!(!deployment.allows_mqtt("opener", "close"));
+        insert_deployment(deployment);
+
+        // Reads the next packet the supervisor sends to the broker
+        async fn next_packet(broker: &mut tokio::net::TcpStream, buffer: &mut BytesMut) -> MqttPacket {
+            loop {
+                match MqttPacket::read(buffer, 1 << 20) {
+                    Ok(packet) => return packet,
+                    Err(rumqttc::mqttbytes::Error::InsufficientBytes(_)) => {}
+                    Err(e) => panic!("Malformed packet: {}", e),
+                }
+                let read = tokio::time::timeout(Duration::from_secs(30), broker.read_buf(buffer)).await.unwrap().unwrap();
+                assert!(read > 0, "The supervisor closed the connection");
+            }
+        }
+        async fn send(broker: &mut tokio::net::TcpStream, packet: MqttPacket) {
+            let mut buffer = BytesMut::new();
+            packet.write(&mut buffer, 1 << 20).unwrap();
+            broker.write_all(&buffer).await.unwrap();
+        }
+        // Reads packets from the supervisor until it publishes a result, with the packets acked
+        async fn result(broker: &mut tokio::net::TcpStream, buffer: &mut BytesMut) -> (Publish, Value, Vec<u16>) {
+            let mut acked = Vec::new();
+            loop {
+                match next_packet(broker, buffer).await {
+                    MqttPacket::Publish(publish) if publish.topic.contains("/results/") => {
+                        let response = serde_json::from_slice::<Value>(&publish.payload).unwrap();
+                        return (publish, response, acked);
+                    }
+                    MqttPacket::PubAck(ack) => acked.push(ack.pkid),
+                    _ => {}
+                }
+            }
+        }
+        // Accepts the connection of the supervisor, which subscribes to executions and tells it
+        // is online, and returns the topics it is under with the results published meanwhile
+        async fn accept(listener: &tokio::net::TcpListener, session_present: bool) -> (tokio::net::TcpStream, BytesMut, Connect, String, Vec<Publish>) {
+            let (mut broker, _) = tokio::time::timeout(Duration::from_secs(10), listener.accept()).await.unwrap().unwrap();
+            let mut buffer = BytesMut::new();
+            let MqttPacket::Connect(connect) = next_packet(&mut broker, &mut buffer).await else { panic!("Expected CONNECT") };
+            send(&mut broker, MqttPacket::ConnAck(ConnAck::new(ConnectReturnCode::Success, session_present))).await;
+            let mut base = None;
+            let mut results = Vec::new();
+            loop {
+                match next_packet(&mut broker, &mut buffer).await {
+                    MqttPacket::Subscribe(subscribe) => {
+                        assert_eq!(subscribe.filters[0].qos, QoS::AtLeastOnce);
+                        base = subscribe.filters[0].path.strip_suffix("/exec/+/+/+").map(str::to_string);
+                        send(&mut broker, MqttPacket::SubAck(SubAck::new(subscribe.pkid, vec![SubscribeReasonCode::Success(QoS::AtLeastOnce)]))).await;
+                    }
+                    MqttPacket::Publish(publish) if publish.topic.ends_with("/status") => {
+                        assert_eq!(&publish.payload[..], b"online");
+                        assert!(publish.retain);
+                        send(&mut broker, MqttPacket::PubAck(PubAck::new(publish.pkid))).await;
+                        break;
+                    }
+                    MqttPacket::Publish(publish) => results.push(publish),
+                    _ => {}
+                }
+            }
+            (broker, buffer, connect, base.expect("Expected SUBSCRIBE"), results)
+        }
+
+        // The supervisor connects to the broker with a retained "offline" as its last will, in a
+        // session the broker keeps
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        set_setting("WASMIOT_MQTT_BROKER", listener.local_addr().unwrap().to_string(), SettingSource::Api);
+        tokio::spawn(run_mqtt_client());
+        let (mut broker, mut buffer, connect, base, _) = accept(&listener, false).await;
+        let will = connect.last_will.unwrap();
+        assert_eq!(&will.message[..], b"offline");
+        assert!(will.retain);
+        assert!(!connect.clean_session);
+        assert!(base.starts_with("wasmiot/"), "{}", base);
+
+        // A raw payload is the input file of the function, removed after the execution
+        let exec_topic = format!("{}/exec/{}/opener/open", base, deployment_id);
+        let mut exec = Publish::new(&exec_topic, QoS::AtLeastOnce, b"hello".to_vec());
+        exec.pkid = 7;
+        send(&mut broker, MqttPacket::Publish(exec)).await;
+        let (published, response, acked) = result(&mut broker, &mut buffer).await;
+        assert_eq!(published.topic, format!("{}/results/{}/opener/open", base, deployment_id));
+        assert_eq!(published.qos, QoS::AtLeastOnce);
+        assert_eq!(acked, vec![7]);
+        assert_eq!(response["success"], true, "{}", response);
+        assert_eq!(response["result"]["result"], "0", "{}", response);
+        let request_id = response["requestId"].as_str().unwrap().to_string();
+        assert!(response["resultUrl"].as_str().unwrap().ends_with(&request_id));
+        assert!(!get_input_path(deployment_id, "opener", &request_id, None).exists());
+
+        // A result the broker has not acknowledged when the connection is lost is published again
+        // once the session is resumed
+        drop(broker);
+        let (mut broker, mut buffer, connect, _, mut results) = accept(&listener, true).await;
+        assert!(!connect.clean_session);
+        let republished = match results.pop() {
+            Some(republished) => republished,
+            None => result(&mut broker, &mut buffer).await.0,
+        };
+        let response = serde_json::from_slice::<Value>(&republished.payload).unwrap();
+        assert_eq!(republished.topic, published.topic);
+        assert_eq!(republished.pkid, published.pkid);
+        assert_eq!(response["requestId"], request_id.as_str());
+        send(&mut broker, MqttPacket::PubAck(PubAck::new(republished.pkid))).await;
+
+        // Functions the deployment does not allow over MQTT are not executed
+        let exec = Publish::new(format!("{}/exec/{}/opener/close", base, deployment_id), QoS::AtMostOnce, b"{}".to_vec());
+        send(&mut broker, MqttPacket::Publish(exec)).await;
+        let (published, response, _) = result(&mut broker, &mut buffer).await;
+        assert_eq!(published.topic, format!("{}/results/{}/opener/close", base, deployment_id));
+        assert_eq!(response["success"], false, "{}", response);
+        assert!(response["error"].as_str().unwrap().contains("not executable over MQTT"), "{}", response);
+
+        remove_setting("WASMIOT_MQTT_BROKER");
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_coap_execution() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::coap::*;
+        // Returns the WASI errno of opening the input, i.e. 0 if the module can see it
+        let opener = r#"(module
+            (import "wasi_snapshot_preview1" "path_open" (func $open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "input.txt")
+            (func (export "open") (result i32)
+                (call $open (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 9) (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64))))"#;
+        let deployment_id = "coap-test-deployment";
+        let module_path = get_module_path(deployment_id, "opener");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, opener).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "opener", None)).unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/opener/open", deployment_id),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let mount = serde_json::json!({ "path": "input.txt", "media_type": "text/plain", "stage": "execution" });
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("opener-id".to_string(), "opener".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::from([("opener".to_string(), HashMap::from([("open".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "opener": { "open": { "from": endpoint, "to": null } } })).unwrap(),
+            serde_json::from_value(serde_json::json!({ "opener": { "open": { "execution": [mount] } } })).unwrap(),
+        ));
+
+        let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        set_setting("WASMIOT_COAP_PORT", port.to_string(), SettingSource::Api);
+        tokio::spawn(run_coap_server("127.0.0.1".parse().unwrap()));
+        sleep(Duration::from_millis(200)).await;
+        let client = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(("127.0.0.1", port)).await.unwrap();
+        let receive = || async {
+            let mut buffer = vec![0; 4096];
+            let len = tokio::time::timeout(Duration::from_secs(30), client.recv(&mut buffer)).await.unwrap().unwrap();
+            Message::parse(&buffer[..len]).unwrap()
+        };
+        let request = |message_id: u16, code: u8, path: &[&str]| {
+            let mut request = Message::new(CONFIRMABLE, code, message_id, &[0xab, message_id as u8]);
+            for segment in path {
+                request.options.push((URI_PATH, segment.as_bytes().to_vec()));
+            }
+            request
+        };
+        let function = [deployment_id, "opener", "open"];
+
+        // Deployed functions are discovered from /.well-known/core
+        client.send(&request(1, GET, &[".well-known", "core"]).encode()).await.unwrap();
+        let response = receive().await;
+        assert_eq!((response.kind, response.code, response.message_id), (ACKNOWLEDGEMENT, CONTENT, 1));
+        assert_eq!(response.uint_option(CONTENT_FORMAT), Some(LINK_FORMAT as u32));
+        let links = String::from_utf8(response.payload).unwrap();
+        assert!(links.contains(&format!("</{}/opener/open>", deployment_id)), "{}", links);
+
+        // An input sent block-wise is collected before the function runs
+        let input = b"hello, this is a longer input";
+        let mut first = request(2, POST, &function);
+        first.add_uint_option(BLOCK1, Block { num: 0, more: true, size: 16 }.encode());
+        first.payload = input[..16].to_vec();
+        client.send(&first.encode()).await.unwrap();
+        let response = receive().await;
+        assert_eq!((response.kind, response.code), (ACKNOWLEDGEMENT, CONTINUE));
+        assert_eq!(response.uint_option(BLOCK1).and_then(Block::decode), Some(Block { num: 0, more: true, size: 16 }));
+
+        // ...and the result is sent separately after acknowledging the request, as CBOR if asked
+        let mut last = request(3, POST, &function);
+        last.add_uint_option(BLOCK1, Block { num: 1, more: false, size: 16 }.encode());
+        last.add_uint_option(ACCEPT, CBOR_FORMAT as u32);
+        last.payload = input[16..].to_vec();
+        client.send(&last.encode()).await.unwrap();
+        let ack = receive().await;
+        assert_eq!((ack.kind, ack.code, ack.message_id), (ACKNOWLEDGEMENT, 0, 3));
+        let response = receive().await;
+        assert_eq!((response.kind, response.code), (CONFIRMABLE, CONTENT));
+        assert_eq!(response.token, last.token);
+        assert_eq!(response.uint_option(CONTENT_FORMAT), Some(CBOR_FORMAT as u32));
+        client.send(&Message::new(ACKNOWLEDGEMENT, 0, response.message_id, &[]).encode()).await.unwrap();
+        let result: Value = ciborium::from_reader(response.payload.as_slice()).unwrap();
+        assert_eq!(result["result"]["result"], "0", "{}", result);
+        assert!(result["resultUrl"].as_str().unwrap().contains("/request-history/"));
+
+        // A retransmitted request is only acknowledged again
+        client.send(&last.encode()).await.unwrap();
+        let ack = receive().await;
+        assert_eq!((ack.kind, ack.code, ack.message_id), (ACKNOWLEDGEMENT, 0, 3));
+
+        // A response larger than the block size the client asks for is fetched block-wise
+        let mut body = Vec::new();
+        for num in 0.. {
+            let mut get = request(10 + num as u16, GET, &function);
+            get.options.push((URI_QUERY, b"unused=argument".to_vec()));
+            get.add_uint_option(BLOCK2, Block { num, more: false, size: 16 }.encode());
+            client.send(&get.encode()).await.unwrap();
+            let mut response = receive().await;
+            if response.kind == ACKNOWLEDGEMENT && response.code == 0 {
+                response = receive().await;
+                client.send(&Message::new(ACKNOWLEDGEMENT, 0, response.message_id, &[]).encode()).await.unwrap();
+            }
+            assert_eq!(response.code, CONTENT);
+            let block = response.uint_option(BLOCK2).and_then(Block::decode).unwrap();
+            assert_eq!((block.num, block.size), (num, 16));
+            assert!(response.payload.len() <= 16);
+            body.extend_from_slice(&response.payload);
+            if num == 0 {
+                assert!(block.more);
+                assert!(response.uint_option(SIZE2).unwrap() > 16);
+
+                // Blocks past the end of the response and invalid block options are refused
+                let mut past_end = request(0x100, GET, &function);
+                past_end.options.push((URI_QUERY, b"unused=argument".to_vec()));
+                past_end.add_uint_option(BLOCK2, Block { num: 0xfffff, more: false, size: 16 }.encode());
+                client.send(&past_end.encode()).await.unwrap();
+                let refused = receive().await;
+                assert_eq!((refused.kind, refused.code, refused.message_id), (ACKNOWLEDGEMENT, BAD_OPTION, 0x100));
+                let mut too_long = request(0x101, GET, &function);
+                too_long.options.push((BLOCK2, vec![0x01, 0x00, 0x00, 0x10]));
+                client.send(&too_long.encode()).await.unwrap();
+                let refused = receive().await;
+                assert_eq!((refused.kind, refused.code, refused.message_id), (ACKNOWLEDGEMENT, BAD_OPTION, 0x101));
+            }
+            if !block.more {
+                break;
+            }
+        }
+        let result: Value = serde_json::from_slice(&body).unwrap();
+        assert!(result["resultUrl"].as_str().unwrap().contains("/request-history/"), "{}", result);
+
+        // Malformed messages are ignored, and the server keeps answering
+        client.send(&[0x4f, GET, 0x01, 0x02, 0xbf]).await.unwrap();
+        client.send(&Message::new(CONFIRMABLE, 0, 0x102, &[]).encode()).await.unwrap();
+        let reset = receive().await;
+        assert_eq!((reset.kind, reset.code, reset.message_id), (RESET, 0, 0x102));
+
+        // Unknown deployments are not found
+        client.send(&request(40, GET, &["no-such-deployment", "opener", "open"]).encode()).await.unwrap();
+        let ack = receive().await;
+        assert_eq!(ack.code, 0);
+        let response = receive().await;
+        assert_eq!(response.code, NOT_FOUND);
+        client.send(&Message::new(ACKNOWLEDGEMENT, 0, response.message_id, &[]).encode()).await.unwrap();
+
+        remove_setting("WASMIOT_COAP_PORT");
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_execution_callbacks() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::callback::{callback_signature, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+        // Receives callbacks, failing them on /fail
+        let received: Arc<Mutex<Vec<(String, String, Value)>>> = Arc::new(Mutex::new(Vec::new()));
+        let server_received = received.clone();
+        let server = HttpServer::new(move || {
+            let received = server_received.clone();
+            App::new().route("/{outcome}", web::post().to(move |req: actix_web::HttpRequest, body: web::Bytes| {
+                let received = received.clone();
+                async move {
+                    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+                    let entry: Value = serde_json::from_slice(&body).unwrap();
+                    received.lock().unwrap().push((header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER), entry));
+                    if req.match_info().get("outcome") == Some("fail") {
+                        HttpResponse::InternalServerError().finish()
+                    } else {
+                        HttpResponse::Ok().finish()
+                    }
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let deployment_id = "callback-test-deployment";
+        let mut deployment = answerer_deployment(deployment_id, serde_json::json!({}));
+        deployment.callback_hosts = vec!["127.0.0.1".to_string()];
+        insert_deployment(deployment);
+        set_setting("WASMIOT_CALLBACK_SECRET", "callback-test-secret", SettingSource::Api);
+        set_setting("WASMIOT_CALLBACK_RETRIES", "1", SettingSource::Api);
+
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let run = |callback_url: String| test::TestRequest::get()
+            .uri(&format!("/{}/modules/answerer/answer?callbackUrl={}", deployment_id, urlencoding::encode(&callback_url)))
+            .to_request();
+        // Waits for the delivery of the callback of a request to be recorded in its entry
+        let delivered = |request_id: String| {
+            let app = &app;
+            async move {
+                for _ in 0..100 {
+                    let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+                    let entry: Value = test::call_and_read_body_json(app, req).await;
+                    if !entry["callback"].is_null() {
+                        return entry;
+                    }
+                    sleep(Duration::from_millis(100)).await;
+                }
+                panic!("Callback of request {} was not recorded", request_id);
+            }
+        };
+
+        // Only hosts allowed by the deployment get callbacks, and only to valid URLs
+        let resp = test::call_service(&app, run(format!("http://localhost:{}/ok", address.port()))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let resp = test::call_service(&app, run("not a url".to_string())).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // The finished entry is posted with a signature of the shared secret
+        let resp: Value = test::call_and_read_body_json(&app, run(format!("http://{}/ok", address))).await;
+        let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let entry = delivered(request_id.clone()).await;
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert!(entry["requestArgs"].get("callbackUrl").is_none(), "{}", entry);
+        assert_eq!(entry["callback"]["delivered"], true, "{}", entry);
+        assert_eq!(entry["callback"]["attempts"], 1);
+        let (timestamp, signature, posted) = received.lock().unwrap().pop().unwrap();
+        assert_eq!(posted["requestId"], request_id.as_str());
+        assert_eq!(posted["result"], entry["result"]);
+        let body = serde_json::to_vec(&posted).unwrap();
+        let expected = callback_signature(b"callback-test-secret", timestamp.parse().unwrap(), &body).unwrap();
+        assert_eq!(signature, format!("sha256={}", expected));
+
+        // A callback that keeps failing is retried and recorded, without failing the execution
+        let resp: Value = test::call_and_read_body_json(&app, run(format!("http://{}/fail", address))).await;
+        let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let entry = delivered(request_id).await;
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert_eq!(entry["callback"]["delivered"], false, "{}", entry);
+        assert_eq!(entry["callback"]["attempts"], 2);
+        assert_eq!(entry["callback"]["status"], 500);
+        assert_eq!(received.lock().unwrap().len(), 2);
+
+        handle.stop(true).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_execution_traceparent() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::telemetry::{outgoing_traceparent, valid_traceparent};
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        assert!(valid_traceparent(traceparent));
+        assert!(!valid_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"));
+        assert!(!valid_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"));
+        assert!(!valid_traceparent("not a traceparent"));
+        // Without an exporter, chained calls pass on the trace of the request as is
+        let span = tracing::info_span!("chained_call");
+        assert_eq!(outgoing_traceparent(&span, Some(traceparent)).as_deref(), Some(traceparent));
+        assert_eq!(outgoing_traceparent(&span, None), None);
+
+        let deployment_id = "traceparent-test-deployment";
+        deploy_answerer(deployment_id, serde_json::json!({}));
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+
+        // A request with a valid traceparent is recorded as part of that trace, others are not
+        for (header, expected) in [(traceparent, Value::from(traceparent)), ("00-bogus-01", Value::Null)] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/{}/modules/answerer/answer", deployment_id))
+                .insert_header(("traceparent", header))
+                .to_request();
+            let resp: Value = test::call_and_read_body_json(&app, req).await;
+            let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+            let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+            let entry: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(entry["success"], true, "{}", entry);
+            assert_eq!(entry["traceparent"], expected, "{}", entry);
+        }
+
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_result_sink() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::result_sink::{upload_outputs, ResultSink};
+        let sink = ResultSink::from_manifest(&serde_json::json!({
+            "type": "s3", "bucket": "results", "prefix": "site-a/", "endpoint": "http://minio.local:9000/"
+        })).unwrap();
+        assert!(!sink.delete_local);
+        assert!(ResultSink::from_manifest(&serde_json::json!({ "type": "gcs", "bucket": "results" })).is_err());
+        assert!(ResultSink::from_manifest(&serde_json::json!({ "type": "s3", "bucket": "" })).is_err());
+        assert!(ResultSink::from_manifest(&serde_json::json!({ "type": "s3", "bucket": "results", "endpoint": "ftp://minio" })).is_err());
+
+        let deployment_id = "sink-test-deployment";
+        let mut entry = RequestEntry::new(
+            deployment_id.to_string(), "camera".to_string(), "take_image".to_string(),
+            "GET".to_string(), Value::Null, HashMap::new(), chrono::Utc::now(),
+        );
+        assert_eq!(
+            sink.object_url(&sink.object_key(&entry, "frame 1.jpg")),
+            format!("http://minio.local:9000/results/site-a/{}/camera/{}/frame%201.jpg", deployment_id, entry.request_id)
+        );
+        let local_url = |entry: &RequestEntry| public_url(&format!("/module_results/{}/camera/{}/frame.jpg", deployment_id, entry.request_id));
+        let output = get_output_path(deployment_id, "camera", &entry.request_id, Some("frame.jpg"));
+        std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+        std::fs::write(&output, vec![0xffu8; 4096]).unwrap();
+
+        // Outputs that cannot be uploaded are served from here, with the reason in the chain trace
+        entry.outputs = vec![local_url(&entry)];
+        let unreachable = ResultSink::from_manifest(&serde_json::json!({
+            "type": "s3", "bucket": "results", "endpoint": "http://127.0.0.1:9", "deleteLocal": true
+        })).unwrap();
+        upload_outputs(&mut entry, &unreachable).await;
+        assert_eq!(entry.outputs, vec![local_url(&entry)]);
+        assert_eq!(entry.chain_trace.len(), 1);
+        assert_eq!(entry.chain_trace[0].url, "http://127.0.0.1:9/results");
+        assert!(entry.chain_trace[0].mirror_error.as_deref().unwrap().starts_with("Serving outputs from this device"), "{:?}", entry.chain_trace);
+        assert!(output.exists());
+
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[cfg(feature = "s3")]
+    #[actix_web::test]
+    async fn api_test_result_sink_upload() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::result_sink::{upload_outputs, ResultSink};
+        // An S3-compatible service accepting every object
+        let uploads: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+        let server_uploads = uploads.clone();
+        let server = HttpServer::new(move || {
+            let uploads = server_uploads.clone();
+            App::new().route("/{path:.*}", web::put().to(move |req: actix_web::HttpRequest| {
+                let uploads = uploads.clone();
+                async move {
+                    uploads.lock().unwrap().push(req.path().to_string());
+                    HttpResponse::Ok().insert_header(("ETag", "\"etag\"")).finish()
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        set_setting("WASMIOT_S3_ACCESS_KEY_ID", "minio", SettingSource::Api);
+        set_setting("WASMIOT_S3_SECRET_ACCESS_KEY", "minio-secret", SettingSource::Api);
+
+        let deployment_id = "sink-upload-test-deployment";
+        let mut entry = RequestEntry::new(
+            deployment_id.to_string(), "camera".to_string(), "take_image".to_string(),
+            "GET".to_string(), Value::Null, HashMap::new(), chrono::Utc::now(),
+        );
+        let output = get_output_path(deployment_id, "camera", &entry.request_id, Some("frame.jpg"));
+        std::fs::create_dir_all(output.parent().unwrap()).unwrap();
+        std::fs::write(&output, vec![0xffu8; 4096]).unwrap();
+        let other = "http://elsewhere:8080/module_results/remote/detector/remote-request/frame.jpg".to_string();
+        entry.outputs = vec![
+            public_url(&format!("/module_results/{}/camera/{}/frame.jpg", deployment_id, entry.request_id)),
+            other.clone(),
+        ];
+        let sink = ResultSink::from_manifest(&serde_json::json!({
+            "type": "s3", "bucket": "results", "prefix": "site-a",
+            "endpoint": format!("http://{}", address), "deleteLocal": true
+        })).unwrap();
+
+        // Uploaded outputs point to the objects and are removed here, others are left as is
+        upload_outputs(&mut entry, &sink).await;
+        let key = format!("site-a/{}/camera/{}/frame.jpg", deployment_id, entry.request_id);
+        assert_eq!(uploads.lock().unwrap().as_slice(), [format!("/results/{}", key)]);
+        assert_eq!(entry.outputs, vec![sink.object_url(&key), other]);
+        assert!(entry.chain_trace.is_empty(), "{:?}", entry.chain_trace);
+        assert!(!output.exists());
+
+        handle.stop(false).await;
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn api_test_unix_socket_listener() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use std::os::unix::fs::PermissionsExt;
+        use supervisor::lib::unix_socket::{bind_socket, is_trusted_peer, is_unix_peer, mark_connection, prepare_socket_path, remove_socket};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let folder = env::temp_dir().join(format!("supervisor-uds-test-{}", std::process::id()));
+        let path = folder.join("sockets").join("supervisor.sock");
+
+        // A stale socket is replaced, other files and sockets in use are left alone
+        prepare_socket_path(&path).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        prepare_socket_path(&path).unwrap();
+        assert!(!path.exists());
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        assert_eq!(prepare_socket_path(&path).unwrap_err().kind(), std::io::ErrorKind::AddrInUse);
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+        let file = folder.join("not-a-socket");
+        std::fs::write(&file, "data").unwrap();
+        assert!(prepare_socket_path(&file).is_err());
+        assert!(file.exists());
+
+        // The same routes are served over the socket, which its clients can be told apart by
+        let server = HttpServer::new(|| {
+            App::new().route("/peer", web::get().to(|req: actix_web::HttpRequest| async move {
+                HttpResponse::Ok().json(serde_json::json!({ "unix": is_unix_peer(&req), "trusted": is_trusted_peer(&req) }))
+            }))
+        })
+        .workers(1)
+        .on_connect(mark_connection)
+        .bind(("127.0.0.1", 0))
+        .unwrap()
+        .listen_uds(bind_socket(&path, 0o600).unwrap())
+        .unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let over_socket = || async {
+            let mut stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+            stream.write_all(b"GET /peer HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            let body = response.split("\r\n\r\n").nth(1).unwrap().to_string();
+            serde_json::from_str::<Value>(&body).unwrap()
+        };
+        assert_eq!(over_socket().await, serde_json::json!({ "unix": true, "trusted": false }));
+        set_setting("WASMIOT_UNIX_SOCKET_TRUSTED", "true", SettingSource::Api);
+        assert_eq!(over_socket().await, serde_json::json!({ "unix": true, "trusted": true }));
+        let over_network: Value = reqwest::get(format!("http://{}/peer", address)).await.unwrap().json().await.unwrap();
+        assert_eq!(over_network, serde_json::json!({ "unix": false, "trusted": false }));
+        remove_setting("WASMIOT_UNIX_SOCKET_TRUSTED");
+
+        handle.stop(false).await;
+        remove_socket(&path);
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&folder).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_orchestrator_api_versions() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::orchestrator_compat::{logging_endpoint, negotiate, orchestrator_state, registration_payload, ApiVersion};
+        use supervisor::lib::zeroconf::register_services_to_orchestrator;
+        assert_eq!(ApiVersion::parse("1"), Some(ApiVersion::V1));
+        assert_eq!(ApiVersion::parse(" 2 "), Some(ApiVersion::V2));
+        assert_eq!(ApiVersion::parse("9"), Some(ApiVersion::V3));
+        assert_eq!(ApiVersion::parse("0"), None);
+        assert_eq!(ApiVersion::parse("v2"), None);
+
+        // Each version gets the registration and log endpoint it expects
+        let registration = serde_json::json!({
+            "name": "camera", "type": "_webthing._tcp.local.", "port": 3005,
+            "properties": { "path": "/" }, "addresses": ["10.0.0.5"], "host": "10.0.0.5",
+            "previousName": "old camera"
+        });
+        let mut flat = registration.clone();
+        flat.as_object_mut().unwrap().remove("previousName");
+        assert_eq!(registration_payload(registration.clone(), ApiVersion::V1), flat);
+        assert_eq!(registration_payload(registration.clone(), ApiVersion::V2), registration);
+        assert_eq!(registration_payload(registration.clone(), ApiVersion::V3), serde_json::json!({
+            "name": "camera", "type": "_webthing._tcp.local.", "properties": { "path": "/" },
+            "endpoint": { "host": "10.0.0.5", "port": 3005, "addresses": ["10.0.0.5"] },
+            "previousName": "old camera"
+        }));
+        assert_eq!(registration_payload(flat.clone(), ApiVersion::V3).get("previousName"), None);
+        assert_eq!(logging_endpoint("http://orchestrator:3000/", ApiVersion::V1), "http://orchestrator:3000/device/logs");
+        assert_eq!(logging_endpoint("http://orchestrator:3000", ApiVersion::V2), "http://orchestrator:3000/device/logs");
+        assert_eq!(logging_endpoint("http://orchestrator:3000", ApiVersion::V3), "http://orchestrator:3000/api/logs");
+
+        // Versions are kept per orchestrator, and orchestrators that do not tell theirs get the current one
+        assert_eq!(negotiate("http://unversioned-orchestrator:3000", None), ApiVersion::CURRENT);
+        assert!(orchestrator_state("http://unversioned-orchestrator:3000").is_none());
+
+        // An orchestrator registering itself gets its logs where its version expects them
+        let app = test::init_service(App::new().route("/register", web::post().to(register_orchestrator))).await;
+        let req = test::TestRequest::post().uri("/register")
+            .insert_header(("X-Wasmiot-Api-Version", "7"))
+            .set_json(serde_json::json!({ "url": "http://new-orchestrator:3000" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("X-Wasmiot-Api-Version").unwrap(), "3");
+        assert_eq!(get_setting("WASMIOT_LOGGING_ENDPOINT").as_deref(), Some("http://new-orchestrator:3000/api/logs"));
+        assert_eq!(orchestrator_state("http://new-orchestrator:3000/file/device/discovery/register").unwrap().api_version, ApiVersion::V3);
+        remove_setting("WASMIOT_ORCHESTRATOR_URL");
+        remove_setting("WASMIOT_LOGGING_ENDPOINT");
+
+        // The version an orchestrator answers a registration with shapes the next registrations
+        let received: Arc<Mutex<Vec<Value>>> = Arc::new(Mutex::new(Vec::new()));
+        let server_received = received.clone();
+        let server = HttpServer::new(move || {
+            let received = server_received.clone();
+            App::new().route("/file/device/discovery/register", web::post().to(move |req: actix_web::HttpRequest, body: web::Json<Value>| {
+                let received = received.clone();
+                async move {
+                    let version = req.headers().get("X-Wasmiot-Api-Version").and_then(|v| v.to_str().ok()).map(str::to_string);
+                    received.lock().unwrap().push(serde_json::json!({ "version": version, "body": body.into_inner() }));
+                    HttpResponse::Ok().insert_header(("X-Wasmiot-Api-Version", "1")).finish()
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        let url = format!("http://{}/file/device/discovery/register", address);
+        let zc = Arc::new(parking_lot::Mutex::new(WebthingZeroconf::new()));
+        for _ in 0..2 {
+            zc.lock().previous_name = Some("old camera".to_string());
+            register_services_to_orchestrator(zc.clone(), &url).await.unwrap();
+        }
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0]["version"], "3");
+        assert_eq!(received[0]["body"]["previousName"], "old camera");
+        assert_eq!(received[1]["body"].get("previousName"), None, "{}", received[1]);
+        assert_eq!(orchestrator_state(&url).unwrap().api_version, ApiVersion::V1);
+
+        handle.stop(false).await;
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_create_pushed_as_multipart() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(
+            App::new()
+                .route("/deploy", web::post().to(deployment_create))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+        ).await;
+        let push = |parts: Vec<(&str, Vec<u8>)>| {
+            let mut body = Vec::new();
+            for (name, contents) in parts {
+                body.extend_from_slice(format!("--push-boundary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes());
+                body.extend_from_slice(&contents);
+                body.extend_from_slice(b"\r\n");
+            }
+            body.extend_from_slice(b"--push-boundary--\r\n");
+            test::TestRequest::post()
+                .uri("/deploy?wait=true")
+                .insert_header(("content-type", "multipart/form-data; boundary=push-boundary"))
+                .set_payload(body)
+                .to_request()
+        };
+        let binary = b"\0asm not really a module".to_vec();
+        let weights = b"model weights".to_vec();
+        let manifest = |weights_digest: &str| serde_json::json!({
+            "deploymentId": "push-test-deployment",
+            "modules": [{
+                "id": "echo-id",
+                "name": "echo",
+                "urls": { "other": { "model.bin": { "sha256": weights_digest } } }
+            }]
+        }).to_string().into_bytes();
+        let weights_digest = hex::encode(Sha256::digest(&weights));
+
+        // The artifacts come with the manifest and nothing is downloaded
+        let resp = test::call_service(&app, push(vec![
+            ("manifest", manifest(&weights_digest)),
+            ("echo", binary.clone()),
+            ("echo/model.bin", weights.clone()),
+        ])).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(std::fs::read(get_module_path("push-test-deployment", "echo")).unwrap(), binary);
+        assert_eq!(std::fs::read(get_params_path("push-test-deployment", "echo", Some("model.bin"))).unwrap(), weights);
+        let req = test::TestRequest::delete().uri("/deploy/push-test-deployment").to_request();
+        test::call_service(&app, req).await;
+
+        // Pushed artifacts are verified against their digests like downloaded ones
+        let resp = test::call_service(&app, push(vec![
+            ("manifest", manifest(&"0".repeat(64))),
+            ("echo", binary.clone()),
+            ("echo/model.bin", weights.clone()),
+        ])).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body.to_string().contains("checksum mismatch"));
+        assert!(!MODULE_FOLDER.join("push-test-deployment").exists());
+
+        // Parts for no module of the manifest and pushes without a manifest are refused
+        let resp = test::call_service(&app, push(vec![
+            ("manifest", manifest(&weights_digest)),
+            ("echo", binary.clone()),
+            ("unknown", binary.clone()),
+        ])).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: Value = test::read_body_json(resp).await;
+        assert!(body["error"].as_str().unwrap().contains("unknown"));
+        let resp = test::call_service(&app, push(vec![("echo", binary)])).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Nothing is left staged
+        let staged = std::fs::read_dir(&*BUNDLE_IMPORT_FOLDER).into_iter().flatten()
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().starts_with("push-"));
+        assert!(!staged);
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_fetches_artifacts_from_peers() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Unique contents, so that nothing is in the artifact cache from an earlier run
+        let nonce = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let shared = format!("\0asm shared by peers {}", nonce).into_bytes();
+        let tampered = format!("\0asm tampered with {}", nonce).into_bytes();
+        let shared_digest = hex::encode(Sha256::digest(&shared));
+        let tampered_digest = hex::encode(Sha256::digest(&tampered));
+
+        // A peer that has the first binary, and something else than the second one
+        let served = HashMap::from([(shared_digest.clone(), shared.clone()), (tampered_digest.clone(), b"not it".to_vec())]);
+        let server = HttpServer::new(move || {
+            let served = served.clone();
+            App::new().route("/modules/by-hash/{sha256}", web::get().to(move |path: web::Path<String>| {
+                let contents = served.get(&path.into_inner()).cloned();
+                async move {
+                    match contents {
+                        Some(contents) => HttpResponse::Ok().body(contents),
+                        None => HttpResponse::NotFound().finish(),
+                    }
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let peer = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let local_dir = PRELOADED_DEPLOYMENTS_FOLDER.join("peer-test");
+        std::fs::create_dir_all(&local_dir).unwrap();
+        std::fs::write(local_dir.join("tampered.wasm"), &tampered).unwrap();
+        let manifest = serde_json::json!({
+            "deploymentId": "peer-test-deployment",
+            "peers": ["http://127.0.0.1:9", peer],
+            "modules": [
+                {
+                    "id": "shared-id",
+                    "name": "shared",
+                    "urls": { "binary": { "url": "http://127.0.0.1:9/shared.wasm", "sha256": shared_digest } }
+                },
+                {
+                    "id": "tampered-id",
+                    "name": "tampered",
+                    "urls": { "binary": { "localPath": local_dir.join("tampered.wasm"), "sha256": tampered_digest } }
+                }
+            ]
+        });
+        let app = test::init_service(
+            App::new()
+                .route("/deploy", web::post().to(deployment_create))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+                .route("/modules/by-hash/{sha256}", web::get().to(module_by_hash))
+        ).await;
+        let req = test::TestRequest::post().uri("/deploy?wait=true").set_json(manifest).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        let download = |module: &str| body["downloads"].as_array().unwrap().iter()
+            .find(|download| download["module"] == module)
+            .cloned()
+            .unwrap();
+
+        // The first binary comes from the peer that has it, and the second one from its own
+        // source once the peer fails verification
+        assert_eq!(download("shared")["source"], "peer");
+        assert_eq!(download("shared")["peer"], peer);
+        assert_eq!(std::fs::read(get_module_path("peer-test-deployment", "shared")).unwrap(), shared);
+        assert_eq!(download("tampered")["source"], "network");
+        assert_eq!(std::fs::read(get_module_path("peer-test-deployment", "tampered")).unwrap(), tampered);
+
+        // Both are now served to other peers from here
+        let req = test::TestRequest::get().uri(&format!("/modules/by-hash/{}", shared_digest)).to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, shared);
+        let req = test::TestRequest::get().uri(&format!("/modules/by-hash/{}", "0".repeat(64))).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+        let req = test::TestRequest::get().uri("/modules/by-hash/..%2Fdeployments").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::delete().uri("/deploy/peer-test-deployment").to_request();
+        test::call_service(&app, req).await;
+        std::fs::remove_file(cache_path(&shared_digest)).ok();
+        std::fs::remove_file(cache_path(&tampered_digest)).ok();
+        std::fs::remove_dir_all(&local_dir).ok();
+        handle.stop(true).await;
+    }
+
+    #[actix_web::test]
+    async fn api_test_metrics_in_both_formats() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        record_execution("metrics-test-deployment", true);
+        record_execution("metrics-test-deployment", true);
+        record_execution("metrics-test-deployment", false);
+
+        let app = test::init_service(App::new().route("/metrics", web::get().to(metrics))).await;
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert!(body.contains("# TYPE supervisor_executions_total counter"), "{}", body);
+        assert!(body.contains("supervisor_executions_total{deployment=\"metrics-test-deployment\",outcome=\"success\"} 2\n"), "{}", body);
+        assert!(body.contains("supervisor_executions_total{deployment=\"metrics-test-deployment\",outcome=\"failure\"} 1\n"), "{}", body);
+
+        // The same values in the line protocol, tagged with the device
+        let lines = render_line_protocol(&collect_metrics(), "edge device", 1_700_000_000_000_000_000);
+        assert!(lines.contains(
+            "supervisor_executions_total,deployment=metrics-test-deployment,device=edge\\ device,outcome=success value=2 1700000000000000000\n"
+        ), "{}", lines);
+
+        // A Telegraf listener that fails the first push and takes the next one
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (server_received, server_attempts) = (received.clone(), attempts.clone());
+        let server = HttpServer::new(move || {
+            let (received, attempts) = (server_received.clone(), server_attempts.clone());
+            App::new().route("/telegraf", web::post().to(move |body: String| {
+                let (received, attempts) = (received.clone(), attempts.clone());
+                async move {
+                    if attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                        return HttpResponse::ServiceUnavailable().finish();
+                    }
+                    received.lock().unwrap().push(body);
+                    HttpResponse::NoContent().finish()
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}/telegraf", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        set_setting("WASMIOT_TELEGRAF_INTERVAL_SECONDS", "1", SettingSource::Api);
+        set_setting("WASMIOT_TELEGRAF_URL", url, SettingSource::Api);
+        let reporter = tokio::spawn(run_metrics_reporter());
+        for _ in 0..50 {
+            if !received.lock().unwrap().is_empty() {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        remove_setting("WASMIOT_TELEGRAF_URL");
+        remove_setting("WASMIOT_TELEGRAF_INTERVAL_SECONDS");
+        reporter.abort();
+        handle.stop(true).await;
+
+        let received = received.lock().unwrap();
+        assert!(attempts.load(std::sync::atomic::Ordering::SeqCst) >= 2);
+        assert!(received[0].contains("supervisor_executions_total,deployment=metrics-test-deployment,device="), "{}", received[0]);
+    }
+
+    #[actix_web::test]
+    async fn api_test_functions_invoked_as_wot_actions() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "wot-action-test-deployment";
+        deploy_answerer(deployment_id, serde_json::json!({}));
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+
+        // The Thing Description advertises the asynchronous forms next to the synchronous one
+        invalidate_description_cache();
+        let req = test::TestRequest::get().uri("/.well-known/wot-thing-description").to_request();
+        let td: Value = test::call_and_read_body_json(&app, req).await;
+        let forms = td["actions"][format!("{}/answerer/answer", deployment_id)]["forms"].as_array().unwrap().clone();
+        let ops: Vec<(&str, &str)> = forms.iter()
+            .map(|form| (form["op"].as_str().unwrap(), form["href"].as_str().unwrap()))
+            .collect();
+        assert!(ops.contains(&("invokeaction", "/wot-action-test-deployment/actions/answerer/answer")), "{:?}", ops);
+        assert!(ops.contains(&("queryaction", "/actions/{actionId}")), "{:?}", ops);
+        assert!(ops.contains(&("cancelaction", "/actions/{actionId}")), "{:?}", ops);
+
+        // Invoking answers at once with the status of the action, which is queried until done
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/actions/answerer/answer", deployment_id))
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let location = resp.headers().get("location").unwrap().to_str().unwrap().to_string();
+        let status: Value = test::read_body_json(resp).await;
+        let href = status["href"].as_str().unwrap().to_string();
+        assert!(location.ends_with(&href), "{} {}", location, href);
+        assert!(["pending", "running", "completed"].contains(&status["status"].as_str().unwrap()), "{}", status);
+        let mut status = Value::Null;
+        for _ in 0..50 {
+            let req = test::TestRequest::get().uri(&href).to_request();
+            status = test::call_and_read_body_json(&app, req).await;
+            if status["status"] == "completed" || status["status"] == "failed" {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(status["status"], "completed", "{}", status);
+        assert!(status.get("output").is_some(), "{}", status);
+        // A finished action cannot be cancelled anymore
+        let req = test::TestRequest::delete().uri(&href).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+
+        // An action waiting for an execution thread is cancelled, one running is not
+        let mut pending = RequestEntry::new(deployment_id.to_string(), "answerer".to_string(), "answer".to_string(), "POST".to_string(), serde_json::json!({}), HashMap::new(), chrono::Utc::now());
+        pending.request_id = "wot-action-test-pending".to_string();
+        register_action(&pending);
+        let req = test::TestRequest::delete().uri("/actions/wot-action-test-pending").to_request();
+        let status: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["status"], "failed", "{}", status);
+        assert!(!start_action("wot-action-test-pending"));
+        finish_action("wot-action-test-pending");
+
+        pending.request_id = "wot-action-test-running".to_string();
+        register_action(&pending);
+        assert!(start_action("wot-action-test-running"));
+        let req = test::TestRequest::delete().uri("/actions/wot-action-test-running").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::get().uri("/actions/wot-action-test-running").to_request();
+        let status: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["status"], "running", "{}", status);
+        finish_action("wot-action-test-running");
+
+        let req = test::TestRequest::delete().uri("/actions/no-such-action").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+        lock_deployments().remove(deployment_id);
+        invalidate_description_cache();
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[cfg(feature = "grpc")]
+    #[actix_web::test]
+    async fn api_test_grpc_api() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::grpc::proto::{self, supervisor_client::SupervisorClient, execution_event::Event};
+        use supervisor::lib::grpc::serve_grpc;
+
+        let deployment_id = "grpc-test-deployment";
+        deploy_answerer(deployment_id, serde_json::json!({}));
+
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_grpc(listener));
+        let mut client = SupervisorClient::connect(format!("http://{}", address)).await.unwrap();
+
+        // Manifests are refused as over HTTP
+        let refused = client.deploy(proto::DeployRequest { manifest_json: "{}".to_string(), wait: false, keep_partial: false }).await.unwrap_err();
+        assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+        assert_eq!(refused.message(), "Missing deploymentId");
+
+        let deployments = client.list_deployments(proto::ListDeploymentsRequest {}).await.unwrap().into_inner().deployments;
+        assert!(deployments.iter().any(|d| d.deployment_id == deployment_id && d.active && !d.degraded), "{:?}", deployments);
+
+        let run = proto::RunFunctionRequest {
+            deployment_id: deployment_id.to_string(),
+            module_name: "answerer".to_string(),
+            function_name: "answer".to_string(),
+            args_json: String::new(),
+            input: None,
+        };
+        let response = client.run_function(run.clone()).await.unwrap().into_inner();
+        let entry = response.entry.unwrap();
+        assert!(entry.success, "{:?}", entry);
+        assert_eq!(entry.method, "GRPC");
+        assert!(response.result_url.ends_with(&entry.request_id));
+
+        // The stream tells how the execution goes, ending with the executed request
+        let mut stream = client.run_function_stream(run.clone()).await.unwrap().into_inner();
+        let mut events = Vec::new();
+        while let Some(event) = stream.message().await.unwrap() {
+            events.push(event.event.unwrap());
+        }
+        assert!(matches!(events.first(), Some(Event::Started(_))), "{:?}", events);
+        assert!(events.iter().any(|event| matches!(event, Event::ReturnedJson(value) if value == "42")), "{:?}", events);
+        let Some(Event::Finished(finished)) = events.last() else { panic!("{:?}", events) };
+        let streamed = finished.entry.clone().unwrap();
+        assert!(streamed.success, "{:?}", streamed);
+
+        let history = client.get_request_history(proto::GetRequestHistoryRequest { request_id: streamed.request_id.clone() })
+            .await.unwrap().into_inner().entries;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].request_id, streamed.request_id);
+        let missing = client.get_request_history(proto::GetRequestHistoryRequest { request_id: "no-such-request".to_string() }).await.unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+
+        let mut wrong = run.clone();
+        wrong.module_name = "no-such-module".to_string();
+        assert_eq!(client.run_function(wrong).await.unwrap_err().code(), tonic::Code::NotFound);
+
+        client.delete_deployment(proto::DeleteDeploymentRequest { deployment_id: deployment_id.to_string() }).await.unwrap();
+        let gone = client.delete_deployment(proto::DeleteDeploymentRequest { deployment_id: deployment_id.to_string() }).await.unwrap_err();
+        assert_eq!(gone.code(), tonic::Code::NotFound);
+
+        server.abort();
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_device_twin() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::orchestrator_compat::{twin_endpoint, ApiVersion};
+
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let req = test::TestRequest::get().uri("/twin").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers().get("etag").unwrap().to_str().unwrap().to_string();
+        let twin: Value = test::read_body_json(resp).await;
+        assert_eq!(etag, format!("\"{}\"", twin["revision"]));
+        assert_eq!(twin["identity"]["name"], get_supervisor_config().name);
+        assert!(twin["capabilities"]["supervisorInterfaces"].is_array());
+        assert!(twin["config"]["healthThresholds"].is_object());
+        assert!(twin["health"]["status"].is_string());
+
+        // Other tests change deployments meanwhile, so the twin is asked again until it stays put
+        let mut not_modified = false;
+        for _ in 0..5 {
+            let req = test::TestRequest::get().uri("/twin").to_request();
+            let etag = test::call_service(&app, req).await.headers().get("etag").unwrap().to_str().unwrap().to_string();
+            let req = test::TestRequest::get().uri("/twin").insert_header(("If-None-Match", etag.clone())).to_request();
+            let resp = test::call_service(&app, req).await;
+            if resp.status() == StatusCode::NOT_MODIFIED {
+                assert_eq!(resp.headers().get("etag").unwrap().to_str().unwrap(), etag);
+                assert!(test::read_body(resp).await.is_empty());
+                not_modified = true;
+                break;
+            }
+        }
+        assert!(not_modified, "The twin never answered 304 Not Modified");
+
+        // Deploying changes the revision, and the twin lists the deployment
+        insert_deployment(Deployment::new("twin-test-deployment".to_string(), HashMap::new(), vec![], HashMap::new(), HashMap::new(), HashMap::new()));
+        invalidate_description_cache();
+        let req = test::TestRequest::get().uri("/twin").insert_header(("If-None-Match", etag.clone())).to_request();
+        let resp = test::call_service(&app, req).await;
+        lock_deployments().remove("twin-test-deployment");
+        invalidate_description_cache();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let changed: Value = test::read_body_json(resp).await;
+        assert!(changed["revision"].as_u64().unwrap() > twin["revision"].as_u64().unwrap());
+        let deployments = changed["deployments"].as_array().unwrap();
+        assert!(deployments.iter().any(|d| d["id"] == "twin-test-deployment"), "{:?}", deployments);
+
+        // Only orchestrators that know twins get them pushed
+        assert_eq!(twin_endpoint("http://orchestrator:3000/", ApiVersion::V3).as_deref(), Some("http://orchestrator:3000/api/device/twin"));
+        assert_eq!(twin_endpoint("http://orchestrator:3000", ApiVersion::V2), None);
+    }
+
+    #[actix_web::test]
+    async fn api_test_module_imports() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "imports-test-deployment";
+        let module_path = get_module_path(deployment_id, "importer");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module
+            (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+            (import "network" "ping" (func (param i32 i32 i32 i32) (result f32)))
+            (import "camera" "takeImage" (func (param i32)))
+            (import "env" "missing" (func))
+            (func (export "run") (result i32) (i32.const 1)))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "importer", None)).unwrap();
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("importer-id".to_string(), "importer".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        ));
+
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}/modules/importer/imports", deployment_id)).to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}/modules/nothing/imports", deployment_id)).to_request();
+        let missing_module = test::call_service(&app, req).await.status();
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(missing_module, StatusCode::NOT_FOUND);
+        assert_eq!(body["satisfied"], false);
+        let imports = body["imports"].as_array().unwrap();
+        let import = |name: &str| imports.iter().find(|import| import["name"] == name).unwrap().clone();
+
+        let proc_exit = import("proc_exit");
+        assert_eq!(proc_exit["provider"], "wasi");
+        assert_eq!(proc_exit["satisfied"], true);
+        assert_eq!(proc_exit["expected"], proc_exit["provided"]);
+        assert_eq!(proc_exit["expected"]["params"], serde_json::json!(["i32"]));
+
+        let ping = import("ping");
+        assert_eq!(ping["provider"], "host");
+        assert_eq!(ping["capability"], "network");
+        assert_eq!(ping["satisfied"], true);
+        assert_eq!(ping["provided"]["results"], serde_json::json!(["f32"]));
+
+        // Declared with a different signature than the camera function has
+        let take_image = import("takeImage");
+        assert_eq!(take_image["capability"], "camera");
+        assert_eq!(take_image["signatureMismatch"], true);
+        assert_eq!(take_image["satisfied"], false);
+        assert_eq!(take_image["provider"], "unsatisfied");
+        assert_eq!(take_image["provided"]["params"], serde_json::json!(["i32", "i32"]));
+
+        let missing = import("missing");
+        assert_eq!(missing["satisfied"], false);
+        assert_eq!(missing["provided"], Value::Null);
+        assert!(missing["problem"].as_str().unwrap().contains("env"));
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_healthchecks() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let push = |manifest: Value| {
+            let mut body = Vec::new();
+            let mut part = |name: &str, contents: &[u8]| {
+                body.extend_from_slice(format!("--healthcheck-boundary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes());
+                body.extend_from_slice(contents);
+                body.extend_from_slice(b"\r\n");
+            };
+            part("manifest", manifest.to_string().as_bytes());
+            part("good", br#"(module (func (export "self_test") (param i32) (result i32) (i32.sub (local.get 0) (i32.const 7))))"#);
+            part("bad", br#"(module (func (export "self_test") (result i32) (i32.const 1)))"#);
+            part("crashing", br#"(module (func (export "self_test") (result i32) (unreachable)))"#);
+            part("looping", br#"(module (func (export "self_test") (result i32) (loop (br 0)) (i32.const 0)))"#);
+            body.extend_from_slice(b"--healthcheck-boundary--\r\n");
+            test::TestRequest::post()
+                .uri("/deploy?wait=true")
+                .insert_header(("content-type", "multipart/form-data; boundary=healthcheck-boundary"))
+                .set_payload(body)
+                .to_request()
+        };
+        let manifest = |deployment_id: &str, policy: &str| serde_json::json!({
+            "deploymentId": deployment_id,
+            "healthcheckPolicy": policy,
+            "modules": [
+                { "id": "good-id", "name": "good", "healthcheck": { "function": "self_test", "args": [7], "expect": 0 } },
+                { "id": "bad-id", "name": "bad", "healthcheck": { "function": "self_test", "expect": 0 } },
+                { "id": "crashing-id", "name": "crashing", "healthcheck": { "function": "self_test" } },
+                { "id": "looping-id", "name": "looping", "healthcheck": { "function": "self_test", "expect": 0 } }
+            ]
+        });
+        set_setting("WASMIOT_HEALTHCHECK_TIMEOUT_SECONDS", "1", SettingSource::Api);
+
+        // A failed healthcheck marks its module degraded by default
+        let resp = test::call_service(&app, push(manifest("healthcheck-test-deployment", "degrade"))).await;
+        let status = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let report = |module: &str| body["healthchecks"].as_array().unwrap().iter().find(|report| report["module"] == module).unwrap().clone();
+        assert_eq!(report("good")["passed"], true);
+        assert_eq!(report("good")["returned"], 0);
+        assert_eq!(report("bad")["passed"], false);
+        assert_eq!(report("bad")["returned"], 1);
+        assert!(report("bad")["error"].as_str().unwrap().contains("instead of 0"));
+        // A trap fails the healthcheck, whatever it is expected to return
+        assert_eq!(report("crashing")["passed"], false);
+        assert!(report("crashing")["error"].as_str().unwrap().contains("unreachable"), "{}", report("crashing"));
+        // A function running over the time limit is stopped, not only given up on
+        assert_eq!(report("looping")["passed"], false);
+        assert!(report("looping")["error"].as_str().unwrap().contains("interrupt"), "{}", report("looping"));
+        assert!(report("looping")["durationMs"].as_u64().unwrap() < 5000, "{}", report("looping"));
+
+        // The healthchecks ran like any execution
+        let request_id = report("good")["requestId"].as_str().unwrap().to_string();
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+        let history: Value = test::read_body_json(test::call_service(&app, req).await).await;
+        assert_eq!(history["method"], HEALTHCHECK_METHOD, "{}", history);
+
+        let req = test::TestRequest::get().uri("/deploy/healthcheck-test-deployment").to_request();
+        let deployment: Value = test::read_body_json(test::call_service(&app, req).await).await;
+        assert!(deployment["degraded_modules"].get("bad").is_some(), "{}", deployment);
+        assert!(deployment["degraded_modules"].get("crashing").is_some(), "{}", deployment);
+        assert!(deployment["degraded_modules"].get("looping").is_some(), "{}", deployment);
+        assert!(deployment["degraded_modules"].get("good").is_none(), "{}", deployment);
+        let req = test::TestRequest::delete().uri("/deploy/healthcheck-test-deployment").to_request();
+        test::call_service(&app, req).await;
+
+        // ...or fails the deployment when the manifest says so
+        let resp = test::call_service(&app, push(manifest("healthcheck-fail-deployment", "fail"))).await;
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "One or more modules failed their healthcheck");
+        assert!(get_deployment("healthcheck-fail-deployment").is_none());
+        assert!(!MODULE_FOLDER.join("healthcheck-fail-deployment").exists());
+
+        let resp = test::call_service(&app, push(manifest("healthcheck-fail-deployment", "sometimes"))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_stats() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "stats-test-deployment";
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            Vec::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        ));
+        let invoke = |function: &str, success: bool, millis: u64| {
+            let mut entry = RequestEntry::new(
+                deployment_id.to_string(),
+                "mod".to_string(),
+                function.to_string(),
+                "GET".to_string(),
+                Value::Null,
+                HashMap::new(),
+                chrono::Utc::now(),
+            );
+            entry.success = success;
+            record_invocation(&entry, Duration::from_millis(millis));
+        };
+        for millis in 1..=100 {
+            invoke("fast", true, millis);
+        }
+        invoke("slow", false, 1000);
+
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}/stats", deployment_id)).to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        let req = test::TestRequest::get().uri("/deploy").to_request();
+        let list: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::get().uri("/deploy/no-such-deployment/stats").to_request();
+        let missing = test::call_service(&app, req).await.status();
+        lock_deployments().remove(deployment_id);
+        remove_stats(deployment_id);
+
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(missing, StatusCode::NOT_FOUND);
+        let fast = &body["functions"]["mod/fast"];
+        assert_eq!(fast["invocations"], 100);
+        assert_eq!(fast["successes"], 100);
+        assert_eq!(fast["failures"], 0);
+        assert!(fast["lastInvokedAt"].is_string());
+        // Percentiles are within the accuracy of the sketch
+        let p50 = fast["latencyMs"]["p50"].as_f64().unwrap();
+        let p99 = fast["latencyMs"]["p99"].as_f64().unwrap();
+        assert!((p50 - 50.0).abs() <= 2.0, "{}", p50);
+        assert!((p99 - 99.0).abs() <= 3.0, "{}", p99);
+        assert_eq!(body["functions"]["mod/slow"]["failures"], 1);
+        assert_eq!(body["total"]["invocations"], 101);
+        assert_eq!(body["total"]["latencyMs"]["max"], 1000.0);
+
+        let summary = list["deployments"].as_array().unwrap().iter()
+            .find(|deployment| deployment["id"] == deployment_id)
+            .map(|deployment| deployment["stats"].clone())
+            .unwrap();
+        assert_eq!(summary["invocations"], 101);
+        assert_eq!(summary["failures"], 1);
+    }
+
+    #[actix_web::test]
+    async fn api_test_isolated_mount_layout() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Adds up the WASI errnos of reading model.pb from /data (fd 3) and from /work (fd 4),
+        // and 10000 if it could be opened for writing in /data
+        let checker = r#"(module
+            (import "wasi_snapshot_preview1" "path_open" (func $open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "model.pb")
+            (func (export "check") (result i32)
+                (i32.add
+                    (i32.add
+                        (call $open (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 8) (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64))
+                        (i32.mul (i32.const 100)
+                            (call $open (i32.const 4) (i32.const 0) (i32.const 16) (i32.const 8) (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64))))
+                    (i32.mul (i32.const 10000)
+                        (i32.eqz (call $open (i32.const 3) (i32.const 0) (i32.const 16) (i32.const 8) (i32.const 8) (i64.const 64) (i64.const 0) (i32.const 0) (i32.const 64)))))))"#;
+        let deployment_id = "layout-test-deployment";
+        let module_path = get_module_path(deployment_id, "checker");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, checker).unwrap();
+        let model_path = get_params_path(deployment_id, "checker", Some("model.pb"));
+        std::fs::create_dir_all(model_path.parent().unwrap()).unwrap();
+        std::fs::write(&model_path, "weights").unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/checker/check", deployment_id),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        // An input with the same name as the model of the deployment
+        let mount = serde_json::json!({ "path": "model.pb", "media_type": "application/octet-stream", "stage": "execution" });
+        let mut config = ModuleConfig::new(
+            "checker-id".to_string(),
+            "checker".to_string(),
+            module_path.clone(),
+            HashMap::from([("model.pb".to_string(), model_path.to_string_lossy().to_string())]),
+            None,
+        );
+        config.mount_layout = MountLayout::Isolated;
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![config],
+            HashMap::from([("checker".to_string(), HashMap::from([("check".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "checker": { "check": { "from": endpoint, "to": null } } })).unwrap(),
+            serde_json::from_value(serde_json::json!({ "checker": { "check": { "execution": [mount] } } })).unwrap(),
+        ));
+
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let body = "--layout-boundary\r\nContent-Disposition: form-data; name=\"model.pb\"; filename=\"upload.pb\"\r\n\r\ninput\r\n--layout-boundary--\r\n";
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/modules/checker/check", deployment_id))
+            .insert_header(("content-type", "multipart/form-data; boundary=layout-boundary"))
+            .set_payload(body)
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+        let entry: Value = test::call_and_read_body_json(&app, req).await;
+        let model = std::fs::read_to_string(&model_path);
+        let input_left = get_params_path(deployment_id, "checker", Some("work/model.pb")).exists();
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        // Both files are seen, and the model is neither replaced by the input nor writable
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert_eq!(entry["result"], "0", "{}", entry);
+        assert_eq!(model.unwrap(), "weights");
+        assert!(!input_left);
+    }
+
+    #[actix_web::test]
+    async fn api_test_validate_execution() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "validate-test-deployment";
+        let module_path = get_module_path(deployment_id, "adder");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module
+            (func (export "add") (param i64) (result i32) (i32.wrap_i64 (local.get 0))))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "adder", None)).unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/adder/add", deployment_id),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let next = serde_json::json!({
+            "url": "http://next-device:8080",
+            "path": "/other-deployment/modules/printer/print",
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let mount = serde_json::json!({ "path": "input.txt", "media_type": "text/plain", "stage": "execution" });
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("adder-id".to_string(), "adder".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::from([("adder".to_string(), HashMap::from([("add".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "adder": { "add": { "from": endpoint, "to": next } } })).unwrap(),
+            serde_json::from_value(serde_json::json!({ "adder": { "add": { "execution": [mount] } } })).unwrap(),
+        ));
+
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let validate = |query: &str, with_file: bool| {
+            let req = test::TestRequest::post().uri(&format!("/{}/modules/adder/add/validate?{}", deployment_id, query));
+            match with_file {
+                true => req
+                    .insert_header(("content-type", "multipart/form-data; boundary=validate-boundary"))
+                    .set_payload("--validate-boundary\r\nContent-Disposition: form-data; name=\"input.txt\"; filename=\"upload.txt\"\r\n\r\nhello\r\n--validate-boundary--\r\n")
+                    .to_request(),
+                false => req.set_json(serde_json::json!({})).to_request(),
+            }
+        };
+
+        // Everything the execution needs is there
+        let resp = test::call_service(&app, validate("a=2", true)).await;
+        let status = resp.status();
+        let valid: Value = test::read_body_json(resp).await;
+
+        // Missing files and arguments that do not fit the parameters are reported
+        let resp = test::call_service(&app, validate("a=two", false)).await;
+        let invalid_status = resp.status();
+        let invalid: Value = test::read_body_json(resp).await;
+        let resp = test::call_service(&app, validate("", true)).await;
+        let too_few_status = resp.status();
+        let too_few: Value = test::read_body_json(resp).await;
+
+        let req = test::TestRequest::get().uri("/request-history").to_request();
+        let history: Value = test::call_and_read_body_json(&app, req).await;
+        let inputs_left = get_params_path(deployment_id, "adder", Some("inputs")).read_dir()
+            .map_or(0, |entries| entries.count());
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        assert_eq!(status, StatusCode::OK, "{}", valid);
+        assert_eq!(valid["valid"], true);
+        assert_eq!(valid["args"], serde_json::json!([{ "name": "a", "type": "i64", "value": 2 }]));
+        assert_eq!(valid["results"], serde_json::json!(["i32"]));
+        assert_eq!(valid["mounts"][0]["path"], "input.txt");
+        assert_eq!(valid["mounts"][0]["guestPath"], "input.txt");
+        assert_eq!(valid["next"]["url"], "http://next-device:8080");
+        assert_eq!(valid["next"]["path"], "/other-deployment/modules/printer/print");
+
+        assert_eq!(invalid_status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(invalid["valid"], false);
+        let problems = invalid["problems"].to_string();
+        assert!(problems.contains("Required input files missing"), "{}", problems);
+        assert!(problems.contains("'a'"), "{}", problems);
+        assert_eq!(too_few_status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(too_few["problems"][0].as_str().unwrap().contains("has 1 parameters but 0 arguments"), "{}", too_few);
+
+        // Nothing was run, recorded or left behind
+        assert!(!history.to_string().contains(deployment_id), "{}", history);
+        assert_eq!(inputs_left, 0);
+    }
+
+    #[actix_web::test]
+    async fn api_test_standby_replicas() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // A replica that takes pushed deployments and describes them back with their digests
+        let pushes: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+        let server_pushes = pushes.clone();
+        let server = HttpServer::new(move || {
+            let get_pushes = server_pushes.clone();
+            let post_pushes = server_pushes.clone();
+            App::new()
+                .route("/deploy/{deployment_id}", web::get().to(move || {
+                    let pushes = get_pushes.clone();
+                    async move {
+                        let Some(body) = pushes.lock().unwrap().last().cloned() else {
+                            return HttpResponse::NotFound().finish();
+                        };
+                        let start = body.find("{\"").unwrap();
+                        let manifest: Value = serde_json::Deserializer::from_str(&body[start..])
+                            .into_iter::<Value>().next().unwrap().unwrap();
+                        let module = &manifest["modules"][0];
+                        HttpResponse::Ok().json(serde_json::json!({
+                            "standby": manifest["standby"],
+                            "_modules": [{
+                                "name": module["name"],
+                                "binary_source": { "url": "file:///pushed", "sha256": module["urls"]["binary"]["sha256"] },
+                                "data_file_sources": {}
+                            }]
+                        }))
+                    }
+                }))
+                .route("/deploy", web::post().to(move |body: web::Bytes| {
+                    let pushes = post_pushes.clone();
+                    async move {
+                        pushes.lock().unwrap().push(String::from_utf8_lossy(&body).to_string());
+                        HttpResponse::Ok().json(serde_json::json!({ "status": "success" }))
+                    }
+                }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let replica = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let deployment_id = "replicated-test-deployment";
+        let binary = b"\0asm replicated module".to_vec();
+        let path = std::env::temp_dir().join("replicated-test-module.wasm");
+        std::fs::write(&path, &binary).unwrap();
+        let mut deployment = Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("echo-id".to_string(), "echo".to_string(), path.clone(), HashMap::new(), None)],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        deployment.replicas = vec![replica.clone(), "http://127.0.0.1:1".to_string()];
+        insert_deployment(deployment);
+
+        // The replica missing the deployment is pushed it, and left alone once it holds it
+        sync_replicas(deployment_id).await;
+        sync_replicas(deployment_id).await;
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}/replicas", deployment_id)).to_request();
+        let statuses: Value = test::call_and_read_body_json(&app, req).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_file(&path).ok();
+        handle.stop(false).await;
+
+        let pushes = pushes.lock().unwrap().clone();
+        assert_eq!(pushes.len(), 1);
+        assert!(pushes[0].contains("name=\"echo\""), "{}", pushes[0]);
+        assert!(pushes[0].contains("\"standby\":true"), "{}", pushes[0]);
+        assert!(pushes[0].contains(&hex::encode(Sha256::digest(&binary))), "{}", pushes[0]);
+        assert_eq!(statuses["replicas"][0]["url"], replica.as_str());
+        assert_eq!(statuses["replicas"][0]["state"], "synced", "{}", statuses);
+        assert!(statuses["replicas"][0]["syncedAt"].is_string());
+        assert_eq!(statuses["replicas"][1]["state"], "unreachable", "{}", statuses);
+
+        // A standby copy is held paused until it is promoted
+        let standby_id = "standby-test-deployment";
+        let mut standby = Deployment::new(
+            standby_id.to_string(),
+            HashMap::new(),
+            Vec::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        );
+        standby.standby = true;
+        standby.active = false;
+        insert_deployment(standby);
+        let promote = |id: &str| test::TestRequest::post().uri(&format!("/deploy/{}/promote", id)).to_request();
+        let resp = test::call_service(&app, promote(standby_id)).await;
+        let promoted = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        let again = test::call_service(&app, promote(standby_id)).await.status();
+        let missing = test::call_service(&app, promote("no-such-deployment")).await.status();
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}", standby_id)).to_request();
+        let described: Value = test::call_and_read_body_json(&app, req).await;
+        lock_deployments().remove(standby_id);
+        std::fs::remove_file(get_deployment_path(standby_id)).ok();
+
+        assert_eq!(promoted, StatusCode::OK, "{}", body);
+        assert_eq!(body["active"], true);
+        assert_eq!(again, StatusCode::CONFLICT);
+        assert_eq!(missing, StatusCode::NOT_FOUND);
+        assert_eq!(described["standby"], false);
+
+        let req = test::TestRequest::post().uri("/deploy?wait=true")
+            .set_json(serde_json::json!({
+                "deploymentId": "invalid-replicas-deployment",
+                "modules": [{ "id": "m", "name": "m", "urls": { "binary": "http://127.0.0.1:1/m.wasm" } }],
+                "replicas": ["not a url"]
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn api_test_declared_parameters() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "parameters-test-deployment";
+        let module_path = get_module_path(deployment_id, "calc");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module
+            (func (export "sub") (param i32 i64) (result i64)
+                (i64.sub (i64.extend_i32_s (local.get 0)) (local.get 1))))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "calc", None)).unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/calc/sub", deployment_id),
+            "method": "POST",
+            "request": {
+                "parameters": [
+                    { "name": "minuend", "in": "query", "required": true, "schema": { "type": "integer", "format": "int32" } },
+                    { "name": "subtrahend", "in": "query", "required": false, "schema": { "type": "integer", "format": "int64", "default": 1 } }
+                ],
+                "request_body": null
+            },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("calc-id".to_string(), "calc".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::from([("calc".to_string(), HashMap::from([("sub".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "calc": { "sub": { "from": endpoint, "to": null } } })).unwrap(),
+            HashMap::new(),
+        ));
+
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let validate = |query: &str| test::TestRequest::post()
+            .uri(&format!("/{}/modules/calc/sub/validate?{}", deployment_id, query))
+            .set_json(serde_json::json!({}))
+            .to_request();
+
+        // Arguments are matched to the parameters by name, whatever order they are given in
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/modules/calc/sub", deployment_id))
+            .set_json(serde_json::json!({ "subtrahend": "3", "minuend": 10 }))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+        let entry: Value = test::call_and_read_body_json(&app, req).await;
+        let shuffled: Value = test::call_and_read_body_json(&app, validate("subtrahend=3&minuend=10")).await;
+        let defaulted: Value = test::call_and_read_body_json(&app, validate("minuend=10")).await;
+
+        // Arguments that do not fit the parameters are refused
+        let resp = test::call_service(&app, validate("minuend=3000000000")).await;
+        let overflow_status = resp.status();
+        let overflow: Value = test::read_body_json(resp).await;
+        let missing: Value = test::call_and_read_body_json(&app, validate("subtrahend=2")).await;
+        let unknown: Value = test::call_and_read_body_json(&app, validate("minuend=1&extra=2&other=3")).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert_eq!(entry["result"], "7", "{}", entry);
+        assert_eq!(shuffled["args"], serde_json::json!([
+            { "name": "minuend", "type": "i32", "value": 10 },
+            { "name": "subtrahend", "type": "i64", "value": 3 }
+        ]), "{}", shuffled);
+        assert_eq!(defaulted["valid"], true, "{}", defaulted);
+        assert_eq!(defaulted["args"][1]["value"], 1);
+        assert_eq!(overflow_status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(overflow["problems"][0].as_str().unwrap().contains("must be a 32-bit integer"), "{}", overflow);
+        assert!(missing["problems"][0].as_str().unwrap().contains("Missing required parameter 'minuend'"), "{}", missing);
+        assert!(unknown["problems"][0].as_str().unwrap().contains("Unknown parameters: extra, other"), "{}", unknown);
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_admission() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let deploy = |deployment_id: &str, requires: Value, module_requires: Value| test::TestRequest::post()
+            .uri("/deploy?wait=true")
+            .set_json(serde_json::json!({
+                "deploymentId": deployment_id,
+                "modules": [{
+                    "id": "m-id",
+                    "name": "m",
+                    "urls": { "binary": "http://127.0.0.1:1/m.wasm" },
+                    "requires": module_requires
+                }],
+                "requires": requires
+            }))
+            .to_request();
+
+        // Every requirement the device does not meet is listed
+        let resp = test::call_service(&app, deploy(
+            "unadmitted-test-deployment",
+            serde_json::json!({ "capabilities": ["network", "teleporter"], "minMemoryBytes": u64::MAX, "architectures": ["pdp11"] }),
+            serde_json::json!({ "minFreeDiskBytes": u64::MAX }),
+        )).await;
+        let status = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        let unmet = body["unmet"].as_array().unwrap();
+        assert_eq!(unmet.len(), 4, "{}", body);
+        assert_eq!(unmet[0]["requirement"], "capabilities");
+        assert_eq!(unmet[0]["required"], serde_json::json!(["teleporter"]));
+        assert_eq!(unmet[1]["requirement"], "minMemoryBytes");
+        assert_eq!(unmet[2]["requirement"], "architectures");
+        assert_eq!(unmet[3]["requirement"], "minFreeDiskBytes");
+        assert_eq!(unmet[3]["module"], "m");
+
+        // Requirements the device meets let the deployment through to downloading its modules
+        let resp = test::call_service(&app, deploy(
+            "admitted-test-deployment",
+            serde_json::json!({ "capabilities": ["network"], "minMemoryBytes": 1, "architectures": [std::env::consts::ARCH] }),
+            Value::Null,
+        )).await;
+        let admitted = resp.status();
+        let resp = test::call_service(&app, deploy("invalid-requires-deployment", serde_json::json!({ "minMemory": 1 }), Value::Null)).await;
+        let invalid = resp.status();
+        let req = test::TestRequest::get().uri("/.well-known/wasmiot-device-description").to_request();
+        let description: Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_ne!(admitted, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_ne!(admitted, StatusCode::OK);
+        assert_eq!(invalid, StatusCode::BAD_REQUEST);
+        let facts = &description["admission"];
+        assert!(facts["capabilities"].as_array().unwrap().contains(&serde_json::json!("network")), "{}", facts);
+        assert!(facts["memoryBytes"].as_u64().unwrap() > 0);
+        assert!(facts["architectures"].as_array().unwrap().contains(&serde_json::json!(std::env::consts::ARCH)));
+    }
+
+    #[actix_web::test]
+    async fn api_test_typed_instructions_and_mounts() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": "/typed-test-deployment/modules/m/f",
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let deploy = |instructions: Value, mounts: Value| test::TestRequest::post()
+            .uri("/deploy?wait=true")
+            .set_json(serde_json::json!({
+                "deploymentId": "typed-test-deployment",
+                "modules": [{ "id": "m-id", "name": "m", "urls": { "binary": "http://127.0.0.1:1/m.wasm" } }],
+                "instructions": instructions,
+                "mounts": mounts
+            }))
+            .to_request();
+
+        // Invalid entries are refused before anything is downloaded, naming where they are
+        let cases = [
+            (serde_json::json!({ "modules": { "m": { "f": { "to": null } } } }), Value::Null, "instructions.modules.m.f: missing field `from`"),
+            (Value::Null, serde_json::json!({ "m": { "f": { "input": [] } } }), "mounts.m.f.input: unknown stage"),
+            (Value::Null, serde_json::json!({ "m": { "f": { "execution": [{ "path": "in.txt", "stage": "execution" }] } } }), "mounts.m.f.execution[0]: missing field `media_type`"),
+            (Value::Null, serde_json::json!({ "m": { "f": { "execution": [{ "path": "in.txt", "media_type": "text/plain", "stage": "output" }] } } }), "mounts.m.f.execution[0]: stage 'output'"),
+        ];
+        for (instructions, mounts, error) in cases {
+            let resp = test::call_service(&app, deploy(instructions, mounts)).await;
+            let status = resp.status();
+            let body: Value = test::read_body_json(resp).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+            assert!(body["error"].as_str().unwrap().starts_with(error), "{}", body);
+        }
+
+        // Deployments saved with raw instructions and mounts are parsed when loaded, and saved typed
+        let mut deployment: Deployment = serde_json::from_value(serde_json::json!({
+            "id": "typed-test-deployment",
+            "_modules": [],
+            "endpoints": {},
+            "_instructions": { "modules": { "m": { "f": { "from": endpoint, "to": null } } } },
+            "_mounts": { "m": { "f": { "execution": [{ "path": "in.txt", "media_type": "text/plain", "stage": "execution" }] } } }
+        })).unwrap();
+        deployment.init();
+        assert_eq!(deployment.instructions["m"]["f"].from.path, "/typed-test-deployment/modules/m/f");
+        assert_eq!(deployment.mounts["m"]["f"][&MountStage::EXECUTION][0].path, "in.txt");
+        let saved = serde_json::to_value(&deployment).unwrap();
+        assert!(saved.get("_instructions").is_none() && saved.get("_mounts").is_none(), "{}", saved);
+        assert_eq!(saved["mounts"]["m"]["f"]["execution"][0]["media_type"], "text/plain");
+        let reloaded: Deployment = serde_json::from_value(saved).unwrap();
+        assert!(reloaded.instructions["m"]["f"].to.is_none());
+    }
+
+    #[actix_web::test]
+    async fn api_test_content_negotiation() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "negotiation-test-deployment";
+        deploy_answerer(deployment_id, serde_json::json!({}));
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+                .route("/request-history", web::get().to(request_history_list_1))
+        ).await;
+        let get = |uri: &str, accept: Option<&str>| {
+            let mut req = test::TestRequest::get().uri(uri);
+            if let Some(accept) = accept {
+                req = req.insert_header(("Accept", accept));
+            }
+            req.to_request()
+        };
+        let run_uri = format!("/{}/modules/answerer/answer", deployment_id);
+
+        // Executions are answered as CBOR of the same structure as the JSON
+        let resp = test::call_service(&app, get(&run_uri, Some("application/cbor"))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/cbor");
+        assert_eq!(resp.headers().get("vary").unwrap(), "Accept");
+        let body = test::read_body(resp).await;
+        let response: Value = ciborium::from_reader(body.as_ref()).unwrap();
+        assert_eq!(response["result"]["result"], "42", "{}", response);
+        let request_id = response["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+
+        // Browsers get JSON from executions, which have no page, and unknown types fall back to JSON
+        let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+        for accept in [None, Some(browser), Some("application/xml")] {
+            let resp = test::call_service(&app, get(&run_uri, accept)).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers().get("content-type").unwrap(), "application/json", "{:?}", accept);
+            let response: Value = test::read_body_json(resp).await;
+            assert_eq!(response["result"]["result"], "42");
+        }
+
+        // The request history is also a readable page, with the output links clickable
+        let resp = test::call_service(&app, get(&format!("/request-history/{}", request_id), Some(browser))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get("content-type").unwrap().to_str().unwrap().starts_with("text/html"));
+        let page = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(page.starts_with("<!DOCTYPE html>"), "{}", page);
+        assert!(page.contains(&format!("<h1>Request {}</h1>", request_id)), "{}", page);
+        assert!(page.contains("<th>deploymentId</th><td>negotiation-test-deployment</td>"), "{}", page);
+
+        // The quality of the types decides between them
+        let resp = test::call_service(&app, get("/request-history", Some("text/html;q=0.5, application/cbor"))).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/cbor");
+        let body = test::read_body(resp).await;
+        let history: Value = ciborium::from_reader(body.as_ref()).unwrap();
+        assert!(history.as_array().unwrap().iter().any(|entry| entry["requestId"] == request_id.as_str()));
+        let resp = test::call_service(&app, get("/request-history/no-such-request", Some("application/cbor"))).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn api_test_registration_status() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let zc = Arc::new(parking_lot::Mutex::new(WebthingZeroconf::new()));
+        zc.lock().register_renewal_time = 60;
+        let app = test::init_service(App::new().app_data(web::Data::new(zc.clone())).configure(configure_routes)).await;
+        let req = test::TestRequest::get().uri("/registration").to_request();
+        let status: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(status["serviceName"], zc.lock().service_name.as_str(), "{}", status);
+        assert_eq!(status["serviceType"], "_webthing._tcp");
+        assert_eq!(status["port"], zc.lock().port);
+        assert_eq!(status["renewalIntervalSeconds"], 60);
+        assert!(status["lastRegisteredAt"].is_string(), "{}", status);
+        // The advertisement has not been started, so the watchdog has had nothing to restart
+        assert_eq!(status["advertising"], false);
+        assert_eq!(status["watchdog"]["restarts"], 0);
+        assert!(status["watchdog"]["lastRestartAt"].is_null());
+
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let req = test::TestRequest::get().uri("/registration").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[actix_web::test]
+    async fn api_test_audit_log() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(
+            App::new()
+                .route("/audit", web::get().to(audit_log))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+        ).await;
+        let audit_request = |token: Option<&str>, query: &str| {
+            let mut req = test::TestRequest::get().uri(&format!("/audit{}", query));
+            if let Some(token) = token {
+                req = req.insert_header(("Authorization", format!("Bearer {}", token)));
+            }
+            req.to_request()
+        };
+
+        // The audit log is not served before a token is set, and only with that token after
+        remove_setting("WASMIOT_API_TOKEN");
+        let resp = test::call_service(&app, audit_request(Some("anything"), "")).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        set_setting("WASMIOT_API_TOKEN", "audit-test-token", SettingSource::Environment);
+        set_setting("WASMIOT_AUDIT_HMAC_KEY", "audit-test-key", SettingSource::Environment);
+        let resp = test::call_service(&app, audit_request(None, "")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = test::call_service(&app, audit_request(Some("wrong-token"), "")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let since = chrono::Utc::now().to_rfc3339();
+
+        // Deleting a deployment that does not exist is audited as a failure
+        let deployment_id = "audit-test-deployment";
+        let resp = test::call_service(&app, test::TestRequest::delete().uri(&format!("/deploy/{}", deployment_id)).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // Executions are audited when they start and finish
+        deploy_answerer(deployment_id, serde_json::json!({}));
+        let resp = test::call_service(&app, test::TestRequest::get()
+            .uri(&format!("/{}/modules/answerer/answer", deployment_id))
+            .peer_addr("192.0.2.7:40000".parse().unwrap())
+            .to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let query = format!("?since={}", urlencoding::encode(&since));
+        let resp = test::call_service(&app, audit_request(Some("audit-test-token"), &query)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["verified"], true, "{}", body);
+        let entries = body["entries"].as_array().unwrap();
+        let of_test = |event: &str| entries.iter()
+            .find(|entry| entry["event"] == event
+                && (entry["details"]["deploymentId"] == deployment_id || entry["details"]["deployment"] == deployment_id))
+            .unwrap_or_else(|| panic!("No {} in {}", event, body))
+            .clone();
+        let deleted = of_test("deployment.delete");
+        assert_eq!(deleted["details"]["success"], false);
+        let started = of_test("execution.start");
+        let finished = of_test("execution.finish");
+        assert_eq!(started["details"]["requestId"], finished["details"]["requestId"]);
+        assert_eq!(finished["details"]["caller"], "192.0.2.7");
+        assert_eq!(finished["details"]["function"], "answer");
+        assert_eq!(finished["details"]["success"], true);
+        assert!(started["seq"].as_u64() < finished["seq"].as_u64());
+
+        // Editing an entry breaks the chain
+        let mut tampered = entries.clone();
+        let index = tampered.iter().position(|entry| entry["seq"] == finished["seq"]).unwrap();
+        tampered[index]["details"]["success"] = serde_json::json!(false);
+        assert_eq!(supervisor::lib::audit::verify_chain(&tampered), Some(false));
+
+        let resp = test::call_service(&app, audit_request(Some("audit-test-token"), "?limit=x")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = test::call_service(&app, audit_request(Some("audit-test-token"), "?limit=1")).await;
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["entries"].as_array().unwrap().len(), 1);
+
+        delete_deployment(deployment_id, "test").await;
+        remove_setting("WASMIOT_API_TOKEN");
+        remove_setting("WASMIOT_AUDIT_HMAC_KEY");
+    }
+
+    #[actix_web::test]
+    async fn api_test_execution_priority() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::execution::{execution_saturated, run_with_priority, should_shed};
+        use supervisor::structs::request_entry::Priority;
+
+        let deployment_id = "priority-test-deployment";
+        deploy_answerer(deployment_id, serde_json::json!({ "priority": "low" }));
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let run = |priority: Option<&str>| {
+            let mut req = test::TestRequest::get().uri(&format!("/{}/modules/answerer/answer", deployment_id));
+            if let Some(priority) = priority {
+                req = req.insert_header(("X-Priority", priority));
+            }
+            req.to_request()
+        };
+
+        let resp = test::call_service(&app, run(Some("urgent"))).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Keep every execution thread busy
+        let mut releases = Vec::new();
+        let mut blockers = Vec::new();
+        for _ in 0..supervisor::lib::constants::get_execution_threads() {
+            let (release, released) = tokio::sync::oneshot::channel::<()>();
+            let (started, has_started) = tokio::sync::oneshot::channel::<()>();
+            releases.push(release);
+            blockers.push(tokio::spawn(run_with_priority(Priority::Normal, move || async move {
+                let _ = started.send(());
+                let _ = released.await;
+            })));
+            has_started.await.unwrap();
+        }
+        assert!(execution_saturated());
+
+        // Waiting jobs are run highest priority first, whatever order they were queued in
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiting = Vec::new();
+        for (priority, name) in [(Priority::Low, "low"), (Priority::Normal, "normal"), (Priority::High, "high")] {
+            let order = order.clone();
+            waiting.push(tokio::spawn(run_with_priority(priority, move || async move {
+                order.lock().unwrap().push(name);
+            })));
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        // Low priority requests are shed while saturated when configured to be
+        assert!(!should_shed(Priority::Low));
+        set_setting("WASMIOT_SHED_LOW_PRIORITY", "true", SettingSource::Environment);
+        assert!(should_shed(Priority::Low));
+        assert!(!should_shed(Priority::Normal));
+        let resp = test::call_service(&app, run(None)).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS, "the endpoint defaults to low priority");
+        assert!(resp.headers().contains_key("retry-after"));
+        remove_setting("WASMIOT_SHED_LOW_PRIORITY");
+
+        for release in releases {
+            release.send(()).unwrap();
+        }
+        for job in blockers.into_iter().chain(waiting) {
+            job.await.unwrap().unwrap();
+        }
+        let order = order.lock().unwrap().clone();
+        assert_eq!(order, vec!["high", "normal", "low"]);
+
+        // The priority and the time waited for a thread are recorded on the request
+        let resp = test::call_service(&app, run(Some("High"))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let response: Value = test::read_body_json(resp).await;
+        let request_id = response["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request()).await;
+        let entry: Value = test::read_body_json(resp).await;
+        assert_eq!(entry["priority"], "high", "{}", entry);
+        assert!(entry["queueWaitMs"].is_u64(), "{}", entry);
+
+        delete_deployment(deployment_id, "test").await;
+    }
+
+    #[actix_web::test]
+    async fn api_test_startup_loading() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::startup::{load_deployments, mark_loading};
+
+        // A saved deployment that loads and one that does not
+        let deployment_id = "startup-test-deployment";
+        let broken_id = "startup-broken-deployment";
+        let deployment = answerer_deployment(deployment_id, serde_json::json!({}));
+        let saved = get_deployment_path(deployment_id);
+        let broken = get_deployment_path(broken_id);
+        std::fs::create_dir_all(saved.parent().unwrap()).unwrap();
+        std::fs::write(&saved, serde_json::to_string(&deployment).unwrap()).unwrap();
+        std::fs::write(&broken, "{ not a deployment").unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .route("/deploy/{deployment_id}", web::get().to(deployment_get_by_id))
+                .route("/deploy/{deployment_id}", web::delete().to(deployment_delete))
+                .route("/startup-report", web::get().to(startup_report_get))
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::get().to(run_module_function_3))
+        ).await;
+        let get = |uri: String| test::TestRequest::get().uri(&uri).to_request();
+        let run_uri = format!("/{}/modules/answerer/answer", deployment_id);
+
+        // Until the deployments are loaded, they are answered as loading
+        let files = vec![saved.clone(), broken.clone()];
+        mark_loading(&files);
+        let resp = test::call_service(&app, get(format!("/deploy/{}", deployment_id))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["loadState"], "loading", "{}", body);
+        let resp = test::call_service(&app, get(run_uri.clone())).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(resp.headers().contains_key("retry-after"));
+
+        load_deployments(files).await;
+
+        let resp = test::call_service(&app, get(format!("/deploy/{}", deployment_id))).await;
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["loadState"], "ready", "{}", body);
+        let resp = test::call_service(&app, get(run_uri.clone())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = test::call_service(&app, get(format!("/deploy/{}", broken_id))).await;
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["loadState"], "failed", "{}", body);
+        assert!(body["loadError"].as_str().unwrap().contains("deserialize"), "{}", body);
+
+        // The report tells how loading each deployment went
+        let resp = test::call_service(&app, get("/startup-report".to_string())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let report: Value = test::read_body_json(resp).await;
+        assert!(report["finishedAt"].is_string(), "{}", report);
+        assert!(report["loaded"].as_u64().unwrap() >= 1 && report["failed"].as_u64().unwrap() >= 1, "{}", report);
+        let load_of = |id: &str| report["deployments"].as_array().unwrap().iter()
+            .find(|load| load["deploymentId"] == id)
+            .unwrap_or_else(|| panic!("No {} in {}", id, report))
+            .clone();
+        assert_eq!(load_of(deployment_id)["state"], "ready");
+        assert!(load_of(deployment_id)["durationMs"].is_u64());
+        assert_eq!(load_of(broken_id)["state"], "failed");
+
+        test::call_service(&app, test::TestRequest::delete().uri(&format!("/deploy/{}", deployment_id)).to_request()).await;
+        std::fs::remove_file(&broken).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_dev_run() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let module = r#"(module (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1))))"#;
+        let body = |module: &str, function: &str, args: &str| format!(
+            "--dev-boundary\r\nContent-Disposition: form-data; name=\"module\"; filename=\"add.wasm\"\r\n\r\n{}\r\n\
+             --dev-boundary\r\nContent-Disposition: form-data; name=\"function\"\r\n\r\n{}\r\n\
+             --dev-boundary\r\nContent-Disposition: form-data; name=\"args\"\r\n\r\n{}\r\n\
+             --dev-boundary--\r\n",
+            module, function, args
+        );
+        let run_module = |module: &str, function: &str, args: &str| test::TestRequest::post()
+            .uri("/dev/run")
+            .insert_header(("content-type", "multipart/form-data; boundary=dev-boundary"))
+            .set_payload(body(module, function, args))
+            .to_request();
+        let run = |function: &str, args: &str| run_module(module, function, args);
+
+        // Not routed at all unless developer mode is on
+        remove_setting("WASMIOT_DEV_MODE");
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let resp = test::call_service(&app, run("add", r#"{"a": 1, "b": 2}"#)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        set_setting("WASMIOT_DEV_MODE", "true", SettingSource::Api);
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        remove_setting("WASMIOT_DEV_MODE");
+        let dev_runs = || std::fs::read_dir(&*supervisor::lib::constants::MODULE_FOLDER)
+            .map(|dir| dir.filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("dev-run-"))
+                .count())
+            .unwrap_or(0);
+
+        // Arguments are coerced to the parameters of the function like for deployed modules
+        let resp = test::call_service(&app, run("add", r#"{"a": "40", "b": 2}"#)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["result"]["result"], serde_json::json!("42"));
+
+        // Errors of the run are reported like for deployed modules
+        let resp = test::call_service(&app, run_module("(module (func", "add", "{}")).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert!(body["error"].is_string());
+
+        let resp = test::call_service(&app, run("add", "[1, 2]")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Nothing is left behind
+        assert_eq!(dev_runs(), 0);
+        assert!(!supervisor::lib::api::lock_deployments().keys().any(|id| id.starts_with("dev-run-")));
+    }
+
+    #[actix_web::test]
+    async fn api_test_device_limits() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::configuration::get_device_description;
+        use supervisor::lib::device_limits::{check_device_limits, device_usage, LimitExceeded};
+        use supervisor::lib::health::take_health_snapshot;
+        use supervisor::structs::supervisor_config::DeviceLimits;
+
+        let deployment_id = "device-limits-test-deployment";
+        let module_path = get_module_path(deployment_id, "limited");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, vec![0u8; 100]).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "limited", None)).unwrap();
+        std::fs::write(get_params_path(deployment_id, "limited", Some("model.bin")), vec![0u8; 50]).unwrap();
+        let output_path = get_output_path(deployment_id, "limited", "limits-request", Some("out.bin"));
+        std::fs::create_dir_all(output_path.parent().unwrap()).unwrap();
+        std::fs::write(&output_path, vec![0u8; 30]).unwrap();
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("limited-id".to_string(), "limited".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        ));
+
+        // Usage is measured from the files of the deployments, outputs apart from artifacts
+        let usage = device_usage();
+        assert!(usage.deployments >= 1);
+        assert!(usage.modules >= 1);
+        assert!(usage.artifact_bytes >= 150);
+        assert!(usage.result_bytes >= 30);
+
+        assert!(check_device_limits(&DeviceLimits::default(), "new-deployment", 3, 1 << 30).is_ok());
+        let limits = DeviceLimits { max_deployments: Some(0), ..Default::default() };
+        let (exceeded, _, _) = check_device_limits(&limits, "new-deployment", 1, 0).unwrap_err();
+        assert_eq!(exceeded, LimitExceeded::Deployments);
+        let limits = DeviceLimits { max_modules: Some(usage.modules), ..Default::default() };
+        let (exceeded, _, _) = check_device_limits(&limits, "new-deployment", usage.modules + 1, 0).unwrap_err();
+        assert_eq!(exceeded, LimitExceeded::Modules);
+        let limits = DeviceLimits { max_artifact_bytes: Some(usage.artifact_bytes), ..Default::default() };
+        let (exceeded, error, measured) = check_device_limits(&limits, "new-deployment", 1, usage.artifact_bytes + 1).unwrap_err();
+        assert_eq!(exceeded, LimitExceeded::ArtifactBytes);
+        assert!(error.contains("bytes"), "{}", error);
+        assert!(measured.artifact_bytes >= 150);
+
+        // Usage and limits are reported for the orchestrator to plan placement with
+        let app = test::init_service(App::new().route("/health", web::get().to(thingi_health))).await;
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/health").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let health: Value = test::read_body_json(resp).await;
+        assert!(health["deviceUsage"]["artifactBytes"].is_u64(), "{}", health);
+        assert!(health["deviceUsage"]["resultBytes"].is_u64());
+        assert!(health["deviceLimits"].is_object());
+        // ...as measured with the health snapshot, not on every request
+        let snapshot = take_health_snapshot();
+        assert!(snapshot.device_usage.artifact_bytes >= 150);
+        assert!(snapshot.device_usage.result_bytes >= 30);
+        // The usage is added to the cached device description as it is served, outside its ETag
+        assert!(get_device_description().get("deviceUsage").is_none());
+        let app = test::init_service(App::new().route("/.well-known/wasmiot-device-description", web::get().to(wasmiot_device_description))).await;
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/.well-known/wasmiot-device-description").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers().get("ETag").unwrap().to_str().unwrap().to_string();
+        let description: Value = test::read_body_json(resp).await;
+        assert!(description["deviceUsage"]["artifactBytes"].is_u64(), "{}", description);
+        assert!(description["deviceLimits"].is_object());
+        let req = test::TestRequest::get()
+            .uri("/.well-known/wasmiot-device-description")
+            .insert_header(("If-None-Match", etag))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+        supervisor::lib::api::lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+    }
+
+    #[actix_web::test]
+    async fn api_test_component_model() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::configuration::get_device_description;
+        use supervisor::lib::wasmtime::is_component;
+
+        let deployment_id = "component-test-deployment";
+        let component_path = get_module_path(deployment_id, "greeter");
+        std::fs::create_dir_all(component_path.parent().unwrap()).unwrap();
+        std::fs::write(&component_path, r#"(component
+            (core module $m
+                (memory (export "mem") 1)
+                (global $next (mut i32) (i32.const 1024))
+                (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+                (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                    (local $ptr i32)
+                    (local.set $ptr (global.get $next))
+                    (global.set $next (i32.add (global.get $next) (local.get 3)))
+                    (local.get $ptr))
+                ;; Returns the string it was given, through a pointer to its address and length
+                (func (export "echo") (param i32 i32) (result i32)
+                    (i32.store (i32.const 0) (local.get 0))
+                    (i32.store (i32.const 4) (local.get 1))
+                    (i32.const 0)))
+            (core instance $i (instantiate $m))
+            (func (export "add") (param "a" s32) (param "b" s32) (result s32)
+                (canon lift (core func $i "add")))
+            (func (export "echo") (param "text" string) (result string)
+                (canon lift (core func $i "echo") (memory $i "mem") (realloc (func $i "realloc")))))"#).unwrap();
+        let core_path = get_module_path(deployment_id, "core");
+        std::fs::write(&core_path, r#"(module (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1))))"#).unwrap();
+
+        // Components are told apart from core modules by their header, also when compiled
+        assert!(is_component(&component_path));
+        assert!(!is_component(&core_path));
+        let header = |layer: u8| {
+            let path = component_path.with_extension(format!("layer{}", layer));
+            std::fs::write(&path, [b"\0asm".as_slice(), &[13, 0, layer, 0]].concat()).unwrap();
+            path
+        };
+        assert!(is_component(&header(1)));
+        assert!(!is_component(&header(0)));
+
+        let mut runtime = WasmtimeRuntime::new(vec![], vec![]).await.unwrap();
+        let config = |name: &str, path: &std::path::Path| {
+            ModuleConfig::new(format!("{}-id", name), name.to_string(), path.to_path_buf(), HashMap::new(), None)
+        };
+        runtime.load_module(config("greeter", &component_path)).await.unwrap();
+        runtime.load_module(config("core", &core_path)).await.unwrap();
+
+        // Scalars and strings are converted to and from the types of the parameters and results,
+        // and arguments are passed by the names of the parameters when they have them
+        let component = runtime.get_module("greeter").await.unwrap();
+        assert!(component.is_component());
+        let mut exports = component.get_all_exports();
+        exports.sort();
+        assert_eq!(exports, vec!["add", "echo"]);
+        let args = |pairs: &[(&str, Value)]| pairs.iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<indexmap::IndexMap<String, Value>>();
+        let sum = runtime.run_component_function("greeter", "add", &args(&[("a", serde_json::json!("40")), ("b", serde_json::json!(2))])).await;
+        assert_eq!(sum, Ok(serde_json::json!(42)));
+        let echo = runtime.run_component_function("greeter", "echo", &args(&[("text", serde_json::json!("hello"))])).await;
+        assert_eq!(echo, Ok(serde_json::json!("hello")));
+
+        // Arguments that do not fit are an error rather than a zero
+        let wrong = runtime.run_component_function("greeter", "add", &args(&[("a", serde_json::json!("forty")), ("b", serde_json::json!(2))])).await;
+        assert!(wrong.unwrap_err().contains("not a valid s32"));
+        let missing = runtime.run_component_function("greeter", "add", &args(&[("a", serde_json::json!(1))])).await;
+        assert!(missing.is_err());
+        let named = runtime.run_component_function("greeter", "add", &args(&[("b", serde_json::json!(2)), ("a", serde_json::json!(-40))])).await;
+        assert_eq!(named, Ok(serde_json::json!(-38)));
+        let unknown = runtime.run_component_function("greeter", "nope", &args(&[])).await;
+        assert!(unknown.is_err());
+
+        // Core modules are run as before
+        assert!(!runtime.get_module("core").await.unwrap().is_component());
+        let sum = runtime.run_function("core", "add", vec![wasmtime::Val::I32(40), wasmtime::Val::I32(2)], 1).await.unwrap();
+        assert!(matches!(sum.as_slice(), [wasmtime::Val::I32(42)]));
+        assert!(runtime.run_component_function("core", "add", &args(&[])).await.is_err());
+
+        // Calls of deployed components are validated against their signatures
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![config("greeter", &component_path)],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        ));
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let validate = |query: &str| test::TestRequest::post()
+            .uri(&format!("/{}/modules/greeter/add/validate?{}", deployment_id, query))
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp = test::call_service(&app, validate("a=40&b=2")).await;
+        let status = resp.status();
+        let valid: Value = test::read_body_json(resp).await;
+        let resp = test::call_service(&app, validate("a=forty&b=2")).await;
+        let invalid: Value = test::read_body_json(resp).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        assert_eq!(status, StatusCode::OK, "{}", valid);
+        assert_eq!(valid["valid"], true, "{}", valid);
+        assert_eq!(valid["args"][0]["type"], "s32");
+        assert_eq!(valid["args"][0]["value"], 40);
+        assert_eq!(valid["results"], serde_json::json!(["s32"]));
+        assert_eq!(invalid["valid"], false, "{}", invalid);
+
+        // The device advertises that it runs components
+        assert_eq!(get_device_description()["moduleFormats"], serde_json::json!(["core", "component"]));
+    }
+
+    #[actix_web::test]
+    async fn api_test_durable_queue() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::durable_queue::{drain_queue, enqueue, load_queue, queue_stats};
+        use supervisor::lib::metrics::collect_metrics;
+
+        let deployment_id = "durable-queue-test-deployment";
+        let module_path = get_module_path(deployment_id, "answer");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module (func (export "answer") (result i32) (i32.const 42)))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "answer", None)).unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/answer/answer", deployment_id),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null },
+            "queueing": "durable"
+        });
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("answer-id".to_string(), "answer".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::from([("answer".to_string(), HashMap::from([("answer".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "answer": { "answer": { "from": endpoint, "to": null } } })).unwrap(),
+            HashMap::new(),
+        ));
+        // Requests left in the queue file by an earlier run come first
+        let earlier = load_queue();
+        set_setting("WASMIOT_EXECUTION_QUEUE_MAX_DEPTH", (earlier + 2).to_string(), SettingSource::Api);
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let execute = || test::TestRequest::post()
+            .uri(&format!("/{}/modules/answer/answer", deployment_id))
+            .set_json(serde_json::json!({}))
+            .to_request();
+
+        // A request already waiting keeps the ones after it from overtaking it
+        let first = RequestEntry::new(
+            deployment_id.to_string(),
+            "answer".to_string(),
+            "answer".to_string(),
+            "POST".to_string(),
+            serde_json::json!({}),
+            HashMap::new(),
+            chrono::Utc::now(),
+        );
+        let first_id = first.request_id.clone();
+        assert_eq!(enqueue(first), Ok(earlier + 1));
+        let resp = test::call_service(&app, execute()).await;
+        let queued_status = resp.status();
+        let queued: Value = test::read_body_json(resp).await;
+        let request_id = queued["requestId"].as_str().unwrap_or_default().to_string();
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+        let resp = test::call_service(&app, req).await;
+        let pending_status = resp.status();
+        let pending: Value = test::read_body_json(resp).await;
+
+        // Beyond the most requests allowed new ones are refused
+        let resp = test::call_service(&app, execute()).await;
+        let full_status = resp.status();
+
+        // The queue is read back from its file as after a restart
+        let reloaded = load_queue();
+        let stats = queue_stats();
+        let metrics = collect_metrics();
+        let depth_metric = metrics.iter().any(|metric| metric.name == "supervisor_execution_queue_depth");
+
+        // Queued requests are run in order as threads free up
+        let mut finished = None;
+        for _ in 0..100 {
+            drain_queue();
+            let first_done = supervisor::lib::api::find_request(&first_id).await;
+            let done = supervisor::lib::api::find_request(&request_id).await;
+            if let (Some(first_done), Some(done)) = (first_done, done) {
+                finished = Some((first_done, done));
+                break;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        let depth_after = queue_stats().depth;
+        remove_setting("WASMIOT_EXECUTION_QUEUE_MAX_DEPTH");
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        assert_eq!(queued_status, StatusCode::ACCEPTED, "{}", queued);
+        assert_eq!(queued["status"], "queued");
+        assert_eq!(queued["position"], earlier + 2);
+        assert!(queued["resultUrl"].as_str().unwrap().ends_with(&request_id));
+        assert_eq!(pending_status, StatusCode::ACCEPTED, "{}", pending);
+        assert_eq!(pending["position"], earlier + 2);
+        assert_eq!(full_status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(reloaded, earlier + 2);
+        assert_eq!(stats.depth, earlier + 2);
+        assert!(stats.oldest_age_seconds.is_some());
+        assert!(depth_metric);
+
+        let (first_done, done) = finished.expect("The queued requests were not run");
+        assert!(first_done.success && done.success);
+        assert_eq!(done.result, Some(serde_json::json!("42")));
+        assert_eq!(depth_after, 0);
+    }
+
+    #[actix_web::test]
+    async fn api_test_memory_string_result() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::deployment::{CallData, EndpointArgs};
+
+        let deployment_id = "memory-string-test-deployment";
+        let module_path = get_module_path(deployment_id, "greeter");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module
+            (memory (export "memory") 1)
+            (data (i32.const 16) "h\c3\a9llo")
+            (data (i32.const 32) "bad \ff")
+            (func (export "greet") (result i32 i32) (i32.const 16) (i32.const 6))
+            (func (export "broken") (result i32 i32) (i32.const 32) (i32.const 5))
+            (func (export "outside") (result i32 i32) (i32.const 65530) (i32.const 100))
+            (func (export "single") (result i32) (i32.const 16)))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "greeter", None)).unwrap();
+        let endpoint = |function: &str| serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/greeter/{}", deployment_id, function),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "text/plain", "source": "memory" }
+        });
+        let functions = ["greet", "broken", "outside", "single"];
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("greeter-id".to_string(), "greeter".to_string(), module_path.clone(), HashMap::new(), None)],
+            HashMap::from([("greeter".to_string(), functions.iter()
+                .map(|function| (function.to_string(), serde_json::from_value::<Endpoint>(endpoint(function)).unwrap()))
+                .collect())]),
+            serde_json::from_value(serde_json::json!({ "greeter": functions.iter()
+                .map(|function| (function.to_string(), serde_json::json!({ "from": endpoint(function), "to": null })))
+                .collect::<serde_json::Map<String, Value>>() })).unwrap(),
+            HashMap::new(),
+        ));
+
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let mut results = HashMap::new();
+        for function in functions {
+            let req = test::TestRequest::post()
+                .uri(&format!("/{}/modules/greeter/{}", deployment_id, function))
+                .set_json(serde_json::json!({}))
+                .to_request();
+            let resp: Value = test::call_and_read_body_json(&app, req).await;
+            let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+            let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+            let entry: Value = test::call_and_read_body_json(&app, req).await;
+            results.insert(function, (resp, entry));
+        }
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        // The string the function points at is the result, in the response and the history
+        let (resp, entry) = &results["greet"];
+        assert_eq!(resp["result"]["result"], "héllo", "{}", resp);
+        assert_eq!(entry["result"], "héllo", "{}", entry);
+        assert_eq!(entry["success"], true);
+        assert_eq!(entry["resultLossy"], false);
+
+        // Invalid UTF-8 is replaced and flagged
+        let (_, entry) = &results["broken"];
+        assert_eq!(entry["result"], "bad \u{FFFD}", "{}", entry);
+        assert_eq!(entry["resultLossy"], true);
+
+        // A range outside the memory or a function not returning a pointer and a length fails
+        for function in ["outside", "single"] {
+            let (_, entry) = &results[function];
+            assert_eq!(entry["success"], false, "{}", entry);
+        }
+
+        // Chained calls get the string as the JSON body the next endpoint declares, or a query parameter
+        let mut next: Value = serde_json::json!({
+            "url": "http://next-device:8080",
+            "path": "/other-deployment/modules/printer/print",
+            "method": "POST",
+            "request": { "parameters": [{ "name": "text" }], "request_body": { "media_type": "application/json" } },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let call = CallData::from_endpoint(&serde_json::from_value(next.clone()).unwrap(), Some(EndpointArgs::Str("héllo & bye".to_string())), None);
+        assert_eq!(call.body, Some(serde_json::json!({ "text": "héllo & bye" })));
+        assert_eq!(call.url, "http://next-device:8080/other-deployment/modules/printer/print");
+        next["request"]["request_body"] = Value::Null;
+        let call = CallData::from_endpoint(&serde_json::from_value(next).unwrap(), Some(EndpointArgs::Str("héllo & bye".to_string())), None);
+        assert_eq!(call.body, None);
+        assert_eq!(call.url, "http://next-device:8080/other-deployment/modules/printer/print?text=h%C3%A9llo%20%26%20bye");
+    }
+
+    #[actix_web::test]
+    async fn api_test_module_load_errors() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+
+        let deployment_id = "load-error-test-deployment";
+        let module_configs = [
+            ("broken", r#"(module (import "missing" "f" (func)) (func (export "run") (result i32) (i32.const 1)))"#),
+            ("fine", r#"(module (func (export "run") (result i32) (i32.const 1)))"#),
+        ].iter().map(|(name, wat)| {
+            let module_path = get_module_path(deployment_id, name);
+            std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+            std::fs::write(&module_path, wat).unwrap();
+            std::fs::create_dir_all(get_params_path(deployment_id, name, None)).unwrap();
+            ModuleConfig::new(format!("{}-id", name), name.to_string(), module_path, HashMap::new(), None)
+        }).collect();
+        let mut deployment = Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            module_configs,
+            HashMap::new(),
+            serde_json::from_value(serde_json::json!({})).unwrap(),
+            HashMap::new(),
+        );
+        deployment.check_module_loads(deployment_id).await;
+        assert_eq!(deployment.load_errors.keys().collect::<Vec<_>>(), vec!["broken"]);
+        assert!(deployment.load_errors["broken"].contains("missing"), "{:?}", deployment.load_errors);
+        insert_deployment(deployment);
+
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/request-history", web::get().to(request_history_list_1))
+                .route("/deploy", web::get().to(deployment_get))
+                .route("/deploy/{deployment_id}/stats", web::get().to(deployment_stats))
+        ).await;
+
+        // Every retry is refused with the load error, but only the first is recorded
+        let mut refusals = Vec::new();
+        for _ in 0..3 {
+            let req = test::TestRequest::post()
+                .uri(&format!("/{}/modules/broken/run", deployment_id))
+                .set_json(serde_json::json!({}))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            let status = resp.status();
+            let body: Value = test::read_body_json(resp).await;
+            refusals.push((status, body));
+        }
+        let call = prepare_function_call(deployment_id, "broken", "run", "POST", serde_json::json!({}), None).await;
+        let req = test::TestRequest::get().uri("/request-history").to_request();
+        let history: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::get().uri("/deploy").to_request();
+        let listing: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}/stats", deployment_id)).to_request();
+        let stats: Value = test::call_and_read_body_json(&app, req).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        for (status, body) in &refusals {
+            assert_eq!(*status, StatusCode::SERVICE_UNAVAILABLE, "{}", body);
+            assert_eq!(body["error"], "module failed to load");
+            assert!(body["loadError"].as_str().unwrap().contains("missing"), "{}", body);
+            assert!(body["hint"].is_string());
+        }
+        assert_eq!(call.unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+        let recorded: Vec<&Value> = history.iter().filter(|entry| entry["deploymentId"] == deployment_id).collect();
+        assert_eq!(recorded.len(), 1, "{:?}", recorded);
+        assert_eq!(recorded[0]["loadError"], true);
+        assert_eq!(recorded[0]["success"], false);
+
+        // The deployment and its statistics tell which modules failed to load
+        let described = listing["deployments"].as_array().unwrap().iter().find(|d| d["id"] == deployment_id).unwrap();
+        assert_eq!(described["loadFailed"], true);
+        assert!(described["loadErrors"]["broken"].is_string(), "{}", described);
+        assert!(described["loadErrors"]["fine"].is_null());
+        assert!(stats["loadErrors"]["broken"].is_string(), "{}", stats);
+    }
+
+    #[actix_web::test]
+    async fn api_test_multipart_parts_mapped_to_mounts() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+
+        let deployment_id = "multipart-mounts-test-deployment";
+        let module_path = get_module_path(deployment_id, "camera");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module (func (export "run") (result i32) (i32.const 0)))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "camera", None)).unwrap();
+        let deploy = |allow_extra_files: bool| {
+            let endpoint = serde_json::json!({
+                "url": "http://localhost:8080",
+                "path": format!("/{}/modules/camera/run", deployment_id),
+                "method": "POST",
+                "request": { "parameters": [], "request_body": null },
+                "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null },
+                "keepInputs": true,
+                "allowExtraFiles": allow_extra_files
+            });
+            let mounts = serde_json::json!([
+                { "path": "input.jpg", "media_type": "image/jpeg", "stage": "execution" },
+                { "path": "notes.txt", "media_type": "text/plain", "stage": "execution" }
+            ]);
+            insert_deployment(Deployment::new(
+                deployment_id.to_string(),
+                HashMap::new(),
+                vec![ModuleConfig::new("camera-id".to_string(), "camera".to_string(), module_path.clone(), HashMap::new(), None)],
+                HashMap::from([("camera".to_string(), HashMap::from([("run".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+                serde_json::from_value(serde_json::json!({ "camera": { "run": { "from": endpoint, "to": null } } })).unwrap(),
+                serde_json::from_value(serde_json::json!({ "camera": { "run": { "execution": mounts } } })).unwrap(),
+            ));
+        };
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let upload = |parts: &[(&str, &str)]| {
+            let mut body = String::new();
+            for (name, filename) in parts {
+                body += &format!("--mounts-boundary\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\ncontents of {}\r\n", name, filename, filename);
+            }
+            body += "--mounts-boundary--\r\n";
+            test::TestRequest::post()
+                .uri(&format!("/{}/modules/camera/run", deployment_id))
+                .insert_header(("content-type", "multipart/form-data; boundary=mounts-boundary"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        // The part is saved under the file name of its mount, not the name it was uploaded with
+        deploy(false);
+        let resp: Value = test::call_and_read_body_json(&app, upload(&[("input.jpg", "photo.JPG"), ("notes.txt", "Notes")])).await;
+        let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+        let entry: Value = test::call_and_read_body_json(&app, req).await;
+        let kept = get_input_path(deployment_id, "camera", &request_id, Some("input.jpg"));
+        let kept_contents = std::fs::read_to_string(&kept).ok();
+
+        // Parts matching no mount, uploaded twice or missing a required mount are refused
+        let mut refused = Vec::new();
+        for parts in [
+            vec![("input.jpg", "photo.JPG"), ("notes.txt", "Notes"), ("thumbnail", "thumb.jpg")],
+            vec![("input.jpg", "a.jpg"), ("input.jpg", "b.jpg"), ("notes.txt", "Notes")],
+            vec![("notes.txt", "notes.txt")],
+        ] {
+            let resp = test::call_service(&app, upload(&parts)).await;
+            let status = resp.status();
+            let body: Value = test::read_body_json(resp).await;
+            refused.push((status, body));
+        }
+
+        // Unless the endpoint allows extra files, which are dropped
+        deploy(true);
+        let resp = test::call_service(&app, upload(&[("input.jpg", "photo.JPG"), ("thumbnail", "thumb.jpg"), ("notes.txt", "Notes")])).await;
+        let extra_status = resp.status();
+        let extra: Value = test::read_body_json(resp).await;
+        let extra_id = extra["resultUrl"].as_str().unwrap_or_default().rsplit('/').next().unwrap().to_string();
+        let mut extra_inputs: Vec<String> = get_input_path(deployment_id, "camera", &extra_id, None).read_dir()
+            .map(|entries| entries.filter_map(Result::ok).map(|e| e.file_name().to_string_lossy().to_string()).collect())
+            .unwrap_or_default();
+        extra_inputs.sort();
+
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert!(entry["requestFiles"]["input.jpg"].as_str().unwrap().ends_with("input.jpg"), "{}", entry);
+        assert_eq!(kept_contents.as_deref(), Some("contents of photo.JPG"));
+
+        for (status, body) in &refused {
+            assert_eq!(*status, StatusCode::BAD_REQUEST, "{}", body);
+        }
+        assert!(refused[0].1["error"].as_str().unwrap().contains("thumbnail"), "{}", refused[0].1);
+        assert_eq!(refused[0].1["mounts"], serde_json::json!(["input.jpg", "notes.txt"]));
+        assert!(refused[1].1["error"].as_str().unwrap().contains("more than once"), "{}", refused[1].1);
+        assert_eq!(refused[2].1["missingMounts"], serde_json::json!(["input.jpg"]));
+
+        assert_eq!(extra_status, StatusCode::OK, "{}", extra);
+        assert_eq!(extra_inputs, vec!["input.jpg", "notes.txt"]);
+    }
+
+    #[actix_web::test]
+    async fn api_test_long_execution_does_not_block_health() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        // Spins for two seconds by the clock of the host, like a long inference would
+        let spinner = r#"(module
+            (import "wasi_snapshot_preview1" "clock_time_get" (func $now (param i32 i64 i32) (result i32)))
+            (memory (export "memory") 1)
+            (func (export "spin") (result i32)
+                (local $start i64)
+                (drop (call $now (i32.const 1) (i64.const 1) (i32.const 0)))
+                (local.set $start (i64.load (i32.const 0)))
+                (loop $spin
+                    (drop (call $now (i32.const 1) (i64.const 1) (i32.const 0)))
+                    (br_if $spin (i64.lt_u (i64.sub (i64.load (i32.const 0)) (local.get $start)) (i64.const 2000000000))))
+                (i32.const 1)))"#;
+        let deployment_id = "long-execution-test-deployment";
+        let module_path = get_module_path(deployment_id, "spinner");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, spinner).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "spinner", None)).unwrap();
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("spinner-id".to_string(), "spinner".to_string(), module_path, HashMap::new(), None)],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+        ));
+
+        // A single worker, like on armv6 devices, serves both the execution and the health checks
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/healthz", web::get().to(healthz))
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = reqwest::Client::new();
+        let started = std::time::Instant::now();
+        let execution = async {
+            let resp = client.post(format!("http://{}/{}/modules/spinner/spin", address, deployment_id))
+                .json(&serde_json::json!({}))
+                .send().await.unwrap();
+            let body: Value = resp.json().await.unwrap();
+            (body, started.elapsed())
+        };
+        let health = async {
+            sleep(Duration::from_millis(200)).await;
+            let mut answered = Vec::new();
+            for _ in 0..10 {
+                let asked = std::time::Instant::now();
+                let resp = client.get(format!("http://{}/healthz", address)).send().await.unwrap();
+                assert_eq!(resp.status(), reqwest::StatusCode::OK);
+                answered.push((asked.elapsed(), started.elapsed()));
+                sleep(Duration::from_millis(100)).await;
+            }
+            answered
+        };
+        let ((body, executed_in), answered) = futures_util::future::join(execution, health).await;
+        handle.stop(false).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        // Every health check was answered within a second while the function was running
+        assert_eq!(body["result"]["result"], "1", "{}", body);
+        assert!(executed_in >= Duration::from_secs(2), "{:?}", executed_in);
+        let slowest = answered.iter().map(|(took, _)| *took).max().unwrap();
+        assert!(slowest < Duration::from_secs(1), "Health check took {:?}", slowest);
+        let during = answered.iter().filter(|(_, at)| *at < executed_in).count();
+        assert!(during >= 5, "Only {} health checks were answered during the execution of {:?}", during, executed_in);
+    }
+
+    #[actix_web::test]
+    async fn api_test_void_function_chain() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use futures_util::StreamExt;
+        use supervisor::lib::deployment::{EndpointData, MediaTypeObject};
+        use supervisor::structs::deployment_supervisor::MountPathFile;
+
+        // The next step of the chain, recording the query and the body it was called with
+        let received: Arc<Mutex<Vec<(String, String)>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorder = received.clone();
+        let server = HttpServer::new(move || {
+            let recorder = recorder.clone();
+            App::new().route("/next/modules/counter/count", web::post().to(move |req: actix_web::HttpRequest, mut payload: web::Payload| {
+                let recorder = recorder.clone();
+                async move {
+                    let mut body = Vec::new();
+                    while let Some(chunk) = payload.next().await {
+                        body.extend_from_slice(&chunk.unwrap());
+                    }
+                    recorder.lock().unwrap().push((req.query_string().to_string(), String::from_utf8_lossy(&body).to_string()));
+                    HttpResponse::Ok().json(serde_json::json!({ "result": { "result": "7" } }))
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        // The first step returns nothing, while the next one takes a parameter
+        let deployment_id = "void-chain-test-deployment";
+        let module_path = get_module_path(deployment_id, "logger");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module (func (export "log")))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "logger", None)).unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/logger/log", deployment_id),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let next = serde_json::json!({
+            "url": format!("http://{}", address),
+            "path": "/next/modules/counter/count",
+            "method": "POST",
+            "request": { "parameters": [{ "name": "value" }], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("logger-id".to_string(), "logger".to_string(), module_path, HashMap::new(), None)],
+            HashMap::from([("logger".to_string(), HashMap::from([("log".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(serde_json::json!({ "logger": { "log": { "from": endpoint, "to": next } } })).unwrap(),
+            HashMap::new(),
+        ));
+
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/modules/logger/log", deployment_id))
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+        let entry: Value = test::call_and_read_body_json(&app, req).await;
+        handle.stop(false).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        // The first step completed without a result, and the chain went on to the next one
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert_eq!(entry["hasResult"], false);
+        assert_eq!(entry["result"], serde_json::json!({ "status": "completed" }));
+        assert_eq!(entry["chainTrace"].as_array().unwrap().len(), 1, "{}", entry);
+        assert_eq!(resp["result"]["result"]["result"], "7", "{}", resp);
+
+        // ...without a made up parameter
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (query, body) = &received[0];
+        assert_eq!(query, "");
+        assert!(!body.contains("Content-Disposition"), "{}", body);
+
+        // Only the output files a function returning nothing declares are passed on
+        let deployment = Deployment::new(String::new(), HashMap::new(), vec![], HashMap::new(), HashMap::new(), HashMap::new());
+        let image: MediaTypeObject = serde_json::from_value(serde_json::json!({ "media_type": "image/png", "encoding": null })).unwrap();
+        let mounts = vec![MountPathFile::new("out.png".to_string(), "image/png".to_string(), MountStage::OUTPUT, None, None, None)];
+        let (args, files) = deployment.parse_endpoint_result(None, &image, &mounts);
+        assert!(args.is_none());
+        assert!(matches!(files, Some(EndpointData::StrList(files)) if files == vec!["out.png".to_string()]));
+        let json: MediaTypeObject = serde_json::from_value(serde_json::json!({ "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null })).unwrap();
+        let (args, files) = deployment.parse_endpoint_result(None, &json, &Vec::new());
+        assert!(args.is_none() && files.is_none());
+    }
+
+    #[actix_web::test]
+    async fn api_test_chained_call_fallback_targets() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+
+        // A target failing with a server error and one serving the step, counting its calls
+        let served = Arc::new(Mutex::new(Vec::new()));
+        let recorder = served.clone();
+        let server = HttpServer::new(move || {
+            let recorder = recorder.clone();
+            App::new()
+                .route("/broken/modules/counter/count", web::post().to(|| async {
+                    HttpResponse::InternalServerError().json(serde_json::json!({ "error": "out of memory" }))
+                }))
+                .route("/next/modules/counter/count", web::post().to(move |req: actix_web::HttpRequest| {
+                    let recorder = recorder.clone();
+                    async move {
+                        recorder.lock().unwrap().push(req.query_string().to_string());
+                        HttpResponse::Ok().json(serde_json::json!({ "result": { "result": "7" } }))
+                    }
+                }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let address = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let deployment_id = "fallback-chain-test-deployment";
+        let module_path = get_module_path(deployment_id, "source");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module (func (export "read") (result i32) i32.const 3))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "source", None)).unwrap();
+        let endpoint = serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/source/read", deployment_id),
+            "method": "POST",
+            "request": { "parameters": [], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let target = |url: String, path: &str| serde_json::json!({
+            "url": url,
+            "path": path,
+            "method": "POST",
+            "request": { "parameters": [{ "name": "value" }], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null }
+        });
+        let primary = target(format!("http://{}", address), "/broken/modules/counter/count");
+        let unreachable = target("http://127.0.0.1:9".to_string(), "/next/modules/counter/count");
+        let fallback = target(format!("http://{}", address), "/next/modules/counter/count");
+        let instructions = serde_json::json!({
+            "source": { "read": { "from": endpoint, "to": primary, "fallbackTargets": [unreachable, fallback] } }
+        });
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("source-id".to_string(), "source".to_string(), module_path, HashMap::new(), None)],
+            HashMap::from([("source".to_string(), HashMap::from([("read".to_string(), serde_json::from_value::<Endpoint>(endpoint.clone()).unwrap())]))]),
+            serde_json::from_value(instructions).unwrap(),
+            HashMap::new(),
+        ));
+
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/request-history/{request_id}", web::get().to(request_history_list))
+        ).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/modules/source/read", deployment_id))
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp: Value = test::call_and_read_body_json(&app, req).await;
+        let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+        let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+        let entry: Value = test::call_and_read_body_json(&app, req).await;
+        handle.stop(false).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        // The last fallback target served the step with the same payload
+        let served_by = format!("http://{}/next/modules/counter/count?value=3", address);
+        assert_eq!(entry["success"], true, "{}", entry);
+        assert_eq!(resp["servedBy"], served_by.as_str(), "{}", resp);
+        assert_eq!(entry["servedBy"], served_by.as_str());
+        assert_eq!(resp["result"]["result"]["result"], "7", "{}", resp);
+        assert_eq!(served.lock().unwrap().as_slice(), ["value=3".to_string()]);
+
+        // ...after each failed attempt was recorded with why it failed
+        let trace = entry["chainTrace"].as_array().unwrap();
+        assert_eq!(trace.len(), 3, "{}", entry);
+        assert!(trace[0]["url"].as_str().unwrap().contains("/broken/"));
+        assert!(trace[0]["error"].as_str().unwrap().contains("(500): out of memory"), "{}", trace[0]);
+        assert!(trace[1]["url"].as_str().unwrap().starts_with("http://127.0.0.1:9/"));
+        assert!(trace[1]["error"].as_str().unwrap().starts_with("Failed to send chained request"), "{}", trace[1]);
+        assert_eq!(trace[2]["url"], served_by.as_str());
+        assert!(trace[2]["error"].is_null());
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_wasm_memory() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::wasm_memory::total_wasm_memory_bytes;
+
+        // Starts with two pages of memory and grows it by one on each call
+        let deployment_id = "wasm-memory-test-deployment";
+        let module_path = get_module_path(deployment_id, "grower");
+        std::fs::create_dir_all(module_path.parent().unwrap()).unwrap();
+        std::fs::write(&module_path, r#"(module (memory (export "memory") 2) (func (export "grow") (result i32) (memory.grow (i32.const 1))))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "grower", None)).unwrap();
+        insert_deployment(Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("grower-id".to_string(), "grower".to_string(), module_path, HashMap::new(), None)],
+            HashMap::new(),
+            serde_json::from_value(serde_json::json!({})).unwrap(),
+            HashMap::new(),
+        ));
+
+        let app = test::init_service(
+            App::new()
+                .route("/{deployment_id}/modules/{module_name}/{function_name}", web::post().to(run_module_function_3))
+                .route("/deploy/{deployment_id}/stats", web::get().to(deployment_stats))
+        ).await;
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}/stats", deployment_id)).to_request();
+        let before: Value = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/{}/modules/grower/grow", deployment_id))
+            .set_json(serde_json::json!({}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        // Measured after the execution, without asking for the statistics
+        let total_after_run = total_wasm_memory_bytes();
+        let req = test::TestRequest::get().uri(&format!("/deploy/{}/stats", deployment_id)).to_request();
+        let after: Value = test::call_and_read_body_json(&app, req).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+
+        // Nothing is measured for a module that has not been run
+        assert_eq!(before["memory"]["linearMemoryBytes"], 0, "{}", before);
+        assert_eq!(before["memory"]["modules"], serde_json::json!({}));
+
+        let page = 64 * 1024;
+        let module = &after["memory"]["modules"]["grower"];
+        assert_eq!(module["linearMemoryBytes"], 3 * page, "{}", after);
+        assert_eq!(after["memory"]["linearMemoryBytes"], 3 * page);
+        assert!(module["measuredAt"].is_string());
+        assert!(module.get("instantiationRssDeltaBytes").is_some());
+        assert!(total_after_run >= 3 * page, "{}", total_after_run);
+    }
+
+    #[actix_web::test]
+    async fn api_test_request_entry_wire_format() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use chrono::TimeZone;
+        use supervisor::structs::request_entry::{CallbackDelivery, ChainStep, REQUEST_ENTRY_SCHEMA_VERSION};
+
+        let mut entry = RequestEntry::new(
+            "wire-format-deployment".to_string(),
+            "camera".to_string(),
+            "take_image".to_string(),
+            "POST".to_string(),
+            serde_json::json!({ "image_width": 640 }),
+            HashMap::from([("input.jpg".to_string(), "/params/input.jpg".to_string())]),
+            chrono::Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap(),
+        );
+        entry.request_id = "wire-format-request".to_string();
+        entry.result = Some(serde_json::json!({ "label_name": "cat" }));
+        entry.outputs = vec!["http://device/out.jpg".to_string()];
+        entry.success = true;
+        entry.chain_trace = vec![ChainStep {
+            url: "http://next/run".to_string(),
+            outputs: vec![],
+            mirrored: false,
+            mirror_error: Some("too large".to_string()),
+            error: None,
+        }];
+        entry.served_by = Some("http://next/run".to_string());
+        entry.callback_url = Some("http://caller/done".to_string());
+        entry.callback = Some(CallbackDelivery { delivered: true, attempts: 1, status: Some(200), error: None });
+        entry.queue_wait_ms = Some(3);
+        entry.bytes_in = 10;
+        entry.bytes_out = 20;
+
+        // Every key the orchestrator reads, in the current format
+        let current = serde_json::json!({
+            "schemaVersion": REQUEST_ENTRY_SCHEMA_VERSION,
+            "requestId": "wire-format-request",
+            "deploymentId": "wire-format-deployment",
+            "moduleName": "camera",
+            "functionName": "take_image",
+            "method": "POST",
+            "requestArgs": { "image_width": 640 },
+            "requestFiles": { "input.jpg": "/params/input.jpg" },
+            "workQueuedAt": "2025-01-02T03:04:05Z",
+            "result": { "label_name": "cat" },
+            "hasResult": true,
+            "resultLossy": false,
+            "outputs": ["http://device/out.jpg"],
+            "success": true,
+            "aborted": false,
+            "loadError": false,
+            "chainTrace": [{ "url": "http://next/run", "outputs": [], "mirrored": false, "mirrorError": "too large", "error": null }],
+            "servedBy": "http://next/run",
+            "inputsRetained": false,
+            "callbackUrl": "http://caller/done",
+            "callback": { "delivered": true, "attempts": 1, "status": 200, "error": null },
+            "traceparent": null,
+            "caller": null,
+            "priority": "normal",
+            "queueWaitMs": 3,
+            "bytesIn": 10,
+            "bytesOut": 20,
+        });
+        assert_eq!(serde_json::to_value(&entry).unwrap(), current);
+
+        // The format of version 1, where arguments and results keep their keys
+        let legacy = serde_json::json!({
+            "request_id": "wire-format-request",
+            "deployment_id": "wire-format-deployment",
+            "module_name": "camera",
+            "function_name": "take_image",
+            "method": "POST",
+            "request_args": { "image_width": 640 },
+            "request_files": { "input.jpg": "/params/input.jpg" },
+            "work_queued_at": "2025-01-02T03:04:05Z",
+            "result": { "label_name": "cat" },
+            "has_result": true,
+            "result_lossy": false,
+            "outputs": ["http://device/out.jpg"],
+            "success": true,
+            "aborted": false,
+            "load_error": false,
+            "chain_trace": [{ "url": "http://next/run", "outputs": [], "mirrored": false, "mirror_error": "too large", "error": null }],
+            "served_by": "http://next/run",
+            "inputs_retained": false,
+            "callback_url": "http://caller/done",
+            "callback": { "delivered": true, "attempts": 1, "status": 200, "error": null },
+            "traceparent": null,
+            "caller": null,
+            "priority": "normal",
+            "queue_wait_ms": 3,
+            "bytes_in": 10,
+            "bytes_out": 20,
+        });
+        assert_eq!(entry.legacy_json(), legacy);
+
+        // Entries saved in either format are read back alike
+        for saved in [&current, &legacy] {
+            let read: RequestEntry = serde_json::from_value(saved.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&read).unwrap(), current);
+        }
+
+        // The request history serves either format
+        archive_requests(std::slice::from_ref(&entry)).unwrap();
+        let app = test::init_service(App::new().route("/request-history/{request_id}", web::get().to(request_history_list))).await;
+        let req = test::TestRequest::get().uri("/request-history/wire-format-request").to_request();
+        let served: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(served, current);
+        let req = test::TestRequest::get().uri("/request-history/wire-format-request?legacy=true").to_request();
+        let served: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(served, legacy);
+    }
+
+    #[actix_web::test]
+    async fn api_test_pulley_matches_native() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::wasmtime::{engine_config, pulley_target};
+        use wasmtime::{Engine, Instance, Module, Store};
+
+        let fibo = r#"(module
+            (func (export "fibo") (param $n i64) (result i64)
+                (local $a i64) (local $b i64) (local $t i64)
+                (local.set $b (i64.const 1))
+                (block $done
+                    (loop $next
+                        (br_if $done (i64.eqz (local.get $n)))
+                        (local.set $t (i64.add (local.get $a) (local.get $b)))
+                        (local.set $a (local.get $b))
+                        (local.set $b (local.get $t))
+                        (local.set $n (i64.sub (local.get $n) (i64.const 1)))
+                        (br $next)))
+                (local.get $a)))"#;
+        let native = Engine::new(&engine_config(false).unwrap()).unwrap();
+        let pulley = Engine::new(&engine_config(true).unwrap()).unwrap();
+        assert!(!native.is_pulley());
+        assert!(pulley.is_pulley());
+        assert!(pulley_target().starts_with("pulley"));
+
+        let run = |engine: &Engine, artifact: &[u8], n: i64| {
+            let module = unsafe { Module::deserialize(engine, artifact) }.unwrap();
+            let mut store = Store::new(engine, ());
+            let instance = Instance::new(&mut store, &module, &[]).unwrap();
+            let fibo = instance.get_typed_func::<i64, i64>(&mut store, "fibo").unwrap();
+            fibo.call(&mut store, n).unwrap()
+        };
+        let native_artifact = native.precompile_module(fibo.as_bytes()).unwrap();
+        let pulley_artifact = pulley.precompile_module(fibo.as_bytes()).unwrap();
+
+        // The interpreter runs bytecode of its own, which native code cannot stand in for
+        assert!(unsafe { Module::deserialize(&native, &pulley_artifact) }.is_err());
+        assert!(unsafe { Module::deserialize(&pulley, &native_artifact) }.is_err());
+
+        // ...and gets the same results, wrapping around like native code
+        for n in [0, 1, 2, 10, 50, 92, 100] {
+            assert_eq!(run(&pulley, &pulley_artifact, n), run(&native, &native_artifact, n), "fibo({})", n);
+        }
+        assert_eq!(run(&pulley, &pulley_artifact, 50), 12_586_269_025);
+    }
+
+    #[actix_web::test]
+    async fn api_test_endpoint_examples() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "endpoint-examples-test-deployment";
+        let path = get_module_path(deployment_id, "adder");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, r#"(module
+            (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+            (func (export "private") (result i32) (i32.const 7)))"#).unwrap();
+        std::fs::create_dir_all(get_params_path(deployment_id, "adder", None)).unwrap();
+        let endpoint = |function_name: &str, capture: bool| serde_json::json!({
+            "url": "http://localhost:8080",
+            "path": format!("/{}/modules/adder/{}", deployment_id, function_name),
+            "method": "GET",
+            "request": { "parameters": [{ "name": "a" }, { "name": "b" }], "request_body": null },
+            "response": { "media_type": "application/json", "schema": { "type": "integer" }, "encoding": null },
+            "captureExamples": capture,
+        });
+        let mut deployment = Deployment::new(
+            deployment_id.to_string(),
+            HashMap::new(),
+            vec![ModuleConfig::new("adder-id".to_string(), "adder".to_string(), path, HashMap::new(), None)],
+            HashMap::from([("adder".to_string(), HashMap::from([
+                ("add".to_string(), serde_json::from_value::<Endpoint>(endpoint("add", true)).unwrap()),
+                ("private".to_string(), serde_json::from_value::<Endpoint>(endpoint("private", false)).unwrap()),
+            ]))]),
+            serde_json::from_value(serde_json::json!({ "adder": {
+                "add": { "from": endpoint("add", true), "to": null },
+                "private": { "from": endpoint("private", false), "to": null },
+            } })).unwrap(),
+            serde_json::from_value(serde_json::json!({ "adder": { "add": {}, "private": {} } })).unwrap(),
+        );
+        deployment.secrets = HashMap::from([
+            ("adder".to_string(), HashMap::from([("TOKEN".to_string(), SecretValue::new("hunter2".to_string()))]))
+        ]);
+        insert_deployment(deployment);
+        let new_entry = |function_name: &str, args: Value| RequestEntry::new(
+            deployment_id.to_string(),
+            "adder".to_string(),
+            function_name.to_string(),
+            "GET".to_string(),
+            args,
+            HashMap::new(),
+            chrono::Utc::now(),
+        );
+
+        // Successful executions are captured
+        let (entry, _) = make_history(new_entry("add", serde_json::json!({ "a": "2", "b": "3" }))).await;
+        assert!(entry.success, "{:?}", entry.result);
+        let (private, _) = make_history(new_entry("private", serde_json::json!({}))).await;
+        assert!(private.success, "{:?}", private.result);
+        let shared = get_deployment(deployment_id).unwrap();
+        let mut deployment = shared.lock().await;
+        assert_eq!(deployment.examples["adder"]["add"].len(), 1);
+        let example = &deployment.examples["adder"]["add"][0];
+        assert_eq!(example.args, serde_json::json!({ "a": "2", "b": "3" }));
+        assert_eq!(example.result, serde_json::json!("5"));
+        assert!(!example.truncated);
+
+        // Secrets are redacted and large values are cut
+        let mut secret = new_entry("add", serde_json::json!({ "a": "Bearer hunter2", "b": ["x".repeat(2000)] }));
+        secret.result = Some(serde_json::json!("y".repeat(2000)));
+        secret.outputs = vec!["http://device/files/out.jpg?signature=abc".to_string()];
+        assert!(deployment.record_example(&secret, 3));
+        let example = &deployment.examples["adder"]["add"][1];
+        let args = example.args.as_str().unwrap();
+        assert!(args.starts_with(r#"{"a":"<redacted>","b":["xxx"#), "{}", args);
+        assert!(args.ends_with("xxx...") && args.len() == 1024 + 3, "{}", args.len());
+        assert!(example.result.as_str().unwrap().len() <= 1024 + 3);
+        assert!(example.truncated);
+        assert_eq!(example.outputs, vec!["out.jpg"]);
+
+        // Only up to the limit, and never for endpoints that turn capturing off
+        assert!(!deployment.record_example(&secret, 2));
+        assert!(!deployment.record_example(&private, 3));
+        assert!(!deployment.examples["adder"].contains_key("private"));
+
+        // The first example is shown on the endpoint
+        let json = deployment_json(&deployment);
+        drop(deployment);
+        let saved: Value = serde_json::from_reader(std::fs::File::open(get_deployment_path(deployment_id)).unwrap()).unwrap();
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_file(get_deployment_path(deployment_id)).ok();
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+        std::fs::remove_dir_all(PARAMS_FOLDER.join(deployment_id)).ok();
+        assert_eq!(json["endpoints"]["adder"]["add"]["request"]["example"], serde_json::json!({ "a": "2", "b": "3" }));
+        assert_eq!(json["endpoints"]["adder"]["add"]["response"]["example"], serde_json::json!("5"));
+        assert!(json["endpoints"]["adder"]["private"]["request"].get("example").is_none());
+        assert_eq!(json["examples"]["adder"]["add"].as_array().unwrap().len(), 2);
+        // Captured examples are kept with the deployment
+        assert_eq!(saved["examples"]["adder"]["add"][0]["result"], serde_json::json!("5"));
+    }
+
+    #[actix_web::test]
+    async fn api_test_two_supervisor_chain() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::test_support::TestSupervisor;
+
+        // The first step doubles its argument and the second, on the other supervisor, adds one
+        let first = TestSupervisor::start().await.unwrap();
+        let second = TestSupervisor::start().await.unwrap();
+        let (first_id, second_id) = ("two-supervisor-chain-first", "two-supervisor-chain-second");
+        let double = first.endpoint(first_id, "doubler", "double", &["n"]);
+        let increment = second.endpoint(second_id, "incrementer", "increment", &["value"]);
+        let manifest = |deployment_id: &str, module_name: &str, function_name: &str, endpoint: &Value, to: &Value| serde_json::json!({
+            "deploymentId": deployment_id,
+            "modules": [{ "id": format!("{}-id", module_name), "name": module_name }],
+            "endpoints": { module_name: { function_name: endpoint } },
+            "instructions": { "modules": { module_name: { function_name: { "from": endpoint, "to": to } } } },
+        });
+        second.deploy(
+            &manifest(second_id, "incrementer", "increment", &increment, &Value::Null),
+            &[("incrementer", br#"(module (func (export "increment") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1))))"#)],
+        ).await.unwrap();
+        first.deploy(
+            &manifest(first_id, "doubler", "double", &double, &increment),
+            &[("doubler", br#"(module (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2))))"#)],
+        ).await.unwrap();
+
+        let resp = first.execute(first_id, "doubler", "double", &[("n", "20")]).await.unwrap();
+        let first_history = first.history().await.unwrap();
+        let second_history = second.history().await.unwrap();
+        let (first_url, second_url) = (first.url(), second.url());
+        // Each supervisor has deployments of its own, apart from those of the process
+        let status = |url: String| async move { reqwest::get(url).await.unwrap().status().as_u16() };
+        assert_eq!(status(format!("{}/deploy/{}", first_url, first_id)).await, 200);
+        assert_eq!(status(format!("{}/deploy/{}", second_url, first_id)).await, 404);
+        assert!(get_deployment(first_id).is_none());
+        first.stop().await;
+        second.stop().await;
+
+        // The result of the second step is the result of the chain
+        assert_eq!(resp["servedBy"], format!("{}/{}/modules/incrementer/increment?value=40", second_url, second_id), "{}", resp);
+        assert_eq!(resp["result"], "41", "{}", resp);
+        assert!(resp["resultUrl"].as_str().unwrap().starts_with(&first_url), "{}", resp);
+
+        // Each supervisor recorded its own step
+        assert_eq!(first_history.len(), 1, "{:?}", first_history);
+        assert_eq!(first_history[0]["functionName"], "double");
+        assert_eq!(first_history[0]["result"], "40");
+        assert_eq!(first_history[0]["success"], true);
+        assert_eq!(second_history.len(), 1, "{:?}", second_history);
+        assert_eq!(second_history[0]["functionName"], "increment");
+        assert_eq!(second_history[0]["requestArgs"]["value"], "40", "{}", second_history[0]);
+        assert_eq!(second_history[0]["result"], "41");
+        assert_eq!(second_history[0]["success"], true);
+    }
+
+    #[actix_web::test]
+    async fn api_test_request_byte_counts() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::test_support::TestSupervisor;
+
+        let first = TestSupervisor::start().await.unwrap();
+        let second = TestSupervisor::start().await.unwrap();
+        let (first_id, second_id) = ("byte-count-first", "byte-count-second");
+        let double = first.endpoint(first_id, "doubler", "double", &["n"]);
+        let increment = second.endpoint(second_id, "incrementer", "increment", &["value"]);
+        let manifest = |deployment_id: &str, module_name: &str, function_name: &str, endpoint: &Value, to: &Value| serde_json::json!({
+            "deploymentId": deployment_id,
+            "modules": [{ "id": format!("{}-id", module_name), "name": module_name }],
+            "endpoints": { module_name: { function_name: endpoint } },
+            "instructions": { "modules": { module_name: { function_name: { "from": endpoint, "to": to } } } },
+        });
+        second.deploy(
+            &manifest(second_id, "incrementer", "increment", &increment, &Value::Null),
+            &[("incrementer", br#"(module (func (export "increment") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1))))"#)],
+        ).await.unwrap();
+        first.deploy(
+            &manifest(first_id, "doubler", "double", &double, &increment),
+            &[("doubler", br#"(module (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2))))"#)],
+        ).await.unwrap();
+
+        let resp = first.execute(first_id, "doubler", "double", &[("n", "20")]).await.unwrap();
+        assert_eq!(resp["result"], "41", "{}", resp);
+        let first_history = first.history().await.unwrap();
+        let second_history = second.history().await.unwrap();
+        let client = reqwest::Client::new();
+        let stats: Value = client.get(format!("{}/deploy/{}/stats", second.url(), second_id))
+            .send().await.unwrap().json().await.unwrap();
+        first.stop().await;
+        second.stop().await;
+
+        // The second step got no body, and sent its entry when the first fetched its resultUrl
+        let (first_entry, second_entry) = (&first_history[0], &second_history[0]);
+        assert_eq!(second_entry["bytesIn"], 0, "{}", second_entry);
+        let sent = second_entry["bytesOut"].as_u64().unwrap();
+        assert!(sent > 0, "{}", second_entry);
+        assert_eq!(stats["total"]["bytesOut"], sent, "{}", stats);
+        assert_eq!(stats["functions"]["incrementer/increment"]["bytesOut"], sent, "{}", stats);
+
+        // The first step got its JSON body, the answer of the chained call and the fetched entry
+        let received = first_entry["bytesIn"].as_u64().unwrap();
+        assert!(received > 2 + sent, "{}", first_entry);
+        assert_eq!(first_entry["bytesOut"], 0, "{}", first_entry);
+
+        // The device totals include both
+        let metrics = collect_metrics();
+        let total = |name: &str| metrics.iter().find(|metric| metric.name == name).unwrap().samples[0].1 as u64;
+        assert!(total("supervisor_received_bytes_total") >= received);
+        assert!(total("supervisor_sent_bytes_total") >= sent);
+    }
+
+    #[actix_web::test]
+    async fn api_test_device_placeholders() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::device_registry::{parse_devices, resolve_target_url};
+        use supervisor::lib::test_support::TestSupervisor;
+
+        // Placeholders are resolved before the query, which holds the arguments as they are
+        let devices = parse_devices(Some(&serde_json::json!({ "camera": "http://10.0.0.2:8080/" }))).unwrap();
+        assert_eq!(
+            resolve_target_url("{device:camera}/dep/modules/m/f?x={self}", &devices, "http://10.0.0.1:8080").unwrap(),
+            "http://10.0.0.2:8080/dep/modules/m/f?x={self}",
+        );
+        assert_eq!(resolve_target_url("{self}/dep/modules/m/f", &devices, "http://10.0.0.1:8080/").unwrap(), "http://10.0.0.1:8080/dep/modules/m/f");
+        let e = resolve_target_url("{device:other}/dep/modules/m/f", &devices, "http://10.0.0.1:8080").unwrap_err();
+        assert!(e.contains("{device:other}"), "{}", e);
+        assert!(parse_devices(Some(&serde_json::json!({ "camera": "ftp://10.0.0.2" }))).is_err());
+
+        let first = TestSupervisor::start().await.unwrap();
+        let second = TestSupervisor::start().await.unwrap();
+        let (first_id, second_id) = ("device-placeholders-first", "device-placeholders-second");
+        let double = first.endpoint(first_id, "doubler", "double", &["n"]);
+        let increment = second.endpoint(second_id, "incrementer", "increment", &["value"]);
+        let mut target = increment.clone();
+        target["url"] = Value::from("{device:incrementer-device}");
+        second.deploy(
+            &serde_json::json!({
+                "deploymentId": second_id,
+                "modules": [{ "id": "incrementer-id", "name": "incrementer" }],
+                "endpoints": { "incrementer": { "increment": increment } },
+                "instructions": { "modules": { "incrementer": { "increment": { "from": increment, "to": null } } } },
+            }),
+            &[("incrementer", br#"(module (func (export "increment") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1))))"#)],
+        ).await.unwrap();
+        first.deploy(
+            &serde_json::json!({
+                "deploymentId": first_id,
+                "modules": [{ "id": "doubler-id", "name": "doubler" }],
+                "endpoints": { "doubler": { "double": double } },
+                "instructions": { "modules": { "doubler": { "double": { "from": double, "to": target } } } },
+                "devices": { "unused-device": "http://127.0.0.1:9" },
+            }),
+            &[("doubler", br#"(module (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2))))"#)],
+        ).await.unwrap();
+
+        // The device is not known yet, so the chained call fails naming the placeholder
+        first.execute(first_id, "doubler", "double", &[("n", "20")]).await.unwrap();
+        let history = first.history().await.unwrap();
+        let unresolved = &history[0];
+        assert_eq!(unresolved["success"], false, "{}", unresolved);
+        assert!(unresolved["result"].as_str().unwrap().contains("{device:incrementer-device}"), "{}", unresolved);
+
+        // Adding the device makes the chain work without deploying again
+        let client = reqwest::Client::new();
+        let devices_url = format!("{}/deploy/{}/devices", first.url(), first_id);
+        let patched = client.patch(&devices_url)
+            .json(&serde_json::json!({ "incrementer-device": second.url(), "unused-device": null }))
+            .send().await.unwrap();
+        assert_eq!(patched.status().as_u16(), 200);
+        let patched: Value = patched.json().await.unwrap();
+        assert_eq!(patched["devices"], serde_json::json!({ "incrementer-device": second.url() }), "{}", patched);
+        let resolved = first.execute(first_id, "doubler", "double", &[("n", "20")]).await.unwrap();
+
+        // Invalid devices are refused without changing the others
+        let refused = client.patch(&devices_url)
+            .json(&serde_json::json!({ "incrementer-device": "not a url" }))
+            .send().await.unwrap();
+        let refused_status = refused.status().as_u16();
+        let deployment: Value = client.get(format!("{}/deploy/{}", first.url(), first_id))
+            .send().await.unwrap().json().await.unwrap();
+        let missing = client.patch(format!("{}/deploy/no-such-deployment/devices", first.url()))
+            .json(&serde_json::json!({})).send().await.unwrap().status().as_u16();
+        let second_url = second.url();
+        first.stop().await;
+        second.stop().await;
+
+        assert_eq!(resolved["result"], "41", "{}", resolved);
+        assert_eq!(resolved["servedBy"], format!("{}/{}/modules/incrementer/increment?value=40", second_url, second_id), "{}", resolved);
+        assert_eq!(refused_status, 400);
+        assert_eq!(deployment["devices"], serde_json::json!({ "incrementer-device": second_url }), "{}", deployment);
+        assert_eq!(missing, 404);
+    }
+
+    #[actix_web::test]
+    async fn api_test_execution_error_response() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::test_support::TestSupervisor;
+
+        // The second step chains to a device that is not known, so it fails after running
+        let first = TestSupervisor::start().await.unwrap();
+        let second = TestSupervisor::start().await.unwrap();
+        let (first_id, second_id) = ("execution-error-first", "execution-error-second");
+        let double = first.endpoint(first_id, "doubler", "double", &["n"]);
+        let increment = second.endpoint(second_id, "incrementer", "increment", &["value"]);
+        let mut unknown = increment.clone();
+        unknown["url"] = Value::from("{device:missing-device}");
+        second.deploy(
+            &serde_json::json!({
+                "deploymentId": second_id,
+                "modules": [{ "id": "incrementer-id", "name": "incrementer" }],
+                "endpoints": { "incrementer": { "increment": increment } },
+                "instructions": { "modules": { "incrementer": { "increment": { "from": increment, "to": unknown } } } },
+            }),
+            &[("incrementer", br#"(module (func (export "increment") (param i32) (result i32) (i32.add (local.get 0) (i32.const 1))))"#)],
+        ).await.unwrap();
+        first.deploy(
+            &serde_json::json!({
+                "deploymentId": first_id,
+                "modules": [{ "id": "doubler-id", "name": "doubler" }],
+                "endpoints": { "doubler": { "double": double } },
+                "instructions": { "modules": { "doubler": { "double": { "from": double, "to": increment, "fallbackTargets": [increment] } } } },
+            }),
+            &[("doubler", br#"(module (func (export "double") (param i32) (result i32) (i32.mul (local.get 0) (i32.const 2))))"#)],
+        ).await.unwrap();
+
+        let client = reqwest::Client::new();
+        let response = client.post(format!("{}/{}/modules/doubler/double", first.url(), first_id))
+            .query(&[("n", "20")])
+            .json(&serde_json::json!({}))
+            .send().await.unwrap();
+        let status = response.status().as_u16();
+        let body: Value = response.json().await.unwrap();
+        let result_url = body["resultUrl"].as_str().unwrap_or_default().to_string();
+        let fetched = client.get(&result_url).send().await.unwrap();
+        let fetched_status = fetched.status().as_u16();
+        let fetched: Value = fetched.json().await.unwrap();
+        let second_history = second.history().await.unwrap();
+        first.stop().await;
+        second.stop().await;
+
+        // The failure is answered at once, with the entry that is also in the request history
+        assert_eq!(status, 500, "{}", body);
+        let error = body["error"].as_str().unwrap();
+        assert!(error.contains("{device:missing-device}"), "{}", body);
+        assert_eq!(body["entry"]["success"], false, "{}", body);
+        assert_eq!(body["entry"]["result"], error, "{}", body);
+        assert_eq!(fetched_status, 500);
+        assert_eq!(fetched, body["entry"]);
+
+        // The function failed on the second supervisor, so the fallback target was not tried
+        assert_eq!(body["entry"]["chainTrace"].as_array().unwrap().len(), 1, "{}", body);
+        assert_eq!(second_history.len(), 1, "{:?}", second_history);
+        assert_eq!(second_history[0]["success"], false);
+    }
+
+    #[actix_web::test]
+    async fn api_test_trap_error_response() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::test_support::TestSupervisor;
+
+        let supervisor = TestSupervisor::start().await.unwrap();
+        let deployment_id = "trap-error-deployment";
+        let crash = supervisor.endpoint(deployment_id, "crasher", "crash", &[]);
+        supervisor.deploy(
+            &serde_json::json!({
+                "deploymentId": deployment_id,
+                "modules": [{ "id": "crasher-id", "name": "crasher" }],
+                "endpoints": { "crasher": { "crash": crash } },
+                "instructions": { "modules": { "crasher": { "crash": { "from": crash, "to": null } } } },
+            }),
+            &[("crasher", br#"(module (func (export "crash") (result i32) (unreachable)))"#)],
+        ).await.unwrap();
+
+        let client = reqwest::Client::new();
+        let response = client.post(format!("{}/{}/modules/crasher/crash", supervisor.url(), deployment_id))
+            .json(&serde_json::json!({}))
+            .send().await.unwrap();
+        let status = response.status().as_u16();
+        let body: Value = response.json().await.unwrap();
+        let history = supervisor.history().await.unwrap();
+        supervisor.stop().await;
+
+        // The trap fails the execution instead of returning 0
+        assert_eq!(status, 500, "{}", body);
+        let error = body["error"].as_str().unwrap();
+        assert!(error.contains("unreachable"), "{}", body);
+        assert!(error.contains("crash"), "{}", body);
+        assert_eq!(body["entry"]["success"], false, "{}", body);
+        assert_eq!(body["entry"]["result"], error, "{}", body);
+        assert_eq!(history[0]["success"], false, "{:?}", history);
+    }
+
+    #[actix_web::test]
+    async fn api_test_background_executions_keep_app_state() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::test_support::TestSupervisor;
+
+        // The healthcheck runs on a task of its own while the deployment is created
+        let supervisor = TestSupervisor::start().await.unwrap();
+        let deployment_id = "background-state-deployment";
+        let answer = supervisor.endpoint(deployment_id, "answerer", "answer", &[]);
+        let created = supervisor.deploy(
+            &serde_json::json!({
+                "deploymentId": deployment_id,
+                "modules": [{ "id": "answerer-id", "name": "answerer", "healthcheck": { "function": "answer", "expect": 42 } }],
+                "endpoints": { "answerer": { "answer": answer } },
+                "instructions": { "modules": { "answerer": { "answer": { "from": answer, "to": null } } } },
+            }),
+            &[("answerer", br#"(module (func (export "answer") (result i32) (i32.const 42)))"#)],
+        ).await.unwrap();
+
+        // ...and so does an invoked action
+        let client = reqwest::Client::new();
+        let invoked = client.post(format!("{}/{}/actions/answerer/answer", supervisor.url(), deployment_id))
+            .json(&serde_json::json!({}))
+            .send().await.unwrap();
+        let invoked_status = invoked.status().as_u16();
+        let href = invoked.json::<Value>().await.unwrap()["href"].as_str().unwrap_or_default().to_string();
+        let mut action = Value::Null;
+        for _ in 0..50 {
+            action = client.get(format!("{}{}", supervisor.url(), href)).send().await.unwrap().json().await.unwrap();
+            if action["status"] == "completed" || action["status"] == "failed" {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        let history = supervisor.history().await.unwrap();
+        supervisor.stop().await;
+
+        let healthcheck = &created["healthchecks"][0];
+        assert_eq!(healthcheck["passed"], true, "{}", created);
+        assert_eq!(invoked_status, 202);
+        assert_eq!(action["status"], "completed", "{}", action);
+        // Both were recorded in the history of the supervisor
+        assert_eq!(history.len(), 2, "{:?}", history);
+        assert_eq!(history[0]["method"], HEALTHCHECK_METHOD);
+        assert_eq!(history[1]["success"], true, "{}", history[1]);
+    }
+
+    #[actix_web::test]
+    async fn api_test_description_cache_per_supervisor() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::test_support::TestSupervisor;
+
+        let first = TestSupervisor::start().await.unwrap();
+        let second = TestSupervisor::start().await.unwrap();
+        let deployment_id = "description-cache-state-deployment";
+        let answer = first.endpoint(deployment_id, "answerer", "answer", &[]);
+        first.deploy(
+            &serde_json::json!({
+                "deploymentId": deployment_id,
+                "modules": [{ "id": "answerer-id", "name": "answerer" }],
+                "endpoints": { "answerer": { "answer": answer } },
+                "instructions": { "modules": { "answerer": { "answer": { "from": answer, "to": null } } } },
+            }),
+            &[("answerer", br#"(module (func (export "answer") (result i32) (i32.const 42)))"#)],
+        ).await.unwrap();
+
+        // The first supervisor caches a description with its deployment...
+        let client = reqwest::Client::new();
+        let action = format!("{}/answerer/answer", deployment_id);
+        let url = |supervisor: &TestSupervisor| format!("{}/.well-known/wot-thing-description", supervisor.url());
+        let first_td: Value = client.get(url(&first)).send().await.unwrap().json().await.unwrap();
+        let first_etag = client.get(url(&first)).send().await.unwrap().headers()["etag"].to_str().unwrap().to_string();
+        // ...which the second, without deployments, does not serve
+        let second_response = client.get(url(&second)).send().await.unwrap();
+        let second_etag = second_response.headers()["etag"].to_str().unwrap().to_string();
+        let second_td: Value = second_response.json().await.unwrap();
+        first.stop().await;
+        second.stop().await;
+
+        assert!(first_td["actions"].get(&action).is_some(), "{}", first_td);
+        assert!(second_td["actions"].get(&action).is_none(), "{}", second_td);
+        assert_ne!(first_etag, second_etag);
+    }
+
+    #[actix_web::test]
+    async fn api_test_description_follows_inserted_deployments() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let deployment_id = "description-insert-test-deployment";
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let req = test::TestRequest::get().uri("/.well-known/wot-thing-description").to_request();
+        let resp = test::call_service(&app, req).await;
+        let etag = resp.headers().get("etag").unwrap().to_str().unwrap().to_string();
+
+        // A deployment added as at startup, without going through the deploy handler
+        deploy_answerer(deployment_id, serde_json::json!({}));
+        let req = test::TestRequest::get()
+            .uri("/.well-known/wot-thing-description")
+            .insert_header(("If-None-Match", etag.as_str()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let status = resp.status();
+        let td: Value = test::read_body_json(resp).await;
+        lock_deployments().remove(deployment_id);
+        std::fs::remove_dir_all(MODULE_FOLDER.join(deployment_id)).ok();
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(td["actions"].get(format!("{}/answerer/answer", deployment_id)).is_some(), "{}", td);
+    }
+
+    #[actix_web::test]
+    async fn api_test_config_patch_invalidates_description() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(
+            App::new()
+                .route("/config", web::patch().to(supervisor_config_patch))
+                .route("/.well-known/wasmiot-device-description", web::get().to(wasmiot_device_description))
+        ).await;
+        let describe = |etag: Option<&str>| {
+            let req = test::TestRequest::get().uri("/.well-known/wasmiot-device-description");
+            match etag {
+                Some(etag) => req.insert_header(("If-None-Match", etag)).to_request(),
+                None => req.to_request(),
+            }
+        };
+        let resp = test::call_service(&app, describe(None)).await;
+        let etag = resp.headers().get("etag").unwrap().to_str().unwrap().to_string();
+
+        // The limits of the configuration are part of the description
+        let req = test::TestRequest::patch().uri("/config")
+            .set_json(serde_json::json!({ "deviceLimits": { "maxDeployments": 987654 } }))
+            .to_request();
+        let patched = test::call_service(&app, req).await.status();
+        let resp = test::call_service(&app, describe(Some(&etag))).await;
+        let status = resp.status();
+        let description: Value = test::read_body_json(resp).await;
+        let req = test::TestRequest::patch().uri("/config")
+            .set_json(serde_json::json!({ "deviceLimits": { "maxDeployments": null } }))
+            .to_request();
+        test::call_service(&app, req).await;
+
+        assert_eq!(patched, StatusCode::OK);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(description["deviceLimits"]["maxDeployments"], 987654, "{}", description);
+    }
+
+    #[actix_web::test]
+    async fn api_test_paused_deployments_left_out_of_thing_description() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::test_support::TestSupervisor;
+
+        let supervisor = TestSupervisor::start().await.unwrap();
+        let deployment_id = "paused-thing-description-deployment";
+        let answer = supervisor.endpoint(deployment_id, "answerer", "answer", &[]);
+        supervisor.deploy(
+            &serde_json::json!({
+                "deploymentId": deployment_id,
+                "modules": [{ "id": "answerer-id", "name": "answerer" }],
+                "endpoints": { "answerer": { "answer": answer } },
+                "instructions": { "modules": { "answerer": { "answer": { "from": answer, "to": null } } } },
+            }),
+            &[("answerer", br#"(module (func (export "answer") (result i32) (i32.const 42)))"#)],
+        ).await.unwrap();
+        let client = reqwest::Client::new();
+        let action = format!("{}/answerer/answer", deployment_id);
+        let td_url = format!("{}/.well-known/wot-thing-description", supervisor.url());
+        let describe = |etag: &str| client.get(&td_url).header("If-None-Match", etag).send();
+
+        let resp = describe("").await.unwrap();
+        let etag = resp.headers()["etag"].to_str().unwrap().to_string();
+        let active_td: Value = resp.json().await.unwrap();
+        // Pausing changes the document, so the ETag of the active deployment no longer matches
+        client.post(format!("{}/deploy/{}/pause", supervisor.url(), deployment_id)).send().await.unwrap();
+        let resp = describe(&etag).await.unwrap();
+        let paused_status = resp.status().as_u16();
+        let paused_td: Value = resp.json().await.unwrap();
+        client.post(format!("{}/deploy/{}/resume", supervisor.url(), deployment_id)).send().await.unwrap();
+        let resumed_td: Value = describe("").await.unwrap().json().await.unwrap();
+        supervisor.stop().await;
+
+        assert!(active_td["actions"].get(&action).is_some(), "{}", active_td);
+        assert_eq!(paused_status, 200);
+        assert!(paused_td["actions"].get(&action).is_none(), "{}", paused_td);
+        assert!(resumed_td["actions"].get(&action).is_some(), "{}", resumed_td);
+    }
+
+    #[actix_web::test]
+    async fn api_test_coap_malformed_messages() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::coap::*;
+        let header = [0x40, GET, 0x12, 0x34];
+        let with_header = |rest: &[u8]| [header.as_slice(), rest].concat();
+
+        // Truncated headers and tokens, and other versions
+        for len in 0..header.len() {
+            assert!(Message::parse(&header[..len]).is_err(), "{:?}", &header[..len]);
+        }
+        assert!(Message::parse(&[0x80, GET, 0x12, 0x34]).is_err());
+        assert!(Message::parse(&[[0x49, GET, 0x12, 0x34].as_slice(), &[0; 9]].concat()).is_err());
+        assert!(Message::parse(&[0x44, GET, 0x12, 0x34, 0xab, 0xcd]).is_err());
+
+        // Bad option lengths and deltas
+        let malformed: &[&[u8]] = &[
+            &[0xbf],                  // Length nibble 15
+            &[0xf1, 0x00],            // Delta nibble 15 without being a payload marker
+            &[0xd0],                  // Extended delta without its byte
+            &[0xe0, 0x01],            // Extended delta missing one of its 2 bytes
+            &[0x1e, 0x01],            // Extended length missing one of its 2 bytes
+            &[0xb5, b'a', b'b'],      // Longer than the rest of the message
+            &[0x1d, 0x00, b'a'],      // Extended length of 13 with one byte
+            &[0xe0, 0xff, 0xff],      // Delta past the largest option number
+            &[0xe0, 0xfe, 0xf2, 0x10], // Option number overflowing
+            &[0xff],                  // Payload marker without a payload
+        ];
+        for rest in malformed {
+            assert!(Message::parse(&with_header(rest)).is_err(), "{:02x?}", rest);
+        }
+        let largest = Message::parse(&with_header(&[0xe0, 0xfe, 0xf2])).unwrap();
+        assert_eq!(largest.options, vec![(u16::MAX, Vec::new())]);
+
+        // Extended deltas and lengths at the edges of their encodings survive a round trip
+        let mut message = Message::new(CONFIRMABLE, POST, 0x1234, &[1, 2, 3]);
+        for (number, len) in [(11, 12), (24, 13), (292, 268), (561, 269), (u16::MAX, 1000)] {
+            message.options.push((number, vec![b'x'; len]));
+        }
+        message.payload = b"payload".to_vec();
+        let datagram = message.encode();
+        assert_eq!(Message::parse(&datagram).unwrap(), message);
+        // ...and cutting it anywhere does not break the parser
+        for len in 0..datagram.len() {
+            if let Ok(truncated) = Message::parse(&datagram[..len]) {
+                assert!(truncated.options.len() <= message.options.len());
+            }
+        }
+
+        // Any datagram that parses encodes back to the same message
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..20_000 {
+            let mut random = || {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            };
+            let len = usize::from(random() % 48);
+            let mut datagram: Vec<u8> = (0..len).map(|_| random()).collect();
+            if let Some(first) = datagram.first_mut() {
+                *first = 0x40 | (*first & 0x3f);
+            }
+            if let Ok(parsed) = Message::parse(&datagram) {
+                assert_eq!(Message::parse(&parsed.encode()).unwrap(), parsed, "{:02x?}", datagram);
+            }
+        }
+
+        // Block options longer than 3 bytes or of the reserved size are refused
+        let with_block = |value: &[u8]| {
+            let mut message = Message::new(CONFIRMABLE, GET, 1, &[]);
+            message.options.push((BLOCK2, value.to_vec()));
+            message.block_option(BLOCK2)
+        };
+        assert_eq!(with_block(&[]), Ok(Some(Block { num: 0, more: false, size: 16 })));
+        assert_eq!(with_block(&[0xff, 0xff, 0xf6]), Ok(Some(Block { num: 0xfffff, more: false, size: 1024 })));
+        assert!(with_block(&[0x01, 0x00, 0x00, 0x06]).is_err());
+        assert!(with_block(&[0x17]).is_err());
+        assert_eq!(Message::new(CONFIRMABLE, GET, 1, &[]).block_option(BLOCK1), Ok(None));
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_not_live_during_healthchecks() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        use supervisor::lib::progress::{get_progress, DeploymentPhase};
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let deployment_id = "healthchecking-test-deployment";
+        let manifest = serde_json::json!({
+            "deploymentId": deployment_id,
+            "standby": true,
+            "modules": [
+                { "id": "answerer-id", "name": "answerer" },
+                { "id": "looping-id", "name": "looping", "healthcheck": { "function": "self_test", "expect": 0 } }
+            ]
+        });
+        let mut body = Vec::new();
+        let mut part = |name: &str, contents: &[u8]| {
+            body.extend_from_slice(format!("--live-boundary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes());
+            body.extend_from_slice(contents);
+            body.extend_from_slice(b"\r\n");
+        };
+        part("manifest", manifest.to_string().as_bytes());
+        part("answerer", br#"(module (func (export "answer") (result i32) (i32.const 42)))"#);
+        part("looping", br#"(module (func (export "self_test") (result i32) (loop (br 0)) (i32.const 0)))"#);
+        body.extend_from_slice(b"--live-boundary--\r\n");
+        let req = test::TestRequest::post()
+            .uri("/deploy?wait=true")
+            .insert_header(("content-type", "multipart/form-data; boundary=live-boundary"))
+            .set_payload(body)
+            .to_request();
+        set_setting("WASMIOT_HEALTHCHECK_TIMEOUT_SECONDS", "1", SettingSource::Api);
+        let execute = || test::TestRequest::get().uri(&format!("/{}/modules/answerer/answer", deployment_id)).to_request();
+
+        // While the healthchecks run, the deployment cannot be executed
+        let watch = async {
+            let mut refused_while_healthchecking = false;
+            while get_progress(deployment_id).is_none_or(|progress| !progress.phase.is_finished()) {
+                if get_progress(deployment_id).is_some_and(|progress| progress.phase == DeploymentPhase::Healthchecking) {
+                    assert!(get_deployment(deployment_id).is_none());
+                    let resp = test::call_service(&app, execute()).await;
+                    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+                    refused_while_healthchecking = true;
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+            refused_while_healthchecking
+        };
+        let (resp, refused_while_healthchecking) = futures_util::future::join(test::call_service(&app, req), watch).await;
+        let status = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(refused_while_healthchecking);
+
+        // A standby deployment is put in place paused
+        let resp = test::call_service(&app, execute()).await;
+        assert_eq!(resp.status(), StatusCode::LOCKED);
+
+        let req = test::TestRequest::delete().uri(&format!("/deploy/{}", deployment_id)).to_request();
+        test::call_service(&app, req).await;
+    }
+
+    #[actix_web::test]
+    async fn api_test_deployment_replacement_rolled_back() {
+        if SUPPRESS_STACKTRACE {
+            let f = |_: &std::panic::PanicHookInfo| {};
+            std::panic::set_hook(Box::new(f));
+        }
+        let app = test::init_service(App::new().configure(configure_routes)).await;
+        let deployment_id = "replacement-test-deployment";
+        let push = |answer: i32, expect: i32| {
+            let manifest = serde_json::json!({
+                "deploymentId": deployment_id,
+                "healthcheckPolicy": "fail",
+                "modules": [
+                    { "id": "answerer-id", "name": "answerer", "healthcheck": { "function": "answer", "expect": expect } }
+                ]
+            });
+            let mut body = Vec::new();
+            let mut part = |name: &str, contents: &[u8]| {
+                body.extend_from_slice(format!("--replacement-boundary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes());
+                body.extend_from_slice(contents);
+                body.extend_from_slice(b"\r\n");
+            };
+            part("manifest", manifest.to_string().as_bytes());
+            part("answerer", format!(r#"(module (func (export "answer") (result i32) (i32.const {})))"#, answer).as_bytes());
+            body.extend_from_slice(b"--replacement-boundary--\r\n");
+            test::TestRequest::post()
+                .uri("/deploy?wait=true")
+                .insert_header(("content-type", "multipart/form-data; boundary=replacement-boundary"))
+                .set_payload(body)
+                .to_request()
+        };
+        let answer = || async {
+            let req = test::TestRequest::get().uri(&format!("/{}/modules/answerer/answer", deployment_id)).to_request();
+            let resp: Value = test::call_and_read_body_json(&app, req).await;
+            let request_id = resp["resultUrl"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+            let req = test::TestRequest::get().uri(&format!("/request-history/{}", request_id)).to_request();
+            let entry: Value = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(entry["success"], true, "{}", entry);
+            entry["result"].clone()
+        };
+
+        let resp = test::call_service(&app, push(42, 42)).await;
+        let status = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(answer().await, "42");
+
+        // A replacement failing its healthcheck leaves the working deployment and its files as they were
+        let resp = test::call_service(&app, push(7, 0)).await;
+        let status = resp.status();
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", body);
+        assert_eq!(body["error"], "One or more modules failed their healthcheck");
+        assert_eq!(answer().await, "42");
+        let binary_source = get_deployment(deployment_id).unwrap().lock().await.modules["answerer"].binary_source.clone().unwrap();
+        let module_path = get_module_path(deployment_id, "answerer");
+        assert_eq!(Some(file_sha256(&module_path).unwrap()), binary_source.sha256);
+        let leftovers: Vec<_> = std::fs::read_dir(&*MODULE_FOLDER).unwrap()
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name.contains(deployment_id) && name != deployment_id)
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+        // ...while a passing one takes its place
+        let resp = test::call_service(&app, push(7, 7)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(answer().await, "7");
+
+        let req = test::TestRequest::delete().uri(&format!("/deploy/{}", deployment_id)).to_request();
+        test::call_service(&app, req).await;
+    }
     
 }